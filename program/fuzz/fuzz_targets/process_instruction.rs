@@ -0,0 +1,238 @@
+//! Feeds arbitrary instructions through the program's entrypoint, on a market created beforehand.
+//!
+//! ```text
+//! cargo fuzz run process_instruction
+//! ```
+//!
+//! The runtime is mocked with syscall stubs which serve the clock and rent sysvars and discard logs. The target
+//! asserts that no instruction panics, and that the orderbook and event queue stay consistent after every successful
+//! instruction as long as the input didn't overwrite the accounts' data directly.
+#![no_main]
+use std::sync::{
+    atomic::{AtomicI64, AtomicU64, Ordering},
+    Once,
+};
+
+use agnostic_orderbook::{
+    entrypoint::process_instruction,
+    instruction::{create_market, AgnosticOrderbookInstruction},
+    state::{
+        critbit::Slab, event_queue::EventQueue, market_state::MarketState, AccountTag,
+        BootingPolicy, EventQueueFullPolicy, MatchingMode,
+    },
+};
+use arbitrary::Arbitrary;
+use libfuzzer_sys::fuzz_target;
+use solana_program::{
+    account_info::AccountInfo, clock::Clock, entrypoint::SUCCESS, program_stubs, pubkey::Pubkey,
+    rent::Rent,
+};
+
+type CallbackInfo = [u8; 32];
+
+const ORDER_CAPACITY: usize = 32;
+const EVENT_CAPACITY: usize = 64;
+const SPARE_ACCOUNT_LEN: usize = 16_384;
+const REGISTER_ACCOUNT_LEN: usize = 10_240;
+
+static SLOT: AtomicU64 = AtomicU64::new(0);
+static UNIX_TIMESTAMP: AtomicI64 = AtomicI64::new(0);
+
+struct SyscallStubs;
+
+impl program_stubs::SyscallStubs for SyscallStubs {
+    fn sol_log(&self, _message: &str) {}
+
+    fn sol_log_data(&self, _fields: &[&[u8]]) {}
+
+    fn sol_get_clock_sysvar(&self, var_addr: *mut u8) -> u64 {
+        let clock = Clock {
+            slot: SLOT.load(Ordering::Relaxed),
+            unix_timestamp: UNIX_TIMESTAMP.load(Ordering::Relaxed),
+            ..Clock::default()
+        };
+        unsafe { *(var_addr as *mut Clock) = clock };
+        SUCCESS
+    }
+
+    fn sol_get_rent_sysvar(&self, var_addr: *mut u8) -> u64 {
+        unsafe { *(var_addr as *mut Rent) = Rent::default() };
+        SUCCESS
+    }
+}
+
+#[derive(Arbitrary, Debug)]
+struct FuzzInstruction {
+    /// The instruction data, whose first byte is the instruction's tag.
+    data: Vec<u8>,
+    /// The indices of the accounts of the instruction in the account pool, the register account being appended last.
+    accounts: Vec<u8>,
+    slot: u64,
+    unix_timestamp: i64,
+}
+
+#[derive(Arbitrary, Debug)]
+struct AccountOverwrite {
+    account: u8,
+    offset: u16,
+    bytes: Vec<u8>,
+}
+
+#[derive(Arbitrary, Debug)]
+struct FuzzInput {
+    split_fills: bool,
+    booting_policy: u8,
+    tick_size: u8,
+    instructions: Vec<FuzzInstruction>,
+    /// Arbitrary writes into the accounts before the instructions are processed, which can corrupt them.
+    overwrites: Vec<AccountOverwrite>,
+}
+
+/// The accounts which the instructions can refer to: the market, its event queue, bids and asks, a spare account for
+/// the instructions which create other accounts, and the register.
+struct AccountPool {
+    keys: Vec<Pubkey>,
+    lamports: Vec<u64>,
+    data: Vec<Vec<u8>>,
+}
+
+const MARKET: usize = 0;
+const EVENT_QUEUE: usize = 1;
+const BIDS: usize = 2;
+const ASKS: usize = 3;
+const REGISTER: usize = 5;
+
+impl AccountPool {
+    fn new() -> Self {
+        let slab_len = Slab::<CallbackInfo>::compute_allocation_size(ORDER_CAPACITY);
+        let data = vec![
+            vec![0; MarketState::LEN + 8],
+            vec![0; EventQueue::<CallbackInfo>::compute_allocation_size(EVENT_CAPACITY)],
+            vec![0; slab_len],
+            vec![0; slab_len],
+            vec![0; SPARE_ACCOUNT_LEN],
+            vec![0; REGISTER_ACCOUNT_LEN],
+        ];
+        Self {
+            keys: (0..data.len()).map(|_| Pubkey::new_unique()).collect(),
+            lamports: vec![u64::MAX / 16; data.len()],
+            data,
+        }
+    }
+
+    fn process(&mut self, data: &[u8], account_indices: &[usize]) -> bool {
+        let program_id = agnostic_orderbook::ID;
+        let infos = self
+            .keys
+            .iter()
+            .zip(self.lamports.iter_mut())
+            .zip(self.data.iter_mut())
+            .map(|((key, lamports), data)| {
+                AccountInfo::new(key, false, true, lamports, data, &program_id, false, 0)
+            })
+            .collect::<Vec<_>>();
+        // Like the runtime, duplicate accounts share the same data
+        let accounts = account_indices
+            .iter()
+            .map(|&i| infos[i].clone())
+            .collect::<Vec<_>>();
+        process_instruction(&program_id, &accounts, data).is_ok()
+    }
+
+    fn check_invariants(&mut self) {
+        MarketState::from_buffer(&self.data[MARKET], AccountTag::Market).unwrap();
+        let event_queue = EventQueue::<CallbackInfo>::from_buffer(
+            &mut self.data[EVENT_QUEUE],
+            AccountTag::EventQueue,
+        )
+        .unwrap();
+        assert!(event_queue.len() <= event_queue.capacity() as u64);
+        let (bids, asks) = self.data[BIDS..=ASKS].split_at_mut(1);
+        let bids = Slab::<CallbackInfo>::from_buffer(&mut bids[0], AccountTag::Bids).unwrap();
+        let asks = Slab::<CallbackInfo>::from_buffer(&mut asks[0], AccountTag::Asks).unwrap();
+        bids.verify().unwrap();
+        asks.verify().unwrap();
+    }
+}
+
+fn create_market_instruction(input: &FuzzInput, pool: &AccountPool) -> Vec<u8> {
+    let booting_policy = match input.booting_policy % 3 {
+        0 => BootingPolicy::BootLeastAggressive,
+        1 => BootingPolicy::RejectNewOrder,
+        _ => BootingPolicy::BootSmallest,
+    };
+    let instruction = create_market(
+        create_market::Accounts {
+            market: &pool.keys[MARKET],
+            event_queue: &pool.keys[EVENT_QUEUE],
+            bids: &pool.keys[BIDS],
+            asks: &pool.keys[ASKS],
+            market_registry: None,
+        },
+        pool.keys[REGISTER],
+        create_market::Params {
+            min_base_order_size: 1,
+            tick_size: std::cmp::max(input.tick_size, 1) as u64,
+            min_quote_order_size: 0,
+            booting_policy,
+            split_fills: input.split_fills,
+            log_events: false,
+            order_capacity: ORDER_CAPACITY as u64,
+            event_capacity: EVENT_CAPACITY as u64,
+            metadata: vec![],
+            quote_scale_exponent: 0,
+            max_price_deviation_bps: 0,
+            matching_mode: MatchingMode::Continuous,
+            maker_fee_bps: 0,
+            taker_fee_bps: 0,
+            max_orders_per_owner: 0,
+            consumer_program: Pubkey::default(),
+            max_events_per_consumer_call: 0,
+            event_queue_full_policy: EventQueueFullPolicy::Reject,
+        },
+    );
+    instruction.data
+}
+
+fuzz_target!(|input: FuzzInput| {
+    static STUBS: Once = Once::new();
+    STUBS.call_once(|| {
+        program_stubs::set_syscall_stubs(Box::new(SyscallStubs));
+    });
+
+    let mut pool = AccountPool::new();
+    let data = create_market_instruction(&input, &pool);
+    assert!(pool.process(&data, &[MARKET, EVENT_QUEUE, BIDS, ASKS, REGISTER]));
+
+    let mut corrupted = false;
+    for overwrite in input.overwrites.iter() {
+        let data = &mut pool.data[overwrite.account as usize % REGISTER];
+        let offset = overwrite.offset as usize % data.len();
+        let len = std::cmp::min(overwrite.bytes.len(), data.len() - offset);
+        data[offset..offset + len].copy_from_slice(&overwrite.bytes[..len]);
+        corrupted |= len != 0;
+    }
+
+    for instruction in input.instructions.iter() {
+        // Resizing and migrating accounts reallocates their data, which needs the memory layout of the runtime
+        let reallocating = [
+            AgnosticOrderbookInstruction::ResizeMarket as u8,
+            AgnosticOrderbookInstruction::ResizeEventQueue as u8,
+            AgnosticOrderbookInstruction::MigrateMarket as u8,
+        ];
+        if matches!(instruction.data.first(), Some(tag) if reallocating.contains(tag)) {
+            continue;
+        }
+        SLOT.store(instruction.slot, Ordering::Relaxed);
+        UNIX_TIMESTAMP.store(instruction.unix_timestamp, Ordering::Relaxed);
+        let mut accounts = instruction
+            .accounts
+            .iter()
+            .map(|&i| i as usize % REGISTER)
+            .collect::<Vec<_>>();
+        accounts.push(REGISTER);
+        if pool.process(&instruction.data, &accounts) && !corrupted {
+            pool.check_invariants();
+        }
+    }
+});
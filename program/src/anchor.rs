@@ -0,0 +1,93 @@
+//! Implementations of Anchor's account traits for the accounts of the orderbook, so that Anchor-based programs can
+//! declare them in their `#[derive(Accounts)]` structs, e.g. as `Account<'info, MarketState>`.
+//!
+//! The 8-byte account tag of the orderbook accounts takes the place of Anchor's discriminator: deserialization checks
+//! the tag and the layout version, then reads the value which follows them. For the slabs and the event queue, only
+//! the header is read, the nodes and events can then be read from the account data with the helpers of [`state`].
+//!
+//! The accounts are only ever written by the orderbook instructions, so their [`AccountSerialize`] implementations
+//! are no-ops, and their [`Owner`] is the program id of this crate.
+//!
+//! This module is only available with the `anchor` feature.
+//!
+//! [`state`]: crate::state
+
+use anchor_lang::{error::ErrorCode, AccountDeserialize, AccountSerialize, Owner};
+use bytemuck::Pod;
+use solana_program::pubkey::Pubkey;
+
+use crate::state::{
+    check_account_version, critbit::SlabHeader, event_queue::EventQueueHeader,
+    market_state::MarketState, read_account_tag, AccountTag, ACCOUNT_TAG_LENGTH,
+};
+
+/// The IDL definitions of the orderbook accounts, to be merged into the `accounts` and `types` of the IDL of a program
+/// which declares them.
+pub const IDL_FRAGMENT: &str = include_str!("../idl/anchor_accounts.json");
+
+fn check_tag(buf: &[u8], expected_tags: &[AccountTag]) -> anchor_lang::Result<()> {
+    if buf.len() < ACCOUNT_TAG_LENGTH {
+        return Err(ErrorCode::AccountDiscriminatorNotFound.into());
+    }
+    if !expected_tags.contains(&read_account_tag(buf)?) {
+        return Err(ErrorCode::AccountDiscriminatorMismatch.into());
+    }
+    check_account_version(buf)?;
+    Ok(())
+}
+
+fn read_after_tag<T: Pod>(buf: &[u8]) -> anchor_lang::Result<T> {
+    buf.get(ACCOUNT_TAG_LENGTH..ACCOUNT_TAG_LENGTH + std::mem::size_of::<T>())
+        .map(bytemuck::pod_read_unaligned)
+        .ok_or_else(|| ErrorCode::AccountDidNotDeserialize.into())
+}
+
+macro_rules! impl_anchor_account {
+    ($t:ty, $($tag:expr),+) => {
+        impl AccountDeserialize for $t {
+            fn try_deserialize(buf: &mut &[u8]) -> anchor_lang::Result<Self> {
+                check_tag(buf, &[$($tag),+])?;
+                Self::try_deserialize_unchecked(buf)
+            }
+
+            fn try_deserialize_unchecked(buf: &mut &[u8]) -> anchor_lang::Result<Self> {
+                read_after_tag(buf)
+            }
+        }
+
+        impl AccountSerialize for $t {}
+
+        impl Owner for $t {
+            fn owner() -> Pubkey {
+                crate::ID
+            }
+        }
+    };
+}
+
+impl_anchor_account!(MarketState, AccountTag::Market);
+impl_anchor_account!(SlabHeader, AccountTag::Bids, AccountTag::Asks);
+impl_anchor_account!(EventQueueHeader, AccountTag::EventQueue);
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::state::critbit::Slab;
+
+    #[test]
+    fn test_account_deserialize() {
+        let allocation_size = Slab::<[u8; 32]>::compute_allocation_size(10);
+        let (mut bids, mut asks) = (vec![0; allocation_size], vec![0; allocation_size]);
+        Slab::<[u8; 32]>::initialize(&mut asks, &mut bids).unwrap();
+
+        assert!(SlabHeader::try_deserialize(&mut &bids[..]).is_ok());
+        assert!(SlabHeader::try_deserialize(&mut &asks[..]).is_ok());
+        assert!(MarketState::try_deserialize(&mut &bids[..]).is_err());
+        assert!(EventQueueHeader::try_deserialize(&mut &asks[..]).is_err());
+        assert!(SlabHeader::try_deserialize(&mut &bids[..4]).is_err());
+
+        // Accounts with an outdated layout are rejected
+        bids[crate::state::ACCOUNT_VERSION_INDEX] = 0;
+        assert!(SlabHeader::try_deserialize(&mut &bids[..]).is_err());
+    }
+}
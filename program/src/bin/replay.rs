@@ -0,0 +1,60 @@
+//! Replays a log of market instructions, and writes the data of the resulting market, event queue, bids and asks
+//! accounts into a directory.
+//!
+//! ```text
+//! cargo run --features simulation --bin replay -- <log file> <output directory>
+//! ```
+//!
+//! The log is read with [`read_log`], and the callback infos of the market are expected to be 32 bytes long. Only the
+//! create_market, new_order, cancel_order and consume_events instructions can be replayed, and the replay stops at the
+//! first entry which holds any other instruction.
+use std::{path::Path, process::exit};
+
+use agnostic_orderbook::replay::{read_log, replay};
+
+fn main() {
+    let args = std::env::args().collect::<Vec<_>>();
+    if args.len() != 3 {
+        eprintln!("Usage: {} <log file> <output directory>", args[0]);
+        eprintln!(
+            "Only the create_market, new_order, cancel_order and consume_events instructions can be replayed."
+        );
+        exit(1);
+    }
+    let log = std::fs::read(&args[1]).unwrap_or_else(|e| {
+        eprintln!("Failed to read the log: {}", e);
+        exit(1)
+    });
+    let entries = read_log(&log).unwrap_or_else(|e| {
+        eprintln!("Failed to decode the log: {}", e);
+        exit(1)
+    });
+    let mut simulator = replay::<[u8; 32]>(&entries).unwrap_or_else(|e| {
+        eprintln!("Entry {} failed: {}", e.entry_index, e.error);
+        exit(1)
+    });
+
+    let output_directory = Path::new(&args[2]);
+    let (market, event_queue, bids, asks) = simulator.buffers();
+    for (name, data) in [
+        ("market", market),
+        ("event_queue", event_queue),
+        ("bids", bids),
+        ("asks", asks),
+    ] {
+        let path = output_directory.join(format!("{}.bin", name));
+        if let Err(e) = std::fs::write(&path, data) {
+            eprintln!("Failed to write {}: {}", path.display(), e);
+            exit(1);
+        }
+    }
+    let orderbook = simulator.orderbook();
+    let (num_bids, num_asks) = (orderbook.bids.len(), orderbook.asks.len());
+    println!(
+        "Replayed {} instructions: {} events in the queue, {} bids and {} asks in the orderbook",
+        entries.len(),
+        simulator.event_queue().len(),
+        num_bids,
+        num_asks
+    );
+}
@@ -0,0 +1,254 @@
+//! Helpers which build an orderbook instruction, invoke it through CPI, and read its result back from the register
+//! account.
+//!
+//! The instruction's own accounts are passed as the instruction's `Accounts` struct, and the program and register
+//! accounts through a [`CpiContext`].
+use bonfida_utils::BorshSize;
+use borsh::{BorshDeserialize, BorshSerialize};
+use bytemuck::Pod;
+use solana_program::{
+    account_info::AccountInfo, entrypoint::ProgramResult, instruction::Instruction,
+    program::invoke_signed, program_error::ProgramError, pubkey::Pubkey,
+};
+
+use crate::{
+    processor::{
+        cancel_order, consume_events, get_order, new_order, new_order_batch, quote_swap,
+        replace_order,
+    },
+    state::orderbook::{
+        read_cancelled_order, read_order_summaries, read_quote, read_swap_quote, CancelledOrder,
+        OrderSummary, Quote, SwapQuote,
+    },
+};
+
+/// The accounts and signer seeds which every orderbook CPI needs on top of the instruction's own accounts.
+pub struct CpiContext<'a, 'b> {
+    /// The orderbook program.
+    pub program: &'a AccountInfo<'b>,
+    /// The register account, into which the orderbook writes the result of the instruction.
+    pub register: &'a AccountInfo<'b>,
+    /// The seeds of the program derived addresses which sign the instruction, if any.
+    pub signer_seeds: &'a [&'a [&'a [u8]]],
+}
+
+impl<'a, 'b> CpiContext<'a, 'b> {
+    /// Creates a context for an instruction which doesn't need any program derived address to sign.
+    pub fn new(program: &'a AccountInfo<'b>, register: &'a AccountInfo<'b>) -> Self {
+        Self {
+            program,
+            register,
+            signer_seeds: &[],
+        }
+    }
+
+    fn invoke(&self, instruction: Instruction, accounts: &[&AccountInfo<'b>]) -> ProgramResult {
+        let mut account_infos = accounts.iter().map(|a| (*a).clone()).collect::<Vec<_>>();
+        account_infos.push(self.register.clone());
+        account_infos.push(self.program.clone());
+        invoke_signed(&instruction, &account_infos, self.signer_seeds)
+    }
+
+    fn read_order_summary(&self) -> Result<OrderSummary, ProgramError> {
+        Option::<OrderSummary>::deserialize(&mut &self.register.data.borrow()[..])
+            .map_err(|_| ProgramError::InvalidAccountData)?
+            .ok_or(ProgramError::InvalidAccountData)
+    }
+}
+
+fn keys<'b>(accounts: &[AccountInfo<'b>]) -> Vec<Pubkey> {
+    accounts.iter().map(|a| *a.key).collect()
+}
+
+/// Invokes a new_order instruction and returns its order summary.
+pub fn new_order<'a, 'b: 'a, C: BorshSerialize + BorshSize>(
+    ctx: &CpiContext<'a, 'b>,
+    accounts: new_order::Accounts<'a, AccountInfo<'b>>,
+    params: new_order::Params<C>,
+) -> Result<OrderSummary, ProgramError> {
+    let event_queue_shards = keys(accounts.event_queue_shards);
+    let instruction = crate::instruction::new_order(
+        new_order::Accounts {
+            market: accounts.market.key,
+            event_queue: accounts.event_queue.key,
+            bids: accounts.bids.key,
+            asks: accounts.asks.key,
+            allowlist: accounts.allowlist.map(|a| a.key),
+            event_queue_shards: &event_queue_shards,
+        },
+        *ctx.register.key,
+        params,
+    );
+    let mut account_infos = vec![
+        accounts.market,
+        accounts.event_queue,
+        accounts.bids,
+        accounts.asks,
+    ];
+    account_infos.extend(accounts.allowlist);
+    account_infos.extend(accounts.event_queue_shards);
+    ctx.invoke(instruction, &account_infos)?;
+    ctx.read_order_summary()
+}
+
+/// Invokes a new_order_batch instruction and returns the order summaries of the orders, in order.
+pub fn new_order_batch<'a, 'b: 'a, C: BorshSerialize + BorshSize>(
+    ctx: &CpiContext<'a, 'b>,
+    accounts: new_order_batch::Accounts<'a, AccountInfo<'b>>,
+    params: new_order_batch::Params<C>,
+) -> Result<Vec<OrderSummary>, ProgramError> {
+    let event_queue_shards = keys(accounts.event_queue_shards);
+    let instruction = crate::instruction::new_order_batch(
+        new_order_batch::Accounts {
+            market: accounts.market.key,
+            event_queue: accounts.event_queue.key,
+            bids: accounts.bids.key,
+            asks: accounts.asks.key,
+            allowlist: accounts.allowlist.map(|a| a.key),
+            event_queue_shards: &event_queue_shards,
+        },
+        *ctx.register.key,
+        params,
+    );
+    let mut account_infos = vec![
+        accounts.market,
+        accounts.event_queue,
+        accounts.bids,
+        accounts.asks,
+    ];
+    account_infos.extend(accounts.allowlist);
+    account_infos.extend(accounts.event_queue_shards);
+    ctx.invoke(instruction, &account_infos)?;
+    read_order_summaries(&ctx.register.data.borrow()).map_err(|_| ProgramError::InvalidAccountData)
+}
+
+/// Invokes a replace_order instruction and returns the order summary of the new order.
+pub fn replace_order<'a, 'b: 'a, C: BorshSerialize + BorshSize>(
+    ctx: &CpiContext<'a, 'b>,
+    accounts: replace_order::Accounts<'a, AccountInfo<'b>>,
+    params: replace_order::Params<C>,
+) -> Result<OrderSummary, ProgramError> {
+    let event_queue_shards = keys(accounts.event_queue_shards);
+    let instruction = crate::instruction::replace_order(
+        replace_order::Accounts {
+            market: accounts.market.key,
+            event_queue: accounts.event_queue.key,
+            bids: accounts.bids.key,
+            asks: accounts.asks.key,
+            allowlist: accounts.allowlist.map(|a| a.key),
+            event_queue_shards: &event_queue_shards,
+        },
+        *ctx.register.key,
+        params,
+    );
+    let mut account_infos = vec![
+        accounts.market,
+        accounts.event_queue,
+        accounts.bids,
+        accounts.asks,
+    ];
+    account_infos.extend(accounts.allowlist);
+    account_infos.extend(accounts.event_queue_shards);
+    ctx.invoke(instruction, &account_infos)?;
+    ctx.read_order_summary()
+}
+
+/// Invokes a cancel_order instruction and returns the cancelled order, whose callback info is of type `C`.
+pub fn cancel_order<'a, 'b: 'a, C: Pod>(
+    ctx: &CpiContext<'a, 'b>,
+    accounts: cancel_order::Accounts<'a, AccountInfo<'b>>,
+    params: cancel_order::Params,
+) -> Result<CancelledOrder<C>, ProgramError> {
+    let instruction = crate::instruction::cancel_order(
+        cancel_order::Accounts {
+            market: accounts.market.key,
+            event_queue: accounts.event_queue.key,
+            bids: accounts.bids.key,
+            asks: accounts.asks.key,
+        },
+        *ctx.register.key,
+        params,
+    );
+    ctx.invoke(
+        instruction,
+        &[
+            accounts.market,
+            accounts.event_queue,
+            accounts.bids,
+            accounts.asks,
+        ],
+    )?;
+    read_cancelled_order(&ctx.register.data.borrow()).map_err(|_| ProgramError::InvalidAccountData)
+}
+
+/// Invokes a consume_events instruction.
+///
+/// When `params.write_to_register` is set, the consumed events can then be read back from the register account with
+/// [`read_consumed_events`](crate::state::event_queue::read_consumed_events).
+pub fn consume_events<'a, 'b: 'a>(
+    ctx: &CpiContext<'a, 'b>,
+    accounts: consume_events::Accounts<'a, AccountInfo<'b>>,
+    params: consume_events::Params,
+) -> ProgramResult {
+    let consumer_accounts = keys(accounts.consumer_accounts);
+    let instruction = crate::instruction::consume_events(
+        consume_events::Accounts {
+            market: accounts.market.key,
+            event_queue: accounts.event_queue.key,
+            consumer_program: accounts.consumer_program.map(|a| a.key),
+            consumer_authority: accounts.consumer_authority.map(|a| a.key),
+            consumer_accounts: &consumer_accounts,
+        },
+        *ctx.register.key,
+        params,
+    );
+    let mut account_infos = vec![accounts.market, accounts.event_queue];
+    account_infos.extend(accounts.consumer_program);
+    account_infos.extend(accounts.consumer_authority);
+    account_infos.extend(accounts.consumer_accounts);
+    ctx.invoke(instruction, &account_infos)
+}
+
+/// Invokes a get_order instruction and returns the top of the orderbook along with the state of the requested order.
+pub fn get_order<'a, 'b: 'a>(
+    ctx: &CpiContext<'a, 'b>,
+    accounts: get_order::Accounts<'a, AccountInfo<'b>>,
+    params: get_order::Params,
+) -> Result<Quote, ProgramError> {
+    let instruction = crate::instruction::get_order(
+        get_order::Accounts {
+            market: accounts.market.key,
+            bids: accounts.bids.key,
+            asks: accounts.asks.key,
+        },
+        *ctx.register.key,
+        params,
+    );
+    ctx.invoke(
+        instruction,
+        &[accounts.market, accounts.bids, accounts.asks],
+    )?;
+    read_quote(&ctx.register.data.borrow()).map_err(|_| ProgramError::InvalidAccountData)
+}
+
+/// Invokes a quote_swap instruction and returns the swap quote.
+pub fn quote_swap<'a, 'b: 'a>(
+    ctx: &CpiContext<'a, 'b>,
+    accounts: quote_swap::Accounts<'a, AccountInfo<'b>>,
+    params: quote_swap::Params,
+) -> Result<SwapQuote, ProgramError> {
+    let instruction = crate::instruction::quote_swap(
+        quote_swap::Accounts {
+            market: accounts.market.key,
+            bids: accounts.bids.key,
+            asks: accounts.asks.key,
+        },
+        *ctx.register.key,
+        params,
+    );
+    ctx.invoke(
+        instruction,
+        &[accounts.market, accounts.bids, accounts.asks],
+    )?;
+    read_swap_quote(&ctx.register.data.borrow()).map_err(|_| ProgramError::InvalidAccountData)
+}
@@ -1,11 +1,15 @@
 use crate::{error::AoError, processor};
-use borsh::BorshSerialize;
-use num_traits::FromPrimitive;
 use solana_program::{
-    account_info::AccountInfo, decode_error::DecodeError, entrypoint::ProgramResult, msg,
-    program_error::PrintProgramError, pubkey::Pubkey,
+    account_info::AccountInfo,
+    entrypoint::ProgramResult,
+    msg,
+    program_error::{PrintProgramError, ProgramError},
+    pubkey::Pubkey,
 };
 
+#[cfg(all(feature = "entrypoint", feature = "wasm"))]
+compile_error!("The entrypoint and wasm features can't be enabled together");
+
 #[cfg(feature = "entrypoint")]
 use solana_program::entrypoint;
 #[cfg(feature = "entrypoint")]
@@ -18,7 +22,9 @@ pub fn process_instruction(
     instruction_data: &[u8],
 ) -> ProgramResult {
     msg!("Entrypoint");
-    let (register_account, accounts) = accounts.split_last().unwrap();
+    let (register_account, accounts) = accounts
+        .split_last()
+        .ok_or(ProgramError::NotEnoughAccountKeys)?;
     match processor::process_instruction::<[u8; 32]>(program_id, accounts, instruction_data) {
         Err(error) => {
             // catch the error so we can print it
@@ -26,18 +32,9 @@ pub fn process_instruction(
             return Err(error);
         }
         Ok(r) => {
-            let mut a: &mut [u8] = &mut register_account.data.borrow_mut();
-            r.serialize(&mut a).unwrap();
+            r.write(&mut register_account.data.borrow_mut())
+                .map_err(|_| ProgramError::AccountDataTooSmall)?;
         }
     }
     Ok(())
 }
-
-impl PrintProgramError for AoError {
-    fn print<E>(&self)
-    where
-        E: 'static + std::error::Error + DecodeError<E> + PrintProgramError + FromPrimitive,
-    {
-        msg!("Error: {}", self)
-    }
-}
@@ -1,7 +1,12 @@
 use num_derive::FromPrimitive;
 use thiserror::Error;
 
-use solana_program::{decode_error::DecodeError, program_error::ProgramError};
+use num_traits::FromPrimitive;
+use solana_program::{
+    decode_error::DecodeError,
+    msg,
+    program_error::{PrintProgramError, ProgramError},
+};
 
 pub type AoResult<T = ()> = Result<T, AoError>;
 
@@ -9,57 +14,125 @@ pub type AoResult<T = ()> = Result<T, AoError>;
 #[derive(Clone, Debug, Error, FromPrimitive)]
 pub enum AoError {
     #[error("This account is already initialized")]
-    AlreadyInitialized,
+    AlreadyInitialized = 0,
     #[error("An invalid bids account has been provided.")]
-    WrongBidsAccount,
+    WrongBidsAccount = 1,
     #[error("An invalid asks account has been provided.")]
-    WrongAsksAccount,
+    WrongAsksAccount = 2,
     #[error("An invalid event queue account has been provided.")]
-    WrongEventQueueAccount,
+    WrongEventQueueAccount = 3,
     #[error("An invalid caller authority account has been provided.")]
-    WrongCallerAuthority, // 5
+    WrongCallerAuthority = 4,
     #[error("The event queue is full.")]
-    EventQueueFull,
+    EventQueueFull = 5,
     #[error("The order could not be found.")]
-    OrderNotFound,
+    OrderNotFound = 6,
     #[error("The order would self trade.")]
-    WouldSelfTrade,
+    WouldSelfTrade = 7,
     #[error("The market's memory is full.")]
-    SlabOutOfSpace,
+    SlabOutOfSpace = 8,
     #[error("The due fee was not payed.")]
-    FeeNotPayed, // 10
+    FeeNotPayed = 9,
     #[error("This instruction is a No-op.")]
-    NoOperations,
+    NoOperations = 10,
     #[error("The market is still active")]
-    MarketStillActive,
+    MarketStillActive = 11,
     #[error("The base quantity must be > 0")]
-    InvalidBaseQuantity,
+    InvalidBaseQuantity = 12,
     #[error("The event queue should be owned by the AO program")]
-    WrongEventQueueOwner,
+    WrongEventQueueOwner = 13,
     #[error("The bids account should be owned by the AO program")]
-    WrongBidsOwner, // 15
+    WrongBidsOwner = 14,
     #[error("The asks account should be owned by the AO program")]
-    WrongAsksOwner,
+    WrongAsksOwner = 15,
     #[error("The market account should be owned by the AO program")]
-    WrongMarketOwner,
+    WrongMarketOwner = 16,
     #[error("The MSRM token account should be owned by the cranker")]
-    WrongMsrmOwner,
+    WrongMsrmOwner = 17,
     #[error("An invalid MSRM mint has been provided")]
-    WrongMsrmMint,
+    WrongMsrmMint = 18,
     #[error("The MSRM token account does not have enough balances")]
-    WrongMsrmBalance, // 20
+    WrongMsrmBalance = 19,
     #[error("Illegal MSRM token account owner")]
-    IllegalMsrmOwner,
+    IllegalMsrmOwner = 20,
     #[error("Limit price must be a tick size multiple")]
-    InvalidLimitPrice,
+    InvalidLimitPrice = 21,
     #[error("Numerical overlflow")]
-    NumericalOverflow,
+    NumericalOverflow = 22,
     #[error("Invalid callback info")]
-    InvalidCallbackInfo,
+    InvalidCallbackInfo = 23,
     #[error("Invalid event tag")]
-    InvalidEventTag, // 25
+    InvalidEventTag = 24,
     #[error("Invalid order side")]
-    InvalidOrderSide, // 25
+    InvalidOrderSide = 25,
+    #[error("The order could not be entirely filled")]
+    UnableToFill = 26,
+    #[error("The displayed quantity of an iceberg order is too small")]
+    InvalidDisplayQuantity = 27,
+    #[error("The stop orders account should be owned by the AO program")]
+    WrongStopOrdersOwner = 28,
+    #[error("An invalid stop orders account has been provided.")]
+    WrongStopOrdersAccount = 29,
+    #[error("The pegged orders account should be owned by the AO program")]
+    WrongPeggedOrdersOwner = 30,
+    #[error("An invalid pegged orders account has been provided.")]
+    WrongPeggedOrdersAccount = 31,
+    #[error("The reference price of the pegged order is unavailable")]
+    MissingPegReference = 32,
+    #[error("The price is not a multiple of the market's tick size")]
+    InvalidTickSize = 33,
+    #[error("The order is smaller than the market's minimum order size")]
+    OrderTooSmall = 34,
+    #[error("The orderbook account is corrupted")]
+    CorruptedSlab = 35,
+    #[error("An invalid dead letter queue account has been provided.")]
+    WrongDeadLetterQueueAccount = 36,
+    #[error("The market's trading status doesn't allow this operation")]
+    OperationNotAllowed = 37,
+    #[error("The account layout is outdated and must be migrated")]
+    OutdatedAccountVersion = 38,
+    #[error("The market registry is full")]
+    MarketRegistryFull = 39,
+    #[error("An invalid market registry account has been provided.")]
+    WrongMarketRegistryAccount = 40,
+    #[error("The limit price deviates too much from the reference price of the market")]
+    PriceOutOfBand = 41,
+    #[error("A resumable order is still being matched on the market")]
+    PendingTakerInProgress = 42,
+    #[error("The owner of the order has too many orders resting in the orderbook")]
+    TooManyOpenOrders = 43,
+    #[error("The market parameters are invalid")]
+    InvalidMarketParameters = 44,
+    #[error("The market can't hold more event queue shards")]
+    TooManyEventQueueShards = 45,
+    #[error("The event queue must be empty")]
+    EventQueueNotEmpty = 46,
+    #[error("Accounts can't be shrunk")]
+    AccountShrinkNotAllowed = 47,
+    #[error("Stop orders can't abort the transaction on self trade")]
+    InvalidSelfTradeBehavior = 48,
+    #[error("The accounts registered on the market must be provided")]
+    MissingRegisteredAccounts = 49,
+    #[error("A resumable order can't have a referral")]
+    InvalidReferral = 50,
+    #[error("An invalid consumer program account has been provided.")]
+    WrongConsumerProgram = 51,
+    #[error("An invalid consumer authority account has been provided.")]
+    WrongConsumerAuthority = 52,
+    #[error("An invalid allowlist account has been provided.")]
+    WrongAllowlistAccount = 53,
+    #[error("The allowlist of the market doesn't authorize the order's callback info.")]
+    OrderNotAllowlisted = 54,
+    #[error("The allowlist can't hold more entries.")]
+    AllowlistFull = 55,
+    #[error("The market account groups don't match the cancel lists.")]
+    MarketAccountsMismatch = 56,
+    #[error("A resumable order can't have a client order id")]
+    InvalidClientOrderId = 57,
+    #[error("The owner already has an order with this client order id")]
+    DuplicateClientOrderId = 58,
+    #[error("The book holds too many orders to be committed")]
+    BookTooLargeToCommit = 59,
 }
 
 impl From<AoError> for ProgramError {
@@ -73,3 +146,28 @@ impl<T> DecodeError<T> for AoError {
         "AOError"
     }
 }
+
+impl PrintProgramError for AoError {
+    fn print<E>(&self)
+    where
+        E: 'static + std::error::Error + DecodeError<E> + PrintProgramError + FromPrimitive,
+    {
+        msg!("Error {}: {}", self.clone() as u32, self)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_error_codes() {
+        let codes = (0..)
+            .map_while(|code| AoError::from_u32(code).map(|e| (code, e)))
+            .collect::<Vec<_>>();
+        assert_eq!(codes.len(), AoError::BookTooLargeToCommit as usize + 1);
+        for (code, error) in codes {
+            assert_eq!(ProgramError::from(error), ProgramError::Custom(code));
+        }
+    }
+}
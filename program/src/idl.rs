@@ -0,0 +1,840 @@
+//! A machine-readable description of the instructions of the program, their parameters and their accounts, from
+//! which clients such as the TypeScript bindings can be generated.
+//!
+//! [`idl`] returns the description, and [`Idl::to_json`] serializes it into a JSON document which follows the layout
+//! of Anchor IDLs, so that existing code generators can consume it:
+//!
+//! ```ignore
+//! std::fs::write("agnostic_orderbook.json", agnostic_orderbook::idl::idl().to_json())?;
+//! ```
+//!
+//! The description is checked against the `Params` and `Accounts` structs of the instructions and against the
+//! parameter enums when the crate is built: adding, removing or retyping a field or a variant without updating this
+//! module is a compilation error. The `callback_info` parameters are described as the `CallbackInfo` defined type,
+//! whose layout is chosen by the caller program.
+//!
+//! This module is only available with the `idl` feature.
+use crate::{
+    instruction::{
+        add_event_queue_shard, cancel_by_client_id, cancel_order, cancel_orders_by_owner,
+        cancel_pegged_order, cancel_stop_order, clean_book, close_market, commit_book,
+        consume_events, continue_match, create_allowlist, create_dead_letter_queue, create_market,
+        create_market_registry, create_pegged_orders, create_stop_orders, expire_events,
+        fix_crossed_book, get_order, mass_cancel_orders, migrate_market, multi_market_cancel,
+        new_order, new_order_batch, new_pegged_order, new_stop_order, poke, prune_expired,
+        prune_orders, quote_swap, replace_order, reprice_pegged, resize_event_queue, resize_market,
+        run_auction, set_market_status, sweep_fees, trigger_orders, update_allowlist, verify_slab,
+        AgnosticOrderbookInstruction,
+    },
+    state::{
+        BootingPolicy, EventQueueFullPolicy, MatchingMode, PegReference, PostOnlyMode,
+        SelfTradeBehavior, Side, TradingStatus,
+    },
+};
+use solana_program::pubkey::Pubkey;
+
+/// The type of an instruction parameter, as serialized with Borsh.
+#[derive(Debug, Clone, PartialEq)]
+pub enum IdlType {
+    #[allow(missing_docs)]
+    U8,
+    #[allow(missing_docs)]
+    U64,
+    #[allow(missing_docs)]
+    U128,
+    #[allow(missing_docs)]
+    I64,
+    #[allow(missing_docs)]
+    Bool,
+    #[allow(missing_docs)]
+    PublicKey,
+    /// A vector, serialized with a `u32` length prefix.
+    Vec(Box<IdlType>),
+    /// A fixed-length array, serialized without a length prefix.
+    Array(Box<IdlType>, usize),
+    /// A type described in the [`types`](Idl::types) of the IDL.
+    Defined(&'static str),
+    /// The callback info type of the caller program.
+    CallbackInfo,
+}
+
+/// A named field of an instruction's parameters or of a defined struct.
+#[derive(Debug, Clone, PartialEq)]
+pub struct IdlField {
+    /// The name of the field in the Rust struct.
+    pub name: &'static str,
+    #[allow(missing_docs)]
+    pub ty: IdlType,
+}
+
+/// How many accounts an entry of an instruction's account list stands for.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum IdlAccountKind {
+    /// Exactly one account.
+    Single,
+    /// Zero or one account.
+    Optional,
+    /// Any number of accounts, which are always last in the account list.
+    Multiple,
+}
+
+/// An entry of an instruction's account list.
+#[derive(Debug, Clone, PartialEq)]
+pub struct IdlAccount {
+    /// The name of the account in the Rust struct.
+    pub name: &'static str,
+    #[allow(missing_docs)]
+    pub writable: bool,
+    #[allow(missing_docs)]
+    pub kind: IdlAccountKind,
+}
+
+/// An instruction of the program.
+#[derive(Debug, Clone, PartialEq)]
+pub struct IdlInstruction {
+    /// The name of the instruction's module and builder.
+    pub name: &'static str,
+    /// The first byte of the instruction data, which is followed by the Borsh serialized parameters.
+    pub discriminant: u8,
+    /// The accounts of the instruction, in order. None of them needs to sign, and the register account is always
+    /// last.
+    pub accounts: Vec<IdlAccount>,
+    /// The parameters of the instruction, in order.
+    pub args: Vec<IdlField>,
+}
+
+/// A type which is used by the parameters of the instructions.
+#[derive(Debug, Clone, PartialEq)]
+pub enum IdlTypeDef {
+    /// A struct, serialized as its fields in order.
+    Struct {
+        #[allow(missing_docs)]
+        name: &'static str,
+        #[allow(missing_docs)]
+        fields: Vec<IdlField>,
+    },
+    /// An enum without fields, serialized as the `u8` index of its variant.
+    Enum {
+        #[allow(missing_docs)]
+        name: &'static str,
+        #[allow(missing_docs)]
+        variants: Vec<&'static str>,
+    },
+}
+
+/// The description of the program's instructions.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Idl {
+    /// The version of the crate.
+    pub version: &'static str,
+    /// The instructions, sorted by discriminant.
+    pub instructions: Vec<IdlInstruction>,
+    /// The types used by the parameters of the instructions.
+    pub types: Vec<IdlTypeDef>,
+}
+
+trait IdlDescribe {
+    fn idl_type() -> IdlType;
+}
+
+/// Stands for the callback info type parameter of the instruction parameters.
+struct CallbackInfoArg;
+
+macro_rules! describe {
+    ($($t:ty => $idl_type:expr),* $(,)?) => {
+        $(impl IdlDescribe for $t {
+            fn idl_type() -> IdlType {
+                $idl_type
+            }
+        })*
+    };
+}
+
+describe!(
+    u8 => IdlType::U8,
+    u64 => IdlType::U64,
+    u128 => IdlType::U128,
+    i64 => IdlType::I64,
+    bool => IdlType::Bool,
+    Pubkey => IdlType::PublicKey,
+    CallbackInfoArg => IdlType::CallbackInfo,
+    new_order::Params<CallbackInfoArg> => IdlType::Defined("NewOrderParams"),
+    Side => IdlType::Defined("Side"),
+    SelfTradeBehavior => IdlType::Defined("SelfTradeBehavior"),
+    PostOnlyMode => IdlType::Defined("PostOnlyMode"),
+    PegReference => IdlType::Defined("PegReference"),
+    BootingPolicy => IdlType::Defined("BootingPolicy"),
+    TradingStatus => IdlType::Defined("TradingStatus"),
+    MatchingMode => IdlType::Defined("MatchingMode"),
+    EventQueueFullPolicy => IdlType::Defined("EventQueueFullPolicy"),
+);
+
+impl<T: IdlDescribe> IdlDescribe for Vec<T> {
+    fn idl_type() -> IdlType {
+        IdlType::Vec(Box::new(T::idl_type()))
+    }
+}
+
+impl<T: IdlDescribe, const N: usize> IdlDescribe for [T; N] {
+    fn idl_type() -> IdlType {
+        IdlType::Array(Box::new(T::idl_type()), N)
+    }
+}
+
+/// Describes the fields of a `Params` struct, which must be listed exhaustively and with their exact types.
+macro_rules! fields {
+    ($module:ident $(<$c:ty>)? { $($field:ident: $ty:ty),* $(,)? }) => {{
+        #[allow(dead_code, clippy::redundant_field_names)]
+        fn check(params: $module::Params$(<$c>)?) {
+            let $module::Params { $($field),* } = params;
+            $(let _: $ty = $field;)*
+        }
+        vec![$(IdlField {
+            name: stringify!($field),
+            ty: <$ty as IdlDescribe>::idl_type(),
+        }),*]
+    }};
+}
+
+macro_rules! check_account_kind {
+    (Single, $account:ident) => {
+        let _: &() = $account;
+    };
+    (Optional, $account:ident) => {
+        let _: Option<&()> = $account;
+    };
+    (Multiple, $account:ident) => {
+        let _: &[()] = $account;
+    };
+}
+
+macro_rules! is_writable {
+    (writable) => {
+        true
+    };
+    (readonly) => {
+        false
+    };
+}
+
+/// Describes the fields of an `Accounts` struct, which must be listed exhaustively and with their exact kinds, and
+/// appends the register account.
+macro_rules! accounts {
+    ($module:ident [ $($account:ident: $kind:ident($access:ident)),* $(,)? ]) => {{
+        #[allow(dead_code)]
+        fn check(accounts: $module::Accounts<'_, ()>) {
+            let $module::Accounts { $($account),* } = accounts;
+            $(check_account_kind!($kind, $account);)*
+        }
+        vec![
+            $(IdlAccount {
+                name: stringify!($account),
+                writable: is_writable!($access),
+                kind: IdlAccountKind::$kind,
+            },)*
+            IdlAccount {
+                name: "register",
+                writable: true,
+                kind: IdlAccountKind::Single,
+            },
+        ]
+    }};
+}
+
+macro_rules! instruction {
+    (
+        $variant:ident => $module:ident $(<$c:ty>)?
+        { $($field:ident: $ty:ty),* $(,)? }
+        [ $($account:ident: $kind:ident($access:ident)),* $(,)? ]
+    ) => {
+        IdlInstruction {
+            name: stringify!($module),
+            discriminant: AgnosticOrderbookInstruction::$variant as u8,
+            accounts: accounts!($module [$($account: $kind($access)),*]),
+            args: fields!($module $(<$c>)? { $($field: $ty),* }),
+        }
+    };
+}
+
+/// Describes an enum, whose variants must be listed exhaustively and in order.
+macro_rules! enum_def {
+    ($name:ident { $($variant:ident),* $(,)? }) => {{
+        const _: () = {
+            let mut index = 0;
+            $(
+                assert!($name::$variant as usize == index);
+                index += 1;
+            )*
+            let _ = index;
+        };
+        #[allow(dead_code)]
+        fn check(value: $name) {
+            match value {
+                $($name::$variant => ()),*
+            }
+        }
+        IdlTypeDef::Enum {
+            name: stringify!($name),
+            variants: vec![$(stringify!($variant)),*],
+        }
+    }};
+}
+
+/// Returns the description of the program's instructions.
+pub fn idl() -> Idl {
+    let mut instructions = vec![
+        instruction!(CreateMarket => create_market {
+            min_base_order_size: u64,
+            tick_size: u64,
+            min_quote_order_size: u64,
+            booting_policy: BootingPolicy,
+            split_fills: bool,
+            log_events: bool,
+            order_capacity: u64,
+            event_capacity: u64,
+            metadata: Vec<u8>,
+            quote_scale_exponent: u8,
+            max_price_deviation_bps: u64,
+            matching_mode: MatchingMode,
+            maker_fee_bps: u64,
+            taker_fee_bps: u64,
+            max_orders_per_owner: u64,
+            consumer_program: Pubkey,
+            max_events_per_consumer_call: u64,
+            event_queue_full_policy: EventQueueFullPolicy,
+        } [
+            market: Single(writable),
+            event_queue: Single(writable),
+            bids: Single(writable),
+            asks: Single(writable),
+            market_registry: Optional(writable),
+        ]),
+        instruction!(NewOrder => new_order<CallbackInfoArg> {
+            max_base_qty: u64,
+            max_quote_qty: u64,
+            limit_price: u64,
+            side: Side,
+            match_limit: u64,
+            callback_info: CallbackInfoArg,
+            post_only: bool,
+            post_allowed: bool,
+            self_trade_behavior: SelfTradeBehavior,
+            max_ts: u64,
+            immediate_or_cancel: bool,
+            fill_or_kill: bool,
+            max_display_qty: u64,
+            post_only_mode: PostOnlyMode,
+            max_slippage: u64,
+            flags: u8,
+            min_base_qty: u64,
+            compute_unit_reserve: u64,
+            resumable: bool,
+            worst_price: u64,
+            referral: [u8; 32],
+            client_order_id: u64,
+        } [
+            market: Single(writable),
+            event_queue: Single(writable),
+            bids: Single(writable),
+            asks: Single(writable),
+            allowlist: Optional(readonly),
+            event_queue_shards: Multiple(writable),
+        ]),
+        instruction!(ConsumeEvents => consume_events {
+            number_of_entries_to_consume: u64,
+            max_seq_num: u64,
+            write_to_register: bool,
+            queue_index: u64,
+        } [
+            market: Single(readonly),
+            event_queue: Single(writable),
+            consumer_program: Optional(readonly),
+            consumer_authority: Optional(readonly),
+            consumer_accounts: Multiple(writable),
+        ]),
+        instruction!(CancelOrder => cancel_order {
+            order_id: u128,
+        } [
+            market: Single(readonly),
+            event_queue: Single(writable),
+            bids: Single(writable),
+            asks: Single(writable),
+        ]),
+        instruction!(CloseMarket => close_market {} [
+            market: Single(writable),
+            event_queue: Single(writable),
+            bids: Single(writable),
+            asks: Single(writable),
+            lamports_target_account: Single(writable),
+            registered_accounts: Multiple(writable),
+        ]),
+        instruction!(MassCancelOrders => mass_cancel_orders {
+            order_ids: Vec<u128>,
+        } [
+            market: Single(readonly),
+            event_queue: Single(writable),
+            bids: Single(writable),
+            asks: Single(writable),
+            event_queue_shards: Multiple(writable),
+        ]),
+        instruction!(PruneOrders => prune_orders {
+            num_orders_to_prune: u64,
+        } [
+            market: Single(readonly),
+            event_queue: Single(writable),
+            bids: Single(writable),
+            asks: Single(writable),
+            event_queue_shards: Multiple(writable),
+        ]),
+        instruction!(PruneExpired => prune_expired {
+            num_orders_to_prune: u64,
+        } [
+            market: Single(readonly),
+            event_queue: Single(writable),
+            bids: Single(writable),
+            asks: Single(writable),
+            event_queue_shards: Multiple(writable),
+        ]),
+        instruction!(CreateStopOrders => create_stop_orders {} [
+            market: Single(writable),
+            stop_orders: Single(writable),
+        ]),
+        instruction!(NewStopOrder => new_stop_order<CallbackInfoArg> {
+            trigger_price: u64,
+            side: Side,
+            max_base_qty: u64,
+            max_quote_qty: u64,
+            limit_price: u64,
+            match_limit: u64,
+            max_slippage: u64,
+            self_trade_behavior: SelfTradeBehavior,
+            max_ts: u64,
+            callback_info: CallbackInfoArg,
+            flags: u8,
+        } [
+            market: Single(readonly),
+            event_queue: Single(writable),
+            stop_orders: Single(writable),
+            allowlist: Optional(readonly),
+            event_queue_shards: Multiple(writable),
+        ]),
+        instruction!(CancelStopOrder => cancel_stop_order {
+            order_id: u128,
+        } [
+            market: Single(readonly),
+            stop_orders: Single(writable),
+        ]),
+        instruction!(TriggerOrders => trigger_orders {
+            max_orders: u64,
+        } [
+            market: Single(writable),
+            event_queue: Single(writable),
+            bids: Single(writable),
+            asks: Single(writable),
+            stop_orders: Single(writable),
+            event_queue_shards: Multiple(writable),
+        ]),
+        instruction!(NewOrderBatch => new_order_batch<CallbackInfoArg> {
+            orders: Vec<new_order::Params<CallbackInfoArg>>,
+        } [
+            market: Single(writable),
+            event_queue: Single(writable),
+            bids: Single(writable),
+            asks: Single(writable),
+            allowlist: Optional(readonly),
+            event_queue_shards: Multiple(writable),
+        ]),
+        instruction!(ReplaceOrder => replace_order<CallbackInfoArg> {
+            order_id: u128,
+            new_order: new_order::Params<CallbackInfoArg>,
+        } [
+            market: Single(writable),
+            event_queue: Single(writable),
+            bids: Single(writable),
+            asks: Single(writable),
+            allowlist: Optional(readonly),
+            event_queue_shards: Multiple(writable),
+        ]),
+        instruction!(CancelOrdersByOwner => cancel_orders_by_owner {
+            callback_info_prefix: Vec<u8>,
+            max_cancels: u64,
+        } [
+            market: Single(readonly),
+            event_queue: Single(writable),
+            bids: Single(writable),
+            asks: Single(writable),
+            event_queue_shards: Multiple(writable),
+        ]),
+        instruction!(CreatePeggedOrders => create_pegged_orders {} [
+            market: Single(writable),
+            pegged_orders: Single(writable),
+        ]),
+        instruction!(NewPeggedOrder => new_pegged_order<CallbackInfoArg> {
+            side: Side,
+            reference: PegReference,
+            offset: i64,
+            max_base_qty: u64,
+            max_quote_qty: u64,
+            callback_info: CallbackInfoArg,
+            max_ts: u64,
+            flags: u8,
+        } [
+            market: Single(writable),
+            event_queue: Single(writable),
+            bids: Single(writable),
+            asks: Single(writable),
+            pegged_orders: Single(writable),
+            allowlist: Optional(readonly),
+            event_queue_shards: Multiple(writable),
+        ]),
+        instruction!(CancelPeggedOrder => cancel_pegged_order {
+            order_id: u128,
+        } [
+            market: Single(readonly),
+            bids: Single(writable),
+            asks: Single(writable),
+            pegged_orders: Single(writable),
+        ]),
+        instruction!(RepricePegged => reprice_pegged {
+            max_orders: u64,
+            start_key: u128,
+        } [
+            market: Single(writable),
+            event_queue: Single(writable),
+            bids: Single(writable),
+            asks: Single(writable),
+            pegged_orders: Single(writable),
+            event_queue_shards: Multiple(writable),
+        ]),
+        instruction!(CleanBook => clean_book {
+            max_orders: u64,
+        } [
+            market: Single(readonly),
+            event_queue: Single(writable),
+            bids: Single(writable),
+            asks: Single(writable),
+            event_queue_shards: Multiple(writable),
+        ]),
+        instruction!(ResizeMarket => resize_market {
+            order_capacity: u64,
+        } [
+            market: Single(readonly),
+            bids: Single(writable),
+            asks: Single(writable),
+        ]),
+        instruction!(VerifySlab => verify_slab {} [
+            market: Single(readonly),
+            bids: Single(readonly),
+            asks: Single(readonly),
+        ]),
+        instruction!(ResizeEventQueue => resize_event_queue {
+            event_capacity: u64,
+        } [
+            market: Single(readonly),
+            event_queue: Single(writable),
+        ]),
+        instruction!(CreateDeadLetterQueue => create_dead_letter_queue {
+            max_event_age_slots: u64,
+        } [
+            market: Single(writable),
+            dead_letter_queue: Single(writable),
+        ]),
+        instruction!(ExpireEvents => expire_events {
+            number_of_entries_to_expire: u64,
+        } [
+            market: Single(readonly),
+            event_queue: Single(writable),
+            dead_letter_queue: Single(writable),
+        ]),
+        instruction!(AddEventQueueShard => add_event_queue_shard {} [
+            market: Single(writable),
+            event_queue: Single(readonly),
+            new_event_queue_shard: Single(writable),
+            event_queue_shards: Multiple(readonly),
+        ]),
+        instruction!(SetMarketStatus => set_market_status {
+            status: TradingStatus,
+        } [
+            market: Single(writable),
+        ]),
+        instruction!(MigrateMarket => migrate_market {} [
+            market: Single(writable),
+            event_queue: Single(writable),
+            bids: Single(writable),
+            asks: Single(writable),
+            registered_accounts: Multiple(writable),
+        ]),
+        instruction!(SweepFees => sweep_fees {} [
+            market: Single(writable),
+            lamports_target_account: Single(writable),
+        ]),
+        instruction!(CreateMarketRegistry => create_market_registry {} [
+            market_registry: Single(writable),
+        ]),
+        instruction!(Poke => poke {} [
+            market: Single(writable),
+        ]),
+        instruction!(FixCrossedBook => fix_crossed_book {
+            max_orders: u64,
+        } [
+            market: Single(readonly),
+            event_queue: Single(writable),
+            bids: Single(writable),
+            asks: Single(writable),
+            event_queue_shards: Multiple(writable),
+        ]),
+        instruction!(RunAuction => run_auction {
+            match_limit: u64,
+            end_auction: bool,
+        } [
+            market: Single(writable),
+            event_queue: Single(writable),
+            bids: Single(writable),
+            asks: Single(writable),
+            event_queue_shards: Multiple(writable),
+        ]),
+        instruction!(ContinueMatch => continue_match {
+            order_id: u128,
+            match_limit: u64,
+            compute_unit_reserve: u64,
+            cancel: bool,
+        } [
+            market: Single(writable),
+            event_queue: Single(writable),
+            bids: Single(writable),
+            asks: Single(writable),
+            event_queue_shards: Multiple(writable),
+        ]),
+        instruction!(GetOrder => get_order {
+            order_id: u128,
+        } [
+            market: Single(readonly),
+            bids: Single(readonly),
+            asks: Single(readonly),
+        ]),
+        instruction!(QuoteSwap => quote_swap {
+            side: Side,
+            amount: u64,
+            exact_in: bool,
+            match_limit: u64,
+        } [
+            market: Single(readonly),
+            bids: Single(readonly),
+            asks: Single(readonly),
+        ]),
+        instruction!(CreateAllowlist => create_allowlist {} [
+            market: Single(writable),
+            allowlist: Single(writable),
+        ]),
+        instruction!(UpdateAllowlist => update_allowlist {
+            entry: [u8; 32],
+            allowed: bool,
+        } [
+            market: Single(readonly),
+            allowlist: Single(writable),
+        ]),
+        instruction!(MultiMarketCancel => multi_market_cancel {
+            order_ids: Vec<Vec<u128>>,
+        } [
+            market_accounts: Multiple(writable),
+        ]),
+        instruction!(CommitBook => commit_book {} [
+            market: Single(writable),
+            bids: Single(readonly),
+            asks: Single(readonly),
+        ]),
+        instruction!(CancelByClientId => cancel_by_client_id {
+            client_order_id: u64,
+            callback_info: Vec<u8>,
+        } [
+            market: Single(readonly),
+            event_queue: Single(writable),
+            bids: Single(writable),
+            asks: Single(writable),
+        ]),
+    ];
+    instructions.sort_by_key(|i| i.discriminant);
+
+    let new_order_params = instructions
+        .iter()
+        .find(|i| i.discriminant == AgnosticOrderbookInstruction::NewOrder as u8)
+        .unwrap()
+        .args
+        .clone();
+    let types = vec![
+        IdlTypeDef::Struct {
+            name: "NewOrderParams",
+            fields: new_order_params,
+        },
+        enum_def!(Side { Bid, Ask }),
+        enum_def!(SelfTradeBehavior {
+            DecrementTake,
+            CancelProvide,
+            AbortTransaction,
+            CancelBoth,
+            CancelLowestPriority,
+        }),
+        enum_def!(PostOnlyMode { Reject, Slide }),
+        enum_def!(PegReference {
+            SameSide,
+            OppositeSide
+        }),
+        enum_def!(BootingPolicy {
+            BootLeastAggressive,
+            RejectNewOrder,
+            BootSmallest,
+        }),
+        enum_def!(TradingStatus {
+            Active,
+            PostOnly,
+            CancelOnly,
+            Halted,
+        }),
+        enum_def!(MatchingMode {
+            Continuous,
+            Auction
+        }),
+        enum_def!(EventQueueFullPolicy {
+            Reject,
+            Overwrite,
+            LogOnly,
+        }),
+    ];
+
+    Idl {
+        version: env!("CARGO_PKG_VERSION"),
+        instructions,
+        types,
+    }
+}
+
+/// Converts a snake case Rust name into the camel case used by Anchor IDLs.
+fn camel_case(name: &str) -> String {
+    let mut words = name.split('_');
+    let mut result = words.next().unwrap_or_default().to_owned();
+    for word in words {
+        let mut chars = word.chars();
+        if let Some(first) = chars.next() {
+            result.extend(first.to_uppercase());
+            result.push_str(chars.as_str());
+        }
+    }
+    result
+}
+
+fn json_list<T>(items: &[T], f: impl Fn(&T) -> String) -> String {
+    format!("[{}]", items.iter().map(f).collect::<Vec<_>>().join(","))
+}
+
+impl IdlType {
+    fn to_json(&self) -> String {
+        match self {
+            IdlType::U8 => "\"u8\"".to_owned(),
+            IdlType::U64 => "\"u64\"".to_owned(),
+            IdlType::U128 => "\"u128\"".to_owned(),
+            IdlType::I64 => "\"i64\"".to_owned(),
+            IdlType::Bool => "\"bool\"".to_owned(),
+            IdlType::PublicKey => "\"publicKey\"".to_owned(),
+            IdlType::Vec(t) => format!("{{\"vec\":{}}}", t.to_json()),
+            IdlType::Array(t, len) => format!("{{\"array\":[{},{}]}}", t.to_json(), len),
+            IdlType::Defined(name) => format!("{{\"defined\":\"{}\"}}", name),
+            IdlType::CallbackInfo => "{\"defined\":\"CallbackInfo\"}".to_owned(),
+        }
+    }
+}
+
+impl IdlField {
+    fn to_json(&self) -> String {
+        format!(
+            "{{\"name\":\"{}\",\"type\":{}}}",
+            camel_case(self.name),
+            self.ty.to_json()
+        )
+    }
+}
+
+impl IdlAccount {
+    fn to_json(&self) -> String {
+        format!(
+            "{{\"name\":\"{}\",\"isMut\":{},\"isSigner\":false,\"isOptional\":{},\"isMultiple\":{}}}",
+            camel_case(self.name),
+            self.writable,
+            self.kind == IdlAccountKind::Optional,
+            self.kind == IdlAccountKind::Multiple
+        )
+    }
+}
+
+impl IdlInstruction {
+    fn to_json(&self) -> String {
+        format!(
+            "{{\"name\":\"{}\",\"discriminant\":{},\"accounts\":{},\"args\":{}}}",
+            camel_case(self.name),
+            self.discriminant,
+            json_list(&self.accounts, IdlAccount::to_json),
+            json_list(&self.args, IdlField::to_json)
+        )
+    }
+}
+
+impl IdlTypeDef {
+    fn to_json(&self) -> String {
+        match self {
+            IdlTypeDef::Struct { name, fields } => format!(
+                "{{\"name\":\"{}\",\"type\":{{\"kind\":\"struct\",\"fields\":{}}}}}",
+                name,
+                json_list(fields, IdlField::to_json)
+            ),
+            IdlTypeDef::Enum { name, variants } => format!(
+                "{{\"name\":\"{}\",\"type\":{{\"kind\":\"enum\",\"variants\":{}}}}}",
+                name,
+                json_list(variants, |v| format!("{{\"name\":\"{}\"}}", v))
+            ),
+        }
+    }
+}
+
+impl Idl {
+    /// Serializes the description into a JSON document which follows the layout of Anchor IDLs.
+    pub fn to_json(&self) -> String {
+        format!(
+            "{{\"version\":\"{}\",\"name\":\"agnostic_orderbook\",\"instructions\":{},\"types\":{}}}",
+            self.version,
+            json_list(&self.instructions, IdlInstruction::to_json),
+            json_list(&self.types, IdlTypeDef::to_json)
+        )
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use num_traits::FromPrimitive;
+
+    #[test]
+    fn test_idl() {
+        let idl = idl();
+
+        // Every instruction is described, once
+        for (index, instruction) in idl.instructions.iter().enumerate() {
+            assert_eq!(instruction.discriminant as usize, index);
+        }
+        assert!(AgnosticOrderbookInstruction::from_u64(idl.instructions.len() as u64).is_none());
+
+        let new_order = &idl.instructions[AgnosticOrderbookInstruction::NewOrder as usize];
+        assert_eq!(new_order.name, "new_order");
+        assert_eq!(new_order.accounts.last().unwrap().name, "register");
+        assert_eq!(
+            new_order.args[5],
+            IdlField {
+                name: "callback_info",
+                ty: IdlType::CallbackInfo
+            }
+        );
+
+        let json = idl.to_json();
+        assert!(json.contains("{\"name\":\"orderIds\",\"type\":{\"vec\":\"u128\"}}"));
+        assert!(json.contains("{\"name\":\"referral\",\"type\":{\"array\":[\"u8\",32]}}"));
+        assert!(json.contains("{\"name\":\"Side\",\"type\":{\"kind\":\"enum\",\"variants\":[{\"name\":\"Bid\"},{\"name\":\"Ask\"}]}}"));
+    }
+}
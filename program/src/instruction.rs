@@ -8,8 +8,14 @@ use solana_program::{
 use bonfida_utils::{BorshSize, InstructionsAccount};
 
 pub use crate::processor::{
-    cancel_order, close_market, consume_events, create_market, mass_cancel_orders, new_order,
-    prune_orders,
+    add_event_queue_shard, cancel_by_client_id, cancel_order, cancel_orders_by_owner,
+    cancel_pegged_order, cancel_stop_order, clean_book, close_market, commit_book, consume_events,
+    continue_match, create_allowlist, create_dead_letter_queue, create_market,
+    create_market_registry, create_pegged_orders, create_stop_orders, expire_events,
+    fix_crossed_book, get_order, mass_cancel_orders, migrate_market, multi_market_cancel,
+    new_order, new_order_batch, new_pegged_order, new_stop_order, poke, prune_expired,
+    prune_orders, quote_swap, replace_order, reprice_pegged, resize_event_queue, resize_market,
+    run_auction, set_market_status, sweep_fees, trigger_orders, update_allowlist, verify_slab,
 };
 
 #[derive(BorshDeserialize, BorshSerialize, FromPrimitive)]
@@ -25,6 +31,7 @@ pub enum AgnosticOrderbookInstruction {
     /// | 1     | ✅       | ❌     | A zeroed out event queue account |
     /// | 2     | ✅       | ❌     | A zeroed out bids account        |
     /// | 3     | ✅       | ❌     | A zeroed out asks account        |
+    /// | 4     | ✅       | ❌     | The market registry (optional)   |
     CreateMarket,
     /// Execute a new order on the orderbook.
     ///
@@ -40,18 +47,26 @@ pub enum AgnosticOrderbookInstruction {
     /// | 1     | ✅       | ❌     | The event queue account |
     /// | 2     | ✅       | ❌     | The bids account        |
     /// | 3     | ✅       | ❌     | The asks account        |
-    /// | 4     | ❌       | ✅     | The caller authority    |
     NewOrder,
-    /// Pop a series of events off the event queue.
+    /// Pop a series of events off the event queue, or off the dead letter queue when one is given in place of the
+    /// event queue.
     ///
     /// Required accounts
     ///
-    /// | index | writable | signer | description                  |
-    /// |-------|----------|--------|------------------------------|
-    /// | 0     | ✅       | ❌     | The market account           |
-    /// | 1     | ✅       | ❌     | The event queue account      |
-    /// | 3     | ❌       | ✅     | The caller authority         |
-    /// | 4     | ✅       | ❌     | The reward target account    |
+    /// | index | writable | signer | description                                            |
+    /// |-------|----------|--------|--------------------------------------------------------|
+    /// | 0     | ❌       | ❌     | The market account                                     |
+    /// | 1     | ✅       | ❌     | The event queue account                                |
+    /// | 2     | ❌       | ❌     | The consumer program, if the market has one            |
+    /// | 3     | ❌       | ❌     | The consumer authority, if the market has a consumer   |
+    /// | 4..   | ✅       | ❌     | The accounts passed on to the consumer program, if any |
+    ///
+    /// When the market has a consumer program, the popped events are pushed into it through CPI, see
+    /// [`consume_events`][`crate::processor::consume_events`].
+    ///
+    /// No signature is required: the instruction is only gated by the ownership of the accounts, so the calling
+    /// program decides whether cranking is permissionless. The AOB doesn't hold any funds, so cranker rewards are
+    /// left to the calling program.
     ConsumeEvents,
     /// Cancel an existing order in the orderbook.
     ///
@@ -63,23 +78,28 @@ pub enum AgnosticOrderbookInstruction {
     /// | 1     | ✅       | ❌     | The event queue account |
     /// | 2     | ✅       | ❌     | The bids account        |
     /// | 3     | ✅       | ❌     | The asks account        |
-    /// | 4     | ❌       | ✅     | The caller authority    |
     CancelOrder,
     /// Close an existing market.
     ///
+    /// The orderbook, the event queue and all the other accounts registered on the market except the allowlist must be
+    /// empty. The accounts are tagged as disabled, their data is zeroed out and their lamports are transferred to the
+    /// target account. The market is removed from its market registry, which is left open.
+    ///
     /// Required accounts
     ///
-    /// | index | writable | signer   | description                 |
-    /// |-------|----------|----------|-----------------------------|
-    /// | 0     | ✅        | ❌      | The market account          |
-    /// | 1     | ✅        | ❌      | The event queue account     |
-    /// | 2     | ✅        | ❌      | The bids account            |
-    /// | 3     | ✅        | ❌      | The asks account            |
-    /// | 4     | ❌        | ✅      | The caller authority        |
-    /// | 5     | ✅        | ❌      | The lamports target account |
+    /// | index | writable | signer   | description                                                                               |
+    /// |-------|----------|----------|-------------------------------------------------------------------------------------------|
+    /// | 0     | ✅        | ❌      | The market account                                                                        |
+    /// | 1     | ✅        | ❌      | The event queue account                                                                   |
+    /// | 2     | ✅        | ❌      | The bids account                                                                          |
+    /// | 3     | ✅        | ❌      | The asks account                                                                          |
+    /// | 4     | ✅        | ❌      | The lamports target account                                                               |
+    /// | 5..   | ✅        | ❌      | The stop orders, pegged orders, allowlist, dead letter queue, shard and registry accounts |
     CloseMarket,
     /// Cancel a series of existing orders in the orderbook.
     ///
+    /// An Out event is pushed onto the event queue for each cancelled order.
+    ///
     /// Required accounts
     ///
     /// | index | writable | signer | description             |
@@ -88,7 +108,6 @@ pub enum AgnosticOrderbookInstruction {
     /// | 1     | ✅       | ❌     | The event queue account |
     /// | 2     | ✅       | ❌     | The bids account        |
     /// | 3     | ✅       | ❌     | The asks account        |
-    /// | 4     | ❌       | ✅     | The caller authority    |
     MassCancelOrders,
     /// Prune orders from the orderbook.
     ///
@@ -100,8 +119,439 @@ pub enum AgnosticOrderbookInstruction {
     /// | 1     | ✅       | ❌     | The event queue account |
     /// | 2     | ✅       | ❌     | The bids account        |
     /// | 3     | ✅       | ❌     | The asks account        |
-    /// | 4     | ❌       | ✅     | The caller authority    |
     PruneOrders,
+    /// Remove expired orders from the orderbook.
+    ///
+    /// Orders whose max timestamp is strictly lower than the current clock's unix timestamp are removed,
+    /// and an Out event is pushed onto the event queue for each one of them.
+    ///
+    /// Required accounts
+    ///
+    /// | index | writable | signer | description             |
+    /// |-------|----------|--------|-------------------------|
+    /// | 0     | ✅       | ❌     | The market account      |
+    /// | 1     | ✅       | ❌     | The event queue account |
+    /// | 2     | ✅       | ❌     | The bids account        |
+    /// | 3     | ✅       | ❌     | The asks account        |
+    PruneExpired,
+    /// Create and initialize the stop orders account of an existing market.
+    ///
+    /// Required accounts
+    ///
+    /// | index | writable | signer | description                      |
+    /// |-------|----------|--------|----------------------------------|
+    /// | 0     | ✅       | ❌     | The market account               |
+    /// | 1     | ✅       | ❌     | A zeroed out stop orders account |
+    CreateStopOrders,
+    /// Place a new stop order, which is kept aside until the market's last trade price reaches its trigger price.
+    ///
+    /// Required accounts
+    ///
+    /// | index | writable | signer | description              |
+    /// |-------|----------|--------|--------------------------|
+    /// | 0     | ❌       | ❌     | The market account       |
+    /// | 1     | ✅       | ❌     | The event queue account  |
+    /// | 2     | ✅       | ❌     | The stop orders account  |
+    NewStopOrder,
+    /// Cancel an existing stop order.
+    ///
+    /// Required accounts
+    ///
+    /// | index | writable | signer | description              |
+    /// |-------|----------|--------|--------------------------|
+    /// | 0     | ❌       | ❌     | The market account       |
+    /// | 1     | ✅       | ❌     | The stop orders account  |
+    CancelStopOrder,
+    /// Execute the stop orders which have been triggered by the market's last trade price.
+    ///
+    /// Triggered stop market orders are matched against the orderbook as immediate-or-cancel orders, and the remainder
+    /// of triggered stop limit orders is posted in the orderbook. An Out event holding the unfilled quantity is pushed
+    /// onto the event queue for each one of them.
+    ///
+    /// Required accounts
+    ///
+    /// | index | writable | signer | description              |
+    /// |-------|----------|--------|--------------------------|
+    /// | 0     | ✅       | ❌     | The market account       |
+    /// | 1     | ✅       | ❌     | The event queue account  |
+    /// | 2     | ✅       | ❌     | The bids account         |
+    /// | 3     | ✅       | ❌     | The asks account         |
+    /// | 4     | ✅       | ❌     | The stop orders account  |
+    TriggerOrders,
+    /// Execute a series of new orders on the orderbook.
+    ///
+    /// The orders are executed sequentially, as if sent through individual new_order instructions.
+    /// One order summary per order is written back into the register account.
+    ///
+    /// Required accounts
+    ///
+    /// | index | writable | signer | description             |
+    /// |-------|----------|--------|-------------------------|
+    /// | 0     | ✅       | ❌     | The market account      |
+    /// | 1     | ✅       | ❌     | The event queue account |
+    /// | 2     | ✅       | ❌     | The bids account        |
+    /// | 3     | ✅       | ❌     | The asks account        |
+    NewOrderBatch,
+    /// Atomically cancel an existing order and execute a new order in its place.
+    ///
+    /// The existing order keeps its priority in the orderbook when only its size is decreased at the same price.
+    ///
+    /// Required accounts
+    ///
+    /// | index | writable | signer | description             |
+    /// |-------|----------|--------|-------------------------|
+    /// | 0     | ✅       | ❌     | The market account      |
+    /// | 1     | ✅       | ❌     | The event queue account |
+    /// | 2     | ✅       | ❌     | The bids account        |
+    /// | 3     | ✅       | ❌     | The asks account        |
+    ReplaceOrder,
+    /// Cancel the orders whose callback information starts with a given prefix, on both sides of the orderbook.
+    ///
+    /// An Out event is pushed onto the event queue for each cancelled order. A prefix which spans the whole callback
+    /// information is looked up through the owner index of the slabs instead of scanning the orderbook.
+    ///
+    /// Required accounts
+    ///
+    /// | index | writable | signer | description             |
+    /// |-------|----------|--------|-------------------------|
+    /// | 0     | ❌       | ❌     | The market account      |
+    /// | 1     | ✅       | ❌     | The event queue account |
+    /// | 2     | ✅       | ❌     | The bids account        |
+    /// | 3     | ✅       | ❌     | The asks account        |
+    CancelOrdersByOwner,
+    /// Create and initialize the pegged orders account of an existing market.
+    ///
+    /// Required accounts
+    ///
+    /// | index | writable | signer | description                        |
+    /// |-------|----------|--------|------------------------------------|
+    /// | 0     | ✅       | ❌     | The market account                 |
+    /// | 1     | ✅       | ❌     | A zeroed out pegged orders account |
+    CreatePeggedOrders,
+    /// Post a new pegged order, whose price is derived from the best price of one of the sides of the orderbook.
+    ///
+    /// The order is posted as a post only order which slides behind the spread if it would cross it.
+    ///
+    /// Required accounts
+    ///
+    /// | index | writable | signer | description               |
+    /// |-------|----------|--------|---------------------------|
+    /// | 0     | ✅       | ❌     | The market account        |
+    /// | 1     | ✅       | ❌     | The event queue account   |
+    /// | 2     | ✅       | ❌     | The bids account          |
+    /// | 3     | ✅       | ❌     | The asks account          |
+    /// | 4     | ✅       | ❌     | The pegged orders account |
+    NewPeggedOrder,
+    /// Cancel an existing pegged order.
+    ///
+    /// Required accounts
+    ///
+    /// | index | writable | signer | description               |
+    /// |-------|----------|--------|---------------------------|
+    /// | 0     | ❌       | ❌     | The market account        |
+    /// | 1     | ✅       | ❌     | The bids account          |
+    /// | 2     | ✅       | ❌     | The asks account          |
+    /// | 3     | ✅       | ❌     | The pegged orders account |
+    CancelPeggedOrder,
+    /// Move the pegged orders of a market to their current peg price.
+    ///
+    /// Pegged orders which are no longer in the orderbook are forgotten, and pegged orders which can't be posted again
+    /// at their new price are taken out of the orderbook with an out event.
+    ///
+    /// Required accounts
+    ///
+    /// | index | writable | signer | description               |
+    /// |-------|----------|--------|---------------------------|
+    /// | 0     | ✅       | ❌     | The market account        |
+    /// | 1     | ✅       | ❌     | The event queue account   |
+    /// | 2     | ✅       | ❌     | The bids account          |
+    /// | 3     | ✅       | ❌     | The asks account          |
+    /// | 4     | ✅       | ❌     | The pegged orders account |
+    RepricePegged,
+    /// Remove the orders whose remaining quantity is below the market's minimum order size from both sides of the
+    /// orderbook, and put them on the event queue as cancelled orders.
+    ///
+    /// Required accounts
+    ///
+    /// | index | writable | signer | description             |
+    /// |-------|----------|--------|-------------------------|
+    /// | 0     | ❌       | ❌     | The market account      |
+    /// | 1     | ✅       | ❌     | The event queue account |
+    /// | 2     | ✅       | ❌     | The bids account        |
+    /// | 3     | ✅       | ❌     | The asks account        |
+    CleanBook,
+    /// Grow the bids and asks accounts of an existing market, preserving the orders they hold.
+    ///
+    /// The accounts should be funded beforehand so as to remain rent exempt at their new size.
+    ///
+    /// Required accounts
+    ///
+    /// | index | writable | signer | description        |
+    /// |-------|----------|--------|--------------------|
+    /// | 0     | ❌       | ❌     | The market account |
+    /// | 1     | ✅       | ❌     | The bids account   |
+    /// | 2     | ✅       | ❌     | The asks account   |
+    ResizeMarket,
+    /// Walk the bids and asks trees of a market and fail with [`AoError::CorruptedSlab`][`crate::error::AoError::CorruptedSlab`]
+    /// if they are inconsistent. The detected [`SlabCorruption`][`crate::state::critbit::SlabCorruption`] is logged.
+    ///
+    /// Required accounts
+    ///
+    /// | index | writable | signer | description        |
+    /// |-------|----------|--------|--------------------|
+    /// | 0     | ❌       | ❌     | The market account |
+    /// | 1     | ❌       | ❌     | The bids account   |
+    /// | 2     | ❌       | ❌     | The asks account   |
+    VerifySlab,
+    /// Grow the event queue of an existing market, preserving the events it holds.
+    ///
+    /// The account should be funded beforehand so as to remain rent exempt at its new size.
+    ///
+    /// Required accounts
+    ///
+    /// | index | writable | signer | description             |
+    /// |-------|----------|--------|-------------------------|
+    /// | 0     | ❌       | ❌     | The market account      |
+    /// | 1     | ✅       | ❌     | The event queue account |
+    ResizeEventQueue,
+    /// Initialize the dead letter queue account of an existing market, which enables event expiry.
+    ///
+    /// The dead letter queue account has the same layout as the event queue, and can be consumed with the
+    /// [`ConsumeEvents`][`AgnosticOrderbookInstruction::ConsumeEvents`] instruction.
+    ///
+    /// Required accounts
+    ///
+    /// | index | writable | signer | description                   |
+    /// |-------|----------|--------|-------------------------------|
+    /// | 0     | ✅       | ❌     | The market account            |
+    /// | 1     | ✅       | ❌     | The dead letter queue account |
+    CreateDeadLetterQueue,
+    /// Move the events which have been pending on the event queue for more than the market's `max_event_age_slots`
+    /// to the dead letter queue, so that the event queue doesn't remain full when it isn't cranked.
+    ///
+    /// Required accounts
+    ///
+    /// | index | writable | signer | description                   |
+    /// |-------|----------|--------|-------------------------------|
+    /// | 0     | ❌       | ❌     | The market account            |
+    /// | 1     | ✅       | ❌     | The event queue account       |
+    /// | 2     | ✅       | ❌     | The dead letter queue account |
+    ExpireEvents,
+    /// Initialize an event queue shard and register it on an existing market. The events are then split between the
+    /// event queue and its shards according to a hash of their maker callback info, so that they can be consumed in
+    /// parallel.
+    ///
+    /// The event queue and the registered shards must be empty. Once a market has shards, all the instructions which
+    /// push events expect the shard accounts after their other accounts, in the order in which they were registered.
+    ///
+    /// Required accounts
+    ///
+    /// | index | writable | signer | description                           |
+    /// |-------|----------|--------|---------------------------------------|
+    /// | 0     | ✅       | ❌     | The market account                    |
+    /// | 1     | ❌       | ❌     | The event queue account               |
+    /// | 2     | ✅       | ❌     | The new event queue shard account     |
+    /// | 3..   | ❌       | ❌     | The registered event queue shards     |
+    AddEventQueueShard,
+    /// Change the trading status of an existing market, which restricts the operations it allows.
+    ///
+    /// See [`TradingStatus`][`crate::state::TradingStatus`].
+    ///
+    /// Required accounts
+    ///
+    /// | index | writable | signer | description        |
+    /// |-------|----------|--------|--------------------|
+    /// | 0     | ✅       | ❌     | The market account |
+    SetMarketStatus,
+    /// Upgrade the accounts of an existing market to the current layout version, in place.
+    ///
+    /// The layout version of an account is stored next to its tag. Markets created before versioning was introduced
+    /// must be migrated before any other instruction can use them.
+    ///
+    /// The market account is grown to the current [`MarketState`](`crate::state::market_state::MarketState`) size if
+    /// needed, and should be funded beforehand so as to remain rent exempt. The same goes for the slab and event queue
+    /// accounts, which are grown to hold their orders and events with the current layout.
+    ///
+    /// Required accounts
+    ///
+    /// | index | writable | signer | description                                                                               |
+    /// |-------|----------|--------|-------------------------------------------------------------------------------------------|
+    /// | 0     | ✅       | ❌     | The market account                                                                        |
+    /// | 1     | ✅       | ❌     | The event queue account                                                                   |
+    /// | 2     | ✅       | ❌     | The bids account                                                                          |
+    /// | 3     | ✅       | ❌     | The asks account                                                                          |
+    /// | 4..   | ✅       | ❌     | The stop orders, pegged orders, allowlist, dead letter queue, shard and registry accounts |
+    MigrateMarket,
+    /// Transfer the lamports held by the market account above its rent exemption threshold to the target account.
+    ///
+    /// Required accounts
+    ///
+    /// | index | writable | signer | description                 |
+    /// |-------|----------|--------|-----------------------------|
+    /// | 0     | ✅       | ❌     | The market account          |
+    /// | 1     | ✅       | ❌     | The lamports target account |
+    SweepFees,
+    /// Initialize a market registry account, which lists the markets created with it.
+    ///
+    /// Markets are appended to the registry when it is passed to create_market, and removed from it when they are
+    /// closed. The size of the account determines how many markets it can hold, see
+    /// [`MarketRegistry::compute_allocation_size`](`crate::state::market_registry::MarketRegistry::compute_allocation_size`).
+    ///
+    /// Required accounts
+    ///
+    /// | index | writable | signer | description                          |
+    /// |-------|----------|--------|--------------------------------------|
+    /// | 0     | ✅       | ❌     | A zeroed out market registry account |
+    CreateMarketRegistry,
+    /// Accumulate the last trade price of the market over the time elapsed since the previous fill or poke.
+    ///
+    /// Readers of [`MarketState::cumulative_price_time`](`crate::state::market_state::MarketState::cumulative_price_time`)
+    /// should poke the market beforehand so that the accumulator is up to date.
+    ///
+    /// Required accounts
+    ///
+    /// | index | writable | signer | description        |
+    /// |-------|----------|--------|--------------------|
+    /// | 0     | ✅       | ❌     | The market account |
+    Poke,
+    /// Uncross an orderbook whose best bid is priced at or above its best ask.
+    ///
+    /// Crossing orders are always matched, so this can only happen as the result of a bug. While the orderbook is
+    /// crossed, the more recent of the best bid and the best ask is removed and an Out event is pushed for it. This
+    /// instruction is meant as a recovery tool, and access to it should be restricted by the caller program.
+    ///
+    /// Required accounts
+    ///
+    /// | index | writable | signer | description             |
+    /// |-------|----------|--------|-------------------------|
+    /// | 0     | ❌       | ❌     | The market account      |
+    /// | 1     | ✅       | ❌     | The event queue account |
+    /// | 2     | ✅       | ❌     | The bids account        |
+    /// | 3     | ✅       | ❌     | The asks account        |
+    FixCrossedBook,
+    /// Match the crossed orders of an auction market at a single clearing price, which maximizes the matched
+    /// quantity (see [`OrderBookState::compute_clearing_price`](`crate::state::orderbook::OrderBookState::compute_clearing_price`)).
+    ///
+    /// Both sides of each match are resting orders, so each match pushes a maker fill event for each of them. The
+    /// market can optionally be switched to continuous matching once the orderbook is no longer crossed, for instance
+    /// at the end of an opening auction.
+    ///
+    /// Required accounts
+    ///
+    /// | index | writable | signer | description             |
+    /// |-------|----------|--------|-------------------------|
+    /// | 0     | ✅       | ❌     | The market account      |
+    /// | 1     | ✅       | ❌     | The event queue account |
+    /// | 2     | ✅       | ❌     | The bids account        |
+    /// | 3     | ✅       | ❌     | The asks account        |
+    RunAuction,
+    /// Continue matching a resumable order whose matching was interrupted by its match limit or compute unit reserve,
+    /// or cancel it.
+    ///
+    /// The order is parked again if matching is interrupted once more. Otherwise, its unmatched remainder is posted
+    /// with the same order id, or reported as unfilled. The quantities of the order summary only cover this
+    /// instruction.
+    ///
+    /// Required accounts
+    ///
+    /// | index | writable | signer | description             |
+    /// |-------|----------|--------|-------------------------|
+    /// | 0     | ✅       | ❌     | The market account      |
+    /// | 1     | ✅       | ❌     | The event queue account |
+    /// | 2     | ✅       | ❌     | The bids account        |
+    /// | 3     | ✅       | ❌     | The asks account        |
+    ContinueMatch,
+    /// Write the best bid and ask price levels of the orderbook, ignoring expired orders, and optionally the state of
+    /// an order into the register account, as a [`Quote`][`crate::state::orderbook::Quote`].
+    ///
+    /// The order is omitted from the quote when it isn't in the orderbook anymore.
+    ///
+    /// Required accounts
+    ///
+    /// | index | writable | signer | description        |
+    /// |-------|----------|--------|--------------------|
+    /// | 0     | ❌       | ❌     | The market account |
+    /// | 1     | ❌       | ❌     | The bids account   |
+    /// | 2     | ❌       | ❌     | The asks account   |
+    GetOrder,
+    /// Walk the opposite side of the orderbook to quote a swap for an exact input or output amount, without modifying
+    /// the orderbook, and write the quote into the register account as a
+    /// [`SwapQuote`][`crate::state::orderbook::SwapQuote`]. Expired orders are skipped.
+    ///
+    /// Required accounts
+    ///
+    /// | index | writable | signer | description        |
+    /// |-------|----------|--------|--------------------|
+    /// | 0     | ❌       | ❌     | The market account |
+    /// | 1     | ❌       | ❌     | The bids account   |
+    /// | 2     | ❌       | ❌     | The asks account   |
+    QuoteSwap,
+    /// Initialize the allowlist account of an existing market, which makes order placement permissioned.
+    ///
+    /// Once the market has an allowlist, the order placing instructions only accept the orders whose callback info
+    /// is authorized by it, and must be given the allowlist account right before the event queue shards.
+    ///
+    /// Required accounts
+    ///
+    /// | index | writable | signer | description           |
+    /// |-------|----------|--------|-----------------------|
+    /// | 0     | ✅       | ❌     | The market account    |
+    /// | 1     | ✅       | ❌     | The allowlist account |
+    CreateAllowlist,
+    /// Add an entry to the allowlist of a market, or remove one from it.
+    ///
+    /// Required accounts
+    ///
+    /// | index | writable | signer | description           |
+    /// |-------|----------|--------|-----------------------|
+    /// | 0     | ❌       | ❌     | The market account    |
+    /// | 1     | ✅       | ❌     | The allowlist account |
+    UpdateAllowlist,
+    /// Cancel series of existing orders across several markets, so that quotes can be pulled from all of them
+    /// atomically.
+    ///
+    /// The orders of each market are cancelled as by a
+    /// [`MassCancelOrders`][`AgnosticOrderbookInstruction::MassCancelOrders`] instruction, and the order summary of
+    /// each market is written into the register account as a `Vec<OrderSummary>`.
+    ///
+    /// Required accounts, for each market in the order of the cancel lists
+    ///
+    /// | index | writable | signer | description                                          |
+    /// |-------|----------|--------|------------------------------------------------------|
+    /// | 0     | ✅       | ❌     | The market account                                   |
+    /// | 1     | ✅       | ❌     | The event queue account                              |
+    /// | 2     | ✅       | ❌     | The bids account                                     |
+    /// | 3     | ✅       | ❌     | The asks account                                     |
+    /// | 4..   | ✅       | ❌     | The event queue shards of the market, if it has any  |
+    MultiMarketCancel,
+    /// Write the Merkle roots over the price levels of the bids and asks of a market into the market state, along with
+    /// the current slot, so that the resting orders can be proven individually against them.
+    ///
+    /// The roots are a snapshot which isn't maintained as the book changes, so they are only as fresh as the last
+    /// commit_book instruction. The instruction fails when the book holds more than
+    /// [`MAX_COMMITTED_ORDERS`][`crate::processor::commit_book::MAX_COMMITTED_ORDERS`] orders. See
+    /// [`merkle`][`crate::state::merkle`].
+    ///
+    /// Required accounts
+    ///
+    /// | index | writable | signer | description        |
+    /// |-------|----------|--------|--------------------|
+    /// | 0     | ✅       | ❌     | The market account |
+    /// | 1     | ❌       | ❌     | The bids account   |
+    /// | 2     | ❌       | ❌     | The asks account   |
+    CommitBook,
+    /// Cancel an existing order in the orderbook, identified by the client order id which its owner attached to it
+    /// at placement rather than by its order id. An owner can't hold two orders with the same non-zero client order id.
+    ///
+    /// Required accounts
+    ///
+    /// | index | writable | signer | description             |
+    /// |-------|----------|--------|-------------------------|
+    /// | 0     | ✅       | ❌     | The market account      |
+    /// | 1     | ✅       | ❌     | The event queue account |
+    /// | 2     | ✅       | ❌     | The bids account        |
+    /// | 3     | ✅       | ❌     | The asks account        |
+    CancelByClientId,
 }
 
 /**
@@ -111,13 +561,11 @@ The event_queue, bids, and asks accounts should be freshly allocated or zeroed o
 
 * The market account will only contain a [`MarketState`](`crate::state::MarketState`) object and should be sized appropriately.
 
-* The event queue will contain an [`EventQueueHeader`](`crate::state::EventQueueHeader`) object followed by a return register sized for a [`OrderSummary`](`crate::orderbook::OrderSummary`)
-(size of [`ORDER_SUMMARY_SIZE`](`crate::orderbook::ORDER_SUMMARY_SIZE`)) and then a series of events [`Event`](`crate::state::Event`). The serialized size of an [`Event`](`crate::state::Event`) object
-is given by [`compute_slot_size`](`crate::state::Event::compute_slot_size`) The size of the queue should be determined
-accordingly.
+* The event queue should be sized with [`compute_event_queue_size`](`crate::state::event_queue::compute_event_queue_size`)
+for the desired event capacity and callback info length, and must hold at least `params.event_capacity` events.
 
-* The asks and bids accounts will contain a header of size [`SLAB_HEADER_LEN`][`crate::critbit::SLAB_HEADER_LEN`] followed by a series of slots of size
-[`compute_slot_size(callback_info_len)`][`crate::critbit::Slab::compute_slot_size`].
+* The asks and bids accounts should be sized with [`compute_slab_size`](`crate::state::critbit::compute_slab_size`)
+for the desired order capacity and callback info length, and must hold at least `params.order_capacity` orders.
 */
 pub fn create_market(
     accounts: create_market::Accounts<Pubkey>,
@@ -163,6 +611,10 @@ pub fn new_order<C: BorshSerialize + BorshSize>(
 }
 
 /// Cancel an existing order in the orderbook.
+///
+/// The cancelled order's remaining quantities, limit price and callback info are written into the register account,
+/// from which they can be read back with [`read_cancelled_order`][`crate::state::orderbook::read_cancelled_order`].
+/// The register account must hold at least `69 + size_of::<C>()` bytes.
 pub fn cancel_order(
     accounts: cancel_order::Accounts<Pubkey>,
     register_account: Pubkey,
@@ -221,7 +673,7 @@ pub fn close_market(
     i
 }
 
-/// Create and initialize a new orderbook market
+/// Cancel a series of existing orders in the orderbook.
 pub fn mass_cancel_orders(
     accounts: mass_cancel_orders::Accounts<Pubkey>,
     register_account: Pubkey,
@@ -229,7 +681,7 @@ pub fn mass_cancel_orders(
 ) -> Instruction {
     let mut i = accounts.get_instruction(
         crate::id(),
-        AgnosticOrderbookInstruction::CloseMarket as u8,
+        AgnosticOrderbookInstruction::MassCancelOrders as u8,
         params,
     );
     i.accounts.push(AccountMeta {
@@ -258,3 +710,668 @@ pub fn prune_orders(
     });
     i
 }
+
+/// Remove expired orders from the order book
+pub fn prune_expired(
+    accounts: prune_expired::Accounts<Pubkey>,
+    register_account: Pubkey,
+    params: prune_expired::Params,
+) -> Instruction {
+    let mut i = accounts.get_instruction(
+        crate::id(),
+        AgnosticOrderbookInstruction::PruneExpired as u8,
+        params,
+    );
+    i.accounts.push(AccountMeta {
+        pubkey: register_account,
+        is_signer: false,
+        is_writable: true,
+    });
+    i
+}
+
+/// Create and initialize the stop orders account of an existing market
+pub fn create_stop_orders(
+    accounts: create_stop_orders::Accounts<Pubkey>,
+    register_account: Pubkey,
+    params: create_stop_orders::Params,
+) -> Instruction {
+    let mut i = accounts.get_instruction(
+        crate::id(),
+        AgnosticOrderbookInstruction::CreateStopOrders as u8,
+        params,
+    );
+    i.accounts.push(AccountMeta {
+        pubkey: register_account,
+        is_signer: false,
+        is_writable: true,
+    });
+    i
+}
+
+/// Place a new stop order
+pub fn new_stop_order<C: BorshSerialize + BorshSize>(
+    accounts: new_stop_order::Accounts<Pubkey>,
+    register_account: Pubkey,
+    params: new_stop_order::Params<C>,
+) -> Instruction {
+    let mut i = accounts.get_instruction(
+        crate::id(),
+        AgnosticOrderbookInstruction::NewStopOrder as u8,
+        params,
+    );
+    i.accounts.push(AccountMeta {
+        pubkey: register_account,
+        is_signer: false,
+        is_writable: true,
+    });
+    i
+}
+
+/// Cancel an existing stop order
+pub fn cancel_stop_order(
+    accounts: cancel_stop_order::Accounts<Pubkey>,
+    register_account: Pubkey,
+    params: cancel_stop_order::Params,
+) -> Instruction {
+    let mut i = accounts.get_instruction(
+        crate::id(),
+        AgnosticOrderbookInstruction::CancelStopOrder as u8,
+        params,
+    );
+    i.accounts.push(AccountMeta {
+        pubkey: register_account,
+        is_signer: false,
+        is_writable: true,
+    });
+    i
+}
+
+/// Execute the stop orders which have been triggered by the last trade price
+pub fn trigger_orders(
+    accounts: trigger_orders::Accounts<Pubkey>,
+    register_account: Pubkey,
+    params: trigger_orders::Params,
+) -> Instruction {
+    let mut i = accounts.get_instruction(
+        crate::id(),
+        AgnosticOrderbookInstruction::TriggerOrders as u8,
+        params,
+    );
+    i.accounts.push(AccountMeta {
+        pubkey: register_account,
+        is_signer: false,
+        is_writable: true,
+    });
+    i
+}
+
+/**
+Execute a series of new orders on the orderbook.
+
+The register account receives a serialized `Vec<OrderSummary>`, and should therefore be at least
+[`compute_register_size(n)`][`crate::state::orderbook::compute_register_size`] bytes long for a batch of `n` orders.
+The summaries can be read back with [`read_order_summaries`][`crate::state::orderbook::read_order_summaries`].
+*/
+pub fn new_order_batch<C: BorshSerialize + BorshSize>(
+    accounts: new_order_batch::Accounts<Pubkey>,
+    register_account: Pubkey,
+    params: new_order_batch::Params<C>,
+) -> Instruction {
+    let mut i = accounts.get_instruction(
+        crate::id(),
+        AgnosticOrderbookInstruction::NewOrderBatch as u8,
+        params,
+    );
+    i.accounts.push(AccountMeta {
+        pubkey: register_account,
+        is_signer: false,
+        is_writable: true,
+    });
+    i
+}
+
+/// Atomically cancel an existing order and execute a new order in its place.
+pub fn replace_order<C: BorshSerialize + BorshSize>(
+    accounts: replace_order::Accounts<Pubkey>,
+    register_account: Pubkey,
+    params: replace_order::Params<C>,
+) -> Instruction {
+    let mut i = accounts.get_instruction(
+        crate::id(),
+        AgnosticOrderbookInstruction::ReplaceOrder as u8,
+        params,
+    );
+    i.accounts.push(AccountMeta {
+        pubkey: register_account,
+        is_signer: false,
+        is_writable: true,
+    });
+    i
+}
+
+/// Cancel the orders of a given owner, identified by a callback information prefix
+pub fn cancel_orders_by_owner(
+    accounts: cancel_orders_by_owner::Accounts<Pubkey>,
+    register_account: Pubkey,
+    params: cancel_orders_by_owner::Params,
+) -> Instruction {
+    let mut i = accounts.get_instruction(
+        crate::id(),
+        AgnosticOrderbookInstruction::CancelOrdersByOwner as u8,
+        params,
+    );
+    i.accounts.push(AccountMeta {
+        pubkey: register_account,
+        is_signer: false,
+        is_writable: true,
+    });
+    i
+}
+
+/// Create and initialize the pegged orders account of an existing market
+pub fn create_pegged_orders(
+    accounts: create_pegged_orders::Accounts<Pubkey>,
+    register_account: Pubkey,
+    params: create_pegged_orders::Params,
+) -> Instruction {
+    let mut i = accounts.get_instruction(
+        crate::id(),
+        AgnosticOrderbookInstruction::CreatePeggedOrders as u8,
+        params,
+    );
+    i.accounts.push(AccountMeta {
+        pubkey: register_account,
+        is_signer: false,
+        is_writable: true,
+    });
+    i
+}
+
+/// Post a new pegged order
+pub fn new_pegged_order<C: BorshSerialize + BorshSize>(
+    accounts: new_pegged_order::Accounts<Pubkey>,
+    register_account: Pubkey,
+    params: new_pegged_order::Params<C>,
+) -> Instruction {
+    let mut i = accounts.get_instruction(
+        crate::id(),
+        AgnosticOrderbookInstruction::NewPeggedOrder as u8,
+        params,
+    );
+    i.accounts.push(AccountMeta {
+        pubkey: register_account,
+        is_signer: false,
+        is_writable: true,
+    });
+    i
+}
+
+/// Cancel an existing pegged order
+pub fn cancel_pegged_order(
+    accounts: cancel_pegged_order::Accounts<Pubkey>,
+    register_account: Pubkey,
+    params: cancel_pegged_order::Params,
+) -> Instruction {
+    let mut i = accounts.get_instruction(
+        crate::id(),
+        AgnosticOrderbookInstruction::CancelPeggedOrder as u8,
+        params,
+    );
+    i.accounts.push(AccountMeta {
+        pubkey: register_account,
+        is_signer: false,
+        is_writable: true,
+    });
+    i
+}
+
+/// Move the pegged orders of a market to their current peg price
+pub fn reprice_pegged(
+    accounts: reprice_pegged::Accounts<Pubkey>,
+    register_account: Pubkey,
+    params: reprice_pegged::Params,
+) -> Instruction {
+    let mut i = accounts.get_instruction(
+        crate::id(),
+        AgnosticOrderbookInstruction::RepricePegged as u8,
+        params,
+    );
+    i.accounts.push(AccountMeta {
+        pubkey: register_account,
+        is_signer: false,
+        is_writable: true,
+    });
+    i
+}
+
+/// Remove the orders whose remaining quantity is below the market's minimum order size
+pub fn clean_book(
+    accounts: clean_book::Accounts<Pubkey>,
+    register_account: Pubkey,
+    params: clean_book::Params,
+) -> Instruction {
+    let mut i = accounts.get_instruction(
+        crate::id(),
+        AgnosticOrderbookInstruction::CleanBook as u8,
+        params,
+    );
+    i.accounts.push(AccountMeta {
+        pubkey: register_account,
+        is_signer: false,
+        is_writable: true,
+    });
+    i
+}
+
+/// Grow the bids and asks accounts of an existing market
+pub fn resize_market(
+    accounts: resize_market::Accounts<Pubkey>,
+    register_account: Pubkey,
+    params: resize_market::Params,
+) -> Instruction {
+    let mut i = accounts.get_instruction(
+        crate::id(),
+        AgnosticOrderbookInstruction::ResizeMarket as u8,
+        params,
+    );
+    i.accounts.push(AccountMeta {
+        pubkey: register_account,
+        is_signer: false,
+        is_writable: true,
+    });
+    i
+}
+
+/// Verify the consistency of the bids and asks accounts of a market
+pub fn verify_slab(
+    accounts: verify_slab::Accounts<Pubkey>,
+    register_account: Pubkey,
+    params: verify_slab::Params,
+) -> Instruction {
+    let mut i = accounts.get_instruction(
+        crate::id(),
+        AgnosticOrderbookInstruction::VerifySlab as u8,
+        params,
+    );
+    i.accounts.push(AccountMeta {
+        pubkey: register_account,
+        is_signer: false,
+        is_writable: true,
+    });
+    i
+}
+
+/// Grow the event queue of an existing market
+pub fn resize_event_queue(
+    accounts: resize_event_queue::Accounts<Pubkey>,
+    register_account: Pubkey,
+    params: resize_event_queue::Params,
+) -> Instruction {
+    let mut i = accounts.get_instruction(
+        crate::id(),
+        AgnosticOrderbookInstruction::ResizeEventQueue as u8,
+        params,
+    );
+    i.accounts.push(AccountMeta {
+        pubkey: register_account,
+        is_signer: false,
+        is_writable: true,
+    });
+    i
+}
+
+/// Initialize the dead letter queue account of an existing market
+pub fn create_dead_letter_queue(
+    accounts: create_dead_letter_queue::Accounts<Pubkey>,
+    register_account: Pubkey,
+    params: create_dead_letter_queue::Params,
+) -> Instruction {
+    let mut i = accounts.get_instruction(
+        crate::id(),
+        AgnosticOrderbookInstruction::CreateDeadLetterQueue as u8,
+        params,
+    );
+    i.accounts.push(AccountMeta {
+        pubkey: register_account,
+        is_signer: false,
+        is_writable: true,
+    });
+    i
+}
+
+/// Move the expired events of a market to its dead letter queue
+pub fn expire_events(
+    accounts: expire_events::Accounts<Pubkey>,
+    register_account: Pubkey,
+    params: expire_events::Params,
+) -> Instruction {
+    let mut i = accounts.get_instruction(
+        crate::id(),
+        AgnosticOrderbookInstruction::ExpireEvents as u8,
+        params,
+    );
+    i.accounts.push(AccountMeta {
+        pubkey: register_account,
+        is_signer: false,
+        is_writable: true,
+    });
+    i
+}
+
+/// Initialize an event queue shard and register it on an existing market
+pub fn add_event_queue_shard(
+    accounts: add_event_queue_shard::Accounts<Pubkey>,
+    register_account: Pubkey,
+    params: add_event_queue_shard::Params,
+) -> Instruction {
+    let mut i = accounts.get_instruction(
+        crate::id(),
+        AgnosticOrderbookInstruction::AddEventQueueShard as u8,
+        params,
+    );
+    i.accounts.push(AccountMeta {
+        pubkey: register_account,
+        is_signer: false,
+        is_writable: true,
+    });
+    i
+}
+
+/// Change the trading status of an existing market
+pub fn set_market_status(
+    accounts: set_market_status::Accounts<Pubkey>,
+    register_account: Pubkey,
+    params: set_market_status::Params,
+) -> Instruction {
+    let mut i = accounts.get_instruction(
+        crate::id(),
+        AgnosticOrderbookInstruction::SetMarketStatus as u8,
+        params,
+    );
+    i.accounts.push(AccountMeta {
+        pubkey: register_account,
+        is_signer: false,
+        is_writable: true,
+    });
+    i
+}
+
+/// Upgrade the accounts of an existing market to the current layout version
+pub fn migrate_market(
+    accounts: migrate_market::Accounts<Pubkey>,
+    register_account: Pubkey,
+    params: migrate_market::Params,
+) -> Instruction {
+    let mut i = accounts.get_instruction(
+        crate::id(),
+        AgnosticOrderbookInstruction::MigrateMarket as u8,
+        params,
+    );
+    i.accounts.push(AccountMeta {
+        pubkey: register_account,
+        is_signer: false,
+        is_writable: true,
+    });
+    i
+}
+
+/// Withdraw the lamports held by a market account above its rent exemption threshold
+pub fn sweep_fees(
+    accounts: sweep_fees::Accounts<Pubkey>,
+    register_account: Pubkey,
+    params: sweep_fees::Params,
+) -> Instruction {
+    let mut i = accounts.get_instruction(
+        crate::id(),
+        AgnosticOrderbookInstruction::SweepFees as u8,
+        params,
+    );
+    i.accounts.push(AccountMeta {
+        pubkey: register_account,
+        is_signer: false,
+        is_writable: true,
+    });
+    i
+}
+
+/// Create and initialize a market registry account
+pub fn create_market_registry(
+    accounts: create_market_registry::Accounts<Pubkey>,
+    register_account: Pubkey,
+    params: create_market_registry::Params,
+) -> Instruction {
+    let mut i = accounts.get_instruction(
+        crate::id(),
+        AgnosticOrderbookInstruction::CreateMarketRegistry as u8,
+        params,
+    );
+    i.accounts.push(AccountMeta {
+        pubkey: register_account,
+        is_signer: false,
+        is_writable: true,
+    });
+    i
+}
+
+/// Bring the price accumulator of a market up to date
+pub fn poke(
+    accounts: poke::Accounts<Pubkey>,
+    register_account: Pubkey,
+    params: poke::Params,
+) -> Instruction {
+    let mut i = accounts.get_instruction(
+        crate::id(),
+        AgnosticOrderbookInstruction::Poke as u8,
+        params,
+    );
+    i.accounts.push(AccountMeta {
+        pubkey: register_account,
+        is_signer: false,
+        is_writable: true,
+    });
+    i
+}
+
+/// Uncross an orderbook whose best bid is priced at or above its best ask
+pub fn fix_crossed_book(
+    accounts: fix_crossed_book::Accounts<Pubkey>,
+    register_account: Pubkey,
+    params: fix_crossed_book::Params,
+) -> Instruction {
+    let mut i = accounts.get_instruction(
+        crate::id(),
+        AgnosticOrderbookInstruction::FixCrossedBook as u8,
+        params,
+    );
+    i.accounts.push(AccountMeta {
+        pubkey: register_account,
+        is_signer: false,
+        is_writable: true,
+    });
+    i
+}
+
+/// Match the crossed orders of an auction market at a single clearing price
+pub fn run_auction(
+    accounts: run_auction::Accounts<Pubkey>,
+    register_account: Pubkey,
+    params: run_auction::Params,
+) -> Instruction {
+    let mut i = accounts.get_instruction(
+        crate::id(),
+        AgnosticOrderbookInstruction::RunAuction as u8,
+        params,
+    );
+    i.accounts.push(AccountMeta {
+        pubkey: register_account,
+        is_signer: false,
+        is_writable: true,
+    });
+    i
+}
+
+/// Continue matching a resumable order, or cancel it
+pub fn continue_match(
+    accounts: continue_match::Accounts<Pubkey>,
+    register_account: Pubkey,
+    params: continue_match::Params,
+) -> Instruction {
+    let mut i = accounts.get_instruction(
+        crate::id(),
+        AgnosticOrderbookInstruction::ContinueMatch as u8,
+        params,
+    );
+    i.accounts.push(AccountMeta {
+        pubkey: register_account,
+        is_signer: false,
+        is_writable: true,
+    });
+    i
+}
+
+/// Read the top of the orderbook and optionally the state of an order into the register account
+pub fn get_order(
+    accounts: get_order::Accounts<Pubkey>,
+    register_account: Pubkey,
+    params: get_order::Params,
+) -> Instruction {
+    let mut i = accounts.get_instruction(
+        crate::id(),
+        AgnosticOrderbookInstruction::GetOrder as u8,
+        params,
+    );
+    i.accounts.push(AccountMeta {
+        pubkey: register_account,
+        is_signer: false,
+        is_writable: true,
+    });
+    i
+}
+
+/// Quote a swap through the orderbook into the register account
+pub fn quote_swap(
+    accounts: quote_swap::Accounts<Pubkey>,
+    register_account: Pubkey,
+    params: quote_swap::Params,
+) -> Instruction {
+    let mut i = accounts.get_instruction(
+        crate::id(),
+        AgnosticOrderbookInstruction::QuoteSwap as u8,
+        params,
+    );
+    i.accounts.push(AccountMeta {
+        pubkey: register_account,
+        is_signer: false,
+        is_writable: true,
+    });
+    i
+}
+
+/**
+Initialize the allowlist account of an existing market
+
+The allowlist account should be sized with [`Allowlist::compute_allocation_size`](`crate::state::allowlist::Allowlist::compute_allocation_size`)
+for the desired number of entries.
+*/
+pub fn create_allowlist(
+    accounts: create_allowlist::Accounts<Pubkey>,
+    register_account: Pubkey,
+    params: create_allowlist::Params,
+) -> Instruction {
+    let mut i = accounts.get_instruction(
+        crate::id(),
+        AgnosticOrderbookInstruction::CreateAllowlist as u8,
+        params,
+    );
+    i.accounts.push(AccountMeta {
+        pubkey: register_account,
+        is_signer: false,
+        is_writable: true,
+    });
+    i
+}
+
+/// Add an entry to the allowlist of a market, or remove one from it
+pub fn update_allowlist(
+    accounts: update_allowlist::Accounts<Pubkey>,
+    register_account: Pubkey,
+    params: update_allowlist::Params,
+) -> Instruction {
+    let mut i = accounts.get_instruction(
+        crate::id(),
+        AgnosticOrderbookInstruction::UpdateAllowlist as u8,
+        params,
+    );
+    i.accounts.push(AccountMeta {
+        pubkey: register_account,
+        is_signer: false,
+        is_writable: true,
+    });
+    i
+}
+
+/**
+Cancel series of existing orders across several markets
+
+The register account receives a serialized `Vec<OrderSummary>`, and should therefore be at least
+[`compute_register_size(n)`][`crate::state::orderbook::compute_register_size`] bytes long for `n` markets.
+The summaries can be read back with [`read_order_summaries`][`crate::state::orderbook::read_order_summaries`].
+*/
+pub fn multi_market_cancel(
+    accounts: multi_market_cancel::Accounts<Pubkey>,
+    register_account: Pubkey,
+    params: multi_market_cancel::Params,
+) -> Instruction {
+    let mut i = accounts.get_instruction(
+        crate::id(),
+        AgnosticOrderbookInstruction::MultiMarketCancel as u8,
+        params,
+    );
+    i.accounts.push(AccountMeta {
+        pubkey: register_account,
+        is_signer: false,
+        is_writable: true,
+    });
+    i
+}
+
+/// Commit to the orders of a market by writing the Merkle roots of its bids and asks
+pub fn commit_book(
+    accounts: commit_book::Accounts<Pubkey>,
+    register_account: Pubkey,
+    params: commit_book::Params,
+) -> Instruction {
+    let mut i = accounts.get_instruction(
+        crate::id(),
+        AgnosticOrderbookInstruction::CommitBook as u8,
+        params,
+    );
+    i.accounts.push(AccountMeta {
+        pubkey: register_account,
+        is_signer: false,
+        is_writable: true,
+    });
+    i
+}
+
+/// Cancel an existing order in the orderbook, identified by its client order id.
+///
+/// The cancelled order is written into the register account as with [`cancel_order`].
+pub fn cancel_by_client_id(
+    accounts: cancel_by_client_id::Accounts<Pubkey>,
+    register_account: Pubkey,
+    params: cancel_by_client_id::Params,
+) -> Instruction {
+    let mut i = accounts.get_instruction(
+        crate::id(),
+        AgnosticOrderbookInstruction::CancelByClientId as u8,
+        params,
+    );
+    i.accounts.push(AccountMeta {
+        pubkey: register_account,
+        is_signer: false,
+        is_writable: true,
+    });
+    i
+}
@@ -45,8 +45,33 @@ those accounts. A prefix of len [`callback_id_len`][`state::market_state::Market
 
 Once event processing is over, it is essential to pop the processed events off the queue. This can be done through the [`consume_events`][`fn@instruction::consume_events`]
 primitive. In general, the event processing logic should be handled by a dedicated cranker on the caller program's side.
+
+## Building instructions
+
+Every instruction has a typed builder in the [`instruction`] module, such as [`new_order`][`fn@instruction::new_order`],
+[`cancel_order`][`fn@instruction::cancel_order`], [`consume_events`][`fn@instruction::consume_events`] or
+[`create_market`][`fn@instruction::create_market`]. The builders take the instruction's `Accounts` and `Params` structs and
+return an [`Instruction`][`solana_program::instruction::Instruction`] whose account metas are in the order, and with the
+writable and signer flags, expected by the program, followed by the register account.
+
+Off-chain clients and caller programs should depend on this crate with the `client` feature, which leaves out the
+entrypoint and exposes the read helpers of the [`utils`] module from [`state`]. With the `serde` feature, the market
+state, the events, the order summaries and the order views implement serde's `Serialize` and `Deserialize`, so that
+indexers can emit them as JSON directly.
+
+## WebAssembly
+
+The account types, such as [`MarketState`][`state::market_state::MarketState`], the
+[`Slab`][`state::critbit::Slab`], the [`EventQueue`][`state::event_queue::EventQueue`] and the
+[`OrderSummary`][`state::OrderSummary`], can be used to decode account data in the browser. The `wasm` feature
+implies `client`, and additionally leaves out the `cpi` helpers, which only make sense on-chain, so that the crate
+builds with `cargo build --target wasm32-unknown-unknown --features wasm`. It can't be combined with the `entrypoint`
+feature.
 */
 
+/// Helpers to invoke the program through CPI and read back the results of its instructions
+#[cfg(not(feature = "wasm"))]
+pub mod cpi;
 #[doc(hidden)]
 pub mod entrypoint;
 #[doc(hidden)]
@@ -63,4 +88,15 @@ pub(crate) mod processor;
 /// Utility functions
 pub mod utils;
 
+#[cfg(feature = "anchor")]
+pub mod anchor;
+#[cfg(feature = "idl")]
+pub mod idl;
+#[cfg(feature = "simulation")]
+pub mod replay;
+#[cfg(feature = "simulation")]
+pub mod simulation;
+#[cfg(feature = "test-utils")]
+pub mod test_utils;
+
 declare_id!("aaobKniTtDGvCZces7GH5UReLYP671bBkB96ahr9x3e");
@@ -4,31 +4,114 @@ use solana_program::{account_info::AccountInfo, msg, program_error::ProgramError
 
 use crate::{
     instruction::AgnosticOrderbookInstruction,
-    state::orderbook::{CallbackInfo, OrderSummary},
+    state::orderbook::{CallbackInfo, OrderSummary, Quote, SwapQuote},
 };
 
-use borsh::BorshDeserialize;
+use borsh::{BorshDeserialize, BorshSerialize};
 
+pub mod add_event_queue_shard;
+pub mod cancel_by_client_id;
 pub mod cancel_order;
+pub mod cancel_orders_by_owner;
+pub mod cancel_pegged_order;
+pub mod cancel_stop_order;
+pub mod clean_book;
 pub mod close_market;
+pub mod commit_book;
 pub mod consume_events;
+pub mod continue_match;
+pub mod create_allowlist;
+pub mod create_dead_letter_queue;
 pub mod create_market;
+pub mod create_market_registry;
+pub mod create_pegged_orders;
+pub mod create_stop_orders;
+pub mod expire_events;
+pub mod fix_crossed_book;
+pub mod get_order;
 pub mod mass_cancel_orders;
+pub mod migrate_market;
+pub mod multi_market_cancel;
 pub mod new_order;
+pub mod new_order_batch;
+pub mod new_pegged_order;
+pub mod new_stop_order;
+pub mod poke;
+pub mod prune_expired;
 pub mod prune_orders;
+pub mod quote_swap;
+pub mod replace_order;
+pub mod reprice_pegged;
+pub mod resize_event_queue;
+pub mod resize_market;
+pub mod run_auction;
+pub mod set_market_status;
+pub mod sweep_fees;
+pub mod trigger_orders;
+pub mod update_allowlist;
+pub mod verify_slab;
+
+/// The information which is written back into the register account once an instruction has been processed.
+pub enum Register {
+    /// An optional order summary, serialized as an `Option<OrderSummary>`.
+    OrderSummary(Option<OrderSummary>),
+    /// One order summary per order of a batch, serialized as a `Vec<OrderSummary>`.
+    OrderSummaries(Vec<OrderSummary>),
+    /// The order removed by a cancel_order or cancel_by_client_id instruction, serialized as described in
+    /// [`CancelledOrder`][`crate::state::orderbook::CancelledOrder`].
+    CancelledOrder {
+        #[allow(missing_docs)]
+        summary: OrderSummary,
+        #[allow(missing_docs)]
+        price: u64,
+        #[allow(missing_docs)]
+        callback_info: Vec<u8>,
+    },
+    /// The events popped by a consume_events instruction, as serialized by
+    /// [`EventQueue::serialize_front`][`crate::state::event_queue::EventQueue::serialize_front`].
+    ConsumedEvents(Vec<u8>),
+    /// The quote of a get_order instruction, serialized as a [`Quote`].
+    Quote(Quote),
+    /// The quote of a quote_swap instruction, serialized as a [`SwapQuote`].
+    SwapQuote(SwapQuote),
+}
+
+impl Register {
+    /// Serializes the register into the data of the register account.
+    pub fn write(&self, register_data: &mut [u8]) -> std::io::Result<()> {
+        let mut register_data = register_data;
+        match self {
+            Register::OrderSummary(s) => s.serialize(&mut register_data),
+            Register::OrderSummaries(s) => s.serialize(&mut register_data),
+            Register::CancelledOrder {
+                summary,
+                price,
+                callback_info,
+            } => {
+                Some(summary).serialize(&mut register_data)?;
+                price.serialize(&mut register_data)?;
+                std::io::Write::write_all(&mut register_data, callback_info)
+            }
+            Register::ConsumedEvents(e) => std::io::Write::write_all(&mut register_data, e),
+            Register::Quote(q) => q.serialize(&mut register_data),
+            Register::SwapQuote(q) => q.serialize(&mut register_data),
+        }
+    }
+}
 
 pub fn process_instruction<C: Pod + BorshDeserialize + CallbackInfo + PartialEq>(
     program_id: &Pubkey,
     accounts: &[AccountInfo],
     instruction_data: &[u8],
-) -> Result<Option<OrderSummary>, ProgramError>
+) -> Result<Register, ProgramError>
 where
     <C as CallbackInfo>::CallbackId: PartialEq,
 {
     msg!("Beginning processing");
-    let instruction =
-        FromPrimitive::from_u8(instruction_data[0]).ok_or(ProgramError::InvalidInstructionData)?;
-    let instruction_data = &instruction_data[1..];
+    let (tag, instruction_data) = instruction_data
+        .split_first()
+        .ok_or(ProgramError::InvalidInstructionData)?;
+    let instruction = FromPrimitive::from_u8(*tag).ok_or(ProgramError::InvalidInstructionData)?;
     msg!("Instruction unpacked");
 
     match instruction {
@@ -44,21 +127,30 @@ where
             let accounts = new_order::Accounts::parse(accounts)?;
             let params = new_order::Params::<C>::try_from_slice(instruction_data)
                 .map_err(|_| ProgramError::InvalidInstructionData)?;
-            return new_order::process(program_id, accounts, params).map(Some);
+            return new_order::process(program_id, accounts, params)
+                .map(|s| Register::OrderSummary(Some(s)));
         }
         AgnosticOrderbookInstruction::ConsumeEvents => {
             msg!("Instruction: Consume Events");
             let accounts = consume_events::Accounts::parse(accounts)?;
             let params = consume_events::Params::try_from_slice(instruction_data)
                 .map_err(|_| ProgramError::InvalidInstructionData)?;
-            consume_events::process::<C>(program_id, accounts, params)?;
+            if let Some(e) = consume_events::process::<C>(program_id, accounts, params)? {
+                return Ok(Register::ConsumedEvents(e));
+            }
         }
         AgnosticOrderbookInstruction::CancelOrder => {
             msg!("Instruction: Cancel Order");
             let accounts = cancel_order::Accounts::parse(accounts)?;
             let params = cancel_order::Params::try_from_slice(instruction_data)
                 .map_err(|_| ProgramError::InvalidInstructionData)?;
-            return cancel_order::process::<C>(program_id, accounts, params).map(Some);
+            return cancel_order::process::<C>(program_id, accounts, params).map(|o| {
+                Register::CancelledOrder {
+                    summary: o.summary,
+                    price: o.price,
+                    callback_info: bytemuck::bytes_of(&o.callback_info).to_vec(),
+                }
+            });
         }
         AgnosticOrderbookInstruction::CloseMarket => {
             msg!("Instruction: Close Market");
@@ -70,7 +162,8 @@ where
             let accounts = mass_cancel_orders::Accounts::parse(accounts)?;
             let params = mass_cancel_orders::Params::try_from_slice(instruction_data)
                 .map_err(|_| ProgramError::InvalidInstructionData)?;
-            return mass_cancel_orders::process::<C>(program_id, accounts, params).map(Some);
+            return mass_cancel_orders::process::<C>(program_id, accounts, params)
+                .map(|s| Register::OrderSummary(Some(s)));
         }
         AgnosticOrderbookInstruction::PruneOrders => {
             msg!("Instruction: Prune Orders");
@@ -79,6 +172,259 @@ where
                 .map_err(|_| ProgramError::InvalidInstructionData)?;
             prune_orders::process::<C>(program_id, accounts, params)?;
         }
+        AgnosticOrderbookInstruction::PruneExpired => {
+            msg!("Instruction: Prune Expired");
+            let accounts = prune_expired::Accounts::parse(accounts)?;
+            let params = prune_expired::Params::try_from_slice(instruction_data)
+                .map_err(|_| ProgramError::InvalidInstructionData)?;
+            prune_expired::process::<C>(program_id, accounts, params)?;
+        }
+        AgnosticOrderbookInstruction::CreateStopOrders => {
+            msg!("Instruction: Create Stop Orders");
+            let accounts = create_stop_orders::Accounts::parse(accounts)?;
+            let params = create_stop_orders::Params::try_from_slice(instruction_data)
+                .map_err(|_| ProgramError::InvalidInstructionData)?;
+            create_stop_orders::process(program_id, accounts, params)?;
+        }
+        AgnosticOrderbookInstruction::NewStopOrder => {
+            msg!("Instruction: New Stop Order");
+            let accounts = new_stop_order::Accounts::parse(accounts)?;
+            let params = new_stop_order::Params::<C>::try_from_slice(instruction_data)
+                .map_err(|_| ProgramError::InvalidInstructionData)?;
+            return new_stop_order::process(program_id, accounts, params)
+                .map(|s| Register::OrderSummary(Some(s)));
+        }
+        AgnosticOrderbookInstruction::CancelStopOrder => {
+            msg!("Instruction: Cancel Stop Order");
+            let accounts = cancel_stop_order::Accounts::parse(accounts)?;
+            let params = cancel_stop_order::Params::try_from_slice(instruction_data)
+                .map_err(|_| ProgramError::InvalidInstructionData)?;
+            return cancel_stop_order::process::<C>(program_id, accounts, params)
+                .map(|s| Register::OrderSummary(Some(s)));
+        }
+        AgnosticOrderbookInstruction::TriggerOrders => {
+            msg!("Instruction: Trigger Orders");
+            let accounts = trigger_orders::Accounts::parse(accounts)?;
+            let params = trigger_orders::Params::try_from_slice(instruction_data)
+                .map_err(|_| ProgramError::InvalidInstructionData)?;
+            trigger_orders::process::<C>(program_id, accounts, params)?;
+        }
+        AgnosticOrderbookInstruction::NewOrderBatch => {
+            msg!("Instruction: New Order Batch");
+            let accounts = new_order_batch::Accounts::parse(accounts)?;
+            let params = new_order_batch::Params::<C>::try_from_slice(instruction_data)
+                .map_err(|_| ProgramError::InvalidInstructionData)?;
+            return new_order_batch::process(program_id, accounts, params)
+                .map(Register::OrderSummaries);
+        }
+        AgnosticOrderbookInstruction::ReplaceOrder => {
+            msg!("Instruction: Replace Order");
+            let accounts = replace_order::Accounts::parse(accounts)?;
+            let params = replace_order::Params::<C>::try_from_slice(instruction_data)
+                .map_err(|_| ProgramError::InvalidInstructionData)?;
+            return replace_order::process(program_id, accounts, params)
+                .map(|s| Register::OrderSummary(Some(s)));
+        }
+        AgnosticOrderbookInstruction::CancelOrdersByOwner => {
+            msg!("Instruction: Cancel Orders By Owner");
+            let accounts = cancel_orders_by_owner::Accounts::parse(accounts)?;
+            let params = cancel_orders_by_owner::Params::try_from_slice(instruction_data)
+                .map_err(|_| ProgramError::InvalidInstructionData)?;
+            return cancel_orders_by_owner::process::<C>(program_id, accounts, params)
+                .map(|s| Register::OrderSummary(Some(s)));
+        }
+        AgnosticOrderbookInstruction::CreatePeggedOrders => {
+            msg!("Instruction: Create Pegged Orders");
+            let accounts = create_pegged_orders::Accounts::parse(accounts)?;
+            let params = create_pegged_orders::Params::try_from_slice(instruction_data)
+                .map_err(|_| ProgramError::InvalidInstructionData)?;
+            create_pegged_orders::process(program_id, accounts, params)?;
+        }
+        AgnosticOrderbookInstruction::NewPeggedOrder => {
+            msg!("Instruction: New Pegged Order");
+            let accounts = new_pegged_order::Accounts::parse(accounts)?;
+            let params = new_pegged_order::Params::<C>::try_from_slice(instruction_data)
+                .map_err(|_| ProgramError::InvalidInstructionData)?;
+            return new_pegged_order::process(program_id, accounts, params)
+                .map(|s| Register::OrderSummary(Some(s)));
+        }
+        AgnosticOrderbookInstruction::CancelPeggedOrder => {
+            msg!("Instruction: Cancel Pegged Order");
+            let accounts = cancel_pegged_order::Accounts::parse(accounts)?;
+            let params = cancel_pegged_order::Params::try_from_slice(instruction_data)
+                .map_err(|_| ProgramError::InvalidInstructionData)?;
+            return cancel_pegged_order::process::<C>(program_id, accounts, params)
+                .map(|s| Register::OrderSummary(Some(s)));
+        }
+        AgnosticOrderbookInstruction::RepricePegged => {
+            msg!("Instruction: Reprice Pegged");
+            let accounts = reprice_pegged::Accounts::parse(accounts)?;
+            let params = reprice_pegged::Params::try_from_slice(instruction_data)
+                .map_err(|_| ProgramError::InvalidInstructionData)?;
+            reprice_pegged::process::<C>(program_id, accounts, params)?;
+        }
+        AgnosticOrderbookInstruction::CleanBook => {
+            msg!("Instruction: Clean Book");
+            let accounts = clean_book::Accounts::parse(accounts)?;
+            let params = clean_book::Params::try_from_slice(instruction_data)
+                .map_err(|_| ProgramError::InvalidInstructionData)?;
+            clean_book::process::<C>(program_id, accounts, params)?;
+        }
+        AgnosticOrderbookInstruction::ResizeMarket => {
+            msg!("Instruction: Resize Market");
+            let accounts = resize_market::Accounts::parse(accounts)?;
+            let params = resize_market::Params::try_from_slice(instruction_data)
+                .map_err(|_| ProgramError::InvalidInstructionData)?;
+            resize_market::process::<C>(program_id, accounts, params)?;
+        }
+        AgnosticOrderbookInstruction::VerifySlab => {
+            msg!("Instruction: Verify Slab");
+            let accounts = verify_slab::Accounts::parse(accounts)?;
+            let params = verify_slab::Params::try_from_slice(instruction_data)
+                .map_err(|_| ProgramError::InvalidInstructionData)?;
+            verify_slab::process::<C>(program_id, accounts, params)?;
+        }
+        AgnosticOrderbookInstruction::ResizeEventQueue => {
+            msg!("Instruction: Resize Event Queue");
+            let accounts = resize_event_queue::Accounts::parse(accounts)?;
+            let params = resize_event_queue::Params::try_from_slice(instruction_data)
+                .map_err(|_| ProgramError::InvalidInstructionData)?;
+            resize_event_queue::process::<C>(program_id, accounts, params)?;
+        }
+        AgnosticOrderbookInstruction::CreateDeadLetterQueue => {
+            msg!("Instruction: Create Dead Letter Queue");
+            let accounts = create_dead_letter_queue::Accounts::parse(accounts)?;
+            let params = create_dead_letter_queue::Params::try_from_slice(instruction_data)
+                .map_err(|_| ProgramError::InvalidInstructionData)?;
+            create_dead_letter_queue::process::<C>(program_id, accounts, params)?;
+        }
+        AgnosticOrderbookInstruction::ExpireEvents => {
+            msg!("Instruction: Expire Events");
+            let accounts = expire_events::Accounts::parse(accounts)?;
+            let params = expire_events::Params::try_from_slice(instruction_data)
+                .map_err(|_| ProgramError::InvalidInstructionData)?;
+            expire_events::process::<C>(program_id, accounts, params)?;
+        }
+        AgnosticOrderbookInstruction::AddEventQueueShard => {
+            msg!("Instruction: Add Event Queue Shard");
+            let accounts = add_event_queue_shard::Accounts::parse(accounts)?;
+            let params = add_event_queue_shard::Params::try_from_slice(instruction_data)
+                .map_err(|_| ProgramError::InvalidInstructionData)?;
+            add_event_queue_shard::process::<C>(program_id, accounts, params)?;
+        }
+        AgnosticOrderbookInstruction::SetMarketStatus => {
+            msg!("Instruction: Set Market Status");
+            let accounts = set_market_status::Accounts::parse(accounts)?;
+            let params = set_market_status::Params::try_from_slice(instruction_data)
+                .map_err(|_| ProgramError::InvalidInstructionData)?;
+            set_market_status::process(program_id, accounts, params)?;
+        }
+        AgnosticOrderbookInstruction::MigrateMarket => {
+            msg!("Instruction: Migrate Market");
+            let accounts = migrate_market::Accounts::parse(accounts)?;
+            let params = migrate_market::Params::try_from_slice(instruction_data)
+                .map_err(|_| ProgramError::InvalidInstructionData)?;
+            migrate_market::process::<C>(program_id, accounts, params)?;
+        }
+        AgnosticOrderbookInstruction::SweepFees => {
+            msg!("Instruction: Sweep Fees");
+            let accounts = sweep_fees::Accounts::parse(accounts)?;
+            let params = sweep_fees::Params::try_from_slice(instruction_data)
+                .map_err(|_| ProgramError::InvalidInstructionData)?;
+            sweep_fees::process(program_id, accounts, params)?;
+        }
+        AgnosticOrderbookInstruction::CreateMarketRegistry => {
+            msg!("Instruction: Create Market Registry");
+            let accounts = create_market_registry::Accounts::parse(accounts)?;
+            let params = create_market_registry::Params::try_from_slice(instruction_data)
+                .map_err(|_| ProgramError::InvalidInstructionData)?;
+            create_market_registry::process(program_id, accounts, params)?;
+        }
+        AgnosticOrderbookInstruction::Poke => {
+            msg!("Instruction: Poke");
+            let accounts = poke::Accounts::parse(accounts)?;
+            let params = poke::Params::try_from_slice(instruction_data)
+                .map_err(|_| ProgramError::InvalidInstructionData)?;
+            poke::process(program_id, accounts, params)?;
+        }
+        AgnosticOrderbookInstruction::FixCrossedBook => {
+            msg!("Instruction: Fix Crossed Book");
+            let accounts = fix_crossed_book::Accounts::parse(accounts)?;
+            let params = fix_crossed_book::Params::try_from_slice(instruction_data)
+                .map_err(|_| ProgramError::InvalidInstructionData)?;
+            fix_crossed_book::process::<C>(program_id, accounts, params)?;
+        }
+        AgnosticOrderbookInstruction::RunAuction => {
+            msg!("Instruction: Run Auction");
+            let accounts = run_auction::Accounts::parse(accounts)?;
+            let params = run_auction::Params::try_from_slice(instruction_data)
+                .map_err(|_| ProgramError::InvalidInstructionData)?;
+            run_auction::process::<C>(program_id, accounts, params)?;
+        }
+        AgnosticOrderbookInstruction::ContinueMatch => {
+            msg!("Instruction: Continue Match");
+            let accounts = continue_match::Accounts::parse(accounts)?;
+            let params = continue_match::Params::try_from_slice(instruction_data)
+                .map_err(|_| ProgramError::InvalidInstructionData)?;
+            return continue_match::process::<C>(program_id, accounts, params)
+                .map(|s| Register::OrderSummary(Some(s)));
+        }
+        AgnosticOrderbookInstruction::GetOrder => {
+            msg!("Instruction: Get Order");
+            let accounts = get_order::Accounts::parse(accounts)?;
+            let params = get_order::Params::try_from_slice(instruction_data)
+                .map_err(|_| ProgramError::InvalidInstructionData)?;
+            return get_order::process::<C>(program_id, accounts, params).map(Register::Quote);
+        }
+        AgnosticOrderbookInstruction::QuoteSwap => {
+            msg!("Instruction: Quote Swap");
+            let accounts = quote_swap::Accounts::parse(accounts)?;
+            let params = quote_swap::Params::try_from_slice(instruction_data)
+                .map_err(|_| ProgramError::InvalidInstructionData)?;
+            return quote_swap::process::<C>(program_id, accounts, params).map(Register::SwapQuote);
+        }
+        AgnosticOrderbookInstruction::CreateAllowlist => {
+            msg!("Instruction: Create Allowlist");
+            let accounts = create_allowlist::Accounts::parse(accounts)?;
+            let params = create_allowlist::Params::try_from_slice(instruction_data)
+                .map_err(|_| ProgramError::InvalidInstructionData)?;
+            create_allowlist::process(program_id, accounts, params)?;
+        }
+        AgnosticOrderbookInstruction::UpdateAllowlist => {
+            msg!("Instruction: Update Allowlist");
+            let accounts = update_allowlist::Accounts::parse(accounts)?;
+            let params = update_allowlist::Params::try_from_slice(instruction_data)
+                .map_err(|_| ProgramError::InvalidInstructionData)?;
+            update_allowlist::process(program_id, accounts, params)?;
+        }
+        AgnosticOrderbookInstruction::MultiMarketCancel => {
+            msg!("Instruction: Multi Market Cancel");
+            let accounts = multi_market_cancel::Accounts::parse(accounts)?;
+            let params = multi_market_cancel::Params::try_from_slice(instruction_data)
+                .map_err(|_| ProgramError::InvalidInstructionData)?;
+            return multi_market_cancel::process::<C>(program_id, accounts, params)
+                .map(Register::OrderSummaries);
+        }
+        AgnosticOrderbookInstruction::CommitBook => {
+            msg!("Instruction: Commit Book");
+            let accounts = commit_book::Accounts::parse(accounts)?;
+            let params = commit_book::Params::try_from_slice(instruction_data)
+                .map_err(|_| ProgramError::InvalidInstructionData)?;
+            commit_book::process::<C>(program_id, accounts, params)?;
+        }
+        AgnosticOrderbookInstruction::CancelByClientId => {
+            msg!("Instruction: Cancel By Client Id");
+            let accounts = cancel_by_client_id::Accounts::parse(accounts)?;
+            let params = cancel_by_client_id::Params::try_from_slice(instruction_data)
+                .map_err(|_| ProgramError::InvalidInstructionData)?;
+            return cancel_by_client_id::process::<C>(program_id, accounts, params).map(|o| {
+                Register::CancelledOrder {
+                    summary: o.summary,
+                    price: o.price,
+                    callback_info: bytemuck::bytes_of(&o.callback_info).to_vec(),
+                }
+            });
+        }
     }
-    Ok(None)
+    Ok(Register::OrderSummary(None))
 }
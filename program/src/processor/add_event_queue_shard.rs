@@ -0,0 +1,119 @@
+//! Initialize an event queue shard and register it on an existing market
+use bonfida_utils::{checks::check_rent_exempt, BorshSize, InstructionsAccount};
+use borsh::{BorshDeserialize, BorshSerialize};
+use bytemuck::Pod;
+use solana_program::{
+    account_info::{next_account_info, AccountInfo},
+    entrypoint::ProgramResult,
+    msg,
+    program_error::ProgramError,
+    pubkey::Pubkey,
+};
+
+use crate::{
+    error::AoError,
+    state::{
+        event_queue::EventQueue,
+        market_state::{MarketState, MAX_EVENT_QUEUE_SHARDS},
+        AccountTag,
+    },
+    utils::{check_account_key, check_account_owner, check_event_queue_shards},
+};
+
+#[derive(BorshDeserialize, BorshSerialize, BorshSize)]
+/**
+The required arguments for an add_event_queue_shard instruction.
+*/
+pub struct Params {}
+
+/// The required accounts for an add_event_queue_shard instruction.
+#[derive(InstructionsAccount)]
+pub struct Accounts<'a, T> {
+    #[allow(missing_docs)]
+    #[cons(writable)]
+    pub market: &'a T,
+    #[allow(missing_docs)]
+    pub event_queue: &'a T,
+    /// The new event queue shard
+    #[cons(writable)]
+    pub new_event_queue_shard: &'a T,
+    /// The event queue shards which are already registered on the market, in the order in which they were registered
+    pub event_queue_shards: &'a [T],
+}
+
+impl<'a, 'b: 'a> Accounts<'a, AccountInfo<'b>> {
+    pub(crate) fn parse(accounts: &'a [AccountInfo<'b>]) -> Result<Self, ProgramError> {
+        let accounts_iter = &mut accounts.iter();
+
+        let a = Self {
+            market: next_account_info(accounts_iter)?,
+            event_queue: next_account_info(accounts_iter)?,
+            new_event_queue_shard: next_account_info(accounts_iter)?,
+            event_queue_shards: accounts_iter.as_slice(),
+        };
+        Ok(a)
+    }
+    pub(crate) fn perform_checks(&self, program_id: &Pubkey) -> Result<(), ProgramError> {
+        check_account_owner(
+            self.market,
+            &program_id.to_bytes(),
+            AoError::WrongMarketOwner,
+        )?;
+        check_account_owner(
+            self.new_event_queue_shard,
+            &program_id.to_bytes(),
+            AoError::WrongEventQueueOwner,
+        )?;
+        Ok(())
+    }
+}
+
+/// Apply the add_event_queue_shard instruction to the provided accounts
+pub fn process<C: Pod>(
+    program_id: &Pubkey,
+    accounts: Accounts<AccountInfo>,
+    _params: Params,
+) -> ProgramResult {
+    accounts.perform_checks(program_id)?;
+    check_rent_exempt(accounts.new_event_queue_shard)?;
+
+    let mut market_data = accounts.market.data.borrow_mut();
+    let market_state = MarketState::from_buffer_mut(&mut market_data, AccountTag::Market)?;
+
+    check_accounts(&accounts, market_state)?;
+
+    let number_of_shards = market_state.event_queue_shards().len();
+    if number_of_shards == MAX_EVENT_QUEUE_SHARDS {
+        msg!("The market can't hold more event queue shards!");
+        return Err(AoError::TooManyEventQueueShards.into());
+    }
+
+    // Adding a queue changes how the events are routed, which could reorder the events of a given maker
+    for queue in std::iter::once(accounts.event_queue).chain(accounts.event_queue_shards) {
+        let mut queue_data = queue.data.borrow_mut();
+        if !EventQueue::<C>::from_buffer(&mut queue_data, AccountTag::EventQueue)?.is_empty() {
+            msg!("The event queue and its shards must be empty before a shard is added!");
+            return Err(AoError::EventQueueNotEmpty.into());
+        }
+    }
+
+    let mut shard_data = accounts.new_event_queue_shard.data.borrow_mut();
+    EventQueue::<C>::check_buffer_size(&shard_data)?;
+    EventQueue::<C>::from_buffer(&mut shard_data, AccountTag::Uninitialized)?;
+    market_state.event_queue_shards[number_of_shards] = *accounts.new_event_queue_shard.key;
+
+    Ok(())
+}
+
+fn check_accounts<'a, 'b: 'a>(
+    accounts: &Accounts<'a, AccountInfo<'b>>,
+    market_state: &MarketState,
+) -> ProgramResult {
+    check_account_key(
+        accounts.event_queue,
+        &market_state.event_queue,
+        AoError::WrongEventQueueAccount,
+    )?;
+    check_event_queue_shards(accounts.event_queue_shards, market_state)?;
+    Ok(())
+}
@@ -0,0 +1,125 @@
+//! Cancel an existing order in the orderbook, identified by the client order id which its owner attached to it.
+
+use bonfida_utils::{BorshSize, InstructionsAccount};
+use borsh::{BorshDeserialize, BorshSerialize};
+use bytemuck::Pod;
+use solana_program::account_info::next_account_info;
+use solana_program::{
+    account_info::AccountInfo, entrypoint::ProgramResult, msg, program_error::ProgramError,
+    pubkey::Pubkey,
+};
+
+use crate::state::orderbook::{CallbackInfo, CancelledOrder, OrderBookState};
+use crate::state::AccountTag;
+use crate::{
+    error::AoError,
+    state::market_state::MarketState,
+    utils::{check_account_key, check_account_owner},
+};
+#[derive(BorshDeserialize, BorshSerialize, Clone, BorshSize)]
+/**
+The required arguments for a cancel_by_client_id instruction.
+*/
+pub struct Params {
+    /// The client order id which was attached to the order at placement, which can't be 0
+    pub client_order_id: u64,
+    /// The callback information of the order, which identifies its owner and must have the market's callback info length
+    pub callback_info: Vec<u8>,
+}
+
+/// The required accounts for a cancel_by_client_id instruction.
+#[derive(InstructionsAccount)]
+pub struct Accounts<'a, T> {
+    #[allow(missing_docs)]
+    pub market: &'a T,
+    #[allow(missing_docs)]
+    #[cons(writable)]
+    pub event_queue: &'a T,
+    #[allow(missing_docs)]
+    #[cons(writable)]
+    pub bids: &'a T,
+    #[allow(missing_docs)]
+    #[cons(writable)]
+    pub asks: &'a T,
+}
+
+impl<'a, 'b: 'a> Accounts<'a, AccountInfo<'b>> {
+    pub(crate) fn parse(accounts: &'a [AccountInfo<'b>]) -> Result<Self, ProgramError> {
+        let accounts_iter = &mut accounts.iter();
+
+        let a = Self {
+            market: next_account_info(accounts_iter)?,
+            event_queue: next_account_info(accounts_iter)?,
+            bids: next_account_info(accounts_iter)?,
+            asks: next_account_info(accounts_iter)?,
+        };
+        Ok(a)
+    }
+
+    /// Perform basic security checks on the accounts
+    pub(crate) fn perform_checks(&self, program_id: &Pubkey) -> Result<(), ProgramError> {
+        check_account_owner(
+            self.market,
+            &program_id.to_bytes(),
+            AoError::WrongMarketOwner,
+        )?;
+        check_account_owner(
+            self.event_queue,
+            &program_id.to_bytes(),
+            AoError::WrongEventQueueOwner,
+        )?;
+        check_account_owner(self.bids, &program_id.to_bytes(), AoError::WrongBidsOwner)?;
+        check_account_owner(self.asks, &program_id.to_bytes(), AoError::WrongAsksOwner)?;
+        Ok(())
+    }
+}
+/// Apply the cancel_by_client_id instruction to the provided accounts
+pub fn process<'a, 'b: 'a, C: CallbackInfo + Pod + PartialEq>(
+    program_id: &Pubkey,
+    accounts: Accounts<'a, AccountInfo<'b>>,
+    params: Params,
+) -> Result<CancelledOrder<C>, ProgramError>
+where
+    <C as CallbackInfo>::CallbackId: PartialEq,
+{
+    accounts.perform_checks(program_id)?;
+    let market_state_data = accounts.market.data.borrow();
+    let market_state = MarketState::from_buffer(&market_state_data, AccountTag::Market)?;
+
+    check_accounts(&accounts, market_state)?;
+    market_state.check_can_cancel_order()?;
+
+    if params.callback_info.len() != std::mem::size_of::<C>() {
+        msg!("The callback information doesn't have the market's callback info length!");
+        return Err(AoError::InvalidCallbackInfo.into());
+    }
+    let callback_info = bytemuck::pod_read_unaligned::<C>(&params.callback_info);
+
+    let mut bids_guard = accounts.bids.data.borrow_mut();
+    let mut asks_guard = accounts.asks.data.borrow_mut();
+
+    let mut order_book = OrderBookState::<C>::new_safe(&mut bids_guard, &mut asks_guard)?;
+
+    let cancelled_order = order_book.cancel_order_by_client_id(
+        params.client_order_id,
+        &callback_info,
+        market_state,
+    )?;
+
+    Ok(cancelled_order)
+}
+
+fn check_accounts<'a, 'b: 'a>(
+    accounts: &Accounts<'a, AccountInfo<'b>>,
+    market_state: &MarketState,
+) -> ProgramResult {
+    check_account_key(
+        accounts.event_queue,
+        &market_state.event_queue,
+        AoError::WrongEventQueueAccount,
+    )?;
+    check_account_key(accounts.bids, &market_state.bids, AoError::WrongBidsAccount)?;
+    check_account_key(accounts.asks, &market_state.asks, AoError::WrongAsksAccount)?;
+
+    Ok(())
+}
@@ -1,6 +1,5 @@
 //! Cancel an existing order in the orderbook.
 
-use bonfida_utils::fp_math::fp32_mul_floor;
 use bonfida_utils::{BorshSize, InstructionsAccount};
 use borsh::{BorshDeserialize, BorshSerialize};
 use bytemuck::Pod;
@@ -10,11 +9,11 @@ use solana_program::{
     pubkey::Pubkey,
 };
 
-use crate::state::orderbook::{CallbackInfo, OrderBookState, OrderSummary};
+use crate::state::orderbook::{CallbackInfo, CancelledOrder, OrderBookState};
 use crate::state::AccountTag;
 use crate::{
     error::AoError,
-    state::{get_side_from_order_id, market_state::MarketState},
+    state::market_state::MarketState,
     utils::{check_account_key, check_account_owner},
 };
 #[derive(BorshDeserialize, BorshSerialize, Clone, BorshSize)]
@@ -77,7 +76,7 @@ pub fn process<'a, 'b: 'a, C: CallbackInfo + Pod + PartialEq>(
     program_id: &Pubkey,
     accounts: Accounts<'a, AccountInfo<'b>>,
     params: Params,
-) -> Result<OrderSummary, ProgramError>
+) -> Result<CancelledOrder<C>, ProgramError>
 where
     <C as CallbackInfo>::CallbackId: PartialEq,
 {
@@ -86,28 +85,16 @@ where
     let market_state = MarketState::from_buffer(&mut market_state_data, AccountTag::Market)?;
 
     check_accounts(&accounts, market_state)?;
+    market_state.check_can_cancel_order()?;
 
     let mut bids_guard = accounts.bids.data.borrow_mut();
     let mut asks_guard = accounts.asks.data.borrow_mut();
 
     let mut order_book = OrderBookState::<C>::new_safe(&mut bids_guard, &mut asks_guard)?;
 
-    let slab = order_book.get_tree(get_side_from_order_id(params.order_id));
-    let (leaf_node, _) = slab
-        .remove_by_key(params.order_id)
-        .ok_or(AoError::OrderNotFound)?;
-    let total_base_qty = leaf_node.base_quantity();
-    let total_quote_qty = fp32_mul_floor(leaf_node.base_quantity(), leaf_node.price())
-        .ok_or(AoError::NumericalOverflow)?;
+    let cancelled_order = order_book.cancel_order(params.order_id, market_state)?;
 
-    let order_summary = OrderSummary {
-        posted_order_id: None,
-        total_base_qty,
-        total_quote_qty,
-        total_base_qty_posted: 0,
-    };
-
-    Ok(order_summary)
+    Ok(cancelled_order)
 }
 
 fn check_accounts<'a, 'b: 'a>(
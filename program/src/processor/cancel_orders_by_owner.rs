@@ -0,0 +1,145 @@
+//! Cancel all the orders of a given owner, identified by a callback information prefix.
+
+use bonfida_utils::{BorshSize, InstructionsAccount};
+use borsh::{BorshDeserialize, BorshSerialize};
+use bytemuck::Pod;
+use solana_program::{
+    account_info::{next_account_info, AccountInfo},
+    entrypoint::ProgramResult,
+    program_error::ProgramError,
+    pubkey::Pubkey,
+};
+
+use crate::{
+    error::AoError,
+    state::{
+        event_queue::EventQueue,
+        market_state::MarketState,
+        orderbook::{CallbackInfo, OrderBookState, OrderSummary},
+        AccountTag,
+    },
+    utils::{check_account_key, check_account_owner, check_event_queue_shards},
+};
+#[derive(BorshDeserialize, BorshSerialize, Clone, BorshSize)]
+/**
+The required arguments for a cancel_orders_by_owner instruction.
+*/
+pub struct Params {
+    /// Orders whose callback information starts with this prefix are cancelled. Passing the whole callback information
+    /// avoids scanning the orderbook
+    pub callback_info_prefix: Vec<u8>,
+    /// Depending on available compute or space on the event queue, there may
+    /// be a limit to the amount of orders that can be cancelled in one transaction
+    pub max_cancels: u64,
+}
+
+/// The required accounts for a cancel_orders_by_owner instruction.
+#[derive(InstructionsAccount)]
+pub struct Accounts<'a, T> {
+    #[allow(missing_docs)]
+    pub market: &'a T,
+    #[allow(missing_docs)]
+    #[cons(writable)]
+    pub event_queue: &'a T,
+    #[allow(missing_docs)]
+    #[cons(writable)]
+    pub bids: &'a T,
+    #[allow(missing_docs)]
+    #[cons(writable)]
+    pub asks: &'a T,
+    /// The event queue shards of the market, in the order in which they were registered
+    #[cons(writable)]
+    pub event_queue_shards: &'a [T],
+}
+
+impl<'a, 'b: 'a> Accounts<'a, AccountInfo<'b>> {
+    pub(crate) fn parse(accounts: &'a [AccountInfo<'b>]) -> Result<Self, ProgramError> {
+        let accounts_iter = &mut accounts.iter();
+
+        let a = Self {
+            market: next_account_info(accounts_iter)?,
+            event_queue: next_account_info(accounts_iter)?,
+            bids: next_account_info(accounts_iter)?,
+            asks: next_account_info(accounts_iter)?,
+            event_queue_shards: accounts_iter.as_slice(),
+        };
+        Ok(a)
+    }
+    /// Perform basic security checks on the accounts
+    pub(crate) fn perform_checks(&self, program_id: &Pubkey) -> Result<(), ProgramError> {
+        check_account_owner(
+            self.market,
+            &program_id.to_bytes(),
+            AoError::WrongMarketOwner,
+        )?;
+        check_account_owner(
+            self.event_queue,
+            &program_id.to_bytes(),
+            AoError::WrongEventQueueOwner,
+        )?;
+        check_account_owner(self.bids, &program_id.to_bytes(), AoError::WrongBidsOwner)?;
+        check_account_owner(self.asks, &program_id.to_bytes(), AoError::WrongAsksOwner)?;
+        for shard in self.event_queue_shards {
+            check_account_owner(shard, &program_id.to_bytes(), AoError::WrongEventQueueOwner)?;
+        }
+        Ok(())
+    }
+}
+/// Apply the cancel_orders_by_owner instruction to the provided accounts
+pub fn process<'a, 'b: 'a, C: CallbackInfo + Pod + PartialEq>(
+    program_id: &Pubkey,
+    accounts: Accounts<'a, AccountInfo<'b>>,
+    params: Params,
+) -> Result<OrderSummary, ProgramError>
+where
+    <C as CallbackInfo>::CallbackId: PartialEq,
+{
+    accounts.perform_checks(program_id)?;
+    let mut market_data = accounts.market.data.borrow_mut();
+    let market_state = MarketState::from_buffer(&mut market_data, AccountTag::Market)?;
+
+    check_accounts(&accounts, market_state)?;
+    market_state.check_can_cancel_order()?;
+
+    let mut bids_guard = accounts.bids.data.borrow_mut();
+    let mut asks_guard = accounts.asks.data.borrow_mut();
+
+    let mut order_book = OrderBookState::<C>::new_safe(&mut bids_guard, &mut asks_guard)?;
+
+    let mut event_queue_guard = accounts.event_queue.data.borrow_mut();
+    let mut event_queue_shards_guards = accounts
+        .event_queue_shards
+        .iter()
+        .map(|a| a.data.borrow_mut())
+        .collect::<Vec<_>>();
+    let mut event_queue = EventQueue::from_market_buffers(
+        &mut event_queue_guard,
+        &mut event_queue_shards_guards,
+        market_state,
+    )?;
+
+    let order_summary = order_book.cancel_orders_by_prefix(
+        &params.callback_info_prefix,
+        params.max_cancels,
+        market_state.quote_scale_exponent,
+        &mut event_queue,
+    )?;
+
+    Ok(order_summary)
+}
+
+fn check_accounts<'a, 'b: 'a>(
+    accounts: &Accounts<'a, AccountInfo<'b>>,
+    market_state: &MarketState,
+) -> ProgramResult {
+    check_account_key(
+        accounts.event_queue,
+        &market_state.event_queue,
+        AoError::WrongEventQueueAccount,
+    )?;
+    check_account_key(accounts.bids, &market_state.bids, AoError::WrongBidsAccount)?;
+    check_account_key(accounts.asks, &market_state.asks, AoError::WrongAsksAccount)?;
+
+    check_event_queue_shards(accounts.event_queue_shards, market_state)?;
+    Ok(())
+}
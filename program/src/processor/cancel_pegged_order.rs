@@ -0,0 +1,150 @@
+//! Cancel an existing pegged order.
+use bonfida_utils::{BorshSize, InstructionsAccount};
+use borsh::{BorshDeserialize, BorshSerialize};
+use bytemuck::Pod;
+use solana_program::{
+    account_info::{next_account_info, AccountInfo},
+    entrypoint::ProgramResult,
+    program_error::ProgramError,
+    pubkey::Pubkey,
+};
+
+use crate::{
+    error::AoError,
+    state::{
+        get_side_from_order_id,
+        market_state::MarketState,
+        orderbook::{CallbackInfo, OrderBookState, OrderSummary},
+        pegged_orders::PeggedOrdersSlab,
+        AccountTag, Side,
+    },
+    utils::{check_account_key, check_account_owner, compute_quote_qty},
+};
+
+#[derive(BorshDeserialize, BorshSerialize, Clone, BorshSize)]
+/**
+The required arguments for a cancel_pegged_order instruction.
+*/
+pub struct Params {
+    /// The id of the pegged order, as returned by the new_pegged_order instruction
+    pub order_id: u128,
+}
+
+/// The required accounts for a cancel_pegged_order instruction.
+#[derive(InstructionsAccount)]
+pub struct Accounts<'a, T> {
+    #[allow(missing_docs)]
+    pub market: &'a T,
+    #[allow(missing_docs)]
+    #[cons(writable)]
+    pub bids: &'a T,
+    #[allow(missing_docs)]
+    #[cons(writable)]
+    pub asks: &'a T,
+    #[allow(missing_docs)]
+    #[cons(writable)]
+    pub pegged_orders: &'a T,
+}
+
+impl<'a, 'b: 'a> Accounts<'a, AccountInfo<'b>> {
+    pub(crate) fn parse(accounts: &'a [AccountInfo<'b>]) -> Result<Self, ProgramError> {
+        let accounts_iter = &mut accounts.iter();
+
+        let a = Self {
+            market: next_account_info(accounts_iter)?,
+            bids: next_account_info(accounts_iter)?,
+            asks: next_account_info(accounts_iter)?,
+            pegged_orders: next_account_info(accounts_iter)?,
+        };
+        Ok(a)
+    }
+
+    /// Perform basic security checks on the accounts
+    pub(crate) fn perform_checks(&self, program_id: &Pubkey) -> Result<(), ProgramError> {
+        check_account_owner(
+            self.market,
+            &program_id.to_bytes(),
+            AoError::WrongMarketOwner,
+        )?;
+        check_account_owner(self.bids, &program_id.to_bytes(), AoError::WrongBidsOwner)?;
+        check_account_owner(self.asks, &program_id.to_bytes(), AoError::WrongAsksOwner)?;
+        check_account_owner(
+            self.pegged_orders,
+            &program_id.to_bytes(),
+            AoError::WrongPeggedOrdersOwner,
+        )?;
+        Ok(())
+    }
+}
+/// Apply the cancel_pegged_order instruction to the provided accounts
+pub fn process<'a, 'b: 'a, C: CallbackInfo + Pod + PartialEq>(
+    program_id: &Pubkey,
+    accounts: Accounts<'a, AccountInfo<'b>>,
+    params: Params,
+) -> Result<OrderSummary, ProgramError>
+where
+    <C as CallbackInfo>::CallbackId: PartialEq,
+{
+    accounts.perform_checks(program_id)?;
+    let mut market_state_data = accounts.market.data.borrow_mut();
+    let market_state = MarketState::from_buffer(&mut market_state_data, AccountTag::Market)?;
+
+    check_accounts(&accounts, market_state)?;
+    market_state.check_can_cancel_order()?;
+
+    let mut pegged_orders_guard = accounts.pegged_orders.data.borrow_mut();
+    let mut pegged_orders_slab =
+        PeggedOrdersSlab::from_buffer(&mut pegged_orders_guard, AccountTag::PeggedOrders)?;
+
+    let (_, pegged_order) = pegged_orders_slab
+        .remove_by_key(params.order_id)
+        .ok_or(AoError::OrderNotFound)?;
+    let book_order_id = pegged_order.order_id;
+
+    let mut bids_guard = accounts.bids.data.borrow_mut();
+    let mut asks_guard = accounts.asks.data.borrow_mut();
+
+    let mut order_book = OrderBookState::<C>::new_safe(&mut bids_guard, &mut asks_guard)?;
+
+    let slab = order_book.get_tree(get_side_from_order_id(book_order_id));
+    let (leaf_node, _) = slab
+        .remove_by_key(book_order_id)
+        .ok_or(AoError::OrderNotFound)?;
+    let total_base_qty = leaf_node.total_base_quantity();
+    let total_quote_qty = compute_quote_qty(
+        Side::Ask,
+        leaf_node.total_base_quantity(),
+        leaf_node.price(),
+        market_state.quote_scale_exponent,
+    )
+    .ok_or(AoError::NumericalOverflow)?;
+
+    let order_summary = OrderSummary {
+        posted_order_id: None,
+        total_base_qty,
+        total_quote_qty,
+        total_base_qty_posted: 0,
+        total_quote_qty_posted: 0,
+        total_base_qty_unfilled: 0,
+        flags: leaf_node.flags(),
+        match_limit_exhausted: false,
+        compute_budget_exhausted: false,
+    };
+
+    Ok(order_summary)
+}
+
+fn check_accounts<'a, 'b: 'a>(
+    accounts: &Accounts<'a, AccountInfo<'b>>,
+    market_state: &MarketState,
+) -> ProgramResult {
+    check_account_key(accounts.bids, &market_state.bids, AoError::WrongBidsAccount)?;
+    check_account_key(accounts.asks, &market_state.asks, AoError::WrongAsksAccount)?;
+    check_account_key(
+        accounts.pegged_orders,
+        &market_state.pegged_orders,
+        AoError::WrongPeggedOrdersAccount,
+    )?;
+
+    Ok(())
+}
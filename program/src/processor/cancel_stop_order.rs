@@ -0,0 +1,112 @@
+//! Cancel an existing stop order.
+use bonfida_utils::{BorshSize, InstructionsAccount};
+use borsh::{BorshDeserialize, BorshSerialize};
+use bytemuck::Pod;
+use solana_program::{
+    account_info::{next_account_info, AccountInfo},
+    entrypoint::ProgramResult,
+    program_error::ProgramError,
+    pubkey::Pubkey,
+};
+
+use crate::{
+    error::AoError,
+    state::{
+        market_state::MarketState, orderbook::OrderSummary, stop_orders::StopOrdersSlab, AccountTag,
+    },
+    utils::{check_account_key, check_account_owner},
+};
+
+#[derive(BorshDeserialize, BorshSerialize, Clone, BorshSize)]
+/**
+The required arguments for a cancel_stop_order instruction.
+*/
+pub struct Params {
+    /// The id of the stop order, as returned by the new_stop_order instruction
+    pub order_id: u128,
+}
+
+/// The required accounts for a cancel_stop_order instruction.
+#[derive(InstructionsAccount)]
+pub struct Accounts<'a, T> {
+    #[allow(missing_docs)]
+    pub market: &'a T,
+    #[allow(missing_docs)]
+    #[cons(writable)]
+    pub stop_orders: &'a T,
+}
+
+impl<'a, 'b: 'a> Accounts<'a, AccountInfo<'b>> {
+    pub(crate) fn parse(accounts: &'a [AccountInfo<'b>]) -> Result<Self, ProgramError> {
+        let accounts_iter = &mut accounts.iter();
+
+        let a = Self {
+            market: next_account_info(accounts_iter)?,
+            stop_orders: next_account_info(accounts_iter)?,
+        };
+        Ok(a)
+    }
+
+    /// Perform basic security checks on the accounts
+    pub(crate) fn perform_checks(&self, program_id: &Pubkey) -> Result<(), ProgramError> {
+        check_account_owner(
+            self.market,
+            &program_id.to_bytes(),
+            AoError::WrongMarketOwner,
+        )?;
+        check_account_owner(
+            self.stop_orders,
+            &program_id.to_bytes(),
+            AoError::WrongStopOrdersOwner,
+        )?;
+        Ok(())
+    }
+}
+/// Apply the cancel_stop_order instruction to the provided accounts
+pub fn process<'a, 'b: 'a, C: Pod>(
+    program_id: &Pubkey,
+    accounts: Accounts<'a, AccountInfo<'b>>,
+    params: Params,
+) -> Result<OrderSummary, ProgramError> {
+    accounts.perform_checks(program_id)?;
+    let mut market_state_data = accounts.market.data.borrow_mut();
+    let market_state = MarketState::from_buffer(&mut market_state_data, AccountTag::Market)?;
+
+    check_accounts(&accounts, market_state)?;
+    market_state.check_can_cancel_order()?;
+
+    let mut stop_orders_guard = accounts.stop_orders.data.borrow_mut();
+    let mut stop_orders_slab =
+        StopOrdersSlab::<C>::from_buffer(&mut stop_orders_guard, AccountTag::StopOrders)?;
+
+    let (leaf_node, stop_order) = stop_orders_slab
+        .remove_by_key(params.order_id)
+        .ok_or(AoError::OrderNotFound)?;
+
+    let order_summary = OrderSummary {
+        posted_order_id: None,
+        total_base_qty: leaf_node.base_quantity(),
+        total_quote_qty: stop_order.max_quote_qty,
+        total_base_qty_posted: 0,
+        total_quote_qty_posted: 0,
+        total_base_qty_unfilled: 0,
+        flags: stop_order.flags,
+        match_limit_exhausted: false,
+        compute_budget_exhausted: false,
+    };
+
+    Ok(order_summary)
+}
+
+fn check_accounts<'a, 'b: 'a>(
+    accounts: &Accounts<'a, AccountInfo<'b>>,
+    market_state: &MarketState,
+) -> ProgramResult {
+    check_account_key(
+        accounts.stop_orders,
+        &market_state.stop_orders,
+        AoError::WrongStopOrdersAccount,
+    )?;
+
+    Ok(())
+}
@@ -3,9 +3,12 @@ use crate::{
     error::AoError,
     state::{
         event_queue::EventQueue,
+        market_registry::MarketRegistry,
         market_state::MarketState,
         orderbook::{CallbackInfo, OrderBookState},
-        AccountTag,
+        pegged_orders::PeggedOrdersSlab,
+        stop_orders::StopOrdersSlab,
+        AccountTag, ACCOUNT_TAG_LENGTH,
     },
     utils::{check_account_key, check_account_owner},
 };
@@ -44,6 +47,11 @@ pub struct Accounts<'a, T> {
     #[allow(missing_docs)]
     #[cons(writable)]
     pub lamports_target_account: &'a T,
+    /// The other accounts which are registered on the market, in this order: the stop orders account, the pegged
+    /// orders account, the dead letter queue account, the event queue shards and the market registry, skipping those
+    /// which don't exist
+    #[cons(writable)]
+    pub registered_accounts: &'a [T],
 }
 
 impl<'a, 'b: 'a> Accounts<'a, AccountInfo<'b>> {
@@ -56,6 +64,7 @@ impl<'a, 'b: 'a> Accounts<'a, AccountInfo<'b>> {
             bids: next_account_info(accounts_iter)?,
             asks: next_account_info(accounts_iter)?,
             lamports_target_account: next_account_info(accounts_iter)?,
+            registered_accounts: accounts_iter.as_slice(),
         };
         Ok(a)
     }
@@ -88,7 +97,7 @@ where
     let mut asks_data = accounts.asks.data.borrow_mut();
 
     // Check if there are still orders in the book
-    let orderbook_state = OrderBookState::<C>::new_safe(&mut bids_data, &mut asks_data).unwrap();
+    let orderbook_state = OrderBookState::<C>::new_safe(&mut bids_data, &mut asks_data)?;
     if !orderbook_state.is_empty() {
         msg!("The orderbook must be empty");
         return Err(ProgramError::from(AoError::MarketStillActive));
@@ -103,25 +112,89 @@ where
         return Err(ProgramError::from(AoError::MarketStillActive));
     }
 
-    *bytemuck::from_bytes_mut(&mut market_data[0..8]) = AccountTag::Disabled as u64;
-    *bytemuck::from_bytes_mut(&mut asks_data[0..8]) = AccountTag::Disabled as u64;
-    *bytemuck::from_bytes_mut(&mut bids_data[0..8]) = AccountTag::Disabled as u64;
-    *bytemuck::from_bytes_mut(&mut event_queue_data[0..8]) = AccountTag::Disabled as u64;
-
-    let mut market_lamports = accounts.market.lamports.borrow_mut();
-    let mut bids_lamports = accounts.bids.lamports.borrow_mut();
-    let mut asks_lamports = accounts.asks.lamports.borrow_mut();
-    let mut event_queue_lamports = accounts.event_queue.lamports.borrow_mut();
+    // The market registry isn't closed along with the market, the market is only removed from it
+    let (closed_accounts, market_registry) = if market_state.market_registry != Pubkey::default() {
+        let (market_registry, closed_accounts) = accounts
+            .registered_accounts
+            .split_last()
+            .ok_or(ProgramError::NotEnoughAccountKeys)?;
+        (closed_accounts, Some(market_registry))
+    } else {
+        (accounts.registered_accounts, None)
+    };
+
+    let mut registered_accounts = closed_accounts.iter();
+    if market_state.stop_orders != Pubkey::default() {
+        let stop_orders = registered_accounts
+            .next()
+            .ok_or(ProgramError::NotEnoughAccountKeys)?;
+        let mut stop_orders_data = stop_orders.data.borrow_mut();
+        if !StopOrdersSlab::<C>::from_buffer(&mut stop_orders_data, AccountTag::StopOrders)?
+            .is_empty()
+        {
+            msg!("The stop orders account must be empty");
+            return Err(ProgramError::from(AoError::MarketStillActive));
+        }
+    }
+    if market_state.pegged_orders != Pubkey::default() {
+        let pegged_orders = registered_accounts
+            .next()
+            .ok_or(ProgramError::NotEnoughAccountKeys)?;
+        let mut pegged_orders_data = pegged_orders.data.borrow_mut();
+        if !PeggedOrdersSlab::from_buffer(&mut pegged_orders_data, AccountTag::PeggedOrders)?
+            .is_empty()
+        {
+            msg!("The pegged orders account must be empty");
+            return Err(ProgramError::from(AoError::MarketStillActive));
+        }
+    }
+    if market_state.order_allowlist != Pubkey::default() {
+        // The allowlist is closed along with the market regardless of its entries
+        registered_accounts
+            .next()
+            .ok_or(ProgramError::NotEnoughAccountKeys)?;
+    }
+    for queue in registered_accounts {
+        let mut queue_data = queue.data.borrow_mut();
+        if !EventQueue::<C>::from_buffer(&mut queue_data, AccountTag::EventQueue)?.is_empty() {
+            msg!("The dead letter queue and the event queue shards need to be empty");
+            return Err(ProgramError::from(AoError::MarketStillActive));
+        }
+    }
 
-    let mut target_lamports = accounts.lamports_target_account.lamports.borrow_mut();
+    if let Some(market_registry) = market_registry {
+        MarketRegistry::from_buffer(
+            &mut market_registry.data.borrow_mut(),
+            AccountTag::MarketRegistry,
+        )?
+        .remove(accounts.market.key)?;
+    }
 
-    **target_lamports +=
-        **market_lamports + **bids_lamports + **asks_lamports + **event_queue_lamports;
+    drop(market_data);
+    drop(bids_data);
+    drop(asks_data);
+    drop(event_queue_data);
 
-    **market_lamports = 0;
-    **bids_lamports = 0;
-    **asks_lamports = 0;
-    **event_queue_lamports = 0;
+    let mut target_lamports = accounts.lamports_target_account.lamports.borrow_mut();
+    for account in [
+        accounts.market,
+        accounts.bids,
+        accounts.asks,
+        accounts.event_queue,
+    ]
+    .iter()
+    .copied()
+    .chain(closed_accounts)
+    {
+        // The accounts are kept tagged as disabled so that they can't be initialized again
+        let mut data = account.data.borrow_mut();
+        *bytemuck::from_bytes_mut(&mut data[0..ACCOUNT_TAG_LENGTH]) = AccountTag::Disabled as u64;
+        data[ACCOUNT_TAG_LENGTH..].fill(0);
+
+        let mut lamports = account.lamports.borrow_mut();
+        **target_lamports += **lamports;
+        **lamports = 0;
+    }
 
     Ok(())
 }
@@ -138,5 +211,28 @@ fn check_accounts<'a, 'b: 'a>(
     check_account_key(accounts.bids, &market_state.bids, AoError::WrongBidsAccount)?;
     check_account_key(accounts.asks, &market_state.asks, AoError::WrongAsksAccount)?;
 
+    let optional_accounts = [
+        market_state.stop_orders,
+        market_state.pegged_orders,
+        market_state.order_allowlist,
+        market_state.dead_letter_queue,
+    ];
+    let registered_accounts = optional_accounts
+        .iter()
+        .chain(market_state.event_queue_shards())
+        .chain(std::iter::once(&market_state.market_registry))
+        .filter(|k| k != &&Pubkey::default())
+        .collect::<Vec<_>>();
+    if accounts.registered_accounts.len() != registered_accounts.len()
+        || accounts
+            .registered_accounts
+            .iter()
+            .zip(registered_accounts)
+            .any(|(a, k)| a.key != k)
+    {
+        msg!("The accounts registered on the market must be provided");
+        return Err(AoError::MissingRegisteredAccounts.into());
+    }
+
     Ok(())
 }
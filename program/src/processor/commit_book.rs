@@ -0,0 +1,124 @@
+//! Commit to the orders of a market by writing the Merkle roots over the price levels of its bids and asks.
+//!
+//! The roots are a snapshot of the book at the slot of the instruction: they aren't updated by the instructions which
+//! modify the book afterwards, so a proof only holds until the proven side changes. Computing them hashes every order
+//! of the book, so the instruction fails when the book holds more than [`MAX_COMMITTED_ORDERS`] orders.
+use bonfida_utils::{BorshSize, InstructionsAccount};
+use borsh::{BorshDeserialize, BorshSerialize};
+use bytemuck::Pod;
+use solana_program::{
+    account_info::{next_account_info, AccountInfo},
+    clock::Clock,
+    entrypoint::ProgramResult,
+    msg,
+    program_error::ProgramError,
+    pubkey::Pubkey,
+    sysvar::Sysvar,
+};
+
+use crate::{
+    error::AoError,
+    state::{
+        critbit::{iter_slab_orders, read_slab_header},
+        market_state::MarketState,
+        merkle, AccountTag,
+    },
+    utils::{check_account_key, check_account_owner},
+};
+
+/// The maximum number of orders, on both sides combined, which a commit_book instruction hashes, so that it fits in
+/// the compute budget of a transaction.
+pub const MAX_COMMITTED_ORDERS: usize = 1024;
+
+#[derive(BorshDeserialize, BorshSerialize, BorshSize)]
+/**
+The required arguments for a commit_book instruction.
+*/
+pub struct Params {}
+
+/// The required accounts for a commit_book instruction.
+#[derive(InstructionsAccount)]
+pub struct Accounts<'a, T> {
+    #[allow(missing_docs)]
+    #[cons(writable)]
+    pub market: &'a T,
+    #[allow(missing_docs)]
+    pub bids: &'a T,
+    #[allow(missing_docs)]
+    pub asks: &'a T,
+}
+
+impl<'a, 'b: 'a> Accounts<'a, AccountInfo<'b>> {
+    pub(crate) fn parse(accounts: &'a [AccountInfo<'b>]) -> Result<Self, ProgramError> {
+        let accounts_iter = &mut accounts.iter();
+
+        let a = Self {
+            market: next_account_info(accounts_iter)?,
+            bids: next_account_info(accounts_iter)?,
+            asks: next_account_info(accounts_iter)?,
+        };
+        Ok(a)
+    }
+
+    /// Perform basic security checks on the accounts
+    pub(crate) fn perform_checks(&self, program_id: &Pubkey) -> Result<(), ProgramError> {
+        check_account_owner(
+            self.market,
+            &program_id.to_bytes(),
+            AoError::WrongMarketOwner,
+        )?;
+        check_account_owner(self.bids, &program_id.to_bytes(), AoError::WrongBidsOwner)?;
+        check_account_owner(self.asks, &program_id.to_bytes(), AoError::WrongAsksOwner)?;
+        Ok(())
+    }
+}
+
+/// Apply the commit_book instruction to the provided accounts
+pub fn process<'a, 'b: 'a, C: Pod>(
+    program_id: &Pubkey,
+    accounts: Accounts<'a, AccountInfo<'b>>,
+    _params: Params,
+) -> ProgramResult {
+    accounts.perform_checks(program_id)?;
+    let mut market_data = accounts.market.data.borrow_mut();
+    let market_state = MarketState::from_buffer_mut(&mut market_data, AccountTag::Market)?;
+
+    check_accounts(&accounts, market_state)?;
+
+    let bids_data = accounts.bids.data.borrow();
+    let asks_data = accounts.asks.data.borrow();
+    // The size of the book is checked before any order is read
+    let num_orders = read_slab_header(&bids_data, AccountTag::Bids)?.leaf_count as usize
+        + read_slab_header(&asks_data, AccountTag::Asks)?.leaf_count as usize;
+    if num_orders > MAX_COMMITTED_ORDERS {
+        msg!(
+            "The book holds {} orders, more than the {} which can be committed!",
+            num_orders,
+            MAX_COMMITTED_ORDERS
+        );
+        return Err(AoError::BookTooLargeToCommit.into());
+    }
+
+    let callback_info_len = std::mem::size_of::<C>();
+    market_state.bids_root = merkle::compute_book_root(
+        iter_slab_orders(&bids_data, AccountTag::Bids, callback_info_len, true)?
+            .map(|(leaf, _)| leaf),
+    );
+    market_state.asks_root = merkle::compute_book_root(
+        iter_slab_orders(&asks_data, AccountTag::Asks, callback_info_len, true)?
+            .map(|(leaf, _)| leaf),
+    );
+    market_state.book_commit_slot = Clock::get()?.slot;
+
+    Ok(())
+}
+
+fn check_accounts<'a, 'b: 'a>(
+    accounts: &Accounts<'a, AccountInfo<'b>>,
+    market_state: &MarketState,
+) -> ProgramResult {
+    check_account_key(accounts.bids, &market_state.bids, AoError::WrongBidsAccount)?;
+    check_account_key(accounts.asks, &market_state.asks, AoError::WrongAsksAccount)?;
+
+    Ok(())
+}
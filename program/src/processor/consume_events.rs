@@ -1,11 +1,23 @@
 //! Pop a series of events off the event queue.
+//!
+//! When the market has a consumer program, the popped events are pushed into it through CPI. Each call is a
+//! `handle_event` instruction whose accounts are the consumer authority, which signs, the market and the consumer
+//! accounts of the consume_events instruction. Its data is the [`HANDLE_EVENT_DISCRIMINATOR`] followed by at most
+//! [`max_events_per_consumer_call`][`MarketState::max_events_per_consumer_call`] events, serialized as by
+//! [`EventQueue::serialize_front`] so that they can be read back with
+//! [`read_consumed_events`][`crate::state::event_queue::read_consumed_events`].
+//!
+//! Anyone can call the consumer program directly: it must check that the consumer authority, as returned by
+//! [`find_consumer_authority`] for the orderbook program and the market, is a signer.
 use bonfida_utils::{BorshSize, InstructionsAccount};
 use borsh::{BorshDeserialize, BorshSerialize};
 use bytemuck::Pod;
 use solana_program::{
     account_info::{next_account_info, AccountInfo},
     entrypoint::ProgramResult,
+    instruction::{AccountMeta, Instruction},
     msg,
+    program::invoke_signed,
     program_error::ProgramError,
     pubkey::Pubkey,
 };
@@ -13,11 +25,28 @@ use solana_program::{
 use crate::{
     error::AoError,
     state::{
-        event_queue::EventQueue, market_state::MarketState, orderbook::CallbackInfo, AccountTag,
+        event_queue::{EventQueue, FillEvent},
+        market_state::MarketState,
+        orderbook::CallbackInfo,
+        AccountTag,
     },
     utils::{check_account_key, check_account_owner},
 };
 
+/// The seed of the program derived address which signs the calls to the consumer program of a market, along with the
+/// market's key.
+pub const CONSUMER_AUTHORITY_SEED: &[u8] = b"consumer_authority";
+
+/// The first bytes of the instruction data of the calls to a consumer program, which are the Anchor discriminator of
+/// a `handle_event` instruction.
+pub const HANDLE_EVENT_DISCRIMINATOR: [u8; 8] = [249, 75, 119, 106, 197, 238, 202, 212];
+
+/// Returns the program derived address which signs the calls of the orderbook program `program_id` to the consumer
+/// program of `market`, along with its bump seed.
+pub fn find_consumer_authority(program_id: &Pubkey, market: &Pubkey) -> (Pubkey, u8) {
+    Pubkey::find_program_address(&[CONSUMER_AUTHORITY_SEED, &market.to_bytes()], program_id)
+}
+
 #[derive(BorshDeserialize, BorshSerialize, Clone, BorshSize)]
 /**
 The required arguments for a consume_events instruction.
@@ -25,6 +54,20 @@ The required arguments for a consume_events instruction.
 pub struct Params {
     /// Depending on applications, it might be optimal to process several events at a time
     pub number_of_entries_to_consume: u64,
+    /// Events with a sequence number greater than this value are not consumed, which lets a consumer stop at its own
+    /// cursor. Use `u64::MAX` to disable this bound.
+    ///
+    /// See [`EventQueueHeader::event_seq_num`][`crate::state::event_queue::EventQueueHeader::event_seq_num`].
+    pub max_seq_num: u64,
+    /// Whether the consumed events are written into the register, so that a calling program can read them back
+    /// with [`read_consumed_events`][`crate::state::event_queue::read_consumed_events`].
+    ///
+    /// The register account should then be at least
+    /// [`compute_consumed_events_register_size`][`crate::state::event_queue::compute_consumed_events_register_size`]
+    /// bytes long.
+    pub write_to_register: bool,
+    /// The index of the consumed queue: 0 for the event queue, or `i` for the `i`-th event queue shard of the market.
+    pub queue_index: u64,
 }
 
 /// The required accounts for a consume_events instruction.
@@ -35,6 +78,13 @@ pub struct Accounts<'a, T> {
     #[allow(missing_docs)]
     #[cons(writable)]
     pub event_queue: &'a T,
+    /// The consumer program of the market, which must be given when the market has one
+    pub consumer_program: Option<&'a T>,
+    /// The program derived address which signs the calls to the consumer program, see [`find_consumer_authority`]
+    pub consumer_authority: Option<&'a T>,
+    /// The accounts passed on to the consumer program
+    #[cons(writable)]
+    pub consumer_accounts: &'a [T],
 }
 
 impl<'a, 'b: 'a> Accounts<'a, AccountInfo<'b>> {
@@ -44,6 +94,9 @@ impl<'a, 'b: 'a> Accounts<'a, AccountInfo<'b>> {
         let a = Self {
             market: next_account_info(accounts_iter)?,
             event_queue: next_account_info(accounts_iter)?,
+            consumer_program: next_account_info(accounts_iter).ok(),
+            consumer_authority: next_account_info(accounts_iter).ok(),
+            consumer_accounts: accounts_iter.as_slice(),
         };
         Ok(a)
     }
@@ -64,49 +117,152 @@ impl<'a, 'b: 'a> Accounts<'a, AccountInfo<'b>> {
 }
 
 /// Apply the consume_events instruction to the provided accounts
+///
+/// When `write_to_register` is set, the consumed events are returned, serialized by
+/// [`EventQueue::serialize_front`]. When the market has a consumer program, they are pushed into it once they have been
+/// popped.
 pub fn process<'a, 'b: 'a, C: CallbackInfo + Pod + PartialEq>(
     program_id: &Pubkey,
     accounts: Accounts<'a, AccountInfo<'b>>,
     params: Params,
-) -> ProgramResult {
+) -> Result<Option<Vec<u8>>, ProgramError> {
     accounts.perform_checks(program_id)?;
     let mut market_data = accounts.market.data.borrow_mut();
     let market_state = MarketState::from_buffer(&mut market_data, AccountTag::Market)?;
 
-    check_accounts(&accounts, market_state)?;
+    check_accounts(&accounts, market_state, params.queue_index)?;
+    let consumer = check_consumer_accounts(program_id, &accounts, market_state)?;
+    let max_events_per_consumer_call = market_state.max_events_per_consumer_call;
     let mut event_queue_guard = accounts.event_queue.data.borrow_mut();
     let mut event_queue =
         EventQueue::<C>::from_buffer(&mut event_queue_guard, AccountTag::EventQueue)?;
 
-    // Reward payout
     let capped_number_of_entries_consumed = std::cmp::min(
-        event_queue.header.count,
+        event_queue.count_up_to(params.max_seq_num),
         params.number_of_entries_to_consume,
     );
 
+    let consumed_events = (params.write_to_register || consumer.is_some())
+        .then(|| event_queue.serialize_front(capped_number_of_entries_consumed));
+
     // Pop Events
-    event_queue.pop_n(params.number_of_entries_to_consume);
+    event_queue.pop_n(capped_number_of_entries_consumed);
 
     msg!(
         "Number of events consumed: {:?}",
         capped_number_of_entries_consumed
     );
 
-    Ok(())
+    if let (Some((consumer_program, consumer_authority, bump)), Some(consumed_events)) =
+        (consumer, &consumed_events)
+    {
+        // The consumer program can't be invoked while the market and event queue are borrowed
+        drop(event_queue);
+        drop(event_queue_guard);
+        drop(market_data);
+
+        let mut account_metas = vec![
+            AccountMeta::new_readonly(*consumer_authority.key, true),
+            AccountMeta::new_readonly(*accounts.market.key, false),
+        ];
+        account_metas.extend(accounts.consumer_accounts.iter().map(|a| AccountMeta {
+            pubkey: *a.key,
+            is_signer: a.is_signer,
+            is_writable: a.is_writable,
+        }));
+        let mut account_infos = vec![consumer_authority.clone(), accounts.market.clone()];
+        account_infos.extend(accounts.consumer_accounts.iter().cloned());
+        account_infos.push(consumer_program.clone());
+
+        let market_key = accounts.market.key.to_bytes();
+        let signer_seeds: &[&[u8]] = &[CONSUMER_AUTHORITY_SEED, &market_key, &[bump]];
+        for data in handle_event_data::<C>(consumed_events, max_events_per_consumer_call) {
+            let instruction = Instruction {
+                program_id: *consumer_program.key,
+                accounts: account_metas.clone(),
+                data,
+            };
+            invoke_signed(&instruction, &account_infos, &[signer_seeds])?;
+        }
+    }
+
+    Ok(consumed_events.filter(|_| params.write_to_register))
 }
 
-fn check_accounts<'a, 'b: 'a>(
+/// Splits events serialized by [`EventQueue::serialize_front`] into the data of `handle_event` instructions of at
+/// most `max_events_per_call` events each.
+fn handle_event_data<C>(consumed_events: &[u8], max_events_per_call: u64) -> Vec<Vec<u8>> {
+    let entry_len = FillEvent::LEN + 2 * std::mem::size_of::<C>();
+    let events_per_call = match max_events_per_call {
+        0 => usize::MAX,
+        n => n as usize,
+    };
+    consumed_events[4..]
+        .chunks(events_per_call.saturating_mul(entry_len))
+        .map(|entries| {
+            let mut data = Vec::with_capacity(HANDLE_EVENT_DISCRIMINATOR.len() + 4 + entries.len());
+            data.extend_from_slice(&HANDLE_EVENT_DISCRIMINATOR);
+            data.extend_from_slice(&((entries.len() / entry_len) as u32).to_le_bytes());
+            data.extend_from_slice(entries);
+            data
+        })
+        .collect()
+}
+
+/// Checks the consumer accounts when the market has a consumer program, and returns the consumer program, the
+/// consumer authority and its bump seed.
+#[allow(clippy::type_complexity)]
+fn check_consumer_accounts<'a, 'b: 'a>(
+    program_id: &Pubkey,
     accounts: &Accounts<'a, AccountInfo<'b>>,
     market_state: &MarketState,
-) -> ProgramResult {
+) -> Result<Option<(&'a AccountInfo<'b>, &'a AccountInfo<'b>, u8)>, ProgramError> {
+    if market_state.consumer_program == Pubkey::default() {
+        return Ok(None);
+    }
+    let consumer_program = accounts.consumer_program.ok_or_else(|| {
+        msg!("The consumer program of the market must be provided!");
+        AoError::WrongConsumerProgram
+    })?;
     check_account_key(
-        accounts.event_queue,
-        &market_state.event_queue,
-        AoError::WrongEventQueueAccount,
+        consumer_program,
+        &market_state.consumer_program,
+        AoError::WrongConsumerProgram,
+    )?;
+    let (consumer_authority_key, bump) = find_consumer_authority(program_id, accounts.market.key);
+    let consumer_authority = accounts.consumer_authority.ok_or_else(|| {
+        msg!("The consumer authority of the market must be provided!");
+        AoError::WrongConsumerAuthority
+    })?;
+    check_account_key(
+        consumer_authority,
+        &consumer_authority_key,
+        AoError::WrongConsumerAuthority,
     )?;
+    Ok(Some((consumer_program, consumer_authority, bump)))
+}
+
+fn check_accounts<'a, 'b: 'a>(
+    accounts: &Accounts<'a, AccountInfo<'b>>,
+    market_state: &MarketState,
+    queue_index: u64,
+) -> ProgramResult {
+    // The dead letter queue is consumed like the event queue
+    if market_state.dead_letter_queue != Pubkey::default()
+        && accounts.event_queue.key == &market_state.dead_letter_queue
+    {
+        return Ok(());
+    }
+    let expected_event_queue = match queue_index {
+        0 => &market_state.event_queue,
+        i => market_state
+            .event_queue_shards()
+            .get(i as usize - 1)
+            .ok_or(AoError::WrongEventQueueAccount)?,
+    };
     check_account_key(
         accounts.event_queue,
-        &market_state.event_queue,
+        expected_event_queue,
         AoError::WrongEventQueueAccount,
     )?;
     Ok(())
@@ -0,0 +1,150 @@
+//! Continue matching a resumable order whose matching was interrupted by its match limit or compute unit reserve.
+
+use bonfida_utils::{BorshSize, InstructionsAccount};
+use borsh::{BorshDeserialize, BorshSerialize};
+use bytemuck::Pod;
+use solana_program::account_info::next_account_info;
+use solana_program::{
+    account_info::AccountInfo, clock::Clock, entrypoint::ProgramResult, msg,
+    program_error::ProgramError, pubkey::Pubkey, sysvar::Sysvar,
+};
+
+use crate::state::event_queue::EventQueue;
+use crate::state::orderbook::{CallbackInfo, OrderBookState};
+use crate::state::{AccountTag, OrderSummary};
+use crate::{
+    error::AoError,
+    state::market_state::MarketState,
+    utils::{check_account_key, check_account_owner, check_event_queue_shards},
+};
+#[derive(BorshDeserialize, BorshSerialize, Clone, BorshSize)]
+/**
+The required arguments for a continue_match instruction.
+*/
+pub struct Params {
+    /// The order id of the resumable order, as returned in the order summary of its new_order instruction.
+    pub order_id: u128,
+    /// The maximum number of orders to match against before the order is parked again.
+    pub match_limit: u64,
+    /// The number of compute units which must remain available for matching to continue against another order, or 0
+    /// to only rely on `match_limit`.
+    pub compute_unit_reserve: u64,
+    /// Whether the resumable order should be cancelled instead of matched.
+    pub cancel: bool,
+}
+
+/// The required accounts for a continue_match instruction.
+#[derive(InstructionsAccount)]
+pub struct Accounts<'a, T> {
+    #[allow(missing_docs)]
+    #[cons(writable)]
+    pub market: &'a T,
+    #[allow(missing_docs)]
+    #[cons(writable)]
+    pub event_queue: &'a T,
+    #[allow(missing_docs)]
+    #[cons(writable)]
+    pub bids: &'a T,
+    #[allow(missing_docs)]
+    #[cons(writable)]
+    pub asks: &'a T,
+    /// The event queue shards of the market, in the order in which they were registered
+    #[cons(writable)]
+    pub event_queue_shards: &'a [T],
+}
+
+impl<'a, 'b: 'a> Accounts<'a, AccountInfo<'b>> {
+    pub(crate) fn parse(accounts: &'a [AccountInfo<'b>]) -> Result<Self, ProgramError> {
+        let accounts_iter = &mut accounts.iter();
+
+        let a = Self {
+            market: next_account_info(accounts_iter)?,
+            event_queue: next_account_info(accounts_iter)?,
+            bids: next_account_info(accounts_iter)?,
+            asks: next_account_info(accounts_iter)?,
+            event_queue_shards: accounts_iter.as_slice(),
+        };
+        Ok(a)
+    }
+
+    /// Perform basic security checks on the accounts
+    pub(crate) fn perform_checks(&self, program_id: &Pubkey) -> Result<(), ProgramError> {
+        check_account_owner(
+            self.market,
+            &program_id.to_bytes(),
+            AoError::WrongMarketOwner,
+        )?;
+        check_account_owner(
+            self.event_queue,
+            &program_id.to_bytes(),
+            AoError::WrongEventQueueOwner,
+        )?;
+        check_account_owner(self.bids, &program_id.to_bytes(), AoError::WrongBidsOwner)?;
+        check_account_owner(self.asks, &program_id.to_bytes(), AoError::WrongAsksOwner)?;
+        for shard in self.event_queue_shards {
+            check_account_owner(shard, &program_id.to_bytes(), AoError::WrongEventQueueOwner)?;
+        }
+        Ok(())
+    }
+}
+/// Apply the continue_match instruction to the provided accounts
+pub fn process<'a, 'b: 'a, C: CallbackInfo + Pod + PartialEq>(
+    program_id: &Pubkey,
+    accounts: Accounts<'a, AccountInfo<'b>>,
+    params: Params,
+) -> Result<OrderSummary, ProgramError>
+where
+    <C as CallbackInfo>::CallbackId: PartialEq,
+{
+    accounts.perform_checks(program_id)?;
+    let mut market_data = accounts.market.data.borrow_mut();
+    let market_state = MarketState::from_buffer_mut(&mut market_data, AccountTag::Market)?;
+
+    check_accounts(&accounts, market_state)?;
+    if params.cancel {
+        market_state.check_can_cancel_order()?;
+    } else {
+        market_state.check_can_place_order(false)?;
+    }
+
+    let mut bids_guard = accounts.bids.data.borrow_mut();
+    let mut asks_guard = accounts.asks.data.borrow_mut();
+
+    let mut order_book = OrderBookState::<C>::new_safe(&mut bids_guard, &mut asks_guard)?;
+
+    let mut event_queue_guard = accounts.event_queue.data.borrow_mut();
+    let mut event_queue_shards_guards = accounts
+        .event_queue_shards
+        .iter()
+        .map(|a| a.data.borrow_mut())
+        .collect::<Vec<_>>();
+    let mut event_queue = EventQueue::from_market_buffers(
+        &mut event_queue_guard,
+        &mut event_queue_shards_guards,
+        market_state,
+    )?;
+
+    let cur_ts = Clock::get()?.unix_timestamp as u64;
+
+    let order_summary =
+        order_book.continue_match(params, &mut event_queue, market_state, cur_ts)?;
+    msg!("Order summary : {:?}", order_summary);
+
+    Ok(order_summary)
+}
+
+fn check_accounts<'a, 'b: 'a>(
+    accounts: &Accounts<'a, AccountInfo<'b>>,
+    market_state: &MarketState,
+) -> ProgramResult {
+    check_account_key(
+        accounts.event_queue,
+        &market_state.event_queue,
+        AoError::WrongEventQueueAccount,
+    )?;
+    check_account_key(accounts.bids, &market_state.bids, AoError::WrongBidsAccount)?;
+    check_account_key(accounts.asks, &market_state.asks, AoError::WrongAsksAccount)?;
+
+    check_event_queue_shards(accounts.event_queue_shards, market_state)?;
+    Ok(())
+}
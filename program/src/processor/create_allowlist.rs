@@ -0,0 +1,85 @@
+//! Create and initialize the allowlist account of an existing market, restricting order placement to the callback
+//! infos it lists
+use bonfida_utils::{checks::check_rent_exempt, BorshSize, InstructionsAccount};
+use borsh::{BorshDeserialize, BorshSerialize};
+use solana_program::{
+    account_info::{next_account_info, AccountInfo},
+    entrypoint::ProgramResult,
+    msg,
+    program_error::ProgramError,
+    pubkey::Pubkey,
+};
+
+use crate::{
+    error::AoError,
+    state::{allowlist::Allowlist, market_state::MarketState, AccountTag},
+    utils::check_account_owner,
+};
+
+#[derive(BorshDeserialize, BorshSerialize, BorshSize)]
+/**
+The required arguments for a create_allowlist instruction.
+*/
+pub struct Params {}
+
+/// The required accounts for a create_allowlist instruction.
+#[derive(InstructionsAccount)]
+pub struct Accounts<'a, T> {
+    #[allow(missing_docs)]
+    #[cons(writable)]
+    pub market: &'a T,
+    #[allow(missing_docs)]
+    #[cons(writable)]
+    pub allowlist: &'a T,
+}
+
+impl<'a, 'b: 'a> Accounts<'a, AccountInfo<'b>> {
+    pub(crate) fn parse(accounts: &'a [AccountInfo<'b>]) -> Result<Self, ProgramError> {
+        let accounts_iter = &mut accounts.iter();
+
+        let a = Self {
+            market: next_account_info(accounts_iter)?,
+            allowlist: next_account_info(accounts_iter)?,
+        };
+        Ok(a)
+    }
+    pub(crate) fn perform_checks(&self, program_id: &Pubkey) -> Result<(), ProgramError> {
+        check_account_owner(
+            self.market,
+            &program_id.to_bytes(),
+            AoError::WrongMarketOwner,
+        )?;
+        check_account_owner(
+            self.allowlist,
+            &program_id.to_bytes(),
+            AoError::WrongAllowlistAccount,
+        )?;
+        Ok(())
+    }
+}
+
+/// Apply the create_allowlist instruction to the provided accounts
+pub fn process(
+    program_id: &Pubkey,
+    accounts: Accounts<AccountInfo>,
+    _params: Params,
+) -> ProgramResult {
+    accounts.perform_checks(program_id)?;
+    check_rent_exempt(accounts.allowlist)?;
+
+    let mut market_data = accounts.market.data.borrow_mut();
+    let market_state = MarketState::from_buffer_mut(&mut market_data, AccountTag::Market)?;
+
+    if market_state.order_allowlist != Pubkey::default() {
+        msg!("The market already has an allowlist account!");
+        return Err(AoError::AlreadyInitialized.into());
+    }
+
+    Allowlist::from_buffer(
+        &mut accounts.allowlist.data.borrow_mut(),
+        AccountTag::Uninitialized,
+    )?;
+    market_state.order_allowlist = *accounts.allowlist.key;
+
+    Ok(())
+}
@@ -0,0 +1,93 @@
+//! Create and initialize the dead letter queue account of an existing market, enabling event expiry
+use bonfida_utils::{checks::check_rent_exempt, BorshSize, InstructionsAccount};
+use borsh::{BorshDeserialize, BorshSerialize};
+use bytemuck::Pod;
+use solana_program::{
+    account_info::{next_account_info, AccountInfo},
+    entrypoint::ProgramResult,
+    msg,
+    program_error::ProgramError,
+    pubkey::Pubkey,
+};
+
+use crate::{
+    error::AoError,
+    state::{event_queue::EventQueue, market_state::MarketState, AccountTag},
+    utils::check_account_owner,
+};
+
+#[derive(BorshDeserialize, BorshSerialize, BorshSize)]
+/**
+The required arguments for a create_dead_letter_queue instruction.
+*/
+pub struct Params {
+    /// The number of slots after which a pending event can be moved from the event queue to the dead letter queue.
+    pub max_event_age_slots: u64,
+}
+
+/// The required accounts for a create_dead_letter_queue instruction.
+#[derive(InstructionsAccount)]
+pub struct Accounts<'a, T> {
+    #[allow(missing_docs)]
+    #[cons(writable)]
+    pub market: &'a T,
+    #[allow(missing_docs)]
+    #[cons(writable)]
+    pub dead_letter_queue: &'a T,
+}
+
+impl<'a, 'b: 'a> Accounts<'a, AccountInfo<'b>> {
+    pub(crate) fn parse(accounts: &'a [AccountInfo<'b>]) -> Result<Self, ProgramError> {
+        let accounts_iter = &mut accounts.iter();
+
+        let a = Self {
+            market: next_account_info(accounts_iter)?,
+            dead_letter_queue: next_account_info(accounts_iter)?,
+        };
+        Ok(a)
+    }
+    pub(crate) fn perform_checks(&self, program_id: &Pubkey) -> Result<(), ProgramError> {
+        check_account_owner(
+            self.market,
+            &program_id.to_bytes(),
+            AoError::WrongMarketOwner,
+        )?;
+        check_account_owner(
+            self.dead_letter_queue,
+            &program_id.to_bytes(),
+            AoError::WrongEventQueueOwner,
+        )?;
+        Ok(())
+    }
+}
+
+/// Apply the create_dead_letter_queue instruction to the provided accounts
+pub fn process<C: Pod>(
+    program_id: &Pubkey,
+    accounts: Accounts<AccountInfo>,
+    params: Params,
+) -> ProgramResult {
+    accounts.perform_checks(program_id)?;
+    check_rent_exempt(accounts.dead_letter_queue)?;
+
+    if params.max_event_age_slots == 0 {
+        msg!("max_event_age_slots must be > 0");
+        return Err(AoError::InvalidMarketParameters.into());
+    }
+
+    let mut market_data = accounts.market.data.borrow_mut();
+    let market_state = MarketState::from_buffer_mut(&mut market_data, AccountTag::Market)?;
+
+    if market_state.dead_letter_queue != Pubkey::default() {
+        msg!("The market already has a dead letter queue account!");
+        return Err(AoError::AlreadyInitialized.into());
+    }
+
+    let mut dead_letter_queue_data = accounts.dead_letter_queue.data.borrow_mut();
+    EventQueue::<C>::check_buffer_size(&dead_letter_queue_data)?;
+    EventQueue::<C>::from_buffer(&mut dead_letter_queue_data, AccountTag::Uninitialized)?;
+    market_state.dead_letter_queue = *accounts.dead_letter_queue.key;
+    market_state.max_event_age_slots = params.max_event_age_slots;
+
+    Ok(())
+}
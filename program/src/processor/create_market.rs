@@ -4,7 +4,7 @@ use bonfida_utils::{
     {BorshSize, InstructionsAccount},
 };
 use borsh::{BorshDeserialize, BorshSerialize};
-use bytemuck::Pod;
+use bytemuck::{Pod, Zeroable};
 use solana_program::{
     account_info::{next_account_info, AccountInfo},
     entrypoint::ProgramResult,
@@ -15,7 +15,16 @@ use solana_program::{
 
 use crate::{
     error::AoError,
-    state::{critbit::Slab, event_queue::EventQueue, market_state::MarketState, AccountTag},
+    state::{
+        critbit::Slab,
+        event_queue::EventQueue,
+        market_registry::MarketRegistry,
+        market_state::{
+            MarketState, PendingTaker, MARKET_METADATA_LEN, MAX_EVENT_QUEUE_SHARDS, MAX_FEE_BPS,
+            MAX_QUOTE_SCALE_EXPONENT,
+        },
+        AccountTag, BootingPolicy, EventQueueFullPolicy, MatchingMode, TradingStatus,
+    },
     utils::check_account_owner,
 };
 
@@ -28,6 +37,40 @@ pub struct Params {
     pub min_base_order_size: u64,
     /// Enables the limiting of price precision on the orderbook (price ticks)
     pub tick_size: u64,
+    /// The minimum quote size (base quantity times price) of an order that can be inserted into the orderbook after matching.
+    pub min_quote_order_size: u64,
+    /// Describes what happens when an order is posted into a full side of the orderbook.
+    pub booting_policy: BootingPolicy,
+    /// Whether each match pushes one event per counterparty instead of a single combined fill event.
+    pub split_fills: bool,
+    /// Whether the fill and out events are also emitted as binary program logs when they are pushed.
+    pub log_events: bool,
+    /// The minimum number of orders that each of the bids and asks accounts must be able to hold.
+    pub order_capacity: u64,
+    /// The minimum number of events that the event queue account must be able to hold.
+    pub event_capacity: u64,
+    /// Opaque caller-defined metadata of at most [`MARKET_METADATA_LEN`] bytes, stored in the market state.
+    pub metadata: Vec<u8>,
+    /// The base 2 exponent of the unit in which the quote quantities of the market are expressed, at most
+    /// [`MAX_QUOTE_SCALE_EXPONENT`]
+    pub quote_scale_exponent: u8,
+    /// The maximum deviation, in basis points, of the limit price of a new order from the reference price of the
+    /// market, or 0 to disable price banding
+    pub max_price_deviation_bps: u64,
+    /// Describes how the orders of the market are matched.
+    pub matching_mode: MatchingMode,
+    /// The fee rate charged to makers on the quote quantity of their fills, in basis points, at most [`MAX_FEE_BPS`]
+    pub maker_fee_bps: u64,
+    /// The fee rate charged to takers on the quote quantity of their fills, in basis points, at most [`MAX_FEE_BPS`]
+    pub taker_fee_bps: u64,
+    /// The maximum number of orders resting in the orderbook with the same callback information, or 0 for no limit
+    pub max_orders_per_owner: u64,
+    /// The program into which consume_events pushes the popped events through CPI, or the default public key for none
+    pub consumer_program: Pubkey,
+    /// The maximum number of events passed to each call of the consumer program, or 0 for no limit
+    pub max_events_per_consumer_call: u64,
+    /// Describes what happens when an event is pushed onto a full event queue.
+    pub event_queue_full_policy: EventQueueFullPolicy,
 }
 
 /// The required accounts for a create_market instruction.
@@ -45,6 +88,9 @@ pub struct Accounts<'a, T> {
     #[allow(missing_docs)]
     #[cons(writable)]
     pub asks: &'a T,
+    /// The market registry to which the market is appended, if any
+    #[cons(writable)]
+    pub market_registry: Option<&'a T>,
 }
 
 impl<'a, 'b: 'a> Accounts<'a, AccountInfo<'b>> {
@@ -56,6 +102,7 @@ impl<'a, 'b: 'a> Accounts<'a, AccountInfo<'b>> {
             event_queue: next_account_info(accounts_iter)?,
             bids: next_account_info(accounts_iter)?,
             asks: next_account_info(accounts_iter)?,
+            market_registry: next_account_info(accounts_iter).ok(),
         };
         Ok(a)
     }
@@ -67,6 +114,13 @@ impl<'a, 'b: 'a> Accounts<'a, AccountInfo<'b>> {
         )?;
         check_account_owner(self.bids, &program_id.to_bytes(), AoError::WrongBidsOwner)?;
         check_account_owner(self.asks, &program_id.to_bytes(), AoError::WrongAsksOwner)?;
+        if let Some(market_registry) = self.market_registry {
+            check_account_owner(
+                market_registry,
+                &program_id.to_bytes(),
+                AoError::WrongMarketRegistryAccount,
+            )?;
+        }
         Ok(())
     }
 }
@@ -78,32 +132,36 @@ pub fn process<'a, 'b: 'a, C: Pod>(
     params: Params,
 ) -> ProgramResult {
     accounts.perform_checks(program_id)?;
-    let Params {
-        min_base_order_size,
-        tick_size,
-    } = params;
 
     check_rent(&accounts)?;
 
-    if min_base_order_size == 0 || tick_size == 0 {
-        msg!("min_base_order_size and tick_size must be > 0");
-        return Err(ProgramError::InvalidArgument);
-    }
+    let order_capacity = params.order_capacity as usize;
+    let event_capacity = params.event_capacity as usize;
+    let new_market_state = new_market_state(
+        params,
+        *accounts.event_queue.key,
+        *accounts.bids.key,
+        *accounts.asks.key,
+        accounts.market_registry.map(|a| *a.key).unwrap_or_default(),
+    )?;
 
-    EventQueue::<C>::check_buffer_size(&accounts.event_queue.data.borrow()).unwrap();
+    EventQueue::<C>::check_buffer_size(&accounts.event_queue.data.borrow())?;
+    check_capacity::<C>(&accounts, order_capacity, event_capacity)?;
 
     let mut market_data = accounts.market.data.borrow_mut();
 
     MarketState::check_buffer_size(&market_data)?;
     let market_state = MarketState::initialize(&mut market_data, AccountTag::Uninitialized)?;
 
-    *market_state = MarketState {
-        event_queue: *accounts.event_queue.key,
-        bids: *accounts.bids.key,
-        asks: *accounts.asks.key,
-        min_base_order_size,
-        tick_size,
-    };
+    *market_state = new_market_state;
+
+    if let Some(market_registry) = accounts.market_registry {
+        MarketRegistry::from_buffer(
+            &mut market_registry.data.borrow_mut(),
+            AccountTag::MarketRegistry,
+        )?
+        .push(accounts.market.key)?;
+    }
 
     let mut event_queue_data = accounts.event_queue.data.borrow_mut();
 
@@ -117,6 +175,106 @@ pub fn process<'a, 'b: 'a, C: Pod>(
     Ok(())
 }
 
+/// Validates the parameters of a create_market instruction and builds the state of the new market from them.
+pub(crate) fn new_market_state(
+    params: Params,
+    event_queue: Pubkey,
+    bids: Pubkey,
+    asks: Pubkey,
+    market_registry: Pubkey,
+) -> Result<MarketState, ProgramError> {
+    let Params {
+        min_base_order_size,
+        tick_size,
+        min_quote_order_size,
+        booting_policy,
+        split_fills,
+        log_events,
+        order_capacity,
+        event_capacity: _,
+        metadata: metadata_bytes,
+        quote_scale_exponent,
+        max_price_deviation_bps,
+        matching_mode,
+        maker_fee_bps,
+        taker_fee_bps,
+        max_orders_per_owner,
+        consumer_program,
+        max_events_per_consumer_call,
+        event_queue_full_policy,
+    } = params;
+
+    if min_base_order_size == 0 || tick_size == 0 {
+        msg!("min_base_order_size and tick_size must be > 0");
+        return Err(AoError::InvalidMarketParameters.into());
+    }
+    if order_capacity == 0 {
+        msg!("order_capacity must be > 0");
+        return Err(AoError::InvalidMarketParameters.into());
+    }
+    if quote_scale_exponent as u64 > MAX_QUOTE_SCALE_EXPONENT {
+        msg!(
+            "quote_scale_exponent must be <= {}",
+            MAX_QUOTE_SCALE_EXPONENT
+        );
+        return Err(AoError::InvalidMarketParameters.into());
+    }
+    if maker_fee_bps > MAX_FEE_BPS || taker_fee_bps > MAX_FEE_BPS {
+        msg!("The fee rates must be <= {} bps", MAX_FEE_BPS);
+        return Err(AoError::InvalidMarketParameters.into());
+    }
+    if metadata_bytes.len() > MARKET_METADATA_LEN {
+        msg!(
+            "The market metadata can't exceed {} bytes",
+            MARKET_METADATA_LEN
+        );
+        return Err(AoError::InvalidMarketParameters.into());
+    }
+    let mut metadata = [0; MARKET_METADATA_LEN];
+    metadata[..metadata_bytes.len()].copy_from_slice(&metadata_bytes);
+
+    Ok(MarketState {
+        event_queue,
+        bids,
+        asks,
+        min_base_order_size,
+        tick_size,
+        stop_orders: Pubkey::default(),
+        last_trade_price: 0,
+        pegged_orders: Pubkey::default(),
+        min_quote_order_size,
+        booting_policy: booting_policy as u64,
+        split_fills: split_fills as u64,
+        dead_letter_queue: Pubkey::default(),
+        max_event_age_slots: 0,
+        log_events: log_events as u64,
+        event_queue_shards: [Pubkey::default(); MAX_EVENT_QUEUE_SHARDS],
+        status: TradingStatus::Active as u64,
+        metadata,
+        market_registry,
+        quote_scale_exponent: quote_scale_exponent as u64,
+        max_price_deviation_bps,
+        cumulative_base_volume: 0,
+        cumulative_quote_volume: 0,
+        cumulative_price_time: [0; 2],
+        last_accumulator_update_ts: 0,
+        matching_mode: matching_mode as u64,
+        pending_taker: PendingTaker::zeroed(),
+        maker_fee_bps,
+        taker_fee_bps,
+        accrued_maker_fees: 0,
+        accrued_taker_fees: 0,
+        max_orders_per_owner,
+        consumer_program,
+        max_events_per_consumer_call,
+        order_allowlist: Pubkey::default(),
+        bids_root: [0; 32],
+        asks_root: [0; 32],
+        book_commit_slot: 0,
+        event_queue_full_policy: event_queue_full_policy as u64,
+    })
+}
+
 fn check_rent(accounts: &Accounts<AccountInfo>) -> ProgramResult {
     check_rent_exempt(accounts.asks)?;
     check_rent_exempt(accounts.bids)?;
@@ -125,3 +283,30 @@ fn check_rent(accounts: &Accounts<AccountInfo>) -> ProgramResult {
 
     Ok(())
 }
+
+fn check_capacity<C>(
+    accounts: &Accounts<AccountInfo>,
+    order_capacity: usize,
+    event_capacity: usize,
+) -> ProgramResult {
+    let slab_size = Slab::<C>::compute_allocation_size(order_capacity);
+    if accounts.bids.data_len() < slab_size || accounts.asks.data_len() < slab_size {
+        msg!(
+            "The bids and asks accounts must be at least {} bytes long to hold {} orders",
+            slab_size,
+            order_capacity
+        );
+        return Err(ProgramError::AccountDataTooSmall);
+    }
+    let event_queue_size = EventQueue::<C>::compute_allocation_size(event_capacity);
+    if accounts.event_queue.data_len() < event_queue_size {
+        msg!(
+            "The event queue account must be at least {} bytes long to hold {} events",
+            event_queue_size,
+            event_capacity
+        );
+        return Err(ProgramError::AccountDataTooSmall);
+    }
+
+    Ok(())
+}
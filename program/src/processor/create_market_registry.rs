@@ -0,0 +1,66 @@
+//! Create and initialize a market registry account, to which markets can then be appended when they are created.
+use bonfida_utils::{checks::check_rent_exempt, BorshSize, InstructionsAccount};
+use borsh::{BorshDeserialize, BorshSerialize};
+use solana_program::{
+    account_info::{next_account_info, AccountInfo},
+    entrypoint::ProgramResult,
+    program_error::ProgramError,
+    pubkey::Pubkey,
+};
+
+use crate::{
+    error::AoError,
+    state::{market_registry::MarketRegistry, AccountTag},
+    utils::check_account_owner,
+};
+
+#[derive(BorshDeserialize, BorshSerialize, BorshSize)]
+/**
+The required arguments for a create_market_registry instruction.
+*/
+pub struct Params {}
+
+/// The required accounts for a create_market_registry instruction.
+#[derive(InstructionsAccount)]
+pub struct Accounts<'a, T> {
+    #[allow(missing_docs)]
+    #[cons(writable)]
+    pub market_registry: &'a T,
+}
+
+impl<'a, 'b: 'a> Accounts<'a, AccountInfo<'b>> {
+    pub(crate) fn parse(accounts: &'a [AccountInfo<'b>]) -> Result<Self, ProgramError> {
+        let accounts_iter = &mut accounts.iter();
+
+        let a = Self {
+            market_registry: next_account_info(accounts_iter)?,
+        };
+        Ok(a)
+    }
+
+    pub(crate) fn perform_checks(&self, program_id: &Pubkey) -> Result<(), ProgramError> {
+        check_account_owner(
+            self.market_registry,
+            &program_id.to_bytes(),
+            AoError::WrongMarketRegistryAccount,
+        )?;
+        Ok(())
+    }
+}
+
+/// Apply the create_market_registry instruction to the provided accounts
+pub fn process(
+    program_id: &Pubkey,
+    accounts: Accounts<AccountInfo>,
+    _params: Params,
+) -> ProgramResult {
+    accounts.perform_checks(program_id)?;
+    check_rent_exempt(accounts.market_registry)?;
+
+    MarketRegistry::from_buffer(
+        &mut accounts.market_registry.data.borrow_mut(),
+        AccountTag::Uninitialized,
+    )?;
+
+    Ok(())
+}
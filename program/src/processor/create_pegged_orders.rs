@@ -0,0 +1,81 @@
+//! Create and initialize the pegged orders account of an existing market
+use bonfida_utils::{checks::check_rent_exempt, BorshSize, InstructionsAccount};
+use borsh::{BorshDeserialize, BorshSerialize};
+use solana_program::{
+    account_info::{next_account_info, AccountInfo},
+    entrypoint::ProgramResult,
+    msg,
+    program_error::ProgramError,
+    pubkey::Pubkey,
+};
+
+use crate::{
+    error::AoError,
+    state::{market_state::MarketState, pegged_orders, AccountTag},
+    utils::check_account_owner,
+};
+
+#[derive(BorshDeserialize, BorshSerialize, BorshSize)]
+/**
+The required arguments for a create_pegged_orders instruction.
+*/
+pub struct Params {}
+
+/// The required accounts for a create_pegged_orders instruction.
+#[derive(InstructionsAccount)]
+pub struct Accounts<'a, T> {
+    #[allow(missing_docs)]
+    #[cons(writable)]
+    pub market: &'a T,
+    #[allow(missing_docs)]
+    #[cons(writable)]
+    pub pegged_orders: &'a T,
+}
+
+impl<'a, 'b: 'a> Accounts<'a, AccountInfo<'b>> {
+    pub(crate) fn parse(accounts: &'a [AccountInfo<'b>]) -> Result<Self, ProgramError> {
+        let accounts_iter = &mut accounts.iter();
+
+        let a = Self {
+            market: next_account_info(accounts_iter)?,
+            pegged_orders: next_account_info(accounts_iter)?,
+        };
+        Ok(a)
+    }
+    pub(crate) fn perform_checks(&self, program_id: &Pubkey) -> Result<(), ProgramError> {
+        check_account_owner(
+            self.market,
+            &program_id.to_bytes(),
+            AoError::WrongMarketOwner,
+        )?;
+        check_account_owner(
+            self.pegged_orders,
+            &program_id.to_bytes(),
+            AoError::WrongPeggedOrdersOwner,
+        )?;
+        Ok(())
+    }
+}
+
+/// Apply the create_pegged_orders instruction to the provided accounts
+pub fn process(
+    program_id: &Pubkey,
+    accounts: Accounts<AccountInfo>,
+    _params: Params,
+) -> ProgramResult {
+    accounts.perform_checks(program_id)?;
+    check_rent_exempt(accounts.pegged_orders)?;
+
+    let mut market_data = accounts.market.data.borrow_mut();
+    let market_state = MarketState::from_buffer_mut(&mut market_data, AccountTag::Market)?;
+
+    if market_state.pegged_orders != Pubkey::default() {
+        msg!("The market already has a pegged orders account!");
+        return Err(AoError::AlreadyInitialized.into());
+    }
+
+    pegged_orders::initialize(&mut accounts.pegged_orders.data.borrow_mut())?;
+    market_state.pegged_orders = *accounts.pegged_orders.key;
+
+    Ok(())
+}
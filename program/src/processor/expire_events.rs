@@ -0,0 +1,133 @@
+//! Move the events which have been pending for too long from the event queue to the dead letter queue.
+use bonfida_utils::{BorshSize, InstructionsAccount};
+use borsh::{BorshDeserialize, BorshSerialize};
+use bytemuck::Pod;
+use solana_program::{
+    account_info::{next_account_info, AccountInfo},
+    clock::Clock,
+    entrypoint::ProgramResult,
+    msg,
+    program_error::ProgramError,
+    pubkey::Pubkey,
+    sysvar::Sysvar,
+};
+
+use crate::{
+    error::AoError,
+    state::{event_queue::EventQueue, market_state::MarketState, AccountTag},
+    utils::{check_account_key, check_account_owner},
+};
+
+#[derive(BorshDeserialize, BorshSerialize, Clone, BorshSize)]
+/**
+The required arguments for an expire_events instruction.
+*/
+pub struct Params {
+    /// The maximum number of events to move to the dead letter queue
+    pub number_of_entries_to_expire: u64,
+}
+
+/// The required accounts for an expire_events instruction.
+#[derive(InstructionsAccount)]
+pub struct Accounts<'a, T> {
+    #[allow(missing_docs)]
+    pub market: &'a T,
+    #[allow(missing_docs)]
+    #[cons(writable)]
+    pub event_queue: &'a T,
+    #[allow(missing_docs)]
+    #[cons(writable)]
+    pub dead_letter_queue: &'a T,
+}
+
+impl<'a, 'b: 'a> Accounts<'a, AccountInfo<'b>> {
+    pub(crate) fn parse(accounts: &'a [AccountInfo<'b>]) -> Result<Self, ProgramError> {
+        let accounts_iter = &mut accounts.iter();
+
+        let a = Self {
+            market: next_account_info(accounts_iter)?,
+            event_queue: next_account_info(accounts_iter)?,
+            dead_letter_queue: next_account_info(accounts_iter)?,
+        };
+        Ok(a)
+    }
+
+    /// Perform basic security checks on the accounts
+    pub(crate) fn perform_checks(&self, program_id: &Pubkey) -> Result<(), ProgramError> {
+        check_account_owner(
+            self.market,
+            &program_id.to_bytes(),
+            AoError::WrongMarketOwner,
+        )?;
+        check_account_owner(
+            self.event_queue,
+            &program_id.to_bytes(),
+            AoError::WrongEventQueueOwner,
+        )?;
+        check_account_owner(
+            self.dead_letter_queue,
+            &program_id.to_bytes(),
+            AoError::WrongEventQueueOwner,
+        )?;
+        Ok(())
+    }
+}
+
+/// Apply the expire_events instruction to the provided accounts
+pub fn process<'a, 'b: 'a, C: Pod>(
+    program_id: &Pubkey,
+    accounts: Accounts<'a, AccountInfo<'b>>,
+    params: Params,
+) -> ProgramResult {
+    accounts.perform_checks(program_id)?;
+    let market_data = accounts.market.data.borrow();
+    let market_state = MarketState::from_buffer(&market_data, AccountTag::Market)?;
+
+    check_accounts(&accounts, market_state)?;
+
+    let mut event_queue_guard = accounts.event_queue.data.borrow_mut();
+    let mut event_queue =
+        EventQueue::<C>::from_buffer(&mut event_queue_guard, AccountTag::EventQueue)?;
+    let mut dead_letter_queue_guard = accounts.dead_letter_queue.data.borrow_mut();
+    let mut dead_letter_queue =
+        EventQueue::<C>::from_buffer(&mut dead_letter_queue_guard, AccountTag::EventQueue)?;
+
+    let current_slot = Clock::get()?.slot;
+    let number_of_expired_events = std::cmp::min(
+        event_queue
+            .count_pushed_before(current_slot.saturating_sub(market_state.max_event_age_slots)),
+        params.number_of_entries_to_expire,
+    );
+
+    let number_of_moved_events =
+        event_queue.move_front_to(&mut dead_letter_queue, number_of_expired_events);
+    if number_of_moved_events == 0 {
+        msg!("No events could be expired");
+        return Err(AoError::NoOperations.into());
+    }
+
+    msg!("Number of events expired: {:?}", number_of_moved_events);
+
+    Ok(())
+}
+
+fn check_accounts<'a, 'b: 'a>(
+    accounts: &Accounts<'a, AccountInfo<'b>>,
+    market_state: &MarketState,
+) -> ProgramResult {
+    if market_state.dead_letter_queue == Pubkey::default() {
+        msg!("Event expiry isn't enabled on this market");
+        return Err(AoError::WrongDeadLetterQueueAccount.into());
+    }
+    check_account_key(
+        accounts.event_queue,
+        &market_state.event_queue,
+        AoError::WrongEventQueueAccount,
+    )?;
+    check_account_key(
+        accounts.dead_letter_queue,
+        &market_state.dead_letter_queue,
+        AoError::WrongDeadLetterQueueAccount,
+    )?;
+    Ok(())
+}
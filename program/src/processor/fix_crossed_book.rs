@@ -0,0 +1,146 @@
+//! Uncross an orderbook whose best bid is priced at or above its best ask, which can only be the result of a bug.
+//!
+//! While the orderbook is crossed, the more recent of the best bid and the best ask is removed and put on the event
+//! queue as a cancelled order. Access to this instruction should be restricted by the caller program.
+
+use bonfida_utils::{BorshSize, InstructionsAccount};
+use borsh::{BorshDeserialize, BorshSerialize};
+use bytemuck::Pod;
+use solana_program::account_info::next_account_info;
+use solana_program::{
+    account_info::AccountInfo, entrypoint::ProgramResult, msg, program_error::ProgramError,
+    pubkey::Pubkey,
+};
+
+use crate::state::event_queue::EventQueue;
+use crate::state::orderbook::{CallbackInfo, OrderBookState};
+use crate::state::{AccountTag, MatchingMode};
+use crate::{
+    error::AoError,
+    state::market_state::MarketState,
+    utils::{check_account_key, check_account_owner, check_event_queue_shards},
+};
+#[derive(BorshDeserialize, BorshSerialize, Clone, BorshSize)]
+/**
+The required arguments for a fix_crossed_book instruction.
+*/
+pub struct Params {
+    /// Depending on available compute or space on the event queue, there may
+    /// be a limit to the amount of crossed orders that can be removed in one transaction
+    pub max_orders: u64,
+}
+
+/// The required accounts for a fix_crossed_book instruction.
+#[derive(InstructionsAccount)]
+pub struct Accounts<'a, T> {
+    #[allow(missing_docs)]
+    pub market: &'a T,
+    #[allow(missing_docs)]
+    #[cons(writable)]
+    pub event_queue: &'a T,
+    #[allow(missing_docs)]
+    #[cons(writable)]
+    pub bids: &'a T,
+    #[allow(missing_docs)]
+    #[cons(writable)]
+    pub asks: &'a T,
+    /// The event queue shards of the market, in the order in which they were registered
+    #[cons(writable)]
+    pub event_queue_shards: &'a [T],
+}
+
+impl<'a, 'b: 'a> Accounts<'a, AccountInfo<'b>> {
+    pub(crate) fn parse(accounts: &'a [AccountInfo<'b>]) -> Result<Self, ProgramError> {
+        let accounts_iter = &mut accounts.iter();
+
+        let a = Self {
+            market: next_account_info(accounts_iter)?,
+            event_queue: next_account_info(accounts_iter)?,
+            bids: next_account_info(accounts_iter)?,
+            asks: next_account_info(accounts_iter)?,
+            event_queue_shards: accounts_iter.as_slice(),
+        };
+        Ok(a)
+    }
+
+    /// Perform basic security checks on the accounts
+    pub(crate) fn perform_checks(&self, program_id: &Pubkey) -> Result<(), ProgramError> {
+        check_account_owner(
+            self.market,
+            &program_id.to_bytes(),
+            AoError::WrongMarketOwner,
+        )?;
+        check_account_owner(
+            self.event_queue,
+            &program_id.to_bytes(),
+            AoError::WrongEventQueueOwner,
+        )?;
+        check_account_owner(self.bids, &program_id.to_bytes(), AoError::WrongBidsOwner)?;
+        check_account_owner(self.asks, &program_id.to_bytes(), AoError::WrongAsksOwner)?;
+        for shard in self.event_queue_shards {
+            check_account_owner(shard, &program_id.to_bytes(), AoError::WrongEventQueueOwner)?;
+        }
+        Ok(())
+    }
+}
+/// Apply the fix_crossed_book instruction to the provided accounts
+pub fn process<'a, 'b: 'a, C: CallbackInfo + Pod + PartialEq>(
+    program_id: &Pubkey,
+    accounts: Accounts<'a, AccountInfo<'b>>,
+    params: Params,
+) -> ProgramResult
+where
+    <C as CallbackInfo>::CallbackId: PartialEq,
+{
+    accounts.perform_checks(program_id)?;
+    let market_state_data = accounts.market.data.borrow();
+    let market_state = MarketState::from_buffer(&market_state_data, AccountTag::Market)?;
+
+    check_accounts(&accounts, market_state)?;
+    if market_state.matching_mode == MatchingMode::Auction as u64 {
+        msg!("The orderbook of an auction market is cleared by run_auction!");
+        return Err(AoError::OperationNotAllowed.into());
+    }
+
+    let mut bids_guard = accounts.bids.data.borrow_mut();
+    let mut asks_guard = accounts.asks.data.borrow_mut();
+
+    let mut order_book = OrderBookState::<C>::new_safe(&mut bids_guard, &mut asks_guard)?;
+
+    let mut event_queue_guard = accounts.event_queue.data.borrow_mut();
+    let mut event_queue_shards_guards = accounts
+        .event_queue_shards
+        .iter()
+        .map(|a| a.data.borrow_mut())
+        .collect::<Vec<_>>();
+    let mut event_queue = EventQueue::from_market_buffers(
+        &mut event_queue_guard,
+        &mut event_queue_shards_guards,
+        market_state,
+    )?;
+
+    if !order_book.is_crossed() {
+        msg!("The orderbook isn't crossed");
+        return Ok(());
+    }
+    let num_removed = order_book.fix_crossed_book(params.max_orders, &mut event_queue)?;
+
+    msg!("Crossed orders removed: {}", num_removed);
+    Ok(())
+}
+
+fn check_accounts<'a, 'b: 'a>(
+    accounts: &Accounts<'a, AccountInfo<'b>>,
+    market_state: &MarketState,
+) -> ProgramResult {
+    check_account_key(
+        accounts.event_queue,
+        &market_state.event_queue,
+        AoError::WrongEventQueueAccount,
+    )?;
+    check_account_key(accounts.bids, &market_state.bids, AoError::WrongBidsAccount)?;
+    check_account_key(accounts.asks, &market_state.asks, AoError::WrongAsksAccount)?;
+
+    check_event_queue_shards(accounts.event_queue_shards, market_state)?;
+    Ok(())
+}
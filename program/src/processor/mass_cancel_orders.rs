@@ -1,6 +1,6 @@
 //! Cancel a series of existing orders in the orderbook.
 
-use bonfida_utils::{fp_math::fp32_mul_floor, BorshSize, InstructionsAccount};
+use bonfida_utils::{BorshSize, InstructionsAccount};
 use borsh::{BorshDeserialize, BorshSerialize};
 use bytemuck::Pod;
 use solana_program::{
@@ -13,23 +13,24 @@ use solana_program::{
 use crate::{
     error::AoError,
     state::{
+        event_queue::{EventQueue, OutEvent},
         get_side_from_order_id,
         market_state::MarketState,
         orderbook::{CallbackInfo, OrderBookState, OrderSummary},
-        AccountTag,
+        AccountTag, Side,
     },
-    utils::{check_account_key, check_account_owner},
+    utils::{check_account_key, check_account_owner, check_event_queue_shards, compute_quote_qty},
 };
 #[derive(BorshDeserialize, BorshSerialize, Clone, BorshSize)]
 /**
-The required arguments for a cancel_order instruction.
+The required arguments for a mass_cancel_orders instruction.
 */
 pub struct Params {
-    /// The order id is a unique identifier for a particular order
+    /// The order ids of the orders to cancel
     pub order_ids: Vec<u128>,
 }
 
-/// The required accounts for a cancel_order instruction.
+/// The required accounts for a mass_cancel_orders instruction.
 #[derive(InstructionsAccount)]
 pub struct Accounts<'a, T> {
     #[allow(missing_docs)]
@@ -43,6 +44,9 @@ pub struct Accounts<'a, T> {
     #[allow(missing_docs)]
     #[cons(writable)]
     pub asks: &'a T,
+    /// The event queue shards of the market, in the order in which they were registered
+    #[cons(writable)]
+    pub event_queue_shards: &'a [T],
 }
 
 impl<'a, 'b: 'a> Accounts<'a, AccountInfo<'b>> {
@@ -54,6 +58,7 @@ impl<'a, 'b: 'a> Accounts<'a, AccountInfo<'b>> {
             event_queue: next_account_info(accounts_iter)?,
             bids: next_account_info(accounts_iter)?,
             asks: next_account_info(accounts_iter)?,
+            event_queue_shards: accounts_iter.as_slice(),
         };
         Ok(a)
     }
@@ -71,10 +76,13 @@ impl<'a, 'b: 'a> Accounts<'a, AccountInfo<'b>> {
         )?;
         check_account_owner(self.bids, &program_id.to_bytes(), AoError::WrongBidsOwner)?;
         check_account_owner(self.asks, &program_id.to_bytes(), AoError::WrongAsksOwner)?;
+        for shard in self.event_queue_shards {
+            check_account_owner(shard, &program_id.to_bytes(), AoError::WrongEventQueueOwner)?;
+        }
         Ok(())
     }
 }
-/// Apply the cancel_order instruction to the provided accounts
+/// Apply the mass_cancel_orders instruction to the provided accounts
 pub fn process<'a, 'b: 'a, C: CallbackInfo + Pod + PartialEq>(
     program_id: &Pubkey,
     accounts: Accounts<'a, AccountInfo<'b>>,
@@ -88,24 +96,48 @@ where
     let market_state = MarketState::from_buffer(&mut market_data, AccountTag::Market)?;
 
     check_accounts(&accounts, market_state)?;
+    market_state.check_can_cancel_order()?;
 
     let mut bids_guard = accounts.bids.data.borrow_mut();
     let mut asks_guard = accounts.asks.data.borrow_mut();
 
     let mut order_book = OrderBookState::<C>::new_safe(&mut bids_guard, &mut asks_guard)?;
 
+    let mut event_queue_guard = accounts.event_queue.data.borrow_mut();
+    let mut event_queue_shards_guards = accounts
+        .event_queue_shards
+        .iter()
+        .map(|a| a.data.borrow_mut())
+        .collect::<Vec<_>>();
+    let mut event_queue = EventQueue::from_market_buffers(
+        &mut event_queue_guard,
+        &mut event_queue_shards_guards,
+        market_state,
+    )?;
+
     let mut total_base_qty = 0u64;
     let mut total_quote_qty = 0u64;
 
     for order_id in params.order_ids {
-        let slab = order_book.get_tree(get_side_from_order_id(order_id));
-        let (leaf_node, _) = slab.remove_by_key(order_id).ok_or(AoError::OrderNotFound)?;
+        let side = get_side_from_order_id(order_id);
+        let slab = order_book.get_tree(side);
+        let (leaf_node, callback_info) =
+            slab.remove_by_key(order_id).ok_or(AoError::OrderNotFound)?;
         total_base_qty = total_base_qty
-            .checked_add(leaf_node.base_quantity())
-            .unwrap();
-        total_quote_qty = fp32_mul_floor(leaf_node.base_quantity(), leaf_node.price())
-            .and_then(|n| n.checked_add(total_quote_qty))
-            .unwrap();
+            .checked_add(leaf_node.total_base_quantity())
+            .ok_or(AoError::NumericalOverflow)?;
+        total_quote_qty = compute_quote_qty(
+            Side::Ask,
+            leaf_node.total_base_quantity(),
+            leaf_node.price(),
+            market_state.quote_scale_exponent,
+        )
+        .and_then(|n| n.checked_add(total_quote_qty))
+        .ok_or(AoError::NumericalOverflow)?;
+        let out = OutEvent::new(side, leaf_node.total_base_quantity(), order_id);
+        event_queue
+            .push_back(out, Some(callback_info), None)
+            .map_err(|_| AoError::EventQueueFull)?;
     }
 
     let order_summary = OrderSummary {
@@ -113,6 +145,11 @@ where
         total_base_qty,
         total_quote_qty,
         total_base_qty_posted: 0,
+        total_quote_qty_posted: 0,
+        total_base_qty_unfilled: 0,
+        flags: 0,
+        match_limit_exhausted: false,
+        compute_budget_exhausted: false,
     };
 
     Ok(order_summary)
@@ -130,5 +167,6 @@ fn check_accounts<'a, 'b: 'a>(
     check_account_key(accounts.bids, &market_state.bids, AoError::WrongBidsAccount)?;
     check_account_key(accounts.asks, &market_state.asks, AoError::WrongAsksAccount)?;
 
+    check_event_queue_shards(accounts.event_queue_shards, market_state)?;
     Ok(())
 }
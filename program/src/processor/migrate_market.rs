@@ -0,0 +1,220 @@
+//! Upgrade the accounts of an existing market to the current layout version.
+use crate::{
+    error::AoError,
+    state::{
+        critbit::compute_migrated_slab_len, event_queue::compute_migrated_event_queue_len,
+        market_state::MarketState, migrate_account, pegged_orders::PeggedOrder,
+        stop_orders::StopOrder, AccountTag, ACCOUNT_TAG_LENGTH, ACCOUNT_VERSION_INDEX,
+    },
+    utils::{check_account_key, check_account_owner},
+};
+use bonfida_utils::{checks::check_rent_exempt, BorshSize, InstructionsAccount};
+use borsh::{BorshDeserialize, BorshSerialize};
+use bytemuck::Pod;
+use solana_program::{
+    account_info::{next_account_info, AccountInfo},
+    entrypoint::ProgramResult,
+    msg,
+    program_error::ProgramError,
+    pubkey::Pubkey,
+};
+
+#[derive(BorshDeserialize, BorshSerialize, BorshSize)]
+/**
+The required arguments for a migrate_market instruction.
+*/
+pub struct Params {}
+
+/// The required accounts for a migrate_market instruction.
+#[derive(InstructionsAccount)]
+pub struct Accounts<'a, T> {
+    #[allow(missing_docs)]
+    #[cons(writable)]
+    pub market: &'a T,
+    #[allow(missing_docs)]
+    #[cons(writable)]
+    pub event_queue: &'a T,
+    #[allow(missing_docs)]
+    #[cons(writable)]
+    pub bids: &'a T,
+    #[allow(missing_docs)]
+    #[cons(writable)]
+    pub asks: &'a T,
+    /// The other accounts which are registered on the market, in this order: the stop orders account, the pegged
+    /// orders account, the order allowlist account, the dead letter queue account, the event queue shards and the
+    /// market registry, skipping those which don't exist
+    #[cons(writable)]
+    pub registered_accounts: &'a [T],
+}
+
+impl<'a, 'b: 'a> Accounts<'a, AccountInfo<'b>> {
+    pub(crate) fn parse(accounts: &'a [AccountInfo<'b>]) -> Result<Self, ProgramError> {
+        let accounts_iter = &mut accounts.iter();
+
+        let a = Self {
+            market: next_account_info(accounts_iter)?,
+            event_queue: next_account_info(accounts_iter)?,
+            bids: next_account_info(accounts_iter)?,
+            asks: next_account_info(accounts_iter)?,
+            registered_accounts: accounts_iter.as_slice(),
+        };
+        Ok(a)
+    }
+
+    pub(crate) fn perform_checks(&self, program_id: &Pubkey) -> Result<(), ProgramError> {
+        check_account_owner(
+            self.market,
+            &program_id.to_bytes(),
+            AoError::WrongMarketOwner,
+        )?;
+        Ok(())
+    }
+}
+
+/// Apply the migrate_market instruction to the provided accounts
+pub fn process<'a, 'b: 'a, C: Pod>(
+    program_id: &Pubkey,
+    accounts: Accounts<'a, AccountInfo<'b>>,
+    _params: Params,
+) -> ProgramResult {
+    accounts.perform_checks(program_id)?;
+    let market_len = ACCOUNT_TAG_LENGTH + MarketState::LEN;
+    if accounts.market.data_len() < market_len {
+        // The market accounts created with an older layout version hold a shorter market state, and should be funded
+        // beforehand so as to remain rent exempt at the new size.
+        accounts.market.realloc(market_len, true)?;
+        check_rent_exempt(accounts.market)?;
+    }
+    let mut market_data = accounts.market.data.borrow_mut();
+    let mut migrated = migrate_account::<C>(&mut market_data, AccountTag::Market)?;
+    let market_state = MarketState::from_buffer(&market_data, AccountTag::Market)?;
+
+    check_accounts(&accounts, market_state)?;
+
+    grow_event_queue::<C>(accounts.event_queue)?;
+    grow_slab::<C>(accounts.bids)?;
+    grow_slab::<C>(accounts.asks)?;
+    for &(account, tag) in [
+        (accounts.event_queue, AccountTag::EventQueue),
+        (accounts.bids, AccountTag::Bids),
+        (accounts.asks, AccountTag::Asks),
+    ]
+    .iter()
+    {
+        migrated |= migrate_account::<C>(&mut account.data.borrow_mut(), tag)?;
+    }
+
+    let mut registered_accounts = accounts.registered_accounts;
+    if market_state.market_registry != Pubkey::default() {
+        let (market_registry, other_accounts) = registered_accounts
+            .split_last()
+            .ok_or(ProgramError::NotEnoughAccountKeys)?;
+        migrated |= migrate_account::<C>(
+            &mut market_registry.data.borrow_mut(),
+            AccountTag::MarketRegistry,
+        )?;
+        registered_accounts = other_accounts;
+    }
+    let mut registered_accounts = registered_accounts.iter();
+    if market_state.stop_orders != Pubkey::default() {
+        let stop_orders = registered_accounts
+            .next()
+            .ok_or(ProgramError::NotEnoughAccountKeys)?;
+        grow_slab::<StopOrder<C>>(stop_orders)?;
+        migrated |= migrate_account::<StopOrder<C>>(
+            &mut stop_orders.data.borrow_mut(),
+            AccountTag::StopOrders,
+        )?;
+    }
+    if market_state.pegged_orders != Pubkey::default() {
+        let pegged_orders = registered_accounts
+            .next()
+            .ok_or(ProgramError::NotEnoughAccountKeys)?;
+        grow_slab::<PeggedOrder>(pegged_orders)?;
+        migrated |= migrate_account::<PeggedOrder>(
+            &mut pegged_orders.data.borrow_mut(),
+            AccountTag::PeggedOrders,
+        )?;
+    }
+    if market_state.order_allowlist != Pubkey::default() {
+        let allowlist = registered_accounts
+            .next()
+            .ok_or(ProgramError::NotEnoughAccountKeys)?;
+        migrated |= migrate_account::<C>(&mut allowlist.data.borrow_mut(), AccountTag::Allowlist)?;
+    }
+    for queue in registered_accounts {
+        grow_event_queue::<C>(queue)?;
+        migrated |= migrate_account::<C>(&mut queue.data.borrow_mut(), AccountTag::EventQueue)?;
+    }
+
+    if !migrated {
+        msg!("The market accounts already use the current layout version");
+    }
+
+    Ok(())
+}
+
+/// Grows a slab account created with an older layout version, whose callback informations are of type `C`, so that
+/// it can hold its orders with the current layout.
+fn grow_slab<C: Pod>(account: &AccountInfo) -> ProgramResult {
+    let version = account.data.borrow()[ACCOUNT_VERSION_INDEX];
+    let new_len = compute_migrated_slab_len(account.data_len(), version, std::mem::size_of::<C>());
+    if new_len > account.data_len() {
+        // The slab account should be funded beforehand so as to remain rent exempt at the new size
+        account.realloc(new_len, true)?;
+        check_rent_exempt(account)?;
+    }
+    Ok(())
+}
+
+/// Grows an event queue account created with an older layout version, whose callback informations are of type `C`, so
+/// that it can hold its events with the current layout.
+fn grow_event_queue<C: Pod>(account: &AccountInfo) -> ProgramResult {
+    let version = account.data.borrow()[ACCOUNT_VERSION_INDEX];
+    let new_len =
+        compute_migrated_event_queue_len(account.data_len(), version, std::mem::size_of::<C>());
+    if new_len > account.data_len() {
+        // The event queue account should be funded beforehand so as to remain rent exempt at the new size
+        account.realloc(new_len, true)?;
+        check_rent_exempt(account)?;
+    }
+    Ok(())
+}
+
+fn check_accounts<'a, 'b: 'a>(
+    accounts: &Accounts<'a, AccountInfo<'b>>,
+    market_state: &MarketState,
+) -> ProgramResult {
+    check_account_key(
+        accounts.event_queue,
+        &market_state.event_queue,
+        AoError::WrongEventQueueAccount,
+    )?;
+    check_account_key(accounts.bids, &market_state.bids, AoError::WrongBidsAccount)?;
+    check_account_key(accounts.asks, &market_state.asks, AoError::WrongAsksAccount)?;
+
+    let optional_accounts = [
+        market_state.stop_orders,
+        market_state.pegged_orders,
+        market_state.order_allowlist,
+        market_state.dead_letter_queue,
+    ];
+    let registered_accounts = optional_accounts
+        .iter()
+        .chain(market_state.event_queue_shards())
+        .chain(std::iter::once(&market_state.market_registry))
+        .filter(|k| k != &&Pubkey::default())
+        .collect::<Vec<_>>();
+    if accounts.registered_accounts.len() != registered_accounts.len()
+        || accounts
+            .registered_accounts
+            .iter()
+            .zip(registered_accounts)
+            .any(|(a, k)| a.key != k)
+    {
+        msg!("The accounts registered on the market must be provided");
+        return Err(AoError::MissingRegisteredAccounts.into());
+    }
+
+    Ok(())
+}
@@ -0,0 +1,103 @@
+//! Cancel series of existing orders across several markets at once.
+//!
+//! The orders of each market are cancelled as by a mass_cancel_orders instruction, so that either all of them are
+//! cancelled or the whole instruction fails.
+
+use bonfida_utils::{BorshSize, InstructionsAccount};
+use borsh::{BorshDeserialize, BorshSerialize};
+use bytemuck::Pod;
+use solana_program::{account_info::AccountInfo, msg, program_error::ProgramError, pubkey::Pubkey};
+
+use crate::{
+    error::AoError,
+    processor::mass_cancel_orders,
+    state::{
+        market_state::MarketState,
+        orderbook::{CallbackInfo, OrderSummary},
+        AccountTag,
+    },
+    utils::check_account_owner,
+};
+
+/// The number of accounts in the account group of a market, before its event queue shards.
+pub const MARKET_ACCOUNT_GROUP_LEN: usize = 4;
+
+#[derive(BorshDeserialize, BorshSerialize, Clone, BorshSize)]
+/**
+The required arguments for a multi_market_cancel instruction.
+*/
+pub struct Params {
+    /// The order ids of the orders to cancel on each market, in the order of the market account groups
+    pub order_ids: Vec<Vec<u128>>,
+}
+
+/// The required accounts for a multi_market_cancel instruction.
+#[derive(InstructionsAccount)]
+pub struct Accounts<'a, T> {
+    /// The account groups of the markets, each made of the market, event queue, bids and asks accounts followed by
+    /// the event queue shards of the market, in the order in which they were registered
+    #[cons(writable)]
+    pub market_accounts: &'a [T],
+}
+
+impl<'a, 'b: 'a> Accounts<'a, AccountInfo<'b>> {
+    pub(crate) fn parse(accounts: &'a [AccountInfo<'b>]) -> Result<Self, ProgramError> {
+        Ok(Self {
+            market_accounts: accounts,
+        })
+    }
+}
+
+/// Apply the multi_market_cancel instruction to the provided accounts
+///
+/// Returns the order summary of the cancelled orders of each market.
+pub fn process<'a, 'b: 'a, C: CallbackInfo + Pod + PartialEq>(
+    program_id: &Pubkey,
+    accounts: Accounts<'a, AccountInfo<'b>>,
+    params: Params,
+) -> Result<Vec<OrderSummary>, ProgramError>
+where
+    <C as CallbackInfo>::CallbackId: PartialEq,
+{
+    let mut remaining_accounts = accounts.market_accounts;
+    let mut order_summaries = Vec::with_capacity(params.order_ids.len());
+    for order_ids in params.order_ids {
+        let (group, other_accounts) = split_market_group(program_id, remaining_accounts)?;
+        remaining_accounts = other_accounts;
+        let accounts = mass_cancel_orders::Accounts {
+            market: &group[0],
+            event_queue: &group[1],
+            bids: &group[2],
+            asks: &group[3],
+            event_queue_shards: &group[MARKET_ACCOUNT_GROUP_LEN..],
+        };
+        let params = mass_cancel_orders::Params { order_ids };
+        order_summaries.push(mass_cancel_orders::process::<C>(
+            program_id, accounts, params,
+        )?);
+    }
+    if !remaining_accounts.is_empty() {
+        msg!("More market accounts than cancel lists have been provided!");
+        return Err(AoError::MarketAccountsMismatch.into());
+    }
+    Ok(order_summaries)
+}
+
+/// Splits the account group of the next market, including its event queue shards, off the front of the accounts.
+#[allow(clippy::type_complexity)]
+fn split_market_group<'a, 'b: 'a>(
+    program_id: &Pubkey,
+    accounts: &'a [AccountInfo<'b>],
+) -> Result<(&'a [AccountInfo<'b>], &'a [AccountInfo<'b>]), ProgramError> {
+    let market = accounts.first().ok_or(ProgramError::NotEnoughAccountKeys)?;
+    check_account_owner(market, &program_id.to_bytes(), AoError::WrongMarketOwner)?;
+    let group_len = {
+        let market_data = market.data.borrow();
+        let market_state = MarketState::from_buffer(&market_data, AccountTag::Market)?;
+        MARKET_ACCOUNT_GROUP_LEN + market_state.event_queue_shards().len()
+    };
+    if accounts.len() < group_len {
+        return Err(ProgramError::NotEnoughAccountKeys);
+    }
+    Ok(accounts.split_at(group_len))
+}
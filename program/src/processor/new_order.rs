@@ -5,21 +5,26 @@ use borsh::{BorshDeserialize, BorshSerialize};
 use bytemuck::Pod;
 use solana_program::{
     account_info::{next_account_info, AccountInfo},
+    clock::Clock,
     entrypoint::ProgramResult,
     msg,
     program_error::ProgramError,
-    pubkey::Pubkey, clock::Clock, sysvar::Sysvar,
+    pubkey::Pubkey,
+    sysvar::Sysvar,
 };
 
 use crate::{
     error::AoError,
     state::{
+        allowlist::next_allowlist_account,
         event_queue::EventQueue,
         market_state::MarketState,
         orderbook::{CallbackInfo, OrderBookState},
-        AccountTag, OrderSummary, SelfTradeBehavior, Side,
+        AccountTag, OrderSummary, PostOnlyMode, SelfTradeBehavior, Side,
+    },
+    utils::{
+        check_account_key, check_account_owner, check_event_queue_shards, check_order_allowlist,
     },
-    utils::{check_account_key, check_account_owner},
 };
 
 #[derive(Clone, BorshSerialize, BorshDeserialize)]
@@ -29,10 +34,14 @@ The required arguments for a new_order instruction.
 pub struct Params<C> {
     /// The maximum quantity of base to be traded.
     pub max_base_qty: u64,
-    /// The maximum quantity of quote to be traded.
+    /// The maximum quantity of quote to be traded, in units of the market's quote scale.
     pub max_quote_qty: u64,
     /// The limit price of the order. This value is understood as a 32-bit fixed point number.
-    /// Must be rounded to the nearest tick size multiple (see [`round_price`][`crate::utils::round_price`])
+    /// Must be rounded to the nearest tick size multiple (see [`round_price`][`crate::utils::round_price`]),
+    /// otherwise the order is rejected with [`AoError::InvalidTickSize`].
+    ///
+    /// A limit price of 0 describes a market order, which is matched at any price within `max_slippage` of the best
+    /// opposite order and is never written into the orderbook.
     pub limit_price: u64,
     /// The order's side.
     pub side: Side,
@@ -47,14 +56,87 @@ pub struct Params<C> {
     pub callback_info: C,
     /// The order will not be matched against the orderbook and will be direcly written into it.
     ///
-    /// The operation will fail if the order's limit_price crosses the spread.
+    /// The operation will fail if the order's limit_price crosses the spread, unless `post_only_mode` is set to
+    /// [`PostOnlyMode::Slide`].
     pub post_only: bool,
     /// The order will be matched against the orderbook, but what remains will not be written as a new order into the orderbook.
     pub post_allowed: bool,
     /// Describes what would happen if this order was matched against an order with an equal `callback_info` field.
     pub self_trade_behavior: SelfTradeBehavior,
     /// The max timestamp at which the posted order will be able to be matched against.
+    ///
+    /// Once expired, the order can be removed from the orderbook with a
+    /// [`PruneExpired`][`crate::instruction::AgnosticOrderbookInstruction::PruneExpired`] instruction.
     pub max_ts: u64,
+    /// The order will be matched against the orderbook and any unfilled remainder will be cancelled.
+    ///
+    /// When set, nothing is ever written into the orderbook regardless of `post_allowed`, and the
+    /// unfilled quantity is reported in the [`OrderSummary`][`crate::orderbook::OrderSummary`].
+    pub immediate_or_cancel: bool,
+    /// The order will only be executed if it can be entirely matched against the orderbook.
+    ///
+    /// If this isn't the case, the instruction fails with [`AoError::UnableToFill`] without modifying the orderbook.
+    /// A fill or kill order is never written into the orderbook.
+    pub fill_or_kill: bool,
+    /// The maximum base quantity of the posted order which is displayed in the orderbook at once, or 0 to display the entire order.
+    ///
+    /// The rest of the order is hidden, and is used to replenish the displayed quantity each time it is entirely matched.
+    /// This value must be greater than the market's minimum base order size.
+    pub max_display_qty: u64,
+    /// Describes what happens when a post only order would cross the spread.
+    pub post_only_mode: PostOnlyMode,
+    /// For market orders, the maximum difference between the price of the best opposite order and the price of
+    /// the matched orders. This value is understood as a 32-bit fixed point number, use `u64::MAX` for no bound.
+    ///
+    /// This parameter is ignored for limit orders.
+    pub max_slippage: u64,
+    /// A caller-defined bitfield attached to the order, such as a reduce-only marker.
+    ///
+    /// The flags are transmitted back through every [`FillEvent`][`crate::state::event_queue::FillEvent`] involving
+    /// the order, as well as through the [`OrderSummary`][`crate::orderbook::OrderSummary`].
+    pub flags: u8,
+    /// The minimum base quantity which has to be matched against the orderbook, or 0 for no minimum.
+    ///
+    /// If less than this quantity would be matched, the instruction fails with [`AoError::UnableToFill`] without
+    /// modifying the orderbook.
+    pub min_base_qty: u64,
+    /// The number of compute units which must remain available for matching to continue against another order, or 0
+    /// to only rely on `match_limit`.
+    ///
+    /// When matching stops because of this reserve, the unmatched remainder of the order is not posted and
+    /// [`OrderSummary::compute_budget_exhausted`][`crate::orderbook::OrderSummary::compute_budget_exhausted`] is set,
+    /// so that the caller can resume matching in another transaction. This is only enforced when the program is built
+    /// with the `compute-budget` feature, see [`remaining_compute_units`][`crate::utils::remaining_compute_units`].
+    pub compute_unit_reserve: u64,
+    /// When matching stops because of `match_limit` or `compute_unit_reserve`, the remainder of the order is parked in
+    /// the market instead of being posted or reported as unfilled, and is continued by a
+    /// [`ContinueMatch`][`crate::instruction::AgnosticOrderbookInstruction::ContinueMatch`] instruction.
+    ///
+    /// No new orders can be placed on the market while a resumable order is parked, so that it keeps its time
+    /// priority. The order id assigned to the parked order is returned in the order summary.
+    pub resumable: bool,
+    /// The worst volume-weighted average price at which the order can be matched, or 0 for no bound. This value is
+    /// understood as a 32-bit fixed point number.
+    ///
+    /// Orders are matched level by level as long as the average price of the matched quantity stays within this
+    /// bound, the last order being partially matched if necessary. Once the bound is reached, matching stops and the
+    /// remainder of the order is reported as unfilled instead of being posted. An order with a worst price can't be
+    /// resumable.
+    pub worst_price: u64,
+    /// An optional tag identifying the referrer of the order, or all zeroes for none.
+    ///
+    /// When set, each fill of the order is followed in the event queue by a
+    /// [`ReferralEvent`][`crate::state::event_queue::ReferralEvent`] carrying this tag, so that fee-sharing programs can
+    /// attribute the matched volume to the referrer when consuming events. An order with a referral can't be
+    /// resumable.
+    pub referral: [u8; 32],
+    /// An optional identifier chosen by the owner of the order, or 0 for none.
+    ///
+    /// It is stored alongside the callback information of the posted order, so that the order can be cancelled by a
+    /// [`CancelByClientId`][`crate::instruction::AgnosticOrderbookInstruction::CancelByClientId`] instruction without
+    /// knowing its order id. A resumable order can't have a client order id, and the order is rejected when its owner
+    /// already has an order with the same non-zero client order id.
+    pub client_order_id: u64,
 }
 
 impl<C: BorshSize> BorshSize for Params<C> {
@@ -69,6 +151,18 @@ impl<C: BorshSize> BorshSize for Params<C> {
             + self.post_allowed.borsh_len()
             + self.self_trade_behavior.borsh_len()
             + self.max_ts.borsh_len()
+            + self.immediate_or_cancel.borsh_len()
+            + self.fill_or_kill.borsh_len()
+            + self.max_display_qty.borsh_len()
+            + self.post_only_mode.borsh_len()
+            + self.max_slippage.borsh_len()
+            + self.flags.borsh_len()
+            + self.min_base_qty.borsh_len()
+            + self.compute_unit_reserve.borsh_len()
+            + self.resumable.borsh_len()
+            + self.worst_price.borsh_len()
+            + self.referral.len()
+            + self.client_order_id.borsh_len()
     }
 }
 
@@ -76,6 +170,7 @@ impl<C: BorshSize> BorshSize for Params<C> {
 #[derive(InstructionsAccount)]
 pub struct Accounts<'a, T> {
     #[allow(missing_docs)]
+    #[cons(writable)]
     pub market: &'a T,
     #[allow(missing_docs)]
     #[cons(writable)]
@@ -86,6 +181,11 @@ pub struct Accounts<'a, T> {
     #[allow(missing_docs)]
     #[cons(writable)]
     pub asks: &'a T,
+    /// The allowlist of the market, which must be given when the market has one
+    pub allowlist: Option<&'a T>,
+    /// The event queue shards of the market, in the order in which they were registered
+    #[cons(writable)]
+    pub event_queue_shards: &'a [T],
 }
 
 impl<'a, 'b: 'a> Accounts<'a, AccountInfo<'b>> {
@@ -97,6 +197,8 @@ impl<'a, 'b: 'a> Accounts<'a, AccountInfo<'b>> {
             event_queue: next_account_info(accounts_iter)?,
             bids: next_account_info(accounts_iter)?,
             asks: next_account_info(accounts_iter)?,
+            allowlist: next_allowlist_account(accounts_iter),
+            event_queue_shards: accounts_iter.as_slice(),
         };
         Ok(a)
     }
@@ -113,6 +215,9 @@ impl<'a, 'b: 'a> Accounts<'a, AccountInfo<'b>> {
         )?;
         check_account_owner(self.bids, &program_id.to_bytes(), AoError::WrongBidsOwner)?;
         check_account_owner(self.asks, &program_id.to_bytes(), AoError::WrongAsksOwner)?;
+        for shard in self.event_queue_shards {
+            check_account_owner(shard, &program_id.to_bytes(), AoError::WrongEventQueueOwner)?;
+        }
         Ok(())
     }
 }
@@ -128,20 +233,11 @@ where
 {
     accounts.perform_checks(program_id)?;
     let mut market_data = accounts.market.data.borrow_mut();
-    let market_state = MarketState::from_buffer(&mut market_data, AccountTag::Market)?;
+    let market_state = MarketState::from_buffer_mut(&mut market_data, AccountTag::Market)?;
 
     check_accounts(&accounts, market_state)?;
-
-    if params.limit_price % market_state.tick_size != 0 {
-        return Err(AoError::InvalidLimitPrice.into());
-    }
-
-    if params.post_allowed && params.limit_price < market_state.tick_size {
-        msg!(
-            "Can't attempt to post an order of price less than market tick size to the orderbook!"
-        );
-        return Err(AoError::InvalidLimitPrice.into());
-    }
+    check_order_allowlist(accounts.allowlist, market_state, [&params.callback_info])?;
+    check_params(&params, market_state)?;
 
     let mut bids_guard = accounts.bids.data.borrow_mut();
     let mut asks_guard = accounts.asks.data.borrow_mut();
@@ -149,16 +245,69 @@ where
     let mut order_book = OrderBookState::new_safe(&mut bids_guard, &mut asks_guard)?;
 
     let mut event_queue_guard = accounts.event_queue.data.borrow_mut();
-    let mut event_queue = EventQueue::from_buffer(&mut event_queue_guard, AccountTag::EventQueue)?;
+    let mut event_queue_shards_guards = accounts
+        .event_queue_shards
+        .iter()
+        .map(|a| a.data.borrow_mut())
+        .collect::<Vec<_>>();
+    let mut event_queue = EventQueue::from_market_buffers(
+        &mut event_queue_guard,
+        &mut event_queue_shards_guards,
+        market_state,
+    )?;
     let cur_ts = Clock::get()?.unix_timestamp as u64;
 
-    let order_summary =
-        order_book.new_order(params, &mut event_queue, market_state.min_base_order_size, cur_ts)?;
+    let order_summary = order_book.new_order(params, &mut event_queue, market_state, cur_ts)?;
     msg!("Order summary : {:?}", order_summary);
 
     Ok(order_summary)
 }
 
+pub(crate) fn check_params<C>(params: &Params<C>, market_state: &MarketState) -> ProgramResult {
+    market_state.check_can_place_order(params.post_only)?;
+
+    if params.post_allowed
+        && !params.immediate_or_cancel
+        && !params.fill_or_kill
+        && params.limit_price != 0
+        && params.limit_price < market_state.tick_size
+    {
+        msg!(
+            "Can't attempt to post an order of price less than market tick size to the orderbook!"
+        );
+        return Err(AoError::InvalidLimitPrice.into());
+    }
+
+    if params.max_base_qty < market_state.min_base_order_size
+        || params.max_quote_qty < market_state.min_quote_order_size
+    {
+        msg!("The order is smaller than the market's minimum order size!");
+        return Err(AoError::OrderTooSmall.into());
+    }
+
+    if params.resumable && params.worst_price != 0 {
+        msg!("A resumable order can't have a worst price!");
+        return Err(AoError::InvalidLimitPrice.into());
+    }
+
+    if params.resumable && params.referral != [0; 32] {
+        msg!("A resumable order can't have a referral!");
+        return Err(AoError::InvalidReferral.into());
+    }
+
+    if params.resumable && params.client_order_id != 0 {
+        msg!("A resumable order can't have a client order id!");
+        return Err(AoError::InvalidClientOrderId.into());
+    }
+
+    if params.max_display_qty != 0 && params.max_display_qty < market_state.min_base_order_size {
+        msg!("The displayed quantity of an iceberg order can't be smaller than the minimum base order size!");
+        return Err(AoError::InvalidDisplayQuantity.into());
+    }
+
+    Ok(())
+}
+
 fn check_accounts<'a, 'b: 'a>(
     accounts: &Accounts<'a, AccountInfo<'b>>,
     market_state: &MarketState,
@@ -171,5 +320,6 @@ fn check_accounts<'a, 'b: 'a>(
     check_account_key(accounts.bids, &market_state.bids, AoError::WrongBidsAccount)?;
     check_account_key(accounts.asks, &market_state.asks, AoError::WrongAsksAccount)?;
 
+    check_event_queue_shards(accounts.event_queue_shards, market_state)?;
     Ok(())
 }
@@ -0,0 +1,253 @@
+//! Place a new pegged order, whose price follows the best price of one of the sides of the orderbook
+use bonfida_utils::{BorshSize, InstructionsAccount};
+use borsh::{BorshDeserialize, BorshSerialize};
+use bytemuck::Pod;
+use solana_program::{
+    account_info::{next_account_info, AccountInfo},
+    clock::Clock,
+    entrypoint::ProgramResult,
+    msg,
+    program_error::ProgramError,
+    pubkey::Pubkey,
+    sysvar::Sysvar,
+};
+
+use crate::{
+    error::AoError,
+    processor::new_order,
+    state::{
+        allowlist::next_allowlist_account,
+        event_queue::EventQueue,
+        market_state::MarketState,
+        orderbook::{CallbackInfo, OrderBookState, OrderSummary},
+        pegged_orders::{self, PeggedOrdersSlab},
+        AccountTag, PegReference, PostOnlyMode, SelfTradeBehavior, Side,
+    },
+    utils::{
+        check_account_key, check_account_owner, check_event_queue_shards, check_order_allowlist,
+    },
+};
+
+#[derive(Clone, BorshSerialize, BorshDeserialize)]
+/**
+The required arguments for a new_pegged_order instruction.
+*/
+pub struct Params<C> {
+    /// The order's side.
+    pub side: Side,
+    /// The best price of the orderbook which the order follows.
+    pub reference: PegReference,
+    /// The signed offset which is added to the reference price. This value is understood as a 32-bit fixed point number.
+    ///
+    /// The resulting price is rounded to the market's tick size, downwards for bids and upwards for asks.
+    pub offset: i64,
+    /// The maximum quantity of base to be posted.
+    pub max_base_qty: u64,
+    /// The maximum quantity of quote to be posted.
+    pub max_quote_qty: u64,
+    /// The callback information is used to attach metadata to an order. This callback information will be transmitted back through the event queue.
+    pub callback_info: C,
+    /// The max timestamp after which the order can be pruned.
+    pub max_ts: u64,
+    /// A caller-defined bitfield attached to the order, see [`new_order::Params::flags`][`crate::instruction::new_order::Params::flags`].
+    pub flags: u8,
+}
+
+impl<C: BorshSize> BorshSize for Params<C> {
+    fn borsh_len(&self) -> usize {
+        self.side.borsh_len()
+            + self.reference.borsh_len()
+            + self.offset.borsh_len()
+            + self.max_base_qty.borsh_len()
+            + self.max_quote_qty.borsh_len()
+            + self.callback_info.borsh_len()
+            + self.max_ts.borsh_len()
+            + self.flags.borsh_len()
+    }
+}
+
+/// The required accounts for a new_pegged_order instruction.
+#[derive(InstructionsAccount)]
+pub struct Accounts<'a, T> {
+    #[allow(missing_docs)]
+    #[cons(writable)]
+    pub market: &'a T,
+    #[allow(missing_docs)]
+    #[cons(writable)]
+    pub event_queue: &'a T,
+    #[allow(missing_docs)]
+    #[cons(writable)]
+    pub bids: &'a T,
+    #[allow(missing_docs)]
+    #[cons(writable)]
+    pub asks: &'a T,
+    #[allow(missing_docs)]
+    #[cons(writable)]
+    pub pegged_orders: &'a T,
+    /// The allowlist of the market, which must be given when the market has one
+    pub allowlist: Option<&'a T>,
+    /// The event queue shards of the market, in the order in which they were registered
+    #[cons(writable)]
+    pub event_queue_shards: &'a [T],
+}
+
+impl<'a, 'b: 'a> Accounts<'a, AccountInfo<'b>> {
+    pub(crate) fn parse(accounts: &'a [AccountInfo<'b>]) -> Result<Self, ProgramError> {
+        let accounts_iter = &mut accounts.iter();
+
+        let a = Self {
+            market: next_account_info(accounts_iter)?,
+            event_queue: next_account_info(accounts_iter)?,
+            bids: next_account_info(accounts_iter)?,
+            asks: next_account_info(accounts_iter)?,
+            pegged_orders: next_account_info(accounts_iter)?,
+            allowlist: next_allowlist_account(accounts_iter),
+            event_queue_shards: accounts_iter.as_slice(),
+        };
+        Ok(a)
+    }
+
+    /// Perform basic security checks on the accounts
+    pub(crate) fn perform_checks(&self, program_id: &Pubkey) -> Result<(), ProgramError> {
+        check_account_owner(
+            self.market,
+            &program_id.to_bytes(),
+            AoError::WrongMarketOwner,
+        )?;
+        check_account_owner(
+            self.event_queue,
+            &program_id.to_bytes(),
+            AoError::WrongEventQueueOwner,
+        )?;
+        check_account_owner(self.bids, &program_id.to_bytes(), AoError::WrongBidsOwner)?;
+        check_account_owner(self.asks, &program_id.to_bytes(), AoError::WrongAsksOwner)?;
+        check_account_owner(
+            self.pegged_orders,
+            &program_id.to_bytes(),
+            AoError::WrongPeggedOrdersOwner,
+        )?;
+        for shard in self.event_queue_shards {
+            check_account_owner(shard, &program_id.to_bytes(), AoError::WrongEventQueueOwner)?;
+        }
+        Ok(())
+    }
+}
+
+/// Apply the new_pegged_order instruction to the provided accounts
+pub fn process<'a, 'b: 'a, C: Pod + CallbackInfo + PartialEq>(
+    program_id: &Pubkey,
+    accounts: Accounts<'a, AccountInfo<'b>>,
+    params: Params<C>,
+) -> Result<OrderSummary, ProgramError>
+where
+    <C as CallbackInfo>::CallbackId: PartialEq,
+{
+    accounts.perform_checks(program_id)?;
+    let mut market_data = accounts.market.data.borrow_mut();
+    let market_state = MarketState::from_buffer_mut(&mut market_data, AccountTag::Market)?;
+
+    check_accounts(&accounts, market_state)?;
+    check_order_allowlist(accounts.allowlist, market_state, [&params.callback_info])?;
+    market_state.check_can_place_order(false)?;
+
+    if params.max_base_qty < market_state.min_base_order_size
+        || params.max_quote_qty < market_state.min_quote_order_size
+    {
+        msg!("The order is smaller than the market's minimum order size!");
+        return Err(AoError::OrderTooSmall.into());
+    }
+
+    let mut bids_guard = accounts.bids.data.borrow_mut();
+    let mut asks_guard = accounts.asks.data.borrow_mut();
+
+    let mut order_book = OrderBookState::new_safe(&mut bids_guard, &mut asks_guard)?;
+
+    let limit_price = pegged_orders::get_peg_price(
+        &order_book,
+        params.side,
+        params.reference,
+        params.offset,
+        market_state.tick_size,
+    )
+    .ok_or(AoError::MissingPegReference)?;
+
+    let mut event_queue_guard = accounts.event_queue.data.borrow_mut();
+    let mut event_queue_shards_guards = accounts
+        .event_queue_shards
+        .iter()
+        .map(|a| a.data.borrow_mut())
+        .collect::<Vec<_>>();
+    let mut event_queue = EventQueue::from_market_buffers(
+        &mut event_queue_guard,
+        &mut event_queue_shards_guards,
+        market_state,
+    )?;
+
+    let mut pegged_orders_guard = accounts.pegged_orders.data.borrow_mut();
+    let mut pegged_orders_slab =
+        PeggedOrdersSlab::from_buffer(&mut pegged_orders_guard, AccountTag::PeggedOrders)?;
+    let cur_ts = Clock::get()?.unix_timestamp as u64;
+
+    let order_summary = order_book.new_order(
+        new_order::Params {
+            max_base_qty: params.max_base_qty,
+            max_quote_qty: params.max_quote_qty,
+            limit_price,
+            side: params.side,
+            match_limit: 1,
+            callback_info: params.callback_info,
+            post_only: true,
+            post_allowed: true,
+            self_trade_behavior: SelfTradeBehavior::DecrementTake,
+            max_ts: params.max_ts,
+            immediate_or_cancel: false,
+            fill_or_kill: false,
+            max_display_qty: 0,
+            post_only_mode: PostOnlyMode::Slide,
+            max_slippage: 0,
+            flags: params.flags,
+            min_base_qty: 0,
+            compute_unit_reserve: 0,
+            resumable: false,
+            worst_price: 0,
+            referral: [0; 32],
+            client_order_id: 0,
+        },
+        &mut event_queue,
+        market_state,
+        cur_ts,
+    )?;
+    if let Some(order_id) = order_summary.posted_order_id {
+        pegged_orders::insert(
+            &mut pegged_orders_slab,
+            order_id,
+            params.max_ts,
+            params.reference,
+            params.offset,
+        )?;
+    }
+    msg!("Order summary : {:?}", order_summary);
+
+    Ok(order_summary)
+}
+
+fn check_accounts<'a, 'b: 'a>(
+    accounts: &Accounts<'a, AccountInfo<'b>>,
+    market_state: &MarketState,
+) -> ProgramResult {
+    check_account_key(
+        accounts.event_queue,
+        &market_state.event_queue,
+        AoError::WrongEventQueueAccount,
+    )?;
+    check_account_key(accounts.bids, &market_state.bids, AoError::WrongBidsAccount)?;
+    check_account_key(accounts.asks, &market_state.asks, AoError::WrongAsksAccount)?;
+    check_account_key(
+        accounts.pegged_orders,
+        &market_state.pegged_orders,
+        AoError::WrongPeggedOrdersAccount,
+    )?;
+
+    check_event_queue_shards(accounts.event_queue_shards, market_state)?;
+    Ok(())
+}
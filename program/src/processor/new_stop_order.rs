@@ -0,0 +1,245 @@
+//! Place a new stop order, which is executed against the orderbook once the market's last trade price reaches its trigger price
+use bonfida_utils::{BorshSize, InstructionsAccount};
+use borsh::{BorshDeserialize, BorshSerialize};
+use bytemuck::Pod;
+use solana_program::{
+    account_info::{next_account_info, AccountInfo},
+    entrypoint::ProgramResult,
+    msg,
+    program_error::ProgramError,
+    pubkey::Pubkey,
+};
+
+use crate::{
+    error::AoError,
+    state::{
+        allowlist::next_allowlist_account,
+        event_queue::EventQueue,
+        market_state::MarketState,
+        orderbook::{CallbackInfo, OrderSummary},
+        stop_orders::{self, StopOrder, StopOrdersSlab},
+        AccountTag, SelfTradeBehavior, Side,
+    },
+    utils::{
+        check_account_key, check_account_owner, check_event_queue_shards, check_order_allowlist,
+    },
+};
+
+#[derive(Clone, BorshSerialize, BorshDeserialize)]
+/**
+The required arguments for a new_stop_order instruction.
+*/
+pub struct Params<C> {
+    /// The price at which the stop order is triggered. This value is understood as a 32-bit fixed point number.
+    ///
+    /// A buy stop order is triggered once the last trade price is greater than or equal to the trigger price,
+    /// and a sell stop order once the last trade price is less than or equal to the trigger price.
+    pub trigger_price: u64,
+    /// The order's side.
+    pub side: Side,
+    /// The maximum quantity of base to be traded once triggered.
+    pub max_base_qty: u64,
+    /// The maximum quantity of quote to be traded once triggered.
+    pub max_quote_qty: u64,
+    /// The limit price of the order once triggered, or 0 for a stop market order.
+    /// This value is understood as a 32-bit fixed point number.
+    pub limit_price: u64,
+    /// The maximum number of orders to match against once triggered.
+    pub match_limit: u64,
+    /// For stop market orders, the maximum difference between the price of the best opposite order and the price of
+    /// the matched orders once triggered.
+    pub max_slippage: u64,
+    /// Describes what would happen if this order was matched against an order with an equal `callback_info` field.
+    ///
+    /// [`SelfTradeBehavior::AbortTransaction`] isn't allowed, since it would prevent other stop orders from being triggered.
+    pub self_trade_behavior: SelfTradeBehavior,
+    /// The max timestamp at which the stop order can be triggered.
+    pub max_ts: u64,
+    /// The callback information is used to attach metadata to an order. This callback information will be transmitted back through the event queue.
+    pub callback_info: C,
+    /// A caller-defined bitfield attached to the order, see [`new_order::Params::flags`][`crate::instruction::new_order::Params::flags`].
+    pub flags: u8,
+}
+
+impl<C: BorshSize> BorshSize for Params<C> {
+    fn borsh_len(&self) -> usize {
+        self.trigger_price.borsh_len()
+            + self.side.borsh_len()
+            + self.max_base_qty.borsh_len()
+            + self.max_quote_qty.borsh_len()
+            + self.limit_price.borsh_len()
+            + self.match_limit.borsh_len()
+            + self.max_slippage.borsh_len()
+            + self.self_trade_behavior.borsh_len()
+            + self.max_ts.borsh_len()
+            + self.callback_info.borsh_len()
+            + self.flags.borsh_len()
+    }
+}
+
+/// The required accounts for a new_stop_order instruction.
+#[derive(InstructionsAccount)]
+pub struct Accounts<'a, T> {
+    #[allow(missing_docs)]
+    pub market: &'a T,
+    #[allow(missing_docs)]
+    #[cons(writable)]
+    pub event_queue: &'a T,
+    #[allow(missing_docs)]
+    #[cons(writable)]
+    pub stop_orders: &'a T,
+    /// The allowlist of the market, which must be given when the market has one
+    pub allowlist: Option<&'a T>,
+    /// The event queue shards of the market, in the order in which they were registered
+    #[cons(writable)]
+    pub event_queue_shards: &'a [T],
+}
+
+impl<'a, 'b: 'a> Accounts<'a, AccountInfo<'b>> {
+    pub(crate) fn parse(accounts: &'a [AccountInfo<'b>]) -> Result<Self, ProgramError> {
+        let accounts_iter = &mut accounts.iter();
+
+        let a = Self {
+            market: next_account_info(accounts_iter)?,
+            event_queue: next_account_info(accounts_iter)?,
+            stop_orders: next_account_info(accounts_iter)?,
+            allowlist: next_allowlist_account(accounts_iter),
+            event_queue_shards: accounts_iter.as_slice(),
+        };
+        Ok(a)
+    }
+    pub(crate) fn perform_checks(&self, program_id: &Pubkey) -> Result<(), ProgramError> {
+        check_account_owner(
+            self.market,
+            &program_id.to_bytes(),
+            AoError::WrongMarketOwner,
+        )?;
+        check_account_owner(
+            self.event_queue,
+            &program_id.to_bytes(),
+            AoError::WrongEventQueueOwner,
+        )?;
+        check_account_owner(
+            self.stop_orders,
+            &program_id.to_bytes(),
+            AoError::WrongStopOrdersOwner,
+        )?;
+        for shard in self.event_queue_shards {
+            check_account_owner(shard, &program_id.to_bytes(), AoError::WrongEventQueueOwner)?;
+        }
+        Ok(())
+    }
+}
+
+/// Apply the new_stop_order instruction to the provided accounts
+pub fn process<'a, 'b: 'a, C: Pod + CallbackInfo>(
+    program_id: &Pubkey,
+    accounts: Accounts<'a, AccountInfo<'b>>,
+    params: Params<C>,
+) -> Result<OrderSummary, ProgramError> {
+    accounts.perform_checks(program_id)?;
+    let mut market_data = accounts.market.data.borrow_mut();
+    let market_state = MarketState::from_buffer(&mut market_data, AccountTag::Market)?;
+
+    check_accounts(&accounts, market_state)?;
+    check_order_allowlist(accounts.allowlist, market_state, [&params.callback_info])?;
+    market_state.check_can_place_order(true)?;
+
+    if params.trigger_price == 0 {
+        msg!("The trigger price of a stop order must be > 0!");
+        return Err(AoError::InvalidLimitPrice.into());
+    }
+
+    if params.trigger_price % market_state.tick_size != 0
+        || params.limit_price % market_state.tick_size != 0
+    {
+        msg!("The trigger and limit prices must be multiples of the tick size!");
+        return Err(AoError::InvalidTickSize.into());
+    }
+
+    if params.max_base_qty == 0 {
+        msg!("The base quantity of a stop order must be > 0!");
+        return Err(AoError::InvalidBaseQuantity.into());
+    }
+
+    if params.max_base_qty < market_state.min_base_order_size
+        || params.max_quote_qty < market_state.min_quote_order_size
+    {
+        msg!("The order is smaller than the market's minimum order size!");
+        return Err(AoError::OrderTooSmall.into());
+    }
+
+    if params.self_trade_behavior == SelfTradeBehavior::AbortTransaction {
+        msg!("Stop orders can't abort the transaction on self trade!");
+        return Err(AoError::InvalidSelfTradeBehavior.into());
+    }
+
+    let mut event_queue_guard = accounts.event_queue.data.borrow_mut();
+    let mut event_queue_shards_guards = accounts
+        .event_queue_shards
+        .iter()
+        .map(|a| a.data.borrow_mut())
+        .collect::<Vec<_>>();
+    let mut event_queue = EventQueue::from_market_buffers(
+        &mut event_queue_guard,
+        &mut event_queue_shards_guards,
+        market_state,
+    )?;
+
+    let mut stop_orders_guard = accounts.stop_orders.data.borrow_mut();
+    let mut stop_orders_slab =
+        StopOrdersSlab::<C>::from_buffer(&mut stop_orders_guard, AccountTag::StopOrders)?;
+
+    let stop_order = StopOrder {
+        limit_price: params.limit_price,
+        max_quote_qty: params.max_quote_qty,
+        match_limit: params.match_limit,
+        max_slippage: params.max_slippage,
+        self_trade_behavior: params.self_trade_behavior as u8,
+        flags: params.flags,
+        callback_info: params.callback_info,
+    };
+    let order_id = stop_orders::insert(
+        &mut stop_orders_slab,
+        &mut event_queue,
+        params.trigger_price,
+        params.side,
+        params.max_base_qty,
+        params.max_ts,
+        stop_order,
+    )?;
+
+    let order_summary = OrderSummary {
+        posted_order_id: Some(order_id),
+        total_base_qty: 0,
+        total_quote_qty: 0,
+        total_base_qty_posted: params.max_base_qty,
+        total_quote_qty_posted: 0,
+        total_base_qty_unfilled: 0,
+        flags: params.flags,
+        match_limit_exhausted: false,
+        compute_budget_exhausted: false,
+    };
+    msg!("Order summary : {:?}", order_summary);
+
+    Ok(order_summary)
+}
+
+fn check_accounts<'a, 'b: 'a>(
+    accounts: &Accounts<'a, AccountInfo<'b>>,
+    market_state: &MarketState,
+) -> ProgramResult {
+    check_account_key(
+        accounts.event_queue,
+        &market_state.event_queue,
+        AoError::WrongEventQueueAccount,
+    )?;
+    check_account_key(
+        accounts.stop_orders,
+        &market_state.stop_orders,
+        AoError::WrongStopOrdersAccount,
+    )?;
+
+    check_event_queue_shards(accounts.event_queue_shards, market_state)?;
+    Ok(())
+}
@@ -16,7 +16,7 @@ use crate::state::{AccountTag, Side};
 use crate::{
     error::AoError,
     state::market_state::MarketState,
-    utils::{check_account_key, check_account_owner},
+    utils::{check_account_key, check_account_owner, check_event_queue_shards},
 };
 #[derive(BorshDeserialize, BorshSerialize, Clone, BorshSize)]
 /**
@@ -42,6 +42,9 @@ pub struct Accounts<'a, T> {
     #[allow(missing_docs)]
     #[cons(writable)]
     pub asks: &'a T,
+    /// The event queue shards of the market, in the order in which they were registered
+    #[cons(writable)]
+    pub event_queue_shards: &'a [T],
 }
 
 impl<'a, 'b: 'a> Accounts<'a, AccountInfo<'b>> {
@@ -53,6 +56,7 @@ impl<'a, 'b: 'a> Accounts<'a, AccountInfo<'b>> {
             event_queue: next_account_info(accounts_iter)?,
             bids: next_account_info(accounts_iter)?,
             asks: next_account_info(accounts_iter)?,
+            event_queue_shards: accounts_iter.as_slice(),
         };
         Ok(a)
     }
@@ -71,6 +75,9 @@ impl<'a, 'b: 'a> Accounts<'a, AccountInfo<'b>> {
         )?;
         check_account_owner(self.bids, &program_id.to_bytes(), AoError::WrongBidsOwner)?;
         check_account_owner(self.asks, &program_id.to_bytes(), AoError::WrongAsksOwner)?;
+        for shard in self.event_queue_shards {
+            check_account_owner(shard, &program_id.to_bytes(), AoError::WrongEventQueueOwner)?;
+        }
         Ok(())
     }
 }
@@ -95,7 +102,16 @@ where
     let mut order_book = OrderBookState::<C>::new_safe(&mut bids_guard, &mut asks_guard)?;
 
     let mut event_queue_guard = accounts.event_queue.data.borrow_mut();
-    let mut event_queue = EventQueue::from_buffer(&mut event_queue_guard, AccountTag::EventQueue)?;
+    let mut event_queue_shards_guards = accounts
+        .event_queue_shards
+        .iter()
+        .map(|a| a.data.borrow_mut())
+        .collect::<Vec<_>>();
+    let mut event_queue = EventQueue::from_market_buffers(
+        &mut event_queue_guard,
+        &mut event_queue_shards_guards,
+        market_state,
+    )?;
     let num_bids = u64::from(order_book.get_tree(Side::Bid).header.leaf_count);
     // Number of bids/asks to prune is bounded by: number of bids, param with max number of orders to prune
     let num_bids_to_prune = cmp::min(num_bids, params.num_orders_to_prune);
@@ -103,7 +119,7 @@ where
     let remaining_num_orders = params
         .num_orders_to_prune
         .checked_sub(num_bids_to_prune)
-        .unwrap();
+        .ok_or(AoError::NumericalOverflow)?;
     let num_asks = u64::from(order_book.get_tree(Side::Ask).header.leaf_count);
     let num_asks_to_prune = cmp::min(num_asks, remaining_num_orders);
     order_book.prune_orders(num_asks_to_prune, Side::Ask, &mut event_queue)?;
@@ -128,5 +144,6 @@ fn check_accounts<'a, 'b: 'a>(
     check_account_key(accounts.bids, &market_state.bids, AoError::WrongBidsAccount)?;
     check_account_key(accounts.asks, &market_state.asks, AoError::WrongAsksAccount)?;
 
+    check_event_queue_shards(accounts.event_queue_shards, market_state)?;
     Ok(())
 }
\ No newline at end of file
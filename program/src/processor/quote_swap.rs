@@ -0,0 +1,118 @@
+//! Quote a swap through the orderbook without modifying it, and write the quote into the register account.
+use bonfida_utils::{BorshSize, InstructionsAccount};
+use borsh::{BorshDeserialize, BorshSerialize};
+use bytemuck::Pod;
+use solana_program::{
+    account_info::{next_account_info, AccountInfo},
+    clock::Clock,
+    entrypoint::ProgramResult,
+    program_error::ProgramError,
+    pubkey::Pubkey,
+    sysvar::Sysvar,
+};
+
+use crate::{
+    error::AoError,
+    state::{
+        market_state::MarketState,
+        orderbook::{CallbackInfo, OrderBookState, SwapQuote},
+        AccountTag, Side,
+    },
+    utils::{check_account_key, check_account_owner},
+};
+
+#[derive(BorshDeserialize, BorshSerialize, Clone, BorshSize)]
+/**
+The required arguments for a quote_swap instruction.
+*/
+pub struct Params {
+    /// The side of the market order which would perform the swap: a bid spends quote to receive base, and an ask spends
+    /// base to receive quote.
+    pub side: Side,
+    /// The amount to spend when `exact_in` is set, or the amount to receive otherwise, in lots.
+    pub amount: u64,
+    /// Whether `amount` is the input or the output of the swap.
+    pub exact_in: bool,
+    /// The maximum number of orders to walk.
+    pub match_limit: u64,
+}
+
+/// The required accounts for a quote_swap instruction.
+#[derive(InstructionsAccount)]
+pub struct Accounts<'a, T> {
+    #[allow(missing_docs)]
+    pub market: &'a T,
+    #[allow(missing_docs)]
+    pub bids: &'a T,
+    #[allow(missing_docs)]
+    pub asks: &'a T,
+}
+
+impl<'a, 'b: 'a> Accounts<'a, AccountInfo<'b>> {
+    pub(crate) fn parse(accounts: &'a [AccountInfo<'b>]) -> Result<Self, ProgramError> {
+        let accounts_iter = &mut accounts.iter();
+
+        let a = Self {
+            market: next_account_info(accounts_iter)?,
+            bids: next_account_info(accounts_iter)?,
+            asks: next_account_info(accounts_iter)?,
+        };
+        Ok(a)
+    }
+
+    /// Perform basic security checks on the accounts
+    pub(crate) fn perform_checks(&self, program_id: &Pubkey) -> Result<(), ProgramError> {
+        check_account_owner(
+            self.market,
+            &program_id.to_bytes(),
+            AoError::WrongMarketOwner,
+        )?;
+        check_account_owner(self.bids, &program_id.to_bytes(), AoError::WrongBidsOwner)?;
+        check_account_owner(self.asks, &program_id.to_bytes(), AoError::WrongAsksOwner)?;
+        Ok(())
+    }
+}
+
+/// Apply the quote_swap instruction to the provided accounts
+pub fn process<'a, 'b: 'a, C: CallbackInfo + Pod + PartialEq>(
+    program_id: &Pubkey,
+    accounts: Accounts<'a, AccountInfo<'b>>,
+    params: Params,
+) -> Result<SwapQuote, ProgramError>
+where
+    <C as CallbackInfo>::CallbackId: PartialEq,
+{
+    accounts.perform_checks(program_id)?;
+    let market_data = accounts.market.data.borrow();
+    let market_state = MarketState::from_buffer(&market_data, AccountTag::Market)?;
+
+    check_accounts(&accounts, market_state)?;
+
+    let mut bids_guard = accounts.bids.data.borrow_mut();
+    let mut asks_guard = accounts.asks.data.borrow_mut();
+    let order_book = OrderBookState::<C>::new_safe(&mut bids_guard, &mut asks_guard)?;
+
+    let cur_ts = Clock::get()?.unix_timestamp as u64;
+    let Params {
+        side,
+        amount,
+        exact_in,
+        match_limit,
+    } = params;
+    let quote = if exact_in {
+        order_book.quote_exact_in(side, amount, match_limit, market_state, cur_ts)?
+    } else {
+        order_book.quote_exact_out(side, amount, match_limit, market_state, cur_ts)?
+    };
+    Ok(quote)
+}
+
+fn check_accounts<'a, 'b: 'a>(
+    accounts: &Accounts<'a, AccountInfo<'b>>,
+    market_state: &MarketState,
+) -> ProgramResult {
+    check_account_key(accounts.bids, &market_state.bids, AoError::WrongBidsAccount)?;
+    check_account_key(accounts.asks, &market_state.asks, AoError::WrongAsksAccount)?;
+
+    Ok(())
+}
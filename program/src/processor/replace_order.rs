@@ -0,0 +1,173 @@
+//! Atomically replace an existing order of the orderbook with a new order
+use bonfida_utils::{BorshSize, InstructionsAccount};
+use borsh::{BorshDeserialize, BorshSerialize};
+use bytemuck::Pod;
+use solana_program::{
+    account_info::{next_account_info, AccountInfo},
+    clock::Clock,
+    entrypoint::ProgramResult,
+    msg,
+    program_error::ProgramError,
+    pubkey::Pubkey,
+    sysvar::Sysvar,
+};
+
+use crate::{
+    error::AoError,
+    processor::new_order,
+    state::{
+        allowlist::next_allowlist_account,
+        event_queue::EventQueue,
+        market_state::MarketState,
+        orderbook::{CallbackInfo, OrderBookState},
+        AccountTag, OrderSummary,
+    },
+    utils::{
+        check_account_key, check_account_owner, check_event_queue_shards, check_order_allowlist,
+    },
+};
+
+#[derive(Clone, BorshSerialize, BorshDeserialize)]
+/**
+The required arguments for a replace_order instruction.
+*/
+pub struct Params<C> {
+    /// The order id of the order to replace.
+    pub order_id: u128,
+    /// The new order. See [`new_order::Params`] for a description of each parameter.
+    ///
+    /// If the new order has the same side and limit price as the existing order and only decreases its size, the existing
+    /// order keeps its priority in the orderbook, and a [`ModifyEvent`][`crate::state::event_queue::ModifyEvent`] is pushed
+    /// onto the event queue. Otherwise, the existing order is taken out of the orderbook with an
+    /// [`OutEvent`][`crate::state::event_queue::OutEvent`].
+    pub new_order: new_order::Params<C>,
+}
+
+impl<C: BorshSize> BorshSize for Params<C> {
+    fn borsh_len(&self) -> usize {
+        self.order_id.borsh_len() + self.new_order.borsh_len()
+    }
+}
+
+/// The required accounts for a replace_order instruction.
+#[derive(InstructionsAccount)]
+pub struct Accounts<'a, T> {
+    #[allow(missing_docs)]
+    #[cons(writable)]
+    pub market: &'a T,
+    #[allow(missing_docs)]
+    #[cons(writable)]
+    pub event_queue: &'a T,
+    #[allow(missing_docs)]
+    #[cons(writable)]
+    pub bids: &'a T,
+    #[allow(missing_docs)]
+    #[cons(writable)]
+    pub asks: &'a T,
+    /// The allowlist of the market, which must be given when the market has one
+    pub allowlist: Option<&'a T>,
+    /// The event queue shards of the market, in the order in which they were registered
+    #[cons(writable)]
+    pub event_queue_shards: &'a [T],
+}
+
+impl<'a, 'b: 'a> Accounts<'a, AccountInfo<'b>> {
+    pub(crate) fn parse(accounts: &'a [AccountInfo<'b>]) -> Result<Self, ProgramError> {
+        let accounts_iter = &mut accounts.iter();
+
+        let a = Self {
+            market: next_account_info(accounts_iter)?,
+            event_queue: next_account_info(accounts_iter)?,
+            bids: next_account_info(accounts_iter)?,
+            asks: next_account_info(accounts_iter)?,
+            allowlist: next_allowlist_account(accounts_iter),
+            event_queue_shards: accounts_iter.as_slice(),
+        };
+        Ok(a)
+    }
+    pub(crate) fn perform_checks(&self, program_id: &Pubkey) -> Result<(), ProgramError> {
+        check_account_owner(
+            self.market,
+            &program_id.to_bytes(),
+            AoError::WrongMarketOwner,
+        )?;
+        check_account_owner(
+            self.event_queue,
+            &program_id.to_bytes(),
+            AoError::WrongEventQueueOwner,
+        )?;
+        check_account_owner(self.bids, &program_id.to_bytes(), AoError::WrongBidsOwner)?;
+        check_account_owner(self.asks, &program_id.to_bytes(), AoError::WrongAsksOwner)?;
+        for shard in self.event_queue_shards {
+            check_account_owner(shard, &program_id.to_bytes(), AoError::WrongEventQueueOwner)?;
+        }
+        Ok(())
+    }
+}
+
+/// Apply the replace_order instruction to the provided accounts
+pub fn process<'a, 'b: 'a, C: Pod + CallbackInfo + PartialEq>(
+    program_id: &Pubkey,
+    accounts: Accounts<'a, AccountInfo<'b>>,
+    params: Params<C>,
+) -> Result<OrderSummary, ProgramError>
+where
+    <C as CallbackInfo>::CallbackId: PartialEq,
+{
+    accounts.perform_checks(program_id)?;
+    let mut market_data = accounts.market.data.borrow_mut();
+    let market_state = MarketState::from_buffer_mut(&mut market_data, AccountTag::Market)?;
+
+    check_accounts(&accounts, market_state)?;
+    check_order_allowlist(
+        accounts.allowlist,
+        market_state,
+        [&params.new_order.callback_info],
+    )?;
+    new_order::check_params(&params.new_order, market_state)?;
+
+    let mut bids_guard = accounts.bids.data.borrow_mut();
+    let mut asks_guard = accounts.asks.data.borrow_mut();
+
+    let mut order_book = OrderBookState::new_safe(&mut bids_guard, &mut asks_guard)?;
+
+    let mut event_queue_guard = accounts.event_queue.data.borrow_mut();
+    let mut event_queue_shards_guards = accounts
+        .event_queue_shards
+        .iter()
+        .map(|a| a.data.borrow_mut())
+        .collect::<Vec<_>>();
+    let mut event_queue = EventQueue::from_market_buffers(
+        &mut event_queue_guard,
+        &mut event_queue_shards_guards,
+        market_state,
+    )?;
+    let cur_ts = Clock::get()?.unix_timestamp as u64;
+
+    let order_summary = order_book.replace_order(
+        params.order_id,
+        params.new_order,
+        &mut event_queue,
+        market_state,
+        cur_ts,
+    )?;
+    msg!("Order summary : {:?}", order_summary);
+
+    Ok(order_summary)
+}
+
+fn check_accounts<'a, 'b: 'a>(
+    accounts: &Accounts<'a, AccountInfo<'b>>,
+    market_state: &MarketState,
+) -> ProgramResult {
+    check_account_key(
+        accounts.event_queue,
+        &market_state.event_queue,
+        AoError::WrongEventQueueAccount,
+    )?;
+    check_account_key(accounts.bids, &market_state.bids, AoError::WrongBidsAccount)?;
+    check_account_key(accounts.asks, &market_state.asks, AoError::WrongAsksAccount)?;
+
+    check_event_queue_shards(accounts.event_queue_shards, market_state)?;
+    Ok(())
+}
@@ -0,0 +1,178 @@
+//! Move the pegged orders of a market to their current peg price.
+use bonfida_utils::{BorshSize, InstructionsAccount};
+use borsh::{BorshDeserialize, BorshSerialize};
+use bytemuck::Pod;
+use solana_program::{
+    account_info::{next_account_info, AccountInfo},
+    clock::Clock,
+    entrypoint::ProgramResult,
+    msg,
+    program_error::ProgramError,
+    pubkey::Pubkey,
+    sysvar::Sysvar,
+};
+
+use crate::{
+    error::AoError,
+    state::{
+        event_queue::EventQueue,
+        market_state::MarketState,
+        orderbook::{CallbackInfo, OrderBookState},
+        pegged_orders::{self, PeggedOrdersSlab},
+        AccountTag,
+    },
+    utils::{check_account_key, check_account_owner, check_event_queue_shards},
+};
+
+#[derive(BorshDeserialize, BorshSerialize, Clone, BorshSize)]
+/**
+The required arguments for a reprice_pegged instruction.
+*/
+pub struct Params {
+    /// Depending on available compute or space on the event queue, there may
+    /// be a limit to the amount of pegged orders that can be repriced in one transaction
+    pub max_orders: u64,
+    /// The pegged orders whose id is lower than this value are skipped, so that successive instructions can walk
+    /// through all the pegged orders of the market. The id from which the next instruction should start is logged
+    /// when some pegged orders haven't been reached. Use 0 to start from the first pegged order.
+    pub start_key: u128,
+}
+
+/// The required accounts for a reprice_pegged instruction.
+#[derive(InstructionsAccount)]
+pub struct Accounts<'a, T> {
+    #[allow(missing_docs)]
+    #[cons(writable)]
+    pub market: &'a T,
+    #[allow(missing_docs)]
+    #[cons(writable)]
+    pub event_queue: &'a T,
+    #[allow(missing_docs)]
+    #[cons(writable)]
+    pub bids: &'a T,
+    #[allow(missing_docs)]
+    #[cons(writable)]
+    pub asks: &'a T,
+    #[allow(missing_docs)]
+    #[cons(writable)]
+    pub pegged_orders: &'a T,
+    /// The event queue shards of the market, in the order in which they were registered
+    #[cons(writable)]
+    pub event_queue_shards: &'a [T],
+}
+
+impl<'a, 'b: 'a> Accounts<'a, AccountInfo<'b>> {
+    pub(crate) fn parse(accounts: &'a [AccountInfo<'b>]) -> Result<Self, ProgramError> {
+        let accounts_iter = &mut accounts.iter();
+
+        let a = Self {
+            market: next_account_info(accounts_iter)?,
+            event_queue: next_account_info(accounts_iter)?,
+            bids: next_account_info(accounts_iter)?,
+            asks: next_account_info(accounts_iter)?,
+            pegged_orders: next_account_info(accounts_iter)?,
+            event_queue_shards: accounts_iter.as_slice(),
+        };
+        Ok(a)
+    }
+
+    /// Perform basic security checks on the accounts
+    pub(crate) fn perform_checks(&self, program_id: &Pubkey) -> Result<(), ProgramError> {
+        check_account_owner(
+            self.market,
+            &program_id.to_bytes(),
+            AoError::WrongMarketOwner,
+        )?;
+        check_account_owner(
+            self.event_queue,
+            &program_id.to_bytes(),
+            AoError::WrongEventQueueOwner,
+        )?;
+        check_account_owner(self.bids, &program_id.to_bytes(), AoError::WrongBidsOwner)?;
+        check_account_owner(self.asks, &program_id.to_bytes(), AoError::WrongAsksOwner)?;
+        check_account_owner(
+            self.pegged_orders,
+            &program_id.to_bytes(),
+            AoError::WrongPeggedOrdersOwner,
+        )?;
+        for shard in self.event_queue_shards {
+            check_account_owner(shard, &program_id.to_bytes(), AoError::WrongEventQueueOwner)?;
+        }
+        Ok(())
+    }
+}
+/// Apply the reprice_pegged instruction to the provided accounts
+pub fn process<'a, 'b: 'a, C: CallbackInfo + Pod + PartialEq>(
+    program_id: &Pubkey,
+    accounts: Accounts<'a, AccountInfo<'b>>,
+    params: Params,
+) -> ProgramResult
+where
+    <C as CallbackInfo>::CallbackId: PartialEq,
+{
+    accounts.perform_checks(program_id)?;
+    let mut market_state_data = accounts.market.data.borrow_mut();
+    let market_state = MarketState::from_buffer_mut(&mut market_state_data, AccountTag::Market)?;
+
+    check_accounts(&accounts, market_state)?;
+    market_state.check_can_place_order(false)?;
+
+    let mut bids_guard = accounts.bids.data.borrow_mut();
+    let mut asks_guard = accounts.asks.data.borrow_mut();
+
+    let mut order_book = OrderBookState::<C>::new_safe(&mut bids_guard, &mut asks_guard)?;
+
+    let mut event_queue_guard = accounts.event_queue.data.borrow_mut();
+    let mut event_queue_shards_guards = accounts
+        .event_queue_shards
+        .iter()
+        .map(|a| a.data.borrow_mut())
+        .collect::<Vec<_>>();
+    let mut event_queue = EventQueue::from_market_buffers(
+        &mut event_queue_guard,
+        &mut event_queue_shards_guards,
+        market_state,
+    )?;
+
+    let mut pegged_orders_guard = accounts.pegged_orders.data.borrow_mut();
+    let mut pegged_orders_slab =
+        PeggedOrdersSlab::from_buffer(&mut pegged_orders_guard, AccountTag::PeggedOrders)?;
+    let cur_ts = Clock::get()?.unix_timestamp as u64;
+
+    let (num_repriced, next_start_key) = pegged_orders::reprice(
+        &mut pegged_orders_slab,
+        &mut order_book,
+        &mut event_queue,
+        market_state,
+        params.max_orders,
+        params.start_key,
+        cur_ts,
+    )?;
+
+    msg!("Pegged orders repriced: {}", num_repriced);
+    if let Some(next_start_key) = next_start_key {
+        msg!("Next pegged order: {}", next_start_key);
+    }
+    Ok(())
+}
+
+fn check_accounts<'a, 'b: 'a>(
+    accounts: &Accounts<'a, AccountInfo<'b>>,
+    market_state: &MarketState,
+) -> ProgramResult {
+    check_account_key(
+        accounts.event_queue,
+        &market_state.event_queue,
+        AoError::WrongEventQueueAccount,
+    )?;
+    check_account_key(accounts.bids, &market_state.bids, AoError::WrongBidsAccount)?;
+    check_account_key(accounts.asks, &market_state.asks, AoError::WrongAsksAccount)?;
+    check_account_key(
+        accounts.pegged_orders,
+        &market_state.pegged_orders,
+        AoError::WrongPeggedOrdersAccount,
+    )?;
+
+    check_event_queue_shards(accounts.event_queue_shards, market_state)?;
+    Ok(())
+}
@@ -0,0 +1,109 @@
+//! Grow the event queue of an existing market.
+use bonfida_utils::{checks::check_rent_exempt, BorshSize, InstructionsAccount};
+use borsh::{BorshDeserialize, BorshSerialize};
+use bytemuck::Pod;
+use solana_program::{
+    account_info::{next_account_info, AccountInfo},
+    entrypoint::ProgramResult,
+    msg,
+    program_error::ProgramError,
+    pubkey::Pubkey,
+};
+
+use crate::{
+    error::AoError,
+    state::{event_queue::EventQueue, market_state::MarketState, AccountTag},
+    utils::{check_account_key, check_account_owner},
+};
+
+#[derive(BorshDeserialize, BorshSerialize, Clone, BorshSize)]
+/**
+The required arguments for a resize_event_queue instruction.
+*/
+pub struct Params {
+    /// The new maximum number of events which the event queue can hold.
+    ///
+    /// The runtime limits how much an account can grow in a single instruction, so large increases in capacity may
+    /// require several instructions.
+    pub event_capacity: u64,
+}
+
+/// The required accounts for a resize_event_queue instruction.
+#[derive(InstructionsAccount)]
+pub struct Accounts<'a, T> {
+    #[allow(missing_docs)]
+    pub market: &'a T,
+    #[allow(missing_docs)]
+    #[cons(writable)]
+    pub event_queue: &'a T,
+}
+
+impl<'a, 'b: 'a> Accounts<'a, AccountInfo<'b>> {
+    pub(crate) fn parse(accounts: &'a [AccountInfo<'b>]) -> Result<Self, ProgramError> {
+        let accounts_iter = &mut accounts.iter();
+
+        let a = Self {
+            market: next_account_info(accounts_iter)?,
+            event_queue: next_account_info(accounts_iter)?,
+        };
+        Ok(a)
+    }
+
+    /// Perform basic security checks on the accounts
+    pub(crate) fn perform_checks(&self, program_id: &Pubkey) -> Result<(), ProgramError> {
+        check_account_owner(
+            self.market,
+            &program_id.to_bytes(),
+            AoError::WrongMarketOwner,
+        )?;
+        check_account_owner(
+            self.event_queue,
+            &program_id.to_bytes(),
+            AoError::WrongEventQueueOwner,
+        )?;
+        Ok(())
+    }
+}
+
+/// Apply the resize_event_queue instruction to the provided accounts
+pub fn process<'a, 'b: 'a, C: Pod>(
+    program_id: &Pubkey,
+    accounts: Accounts<'a, AccountInfo<'b>>,
+    params: Params,
+) -> ProgramResult {
+    accounts.perform_checks(program_id)?;
+    let market_data = accounts.market.data.borrow();
+    let market_state = MarketState::from_buffer(&market_data, AccountTag::Market)?;
+
+    check_accounts(&accounts, market_state)?;
+
+    let new_len = EventQueue::<C>::compute_allocation_size(params.event_capacity as usize);
+    let old_len = accounts.event_queue.data_len();
+    if new_len < old_len {
+        msg!("The event queue can't be shrunk!");
+        return Err(AoError::AccountShrinkNotAllowed.into());
+    }
+    // Makes sure that the account holds an event queue before moving its contents around
+    EventQueue::<C>::from_buffer(
+        &mut accounts.event_queue.data.borrow_mut(),
+        AccountTag::EventQueue,
+    )?;
+    accounts.event_queue.realloc(new_len, false)?;
+    EventQueue::<C>::grow(&mut accounts.event_queue.data.borrow_mut(), old_len);
+    check_rent_exempt(accounts.event_queue)?;
+
+    Ok(())
+}
+
+fn check_accounts<'a, 'b: 'a>(
+    accounts: &Accounts<'a, AccountInfo<'b>>,
+    market_state: &MarketState,
+) -> ProgramResult {
+    check_account_key(
+        accounts.event_queue,
+        &market_state.event_queue,
+        AoError::WrongEventQueueAccount,
+    )?;
+
+    Ok(())
+}
@@ -0,0 +1,111 @@
+//! Grow the bids and asks accounts of an existing market.
+use bonfida_utils::{checks::check_rent_exempt, BorshSize, InstructionsAccount};
+use borsh::{BorshDeserialize, BorshSerialize};
+use bytemuck::Pod;
+use solana_program::{
+    account_info::{next_account_info, AccountInfo},
+    entrypoint::ProgramResult,
+    msg,
+    program_error::ProgramError,
+    pubkey::Pubkey,
+};
+
+use crate::{
+    error::AoError,
+    state::{critbit::Slab, market_state::MarketState, AccountTag},
+    utils::{check_account_key, check_account_owner},
+};
+
+#[derive(BorshDeserialize, BorshSerialize, Clone, BorshSize)]
+/**
+The required arguments for a resize_market instruction.
+*/
+pub struct Params {
+    /// The new maximum number of orders on each side of the orderbook.
+    ///
+    /// The runtime limits how much an account can grow in a single instruction, so large increases in capacity may
+    /// require several instructions.
+    pub order_capacity: u64,
+}
+
+/// The required accounts for a resize_market instruction.
+#[derive(InstructionsAccount)]
+pub struct Accounts<'a, T> {
+    #[allow(missing_docs)]
+    pub market: &'a T,
+    #[allow(missing_docs)]
+    #[cons(writable)]
+    pub bids: &'a T,
+    #[allow(missing_docs)]
+    #[cons(writable)]
+    pub asks: &'a T,
+}
+
+impl<'a, 'b: 'a> Accounts<'a, AccountInfo<'b>> {
+    pub(crate) fn parse(accounts: &'a [AccountInfo<'b>]) -> Result<Self, ProgramError> {
+        let accounts_iter = &mut accounts.iter();
+
+        let a = Self {
+            market: next_account_info(accounts_iter)?,
+            bids: next_account_info(accounts_iter)?,
+            asks: next_account_info(accounts_iter)?,
+        };
+        Ok(a)
+    }
+
+    /// Perform basic security checks on the accounts
+    pub(crate) fn perform_checks(&self, program_id: &Pubkey) -> Result<(), ProgramError> {
+        check_account_owner(
+            self.market,
+            &program_id.to_bytes(),
+            AoError::WrongMarketOwner,
+        )?;
+        check_account_owner(self.bids, &program_id.to_bytes(), AoError::WrongBidsOwner)?;
+        check_account_owner(self.asks, &program_id.to_bytes(), AoError::WrongAsksOwner)?;
+        Ok(())
+    }
+}
+
+/// Apply the resize_market instruction to the provided accounts
+pub fn process<'a, 'b: 'a, C: Pod>(
+    program_id: &Pubkey,
+    accounts: Accounts<'a, AccountInfo<'b>>,
+    params: Params,
+) -> ProgramResult {
+    accounts.perform_checks(program_id)?;
+    let market_data = accounts.market.data.borrow();
+    let market_state = MarketState::from_buffer(&market_data, AccountTag::Market)?;
+
+    check_accounts(&accounts, market_state)?;
+
+    let new_len = Slab::<C>::compute_allocation_size(params.order_capacity as usize);
+    for (slab_account, tag) in [
+        (accounts.bids, AccountTag::Bids),
+        (accounts.asks, AccountTag::Asks),
+    ]
+    .iter()
+    {
+        let old_len = slab_account.data_len();
+        if new_len < old_len {
+            msg!("The orderbook accounts can't be shrunk!");
+            return Err(AoError::AccountShrinkNotAllowed.into());
+        }
+        // Makes sure that the account holds a slab before moving its contents around
+        Slab::<C>::from_buffer(&mut slab_account.data.borrow_mut(), *tag)?;
+        slab_account.realloc(new_len, false)?;
+        Slab::<C>::grow(&mut slab_account.data.borrow_mut(), old_len);
+        check_rent_exempt(slab_account)?;
+    }
+
+    Ok(())
+}
+
+fn check_accounts<'a, 'b: 'a>(
+    accounts: &Accounts<'a, AccountInfo<'b>>,
+    market_state: &MarketState,
+) -> ProgramResult {
+    check_account_key(accounts.bids, &market_state.bids, AoError::WrongBidsAccount)?;
+    check_account_key(accounts.asks, &market_state.asks, AoError::WrongAsksAccount)?;
+
+    Ok(())
+}
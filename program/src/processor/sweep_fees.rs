@@ -0,0 +1,80 @@
+//! Withdraw the lamports held by a market account above its rent exemption threshold.
+use bonfida_utils::{BorshSize, InstructionsAccount};
+use borsh::{BorshDeserialize, BorshSerialize};
+use solana_program::{
+    account_info::{next_account_info, AccountInfo},
+    entrypoint::ProgramResult,
+    msg,
+    program_error::ProgramError,
+    pubkey::Pubkey,
+    rent::Rent,
+    sysvar::Sysvar,
+};
+
+use crate::{
+    error::AoError,
+    state::{market_state::MarketState, AccountTag},
+    utils::check_account_owner,
+};
+
+#[derive(BorshDeserialize, BorshSerialize, BorshSize)]
+/**
+The required arguments for a sweep_fees instruction.
+*/
+pub struct Params {}
+
+/// The required accounts for a sweep_fees instruction.
+#[derive(InstructionsAccount)]
+pub struct Accounts<'a, T> {
+    #[allow(missing_docs)]
+    #[cons(writable)]
+    pub market: &'a T,
+    #[allow(missing_docs)]
+    #[cons(writable)]
+    pub lamports_target_account: &'a T,
+}
+
+impl<'a, 'b: 'a> Accounts<'a, AccountInfo<'b>> {
+    pub(crate) fn parse(accounts: &'a [AccountInfo<'b>]) -> Result<Self, ProgramError> {
+        let accounts_iter = &mut accounts.iter();
+
+        let a = Self {
+            market: next_account_info(accounts_iter)?,
+            lamports_target_account: next_account_info(accounts_iter)?,
+        };
+        Ok(a)
+    }
+
+    /// Perform basic security checks on the accounts
+    pub(crate) fn perform_checks(&self, program_id: &Pubkey) -> Result<(), ProgramError> {
+        check_account_owner(
+            self.market,
+            &program_id.to_bytes(),
+            AoError::WrongMarketOwner,
+        )?;
+        Ok(())
+    }
+}
+
+/// Apply the sweep_fees instruction to the provided accounts
+pub fn process(
+    program_id: &Pubkey,
+    accounts: Accounts<AccountInfo>,
+    _params: Params,
+) -> ProgramResult {
+    accounts.perform_checks(program_id)?;
+    let data_len = {
+        let market_data = accounts.market.data.borrow();
+        MarketState::from_buffer(&market_data, AccountTag::Market)?;
+        market_data.len()
+    };
+
+    let minimum_balance = Rent::get()?.minimum_balance(data_len);
+    let mut market_lamports = accounts.market.lamports.borrow_mut();
+    let excess = market_lamports.saturating_sub(minimum_balance);
+    **market_lamports -= excess;
+    **accounts.lamports_target_account.lamports.borrow_mut() += excess;
+    msg!("Swept {} lamports from the market account", excess);
+
+    Ok(())
+}
@@ -0,0 +1,99 @@
+//! Add an entry to the allowlist of a market, or remove one from it
+use bonfida_utils::{BorshSize, InstructionsAccount};
+use borsh::{BorshDeserialize, BorshSerialize};
+use solana_program::{
+    account_info::{next_account_info, AccountInfo},
+    entrypoint::ProgramResult,
+    program_error::ProgramError,
+    pubkey::Pubkey,
+};
+
+use crate::{
+    error::AoError,
+    state::{
+        allowlist::{Allowlist, ALLOWLIST_ENTRY_LEN},
+        market_state::MarketState,
+        AccountTag,
+    },
+    utils::{check_account_key, check_account_owner},
+};
+
+#[derive(BorshDeserialize, BorshSerialize)]
+/**
+The required arguments for an update_allowlist instruction.
+*/
+pub struct Params {
+    /// The entry to add or remove, as returned by [`allowlist_entry`][`crate::state::allowlist::allowlist_entry`] for
+    /// the callback info to authorize.
+    pub entry: [u8; ALLOWLIST_ENTRY_LEN],
+    /// Whether the entry is added to the allowlist, or removed from it.
+    pub allowed: bool,
+}
+
+impl BorshSize for Params {
+    fn borsh_len(&self) -> usize {
+        self.entry.len() + self.allowed.borsh_len()
+    }
+}
+
+/// The required accounts for an update_allowlist instruction.
+#[derive(InstructionsAccount)]
+pub struct Accounts<'a, T> {
+    #[allow(missing_docs)]
+    pub market: &'a T,
+    #[allow(missing_docs)]
+    #[cons(writable)]
+    pub allowlist: &'a T,
+}
+
+impl<'a, 'b: 'a> Accounts<'a, AccountInfo<'b>> {
+    pub(crate) fn parse(accounts: &'a [AccountInfo<'b>]) -> Result<Self, ProgramError> {
+        let accounts_iter = &mut accounts.iter();
+
+        let a = Self {
+            market: next_account_info(accounts_iter)?,
+            allowlist: next_account_info(accounts_iter)?,
+        };
+        Ok(a)
+    }
+    pub(crate) fn perform_checks(&self, program_id: &Pubkey) -> Result<(), ProgramError> {
+        check_account_owner(
+            self.market,
+            &program_id.to_bytes(),
+            AoError::WrongMarketOwner,
+        )?;
+        check_account_owner(
+            self.allowlist,
+            &program_id.to_bytes(),
+            AoError::WrongAllowlistAccount,
+        )?;
+        Ok(())
+    }
+}
+
+/// Apply the update_allowlist instruction to the provided accounts
+pub fn process(
+    program_id: &Pubkey,
+    accounts: Accounts<AccountInfo>,
+    params: Params,
+) -> ProgramResult {
+    accounts.perform_checks(program_id)?;
+
+    let market_data = accounts.market.data.borrow();
+    let market_state = MarketState::from_buffer(&market_data, AccountTag::Market)?;
+    check_account_key(
+        accounts.allowlist,
+        &market_state.order_allowlist,
+        AoError::WrongAllowlistAccount,
+    )?;
+
+    let mut allowlist_data = accounts.allowlist.data.borrow_mut();
+    let mut allowlist = Allowlist::from_buffer(&mut allowlist_data, AccountTag::Allowlist)?;
+    if params.allowed {
+        allowlist.insert(&params.entry)?;
+    } else {
+        allowlist.remove(&params.entry)?;
+    }
+
+    Ok(())
+}
@@ -0,0 +1,352 @@
+//! Deterministic replay of the instructions of a market, which rebuilds its orderbook and event queue from an ordered
+//! log of instructions.
+//!
+//! Each [`LogEntry`] holds the instruction data of an instruction which was successfully executed on the market, as
+//! built by the [`instruction`][`crate::instruction`] builders, along with the slot and unix timestamp at which it was
+//! executed. The entries are executed in order by a [`Simulator`], whose slab and event queue buffers then match the
+//! data of the market's accounts bit-for-bit. This lets a cranker which lost events reconcile its state with the
+//! market.
+//!
+//! The log must start with the create_market instruction of the market. Only the create_market, new_order,
+//! cancel_order and consume_events instructions can be replayed: a log which holds any other instruction, such as
+//! replace_order, new_order_batch, cancel_orders_by_owner, mass_cancel, the prune instructions, clean_book,
+//! trigger_orders, reprice_pegged, run_auction or cancel_by_client_id, is rejected at that entry with
+//! [`ProgramError::InvalidInstructionData`]. Replay is thus limited to markets which are only ever driven by these four
+//! instructions.
+//!
+//! This module is only available with the `simulation` feature.
+use borsh::{BorshDeserialize, BorshSerialize};
+use bytemuck::Pod;
+use num_traits::FromPrimitive;
+use solana_program::{msg, program_error::ProgramError};
+
+use crate::{
+    instruction::AgnosticOrderbookInstruction,
+    processor::{cancel_order, consume_events, create_market, new_order},
+    simulation::Simulator,
+    state::orderbook::CallbackInfo,
+};
+
+/// An instruction of a replay log, along with the slot and unix timestamp at which it was executed.
+///
+/// A log is stored as the concatenation of its Borsh serialized entries, and can be read back with [`read_log`].
+#[derive(BorshDeserialize, BorshSerialize, Clone, Debug, PartialEq)]
+pub struct LogEntry {
+    /// The slot at which the instruction was executed, which is recorded for the events it pushed.
+    pub slot: u64,
+    /// The unix timestamp at which the instruction was executed, against which order expiries are checked.
+    pub unix_timestamp: u64,
+    /// The instruction data, made of the instruction's tag followed by its Borsh serialized parameters.
+    pub instruction_data: Vec<u8>,
+}
+
+/// An instruction which can be replayed, decoded from its instruction data.
+pub enum ReplayInstruction<C> {
+    #[allow(missing_docs)]
+    CreateMarket(create_market::Params),
+    #[allow(missing_docs)]
+    NewOrder(new_order::Params<C>),
+    #[allow(missing_docs)]
+    CancelOrder(cancel_order::Params),
+    #[allow(missing_docs)]
+    ConsumeEvents(consume_events::Params),
+}
+
+impl<C: Pod + BorshDeserialize> ReplayInstruction<C> {
+    /// Decodes the instruction data of a replayable instruction.
+    pub fn unpack(instruction_data: &[u8]) -> Result<Self, ProgramError> {
+        let (tag, params) = instruction_data
+            .split_first()
+            .ok_or(ProgramError::InvalidInstructionData)?;
+        let invalid_data = |_| ProgramError::InvalidInstructionData;
+        let instruction = match FromPrimitive::from_u8(*tag) {
+            Some(AgnosticOrderbookInstruction::CreateMarket) => Self::CreateMarket(
+                create_market::Params::try_from_slice(params).map_err(invalid_data)?,
+            ),
+            Some(AgnosticOrderbookInstruction::NewOrder) => {
+                Self::NewOrder(new_order::Params::try_from_slice(params).map_err(invalid_data)?)
+            }
+            Some(AgnosticOrderbookInstruction::CancelOrder) => Self::CancelOrder(
+                cancel_order::Params::try_from_slice(params).map_err(invalid_data)?,
+            ),
+            Some(AgnosticOrderbookInstruction::ConsumeEvents) => Self::ConsumeEvents(
+                consume_events::Params::try_from_slice(params).map_err(invalid_data)?,
+            ),
+            _ => {
+                msg!("The instruction with tag {} can't be replayed!", tag);
+                return Err(ProgramError::InvalidInstructionData);
+            }
+        };
+        Ok(instruction)
+    }
+}
+
+/// Reads the entries of a replay log.
+pub fn read_log(data: &[u8]) -> Result<Vec<LogEntry>, ProgramError> {
+    let mut data = data;
+    let mut entries = vec![];
+    while !data.is_empty() {
+        entries.push(
+            LogEntry::deserialize(&mut data).map_err(|_| ProgramError::InvalidInstructionData)?,
+        );
+    }
+    Ok(entries)
+}
+
+/// The error returned when an entry of a replay log fails.
+#[derive(Debug, Clone, PartialEq)]
+pub struct ReplayError {
+    /// The index of the failed entry in the log.
+    pub entry_index: usize,
+    #[allow(missing_docs)]
+    pub error: ProgramError,
+}
+
+/// Executes the instructions of a market one after the other.
+pub struct Replayer<C> {
+    simulator: Option<Simulator<C>>,
+}
+
+impl<C: Pod + CallbackInfo + PartialEq> Default for Replayer<C>
+where
+    <C as CallbackInfo>::CallbackId: PartialEq,
+{
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<C: Pod + CallbackInfo + PartialEq> Replayer<C>
+where
+    <C as CallbackInfo>::CallbackId: PartialEq,
+{
+    /// Creates a replayer, whose first instruction must create the market.
+    pub fn new() -> Self {
+        Self { simulator: None }
+    }
+
+    /// Executes an instruction at the given slot and unix timestamp.
+    ///
+    /// The market must be created exactly once, by the first instruction. Only the main event queue of the market is
+    /// replayed, so that the consumed queue must be the main one.
+    pub fn apply(
+        &mut self,
+        slot: u64,
+        unix_timestamp: u64,
+        instruction: ReplayInstruction<C>,
+    ) -> Result<(), ProgramError> {
+        let instruction = match instruction {
+            ReplayInstruction::CreateMarket(params) => {
+                if self.simulator.is_some() {
+                    msg!("The market has already been created!");
+                    return Err(ProgramError::AccountAlreadyInitialized);
+                }
+                self.simulator = Some(Simulator::new(params)?);
+                return Ok(());
+            }
+            instruction => instruction,
+        };
+        let simulator = match self.simulator.as_mut() {
+            Some(simulator) => simulator,
+            None => {
+                msg!("The log must start with the creation of the market!");
+                return Err(ProgramError::UninitializedAccount);
+            }
+        };
+        simulator.set_slot(slot);
+        match instruction {
+            ReplayInstruction::NewOrder(params) => {
+                simulator.new_order(params, unix_timestamp)?;
+            }
+            ReplayInstruction::CancelOrder(params) => {
+                simulator.cancel_order(params.order_id)?;
+            }
+            ReplayInstruction::ConsumeEvents(params) => {
+                if params.queue_index != 0 {
+                    msg!("Only the main event queue of the market is replayed!");
+                    return Err(ProgramError::InvalidArgument);
+                }
+                let number_of_entries = std::cmp::min(
+                    simulator.event_queue().count_up_to(params.max_seq_num),
+                    params.number_of_entries_to_consume,
+                );
+                simulator.consume_events(number_of_entries);
+            }
+            ReplayInstruction::CreateMarket(_) => unreachable!(),
+        }
+        Ok(())
+    }
+
+    /// Decodes and executes an entry of a replay log.
+    pub fn apply_entry(&mut self, entry: &LogEntry) -> Result<(), ProgramError>
+    where
+        C: BorshDeserialize,
+    {
+        let instruction = ReplayInstruction::unpack(&entry.instruction_data)?;
+        self.apply(entry.slot, entry.unix_timestamp, instruction)
+    }
+
+    /// Returns the simulator which holds the replayed market, once it has been created.
+    pub fn simulator(&mut self) -> Option<&mut Simulator<C>> {
+        self.simulator.as_mut()
+    }
+
+    /// Consumes the replayer and returns the simulator which holds the replayed market, once it has been created.
+    pub fn into_simulator(self) -> Option<Simulator<C>> {
+        self.simulator
+    }
+}
+
+/// Replays the entries of a log, and returns the simulator which holds the resulting market.
+pub fn replay<C: Pod + BorshDeserialize + CallbackInfo + PartialEq>(
+    log: &[LogEntry],
+) -> Result<Simulator<C>, ReplayError>
+where
+    <C as CallbackInfo>::CallbackId: PartialEq,
+{
+    let mut replayer = Replayer::new();
+    for (entry_index, entry) in log.iter().enumerate() {
+        replayer
+            .apply_entry(entry)
+            .map_err(|error| ReplayError { entry_index, error })?;
+    }
+    replayer.into_simulator().ok_or(ReplayError {
+        entry_index: log.len(),
+        error: ProgramError::UninitializedAccount,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use solana_program::pubkey::Pubkey;
+
+    use crate::state::{
+        event_queue::EventRef, BootingPolicy, EventQueueFullPolicy, MatchingMode, PostOnlyMode,
+        SelfTradeBehavior, Side,
+    };
+
+    fn market_params() -> create_market::Params {
+        create_market::Params {
+            min_base_order_size: 10,
+            tick_size: 1,
+            min_quote_order_size: 0,
+            booting_policy: BootingPolicy::BootLeastAggressive,
+            split_fills: false,
+            log_events: false,
+            order_capacity: 100,
+            event_capacity: 100,
+            metadata: vec![],
+            quote_scale_exponent: 0,
+            max_price_deviation_bps: 0,
+            matching_mode: MatchingMode::Continuous,
+            maker_fee_bps: 0,
+            taker_fee_bps: 0,
+            max_orders_per_owner: 0,
+            consumer_program: Pubkey::default(),
+            max_events_per_consumer_call: 0,
+            event_queue_full_policy: EventQueueFullPolicy::Reject,
+        }
+    }
+
+    fn order(side: Side, max_base_qty: u64, max_ts: u64) -> ReplayInstruction<[u8; 32]> {
+        ReplayInstruction::NewOrder(new_order::Params {
+            max_base_qty,
+            max_quote_qty: u64::MAX,
+            limit_price: 10 << 32,
+            side,
+            match_limit: 10,
+            callback_info: [side as u8; 32],
+            post_only: false,
+            post_allowed: true,
+            self_trade_behavior: SelfTradeBehavior::DecrementTake,
+            max_ts,
+            immediate_or_cancel: false,
+            fill_or_kill: false,
+            max_display_qty: 0,
+            post_only_mode: PostOnlyMode::Reject,
+            max_slippage: 0,
+            flags: 0,
+            min_base_qty: 0,
+            compute_unit_reserve: 0,
+            resumable: false,
+            worst_price: 0,
+            referral: [0; 32],
+            client_order_id: 0,
+        })
+    }
+
+    fn run(replayer: &mut Replayer<[u8; 32]>) {
+        replayer
+            .apply(1, 100, ReplayInstruction::CreateMarket(market_params()))
+            .unwrap();
+        replayer.apply(2, 101, order(Side::Ask, 100, 110)).unwrap();
+        replayer
+            .apply(3, 102, order(Side::Ask, 50, u64::MAX))
+            .unwrap();
+        replayer
+            .apply(4, 103, order(Side::Bid, 30, u64::MAX))
+            .unwrap();
+        replayer
+            .apply(
+                5,
+                104,
+                ReplayInstruction::ConsumeEvents(consume_events::Params {
+                    number_of_entries_to_consume: 10,
+                    max_seq_num: u64::MAX,
+                    write_to_register: false,
+                    queue_index: 0,
+                }),
+            )
+            .unwrap();
+        // The first ask has expired by then
+        replayer
+            .apply(6, 120, order(Side::Bid, 60, u64::MAX))
+            .unwrap();
+    }
+
+    #[test]
+    fn test_replay() {
+        let (mut first, mut second) = (Replayer::new(), Replayer::new());
+        run(&mut first);
+        run(&mut second);
+        let (first, second) = (first.simulator().unwrap(), second.simulator().unwrap());
+        let (_, first_event_queue, first_bids, first_asks) = first.buffers();
+        let (_, second_event_queue, second_bids, second_asks) = second.buffers();
+        assert_eq!(first_event_queue, second_event_queue);
+        assert_eq!((first_bids, first_asks), (second_bids, second_asks));
+
+        // The expired ask is taken out of the orderbook and the bid is matched against the second one, at slot 6
+        let mut event_queue = first.event_queue();
+        assert_eq!(event_queue.len(), 3);
+        assert!(matches!(event_queue.peek_at(0), Some(EventRef::Out(_))));
+        assert!(matches!(event_queue.peek_at(1), Some(EventRef::Fill(_))));
+        assert_eq!(event_queue.push_slot_at(1), Some(6));
+        event_queue.pop_n(3);
+        assert_eq!(first.orderbook().bids.len(), 1);
+        assert!(first.orderbook().asks.is_empty());
+    }
+
+    #[test]
+    fn test_replay_order() {
+        let mut replayer = Replayer::<[u8; 32]>::new();
+        assert_eq!(
+            replayer.apply(0, 0, order(Side::Bid, 10, u64::MAX)),
+            Err(ProgramError::UninitializedAccount)
+        );
+        replayer
+            .apply(0, 0, ReplayInstruction::CreateMarket(market_params()))
+            .unwrap();
+        assert_eq!(
+            replayer.apply(0, 0, ReplayInstruction::CreateMarket(market_params())),
+            Err(ProgramError::AccountAlreadyInitialized)
+        );
+        assert!(matches!(
+            ReplayInstruction::<[u8; 32]>::unpack(
+                &[AgnosticOrderbookInstruction::RunAuction as u8]
+            ),
+            Err(ProgramError::InvalidInstructionData)
+        ));
+        assert_eq!(replay::<[u8; 32]>(&[]).err().unwrap().entry_index, 0);
+    }
+}
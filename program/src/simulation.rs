@@ -0,0 +1,232 @@
+//! A native simulator of the matching engine, which runs on plain `Vec<u8>` buffers without a Solana runtime.
+//!
+//! The [`Simulator`] goes through the same parameter checks and orderbook logic as the program's instructions, so
+//! that its slab and event queue buffers match the data of the corresponding accounts bit-for-bit. The keys stored in
+//! its market state are the default public key.
+//!
+//! This module is only available with the `simulation` feature.
+use std::marker::PhantomData;
+
+use bytemuck::Pod;
+use num_traits::FromPrimitive;
+use solana_program::{program_error::ProgramError, pubkey::Pubkey};
+
+use crate::{
+    processor::{create_market, new_order},
+    state::{
+        critbit::Slab,
+        event_queue::EventQueue,
+        market_state::MarketState,
+        orderbook::{CallbackInfo, CancelledOrder, OrderBookState, OrderSummary},
+        AccountTag, EventQueueFullPolicy, ACCOUNT_TAG_LENGTH,
+    },
+};
+
+/// A market held in memory, along with its event queue and orderbook.
+pub struct Simulator<C> {
+    market: Vec<u8>,
+    event_queue: Vec<u8>,
+    bids: Vec<u8>,
+    asks: Vec<u8>,
+    slot: u64,
+    _callback_info: PhantomData<C>,
+}
+
+impl<C: Pod + CallbackInfo + PartialEq> Simulator<C>
+where
+    <C as CallbackInfo>::CallbackId: PartialEq,
+{
+    /// Creates a market with the same parameters as a create_market instruction.
+    pub fn new(params: create_market::Params) -> Result<Self, ProgramError> {
+        let order_capacity = params.order_capacity as usize;
+        let event_capacity = params.event_capacity as usize;
+        let market_state = create_market::new_market_state(
+            params,
+            Pubkey::default(),
+            Pubkey::default(),
+            Pubkey::default(),
+            Pubkey::default(),
+        )?;
+
+        let mut market = vec![0; ACCOUNT_TAG_LENGTH + MarketState::LEN];
+        *MarketState::initialize(&mut market, AccountTag::Uninitialized)? = market_state;
+
+        let mut event_queue = vec![0; EventQueue::<C>::compute_allocation_size(event_capacity)];
+        EventQueue::<C>::from_buffer(&mut event_queue, AccountTag::Uninitialized)?;
+
+        let slab_size = Slab::<C>::compute_allocation_size(order_capacity);
+        let (mut bids, mut asks) = (vec![0; slab_size], vec![0; slab_size]);
+        Slab::<C>::initialize(&mut asks, &mut bids)?;
+
+        Ok(Self {
+            market,
+            event_queue,
+            bids,
+            asks,
+            slot: 0,
+            _callback_info: PhantomData,
+        })
+    }
+
+    /// Executes a new order at the given unix timestamp, as a new_order instruction would.
+    pub fn new_order(
+        &mut self,
+        params: new_order::Params<C>,
+        cur_ts: u64,
+    ) -> Result<OrderSummary, ProgramError> {
+        let market_state = MarketState::from_buffer_mut(&mut self.market, AccountTag::Market)?;
+        new_order::check_params(&params, market_state)?;
+
+        let mut order_book = OrderBookState::new_safe(&mut self.bids, &mut self.asks)?;
+        let mut event_queue =
+            EventQueue::from_buffer(&mut self.event_queue, AccountTag::EventQueue)?;
+        event_queue.current_slot = self.slot;
+        event_queue.log_events = market_state.log_events != 0;
+        event_queue.full_policy =
+            EventQueueFullPolicy::from_u64(market_state.event_queue_full_policy).unwrap();
+
+        Ok(order_book.new_order(params, &mut event_queue, market_state, cur_ts)?)
+    }
+
+    /// Cancels an order, as a cancel_order instruction would.
+    pub fn cancel_order(&mut self, order_id: u128) -> Result<CancelledOrder<C>, ProgramError> {
+        let market_state = MarketState::from_buffer(&self.market, AccountTag::Market)?;
+        market_state.check_can_cancel_order()?;
+
+        let mut order_book = OrderBookState::<C>::new_safe(&mut self.bids, &mut self.asks)?;
+        Ok(order_book.cancel_order(order_id, market_state)?)
+    }
+
+    /// Pops at most `number_of_entries_to_consume` events off the event queue and returns the number of popped events.
+    pub fn consume_events(&mut self, number_of_entries_to_consume: u64) -> u64 {
+        let mut event_queue = self.event_queue();
+        let number_of_entries = std::cmp::min(event_queue.len(), number_of_entries_to_consume);
+        event_queue.pop_n(number_of_entries);
+        number_of_entries
+    }
+
+    /// Sets the slot which is recorded for the events pushed from then on, which is 0 by default.
+    pub fn set_slot(&mut self, slot: u64) {
+        self.slot = slot;
+    }
+
+    /// Returns the state of the market.
+    pub fn market_state(&self) -> &MarketState {
+        MarketState::from_buffer(&self.market, AccountTag::Market).unwrap()
+    }
+
+    /// Returns the orderbook, which can be read or modified directly.
+    pub fn orderbook(&mut self) -> OrderBookState<'_, C> {
+        OrderBookState::new_safe(&mut self.bids, &mut self.asks).unwrap()
+    }
+
+    /// Returns the event queue, which can be read or modified directly.
+    pub fn event_queue(&mut self) -> EventQueue<'_, C> {
+        let mut event_queue =
+            EventQueue::from_buffer(&mut self.event_queue, AccountTag::EventQueue).unwrap();
+        event_queue.current_slot = self.slot;
+        event_queue
+    }
+
+    /// Returns the buffers of the market, event queue, bids and asks, in that order, which hold the same data as the
+    /// corresponding accounts.
+    pub fn buffers(&self) -> (&[u8], &[u8], &[u8], &[u8]) {
+        (&self.market, &self.event_queue, &self.bids, &self.asks)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::state::{
+        BootingPolicy, EventQueueFullPolicy, MatchingMode, PostOnlyMode, SelfTradeBehavior, Side,
+    };
+
+    fn market_params() -> create_market::Params {
+        create_market::Params {
+            min_base_order_size: 10,
+            tick_size: 1,
+            min_quote_order_size: 0,
+            booting_policy: BootingPolicy::BootLeastAggressive,
+            split_fills: false,
+            log_events: false,
+            order_capacity: 100,
+            event_capacity: 100,
+            metadata: vec![],
+            quote_scale_exponent: 0,
+            max_price_deviation_bps: 0,
+            matching_mode: MatchingMode::Continuous,
+            maker_fee_bps: 0,
+            taker_fee_bps: 0,
+            max_orders_per_owner: 0,
+            consumer_program: Pubkey::default(),
+            max_events_per_consumer_call: 0,
+            event_queue_full_policy: EventQueueFullPolicy::Reject,
+        }
+    }
+
+    fn order(
+        side: Side,
+        max_base_qty: u64,
+        callback_info: [u8; 32],
+    ) -> new_order::Params<[u8; 32]> {
+        new_order::Params {
+            max_base_qty,
+            max_quote_qty: u64::MAX,
+            limit_price: 10 << 32,
+            side,
+            callback_info,
+            post_only: false,
+            post_allowed: true,
+            self_trade_behavior: SelfTradeBehavior::DecrementTake,
+            match_limit: 10,
+            max_ts: u64::MAX,
+            immediate_or_cancel: false,
+            fill_or_kill: false,
+            max_display_qty: 0,
+            post_only_mode: PostOnlyMode::Reject,
+            max_slippage: 0,
+            flags: 0,
+            min_base_qty: 0,
+            compute_unit_reserve: 0,
+            resumable: false,
+            worst_price: 0,
+            referral: [0; 32],
+            client_order_id: 0,
+        }
+    }
+
+    #[test]
+    fn test_simulator() {
+        let mut simulator = Simulator::<[u8; 32]>::new(market_params()).unwrap();
+
+        let ask = simulator
+            .new_order(order(Side::Ask, 100, [1; 32]), 0)
+            .unwrap();
+        let ask_id = ask.posted_order_id.unwrap();
+        assert_eq!(simulator.orderbook().asks.len(), 1);
+
+        // The orders are checked like in the new_order instruction
+        assert!(simulator
+            .new_order(order(Side::Bid, 1, [2; 32]), 0)
+            .is_err());
+
+        let bid = simulator
+            .new_order(order(Side::Bid, 40, [2; 32]), 1)
+            .unwrap();
+        assert_eq!(bid.posted_order_id, None);
+        assert_eq!(bid.total_base_qty, 40);
+        assert_eq!(simulator.market_state().last_trade_price, 10 << 32);
+        assert_eq!(simulator.event_queue().len(), 1);
+
+        let cancelled = simulator.cancel_order(ask_id).unwrap();
+        assert_eq!(cancelled.summary.total_base_qty, 60);
+        assert_eq!(cancelled.price, 10 << 32);
+        assert_eq!(cancelled.callback_info, [1; 32]);
+        assert!(simulator.orderbook().is_empty());
+        assert!(simulator.cancel_order(ask_id).is_err());
+
+        assert_eq!(simulator.consume_events(10), 1);
+        assert_eq!(simulator.event_queue().len(), 0);
+    }
+}
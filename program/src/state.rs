@@ -1,19 +1,27 @@
 use bonfida_utils::BorshSize;
 use borsh::{BorshDeserialize, BorshSerialize};
-use bytemuck::{CheckedBitPattern, NoUninit};
+use bytemuck::{CheckedBitPattern, NoUninit, Pod};
 use num_derive::{FromPrimitive, ToPrimitive};
-use solana_program::program_error::ProgramError;
+use solana_program::{msg, program_error::ProgramError};
 use std::convert::TryFrom;
 
+use crate::error::AoError;
+
 pub use crate::state::orderbook::{OrderSummary, ORDER_SUMMARY_SIZE};
 #[cfg(feature = "no-entrypoint")]
-pub use crate::utils::get_spread;
+pub use crate::utils::{get_market_status, get_spread, MarketStatus};
 
 /// Describes the orderbook's underlying data structure, the [`Slab`].
+pub mod allowlist;
 pub mod critbit;
 pub mod event_queue;
+pub mod market_registry;
 pub mod market_state;
+pub mod merkle;
+pub mod open_orders;
 pub mod orderbook;
+pub mod pegged_orders;
+pub mod stop_orders;
 
 /// The index of the [`AccountTag`] in the account buffers.
 pub const ACCOUNT_TAG_INDEX: usize = 0;
@@ -21,6 +29,14 @@ pub const ACCOUNT_TAG_INDEX: usize = 0;
 /// The length of the [`AccountTag`] in the account buffers.
 pub const ACCOUNT_TAG_LENGTH: usize = 8;
 
+/// The index of the layout version in the account buffers, inside the padding of the [`AccountTag`].
+pub const ACCOUNT_VERSION_INDEX: usize = 1;
+
+/// The layout version of the market, event queue and slab accounts written by this program.
+///
+/// Accounts created before versioning was introduced hold a version of 0.
+pub const CURRENT_ACCOUNT_VERSION: u8 = 1;
+
 #[derive(Clone, Copy, PartialEq, Debug)]
 #[allow(missing_docs)]
 #[repr(u8)]
@@ -32,6 +48,10 @@ pub enum AccountTag {
     Bids,
     Asks,
     Disabled,
+    StopOrders,
+    PeggedOrders,
+    MarketRegistry,
+    Allowlist,
 }
 
 impl Default for AccountTag {
@@ -44,13 +64,17 @@ impl TryFrom<&[u8]> for AccountTag {
     type Error = ProgramError;
 
     fn try_from(value: &[u8]) -> Result<Self, Self::Error> {
-        match bytemuck::from_bytes::<u64>(value) {
+        match value[ACCOUNT_TAG_INDEX] {
             0 => Ok(Self::Uninitialized),
             128 => Ok(Self::Market),
             129 => Ok(Self::EventQueue),
             130 => Ok(Self::Bids),
             131 => Ok(Self::Asks),
             132 => Ok(Self::Disabled),
+            133 => Ok(Self::StopOrders),
+            134 => Ok(Self::PeggedOrders),
+            135 => Ok(Self::MarketRegistry),
+            136 => Ok(Self::Allowlist),
             _ => {
                 return Err(ProgramError::InvalidAccountData);
             }
@@ -58,6 +82,84 @@ impl TryFrom<&[u8]> for AccountTag {
     }
 }
 
+/// Writes the given tag along with the current layout version at the front of an account buffer.
+pub(crate) fn write_account_tag(buffer: &mut [u8], tag: AccountTag) {
+    buffer[ACCOUNT_TAG_INDEX..ACCOUNT_TAG_LENGTH].fill(0);
+    buffer[ACCOUNT_TAG_INDEX] = tag as u8;
+    buffer[ACCOUNT_VERSION_INDEX] = CURRENT_ACCOUNT_VERSION;
+}
+
+/// Reads the tag of an account buffer.
+pub(crate) fn read_account_tag(buffer: &[u8]) -> Result<AccountTag, ProgramError> {
+    let tag = buffer
+        .get(ACCOUNT_TAG_INDEX..ACCOUNT_TAG_LENGTH)
+        .ok_or_else(|| {
+            msg!("The account is too small to hold a tag!");
+            ProgramError::AccountDataTooSmall
+        })?;
+    AccountTag::try_from(tag)
+}
+
+/// Checks that an account buffer uses the current layout version.
+pub fn check_account_version(buffer: &[u8]) -> Result<(), ProgramError> {
+    if buffer[ACCOUNT_VERSION_INDEX] != CURRENT_ACCOUNT_VERSION {
+        msg!(
+            "The account has layout version {}, it must be migrated to version {}",
+            buffer[ACCOUNT_VERSION_INDEX],
+            CURRENT_ACCOUNT_VERSION
+        );
+        return Err(AoError::OutdatedAccountVersion.into());
+    }
+    Ok(())
+}
+
+/// Upgrades an account buffer with the given tag to the current layout version, in place.
+///
+/// Slab and event queue accounts hold callback informations of type `C`, and should have been grown beforehand to the
+/// length returned by [`compute_migrated_slab_len`][`critbit::compute_migrated_slab_len`] or
+/// [`compute_migrated_event_queue_len`][`event_queue::compute_migrated_event_queue_len`]. The type is ignored for other
+/// accounts.
+///
+/// Returns whether the account was outdated.
+pub(crate) fn migrate_account<C: Pod>(
+    buffer: &mut [u8],
+    expected_tag: AccountTag,
+) -> Result<bool, ProgramError> {
+    if read_account_tag(buffer)? != expected_tag {
+        msg!("Invalid account tag for migration!");
+        return Err(ProgramError::InvalidAccountData);
+    }
+    let mut version = buffer[ACCOUNT_VERSION_INDEX];
+    if version > CURRENT_ACCOUNT_VERSION {
+        msg!("The account has an unknown layout version {}", version);
+        return Err(ProgramError::InvalidAccountData);
+    }
+    let outdated = version < CURRENT_ACCOUNT_VERSION;
+    while version < CURRENT_ACCOUNT_VERSION {
+        match version {
+            // The version 1 slab layout has a larger header which ends with the checksum of the slab, and wider
+            // leaves which hold the hidden quantity, maximum display quantity and flags of their order, followed by
+            // the owner index and the client order ids. The version 1 event queue layout has a larger header, which
+            // holds the event sequence number and the maximum length of the queue, and records the slot at which each
+            // event was pushed. The fields added to the market layout are zeroed out when the market account is
+            // grown, and the other accounts didn't exist before versioning was introduced.
+            0 => match expected_tag {
+                AccountTag::Bids | AccountTag::Asks => {
+                    critbit::Slab::<C>::migrate_unversioned(buffer)
+                }
+                AccountTag::EventQueue => {
+                    event_queue::migrate_unversioned_event_queue(buffer, std::mem::size_of::<C>())
+                }
+                _ => {}
+            },
+            _ => unreachable!(),
+        }
+        version += 1;
+        buffer[ACCOUNT_VERSION_INDEX] = version;
+    }
+    Ok(outdated)
+}
+
 #[derive(
     BorshDeserialize,
     BorshSerialize,
@@ -73,6 +175,7 @@ impl TryFrom<&[u8]> for AccountTag {
 )]
 #[repr(u8)]
 #[allow(missing_docs)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub enum Side {
     Bid,
     Ask,
@@ -90,6 +193,7 @@ impl Side {
 
 #[derive(BorshDeserialize, BorshSerialize, Clone, PartialEq, FromPrimitive, BorshSize)]
 /// Describes what happens when two order with identical callback informations are matched together
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub enum SelfTradeBehavior {
     /// The orders are matched together
     DecrementTake,
@@ -98,6 +202,89 @@ pub enum SelfTradeBehavior {
     CancelProvide,
     /// The entire transaction fails and the program returns an error.
     AbortTransaction,
+    /// The order on the provide side is cancelled, and the remainder of the current order is cancelled as well.
+    CancelBoth,
+    /// The order with the lowest time priority, which is always the current order, is cancelled. Matching stops and
+    /// the remainder of the current order is cancelled, while the order on the provide side is left untouched.
+    CancelLowestPriority,
+}
+
+#[derive(BorshDeserialize, BorshSerialize, Clone, Copy, PartialEq, FromPrimitive, BorshSize)]
+/// Describes which best price of the orderbook a pegged order follows
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum PegReference {
+    /// The best price on the same side as the pegged order
+    SameSide,
+    /// The best price on the opposite side of the pegged order
+    OppositeSide,
+}
+
+#[derive(BorshDeserialize, BorshSerialize, Clone, Copy, PartialEq, FromPrimitive, BorshSize)]
+/// Describes what happens when a post only order would cross the spread
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum PostOnlyMode {
+    /// The order is not written into the orderbook.
+    Reject,
+    /// The order's limit price is moved to one tick away from the best opposite order, and the order is written
+    /// into the orderbook at that price.
+    Slide,
+}
+
+#[derive(BorshDeserialize, BorshSerialize, Clone, Copy, PartialEq, FromPrimitive, BorshSize)]
+/// Describes what happens when an order is posted into a full side of the orderbook
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum BootingPolicy {
+    /// The least aggressive order is cancelled if the new order is more aggressive, otherwise the new order isn't
+    /// written into the orderbook.
+    BootLeastAggressive,
+    /// The new order is rejected and the instruction fails with [`AoError::SlabOutOfSpace`][`crate::error::AoError::SlabOutOfSpace`].
+    RejectNewOrder,
+    /// The order with the smallest base quantity is cancelled if it is smaller than the new order, otherwise the new
+    /// order isn't written into the orderbook.
+    BootSmallest,
+}
+
+#[derive(BorshDeserialize, BorshSerialize, Clone, Copy, PartialEq, FromPrimitive, BorshSize)]
+/// Describes which operations are allowed on a market
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum TradingStatus {
+    /// Orders can be posted, matched and cancelled.
+    Active,
+    /// Only post only orders can be placed, and orders can be cancelled.
+    PostOnly,
+    /// Orders can only be cancelled.
+    CancelOnly,
+    /// Orders can't be placed nor cancelled. Events can still be consumed.
+    Halted,
+}
+
+#[derive(BorshDeserialize, BorshSerialize, Clone, Copy, PartialEq, FromPrimitive, BorshSize)]
+/// Describes how the orders of a market are matched
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum MatchingMode {
+    /// New orders are matched against the orderbook as they are placed.
+    Continuous,
+    /// New orders are only posted, and the crossed orders are periodically matched at a single clearing price by the
+    /// run_auction instruction. Orders which can't be posted, such as market, immediate-or-cancel or fill-or-kill
+    /// orders, are rejected.
+    Auction,
+}
+
+#[derive(BorshDeserialize, BorshSerialize, Clone, Copy, PartialEq, FromPrimitive, BorshSize)]
+/// Describes what happens when an event is pushed onto a full event queue
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum EventQueueFullPolicy {
+    /// The instruction which pushes the event fails, so that no event is ever lost.
+    Reject,
+    /// The oldest unconsumed event is dropped to make room for the new event. The sequence number of the event at the
+    /// head of the queue moves past the dropped events, so that consumers can detect them.
+    ///
+    /// Fills can be lost from the queue with this policy, so consumers must check the sequence numbers for gaps. Each
+    /// dropped event is emitted as a binary program log, in the same format as with the LogOnly policy.
+    Overwrite,
+    /// The event isn't written into the queue, and is only emitted as a binary program log made of the serialized
+    /// event followed by its maker and taker callback infos, which are zeroed out when the event has none.
+    LogOnly,
 }
 
 /// This byte flag is set for order_ids with side Bid, and unset for side Ask
@@ -111,3 +298,53 @@ pub fn get_side_from_order_id(order_id: u128) -> Side {
         Side::Ask
     }
 }
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord, Hash)]
+/// A typed view over an order id.
+///
+/// The upper 64 bits of an order id hold the order's limit price (FP32), and the lower 64 bits hold the sequence
+/// number which was assigned to the order when it was posted. For bids, the sequence number is stored negated: this
+/// sets the [`ORDER_ID_SIDE_FLAG`], and makes older bids at a given price sort after newer ones, so that the critbit
+/// trees yield price-time priority on both sides.
+pub struct OrderId(pub u128);
+
+impl OrderId {
+    /// Composes an order id from its limit price (FP32), sequence number and side.
+    pub fn new(price: u64, sequence: u64, side: Side) -> Self {
+        let lower = match side {
+            Side::Bid => !sequence,
+            Side::Ask => sequence,
+        };
+        Self(((price as u128) << 64) | (lower as u128))
+    }
+
+    /// The limit price (FP32) of the order.
+    pub fn price(&self) -> u64 {
+        (self.0 >> 64) as u64
+    }
+
+    /// The sequence number which was assigned to the order when it was posted.
+    pub fn sequence(&self) -> u64 {
+        match self.side() {
+            Side::Bid => !(self.0 as u64),
+            Side::Ask => self.0 as u64,
+        }
+    }
+
+    /// The side of the order.
+    pub fn side(&self) -> Side {
+        get_side_from_order_id(self.0)
+    }
+}
+
+impl From<u128> for OrderId {
+    fn from(order_id: u128) -> Self {
+        Self(order_id)
+    }
+}
+
+impl From<OrderId> for u128 {
+    fn from(order_id: OrderId) -> Self {
+        order_id.0
+    }
+}
@@ -0,0 +1,186 @@
+//! An allowlist restricts order placement on a permissioned market to the callback infos it lists.
+//!
+//! Each entry of the allowlist is the first [`ALLOWLIST_ENTRY_LEN`] bytes of an authorized callback info, padded with
+//! zeroes, which is typically the key of the user account of the calling program. The allowlist is registered on its
+//! market by create_allowlist, and its entries are added and removed by update_allowlist.
+use bytemuck::{Pod, Zeroable};
+use solana_program::{account_info::AccountInfo, msg, program_error::ProgramError};
+use std::mem::size_of;
+
+use super::{
+    check_account_version, read_account_tag, write_account_tag, AccountTag, ACCOUNT_TAG_LENGTH,
+};
+use crate::error::AoError;
+
+/// The length in bytes of an allowlist entry.
+pub const ALLOWLIST_ENTRY_LEN: usize = 32;
+
+#[derive(Debug, Copy, Clone, Pod, Zeroable)]
+#[repr(C)]
+/// The header of an allowlist account.
+pub struct AllowlistHeader {
+    /// The number of entries in the allowlist.
+    pub count: u64,
+}
+
+impl AllowlistHeader {
+    /// Expected size in bytes of AllowlistHeader
+    pub const LEN: usize = size_of::<Self>();
+}
+
+/// Returns the allowlist entry which authorizes the given callback info.
+pub fn allowlist_entry<C: Pod>(callback_info: &C) -> [u8; ALLOWLIST_ENTRY_LEN] {
+    let bytes = bytemuck::bytes_of(callback_info);
+    let len = bytes.len().min(ALLOWLIST_ENTRY_LEN);
+    let mut entry = [0; ALLOWLIST_ENTRY_LEN];
+    entry[..len].copy_from_slice(&bytes[..len]);
+    entry
+}
+
+/// A compact list of authorized callback infos.
+pub struct Allowlist<'a> {
+    /// The header of the allowlist.
+    pub header: &'a mut AllowlistHeader,
+    entries: &'a mut [[u8; ALLOWLIST_ENTRY_LEN]],
+}
+
+impl<'a> Allowlist<'a> {
+    /// Compute the allocation size for an allowlist of a desired capacity
+    pub fn compute_allocation_size(desired_entry_capacity: usize) -> usize {
+        ACCOUNT_TAG_LENGTH + AllowlistHeader::LEN + desired_entry_capacity * ALLOWLIST_ENTRY_LEN
+    }
+
+    /// Instantiates an allowlist object from an account's data buffer.
+    ///
+    /// An uninitialized buffer is tagged as an allowlist.
+    pub fn from_buffer(
+        buffer: &'a mut [u8],
+        expected_tag: AccountTag,
+    ) -> Result<Self, ProgramError> {
+        let tag = read_account_tag(buffer)?;
+        if tag != expected_tag {
+            msg!("Invalid account tag for allowlist!");
+            return Err(ProgramError::InvalidAccountData);
+        }
+        if buffer.len() < Self::compute_allocation_size(1) {
+            msg!("The allowlist account is too small!");
+            return Err(ProgramError::AccountDataTooSmall);
+        }
+        if tag == AccountTag::Uninitialized {
+            write_account_tag(buffer, AccountTag::Allowlist);
+        } else {
+            check_account_version(buffer)?;
+        }
+
+        let capacity =
+            (buffer.len() - ACCOUNT_TAG_LENGTH - AllowlistHeader::LEN) / ALLOWLIST_ENTRY_LEN;
+        let (header, entries) = buffer[ACCOUNT_TAG_LENGTH..].split_at_mut(AllowlistHeader::LEN);
+        Ok(Self {
+            header: bytemuck::from_bytes_mut(header),
+            entries: bytemuck::cast_slice_mut(&mut entries[..capacity * ALLOWLIST_ENTRY_LEN]),
+        })
+    }
+
+    /// The maximum number of entries which the allowlist can hold.
+    pub fn capacity(&self) -> usize {
+        self.entries.len()
+    }
+
+    /// The entries of the allowlist.
+    pub fn entries(&self) -> &[[u8; ALLOWLIST_ENTRY_LEN]] {
+        &self.entries[..self.header.count as usize]
+    }
+
+    /// Whether the allowlist authorizes the given callback info.
+    pub fn allows<C: Pod>(&self, callback_info: &C) -> bool {
+        self.entries().contains(&allowlist_entry(callback_info))
+    }
+
+    /// Appends an entry to the allowlist, unless it is already listed.
+    pub fn insert(&mut self, entry: &[u8; ALLOWLIST_ENTRY_LEN]) -> Result<(), AoError> {
+        if self.entries().contains(entry) {
+            return Ok(());
+        }
+        let count = self.header.count as usize;
+        if count == self.capacity() {
+            msg!("The allowlist can't hold more entries!");
+            return Err(AoError::AllowlistFull);
+        }
+        self.entries[count] = *entry;
+        self.header.count += 1;
+        Ok(())
+    }
+
+    /// Removes an entry from the allowlist, moving the last entry of the allowlist in its place.
+    pub fn remove(&mut self, entry: &[u8; ALLOWLIST_ENTRY_LEN]) -> Result<(), AoError> {
+        let index = self
+            .entries()
+            .iter()
+            .position(|e| e == entry)
+            .ok_or_else(|| {
+                msg!("The entry isn't in the allowlist!");
+                AoError::OrderNotAllowlisted
+            })?;
+        let last = self.header.count as usize - 1;
+        self.entries.swap(index, last);
+        self.entries[last] = [0; ALLOWLIST_ENTRY_LEN];
+        self.header.count -= 1;
+        Ok(())
+    }
+}
+
+/// Takes the next account off an account iterator if it is an allowlist, so that the allowlist can precede the event
+/// queue shards of the order placing instructions.
+pub(crate) fn next_allowlist_account<'a, 'b: 'a>(
+    accounts_iter: &mut std::slice::Iter<'a, AccountInfo<'b>>,
+) -> Option<&'a AccountInfo<'b>> {
+    let account = accounts_iter.as_slice().first()?;
+    let is_allowlist = account
+        .try_borrow_data()
+        .ok()
+        .and_then(|data| read_account_tag(&data).ok())
+        == Some(AccountTag::Allowlist);
+    if is_allowlist {
+        accounts_iter.next()
+    } else {
+        None
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_allowlist() {
+        let mut buffer = vec![0; Allowlist::compute_allocation_size(2)];
+        let mut allowlist = Allowlist::from_buffer(&mut buffer, AccountTag::Uninitialized).unwrap();
+        assert_eq!(allowlist.capacity(), 2);
+
+        let allowed = [1u8; 64];
+        let other = [2u8; 64];
+        allowlist.insert(&allowlist_entry(&allowed)).unwrap();
+        allowlist.insert(&allowlist_entry(&allowed)).unwrap();
+        assert_eq!(allowlist.entries().len(), 1);
+        assert!(allowlist.allows(&allowed));
+        assert!(!allowlist.allows(&other));
+
+        // Shorter callback infos are padded with zeroes
+        allowlist.insert(&allowlist_entry(&[3u8; 8])).unwrap();
+        assert!(allowlist.allows(&[3u8; 8]));
+        assert!(matches!(
+            allowlist.insert(&allowlist_entry(&other)),
+            Err(AoError::AllowlistFull)
+        ));
+
+        allowlist.remove(&allowlist_entry(&allowed)).unwrap();
+        assert!(!allowlist.allows(&allowed));
+        assert!(matches!(
+            allowlist.remove(&allowlist_entry(&allowed)),
+            Err(AoError::OrderNotAllowlisted)
+        ));
+
+        let allowlist = Allowlist::from_buffer(&mut buffer, AccountTag::Allowlist).unwrap();
+        assert_eq!(allowlist.entries(), &[allowlist_entry(&[3u8; 8])]);
+    }
+}
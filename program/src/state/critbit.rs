@@ -1,11 +1,17 @@
 #![allow(missing_docs)]
-use super::{ACCOUNT_TAG_INDEX, ACCOUNT_TAG_LENGTH};
+use super::{
+    check_account_version, read_account_tag, write_account_tag, ACCOUNT_TAG_INDEX,
+    ACCOUNT_TAG_LENGTH,
+};
 use crate::error::AoError;
-use crate::state::AccountTag;
+use crate::state::{
+    merkle::{self, OrderProof},
+    AccountTag, OrderId, Side,
+};
 use borsh::{BorshDeserialize, BorshSerialize};
 use bytemuck::{Pod, Zeroable};
-use solana_program::{msg, program_error::ProgramError};
-use std::convert::{TryFrom, TryInto};
+use solana_program::{hash::hashv, msg, program_error::ProgramError};
+use std::convert::TryInto;
 
 #[doc(hidden)]
 pub type IoError = std::io::Error;
@@ -23,6 +29,9 @@ pub struct SlabHeader {
 
     root_node: u32,
     pub leaf_count: u32,
+
+    /// The xor of the hashes of the leaves in the tree, which commits to the orders of the slab.
+    pub checksum: [u8; 32],
 }
 
 impl SlabHeader {
@@ -36,6 +45,9 @@ pub struct Slab<'a, C> {
     pub leaf_nodes: &'a mut [LeafNode],
     pub inner_nodes: &'a mut [InnerNode],
     pub callback_infos: &'a mut [C],
+    pub owner_links: &'a mut [OwnerLink],
+    pub owner_table: &'a mut [OwnerEntry],
+    pub client_order_ids: &'a mut [[u8; CLIENT_ORDER_ID_LEN]],
 }
 
 #[derive(Clone, Copy, Debug, PartialEq)]
@@ -51,12 +63,19 @@ pub struct Slab<'a, C> {
 ///    pub base_quantity: u64,
 ///    /// The maximum timestamp at which this order is able to be matched against.
 ///    pub max_ts: u64,
+///    /// The quantity of base asset which is not displayed in the orderbook yet (iceberg orders).
+///    pub hidden_quantity: u64,
+///    /// The maximum quantity of base asset displayed at once, or 0 for regular orders.
+///    pub max_display_quantity: u64,
+///    /// The caller-defined flags of the underlying order.
+///    pub flags: u8,
+///    pub(crate) _padding: [u8; 7],
 /// ```
-pub struct LeafNode([u8; 32]);
+pub struct LeafNode([u8; 56]);
 
 impl Default for LeafNode {
     fn default() -> Self {
-        Self([0; 32])
+        Self([0; 56])
     }
 }
 
@@ -68,6 +87,9 @@ impl LeafNode {
     const KEY_INDEX: usize = 0;
     const BASE_QUANTITY_INDEX: usize = 16;
     const MAX_TS_INDEX: usize = 24;
+    const HIDDEN_QUANTITY_INDEX: usize = 32;
+    const MAX_DISPLAY_QUANTITY_INDEX: usize = 40;
+    const FLAGS_INDEX: usize = 48;
 
     pub(crate) fn new(key: u128, base_quantity: u64, max_ts: u64) -> Self {
         let mut leaf = Self::default();
@@ -97,7 +119,39 @@ impl LeafNode {
 
     /// The maximum timestamp at which this order is able to be matched against.
     pub(crate) fn max_ts(&self) -> u64 {
-        u64::from_le_bytes(self.0[Self::MAX_TS_INDEX..Self::LEN].try_into().unwrap())
+        u64::from_le_bytes(
+            self.0[Self::MAX_TS_INDEX..Self::HIDDEN_QUANTITY_INDEX]
+                .try_into()
+                .unwrap(),
+        )
+    }
+
+    /// The quantity of base asset which is hidden from the orderbook, for iceberg orders.
+    pub(crate) fn hidden_quantity(&self) -> u64 {
+        u64::from_le_bytes(
+            self.0[Self::HIDDEN_QUANTITY_INDEX..Self::MAX_DISPLAY_QUANTITY_INDEX]
+                .try_into()
+                .unwrap(),
+        )
+    }
+
+    /// The maximum quantity of base asset displayed at once, 0 if the order isn't an iceberg order.
+    pub(crate) fn max_display_quantity(&self) -> u64 {
+        u64::from_le_bytes(
+            self.0[Self::MAX_DISPLAY_QUANTITY_INDEX..Self::FLAGS_INDEX]
+                .try_into()
+                .unwrap(),
+        )
+    }
+
+    /// The caller-defined flags of the underlying order.
+    pub(crate) fn flags(&self) -> u8 {
+        self.0[Self::FLAGS_INDEX]
+    }
+
+    /// The total quantity of base asset associated with the underlying order, hidden quantity included.
+    pub(crate) fn total_base_quantity(&self) -> u64 {
+        self.base_quantity() + self.hidden_quantity()
     }
 
     /// Sets the node's key.
@@ -114,10 +168,40 @@ impl LeafNode {
 
     /// Sets the maximum timestamp of the associated order.
     pub(crate) fn set_max_ts(&mut self, value: u64) {
-        let dst = &mut self.0[Self::MAX_TS_INDEX..Self::LEN];
+        let dst = &mut self.0[Self::MAX_TS_INDEX..Self::HIDDEN_QUANTITY_INDEX];
+        dst.copy_from_slice(&value.to_le_bytes()[..]);
+    }
+
+    /// Sets the hidden base quantity of the associated order.
+    pub(crate) fn set_hidden_quantity(&mut self, value: u64) {
+        let dst = &mut self.0[Self::HIDDEN_QUANTITY_INDEX..Self::MAX_DISPLAY_QUANTITY_INDEX];
+        dst.copy_from_slice(&value.to_le_bytes()[..]);
+    }
+
+    /// Sets the maximum displayed base quantity of the associated order.
+    pub(crate) fn set_max_display_quantity(&mut self, value: u64) {
+        let dst = &mut self.0[Self::MAX_DISPLAY_QUANTITY_INDEX..Self::FLAGS_INDEX];
         dst.copy_from_slice(&value.to_le_bytes()[..]);
     }
 
+    /// Sets the caller-defined flags of the associated order.
+    pub(crate) fn set_flags(&mut self, value: u8) {
+        self.0[Self::FLAGS_INDEX] = value;
+    }
+
+    /// Moves a new tranche of the hidden quantity to the displayed quantity of an iceberg order.
+    ///
+    /// Returns the quantity which was moved.
+    pub(crate) fn replenish(&mut self) -> u64 {
+        let refill = self.hidden_quantity().min(
+            self.max_display_quantity()
+                .saturating_sub(self.base_quantity()),
+        );
+        self.set_base_quantity(self.base_quantity() + refill);
+        self.set_hidden_quantity(self.hidden_quantity() - refill);
+        refill
+    }
+
     /// Parse a leaf node's price.
     pub fn price(&self) -> u64 {
         Self::price_from_key(self.key())
@@ -128,14 +212,47 @@ impl LeafNode {
         self.key()
     }
 
+    /// The hash of the node, which is accumulated into the checksum of its slab.
+    pub fn hash(&self) -> [u8; 32] {
+        hashv(&[&self.0]).to_bytes()
+    }
+
     /// Deduce an associated price from an order_id.
     pub(crate) fn price_from_key(key: u128) -> u64 {
-        (key >> 64) as u64
+        OrderId(key).price()
     }
 }
 
 pub type NodeHandle = u32;
 
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+/// Describes an inconsistency found by [`Slab::verify`]. The handles are reported the way they are stored in the
+/// tree, which means that inner node handles have their [`INNER_FLAG`] set.
+pub enum SlabCorruption {
+    /// A node handle points past the end of the node arrays.
+    HandleOutOfBounds(NodeHandle),
+    /// A node is the child of more than one inner node, or the tree contains a cycle.
+    NodeReachedTwice(NodeHandle),
+    /// The prefix length of an inner node doesn't exceed the prefix length of its parent.
+    InvalidPrefixLen(NodeHandle),
+    /// A node hangs on the wrong side of its parent.
+    WrongCritbit(NodeHandle),
+    /// The key of a node doesn't share the prefix of its parent.
+    PrefixMismatch(NodeHandle),
+    /// The number of leaves in the tree differs from the header's leaf count.
+    LeafCountMismatch,
+    /// A free list points past the bump index of its node array.
+    FreeListHandleOutOfBounds(NodeHandle),
+    /// A node in a free list is part of the tree, or appears twice in the free list.
+    FreeNodeInUse(NodeHandle),
+    /// The number of used and free nodes differs from the bump index.
+    BumpIndexMismatch,
+    /// The owner index doesn't list every leaf of the tree exactly once, under its callback information.
+    OwnerIndexMismatch,
+    /// The checksum in the header doesn't match the leaves in the tree.
+    ChecksumMismatch,
+}
+
 pub const INNER_FLAG: u32 = 1 << 31;
 #[derive(Clone, Copy, Debug, PartialEq)]
 #[repr(transparent)]
@@ -243,25 +360,210 @@ impl Node {
     }
 }
 
+/// The handle which ends the order lists of the owner index.
+pub const NO_LEAF: NodeHandle = u32::MAX;
+
+/// The links of a leaf in the list of the orders sharing its callback information, which make up the owner index of a
+/// slab along with the [`OwnerEntry`] table.
+#[derive(Clone, Copy, Debug, PartialEq, Pod, Zeroable)]
+#[repr(C, packed)]
+pub struct OwnerLink {
+    /// The previous leaf in the list, or [`NO_LEAF`] for the first one.
+    pub prev: NodeHandle,
+    /// The next leaf in the list, or [`NO_LEAF`] for the last one.
+    pub next: NodeHandle,
+}
+
+impl OwnerLink {
+    pub const LEN: usize = std::mem::size_of::<Self>();
+}
+
+/// An entry of the open addressing table of the owner index, which maps a callback information to the list of the
+/// orders which hold it.
+///
+/// Entries are placed by linear probing from the hash of their callback information. The table holds twice as many
+/// entries as the slab holds leaves, so that it is never more than half full.
+#[derive(Clone, Copy, Debug, PartialEq, Pod, Zeroable)]
+#[repr(C, packed)]
+pub struct OwnerEntry {
+    /// The hash of the callback information, see [`compute_owner_hash`].
+    pub hash: u64,
+    /// The first leaf of the list.
+    pub head: NodeHandle,
+    /// The number of leaves in the list, or 0 for an empty entry.
+    pub count: u32,
+}
+
+impl OwnerEntry {
+    pub const LEN: usize = std::mem::size_of::<Self>();
+}
+
+/// The number of owner table entries allocated per leaf of a slab.
+pub const OWNER_ENTRIES_PER_LEAF: usize = 2;
+
+/// Computes the hash under which the owner index of a slab files a callback information, which is its 64-bit FNV-1a
+/// hash.
+///
+/// Entries are always confirmed by comparing callback informations, so a cheap hash is enough.
+pub fn compute_owner_hash(callback_info: &[u8]) -> u64 {
+    callback_info.iter().fold(0xcbf29ce484222325, |hash, b| {
+        (hash ^ *b as u64).wrapping_mul(0x100000001b3)
+    })
+}
+
+/// Places an entry in the first empty slot of an owner table, starting from its hash.
+fn place_owner_entry(owner_table: &mut [OwnerEntry], entry: OwnerEntry) {
+    let mut i = (entry.hash % owner_table.len() as u64) as usize;
+    while owner_table[i].count != 0 {
+        i = (i + 1) % owner_table.len();
+    }
+    owner_table[i] = entry;
+}
+
+/// The length in bytes of the data which a slab account holds per leaf.
+fn compute_slab_leaf_size(callback_info_len: usize) -> usize {
+    LeafNode::LEN
+        + callback_info_len
+        + OwnerLink::LEN
+        + OWNER_ENTRIES_PER_LEAF * OwnerEntry::LEN
+        + CLIENT_ORDER_ID_LEN
+}
+
+/// Computes the lengths in bytes of the consecutive regions of a slab account with `capacity` inner nodes, which come
+/// after its header: the leaves, the inner nodes, the callback informations, the owner links, the owner table and the
+/// client order ids.
+fn compute_slab_regions(capacity: usize, callback_info_len: usize) -> [usize; 6] {
+    let leaf_count = capacity + 1;
+    [
+        leaf_count * LeafNode::LEN,
+        capacity * InnerNode::LEN,
+        leaf_count * callback_info_len,
+        leaf_count * OwnerLink::LEN,
+        leaf_count * OWNER_ENTRIES_PER_LEAF * OwnerEntry::LEN,
+        leaf_count * CLIENT_ORDER_ID_LEN,
+    ]
+}
+
+/// Computes the allocation size of a slab account (bids, asks, stop orders or pegged orders) which holds up to
+/// `order_capacity` orders with callback informations of `callback_info_len` bytes.
+pub fn compute_slab_size(order_capacity: usize, callback_info_len: usize) -> usize {
+    let leaf_size = compute_slab_leaf_size(callback_info_len);
+    ACCOUNT_TAG_LENGTH
+        + SlabHeader::LEN
+        + leaf_size
+        + (order_capacity.checked_sub(1).unwrap()) * (leaf_size + InnerNode::LEN)
+}
+
+/// The length in bytes of the client order id of a leaf.
+pub const CLIENT_ORDER_ID_LEN: usize = 8;
+
+/// The length in bytes of the leaves of the slabs created before versioning was introduced.
+pub const UNVERSIONED_LEAF_NODE_LEN: usize = 32;
+
+/// Computes the length to which the data of a slab account with the layout version `version` should be grown so that
+/// it can be migrated to the current layout version, keeping its capacity.
+pub(crate) fn compute_migrated_slab_len(
+    data_len: usize,
+    version: u8,
+    callback_info_len: usize,
+) -> usize {
+    if version != 0 {
+        return data_len;
+    }
+    // The slabs created before versioning was introduced have a header without checksum, narrower leaves and no
+    // owner index or client order ids
+    let header_len = SlabHeader::LEN - SLAB_CHECKSUM_LEN;
+    let leaf_size = UNVERSIONED_LEAF_NODE_LEN + callback_info_len;
+    let capacity =
+        (data_len - ACCOUNT_TAG_LENGTH - header_len - leaf_size) / (leaf_size + InnerNode::LEN);
+    let old_len =
+        ACCOUNT_TAG_LENGTH + header_len + leaf_size + capacity * (leaf_size + InnerNode::LEN);
+    // The bytes which don't fit another order are kept
+    compute_slab_size(capacity + 1, callback_info_len) + data_len - old_len
+}
+
+/// The length in bytes of the checksum of a slab.
+pub const SLAB_CHECKSUM_LEN: usize = 32;
+
+/// Computes the checksum of a set of leaves, which is the one held in the header of a slab whose tree holds exactly
+/// these leaves.
+///
+/// The checksum doesn't depend on the order of the leaves, so that an off-chain copy of an orderbook can be checked
+/// against the on-chain slab without replaying its history.
+pub fn compute_checksum<'a>(leaves: impl IntoIterator<Item = &'a LeafNode>) -> [u8; 32] {
+    let mut checksum = [0; SLAB_CHECKSUM_LEN];
+    for leaf in leaves {
+        xor_hash(&mut checksum, &leaf.hash());
+    }
+    checksum
+}
+
+fn xor_hash(checksum: &mut [u8; SLAB_CHECKSUM_LEN], hash: &[u8; 32]) {
+    checksum.iter_mut().zip(hash).for_each(|(c, h)| *c ^= h);
+}
+
 impl<'slab, C> Slab<'slab, C> {
     pub fn initialize(asks_data: &mut [u8], bids_data: &mut [u8]) -> Result<(), ProgramError> {
         if asks_data[ACCOUNT_TAG_INDEX] != AccountTag::Uninitialized as u8
             || bids_data[ACCOUNT_TAG_INDEX] != AccountTag::Uninitialized as u8
         {
+            msg!("The bids and asks accounts are already initialized!");
             return Err(ProgramError::AccountAlreadyInitialized);
         }
-        asks_data[ACCOUNT_TAG_INDEX] = AccountTag::Asks as u8;
-        bids_data[ACCOUNT_TAG_INDEX] = AccountTag::Bids as u8;
+        write_account_tag(asks_data, AccountTag::Asks);
+        write_account_tag(bids_data, AccountTag::Bids);
         Ok(())
     }
 
     pub fn compute_allocation_size(desired_order_capacity: usize) -> usize {
-        ACCOUNT_TAG_LENGTH
-            + SlabHeader::LEN
-            + LeafNode::LEN
-            + std::mem::size_of::<C>()
-            + (desired_order_capacity.checked_sub(1).unwrap())
-                * (LeafNode::LEN + InnerNode::LEN + std::mem::size_of::<C>())
+        compute_slab_size(desired_order_capacity, std::mem::size_of::<C>())
+    }
+
+    fn compute_capacity(buffer_len: usize) -> usize {
+        let leaf_size = compute_slab_leaf_size(std::mem::size_of::<C>());
+        (buffer_len - SlabHeader::LEN - ACCOUNT_TAG_LENGTH - leaf_size)
+            / (leaf_size + InnerNode::LEN)
+    }
+
+    /// Moves the contents of a slab buffer which has just been grown from `old_len` bytes to its current length, so
+    /// that it can be parsed again with its new capacity.
+    ///
+    /// Node handles are preserved, as well as the slab header. The owner table is rebuilt, since the slots of its
+    /// entries depend on its length.
+    pub(crate) fn grow(buffer: &mut [u8], old_len: usize) {
+        let old_regions =
+            compute_slab_regions(Self::compute_capacity(old_len), std::mem::size_of::<C>());
+        let new_regions = compute_slab_regions(
+            Self::compute_capacity(buffer.len()),
+            std::mem::size_of::<C>(),
+        );
+        let mut old_starts = [ACCOUNT_TAG_LENGTH + SlabHeader::LEN; 6];
+        let mut new_starts = old_starts;
+        for i in 1..old_starts.len() {
+            old_starts[i] = old_starts[i - 1] + old_regions[i - 1];
+            new_starts[i] = new_starts[i - 1] + new_regions[i - 1];
+        }
+        let owner_entries = bytemuck::cast_slice::<_, OwnerEntry>(
+            &buffer[old_starts[4]..old_starts[4] + old_regions[4]],
+        )
+        .iter()
+        .filter(|e| e.count != 0)
+        .copied()
+        .collect::<Vec<_>>();
+
+        // The regions are moved starting from the last one, so that none is overwritten before being moved
+        for i in (0..6).rev().filter(|i| *i != 4) {
+            buffer.copy_within(old_starts[i]..old_starts[i] + old_regions[i], new_starts[i]);
+            buffer[new_starts[i] + old_regions[i]..new_starts[i] + new_regions[i]].fill(0);
+        }
+        buffer[new_starts[4]..new_starts[5]].fill(0);
+        buffer[new_starts[5] + new_regions[5]..].fill(0);
+        let owner_table = bytemuck::cast_slice_mut::<_, OwnerEntry>(
+            &mut buffer[new_starts[4]..new_starts[4] + new_regions[4]],
+        );
+        for entry in owner_entries {
+            place_owner_entry(owner_table, entry);
+        }
     }
 }
 
@@ -270,7 +572,7 @@ impl<'a, C: Pod> Slab<'a, C> {
         buffer: &'a mut [u8],
         expected_tag: AccountTag,
     ) -> Result<Self, ProgramError> {
-        match AccountTag::try_from(&buffer[ACCOUNT_TAG_INDEX..ACCOUNT_TAG_LENGTH]) {
+        match read_account_tag(buffer) {
             Ok(a) => {
                 if a != expected_tag {
                     msg!("Invalid account tag for slab!");
@@ -281,26 +583,107 @@ impl<'a, C: Pod> Slab<'a, C> {
                 return Err(e);
             }
         };
-        let callback_info_len = std::mem::size_of::<C>();
-        let leaf_size = LeafNode::LEN + callback_info_len;
-        let capacity = (buffer.len() - SlabHeader::LEN - ACCOUNT_TAG_LENGTH - leaf_size)
-            / (leaf_size + InnerNode::LEN);
+        check_account_version(buffer)?;
+        if buffer.len() < Self::compute_allocation_size(1) {
+            msg!("The slab account is too small!");
+            return Err(ProgramError::AccountDataTooSmall);
+        }
+        Ok(Self::from_buffer_unchecked(buffer))
+    }
+
+    fn from_buffer_unchecked(buffer: &'a mut [u8]) -> Self {
+        let regions = compute_slab_regions(
+            Self::compute_capacity(buffer.len()),
+            std::mem::size_of::<C>(),
+        );
 
         let (header, rem) = buffer[ACCOUNT_TAG_LENGTH..].split_at_mut(SlabHeader::LEN);
-        let (leaves, rem) = rem.split_at_mut((capacity + 1) * LeafNode::LEN);
-        let (inner_nodes, callback_infos) = rem.split_at_mut(capacity * InnerNode::LEN);
+        let (leaves, rem) = rem.split_at_mut(regions[0]);
+        let (inner_nodes, rem) = rem.split_at_mut(regions[1]);
+        let (callback_infos, rem) = rem.split_at_mut(regions[2]);
+        let (owner_links, rem) = rem.split_at_mut(regions[3]);
+        let (owner_table, rem) = rem.split_at_mut(regions[4]);
+        // The buffer can be longer than the allocation size of its capacity
+        let client_order_ids = &mut rem[..regions[5]];
         let header = bytemuck::from_bytes_mut::<SlabHeader>(header);
 
-        Ok(Self {
+        Self {
             header,
             leaf_nodes: bytemuck::cast_slice_mut::<_, LeafNode>(leaves),
             inner_nodes: bytemuck::cast_slice_mut::<_, InnerNode>(inner_nodes),
             callback_infos: bytemuck::cast_slice_mut::<_, C>(callback_infos),
-        })
+            owner_links: bytemuck::cast_slice_mut(owner_links),
+            owner_table: bytemuck::cast_slice_mut(owner_table),
+            client_order_ids: bytemuck::cast_slice_mut(client_order_ids),
+        }
+    }
+
+    /// Moves the contents of a slab buffer created before versioning was introduced, which has just been grown to the
+    /// length returned by [`compute_migrated_slab_len`], so as to upgrade it to the current layout.
+    ///
+    /// The leaves are widened with zeroed fields, which makes their orders regular orders without flags or client
+    /// order id. The checksum and the owner index are then computed from the leaves of the tree. The capacity and the
+    /// node handles of the slab are preserved.
+    pub(crate) fn migrate_unversioned(buffer: &mut [u8]) {
+        // The grown buffer has the capacity of the original slab in the current layout
+        let capacity = Self::compute_capacity(buffer.len());
+        let callback_info_len = std::mem::size_of::<C>();
+        let old_leaves_start = ACCOUNT_TAG_LENGTH + SlabHeader::LEN - SLAB_CHECKSUM_LEN;
+        let old_inner_nodes_start = old_leaves_start + (capacity + 1) * UNVERSIONED_LEAF_NODE_LEN;
+        let old_callback_infos_start = old_inner_nodes_start + capacity * InnerNode::LEN;
+        let regions = compute_slab_regions(capacity, callback_info_len);
+        let new_leaves_start = ACCOUNT_TAG_LENGTH + SlabHeader::LEN;
+        let new_inner_nodes_start = new_leaves_start + regions[0];
+        let new_callback_infos_start = new_inner_nodes_start + regions[1];
+
+        // The nodes are moved starting from the last ones, so that none is overwritten before being moved
+        buffer.copy_within(
+            old_callback_infos_start..old_callback_infos_start + regions[2],
+            new_callback_infos_start,
+        );
+        buffer.copy_within(
+            old_inner_nodes_start..old_callback_infos_start,
+            new_inner_nodes_start,
+        );
+        for handle in (0..capacity + 1).rev() {
+            let old_start = old_leaves_start + handle * UNVERSIONED_LEAF_NODE_LEN;
+            let new_start = new_leaves_start + handle * LeafNode::LEN;
+            buffer.copy_within(old_start..old_start + UNVERSIONED_LEAF_NODE_LEN, new_start);
+            buffer[new_start + UNVERSIONED_LEAF_NODE_LEN..new_start + LeafNode::LEN].fill(0);
+        }
+        buffer[new_callback_infos_start + regions[2]..].fill(0);
+
+        let mut slab = Slab::<C>::from_buffer_unchecked(buffer);
+        let handles = slab.iter_handles(true).collect::<Vec<_>>();
+        slab.header.checksum =
+            compute_checksum(handles.iter().map(|h| &slab.leaf_nodes[*h as usize]));
+        for h in handles {
+            slab.link_owner(h);
+        }
     }
 }
 
-impl<'a, C> Slab<'a, C> {
+impl<'a, C: Pod> Slab<'a, C> {
+    /// Returns the maximum number of orders which the Slab can hold.
+    pub fn capacity(&self) -> u64 {
+        self.leaf_nodes.len() as u64
+    }
+
+    /// Returns the number of orders currently in the Slab.
+    pub fn len(&self) -> u64 {
+        self.header.leaf_count as u64
+    }
+
+    /// Checks whether the Slab currently holds no orders.
+    pub fn is_empty(&self) -> bool {
+        self.header.leaf_count == 0
+    }
+
+    /// Returns the number of orders which can still be inserted into the Slab.
+    pub fn free_nodes(&self) -> u64 {
+        self.capacity() - self.len()
+    }
+
     pub fn root(&self) -> Option<NodeHandle> {
         if self.header.leaf_count == 0 {
             None
@@ -328,6 +711,7 @@ impl<'a, C> Slab<'a, C> {
     }
 
     pub(crate) fn free_leaf(&mut self, handle: NodeHandle) {
+        self.unlink_owner(handle);
         if self.header.leaf_free_list_len != 0 {
             let next = self.header.leaf_free_list_head;
             self.leaf_nodes[handle as usize].set_base_quantity(next as u64);
@@ -335,6 +719,7 @@ impl<'a, C> Slab<'a, C> {
 
         self.header.leaf_free_list_len += 1;
         self.header.leaf_free_list_head = handle;
+        self.client_order_ids[handle as usize] = [0; CLIENT_ORDER_ID_LEN];
     }
 
     pub(crate) fn allocate_inner_node(&mut self) -> Result<NodeHandle, IoError> {
@@ -366,16 +751,22 @@ impl<'a, C> Slab<'a, C> {
         self.header.inner_node_free_list_head = !handle;
     }
 
+    /// Inserts a leaf into the tree along with its callback information, and returns its handle. When a leaf with the
+    /// same key is already in the tree, it is overwritten and returned.
     pub(crate) fn insert_leaf(
         &mut self,
         new_leaf: &LeafNode,
+        callback_info: &C,
     ) -> Result<(NodeHandle, Option<LeafNode>), AoError> {
         let mut root: NodeHandle = if self.header.leaf_count == 0 {
             // create a new root if none exists
             let new_leaf_handle = self.allocate_leaf().map_err(|_| AoError::SlabOutOfSpace)?;
             self.leaf_nodes[new_leaf_handle as usize] = *new_leaf;
+            self.callback_infos[new_leaf_handle as usize] = *callback_info;
+            self.link_owner(new_leaf_handle);
             self.header.root_node = new_leaf_handle;
             self.header.leaf_count += 1;
+            self.toggle_checksum(new_leaf);
             return Ok((new_leaf_handle, None));
         } else {
             self.header.root_node
@@ -404,6 +795,9 @@ impl<'a, C> Slab<'a, C> {
                         // clobber the existing leaf
                         let leaf_copy = *root_node;
                         *root_node = *new_leaf;
+                        self.toggle_checksum(&leaf_copy);
+                        self.toggle_checksum(new_leaf);
+                        self.set_callback_info(root, callback_info);
                         return Ok((root, Some(leaf_copy)));
                     }
                     let shared_prefix_len: u32 = (root_node.key() ^ new_leaf.key()).leading_zeros();
@@ -419,6 +813,8 @@ impl<'a, C> Slab<'a, C> {
 
             let new_leaf_handle = self.allocate_leaf().map_err(|_| AoError::SlabOutOfSpace)?;
             self.leaf_nodes[new_leaf_handle as usize] = *new_leaf;
+            self.callback_infos[new_leaf_handle as usize] = *callback_info;
+            self.link_owner(new_leaf_handle);
 
             let new_root_node_handle = self.allocate_inner_node().unwrap();
             let new_root_node = &mut self.inner_nodes[(!new_root_node_handle) as usize];
@@ -434,6 +830,7 @@ impl<'a, C> Slab<'a, C> {
                 self.header.root_node = new_root_node_handle;
             }
             self.header.leaf_count += 1;
+            self.toggle_checksum(new_leaf);
             return Ok((new_leaf_handle, None));
         }
     }
@@ -443,9 +840,169 @@ impl<'a, C> Slab<'a, C> {
         &self.callback_infos[leaf_handle as usize]
     }
 
+    /// Replaces the callback information of a leaf, and moves the leaf to the order list of its new owner.
+    pub fn set_callback_info(&mut self, leaf_handle: NodeHandle, callback_info: &C) {
+        self.unlink_owner(leaf_handle);
+        self.callback_infos[leaf_handle as usize] = *callback_info;
+        self.link_owner(leaf_handle);
+    }
+
+    /// Returns the number of orders of the slab which hold the given callback information, using the owner index.
+    pub fn count_owner_orders(&self, callback_info: &C) -> u64 {
+        self.find_owner_entry(callback_info)
+            .map_or(0, |i| self.owner_table[i].count as u64)
+    }
+
+    /// Returns an iterator over the handles of the orders of the slab which hold the given callback information, using
+    /// the owner index. The most recently inserted orders come first.
+    pub fn iter_owner_handles(&self, callback_info: &C) -> impl Iterator<Item = NodeHandle> + '_ {
+        let head = self
+            .find_owner_entry(callback_info)
+            .ok()
+            .map(|i| self.owner_table[i].head);
+        std::iter::successors(head, move |h| {
+            Some(self.owner_links[*h as usize].next).filter(|n| *n != NO_LEAF)
+        })
+    }
+
+    /// Looks up the slot of the owner table which holds the given callback information, or else the empty slot where it
+    /// would be placed.
+    fn find_owner_entry(&self, callback_info: &C) -> Result<usize, usize> {
+        let hash = compute_owner_hash(bytemuck::bytes_of(callback_info));
+        let mut i = (hash % self.owner_table.len() as u64) as usize;
+        loop {
+            let entry = self.owner_table[i];
+            if entry.count == 0 {
+                return Err(i);
+            }
+            if entry.hash == hash
+                && bytemuck::bytes_of(&self.callback_infos[entry.head as usize])
+                    == bytemuck::bytes_of(callback_info)
+            {
+                return Ok(i);
+            }
+            i = (i + 1) % self.owner_table.len();
+        }
+    }
+
+    /// Adds a leaf whose callback information has been written to the front of the order list of its owner.
+    fn link_owner(&mut self, leaf_handle: NodeHandle) {
+        let callback_info = self.callback_infos[leaf_handle as usize];
+        match self.find_owner_entry(&callback_info) {
+            Ok(i) => {
+                let head = self.owner_table[i].head;
+                self.owner_links[head as usize].prev = leaf_handle;
+                self.owner_links[leaf_handle as usize] = OwnerLink {
+                    prev: NO_LEAF,
+                    next: head,
+                };
+                self.owner_table[i].head = leaf_handle;
+                self.owner_table[i].count += 1;
+            }
+            Err(i) => {
+                self.owner_links[leaf_handle as usize] = OwnerLink {
+                    prev: NO_LEAF,
+                    next: NO_LEAF,
+                };
+                self.owner_table[i] = OwnerEntry {
+                    hash: compute_owner_hash(bytemuck::bytes_of(&callback_info)),
+                    head: leaf_handle,
+                    count: 1,
+                };
+            }
+        }
+    }
+
+    /// Removes a leaf from the order list of its owner.
+    fn unlink_owner(&mut self, leaf_handle: NodeHandle) {
+        let callback_info = self.callback_infos[leaf_handle as usize];
+        let i = self.find_owner_entry(&callback_info).unwrap();
+        let OwnerLink { prev, next } = self.owner_links[leaf_handle as usize];
+        if prev == NO_LEAF {
+            self.owner_table[i].head = next;
+        } else {
+            self.owner_links[prev as usize].next = next;
+        }
+        if next != NO_LEAF {
+            self.owner_links[next as usize].prev = prev;
+        }
+        self.owner_table[i].count -= 1;
+        if self.owner_table[i].count == 0 {
+            self.remove_owner_entry(i);
+        }
+    }
+
+    /// Empties a slot of the owner table, and shifts back the entries which were placed after it by linear probing.
+    fn remove_owner_entry(&mut self, mut hole: usize) {
+        let len = self.owner_table.len();
+        let mut i = hole;
+        loop {
+            i = (i + 1) % len;
+            let entry = self.owner_table[i];
+            if entry.count == 0 {
+                break;
+            }
+            // The entry can fill the hole if the hole lies between its ideal slot and its current slot
+            let ideal = (entry.hash % len as u64) as usize;
+            if (i + len - ideal) % len >= (i + len - hole) % len {
+                self.owner_table[hole] = entry;
+                hole = i;
+            }
+        }
+        self.owner_table[hole] = OwnerEntry::zeroed();
+    }
+
+    /// The client order id of a leaf, or 0 if the order was placed without one.
     #[inline(always)]
-    pub fn get_callback_info_mut(&mut self, leaf_handle: NodeHandle) -> &mut C {
-        &mut self.callback_infos[leaf_handle as usize]
+    pub fn get_client_order_id(&self, leaf_handle: NodeHandle) -> u64 {
+        u64::from_le_bytes(self.client_order_ids[leaf_handle as usize])
+    }
+
+    #[inline(always)]
+    pub fn set_client_order_id(&mut self, leaf_handle: NodeHandle, client_order_id: u64) {
+        self.client_order_ids[leaf_handle as usize] = client_order_id.to_le_bytes();
+    }
+
+    /// Adds a leaf to the checksum of the slab if it is absent from it, and removes it otherwise.
+    fn toggle_checksum(&mut self, leaf: &LeafNode) {
+        xor_hash(&mut self.header.checksum, &leaf.hash());
+    }
+
+    /// Applies an in-place update to a leaf of the tree and keeps the checksum of the slab up to date.
+    ///
+    /// The update must not change the key of the leaf.
+    pub(crate) fn update_leaf<R>(
+        &mut self,
+        leaf_handle: NodeHandle,
+        update: impl FnOnce(&mut LeafNode) -> R,
+    ) -> R {
+        let old_leaf = self.leaf_nodes[leaf_handle as usize];
+        let result = update(&mut self.leaf_nodes[leaf_handle as usize]);
+        let new_leaf = self.leaf_nodes[leaf_handle as usize];
+        debug_assert_eq!(old_leaf.key(), new_leaf.key());
+        self.toggle_checksum(&old_leaf);
+        self.toggle_checksum(&new_leaf);
+        result
+    }
+
+    /// Computes the checksum of the leaves in the tree from scratch.
+    pub fn compute_checksum(&self) -> [u8; 32] {
+        compute_checksum(self.iter(true))
+    }
+
+    /// Checks that the checksum in the header of the slab matches the leaves in the tree.
+    pub fn verify_checksum(&self) -> bool {
+        self.header.checksum == self.compute_checksum()
+    }
+
+    /// Computes the Merkle root over the price levels of the slab, see [`merkle`][`crate::state::merkle`].
+    pub fn compute_merkle_root(&self) -> [u8; 32] {
+        merkle::compute_book_root(self.iter(true))
+    }
+
+    /// Builds the proof that an order rests in the slab, which holds against the Merkle root of the slab.
+    pub fn prove_order(&self, order_id: u128) -> Option<OrderProof> {
+        merkle::prove_order(self.iter(true), order_id)
     }
 
     pub fn remove_by_key(&mut self, search_key: u128) -> Option<(LeafNode, &C)> {
@@ -481,6 +1038,7 @@ impl<'a, C> Slab<'a, C> {
 
             self.header.root_node = 0;
             self.header.leaf_count = 0;
+            self.toggle_checksum(&leaf_copy);
             return Some((leaf_copy, self.get_callback_info(parent_h)));
         }
         loop {
@@ -522,6 +1080,7 @@ impl<'a, C> Slab<'a, C> {
         let removed_leaf = self.leaf_nodes[child_h as usize];
         self.free_leaf(child_h);
         self.free_inner_node(parent_h);
+        self.toggle_checksum(&removed_leaf);
         Some((removed_leaf, self.get_callback_info(child_h)))
     }
 
@@ -564,9 +1123,55 @@ impl<'a, C> Slab<'a, C> {
         }
     }
 
+    /// Get a price ascending or price descending iterator over the handles of all the Slab's orders, without consuming the Slab
+    pub fn iter_handles(&self, price_ascending: bool) -> SlabHandleIterator<'_, 'a, C> {
+        SlabHandleIterator {
+            search_stack: if self.header.leaf_count == 0 {
+                vec![]
+            } else {
+                vec![self.header.root_node]
+            },
+            slab: self,
+            ascending: price_ascending,
+        }
+    }
+
+    /// Get a price ascending or price descending iterator over all the Slab's orders, without consuming the Slab.
+    ///
+    /// Orders are yielded in key order, which is price-time priority order when iterating over asks in ascending order
+    /// or over bids in descending order.
+    pub fn iter(&self, price_ascending: bool) -> impl Iterator<Item = &LeafNode> + '_ {
+        self.iter_handles(price_ascending)
+            .map(move |h| &self.leaf_nodes[h as usize])
+    }
+
+    /// Aggregates the visible quantities of the Slab's orders by price level, for at most `levels` price levels
+    /// starting from the best price.
+    ///
+    /// Returns a vector of `(price, base_quantity)` tuples, where prices are understood as 32-bit fixed point numbers.
+    /// The `side` parameter describes whether the Slab holds bids or asks.
+    pub fn get_depth(&self, levels: usize, side: Side) -> Vec<(u64, u64)> {
+        let mut depth: Vec<(u64, u64)> = Vec::with_capacity(levels);
+        for leaf in self.iter(side == Side::Ask) {
+            let price = leaf.price();
+            match depth.last_mut() {
+                Some((last_price, qty)) if *last_price == price => {
+                    *qty = qty.saturating_add(leaf.base_quantity());
+                }
+                _ => {
+                    if depth.len() == levels {
+                        break;
+                    }
+                    depth.push((price, leaf.base_quantity()));
+                }
+            }
+        }
+        depth
+    }
+
     #[cfg(feature = "utils")]
     /// Get the current critbit's depth. Walks though the entire tree.
-    pub fn get_depth(&self) -> usize {
+    pub fn get_tree_depth(&self) -> usize {
         if self.header.leaf_count == 0 {
             return 0;
         }
@@ -576,8 +1181,8 @@ impl<'a, C> Slab<'a, C> {
             match Node::from_handle(current_node) {
                 Node::Inner => {
                     let node = self.inner_nodes[(!current_node) as usize];
-                    stack.push((node.children[0], current_depth + 1));
-                    stack.push((node.children[1], current_depth + 1));
+                    stack.push((node.children()[0], current_depth + 1));
+                    stack.push((node.children()[1], current_depth + 1));
                 }
                 Node::Leaf => max_depth = std::cmp::max(current_depth, max_depth),
             }
@@ -603,8 +1208,146 @@ impl<'a, C> Slab<'a, C> {
         }
     }
 
+    /// Walks the entire tree and its free lists, and reports the first inconsistency found.
+    ///
+    /// This checks that every node is reachable at most once, that the key prefixes of the nodes match the branches
+    /// leading to them, that the leaf count matches the header, that the free lists only hold unused nodes, and that the
+    /// owner index lists every leaf of the tree.
+    pub fn verify(&self) -> Result<(), SlabCorruption> {
+        let mut seen_leaves = vec![false; self.leaf_nodes.len()];
+        let mut seen_inner_nodes = vec![false; self.inner_nodes.len()];
+        let mut leaf_count = 0u64;
+        let mut inner_node_count = 0u64;
+        // Each node is stacked along with the prefix length, key and critbit of the branch leading to it
+        let mut stack = self
+            .root()
+            .map(|root| (root, None))
+            .into_iter()
+            .collect::<Vec<(NodeHandle, Option<(u64, u128, bool)>)>>();
+        while let Some((h, branch)) = stack.pop() {
+            let key = match Node::from_handle(h) {
+                Node::Leaf => {
+                    let seen = seen_leaves
+                        .get_mut(h as usize)
+                        .ok_or(SlabCorruption::HandleOutOfBounds(h))?;
+                    if *seen {
+                        return Err(SlabCorruption::NodeReachedTwice(h));
+                    }
+                    *seen = true;
+                    leaf_count += 1;
+                    self.leaf_nodes[h as usize].key()
+                }
+                Node::Inner => {
+                    let seen = seen_inner_nodes
+                        .get_mut((!h) as usize)
+                        .ok_or(SlabCorruption::HandleOutOfBounds(h))?;
+                    if *seen {
+                        return Err(SlabCorruption::NodeReachedTwice(h));
+                    }
+                    *seen = true;
+                    inner_node_count += 1;
+                    let node = &self.inner_nodes[(!h) as usize];
+                    if node.prefix_len() >= 128
+                        || matches!(branch, Some((p, _, _)) if node.prefix_len() <= p)
+                    {
+                        return Err(SlabCorruption::InvalidPrefixLen(h));
+                    }
+                    let children = node.children();
+                    stack.push((children[0], Some((node.prefix_len(), node.key(), false))));
+                    stack.push((children[1], Some((node.prefix_len(), node.key(), true))));
+                    node.key()
+                }
+            };
+            if let Some((prefix_len, prefix, critbit)) = branch {
+                if (key & ((1u128 << 127) >> prefix_len) != 0) != critbit {
+                    return Err(SlabCorruption::WrongCritbit(h));
+                }
+                let prefix_mask = (((((1u128) << 127) as i128) >> prefix_len) as u128) << 1;
+                if (key ^ prefix) & prefix_mask != 0 {
+                    return Err(SlabCorruption::PrefixMismatch(h));
+                }
+            }
+        }
+        if leaf_count != self.header.leaf_count as u64 {
+            return Err(SlabCorruption::LeafCountMismatch);
+        }
+        let in_tree = seen_leaves.clone();
+
+        // Free nodes are marked as seen as well, which also catches loops in the free lists
+        let mut h = self.header.leaf_free_list_head;
+        for _ in 0..self.header.leaf_free_list_len {
+            if h >= self.header.leaf_bump_index || h as usize >= self.leaf_nodes.len() {
+                return Err(SlabCorruption::FreeListHandleOutOfBounds(h));
+            }
+            if seen_leaves[h as usize] {
+                return Err(SlabCorruption::FreeNodeInUse(h));
+            }
+            seen_leaves[h as usize] = true;
+            h = self.leaf_nodes[h as usize].base_quantity() as u32;
+        }
+        let mut h = self.header.inner_node_free_list_head;
+        for _ in 0..self.header.inner_node_free_list_len {
+            if h >= self.header.inner_node_bump_index || h as usize >= self.inner_nodes.len() {
+                return Err(SlabCorruption::FreeListHandleOutOfBounds(!h));
+            }
+            if seen_inner_nodes[h as usize] {
+                return Err(SlabCorruption::FreeNodeInUse(!h));
+            }
+            seen_inner_nodes[h as usize] = true;
+            h = self.inner_nodes[h as usize].prefix_len() as u32;
+        }
+        if leaf_count + self.header.leaf_free_list_len as u64 != self.header.leaf_bump_index as u64
+            || inner_node_count + self.header.inner_node_free_list_len as u64
+                != self.header.inner_node_bump_index as u64
+        {
+            return Err(SlabCorruption::BumpIndexMismatch);
+        }
+
+        let mut listed = vec![false; self.leaf_nodes.len()];
+        let mut listed_count = 0u64;
+        for (i, entry) in self.owner_table.iter().enumerate() {
+            if entry.count == 0 {
+                continue;
+            }
+            let callback_info = self
+                .callback_infos
+                .get(entry.head as usize)
+                .ok_or(SlabCorruption::OwnerIndexMismatch)?;
+            if self.find_owner_entry(callback_info) != Ok(i) {
+                return Err(SlabCorruption::OwnerIndexMismatch);
+            }
+            let mut prev = NO_LEAF;
+            let mut h = entry.head;
+            for _ in 0..entry.count {
+                if !in_tree.get(h as usize).copied().unwrap_or(false)
+                    || listed[h as usize]
+                    || self.owner_links[h as usize].prev != prev
+                    || bytemuck::bytes_of(&self.callback_infos[h as usize])
+                        != bytemuck::bytes_of(callback_info)
+                {
+                    return Err(SlabCorruption::OwnerIndexMismatch);
+                }
+                listed[h as usize] = true;
+                listed_count += 1;
+                prev = h;
+                h = self.owner_links[h as usize].next;
+            }
+            if h != NO_LEAF {
+                return Err(SlabCorruption::OwnerIndexMismatch);
+            }
+        }
+        if listed_count != leaf_count {
+            return Err(SlabCorruption::OwnerIndexMismatch);
+        }
+        if !self.verify_checksum() {
+            return Err(SlabCorruption::ChecksumMismatch);
+        }
+        Ok(())
+    }
+
     #[cfg(test)]
     fn check_invariants(&self) {
+        assert_eq!(self.verify(), Ok(()));
         // first check the live tree contents
         let mut leaf_count = 0;
         let mut inner_node_count = 0;
@@ -699,12 +1442,50 @@ impl<'a, C> Slab<'a, C> {
             self.header.leaf_bump_index as u64
         );
         assert_eq!(leaf_count, self.header.leaf_count as u64);
+
+        // then check that the free lists are disjoint from the live tree
+        let mut seen_leaves = vec![false; self.leaf_nodes.len()];
+        let mut seen_inner_nodes = vec![false; self.inner_nodes.len()];
+        let mut stack = self.root().into_iter().collect::<Vec<_>>();
+        while let Some(h) = stack.pop() {
+            match Node::from_handle(h) {
+                Node::Leaf => {
+                    assert!(!seen_leaves[h as usize]);
+                    seen_leaves[h as usize] = true;
+                }
+                Node::Inner => {
+                    assert!(!seen_inner_nodes[(!h) as usize]);
+                    seen_inner_nodes[(!h) as usize] = true;
+                    stack.extend_from_slice(&self.inner_nodes[(!h) as usize].children());
+                }
+            }
+        }
+        let mut h = self.header.leaf_free_list_head;
+        for _ in 0..self.header.leaf_free_list_len {
+            assert!(h < self.header.leaf_bump_index);
+            // Free nodes are marked as seen as well, which also catches loops in the free lists
+            assert!(!seen_leaves[h as usize]);
+            seen_leaves[h as usize] = true;
+            h = self.leaf_nodes[h as usize].base_quantity() as u32;
+        }
+        let mut h = self.header.inner_node_free_list_head;
+        for _ in 0..self.header.inner_node_free_list_len {
+            assert!(h < self.header.inner_node_bump_index);
+            assert!(!seen_inner_nodes[h as usize]);
+            seen_inner_nodes[h as usize] = true;
+            h = self.inner_nodes[h as usize].prefix_len() as u32;
+        }
+
+        // finally check the in-order traversal
+        let keys = self.iter(true).map(|l| l.key()).collect::<Vec<_>>();
+        assert_eq!(keys.len() as u64, leaf_count);
+        assert!(keys.windows(2).all(|w| w[0] < w[1]));
+        assert!(self.iter(false).map(|l| l.key()).eq(keys.into_iter().rev()));
     }
 
     /////////////////////////////////////////
     // Misc
 
-    #[cfg(any(test, feature = "utils"))]
     pub fn find_by_key(&self, search_key: u128) -> Option<NodeHandle> {
         let mut node_handle: NodeHandle = self.root()?;
         loop {
@@ -730,10 +1511,10 @@ impl<'a, C> Slab<'a, C> {
     }
 }
 
-impl<'queue, C: Clone> Slab<'queue, C> {
+impl<'queue, C: Pod> Slab<'queue, C> {
     #[cfg(test)]
     fn traverse(&self) -> Vec<(LeafNode, C)> {
-        fn walk_rec<'a, C: Clone>(
+        fn walk_rec<'a, C: Pod>(
             slab: &Slab<'a, C>,
             sub_root: NodeHandle,
             buf: &mut Vec<(LeafNode, C)>,
@@ -789,6 +1570,129 @@ impl<'a, C: Pod> Iterator for SlabIterator<'a, C> {
     }
 }
 
+pub struct SlabHandleIterator<'s, 'a, C> {
+    slab: &'s Slab<'a, C>,
+    search_stack: Vec<u32>,
+    ascending: bool,
+}
+
+impl<'s, 'a, C> Iterator for SlabHandleIterator<'s, 'a, C> {
+    type Item = NodeHandle;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        while let Some(current) = self.search_stack.pop() {
+            match Node::from_handle(current) {
+                Node::Inner => {
+                    let n = &self.slab.inner_nodes[(!current) as usize];
+                    let children = n.children();
+                    self.search_stack.push(children[self.ascending as usize]);
+                    self.search_stack.push(children[!self.ascending as usize]);
+                }
+                Node::Leaf => return Some(current),
+            }
+        }
+        None
+    }
+}
+
+/// Reads the header of a slab account's data without needing a mutable borrow of the data.
+pub fn read_slab_header(
+    buffer: &[u8],
+    expected_tag: AccountTag,
+) -> Result<SlabHeader, ProgramError> {
+    if read_account_tag(buffer)? != expected_tag {
+        msg!("Invalid account tag for slab!");
+        return Err(ProgramError::InvalidAccountData);
+    }
+    check_account_version(buffer)?;
+    if buffer.len() < ACCOUNT_TAG_LENGTH + SlabHeader::LEN {
+        msg!("The slab account is too small!");
+        return Err(ProgramError::AccountDataTooSmall);
+    }
+    Ok(bytemuck::pod_read_unaligned::<SlabHeader>(
+        &buffer[ACCOUNT_TAG_LENGTH..ACCOUNT_TAG_LENGTH + SlabHeader::LEN],
+    ))
+}
+
+/// Iterates over the orders of a slab account's data, along with their callback infos of `callback_info_len` bytes,
+/// without needing a mutable borrow of the data or knowing the callback info type.
+///
+/// Orders are yielded in key order, as with [`Slab::iter`].
+pub fn iter_slab_orders(
+    buffer: &[u8],
+    expected_tag: AccountTag,
+    callback_info_len: usize,
+    price_ascending: bool,
+) -> Result<impl Iterator<Item = (&LeafNode, &[u8])>, ProgramError> {
+    let header = read_slab_header(buffer, expected_tag)?;
+    let leaf_size = compute_slab_leaf_size(callback_info_len);
+    let capacity = buffer
+        .len()
+        .checked_sub(SlabHeader::LEN + ACCOUNT_TAG_LENGTH + leaf_size)
+        .ok_or(ProgramError::InvalidAccountData)?
+        / (leaf_size + InnerNode::LEN);
+
+    let rem = &buffer[ACCOUNT_TAG_LENGTH + SlabHeader::LEN..];
+    let (leaves, rem) = rem.split_at((capacity + 1) * LeafNode::LEN);
+    let (inner_nodes, callback_infos) = rem.split_at(capacity * InnerNode::LEN);
+    let leaves = bytemuck::cast_slice::<_, LeafNode>(leaves);
+    let inner_nodes = bytemuck::cast_slice::<_, InnerNode>(inner_nodes);
+
+    let mut search_stack = if header.leaf_count == 0 {
+        vec![]
+    } else {
+        vec![header.root_node]
+    };
+    Ok(std::iter::from_fn(move || {
+        while let Some(current) = search_stack.pop() {
+            match Node::from_handle(current) {
+                Node::Inner => {
+                    let children = inner_nodes[(!current) as usize].children();
+                    search_stack.push(children[price_ascending as usize]);
+                    search_stack.push(children[!price_ascending as usize]);
+                }
+                Node::Leaf => {
+                    let offset = current as usize * callback_info_len;
+                    return Some((
+                        &leaves[current as usize],
+                        &callback_infos[offset..offset + callback_info_len],
+                    ));
+                }
+            }
+        }
+        None
+    }))
+}
+
+/// Reads the orders of a slab account's data, along with their callback infos of `callback_info_len` bytes, without
+/// needing a mutable borrow of the data or knowing the callback info type.
+///
+/// Orders are returned in key order, as with [`Slab::iter`].
+pub fn read_slab_orders(
+    buffer: &[u8],
+    expected_tag: AccountTag,
+    callback_info_len: usize,
+    price_ascending: bool,
+) -> Result<Vec<(LeafNode, &[u8])>, ProgramError> {
+    Ok(
+        iter_slab_orders(buffer, expected_tag, callback_info_len, price_ascending)?
+            .map(|(leaf, callback_info)| (*leaf, callback_info))
+            .collect(),
+    )
+}
+
+/// Checks that the checksum in the header of a slab account's data matches the orders it holds, so that a fetched
+/// snapshot of the account can be verified without replaying the history of the slab.
+pub fn verify_slab_checksum(
+    buffer: &[u8],
+    expected_tag: AccountTag,
+    callback_info_len: usize,
+) -> Result<bool, ProgramError> {
+    let header = read_slab_header(buffer, expected_tag)?;
+    let orders = iter_slab_orders(buffer, expected_tag, callback_info_len, true)?;
+    Ok(header.checksum == compute_checksum(orders.map(|(leaf, _)| leaf)))
+}
+
 /////////////////////////////////////
 // Tests
 
@@ -796,6 +1700,7 @@ impl<'a, C: Pod> Iterator for SlabIterator<'a, C> {
 mod tests {
     use super::*;
     use crate::state::orderbook::CallbackInfo;
+    use crate::state::{migrate_account, ACCOUNT_VERSION_INDEX};
     use rand::prelude::*;
     use solana_program::pubkey::Pubkey;
 
@@ -819,7 +1724,7 @@ mod tests {
 
         for trial in 0..10u64 {
             let mut bytes = vec![0u8; Slab::<[u8; 32]>::compute_allocation_size(10_000)];
-            bytes[0] = AccountTag::Asks as u8;
+            write_account_tag(&mut bytes, AccountTag::Asks);
             let mut slab = Slab::from_buffer(&mut bytes, AccountTag::Asks).unwrap();
 
             let mut model: BTreeMap<u128, (LeafNode, TestCallbackInfo)> = BTreeMap::new();
@@ -840,11 +1745,10 @@ mod tests {
                 println!("key : {:x}", key);
                 println!("owner : {:?}", &owner.to_bytes());
                 println!("{}", i);
-                let h = slab.insert_leaf(&leaf).unwrap().0;
                 let callback_info = TestCallbackInfo {
                     key: owner.to_bytes(),
                 };
-                *slab.get_callback_info_mut(h) = callback_info;
+                slab.insert_leaf(&leaf, &callback_info).unwrap();
                 model
                     .insert(key, (leaf, callback_info))
                     .ok_or(())
@@ -881,6 +1785,336 @@ mod tests {
         }
     }
 
+    #[test]
+    fn slab_utilization() {
+        let mut bytes = vec![0u8; compute_slab_size(10, 32)];
+        write_account_tag(&mut bytes, AccountTag::Asks);
+        let mut slab = Slab::<[u8; 32]>::from_buffer(&mut bytes, AccountTag::Asks).unwrap();
+        assert_eq!(slab.capacity(), 10);
+        assert!(slab.is_empty());
+
+        for key in 0..10 {
+            slab.insert_leaf(&LeafNode::new(key, 1, u64::MAX), &[0; 32])
+                .unwrap();
+        }
+        assert_eq!(slab.len(), 10);
+        assert_eq!(slab.free_nodes(), 0);
+        assert!(slab
+            .insert_leaf(&LeafNode::new(10, 1, u64::MAX), &[0; 32])
+            .is_err());
+
+        slab.remove_by_key(3).unwrap();
+        assert_eq!(slab.len(), 9);
+        assert_eq!(slab.free_nodes(), 1);
+    }
+
+    #[test]
+    fn slab_grow() {
+        let mut bytes = vec![0u8; Slab::<[u8; 32]>::compute_allocation_size(10)];
+        write_account_tag(&mut bytes, AccountTag::Asks);
+        let mut slab = Slab::<[u8; 32]>::from_buffer(&mut bytes, AccountTag::Asks).unwrap();
+        for key in 0..10u128 {
+            let h = slab
+                .insert_leaf(&LeafNode::new(key, 1, u64::MAX), &[key as u8; 32])
+                .unwrap()
+                .0;
+            slab.set_client_order_id(h, key as u64 + 100);
+        }
+        slab.remove_by_key(3).unwrap();
+
+        let old_len = bytes.len();
+        bytes.resize(Slab::<[u8; 32]>::compute_allocation_size(20), 0);
+        Slab::<[u8; 32]>::grow(&mut bytes, old_len);
+        let mut slab = Slab::<[u8; 32]>::from_buffer(&mut bytes, AccountTag::Asks).unwrap();
+        assert_eq!(slab.capacity(), 20);
+        assert_eq!(slab.len(), 9);
+
+        for key in (0..10u128).filter(|k| *k != 3) {
+            let h = slab.find_by_key(key).unwrap();
+            assert_eq!(slab.get_callback_info(h), &[key as u8; 32]);
+            assert_eq!(slab.get_client_order_id(h), key as u64 + 100);
+        }
+        for key in 10..21u128 {
+            let h = slab
+                .insert_leaf(&LeafNode::new(key, 1, u64::MAX), &[key as u8; 32])
+                .unwrap()
+                .0;
+            // The client order id of a removed order doesn't carry over to the order reusing its leaf
+            assert_eq!(slab.get_client_order_id(h), 0);
+        }
+        assert_eq!(slab.free_nodes(), 0);
+        assert!(slab
+            .iter(true)
+            .map(|l| l.key())
+            .eq((0..21u128).filter(|k| *k != 3)));
+    }
+
+    #[test]
+    fn slab_verify() {
+        let mut bytes = vec![0u8; Slab::<[u8; 32]>::compute_allocation_size(10)];
+        write_account_tag(&mut bytes, AccountTag::Asks);
+        let mut slab = Slab::<[u8; 32]>::from_buffer(&mut bytes, AccountTag::Asks).unwrap();
+        assert_eq!(slab.verify(), Ok(()));
+        for key in 0..8u128 {
+            slab.insert_leaf(&LeafNode::new(key, 1, u64::MAX), &[0; 32])
+                .unwrap();
+        }
+        slab.remove_by_key(3).unwrap();
+        assert_eq!(slab.verify(), Ok(()));
+
+        slab.header.leaf_count += 1;
+        assert_eq!(slab.verify(), Err(SlabCorruption::LeafCountMismatch));
+        slab.header.leaf_count -= 1;
+
+        let root = slab.root().unwrap();
+        let children = slab.inner_nodes[(!root) as usize].children();
+        slab.inner_nodes[(!root) as usize].set_children(0, children[1]);
+        slab.inner_nodes[(!root) as usize].set_children(1, children[0]);
+        assert_eq!(
+            slab.verify(),
+            Err(SlabCorruption::WrongCritbit(children[0]))
+        );
+        slab.inner_nodes[(!root) as usize].set_children(0, children[0]);
+        slab.inner_nodes[(!root) as usize].set_children(1, children[1]);
+
+        let free_leaf = slab.header.leaf_free_list_head;
+        let live_leaf = slab.find_by_key(4).unwrap();
+        slab.header.leaf_free_list_head = live_leaf;
+        assert_eq!(slab.verify(), Err(SlabCorruption::FreeNodeInUse(live_leaf)));
+        slab.header.leaf_free_list_head = free_leaf;
+        assert_eq!(slab.verify(), Ok(()));
+    }
+
+    #[test]
+    fn slab_checksum() {
+        let mut bytes = vec![0u8; Slab::<[u8; 32]>::compute_allocation_size(10)];
+        write_account_tag(&mut bytes, AccountTag::Asks);
+        let mut slab = Slab::<[u8; 32]>::from_buffer(&mut bytes, AccountTag::Asks).unwrap();
+        assert_eq!(slab.header.checksum, [0; SLAB_CHECKSUM_LEN]);
+        for key in 0..8u128 {
+            slab.insert_leaf(&LeafNode::new(key, 1, u64::MAX), &[0; 32])
+                .unwrap();
+        }
+        slab.insert_leaf(&LeafNode::new(5, 2, u64::MAX), &[0; 32])
+            .unwrap();
+        slab.remove_by_key(3).unwrap();
+        let h = slab.find_by_key(6).unwrap();
+        slab.update_leaf(h, |leaf| leaf.set_base_quantity(3));
+        assert!(slab.verify_checksum());
+
+        // The checksum doesn't depend on the order of the leaves
+        let mut leaves = slab.iter(false).copied().collect::<Vec<_>>();
+        assert_eq!(compute_checksum(&leaves), slab.header.checksum);
+        leaves.pop();
+        assert_ne!(compute_checksum(&leaves), slab.header.checksum);
+
+        slab.leaf_nodes[h as usize].set_base_quantity(4);
+        assert_eq!(slab.verify(), Err(SlabCorruption::ChecksumMismatch));
+        slab.leaf_nodes[h as usize].set_base_quantity(3);
+        assert_eq!(slab.verify(), Ok(()));
+        assert!(verify_slab_checksum(&bytes, AccountTag::Asks, 32).unwrap());
+    }
+
+    #[test]
+    fn slab_migration_from_unversioned() {
+        let capacity = 10;
+        let mut bytes = vec![0u8; Slab::<[u8; 32]>::compute_allocation_size(capacity)];
+        write_account_tag(&mut bytes, AccountTag::Bids);
+        let mut slab = Slab::<[u8; 32]>::from_buffer(&mut bytes, AccountTag::Bids).unwrap();
+        for key in 0..8u128 {
+            slab.insert_leaf(
+                &LeafNode::new(key, key as u64 + 1, u64::MAX),
+                &[key as u8; 32],
+            )
+            .unwrap();
+        }
+        slab.remove_by_key(3).unwrap();
+        slab.remove_by_key(6).unwrap();
+
+        // A slab created before versioning has a header without checksum, narrower leaves and no owner index or
+        // client order ids
+        let header_end = ACCOUNT_TAG_LENGTH + SlabHeader::LEN;
+        let leaves_end = header_end + capacity * LeafNode::LEN;
+        let callback_infos_end = leaves_end + (capacity - 1) * InnerNode::LEN + capacity * 32;
+        let mut old_bytes = bytes[..header_end - SLAB_CHECKSUM_LEN].to_vec();
+        old_bytes[ACCOUNT_VERSION_INDEX] = 0;
+        for leaf in bytes[header_end..leaves_end].chunks(LeafNode::LEN) {
+            assert!(leaf[UNVERSIONED_LEAF_NODE_LEN..].iter().all(|b| *b == 0));
+            old_bytes.extend_from_slice(&leaf[..UNVERSIONED_LEAF_NODE_LEN]);
+        }
+        old_bytes.extend_from_slice(&bytes[leaves_end..callback_infos_end]);
+
+        assert_eq!(
+            compute_migrated_slab_len(old_bytes.len(), 0, 32),
+            bytes.len()
+        );
+        old_bytes.resize(bytes.len(), 0);
+        assert!(migrate_account::<[u8; 32]>(&mut old_bytes, AccountTag::Bids).unwrap());
+        // The owner index is rebuilt, so only its contents may differ
+        assert_eq!(old_bytes[..callback_infos_end], bytes[..callback_infos_end]);
+        let client_order_ids_start = bytes.len() - capacity * CLIENT_ORDER_ID_LEN;
+        assert_eq!(
+            old_bytes[client_order_ids_start..],
+            bytes[client_order_ids_start..]
+        );
+        let slab = Slab::<[u8; 32]>::from_buffer(&mut old_bytes, AccountTag::Bids).unwrap();
+        assert_eq!(slab.verify(), Ok(()));
+        assert_eq!(slab.len(), 6);
+        let h = slab.find_by_key(5).unwrap();
+        assert_eq!(slab.leaf_nodes[h as usize].base_quantity(), 6);
+        assert_eq!(slab.get_callback_info(h), &[5; 32]);
+        assert_eq!(slab.count_owner_orders(&[5; 32]), 1);
+        assert_eq!(slab.count_owner_orders(&[6; 32]), 0);
+    }
+
+    #[test]
+    fn simulate_iter() {
+        use std::collections::BTreeMap;
+
+        let mut bytes = vec![0u8; Slab::<[u8; 32]>::compute_allocation_size(1_000)];
+        write_account_tag(&mut bytes, AccountTag::Asks);
+        let mut slab = Slab::<[u8; 32]>::from_buffer(&mut bytes, AccountTag::Asks).unwrap();
+        let mut model: BTreeMap<u128, LeafNode> = BTreeMap::new();
+        let mut rng = StdRng::seed_from_u64(0);
+
+        assert_eq!(slab.iter(true).next(), None);
+
+        for _ in 0..500 {
+            let leaf = LeafNode::new(rng.gen(), rng.gen(), u64::MAX);
+            slab.insert_leaf(&leaf, &[0; 32]).unwrap();
+            model.insert(leaf.key(), leaf);
+        }
+
+        assert!(slab.iter(true).eq(model.values()));
+        assert!(slab.iter(false).eq(model.values().rev()));
+    }
+
+    #[test]
+    fn slab_owner_index() {
+        let mut bytes = vec![0u8; Slab::<[u8; 32]>::compute_allocation_size(10)];
+        write_account_tag(&mut bytes, AccountTag::Asks);
+        let mut slab = Slab::<[u8; 32]>::from_buffer(&mut bytes, AccountTag::Asks).unwrap();
+        for key in 0..9u128 {
+            slab.insert_leaf(&LeafNode::new(key, 1, u64::MAX), &[key as u8 % 3; 32])
+                .unwrap();
+        }
+        slab.check_invariants();
+        assert_eq!(slab.count_owner_orders(&[1; 32]), 3);
+        assert_eq!(slab.count_owner_orders(&[3; 32]), 0);
+        let owner_keys = |slab: &Slab<[u8; 32]>, owner: u8| {
+            slab.iter_owner_handles(&[owner; 32])
+                .map(|h| slab.leaf_nodes[h as usize].key())
+                .collect::<Vec<_>>()
+        };
+        assert_eq!(owner_keys(&slab, 1), vec![7, 4, 1]);
+
+        // Removing, overwriting and reassigning orders keeps the lists up to date
+        slab.remove_by_key(4).unwrap();
+        slab.insert_leaf(&LeafNode::new(7, 2, u64::MAX), &[2; 32])
+            .unwrap();
+        let h = slab.find_by_key(0).unwrap();
+        slab.set_callback_info(h, &[1; 32]);
+        slab.check_invariants();
+        assert_eq!(owner_keys(&slab, 0), vec![6, 3]);
+        assert_eq!(owner_keys(&slab, 1), vec![0, 1]);
+        assert_eq!(owner_keys(&slab, 2), vec![7, 8, 5, 2]);
+        for key in [0, 1] {
+            slab.remove_by_key(key).unwrap();
+        }
+        slab.check_invariants();
+        assert_eq!(slab.count_owner_orders(&[1; 32]), 0);
+        assert_eq!(owner_keys(&slab, 1), vec![]);
+
+        // The owner table is rebuilt when the slab is grown
+        let old_len = bytes.len();
+        bytes.resize(Slab::<[u8; 32]>::compute_allocation_size(40), 0);
+        Slab::<[u8; 32]>::grow(&mut bytes, old_len);
+        let mut slab = Slab::<[u8; 32]>::from_buffer(&mut bytes, AccountTag::Asks).unwrap();
+        slab.check_invariants();
+        assert_eq!(owner_keys(&slab, 2), vec![7, 8, 5, 2]);
+        for key in 10..40u128 {
+            slab.insert_leaf(&LeafNode::new(key, 1, u64::MAX), &[key as u8; 32])
+                .unwrap();
+        }
+        slab.check_invariants();
+        assert_eq!(slab.count_owner_orders(&[2; 32]), 4);
+
+        // A corrupted owner table is reported
+        let h = slab.find_by_key(3).unwrap();
+        slab.owner_links[h as usize].next = slab.find_by_key(8).unwrap();
+        assert_eq!(slab.verify(), Err(SlabCorruption::OwnerIndexMismatch));
+    }
+
+    #[test]
+    fn fuzz_operations() {
+        use std::collections::BTreeMap;
+
+        // A failing run can be reproduced with SLAB_FUZZ_SEED=<seed> cargo test fuzz_operations
+        let seeds = match std::env::var("SLAB_FUZZ_SEED") {
+            Ok(seed) => vec![seed.parse::<u64>().unwrap()],
+            Err(_) => (0..20).collect(),
+        };
+        for seed in seeds {
+            println!("Seed : {}", seed);
+            let mut rng = StdRng::seed_from_u64(seed);
+            // A small slab makes the free lists and the out of space path get exercised
+            let mut bytes = vec![0u8; Slab::<[u8; 32]>::compute_allocation_size(64)];
+            write_account_tag(&mut bytes, AccountTag::Asks);
+            let mut slab = Slab::<[u8; 32]>::from_buffer(&mut bytes, AccountTag::Asks).unwrap();
+            let mut model: BTreeMap<u128, (LeafNode, [u8; 32])> = BTreeMap::new();
+
+            // Keys are drawn from a narrow space in order to produce long shared prefixes and collisions
+            let prefix = rng.gen::<u128>() & !0xffff;
+            let gen_key = |rng: &mut StdRng| match rng.gen_range(0..3) {
+                0 => rng.gen::<u128>(),
+                1 => prefix | rng.gen_range(0..0x10000u64) as u128,
+                _ => prefix | (1u128 << rng.gen_range(0..16u64)),
+            };
+
+            for _ in 0..5_000 {
+                match rng.gen_range(0..4) {
+                    0 | 1 => {
+                        let key = gen_key(&mut rng);
+                        let leaf = LeafNode::new(key, rng.gen(), rng.gen());
+                        let callback_info = [rng.gen(); 32];
+                        match slab.insert_leaf(&leaf, &callback_info) {
+                            Ok((_, old_leaf)) => {
+                                let model_value = model.insert(key, (leaf, callback_info));
+                                assert_eq!(old_leaf, model_value.map(|(l, _)| l));
+                            }
+                            Err(e) => {
+                                assert!(matches!(e, AoError::SlabOutOfSpace));
+                                assert_eq!(model.len() as u64, slab.capacity());
+                                assert!(!model.contains_key(&key));
+                            }
+                        }
+                    }
+                    2 => {
+                        let key = match model
+                            .keys()
+                            .nth(rng.gen_range(0..model.len().max(1) as u64) as usize)
+                        {
+                            Some(k) if rng.gen_bool(0.8) => *k,
+                            _ => gen_key(&mut rng),
+                        };
+                        let slab_value = slab.remove_by_key(key).map(|(l, c)| (l, *c));
+                        assert_eq!(slab_value, model.remove(&key));
+                    }
+                    _ => {
+                        let key = gen_key(&mut rng);
+                        let slab_value = slab
+                            .find_by_key(key)
+                            .map(|h| (slab.leaf_nodes[h as usize], *slab.get_callback_info(h)));
+                        assert_eq!(slab_value.as_ref(), model.get(&key));
+                    }
+                }
+                slab.check_invariants();
+                let model_state = model.values().copied().collect::<Vec<_>>();
+                assert_eq!(model_state, slab.traverse());
+            }
+        }
+    }
+
     #[test]
     #[cfg(not(feature = "quick-test"))]
     fn simulate_operations() {
@@ -888,7 +2122,7 @@ mod tests {
         use std::collections::BTreeMap;
 
         let mut bytes = vec![0u8; Slab::<[u8; 32]>::compute_allocation_size(10_000)];
-        bytes[0] = AccountTag::Asks as u8;
+        write_account_tag(&mut bytes, AccountTag::Asks);
         let mut slab = Slab::from_buffer(&mut bytes, AccountTag::Asks).unwrap();
 
         let mut model: BTreeMap<u128, (LeafNode, [u8; 32])> = BTreeMap::new();
@@ -945,14 +2179,13 @@ mod tests {
                         let qty = rng.gen();
 
                         let leaf = LeafNode::new(key, qty, u64::MAX);
-                        let (leaf_h, old_leaf) = slab.insert_leaf(&leaf).unwrap();
-                        let old_owner = *slab.get_callback_info(leaf_h);
-                        *slab.get_callback_info_mut(leaf_h) = owner;
+                        let old_owner = slab.find_by_key(key).map(|h| *slab.get_callback_info(h));
+                        let (_, old_leaf) = slab.insert_leaf(&leaf, &owner).unwrap();
 
                         println!("Insert {:x}", key);
 
                         all_keys.push(key);
-                        let slab_value = old_leaf.map(|l| (l, old_owner));
+                        let slab_value = old_leaf.zip(old_owner);
                         let model_value = model.insert(key, (leaf, owner));
                         if slab_value != model_value {
                             slab.dump();
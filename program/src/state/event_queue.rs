@@ -8,13 +8,20 @@ use borsh::{BorshDeserialize, BorshSerialize};
 use bytemuck::{CheckedBitPattern, NoUninit, Pod, Zeroable};
 use num_derive::FromPrimitive;
 use num_traits::FromPrimitive;
-use solana_program::{entrypoint::ProgramResult, msg, program_error::ProgramError};
-use std::convert::{TryFrom, TryInto};
+use solana_program::{
+    clock::Clock, entrypoint::ProgramResult, log::sol_log_data, msg, program_error::ProgramError,
+    sysvar::Sysvar,
+};
+use std::{cell::RefMut, convert::TryInto};
 
 pub use crate::state::orderbook::{OrderSummary, ORDER_SUMMARY_SIZE};
+use crate::utils::compute_fee;
 pub use crate::utils::get_spread;
 
-use super::{AccountTag, Side, ACCOUNT_TAG_INDEX, ACCOUNT_TAG_LENGTH};
+use super::{
+    check_account_version, market_state::MarketState, read_account_tag, write_account_tag,
+    AccountTag, EventQueueFullPolicy, OrderId, Side, ACCOUNT_TAG_LENGTH,
+};
 
 #[derive(Clone, Copy, Debug, PartialEq)]
 #[repr(transparent)]
@@ -27,7 +34,14 @@ use super::{AccountTag, Side, ACCOUNT_TAG_INDEX, ACCOUNT_TAG_LENGTH};
 ///    pub tag: u8,
 ///    /// The u8 representation for a [`Side`] enum.
 ///    pub taker_side: u8,
-///    pub(crate) _padding: [u8; 6],
+///    /// The caller-defined flags of the taker order.
+///    pub taker_flags: u8,
+///    /// The caller-defined flags of the maker order.
+///    pub maker_flags: u8,
+///    /// The fee rate charged to the maker, in basis points.
+///    pub maker_fee_bps: u16,
+///    /// The fee rate charged to the taker, in basis points.
+///    pub taker_fee_bps: u16,
 ///    /// The total quote size of the transaction.
 ///    pub quote_size: u64,
 ///    /// The order id of the maker order.
@@ -35,6 +49,14 @@ use super::{AccountTag, Side, ACCOUNT_TAG_INDEX, ACCOUNT_TAG_LENGTH};
 ///    /// The total base size of the transaction.
 ///    pub base_size: u64,
 /// ```
+#[cfg_attr(
+    feature = "serde",
+    derive(serde::Serialize, serde::Deserialize),
+    serde(
+        into = "serde_fields::FillEventFields",
+        from = "serde_fields::FillEventFields"
+    )
+)]
 pub struct FillEvent([u8; 40]);
 
 impl Default for FillEvent {
@@ -51,15 +73,28 @@ impl FillEvent {
     pub const LEN: usize = std::mem::size_of::<Self>();
     const TAG_INDEX: usize = 0;
     const SIDE_INDEX: usize = 1;
+    const TAKER_FLAGS_INDEX: usize = 2;
+    const MAKER_FLAGS_INDEX: usize = 3;
+    const MAKER_FEE_BPS_INDEX: usize = 4;
+    const TAKER_FEE_BPS_INDEX: usize = 6;
     const QUOTE_SIZE_INDEX: usize = 8;
     const MAKER_ORDER_ID_INDEX: usize = 16;
     const BASE_SIZE_INDEX: usize = 32;
 
     /// Initializes a new [`FillEvent`].
-    pub fn new(taker_side: Side, quote_size: u64, maker_order_id: u128, base_size: u64) -> Self {
+    pub fn new(
+        taker_side: Side,
+        quote_size: u64,
+        maker_order_id: u128,
+        base_size: u64,
+        taker_flags: u8,
+        maker_flags: u8,
+    ) -> Self {
         let mut data = [0; 40];
         data[Self::TAG_INDEX] = EventTag::Fill as u8;
         data[Self::SIDE_INDEX] = taker_side as u8;
+        data[Self::TAKER_FLAGS_INDEX] = taker_flags;
+        data[Self::MAKER_FLAGS_INDEX] = maker_flags;
 
         let dst = &mut data[Self::QUOTE_SIZE_INDEX..Self::MAKER_ORDER_ID_INDEX];
         dst.copy_from_slice(&quote_size.to_le_bytes()[..]);
@@ -82,6 +117,16 @@ impl FillEvent {
         Side::from_u8(self.0[Self::SIDE_INDEX]).unwrap()
     }
 
+    /// Gets the caller-defined flags of the taker order.
+    pub fn taker_flags(&self) -> u8 {
+        self.0[Self::TAKER_FLAGS_INDEX]
+    }
+
+    /// Gets the caller-defined flags of the maker order.
+    pub fn maker_flags(&self) -> u8 {
+        self.0[Self::MAKER_FLAGS_INDEX]
+    }
+
     /// Gets the quote size.
     pub fn quote_size(&self) -> u64 {
         u64::from_le_bytes(
@@ -91,6 +136,12 @@ impl FillEvent {
         )
     }
 
+    /// Sets the quote size.
+    pub(crate) fn set_quote_size(&mut self, quote_size: u64) {
+        self.0[Self::QUOTE_SIZE_INDEX..Self::MAKER_ORDER_ID_INDEX]
+            .copy_from_slice(&quote_size.to_le_bytes());
+    }
+
     /// Gets the maker order id.
     pub fn maker_order_id(&self) -> u128 {
         u128::from_le_bytes(
@@ -104,6 +155,96 @@ impl FillEvent {
     pub fn base_size(&self) -> u64 {
         u64::from_le_bytes(self.0[Self::BASE_SIZE_INDEX..Self::LEN].try_into().unwrap())
     }
+
+    /// Gets the fee rate charged to the maker, in basis points.
+    pub fn maker_fee_bps(&self) -> u16 {
+        u16::from_le_bytes(
+            self.0[Self::MAKER_FEE_BPS_INDEX..Self::TAKER_FEE_BPS_INDEX]
+                .try_into()
+                .unwrap(),
+        )
+    }
+
+    /// Gets the fee rate charged to the taker, in basis points.
+    pub fn taker_fee_bps(&self) -> u16 {
+        u16::from_le_bytes(
+            self.0[Self::TAKER_FEE_BPS_INDEX..Self::QUOTE_SIZE_INDEX]
+                .try_into()
+                .unwrap(),
+        )
+    }
+
+    /// Sets the fee rates charged to the maker and to the taker, in basis points.
+    pub(crate) fn set_fee_rates(&mut self, maker_fee_bps: u16, taker_fee_bps: u16) {
+        self.0[Self::MAKER_FEE_BPS_INDEX..Self::TAKER_FEE_BPS_INDEX]
+            .copy_from_slice(&maker_fee_bps.to_le_bytes());
+        self.0[Self::TAKER_FEE_BPS_INDEX..Self::QUOTE_SIZE_INDEX]
+            .copy_from_slice(&taker_fee_bps.to_le_bytes());
+    }
+
+    /// Gets the fee charged to the maker on the quote size, in quote lots, as accrued by the market.
+    pub fn maker_fee(&self) -> u64 {
+        compute_fee(self.quote_size(), self.maker_fee_bps())
+    }
+
+    /// Gets the fee charged to the taker on the quote size, in quote lots, as accrued by the market.
+    pub fn taker_fee(&self) -> u64 {
+        compute_fee(self.quote_size(), self.taker_fee_bps())
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+#[repr(transparent)]
+/// Represents the maker side of a match, on markets which split fills.
+///
+/// It has the same layout as a [`FillEvent`], but is only associated with the maker's callback information.
+/// Its quote size is rounded against the maker, and may thus differ by one unit from the quote size of the matching
+/// [`TakerFillEvent`].
+#[cfg_attr(
+    feature = "serde",
+    derive(serde::Serialize, serde::Deserialize),
+    serde(
+        into = "serde_fields::FillEventFields",
+        from = "serde_fields::FillEventFields"
+    )
+)]
+pub struct MakerFillEvent(pub FillEvent);
+
+unsafe impl Zeroable for MakerFillEvent {}
+unsafe impl Pod for MakerFillEvent {}
+
+impl MakerFillEvent {
+    /// Initializes a new [`MakerFillEvent`] from the combined [`FillEvent`] of a match.
+    pub fn new(mut fill: FillEvent) -> Self {
+        fill.0[FillEvent::TAG_INDEX] = EventTag::MakerFill as u8;
+        Self(fill)
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+#[repr(transparent)]
+/// Represents the taker side of a match, on markets which split fills.
+///
+/// It has the same layout as a [`FillEvent`], but is only associated with the taker's callback information.
+#[cfg_attr(
+    feature = "serde",
+    derive(serde::Serialize, serde::Deserialize),
+    serde(
+        into = "serde_fields::FillEventFields",
+        from = "serde_fields::FillEventFields"
+    )
+)]
+pub struct TakerFillEvent(pub FillEvent);
+
+unsafe impl Zeroable for TakerFillEvent {}
+unsafe impl Pod for TakerFillEvent {}
+
+impl TakerFillEvent {
+    /// Initializes a new [`TakerFillEvent`] from the combined [`FillEvent`] of a match.
+    pub fn new(mut fill: FillEvent) -> Self {
+        fill.0[FillEvent::TAG_INDEX] = EventTag::TakerFill as u8;
+        Self(fill)
+    }
 }
 
 #[derive(Clone, Copy, Debug, PartialEq)]
@@ -123,6 +264,14 @@ impl FillEvent {
 ///    /// The total base size of the transaction.
 ///    pub base_size: u64,
 /// ```
+#[cfg_attr(
+    feature = "serde",
+    derive(serde::Serialize, serde::Deserialize),
+    serde(
+        into = "serde_fields::OrderEventFields",
+        from = "serde_fields::OrderEventFields"
+    )
+)]
 pub struct OutEvent([u8; 40]);
 
 impl Default for OutEvent {
@@ -182,6 +331,337 @@ impl OutEvent {
     }
 }
 
+#[derive(Clone, Copy, Debug, PartialEq)]
+#[repr(transparent)]
+/// Represents the displayed quantity of an iceberg order being refreshed from its hidden quantity.
+///
+/// The actual inner data of the structure has the following fields.
+///
+/// ```ignore
+///    /// The u8 representation for an [`EventTag`] enum.
+///    pub tag: u8,
+///    /// The u8 representation for a [`Side`] enum.
+///    pub side: u8,
+///    pub(crate) _padding: [u8; 14],
+///    /// The order id of the replenished order.
+///    pub order_id: u128,
+///    /// The base size which was moved from the hidden quantity to the displayed quantity.
+///    pub base_size: u64,
+/// ```
+#[cfg_attr(
+    feature = "serde",
+    derive(serde::Serialize, serde::Deserialize),
+    serde(
+        into = "serde_fields::OrderEventFields",
+        from = "serde_fields::OrderEventFields"
+    )
+)]
+pub struct ReplenishEvent([u8; 40]);
+
+impl Default for ReplenishEvent {
+    fn default() -> Self {
+        Self([0; 40])
+    }
+}
+
+unsafe impl Zeroable for ReplenishEvent {}
+unsafe impl Pod for ReplenishEvent {}
+
+impl ReplenishEvent {
+    /// Byte length of the ReplenishEvent object.
+    pub const LEN: usize = std::mem::size_of::<Self>();
+    const TAG_INDEX: usize = 0;
+    const SIDE_INDEX: usize = 1;
+    const ORDER_ID_INDEX: usize = 16;
+    const BASE_SIZE_INDEX: usize = 32;
+
+    /// Initializes a new [`ReplenishEvent`].
+    pub fn new(side: Side, base_size: u64, order_id: u128) -> Self {
+        let mut data = [0; 40];
+        data[Self::TAG_INDEX] = EventTag::Replenish as u8;
+        data[Self::SIDE_INDEX] = side as u8;
+
+        let dst = &mut data[Self::ORDER_ID_INDEX..Self::BASE_SIZE_INDEX];
+        dst.copy_from_slice(&order_id.to_le_bytes()[..]);
+
+        let dst = &mut data[Self::BASE_SIZE_INDEX..Self::LEN];
+        dst.copy_from_slice(&base_size.to_le_bytes()[..]);
+        Self(data)
+    }
+
+    /// Gets the [`Side`] enum.
+    pub fn side(&self) -> Side {
+        Side::from_u8(self.0[Self::SIDE_INDEX]).unwrap()
+    }
+
+    /// Gets the replenished base size.
+    pub fn base_size(&self) -> u64 {
+        u64::from_le_bytes(self.0[Self::BASE_SIZE_INDEX..Self::LEN].try_into().unwrap())
+    }
+
+    /// Gets the order id.
+    pub fn order_id(&self) -> u128 {
+        u128::from_le_bytes(
+            self.0[Self::ORDER_ID_INDEX..Self::BASE_SIZE_INDEX]
+                .try_into()
+                .unwrap(),
+        )
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+#[repr(transparent)]
+/// Represents an order being forcibly removed from the orderbook by the AOB rather than by its owner.
+///
+/// The actual inner data of the structure has the following fields.
+///
+/// ```ignore
+///    /// The u8 representation for an [`EventTag`] enum.
+///    pub tag: u8,
+///    /// The u8 representation for a [`Side`] enum.
+///    pub side: u8,
+///    /// The u8 representation for a [`BootReason`] enum.
+///    pub reason: u8,
+///    pub(crate) _padding: [u8; 13],
+///    /// The order id of the booted order.
+///    pub order_id: u128,
+///    /// The total base size of the booted order.
+///    pub base_size: u64,
+/// ```
+#[cfg_attr(
+    feature = "serde",
+    derive(serde::Serialize, serde::Deserialize),
+    serde(
+        into = "serde_fields::BootEventFields",
+        from = "serde_fields::BootEventFields"
+    )
+)]
+pub struct BootEvent([u8; 40]);
+
+impl Default for BootEvent {
+    fn default() -> Self {
+        Self([0; 40])
+    }
+}
+
+unsafe impl Zeroable for BootEvent {}
+unsafe impl Pod for BootEvent {}
+
+impl BootEvent {
+    /// Byte length of the BootEvent object.
+    pub const LEN: usize = std::mem::size_of::<Self>();
+    const TAG_INDEX: usize = 0;
+    const SIDE_INDEX: usize = 1;
+    const REASON_INDEX: usize = 2;
+    const ORDER_ID_INDEX: usize = 16;
+    const BASE_SIZE_INDEX: usize = 32;
+
+    /// Initializes a new [`BootEvent`].
+    pub fn new(side: Side, reason: BootReason, base_size: u64, order_id: u128) -> Self {
+        let mut data = [0; 40];
+        data[Self::TAG_INDEX] = EventTag::Boot as u8;
+        data[Self::SIDE_INDEX] = side as u8;
+        data[Self::REASON_INDEX] = reason as u8;
+
+        let dst = &mut data[Self::ORDER_ID_INDEX..Self::BASE_SIZE_INDEX];
+        dst.copy_from_slice(&order_id.to_le_bytes()[..]);
+
+        let dst = &mut data[Self::BASE_SIZE_INDEX..Self::LEN];
+        dst.copy_from_slice(&base_size.to_le_bytes()[..]);
+        Self(data)
+    }
+
+    /// Gets the [`Side`] enum.
+    pub fn side(&self) -> Side {
+        Side::from_u8(self.0[Self::SIDE_INDEX]).unwrap()
+    }
+
+    /// Gets the [`BootReason`] enum.
+    pub fn reason(&self) -> BootReason {
+        BootReason::from_u8(self.0[Self::REASON_INDEX]).unwrap()
+    }
+
+    /// Gets the base size.
+    pub fn base_size(&self) -> u64 {
+        u64::from_le_bytes(self.0[Self::BASE_SIZE_INDEX..Self::LEN].try_into().unwrap())
+    }
+
+    /// Gets the order id.
+    pub fn order_id(&self) -> u128 {
+        u128::from_le_bytes(
+            self.0[Self::ORDER_ID_INDEX..Self::BASE_SIZE_INDEX]
+                .try_into()
+                .unwrap(),
+        )
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+#[repr(transparent)]
+/// Represents an order being modified in place, keeping its priority in the orderbook.
+///
+/// The actual inner data of the structure has the following fields.
+///
+/// ```ignore
+///    /// The u8 representation for an [`EventTag`] enum.
+///    pub tag: u8,
+///    /// The u8 representation for a [`Side`] enum.
+///    pub side: u8,
+///    pub(crate) _padding: [u8; 6],
+///    /// The base size which was removed from the order.
+///    pub base_size_removed: u64,
+///    /// The order id of the modified order.
+///    pub order_id: u128,
+///    /// The base size of the order after its modification.
+///    pub new_base_size: u64,
+/// ```
+#[cfg_attr(
+    feature = "serde",
+    derive(serde::Serialize, serde::Deserialize),
+    serde(
+        into = "serde_fields::ModifyEventFields",
+        from = "serde_fields::ModifyEventFields"
+    )
+)]
+pub struct ModifyEvent([u8; 40]);
+
+impl Default for ModifyEvent {
+    fn default() -> Self {
+        Self([0; 40])
+    }
+}
+
+unsafe impl Zeroable for ModifyEvent {}
+unsafe impl Pod for ModifyEvent {}
+
+impl ModifyEvent {
+    /// Byte length of the ModifyEvent object.
+    pub const LEN: usize = std::mem::size_of::<Self>();
+    const TAG_INDEX: usize = 0;
+    const SIDE_INDEX: usize = 1;
+    const BASE_SIZE_REMOVED_INDEX: usize = 8;
+    const ORDER_ID_INDEX: usize = 16;
+    const NEW_BASE_SIZE_INDEX: usize = 32;
+
+    /// Initializes a new [`ModifyEvent`].
+    pub fn new(side: Side, order_id: u128, new_base_size: u64, base_size_removed: u64) -> Self {
+        let mut data = [0; 40];
+        data[Self::TAG_INDEX] = EventTag::Modify as u8;
+        data[Self::SIDE_INDEX] = side as u8;
+
+        let dst = &mut data[Self::BASE_SIZE_REMOVED_INDEX..Self::ORDER_ID_INDEX];
+        dst.copy_from_slice(&base_size_removed.to_le_bytes()[..]);
+
+        let dst = &mut data[Self::ORDER_ID_INDEX..Self::NEW_BASE_SIZE_INDEX];
+        dst.copy_from_slice(&order_id.to_le_bytes()[..]);
+
+        let dst = &mut data[Self::NEW_BASE_SIZE_INDEX..Self::LEN];
+        dst.copy_from_slice(&new_base_size.to_le_bytes()[..]);
+        Self(data)
+    }
+
+    /// Gets the [`Side`] enum.
+    pub fn side(&self) -> Side {
+        Side::from_u8(self.0[Self::SIDE_INDEX]).unwrap()
+    }
+
+    /// Gets the base size which was removed from the order.
+    pub fn base_size_removed(&self) -> u64 {
+        u64::from_le_bytes(
+            self.0[Self::BASE_SIZE_REMOVED_INDEX..Self::ORDER_ID_INDEX]
+                .try_into()
+                .unwrap(),
+        )
+    }
+
+    /// Gets the order id.
+    pub fn order_id(&self) -> u128 {
+        u128::from_le_bytes(
+            self.0[Self::ORDER_ID_INDEX..Self::NEW_BASE_SIZE_INDEX]
+                .try_into()
+                .unwrap(),
+        )
+    }
+
+    /// Gets the base size of the order after its modification.
+    pub fn new_base_size(&self) -> u64 {
+        u64::from_le_bytes(
+            self.0[Self::NEW_BASE_SIZE_INDEX..Self::LEN]
+                .try_into()
+                .unwrap(),
+        )
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+#[repr(transparent)]
+/// Represents the referral tag of a taker order, attributing the fill which immediately precedes it in the event queue
+/// to a referrer.
+///
+/// It follows the [`FillEvent`] or [`TakerFillEvent`] of each match of an order placed with a referral, and is
+/// associated with the same callback informations, so that it is always consumed along with its fill.
+///
+/// The actual inner data of the structure has the following fields.
+///
+/// ```ignore
+///    /// The u8 representation for an [`EventTag`] enum.
+///    pub tag: u8,
+///    pub(crate) _padding: [u8; 7],
+///    /// The referral tag of the taker order.
+///    pub referral: [u8; 32],
+/// ```
+#[cfg_attr(
+    feature = "serde",
+    derive(serde::Serialize, serde::Deserialize),
+    serde(
+        into = "serde_fields::ReferralEventFields",
+        from = "serde_fields::ReferralEventFields"
+    )
+)]
+pub struct ReferralEvent([u8; 40]);
+
+impl Default for ReferralEvent {
+    fn default() -> Self {
+        Self([0; 40])
+    }
+}
+
+unsafe impl Zeroable for ReferralEvent {}
+unsafe impl Pod for ReferralEvent {}
+
+impl ReferralEvent {
+    /// Byte length of the ReferralEvent object.
+    pub const LEN: usize = std::mem::size_of::<Self>();
+    const TAG_INDEX: usize = 0;
+    const REFERRAL_INDEX: usize = 8;
+
+    /// Initializes a new [`ReferralEvent`].
+    pub fn new(referral: [u8; 32]) -> Self {
+        let mut data = [0; 40];
+        data[Self::TAG_INDEX] = EventTag::Referral as u8;
+        data[Self::REFERRAL_INDEX..Self::LEN].copy_from_slice(&referral);
+        Self(data)
+    }
+
+    /// Gets the referral tag.
+    pub fn referral(&self) -> [u8; 32] {
+        self.0[Self::REFERRAL_INDEX..Self::LEN].try_into().unwrap()
+    }
+}
+
+#[derive(FromPrimitive, Clone, Copy, Debug, PartialEq)]
+#[repr(u8)]
+/// Describes why an order was booted out of the orderbook
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum BootReason {
+    /// The order was evicted to make room for a new order in a full side of the orderbook, following the market's
+    /// [`BootingPolicy`][`crate::state::BootingPolicy`].
+    OrderbookFull,
+    /// The order was removed by a [`PruneOrders`][`crate::instruction::AgnosticOrderbookInstruction::PruneOrders`]
+    /// instruction.
+    Pruned,
+}
+
 #[derive(PartialEq, Debug)]
 /// An immutable reference to an event in the EventQueue.
 pub enum EventRef<'a, C> {
@@ -189,6 +669,18 @@ pub enum EventRef<'a, C> {
     Fill(FillEventRef<'a, C>),
     #[allow(missing_docs)]
     Out(OutEventRef<'a, C>),
+    #[allow(missing_docs)]
+    Replenish(ReplenishEventRef<'a, C>),
+    #[allow(missing_docs)]
+    Boot(BootEventRef<'a, C>),
+    #[allow(missing_docs)]
+    Modify(ModifyEventRef<'a, C>),
+    #[allow(missing_docs)]
+    MakerFill(MakerFillEventRef<'a, C>),
+    #[allow(missing_docs)]
+    TakerFill(TakerFillEventRef<'a, C>),
+    #[allow(missing_docs)]
+    Referral(ReferralEventRef<'a, C>),
 }
 
 #[derive(PartialEq, Debug)]
@@ -211,29 +703,324 @@ pub struct OutEventRef<'a, C> {
     pub callback_info: &'a C,
 }
 
+#[derive(PartialEq, Debug)]
+/// An immutable reference to a Replenish event in the EventQueue, as well as the associated callback information.
+pub struct ReplenishEventRef<'a, C> {
+    #[allow(missing_docs)]
+    pub event: &'a ReplenishEvent,
+    #[allow(missing_docs)]
+    pub callback_info: &'a C,
+}
+
+#[derive(PartialEq, Debug)]
+/// An immutable reference to a Boot event in the EventQueue, as well as the associated callback information.
+pub struct BootEventRef<'a, C> {
+    #[allow(missing_docs)]
+    pub event: &'a BootEvent,
+    #[allow(missing_docs)]
+    pub callback_info: &'a C,
+}
+
+#[derive(PartialEq, Debug)]
+/// An immutable reference to a Modify event in the EventQueue, as well as the associated callback information.
+pub struct ModifyEventRef<'a, C> {
+    #[allow(missing_docs)]
+    pub event: &'a ModifyEvent,
+    #[allow(missing_docs)]
+    pub callback_info: &'a C,
+}
+
+#[derive(PartialEq, Debug)]
+/// An immutable reference to a MakerFill event in the EventQueue, as well as the associated maker callback information.
+pub struct MakerFillEventRef<'a, C> {
+    #[allow(missing_docs)]
+    pub event: &'a MakerFillEvent,
+    #[allow(missing_docs)]
+    pub callback_info: &'a C,
+}
+
+#[derive(PartialEq, Debug)]
+/// An immutable reference to a TakerFill event in the EventQueue, as well as the associated taker callback information.
+pub struct TakerFillEventRef<'a, C> {
+    #[allow(missing_docs)]
+    pub event: &'a TakerFillEvent,
+    #[allow(missing_docs)]
+    pub callback_info: &'a C,
+}
+
+#[derive(PartialEq, Debug)]
+/// An immutable reference to a Referral event in the EventQueue, as well as the callback information of the fill it
+/// follows.
+pub struct ReferralEventRef<'a, C> {
+    #[allow(missing_docs)]
+    pub event: &'a ReferralEvent,
+    #[allow(missing_docs)]
+    pub callback_info: &'a C,
+}
+
+/// The fields of the events, through which they are serialized with serde.
+#[cfg(feature = "serde")]
+mod serde_fields {
+    use super::*;
+
+    #[derive(serde::Serialize, serde::Deserialize)]
+    pub struct FillEventFields {
+        taker_side: Side,
+        quote_size: u64,
+        maker_order_id: u128,
+        base_size: u64,
+        taker_flags: u8,
+        maker_flags: u8,
+        #[serde(default)]
+        maker_fee_bps: u16,
+        #[serde(default)]
+        taker_fee_bps: u16,
+    }
+
+    impl From<FillEvent> for FillEventFields {
+        fn from(event: FillEvent) -> Self {
+            Self {
+                taker_side: event.taker_side(),
+                quote_size: event.quote_size(),
+                maker_order_id: event.maker_order_id(),
+                base_size: event.base_size(),
+                taker_flags: event.taker_flags(),
+                maker_flags: event.maker_flags(),
+                maker_fee_bps: event.maker_fee_bps(),
+                taker_fee_bps: event.taker_fee_bps(),
+            }
+        }
+    }
+
+    impl From<FillEventFields> for FillEvent {
+        fn from(fields: FillEventFields) -> Self {
+            let mut event = FillEvent::new(
+                fields.taker_side,
+                fields.quote_size,
+                fields.maker_order_id,
+                fields.base_size,
+                fields.taker_flags,
+                fields.maker_flags,
+            );
+            event.set_fee_rates(fields.maker_fee_bps, fields.taker_fee_bps);
+            event
+        }
+    }
+
+    impl From<MakerFillEvent> for FillEventFields {
+        fn from(event: MakerFillEvent) -> Self {
+            event.0.into()
+        }
+    }
+
+    impl From<FillEventFields> for MakerFillEvent {
+        fn from(fields: FillEventFields) -> Self {
+            MakerFillEvent::new(fields.into())
+        }
+    }
+
+    impl From<TakerFillEvent> for FillEventFields {
+        fn from(event: TakerFillEvent) -> Self {
+            event.0.into()
+        }
+    }
+
+    impl From<FillEventFields> for TakerFillEvent {
+        fn from(fields: FillEventFields) -> Self {
+            TakerFillEvent::new(fields.into())
+        }
+    }
+
+    #[derive(serde::Serialize, serde::Deserialize)]
+    pub struct OrderEventFields {
+        side: Side,
+        order_id: u128,
+        base_size: u64,
+    }
+
+    impl From<OutEvent> for OrderEventFields {
+        fn from(event: OutEvent) -> Self {
+            Self {
+                side: event.side(),
+                order_id: event.order_id(),
+                base_size: event.base_size(),
+            }
+        }
+    }
+
+    impl From<OrderEventFields> for OutEvent {
+        fn from(fields: OrderEventFields) -> Self {
+            OutEvent::new(fields.side, fields.base_size, fields.order_id)
+        }
+    }
+
+    impl From<ReplenishEvent> for OrderEventFields {
+        fn from(event: ReplenishEvent) -> Self {
+            Self {
+                side: event.side(),
+                order_id: event.order_id(),
+                base_size: event.base_size(),
+            }
+        }
+    }
+
+    impl From<OrderEventFields> for ReplenishEvent {
+        fn from(fields: OrderEventFields) -> Self {
+            ReplenishEvent::new(fields.side, fields.base_size, fields.order_id)
+        }
+    }
+
+    #[derive(serde::Serialize, serde::Deserialize)]
+    pub struct BootEventFields {
+        side: Side,
+        reason: BootReason,
+        order_id: u128,
+        base_size: u64,
+    }
+
+    impl From<BootEvent> for BootEventFields {
+        fn from(event: BootEvent) -> Self {
+            Self {
+                side: event.side(),
+                reason: event.reason(),
+                order_id: event.order_id(),
+                base_size: event.base_size(),
+            }
+        }
+    }
+
+    impl From<BootEventFields> for BootEvent {
+        fn from(fields: BootEventFields) -> Self {
+            BootEvent::new(
+                fields.side,
+                fields.reason,
+                fields.base_size,
+                fields.order_id,
+            )
+        }
+    }
+
+    #[derive(serde::Serialize, serde::Deserialize)]
+    pub struct ModifyEventFields {
+        side: Side,
+        order_id: u128,
+        new_base_size: u64,
+        base_size_removed: u64,
+    }
+
+    impl From<ModifyEvent> for ModifyEventFields {
+        fn from(event: ModifyEvent) -> Self {
+            Self {
+                side: event.side(),
+                order_id: event.order_id(),
+                new_base_size: event.new_base_size(),
+                base_size_removed: event.base_size_removed(),
+            }
+        }
+    }
+
+    impl From<ModifyEventFields> for ModifyEvent {
+        fn from(fields: ModifyEventFields) -> Self {
+            ModifyEvent::new(
+                fields.side,
+                fields.order_id,
+                fields.new_base_size,
+                fields.base_size_removed,
+            )
+        }
+    }
+
+    #[derive(serde::Serialize, serde::Deserialize)]
+    pub struct ReferralEventFields {
+        referral: [u8; 32],
+    }
+
+    impl From<ReferralEvent> for ReferralEventFields {
+        fn from(event: ReferralEvent) -> Self {
+            Self {
+                referral: event.referral(),
+            }
+        }
+    }
+
+    impl From<ReferralEventFields> for ReferralEvent {
+        fn from(fields: ReferralEventFields) -> Self {
+            ReferralEvent::new(fields.referral)
+        }
+    }
+}
+
 #[derive(FromPrimitive, Clone, Copy, CheckedBitPattern, NoUninit)]
 #[repr(u8)]
 pub(crate) enum EventTag {
     Fill,
     Out,
+    Replenish,
+    Boot,
+    Modify,
+    MakerFill,
+    TakerFill,
+    Referral,
+}
+
+pub(crate) type GenericEvent = FillEvent;
+
+pub(crate) trait Event {
+    fn to_generic(&mut self) -> &GenericEvent;
+}
+
+impl Event for FillEvent {
+    fn to_generic(&mut self) -> &GenericEvent {
+        self.0[0] = EventTag::Fill as u8;
+        self
+    }
+}
+
+impl Event for OutEvent {
+    fn to_generic(&mut self) -> &GenericEvent {
+        self.0[0] = EventTag::Out as u8;
+        bytemuck::cast_ref(self)
+    }
+}
+
+impl Event for ReplenishEvent {
+    fn to_generic(&mut self) -> &GenericEvent {
+        self.0[0] = EventTag::Replenish as u8;
+        bytemuck::cast_ref(self)
+    }
+}
+
+impl Event for BootEvent {
+    fn to_generic(&mut self) -> &GenericEvent {
+        self.0[0] = EventTag::Boot as u8;
+        bytemuck::cast_ref(self)
+    }
 }
 
-pub(crate) type GenericEvent = FillEvent;
+impl Event for ModifyEvent {
+    fn to_generic(&mut self) -> &GenericEvent {
+        self.0[0] = EventTag::Modify as u8;
+        bytemuck::cast_ref(self)
+    }
+}
 
-pub(crate) trait Event {
-    fn to_generic(&mut self) -> &GenericEvent;
+impl Event for MakerFillEvent {
+    fn to_generic(&mut self) -> &GenericEvent {
+        self.0 .0[0] = EventTag::MakerFill as u8;
+        bytemuck::cast_ref(self)
+    }
 }
 
-impl Event for FillEvent {
+impl Event for TakerFillEvent {
     fn to_generic(&mut self) -> &GenericEvent {
-        self.0[0] = EventTag::Fill as u8;
-        self
+        self.0 .0[0] = EventTag::TakerFill as u8;
+        bytemuck::cast_ref(self)
     }
 }
 
-impl Event for OutEvent {
+impl Event for ReferralEvent {
     fn to_generic(&mut self) -> &GenericEvent {
-        self.0[0] = EventTag::Out as u8;
+        self.0[0] = EventTag::Referral as u8;
         bytemuck::cast_ref(self)
     }
 }
@@ -251,6 +1038,13 @@ pub struct EventQueueHeader {
     pub count: u64,
     /// The sequence number.
     seq_num: u64,
+    /// The sequence number of the event at the head of the queue.
+    ///
+    /// Every pushed event is assigned the next sequence number, which is never reused, so that consumers can track
+    /// their position in the queue and detect missed events.
+    pub event_seq_num: u64,
+    /// The largest length ever reached by the event queue.
+    pub max_count_seen: u64,
 }
 
 impl EventQueueHeader {
@@ -258,8 +1052,24 @@ impl EventQueueHeader {
     pub const LEN: usize = std::mem::size_of::<Self>();
 }
 
-/// The event queue account contains a serialized header, a register
-/// and a circular buffer of serialized events.
+/// The byte size of the slot which is recorded for each event when it is pushed.
+const PUSH_SLOT_LEN: usize = std::mem::size_of::<u64>();
+
+/// The event queue account contains a serialized header, followed by a circular buffer of serialized events, the
+/// slots at which they were pushed, and their callback informations.
+///
+/// The account data is laid out as follows, for a capacity of `n` events and callback infos of `c` bytes:
+///
+/// | offset                 | length             | description                                                      |
+/// |------------------------|--------------------|------------------------------------------------------------------|
+/// | 0                      | 8                  | The [`AccountTag`] and the layout version                        |
+/// | 8                      | 40                 | The [`EventQueueHeader`]                                         |
+/// | 48                     | `n * 40`           | The events, each in the layout of a [`FillEvent`]                |
+/// | `48 + n * 40`          | `n * 8`            | The slots at which the events were pushed                        |
+/// | `48 + n * 48`          | `n * 2 * c`        | The maker and taker callback infos of each event, in that order  |
+///
+/// The event at position `i` in the queue is stored at index `(head + i) % n` of each array, and its sequence number is
+/// `event_seq_num + i`. [`parse_events`] decodes this layout without knowing the callback info type.
 ///
 /// This struct is used at runtime but doesn't represent a serialized event queue.
 pub struct EventQueue<'a, C> {
@@ -267,8 +1077,18 @@ pub struct EventQueue<'a, C> {
     pub(crate) header: &'a mut EventQueueHeader,
     /// The events in the queue.
     pub(crate) events: &'a mut [FillEvent],
+    /// The slots at which the events in the queue were pushed.
+    pub(crate) push_slots: &'a mut [u64],
     /// The callback information associated with the events in the queue.
     pub(crate) callback_infos: &'a mut [C],
+    /// The slot which is recorded for the events pushed through this object.
+    pub(crate) current_slot: u64,
+    /// Whether the fill and out events pushed through this object are also emitted with `sol_log_data`.
+    pub(crate) log_events: bool,
+    /// What happens when an event is pushed through this object onto a full queue.
+    pub(crate) full_policy: EventQueueFullPolicy,
+    /// The shards of the event queue, between which the pushed events are split.
+    pub(crate) shards: Vec<EventQueue<'a, C>>,
 }
 
 impl<'queue, C: Pod> EventQueue<'queue, C> {
@@ -277,77 +1097,327 @@ impl<'queue, C: Pod> EventQueue<'queue, C> {
         buffer: &'queue mut [u8],
         expected_tag: AccountTag,
     ) -> Result<Self, ProgramError> {
-        match AccountTag::try_from(&buffer[ACCOUNT_TAG_INDEX..ACCOUNT_TAG_LENGTH]) {
+        if buffer.len() < Self::compute_allocation_size(1) {
+            msg!("The event queue account is too small!");
+            return Err(ProgramError::AccountDataTooSmall);
+        }
+        match read_account_tag(buffer) {
             Ok(a) => {
                 if a != expected_tag {
                     msg!("Invalid account tag for event queue!");
                     return Err(ProgramError::InvalidAccountData);
                 }
-                bytemuck::bytes_of(&(AccountTag::EventQueue as u64))
-                    .iter()
-                    .enumerate()
-                    .for_each(|(idx, byte)| buffer[ACCOUNT_TAG_INDEX + idx] = *byte);
+                if a == AccountTag::Uninitialized {
+                    write_account_tag(buffer, AccountTag::EventQueue);
+                } else {
+                    check_account_version(buffer)?;
+                }
             }
             Err(e) => {
                 return Err(e);
             }
         };
 
-        let callback_info_len = std::mem::size_of::<C>();
-        let capacity = (buffer.len() - ACCOUNT_TAG_LENGTH - EventQueueHeader::LEN)
-            / (FillEvent::LEN + 2 * callback_info_len);
+        let capacity = Self::compute_capacity(buffer.len());
 
         let (header, remaining) = buffer[ACCOUNT_TAG_LENGTH..].split_at_mut(EventQueueHeader::LEN);
 
-        let (events, callback_infos) = remaining.split_at_mut(capacity * FillEvent::LEN);
+        let (events, remaining) = remaining.split_at_mut(capacity * FillEvent::LEN);
+        let (push_slots, remaining) = remaining.split_at_mut(capacity * PUSH_SLOT_LEN);
+        // The buffer can be longer than the allocation size of its capacity
+        let callback_infos = &mut remaining[..2 * capacity * std::mem::size_of::<C>()];
         Ok(Self {
             header: bytemuck::from_bytes_mut(header),
             events: bytemuck::cast_slice_mut(events),
+            push_slots: bytemuck::cast_slice_mut(push_slots),
             callback_infos: bytemuck::cast_slice_mut(callback_infos),
+            // The clock isn't available outside of the runtime
+            current_slot: Clock::get().map(|c| c.slot).unwrap_or_default(),
+            log_events: false,
+            full_policy: EventQueueFullPolicy::Reject,
+            shards: Vec::new(),
         })
     }
+
+    /// Instantiates the event queue of a market from the data buffers of its account and of its shards' accounts,
+    /// which should have been checked against the market state.
+    pub(crate) fn from_market_buffers(
+        buffer: &'queue mut [u8],
+        shard_buffers: &'queue mut [RefMut<&mut [u8]>],
+        market_state: &MarketState,
+    ) -> Result<Self, ProgramError> {
+        let mut event_queue = Self::from_buffer(buffer, AccountTag::EventQueue)?;
+        event_queue.shards = shard_buffers
+            .iter_mut()
+            .map(|b| Self::from_buffer(b, AccountTag::EventQueue))
+            .collect::<Result<_, _>>()?;
+        event_queue.log_events = market_state.log_events != 0;
+        event_queue.full_policy =
+            EventQueueFullPolicy::from_u64(market_state.event_queue_full_policy).unwrap();
+        for shard in event_queue.shards.iter_mut() {
+            shard.log_events = event_queue.log_events;
+            shard.full_policy = event_queue.full_policy;
+        }
+        Ok(event_queue)
+    }
 }
 
-impl<'queue, C: Clone> EventQueue<'queue, C> {
+impl<'queue, C: Pod> EventQueue<'queue, C> {
     pub(crate) fn push_back<Ev: Event>(
         &mut self,
         mut event: Ev,
         maker_callback_info: Option<&C>,
         taker_callback_info: Option<&C>,
     ) -> Result<(), Ev> {
+        if let Some(c) = maker_callback_info.filter(|_| !self.shards.is_empty()) {
+            let shard_index = Self::compute_shard_index(c, self.shards.len() + 1);
+            if shard_index != 0 {
+                return self.shards[shard_index - 1].push_back(
+                    event,
+                    maker_callback_info,
+                    taker_callback_info,
+                );
+            }
+        }
         if self.full() {
-            return Err(event);
+            match self.full_policy {
+                EventQueueFullPolicy::Reject => return Err(event),
+                EventQueueFullPolicy::Overwrite => {
+                    // The dropped event is emitted as with the LogOnly policy, so that it can still be recovered
+                    let head = self.header.head as usize;
+                    sol_log_data(&[
+                        &self.events[head].0,
+                        bytemuck::bytes_of(&self.callback_infos[2 * head]),
+                        bytemuck::bytes_of(&self.callback_infos[2 * head + 1]),
+                    ]);
+                    self.pop_n(1)
+                }
+                EventQueueFullPolicy::LogOnly => {
+                    let zeroed = C::zeroed();
+                    sol_log_data(&[
+                        &event.to_generic().0,
+                        bytemuck::bytes_of(maker_callback_info.unwrap_or(&zeroed)),
+                        bytemuck::bytes_of(taker_callback_info.unwrap_or(&zeroed)),
+                    ]);
+                    return Ok(());
+                }
+            }
         }
         let generic_event = event.to_generic();
         let event_idx =
             (self.header.head as usize + self.header.count as usize) % self.events.len();
         self.events[event_idx] = *generic_event;
+        self.push_slots[event_idx] = self.current_slot;
 
         self.header.count += 1;
+        self.header.max_count_seen = std::cmp::max(self.header.max_count_seen, self.header.count);
 
-        if let Some(c) = maker_callback_info {
-            self.callback_infos[event_idx * 2] = c.clone();
-        }
+        // Missing callback infos are zeroed out, so that the stale ones of a previous event are never read back
+        self.callback_infos[event_idx * 2] = maker_callback_info.copied().unwrap_or_else(C::zeroed);
+        self.callback_infos[event_idx * 2 + 1] =
+            taker_callback_info.copied().unwrap_or_else(C::zeroed);
 
-        if let Some(c) = taker_callback_info {
-            self.callback_infos[event_idx * 2 + 1] = c.clone();
+        if self.log_events {
+            self.log_event(event_idx);
         }
 
         Ok(())
     }
 }
 
+impl<'queue, C: Pod> EventQueue<'queue, C> {
+    /// Computes the index of the queue which receives the events of a given maker when the events are split between
+    /// `number_of_queues` queues, 0 being the main event queue.
+    ///
+    /// All the events of a maker are routed to the same queue, so that they remain ordered.
+    pub fn compute_shard_index(maker_callback_info: &C, number_of_queues: usize) -> usize {
+        // FNV-1a
+        let hash = bytemuck::bytes_of(maker_callback_info)
+            .iter()
+            .fold(0xcbf29ce484222325u64, |h, b| {
+                (h ^ *b as u64).wrapping_mul(0x100000001b3)
+            });
+        (hash % number_of_queues as u64) as usize
+    }
+
+    /// Emits the event at position `event_idx` of the circular buffer as a binary program log, along with its callback
+    /// informations, if it is a fill or an out event.
+    ///
+    /// The log data is made of the serialized event, followed by the maker callback info and, for
+    /// [`FillEvent`] objects, the taker callback info.
+    fn log_event(&self, event_idx: usize) {
+        let event = &self.events[event_idx];
+        let maker_callback_info = bytemuck::bytes_of(&self.callback_infos[2 * event_idx]);
+        let taker_callback_info = bytemuck::bytes_of(&self.callback_infos[2 * event_idx + 1]);
+        match event.tag() {
+            EventTag::Fill => sol_log_data(&[&event.0, maker_callback_info, taker_callback_info]),
+            EventTag::Out | EventTag::MakerFill | EventTag::TakerFill | EventTag::Referral => {
+                sol_log_data(&[&event.0, maker_callback_info])
+            }
+            _ => {}
+        }
+    }
+
+    /// Serializes the `n` events at the front of the queue, as written into the register by a consume_events
+    /// instruction.
+    ///
+    /// The output is made of the number of serialized events as a little-endian `u32`, followed by each event and its
+    /// maker and taker callback infos. It can be read back with [`read_consumed_events`].
+    pub fn serialize_front(&self, n: u64) -> Vec<u8> {
+        let n = std::cmp::min(n, self.header.count);
+        let mut data =
+            Vec::with_capacity(4 + n as usize * (FillEvent::LEN + 2 * std::mem::size_of::<C>()));
+        data.extend_from_slice(&(n as u32).to_le_bytes());
+        for i in 0..n {
+            let event_idx = (self.header.head + i) as usize % self.events.len();
+            data.extend_from_slice(&self.events[event_idx].0);
+            data.extend_from_slice(bytemuck::bytes_of(&self.callback_infos[2 * event_idx]));
+            data.extend_from_slice(bytemuck::bytes_of(&self.callback_infos[2 * event_idx + 1]));
+        }
+        data
+    }
+
+    /// Moves the `n` events at the front of the queue to the back of `destination`, along with their push slots and
+    /// callback informations.
+    ///
+    /// Returns the number of events which were moved, which is capped by the remaining capacity of `destination`.
+    pub(crate) fn move_front_to(&mut self, destination: &mut EventQueue<C>, n: u64) -> u64 {
+        let n = std::cmp::min(
+            std::cmp::min(n, self.header.count),
+            destination.remaining_capacity(),
+        );
+        for i in 0..n {
+            let src_idx = (self.header.head + i) as usize % self.events.len();
+            let dst_idx = (destination.header.head + destination.header.count) as usize
+                % destination.events.len();
+            destination.events[dst_idx] = self.events[src_idx];
+            destination.push_slots[dst_idx] = self.push_slots[src_idx];
+            destination.callback_infos[2 * dst_idx] = self.callback_infos[2 * src_idx];
+            destination.callback_infos[2 * dst_idx + 1] = self.callback_infos[2 * src_idx + 1];
+            destination.header.count += 1;
+            destination.header.max_count_seen =
+                std::cmp::max(destination.header.max_count_seen, destination.header.count);
+        }
+        self.pop_n(n);
+        n
+    }
+}
+
+/// Computes the allocation size of an event queue account (or of a dead letter queue or an event queue shard) which
+/// holds up to `event_capacity` events with callback informations of `callback_info_len` bytes.
+pub fn compute_event_queue_size(event_capacity: usize, callback_info_len: usize) -> usize {
+    event_capacity * (FillEvent::LEN + PUSH_SLOT_LEN + 2 * callback_info_len)
+        + EventQueueHeader::LEN
+        + ACCOUNT_TAG_LENGTH
+}
+
+/// The length in bytes of the header of the event queues created before versioning was introduced, which only held
+/// the head, the length and the order sequence number of the queue.
+pub const UNVERSIONED_EVENT_QUEUE_HEADER_LEN: usize = 24;
+
+/// Computes the length to which the data of an event queue account with the layout version `version` should be grown
+/// so that it can be migrated to the current layout version, keeping its capacity.
+pub(crate) fn compute_migrated_event_queue_len(
+    data_len: usize,
+    version: u8,
+    callback_info_len: usize,
+) -> usize {
+    if version != 0 {
+        return data_len;
+    }
+    let capacity = (data_len - ACCOUNT_TAG_LENGTH - UNVERSIONED_EVENT_QUEUE_HEADER_LEN)
+        / (FillEvent::LEN + 2 * callback_info_len);
+    data_len + EventQueueHeader::LEN - UNVERSIONED_EVENT_QUEUE_HEADER_LEN + capacity * PUSH_SLOT_LEN
+}
+
+/// Moves the contents of an event queue buffer created before versioning was introduced, which has just been grown to
+/// the length returned by [`compute_migrated_event_queue_len`], so as to upgrade it to the current layout.
+///
+/// The pending events keep their position in the queue. Their push slots are zeroed out, and the event sequence
+/// number of the queue starts from zero.
+pub(crate) fn migrate_unversioned_event_queue(buffer: &mut [u8], callback_info_len: usize) {
+    let slot_size = FillEvent::LEN + PUSH_SLOT_LEN + 2 * callback_info_len;
+    // The grown buffer has the capacity of the original queue in the current layout
+    let capacity = (buffer.len() - ACCOUNT_TAG_LENGTH - EventQueueHeader::LEN) / slot_size;
+    let old_events_start = ACCOUNT_TAG_LENGTH + UNVERSIONED_EVENT_QUEUE_HEADER_LEN;
+    let new_events_start = ACCOUNT_TAG_LENGTH + EventQueueHeader::LEN;
+    let old_callback_infos_start = old_events_start + capacity * FillEvent::LEN;
+    let new_push_slots_start = new_events_start + capacity * FillEvent::LEN;
+    let new_callback_infos_start = new_push_slots_start + capacity * PUSH_SLOT_LEN;
+    let callback_infos_len = 2 * capacity * callback_info_len;
+
+    // The callback infos are moved first, since the events are moved over their old location
+    buffer.copy_within(
+        old_callback_infos_start..old_callback_infos_start + callback_infos_len,
+        new_callback_infos_start,
+    );
+    buffer.copy_within(old_events_start..old_callback_infos_start, new_events_start);
+    buffer[new_push_slots_start..new_callback_infos_start].fill(0);
+    buffer[old_events_start..new_events_start].fill(0);
+    let header = bytemuck::from_bytes_mut::<EventQueueHeader>(
+        &mut buffer[ACCOUNT_TAG_LENGTH..new_events_start],
+    );
+    header.max_count_seen = header.count;
+}
+
 impl<'queue, C> EventQueue<'queue, C> {
     /// Compute the allocation size for an event queue of a desired capacity
     pub fn compute_allocation_size(desired_event_capacity: usize) -> usize {
-        desired_event_capacity * (FillEvent::LEN + 2 * std::mem::size_of::<C>())
-            + EventQueueHeader::LEN
-            + ACCOUNT_TAG_LENGTH
+        compute_event_queue_size(desired_event_capacity, std::mem::size_of::<C>())
+    }
+
+    /// The number of bytes taken up by each event in the queue, including its push slot and callback informations
+    fn compute_slot_size() -> usize {
+        FillEvent::LEN + PUSH_SLOT_LEN + 2 * std::mem::size_of::<C>()
+    }
+
+    fn compute_capacity(buffer_len: usize) -> usize {
+        (buffer_len - ACCOUNT_TAG_LENGTH - EventQueueHeader::LEN) / Self::compute_slot_size()
+    }
+
+    /// Moves the contents of an event queue buffer which has just been grown from `old_len` bytes to its current
+    /// length, so that it can be parsed again with its new capacity.
+    ///
+    /// The pending events are unwrapped to the start of the circular buffer, and keep their order.
+    pub(crate) fn grow(buffer: &mut [u8], old_len: usize) {
+        let callback_info_len = std::mem::size_of::<C>();
+        let old_capacity = Self::compute_capacity(old_len);
+        let new_capacity = Self::compute_capacity(buffer.len());
+        let events_start = ACCOUNT_TAG_LENGTH + EventQueueHeader::LEN;
+        let old_push_slots_start = events_start + old_capacity * FillEvent::LEN;
+        let new_push_slots_start = events_start + new_capacity * FillEvent::LEN;
+        let push_slots_len = old_capacity * PUSH_SLOT_LEN;
+        let old_callback_infos_start = old_push_slots_start + push_slots_len;
+        let new_callback_infos_start = new_push_slots_start + new_capacity * PUSH_SLOT_LEN;
+        let callback_infos_len = 2 * old_capacity * callback_info_len;
+
+        let header = bytemuck::from_bytes_mut::<EventQueueHeader>(
+            &mut buffer[ACCOUNT_TAG_LENGTH..events_start],
+        );
+        let head = header.head as usize;
+        header.head = 0;
+
+        buffer[events_start..old_push_slots_start].rotate_left(head * FillEvent::LEN);
+        buffer[old_push_slots_start..old_callback_infos_start].rotate_left(head * PUSH_SLOT_LEN);
+        buffer[old_callback_infos_start..old_callback_infos_start + callback_infos_len]
+            .rotate_left(2 * head * callback_info_len);
+        // The callback infos are moved first, since the push slots are moved over their old location
+        buffer.copy_within(
+            old_callback_infos_start..old_callback_infos_start + callback_infos_len,
+            new_callback_infos_start,
+        );
+        buffer.copy_within(
+            old_push_slots_start..old_callback_infos_start,
+            new_push_slots_start,
+        );
+        buffer[old_push_slots_start..new_push_slots_start].fill(0);
+        buffer[new_push_slots_start + push_slots_len..new_callback_infos_start].fill(0);
+        buffer[new_callback_infos_start + callback_infos_len..].fill(0);
     }
 
     pub(crate) fn check_buffer_size(buffer: &[u8]) -> ProgramResult {
         const HEADER_OFFSET: usize = EventQueueHeader::LEN + 8;
-        let event_size: usize = FillEvent::LEN + 2 * std::mem::size_of::<C>();
+        let event_size: usize = Self::compute_slot_size();
         let account_len = buffer.len();
         if account_len < HEADER_OFFSET + 5 * event_size {
             msg!("The event queue account is too small!");
@@ -362,12 +1432,7 @@ impl<'queue, C> EventQueue<'queue, C> {
 
     pub(crate) fn gen_order_id(&mut self, limit_price: u64, side: Side) -> u128 {
         let seq_num = self.gen_seq_num();
-        let upper = (limit_price as u128) << 64;
-        let lower = match side {
-            Side::Bid => !seq_num,
-            Side::Ask => seq_num,
-        };
-        upper | (lower as u128)
+        OrderId::new(limit_price, seq_num, side).into()
     }
 
     fn gen_seq_num(&mut self) -> u64 {
@@ -390,6 +1455,18 @@ impl<'queue, C> EventQueue<'queue, C> {
         Some(self.get_event(event_idx))
     }
 
+    /// Returns an iterator over the first `n` events of the queue, or over all of its events if it holds less than `n`.
+    ///
+    /// This is meant for crankers, which can decode a batch of events without computing the offsets of the
+    /// circular buffer themselves.
+    pub fn peek_n(&self, n: u64) -> impl Iterator<Item = EventRef<'_, C>> {
+        QueueIterator {
+            queue: self,
+            current_index: 0,
+            remaining: std::cmp::min(n, self.header.count),
+        }
+    }
+
     fn get_event(&self, event_idx: usize) -> EventRef<'_, C> {
         let event = &self.events[event_idx];
         match event.tag() {
@@ -402,13 +1479,69 @@ impl<'queue, C> EventQueue<'queue, C> {
                 event: bytemuck::cast_ref(event),
                 callback_info: &self.callback_infos[2 * event_idx],
             }),
+            EventTag::Replenish => EventRef::Replenish(ReplenishEventRef {
+                event: bytemuck::cast_ref(event),
+                callback_info: &self.callback_infos[2 * event_idx],
+            }),
+            EventTag::Boot => EventRef::Boot(BootEventRef {
+                event: bytemuck::cast_ref(event),
+                callback_info: &self.callback_infos[2 * event_idx],
+            }),
+            EventTag::Modify => EventRef::Modify(ModifyEventRef {
+                event: bytemuck::cast_ref(event),
+                callback_info: &self.callback_infos[2 * event_idx],
+            }),
+            EventTag::MakerFill => EventRef::MakerFill(MakerFillEventRef {
+                event: bytemuck::cast_ref(event),
+                callback_info: &self.callback_infos[2 * event_idx],
+            }),
+            EventTag::TakerFill => EventRef::TakerFill(TakerFillEventRef {
+                event: bytemuck::cast_ref(event),
+                callback_info: &self.callback_infos[2 * event_idx],
+            }),
+            EventTag::Referral => EventRef::Referral(ReferralEventRef {
+                event: bytemuck::cast_ref(event),
+                callback_info: &self.callback_infos[2 * event_idx],
+            }),
+        }
+    }
+
+    /// Returns the slot at which the event at position `index` in the queue was pushed.
+    pub fn push_slot_at(&self, index: u64) -> Option<u64> {
+        if self.header.count <= index {
+            return None;
         }
+        Some(self.push_slots[(self.header.head + index) as usize % self.events.len()])
+    }
+
+    /// Returns the number of events at the front of the queue which were pushed strictly before `slot`.
+    pub fn count_pushed_before(&self, slot: u64) -> u64 {
+        (0..self.header.count)
+            .take_while(|&i| self.push_slot_at(i).unwrap() < slot)
+            .count() as u64
+    }
+
+    /// Returns the sequence number of the event at position `index` in the queue.
+    pub fn seq_num_at(&self, index: u64) -> u64 {
+        self.header.event_seq_num + index
+    }
+
+    /// Returns the number of events at the front of the queue whose sequence number doesn't exceed `max_seq_num`.
+    pub fn count_up_to(&self, max_seq_num: u64) -> u64 {
+        if max_seq_num < self.header.event_seq_num {
+            return 0;
+        }
+        std::cmp::min(
+            self.header.count,
+            (max_seq_num - self.header.event_seq_num).saturating_add(1),
+        )
     }
 
     /// Pop n entries from the event queue
     pub fn pop_n(&mut self, number_of_entries_to_pop: u64) {
         let capped_number_of_entries_to_pop =
             std::cmp::min(self.header.count, number_of_entries_to_pop);
+        self.header.event_seq_num += capped_number_of_entries_to_pop;
         self.header.count -= capped_number_of_entries_to_pop;
         self.header.head =
             (self.header.head + capped_number_of_entries_to_pop) % (self.events.len() as u64);
@@ -432,6 +1565,193 @@ impl<'queue, C> EventQueue<'queue, C> {
     pub fn len(&self) -> u64 {
         self.header.count
     }
+
+    /// Returns the maximum number of events which the event queue can hold
+    pub fn capacity(&self) -> u64 {
+        self.events.len() as u64
+    }
+
+    /// Returns the largest length ever reached by the event queue, which can be used to monitor how close it has
+    /// come to being full
+    pub fn max_count_seen(&self) -> u64 {
+        self.header.max_count_seen
+    }
+
+    /// Returns the number of events which can still be pushed onto the event queue
+    ///
+    /// When the event queue has shards, this is the smallest remaining capacity of the event queue and its shards,
+    /// since all the events of an instruction could be routed to the same queue. It is unbounded when the events
+    /// pushed onto a full queue aren't rejected.
+    pub fn remaining_capacity(&self) -> u64 {
+        if self.full_policy != EventQueueFullPolicy::Reject {
+            return u64::MAX;
+        }
+        self.shards
+            .iter()
+            .map(|s| s.remaining_capacity())
+            .fold(self.events.len() as u64 - self.header.count, std::cmp::min)
+    }
+}
+
+/// Computes the size of a register account which can hold `n` events written by a consume_events instruction.
+pub fn compute_consumed_events_register_size<C>(n: usize) -> usize {
+    4 + n * (FillEvent::LEN + 2 * std::mem::size_of::<C>())
+}
+
+/// Reads back the events written into a register account by a consume_events instruction, along with their maker and
+/// taker callback infos.
+///
+/// The taker callback info is only meaningful for [`FillEvent`] objects.
+pub fn read_consumed_events<C: Pod>(
+    register_data: &[u8],
+) -> Result<Vec<(FillEvent, C, C)>, ProgramError> {
+    let entry_len = FillEvent::LEN + 2 * std::mem::size_of::<C>();
+    let count = register_data
+        .get(..4)
+        .map(|c| u32::from_le_bytes(c.try_into().unwrap()) as usize)
+        .ok_or(ProgramError::InvalidAccountData)?;
+    let entries = register_data
+        .get(4..4 + count * entry_len)
+        .ok_or(ProgramError::InvalidAccountData)?;
+    Ok(entries
+        .chunks_exact(entry_len)
+        .map(|entry| {
+            let (event, callback_infos) = entry.split_at(FillEvent::LEN);
+            let (maker_callback_info, taker_callback_info) =
+                callback_infos.split_at(std::mem::size_of::<C>());
+            (
+                bytemuck::pod_read_unaligned(event),
+                bytemuck::pod_read_unaligned(maker_callback_info),
+                bytemuck::pod_read_unaligned(taker_callback_info),
+            )
+        })
+        .collect())
+}
+
+/// An event decoded from the data of an event queue account by [`parse_events`], along with its callback infos.
+#[derive(Debug, Clone, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum DecodedEvent {
+    #[allow(missing_docs)]
+    Fill {
+        event: FillEvent,
+        maker_callback_info: Vec<u8>,
+        taker_callback_info: Vec<u8>,
+    },
+    #[allow(missing_docs)]
+    Out {
+        event: OutEvent,
+        callback_info: Vec<u8>,
+    },
+    #[allow(missing_docs)]
+    Replenish {
+        event: ReplenishEvent,
+        callback_info: Vec<u8>,
+    },
+    #[allow(missing_docs)]
+    Boot {
+        event: BootEvent,
+        callback_info: Vec<u8>,
+    },
+    #[allow(missing_docs)]
+    Modify {
+        event: ModifyEvent,
+        callback_info: Vec<u8>,
+    },
+    #[allow(missing_docs)]
+    MakerFill {
+        event: MakerFillEvent,
+        callback_info: Vec<u8>,
+    },
+    #[allow(missing_docs)]
+    TakerFill {
+        event: TakerFillEvent,
+        callback_info: Vec<u8>,
+    },
+    #[allow(missing_docs)]
+    Referral {
+        event: ReferralEvent,
+        callback_info: Vec<u8>,
+    },
+}
+
+/// Decodes the events held in the data of an event queue account whose callback infos are `callback_info_len` bytes
+/// long, without needing a mutable borrow of the data or knowing the callback info type.
+///
+/// The events are returned from the front of the queue, along with their sequence numbers. This is meant for indexers
+/// which decode snapshots of the account fetched over RPC.
+pub fn parse_events(
+    data: &[u8],
+    callback_info_len: usize,
+) -> Result<Vec<(u64, DecodedEvent)>, ProgramError> {
+    if read_account_tag(data)? != AccountTag::EventQueue {
+        msg!("Invalid account tag for event queue!");
+        return Err(ProgramError::InvalidAccountData);
+    }
+    check_account_version(data)?;
+    let capacity = data
+        .len()
+        .checked_sub(ACCOUNT_TAG_LENGTH + EventQueueHeader::LEN)
+        .ok_or(ProgramError::InvalidAccountData)?
+        / (FillEvent::LEN + PUSH_SLOT_LEN + 2 * callback_info_len);
+    if capacity == 0 {
+        return Err(ProgramError::InvalidAccountData);
+    }
+
+    let (header, remaining) = data[ACCOUNT_TAG_LENGTH..].split_at(EventQueueHeader::LEN);
+    let (events, remaining) = remaining.split_at(capacity * FillEvent::LEN);
+    let callback_infos = &remaining[capacity * PUSH_SLOT_LEN..];
+    let header = bytemuck::pod_read_unaligned::<EventQueueHeader>(header);
+    let events = bytemuck::cast_slice::<_, FillEvent>(events);
+
+    (0..header.count)
+        .map(|i| {
+            let event_idx = (header.head + i) as usize % capacity;
+            let event = events[event_idx];
+            let offset = 2 * event_idx * callback_info_len;
+            let callback_info = callback_infos[offset..offset + callback_info_len].to_vec();
+            let decoded = match EventTag::from_u8(event.0[FillEvent::TAG_INDEX])
+                .ok_or(ProgramError::InvalidAccountData)?
+            {
+                EventTag::Fill => DecodedEvent::Fill {
+                    event,
+                    maker_callback_info: callback_info,
+                    taker_callback_info: callback_infos
+                        [offset + callback_info_len..offset + 2 * callback_info_len]
+                        .to_vec(),
+                },
+                EventTag::Out => DecodedEvent::Out {
+                    event: bytemuck::cast(event),
+                    callback_info,
+                },
+                EventTag::Replenish => DecodedEvent::Replenish {
+                    event: bytemuck::cast(event),
+                    callback_info,
+                },
+                EventTag::Boot => DecodedEvent::Boot {
+                    event: bytemuck::cast(event),
+                    callback_info,
+                },
+                EventTag::Modify => DecodedEvent::Modify {
+                    event: bytemuck::cast(event),
+                    callback_info,
+                },
+                EventTag::MakerFill => DecodedEvent::MakerFill {
+                    event: bytemuck::cast(event),
+                    callback_info,
+                },
+                EventTag::TakerFill => DecodedEvent::TakerFill {
+                    event: bytemuck::cast(event),
+                    callback_info,
+                },
+                EventTag::Referral => DecodedEvent::Referral {
+                    event: bytemuck::cast(event),
+                    callback_info,
+                },
+            };
+            Ok((header.event_seq_num + i, decoded))
+        })
+        .collect()
 }
 
 /// Utility struct for iterating over a queue
@@ -459,6 +1779,7 @@ impl<'a, C> Iterator for QueueIterator<'a, C> {
 #[cfg(test)]
 mod tests {
     use super::*;
+    use crate::state::migrate_account;
 
     type EventQueueTest<'a> = EventQueue<'a, [u8; 32]>;
 
@@ -487,6 +1808,8 @@ mod tests {
                             seq_gen.next().unwrap(),
                             seq_gen.next().unwrap() as u128,
                             seq_gen.next().unwrap(),
+                            0,
+                            0,
                         ),
                         Some(&[seq_gen.next().unwrap() as u8; 32]),
                         Some(&[seq_gen.next().unwrap() as u8; 32]),
@@ -511,6 +1834,8 @@ mod tests {
             seq_gen.next().unwrap(),
             seq_gen.next().unwrap() as u128,
             seq_gen.next().unwrap(),
+            0,
+            0,
         );
         assert_eq!(
             extra_event,
@@ -521,6 +1846,11 @@ mod tests {
         let mut parity_gen = 0..;
 
         assert!(event_queue.peek_at(100).is_none());
+        assert_eq!(event_queue.peek_n(10).count(), 10);
+        assert_eq!(event_queue.peek_n(1000).count(), 100);
+        assert!(event_queue
+            .peek_n(10)
+            .eq((0..10).map(|i| event_queue.peek_at(i).unwrap())));
 
         for (i, e) in event_queue.iter().enumerate() {
             let is_fill = parity_gen.next().unwrap() % 7 != 3;
@@ -549,6 +1879,8 @@ mod tests {
                                 seq_gen.next().unwrap(),
                                 seq_gen.next().unwrap() as u128,
                                 seq_gen.next().unwrap(),
+                                0,
+                                0,
                             ),
                             maker_callback_info: &[seq_gen.next().unwrap() as u8; 32],
                             taker_callback_info: &[seq_gen.next().unwrap() as u8; 32]
@@ -556,9 +1888,495 @@ mod tests {
                     );
                     assert_eq!(EventRef::Fill(e), event_queue.peek_at(i as u64).unwrap());
                 }
+                _ => unreachable!(),
             }
             number_of_events = i + 1;
         }
         assert_eq!(number_of_events, 100);
     }
+
+    #[test]
+    fn test_order_id() {
+        let allocation_size = EventQueue::<[u8; 32]>::compute_allocation_size(10);
+        let mut buffer = vec![0; allocation_size];
+        let mut event_queue =
+            EventQueueTest::from_buffer(&mut buffer, AccountTag::Uninitialized).unwrap();
+
+        let ask_id = OrderId(event_queue.gen_order_id(10 << 32, Side::Ask));
+        let first_bid_id = OrderId(event_queue.gen_order_id(9 << 32, Side::Bid));
+        let second_bid_id = OrderId(event_queue.gen_order_id(9 << 32, Side::Bid));
+
+        assert_eq!(ask_id.price(), 10 << 32);
+        assert_eq!(ask_id.sequence(), 0);
+        assert_eq!(ask_id.side(), Side::Ask);
+        assert_eq!(first_bid_id.price(), 9 << 32);
+        assert_eq!(first_bid_id.sequence(), 1);
+        assert_eq!(first_bid_id.side(), Side::Bid);
+        assert_eq!(second_bid_id.sequence(), 2);
+        assert_eq!(OrderId::new(9 << 32, 2, Side::Bid), second_bid_id);
+        // The oldest bid at a given price has the highest order id
+        assert!(first_bid_id > second_bid_id);
+    }
+
+    #[test]
+    fn test_event_queue_grow() {
+        let mut buffer = vec![0; EventQueueTest::compute_allocation_size(5)];
+        let mut event_queue =
+            EventQueueTest::from_buffer(&mut buffer, AccountTag::Uninitialized).unwrap();
+        // The pending events wrap around the end of the circular buffer
+        for i in 0..5 {
+            event_queue
+                .push_back(
+                    OutEvent::new(Side::Ask, i, i as u128),
+                    Some(&[i as u8; 32]),
+                    None,
+                )
+                .unwrap();
+        }
+        event_queue.pop_n(3);
+        for i in 5..8 {
+            event_queue.current_slot = i;
+            event_queue
+                .push_back(
+                    OutEvent::new(Side::Ask, i, i as u128),
+                    Some(&[i as u8; 32]),
+                    None,
+                )
+                .unwrap();
+        }
+
+        let old_len = buffer.len();
+        buffer.resize(EventQueueTest::compute_allocation_size(10), 0);
+        EventQueueTest::grow(&mut buffer, old_len);
+        let mut event_queue =
+            EventQueueTest::from_buffer(&mut buffer, AccountTag::EventQueue).unwrap();
+        assert_eq!(event_queue.len(), 5);
+        assert_eq!(event_queue.remaining_capacity(), 5);
+        assert_eq!(event_queue.push_slot_at(0), Some(0));
+        assert_eq!(event_queue.push_slot_at(4), Some(7));
+        for i in 8..13 {
+            event_queue
+                .push_back(
+                    OutEvent::new(Side::Ask, i, i as u128),
+                    Some(&[i as u8; 32]),
+                    None,
+                )
+                .unwrap();
+        }
+        assert!(event_queue.full());
+        for (e, i) in event_queue.iter().zip(3..) {
+            assert_eq!(
+                e,
+                EventRef::Out(OutEventRef {
+                    event: &OutEvent::new(Side::Ask, i, i as u128),
+                    callback_info: &[i as u8; 32]
+                })
+            );
+        }
+    }
+
+    #[test]
+    fn test_event_queue_migration() {
+        // An event queue created before versioning, with a smaller header and no push slots, whose pending events wrap
+        // around the end of the circular buffer
+        let capacity = 5u64;
+        let mut buffer = vec![0; ACCOUNT_TAG_LENGTH + UNVERSIONED_EVENT_QUEUE_HEADER_LEN];
+        buffer[0] = AccountTag::EventQueue as u8;
+        buffer[ACCOUNT_TAG_LENGTH..].copy_from_slice(bytemuck::cast_slice(&[3u64, 4, 42]));
+        let mut callback_infos = vec![];
+        for position in 0..capacity {
+            let i = (position + capacity - 3) % capacity;
+            buffer.extend_from_slice(bytemuck::bytes_of(&OutEvent::new(Side::Bid, i, i as u128)));
+            callback_infos.extend_from_slice(&[i as u8; 64]);
+        }
+        buffer.extend_from_slice(&callback_infos);
+
+        let new_len = compute_migrated_event_queue_len(buffer.len(), 0, 32);
+        assert_eq!(
+            new_len,
+            EventQueueTest::compute_allocation_size(capacity as usize)
+        );
+        buffer.resize(new_len, 0);
+        assert!(EventQueueTest::from_buffer(&mut buffer, AccountTag::EventQueue).is_err());
+        assert!(migrate_account::<[u8; 32]>(&mut buffer, AccountTag::EventQueue).unwrap());
+
+        let mut event_queue =
+            EventQueueTest::from_buffer(&mut buffer, AccountTag::EventQueue).unwrap();
+        assert_eq!(event_queue.header.seq_num, 42);
+        assert_eq!(event_queue.header.max_count_seen, 4);
+        assert_eq!(event_queue.seq_num_at(0), 0);
+        assert_eq!(event_queue.push_slot_at(0), Some(0));
+        event_queue
+            .push_back(OutEvent::new(Side::Bid, 4, 4), Some(&[4; 32]), None)
+            .unwrap();
+        assert!(event_queue.full());
+        for (e, i) in event_queue.iter().zip(0..) {
+            assert_eq!(
+                e,
+                EventRef::Out(OutEventRef {
+                    event: &OutEvent::new(Side::Bid, i, i as u128),
+                    callback_info: &[i as u8; 32]
+                })
+            );
+        }
+    }
+
+    #[test]
+    fn test_event_seq_num() {
+        let mut buffer = vec![0; EventQueueTest::compute_allocation_size(5)];
+        let mut event_queue =
+            EventQueueTest::from_buffer(&mut buffer, AccountTag::Uninitialized).unwrap();
+        for i in 0..4 {
+            event_queue
+                .push_back(OutEvent::new(Side::Ask, i, i as u128), Some(&[0; 32]), None)
+                .unwrap();
+        }
+        assert_eq!(event_queue.seq_num_at(0), 0);
+        assert_eq!(event_queue.count_up_to(1), 2);
+        assert_eq!(event_queue.count_up_to(u64::MAX), 4);
+
+        event_queue.pop_n(event_queue.count_up_to(1));
+        assert_eq!(event_queue.seq_num_at(0), 2);
+        assert_eq!(event_queue.count_up_to(1), 0);
+        assert_eq!(event_queue.count_up_to(2), 1);
+        // Sequence numbers keep increasing as the circular buffer wraps around
+        for i in 4..7 {
+            event_queue
+                .push_back(OutEvent::new(Side::Ask, i, i as u128), Some(&[0; 32]), None)
+                .unwrap();
+        }
+        event_queue.pop_n(4);
+        assert_eq!(event_queue.seq_num_at(0), 6);
+        match event_queue.peek_at(0).unwrap() {
+            EventRef::Out(o) => assert_eq!(o.event.order_id(), 6),
+            _ => unreachable!(),
+        }
+    }
+
+    #[test]
+    fn test_event_queue_full_policy() {
+        let mut buffer = vec![0; EventQueueTest::compute_allocation_size(3)];
+        let mut event_queue =
+            EventQueueTest::from_buffer(&mut buffer, AccountTag::Uninitialized).unwrap();
+        for i in 0..3 {
+            event_queue
+                .push_back(OutEvent::new(Side::Ask, i, i as u128), Some(&[0; 32]), None)
+                .unwrap();
+        }
+        assert!(event_queue
+            .push_back(OutEvent::new(Side::Ask, 3, 3), Some(&[0; 32]), None)
+            .is_err());
+
+        // The oldest events are overwritten, and the sequence numbers reveal that they were dropped
+        event_queue.full_policy = EventQueueFullPolicy::Overwrite;
+        assert_eq!(event_queue.remaining_capacity(), u64::MAX);
+        for i in 3..5 {
+            event_queue
+                .push_back(OutEvent::new(Side::Ask, i, i as u128), Some(&[0; 32]), None)
+                .unwrap();
+        }
+        assert_eq!(event_queue.len(), 3);
+        assert_eq!(event_queue.seq_num_at(0), 2);
+        match event_queue.peek_at(0).unwrap() {
+            EventRef::Out(o) => assert_eq!(o.event.order_id(), 2),
+            _ => unreachable!(),
+        }
+
+        // The events are only logged, and the queue is left untouched
+        event_queue.full_policy = EventQueueFullPolicy::LogOnly;
+        event_queue
+            .push_back(OutEvent::new(Side::Ask, 5, 5), Some(&[0; 32]), None)
+            .unwrap();
+        assert_eq!(event_queue.len(), 3);
+        assert_eq!(event_queue.seq_num_at(0), 2);
+        assert_eq!(event_queue.seq_num_at(2), 4);
+    }
+
+    #[test]
+    fn test_event_queue_overwrite_gap() {
+        let mut buffer = vec![0; EventQueueTest::compute_allocation_size(3)];
+        let mut event_queue =
+            EventQueueTest::from_buffer(&mut buffer, AccountTag::Uninitialized).unwrap();
+        event_queue.full_policy = EventQueueFullPolicy::Overwrite;
+        for i in 0..3 {
+            event_queue
+                .push_back(
+                    OutEvent::new(Side::Ask, i, i as u128),
+                    Some(&[i as u8; 32]),
+                    None,
+                )
+                .unwrap();
+        }
+        // The consumer processes the first event and expects the sequence number 1 next
+        event_queue.pop_n(1);
+        let next_seq_num = 1;
+
+        // Two unconsumed events are overwritten before the consumer comes back
+        for i in 3..6 {
+            event_queue
+                .push_back(
+                    OutEvent::new(Side::Ask, i, i as u128),
+                    Some(&[i as u8; 32]),
+                    None,
+                )
+                .unwrap();
+        }
+        assert_eq!(event_queue.len(), 3);
+        assert_eq!(event_queue.seq_num_at(0) - next_seq_num, 2);
+        for (i, event) in event_queue.iter().enumerate() {
+            match event {
+                EventRef::Out(o) => {
+                    assert_eq!(o.event.order_id(), i as u128 + 3);
+                    assert_eq!(o.callback_info, &[i as u8 + 3; 32]);
+                }
+                _ => unreachable!(),
+            }
+        }
+    }
+
+    #[test]
+    fn test_event_queue_metrics() {
+        let mut buffer = vec![0; compute_event_queue_size(5, 32)];
+        let mut event_queue =
+            EventQueueTest::from_buffer(&mut buffer, AccountTag::Uninitialized).unwrap();
+        assert_eq!(event_queue.capacity(), 5);
+        assert_eq!(event_queue.max_count_seen(), 0);
+        for i in 0..3 {
+            event_queue
+                .push_back(OutEvent::new(Side::Ask, i, i as u128), Some(&[0; 32]), None)
+                .unwrap();
+        }
+        assert_eq!(event_queue.max_count_seen(), 3);
+        event_queue.pop_n(3);
+        event_queue
+            .push_back(OutEvent::new(Side::Ask, 3, 3), Some(&[0; 32]), None)
+            .unwrap();
+        assert_eq!(event_queue.len(), 1);
+        assert_eq!(event_queue.max_count_seen(), 3);
+        assert_eq!(event_queue.remaining_capacity(), 4);
+    }
+
+    #[test]
+    fn test_event_queue_expiry() {
+        let mut buffer = vec![0; EventQueueTest::compute_allocation_size(5)];
+        let mut event_queue =
+            EventQueueTest::from_buffer(&mut buffer, AccountTag::Uninitialized).unwrap();
+        let mut dead_letter_buffer = vec![0; EventQueueTest::compute_allocation_size(5)];
+        let mut dead_letter_queue =
+            EventQueueTest::from_buffer(&mut dead_letter_buffer, AccountTag::Uninitialized)
+                .unwrap();
+        for i in 0..4 {
+            event_queue.current_slot = 10 * i;
+            event_queue
+                .push_back(
+                    OutEvent::new(Side::Ask, i, i as u128),
+                    Some(&[i as u8; 32]),
+                    None,
+                )
+                .unwrap();
+        }
+        assert_eq!(event_queue.count_pushed_before(0), 0);
+        assert_eq!(event_queue.count_pushed_before(15), 2);
+        assert_eq!(event_queue.count_pushed_before(100), 4);
+
+        assert_eq!(
+            event_queue.move_front_to(&mut dead_letter_queue, event_queue.count_pushed_before(15)),
+            2
+        );
+        assert_eq!(event_queue.len(), 2);
+        assert_eq!(event_queue.seq_num_at(0), 2);
+        assert_eq!(event_queue.push_slot_at(0), Some(20));
+        assert_eq!(dead_letter_queue.len(), 2);
+        assert_eq!(dead_letter_queue.push_slot_at(1), Some(10));
+        for (e, i) in dead_letter_queue.iter().zip(0..) {
+            assert_eq!(
+                e,
+                EventRef::Out(OutEventRef {
+                    event: &OutEvent::new(Side::Ask, i, i as u128),
+                    callback_info: &[i as u8; 32],
+                })
+            );
+        }
+
+        // The number of moved events is capped by the remaining capacity of the destination
+        for i in 0..2 {
+            dead_letter_queue
+                .push_back(OutEvent::new(Side::Ask, i, i as u128), Some(&[0; 32]), None)
+                .unwrap();
+        }
+        assert_eq!(event_queue.move_front_to(&mut dead_letter_queue, 2), 1);
+        assert!(dead_letter_queue.full());
+        assert_eq!(event_queue.len(), 1);
+    }
+
+    #[test]
+    fn test_consumed_events_register() {
+        let mut buffer = vec![0; EventQueueTest::compute_allocation_size(5)];
+        let mut event_queue =
+            EventQueueTest::from_buffer(&mut buffer, AccountTag::Uninitialized).unwrap();
+        let fill = FillEvent::new(Side::Bid, 10, 1, 5, 0, 0);
+        event_queue
+            .push_back(fill, Some(&[1; 32]), Some(&[2; 32]))
+            .unwrap();
+        event_queue
+            .push_back(OutEvent::new(Side::Ask, 3, 2), Some(&[3; 32]), None)
+            .unwrap();
+
+        let mut register = vec![0; compute_consumed_events_register_size::<[u8; 32]>(2)];
+        let data = event_queue.serialize_front(5);
+        assert_eq!(data.len(), register.len());
+        register.copy_from_slice(&data);
+        let events = read_consumed_events::<[u8; 32]>(&register).unwrap();
+        assert_eq!(events.len(), 2);
+        assert_eq!(events[0], (fill, [1; 32], [2; 32]));
+        assert!(matches!(events[1].0.tag(), EventTag::Out));
+        assert_eq!(events[1].1, [3; 32]);
+
+        assert_eq!(
+            read_consumed_events::<[u8; 32]>(&[]),
+            Err(ProgramError::InvalidAccountData)
+        );
+        assert_eq!(
+            read_consumed_events::<[u8; 32]>(&register[..register.len() - 1]),
+            Err(ProgramError::InvalidAccountData)
+        );
+    }
+
+    #[test]
+    fn test_parse_events() {
+        let mut buffer = vec![0; EventQueueTest::compute_allocation_size(5)];
+        let mut event_queue =
+            EventQueueTest::from_buffer(&mut buffer, AccountTag::Uninitialized).unwrap();
+        // Wrap the queue around the end of its circular buffer
+        for i in 0..4 {
+            event_queue
+                .push_back(OutEvent::new(Side::Ask, 1, i), Some(&[0; 32]), None)
+                .unwrap();
+        }
+        event_queue.pop_n(4);
+        let fill = FillEvent::new(Side::Bid, 10, 1, 5, 0, 0);
+        event_queue
+            .push_back(fill, Some(&[1; 32]), Some(&[2; 32]))
+            .unwrap();
+        event_queue
+            .push_back(OutEvent::new(Side::Ask, 3, 2), Some(&[3; 32]), None)
+            .unwrap();
+
+        let events = parse_events(&buffer, 32).unwrap();
+        assert_eq!(events.len(), 2);
+        assert_eq!(
+            events[0],
+            (
+                4,
+                DecodedEvent::Fill {
+                    event: fill,
+                    maker_callback_info: vec![1; 32],
+                    taker_callback_info: vec![2; 32],
+                }
+            )
+        );
+        match &events[1] {
+            (
+                5,
+                DecodedEvent::Out {
+                    event,
+                    callback_info,
+                },
+            ) => {
+                assert_eq!(event.order_id(), 2);
+                assert_eq!(event.base_size(), 3);
+                assert_eq!(callback_info, &vec![3; 32]);
+            }
+            _ => panic!("Expected an out event"),
+        }
+
+        assert_eq!(
+            parse_events(&buffer[..ACCOUNT_TAG_LENGTH], 32),
+            Err(ProgramError::InvalidAccountData)
+        );
+    }
+
+    #[cfg(feature = "serde")]
+    #[test]
+    fn test_serde_events() {
+        let events = vec![
+            DecodedEvent::Fill {
+                event: FillEvent::new(Side::Bid, 10, 1, 5, 2, 3),
+                maker_callback_info: vec![1; 32],
+                taker_callback_info: vec![2; 32],
+            },
+            DecodedEvent::Boot {
+                event: BootEvent::new(Side::Ask, BootReason::Pruned, 7, 4),
+                callback_info: vec![3; 32],
+            },
+            DecodedEvent::TakerFill {
+                event: TakerFillEvent::new(FillEvent::new(Side::Ask, 1, 2, 3, 0, 0)),
+                callback_info: vec![4; 32],
+            },
+            DecodedEvent::Referral {
+                event: ReferralEvent::new([5; 32]),
+                callback_info: vec![4; 32],
+            },
+        ];
+        let json = serde_json::to_string(&events).unwrap();
+        assert!(json.contains("\"maker_order_id\":1"));
+        assert_eq!(
+            serde_json::from_str::<Vec<DecodedEvent>>(&json).unwrap(),
+            events
+        );
+    }
+
+    #[test]
+    fn test_event_queue_shards() {
+        let mut buffers = (0..3)
+            .map(|_| vec![0; EventQueueTest::compute_allocation_size(20)])
+            .collect::<Vec<_>>();
+        let (main_buffer, shard_buffers) = buffers.split_first_mut().unwrap();
+        let mut event_queue =
+            EventQueueTest::from_buffer(main_buffer, AccountTag::Uninitialized).unwrap();
+        event_queue.shards = shard_buffers
+            .iter_mut()
+            .map(|b| EventQueueTest::from_buffer(b, AccountTag::Uninitialized).unwrap())
+            .collect();
+
+        for i in 0..20u8 {
+            event_queue
+                .push_back(
+                    OutEvent::new(Side::Ask, i as u64, i as u128),
+                    Some(&[i % 5; 32]),
+                    None,
+                )
+                .unwrap();
+        }
+        let queues = std::iter::once(&event_queue).chain(event_queue.shards.iter());
+        let mut total = 0;
+        for (queue_index, queue) in queues.enumerate() {
+            total += queue.len();
+            // All the events of a maker are routed to the same queue, in order
+            let mut last_order_id = None;
+            for e in queue.iter() {
+                match e {
+                    EventRef::Out(o) => {
+                        assert_eq!(
+                            EventQueueTest::compute_shard_index(o.callback_info, 3),
+                            queue_index
+                        );
+                        assert!(last_order_id < Some(o.event.order_id()));
+                        last_order_id = Some(o.event.order_id());
+                    }
+                    _ => unreachable!(),
+                }
+            }
+        }
+        assert_eq!(total, 20);
+        assert!(event_queue.shards.iter().all(|s| !s.is_empty()));
+        assert_eq!(
+            event_queue.remaining_capacity(),
+            std::iter::once(&event_queue)
+                .chain(event_queue.shards.iter())
+                .map(|q| 20 - q.len())
+                .min()
+                .unwrap()
+        );
+    }
 }
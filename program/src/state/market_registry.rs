@@ -0,0 +1,145 @@
+//! A market registry lists the markets which were created with it, so that they can be enumerated without scanning
+//! all the accounts of the program.
+//!
+//! Markets are appended to their registry by create_market and removed from it by close_market.
+use bytemuck::{Pod, Zeroable};
+use solana_program::{msg, program_error::ProgramError, pubkey::Pubkey};
+use std::mem::size_of;
+
+use super::{
+    check_account_version, read_account_tag, write_account_tag, AccountTag, ACCOUNT_TAG_LENGTH,
+};
+use crate::error::AoError;
+
+#[derive(Debug, Copy, Clone, Pod, Zeroable)]
+#[repr(C)]
+/// The header of a market registry account.
+pub struct MarketRegistryHeader {
+    /// The number of markets in the registry.
+    pub count: u64,
+}
+
+impl MarketRegistryHeader {
+    /// Expected size in bytes of MarketRegistryHeader
+    pub const LEN: usize = size_of::<Self>();
+}
+
+/// A compact list of market account keys.
+pub struct MarketRegistry<'a> {
+    /// The header of the registry.
+    pub header: &'a mut MarketRegistryHeader,
+    markets: &'a mut [Pubkey],
+}
+
+impl<'a> MarketRegistry<'a> {
+    /// Compute the allocation size for a market registry of a desired capacity
+    pub fn compute_allocation_size(desired_market_capacity: usize) -> usize {
+        ACCOUNT_TAG_LENGTH
+            + MarketRegistryHeader::LEN
+            + desired_market_capacity * size_of::<Pubkey>()
+    }
+
+    /// Instantiates a market registry object from an account's data buffer.
+    ///
+    /// An uninitialized buffer is tagged as a market registry.
+    pub fn from_buffer(
+        buffer: &'a mut [u8],
+        expected_tag: AccountTag,
+    ) -> Result<Self, ProgramError> {
+        let tag = read_account_tag(buffer)?;
+        if tag != expected_tag {
+            msg!("Invalid account tag for market registry!");
+            return Err(ProgramError::InvalidAccountData);
+        }
+        if buffer.len() < Self::compute_allocation_size(1) {
+            msg!("The market registry account is too small!");
+            return Err(ProgramError::AccountDataTooSmall);
+        }
+        if tag == AccountTag::Uninitialized {
+            write_account_tag(buffer, AccountTag::MarketRegistry);
+        } else {
+            check_account_version(buffer)?;
+        }
+
+        let capacity =
+            (buffer.len() - ACCOUNT_TAG_LENGTH - MarketRegistryHeader::LEN) / size_of::<Pubkey>();
+        let (header, markets) =
+            buffer[ACCOUNT_TAG_LENGTH..].split_at_mut(MarketRegistryHeader::LEN);
+        Ok(Self {
+            header: bytemuck::from_bytes_mut(header),
+            markets: bytemuck::cast_slice_mut(&mut markets[..capacity * size_of::<Pubkey>()]),
+        })
+    }
+
+    /// The maximum number of markets which the registry can hold.
+    pub fn capacity(&self) -> usize {
+        self.markets.len()
+    }
+
+    /// The keys of the markets in the registry.
+    pub fn markets(&self) -> &[Pubkey] {
+        &self.markets[..self.header.count as usize]
+    }
+
+    /// Appends a market to the registry.
+    pub fn push(&mut self, market: &Pubkey) -> Result<(), AoError> {
+        let count = self.header.count as usize;
+        if count == self.capacity() {
+            msg!("The market registry can't hold more markets!");
+            return Err(AoError::MarketRegistryFull);
+        }
+        self.markets[count] = *market;
+        self.header.count += 1;
+        Ok(())
+    }
+
+    /// Removes a market from the registry, moving the last market of the registry in its place.
+    pub fn remove(&mut self, market: &Pubkey) -> Result<(), AoError> {
+        let index = self
+            .markets()
+            .iter()
+            .position(|k| k == market)
+            .ok_or(AoError::WrongMarketRegistryAccount)?;
+        let last = self.header.count as usize - 1;
+        self.markets.swap(index, last);
+        self.markets[last] = Pubkey::default();
+        self.header.count -= 1;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_market_registry() {
+        let mut buffer = vec![0; MarketRegistry::compute_allocation_size(3)];
+        let mut registry =
+            MarketRegistry::from_buffer(&mut buffer, AccountTag::Uninitialized).unwrap();
+        assert_eq!(registry.capacity(), 3);
+        let markets = [
+            Pubkey::new_unique(),
+            Pubkey::new_unique(),
+            Pubkey::new_unique(),
+        ];
+        for m in markets.iter() {
+            registry.push(m).unwrap();
+        }
+        assert!(matches!(
+            registry.push(&Pubkey::new_unique()),
+            Err(AoError::MarketRegistryFull)
+        ));
+
+        registry.remove(&markets[0]).unwrap();
+        assert_eq!(registry.markets(), &[markets[2], markets[1]]);
+        assert!(matches!(
+            registry.remove(&markets[0]),
+            Err(AoError::WrongMarketRegistryAccount)
+        ));
+
+        let registry =
+            MarketRegistry::from_buffer(&mut buffer, AccountTag::MarketRegistry).unwrap();
+        assert_eq!(registry.markets().len(), 2);
+    }
+}
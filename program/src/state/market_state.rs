@@ -2,16 +2,87 @@
 //! relevant accounts.
 pub use crate::state::orderbook::{OrderSummary, ORDER_SUMMARY_SIZE};
 #[cfg(feature = "no-entrypoint")]
-pub use crate::utils::get_spread;
+pub use crate::utils::{get_market_status, get_spread, MarketStatus};
 use bytemuck::{Pod, Zeroable};
 use solana_program::{entrypoint::ProgramResult, msg, program_error::ProgramError, pubkey::Pubkey};
-use std::{convert::TryFrom, mem::size_of};
+use std::mem::size_of;
 
-use super::{AccountTag, ACCOUNT_TAG_INDEX, ACCOUNT_TAG_LENGTH};
+use super::{
+    check_account_version, event_queue::FillEvent, read_account_tag, write_account_tag, AccountTag,
+    BootingPolicy, EventQueueFullPolicy, OrderId, Side, TradingStatus, ACCOUNT_TAG_LENGTH,
+};
+#[cfg(test)]
+use super::{migrate_account, ACCOUNT_VERSION_INDEX, CURRENT_ACCOUNT_VERSION};
+use crate::{
+    error::AoError,
+    utils::{compute_fee, compute_quote_qty},
+};
+use num_traits::FromPrimitive;
+
+/// The maximum number of event queue shards which can be added to a market, on top of its main event queue.
+pub const MAX_EVENT_QUEUE_SHARDS: usize = 4;
+
+/// The maximum base 2 exponent of the unit in which the quote quantities of a market are expressed.
+pub const MAX_QUOTE_SCALE_EXPONENT: u64 = 32;
+
+/// The maximum fee rate of a market, in basis points.
+pub const MAX_FEE_BPS: u64 = 10_000;
+
+/// The length in bytes of the caller-defined metadata stored in a market.
+pub const MARKET_METADATA_LEN: usize = 256;
+
+/// The maximum length in bytes of the callback information of a resumable order.
+pub const PENDING_TAKER_CALLBACK_INFO_LEN: usize = 64;
+
+#[derive(Debug, Copy, Clone, Pod, Zeroable)]
+#[repr(C)]
+/// The remainder of a resumable order whose matching was interrupted, which is continued by a continue_match
+/// instruction.
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct PendingTaker {
+    /// Set to 1 when a resumable order is being matched.
+    pub active: u64,
+    /// The sequence number of the order id assigned to the order.
+    pub sequence_number: u64,
+    /// The u64 representation for the [`Side`] enum of the order.
+    pub side: u64,
+    /// The limit price of the order (FP32). For market orders, this is the effective limit price.
+    pub limit_price: u64,
+    /// The base quantity which remains to be matched.
+    pub base_qty_remaining: u64,
+    /// The quote quantity which remains to be matched.
+    pub quote_qty_remaining: u64,
+    /// The max timestamp at which the order will be able to be matched against.
+    pub max_ts: u64,
+    /// The maximum base quantity displayed in the orderbook if the remainder of the order is posted.
+    pub max_display_qty: u64,
+    /// The u64 representation for the [`SelfTradeBehavior`] enum of the order.
+    pub self_trade_behavior: u64,
+    /// Set to 1 when the unmatched remainder of the order is posted once matching completes.
+    pub post_allowed: u64,
+    /// The caller-defined flags of the order.
+    pub flags: u64,
+    /// The callback information of the order, padded with zeroes.
+    #[cfg_attr(feature = "serde", serde(with = "crate::utils::serde_byte_array"))]
+    pub callback_info: [u8; PENDING_TAKER_CALLBACK_INFO_LEN],
+}
+
+impl PendingTaker {
+    /// The order id assigned to the resumable order.
+    pub fn order_id(&self) -> u128 {
+        OrderId::new(
+            self.limit_price,
+            self.sequence_number,
+            Side::from_u64(self.side).unwrap(),
+        )
+        .into()
+    }
+}
 
 #[derive(Debug, Copy, Clone, Pod, Zeroable)]
 #[repr(C)]
 /// The orderbook market's central state
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub struct MarketState {
     /// The public key of the orderbook's event queue account
     pub event_queue: Pubkey,
@@ -21,8 +92,109 @@ pub struct MarketState {
     pub asks: Pubkey,
     /// The minimum order size that can be inserted into the orderbook after matching.
     pub min_base_order_size: u64,
-    /// Tick size (FP32)
+    /// Tick size (FP32). Limit prices which are not a multiple of the tick size are rejected.
     pub tick_size: u64,
+    /// The public key of the orderbook's stop orders account, or the default public key if stop orders aren't enabled
+    pub stop_orders: Pubkey,
+    /// The price (FP32) at which the last match occured on the orderbook, used to trigger stop orders
+    pub last_trade_price: u64,
+    /// The public key of the orderbook's pegged orders account, or the default public key if pegged orders aren't enabled
+    pub pegged_orders: Pubkey,
+    /// The minimum quote size (base quantity times price) of an order that can be inserted into the orderbook after matching.
+    pub min_quote_order_size: u64,
+    /// The u64 representation for the [`BootingPolicy`][`crate::state::BootingPolicy`] enum, which describes what happens when an order is posted into a full side of the orderbook.
+    pub booting_policy: u64,
+    /// When set to 1, each match pushes a [`MakerFillEvent`][`crate::state::event_queue::MakerFillEvent`] and a
+    /// [`TakerFillEvent`][`crate::state::event_queue::TakerFillEvent`] instead of a single combined
+    /// [`FillEvent`][`crate::state::event_queue::FillEvent`].
+    pub split_fills: u64,
+    /// The public key of the orderbook's dead letter queue account, or the default public key if event expiry isn't
+    /// enabled
+    pub dead_letter_queue: Pubkey,
+    /// The number of slots after which a pending event can be moved from the event queue to the dead letter queue,
+    /// or 0 if event expiry isn't enabled
+    pub max_event_age_slots: u64,
+    /// When set to 1, the fill and out events are also emitted as binary program logs when they are pushed, so that
+    /// they can be indexed from the transaction logs.
+    pub log_events: u64,
+    /// The public keys of the orderbook's event queue shards, followed by default public keys for the unused slots.
+    ///
+    /// When shards are registered, the events are split between the event queue and its shards according to a hash
+    /// of their maker callback info.
+    pub event_queue_shards: [Pubkey; MAX_EVENT_QUEUE_SHARDS],
+    /// The u64 representation for the [`TradingStatus`] enum, which describes which operations are allowed on the
+    /// market.
+    pub status: u64,
+    /// Opaque caller-defined metadata, such as the base and quote mints and their decimals.
+    #[cfg_attr(feature = "serde", serde(with = "crate::utils::serde_byte_array"))]
+    pub metadata: [u8; MARKET_METADATA_LEN],
+    /// The public key of the market registry which lists the market, or the default public key if the market isn't
+    /// registered
+    pub market_registry: Pubkey,
+    /// The base 2 exponent of the unit in which the quote quantities of the market are expressed, in terms of raw quote
+    /// quantities (base quantity times FP32 price). A larger exponent allows for larger quote quantities on markets
+    /// with very small price units, at the expense of precision.
+    pub quote_scale_exponent: u64,
+    /// The maximum deviation, in basis points, of the limit price of a new order from the best opposite price (or the
+    /// last trade price when the opposite side of the orderbook is empty), or 0 if price banding isn't enabled.
+    pub max_price_deviation_bps: u64,
+    /// The total base quantity matched on the market since its creation, wrapping around on overflow.
+    ///
+    /// The volume over a period of time is the wrapping difference between two readings of this counter.
+    pub cumulative_base_volume: u64,
+    /// The total quote quantity matched on the market since its creation, wrapping around on overflow.
+    pub cumulative_quote_volume: u64,
+    /// The time-weighted sum of the last trade price, as the little-endian words of a u128 which wraps around on
+    /// overflow. See [`MarketState::cumulative_price_time`].
+    pub cumulative_price_time: [u64; 2],
+    /// The unix timestamp at which the price accumulator was last updated.
+    pub last_accumulator_update_ts: u64,
+    /// The u64 representation for the [`MatchingMode`][`crate::state::MatchingMode`] enum, which describes how the
+    /// orders of the market are matched.
+    pub matching_mode: u64,
+    /// The remainder of the resumable order which is being matched, if any. No new orders can be placed until it has
+    /// been entirely matched or cancelled.
+    pub pending_taker: PendingTaker,
+    /// The fee rate charged to makers on the quote quantity of their fills, in basis points.
+    pub maker_fee_bps: u64,
+    /// The fee rate charged to takers on the quote quantity of their fills, in basis points.
+    pub taker_fee_bps: u64,
+    /// The total fees charged to makers on the market, in quote lots, wrapping around on overflow.
+    ///
+    /// The fees over a period of time are the wrapping difference between two readings of this counter.
+    pub accrued_maker_fees: u64,
+    /// The total fees charged to takers on the market, in quote lots, wrapping around on overflow.
+    pub accrued_taker_fees: u64,
+    /// The maximum number of orders resting in the orderbook with the same callback information, across both sides,
+    /// or 0 for no limit.
+    ///
+    /// The limit is enforced through the owner index of the slabs, so that its cost doesn't grow with the number of
+    /// resting orders.
+    pub max_orders_per_owner: u64,
+    /// The program into which the consume_events instructions push the popped events through CPI, or the default
+    /// public key when the events are only read by the calling program.
+    ///
+    /// See [`consume_events`][`crate::instruction::consume_events`].
+    pub consumer_program: Pubkey,
+    /// The maximum number of events passed to each call of the consumer program, or 0 for no limit.
+    pub max_events_per_consumer_call: u64,
+    /// The public key of the allowlist of the market, or the default public key if order placement is permissionless.
+    ///
+    /// See [`Allowlist`][`crate::state::allowlist::Allowlist`].
+    pub order_allowlist: Pubkey,
+    /// The Merkle root over the price levels of the bids as of the last commit_book instruction, or zeroes if the book
+    /// was never committed. The root is a snapshot, which goes stale as soon as the bids change after
+    /// `book_commit_slot`.
+    ///
+    /// See [`merkle`][`crate::state::merkle`].
+    pub bids_root: [u8; 32],
+    /// The Merkle root over the price levels of the asks as of the last commit_book instruction.
+    pub asks_root: [u8; 32],
+    /// The slot of the last commit_book instruction, at which the Merkle roots of the book were computed.
+    pub book_commit_slot: u64,
+    /// The u64 representation for the [`EventQueueFullPolicy`][`crate::state::EventQueueFullPolicy`] enum, which
+    /// describes what happens when an event is pushed onto a full event queue.
+    pub event_queue_full_policy: u64,
 }
 
 impl MarketState {
@@ -34,22 +206,21 @@ impl MarketState {
         buffer: &mut [u8],
         expected_tag: AccountTag,
     ) -> Result<&mut Self, ProgramError> {
-        match AccountTag::try_from(&buffer[ACCOUNT_TAG_INDEX..ACCOUNT_TAG_LENGTH]) {
+        match read_account_tag(buffer) {
             Ok(a) => {
                 if a != expected_tag {
                     msg!("Invalid account tag for market!");
                     return Err(ProgramError::InvalidAccountData);
                 }
-                bytemuck::bytes_of(&(AccountTag::Market as u64))
-                    .iter()
-                    .enumerate()
-                    .for_each(|(idx, byte)| buffer[ACCOUNT_TAG_INDEX + idx] = *byte);
+                write_account_tag(buffer, AccountTag::Market);
             }
             Err(e) => {
                 return Err(e);
             }
         };
 
+        Self::check_buffer_len(buffer)?;
+
         let (_, data) = buffer.split_at_mut(ACCOUNT_TAG_LENGTH);
 
         Ok(bytemuck::from_bytes_mut(data))
@@ -57,7 +228,7 @@ impl MarketState {
 
     #[allow(missing_docs)]
     pub fn from_buffer(buffer: &[u8], expected_tag: AccountTag) -> Result<&Self, ProgramError> {
-        match AccountTag::try_from(&buffer[ACCOUNT_TAG_INDEX..8]) {
+        match read_account_tag(buffer) {
             Ok(a) => {
                 if a != expected_tag {
                     msg!("Invalid account tag for market!");
@@ -68,10 +239,180 @@ impl MarketState {
                 return Err(e);
             }
         };
+        check_account_version(buffer)?;
+        Self::check_buffer_len(buffer)?;
 
         let (_, data) = buffer.split_at(ACCOUNT_TAG_LENGTH);
+        let market_state: &Self = bytemuck::from_bytes(data);
+        market_state.check_policies()?;
+
+        Ok(market_state)
+    }
+
+    #[allow(missing_docs)]
+    pub fn from_buffer_mut(
+        buffer: &mut [u8],
+        expected_tag: AccountTag,
+    ) -> Result<&mut Self, ProgramError> {
+        match read_account_tag(buffer) {
+            Ok(a) => {
+                if a != expected_tag {
+                    msg!("Invalid account tag for market!");
+                    return Err(ProgramError::InvalidAccountData);
+                }
+            }
+            Err(e) => {
+                return Err(e);
+            }
+        };
+        check_account_version(buffer)?;
+        Self::check_buffer_len(buffer)?;
+
+        let (_, data) = buffer.split_at_mut(ACCOUNT_TAG_LENGTH);
+        let market_state: &mut Self = bytemuck::from_bytes_mut(data);
+        market_state.check_policies()?;
+
+        Ok(market_state)
+    }
+
+    fn check_buffer_len(buffer: &[u8]) -> ProgramResult {
+        if buffer.len() != ACCOUNT_TAG_LENGTH + Self::LEN {
+            msg!("Invalid account size for market!");
+            return Err(ProgramError::InvalidAccountData);
+        }
+        Ok(())
+    }
+
+    /// Checks that the policies of the market hold valid values, so that they can be decoded without failing.
+    fn check_policies(&self) -> ProgramResult {
+        if BootingPolicy::from_u64(self.booting_policy).is_none() {
+            msg!("Invalid booting policy for market!");
+            return Err(ProgramError::InvalidAccountData);
+        }
+        if EventQueueFullPolicy::from_u64(self.event_queue_full_policy).is_none() {
+            msg!("Invalid event queue full policy for market!");
+            return Err(ProgramError::InvalidAccountData);
+        }
+        Ok(())
+    }
+
+    /// Returns the public keys of the registered event queue shards.
+    pub fn event_queue_shards(&self) -> &[Pubkey] {
+        let len = self
+            .event_queue_shards
+            .iter()
+            .position(|k| k == &Pubkey::default())
+            .unwrap_or(MAX_EVENT_QUEUE_SHARDS);
+        &self.event_queue_shards[..len]
+    }
+
+    /// Checks that the market's status allows new orders to be placed, and to be matched unless `post_only` is set.
+    pub fn check_can_place_order(&self, post_only: bool) -> ProgramResult {
+        match TradingStatus::from_u64(self.status).unwrap() {
+            TradingStatus::Active => Ok(()),
+            TradingStatus::PostOnly if post_only => Ok(()),
+            _ => {
+                msg!("The market doesn't accept this order in its current status!");
+                Err(AoError::OperationNotAllowed.into())
+            }
+        }
+    }
+
+    /// Checks that the market's status allows orders to be cancelled.
+    pub fn check_can_cancel_order(&self) -> ProgramResult {
+        if TradingStatus::from_u64(self.status).unwrap() == TradingStatus::Halted {
+            msg!("Orders can't be cancelled while the market is halted!");
+            return Err(AoError::OperationNotAllowed.into());
+        }
+        Ok(())
+    }
+
+    /// Checks that a new order on the given side doesn't cross further than `max_price_deviation_bps` beyond the best
+    /// opposite price, or beyond the last trade price when the opposite side of the orderbook is empty.
+    pub fn check_price_band(
+        &self,
+        side: Side,
+        limit_price: u64,
+        best_opposite_price: Option<u64>,
+    ) -> Result<(), AoError> {
+        if self.max_price_deviation_bps == 0 {
+            return Ok(());
+        }
+        let reference_price = match best_opposite_price {
+            Some(p) => p,
+            None if self.last_trade_price != 0 => self.last_trade_price,
+            None => return Ok(()),
+        };
+        let deviation = (reference_price as u128 * self.max_price_deviation_bps as u128 / 10_000)
+            .min(u64::MAX as u128) as u64;
+        let out_of_band = match side {
+            Side::Bid => limit_price > reference_price.saturating_add(deviation),
+            Side::Ask => limit_price < reference_price.saturating_sub(deviation),
+        };
+        if out_of_band {
+            msg!("The limit price deviates too much from the reference price of the market!");
+            return Err(AoError::PriceOutOfBand);
+        }
+        Ok(())
+    }
+
+    /// Checks whether an order of `base_qty` at `price` (FP32) is large enough to be inserted into the orderbook.
+    pub fn is_postable_size(&self, base_qty: u64, price: u64) -> bool {
+        base_qty >= self.min_base_order_size
+            && compute_quote_qty(Side::Ask, base_qty, price, self.quote_scale_exponent)
+                .unwrap_or(u64::MAX)
+                >= self.min_quote_order_size
+    }
+
+    /// The sum of the last trade price (FP32) multiplied by the number of seconds during which it held.
+    ///
+    /// The time-weighted average price over a period of time is the wrapping difference between two readings of this
+    /// accumulator, divided by the time elapsed between their `last_accumulator_update_ts`. Since the accumulator is
+    /// only updated on fills and poke instructions, readers should poke the market before reading it.
+    pub fn cumulative_price_time(&self) -> u128 {
+        (self.cumulative_price_time[1] as u128) << 64 | self.cumulative_price_time[0] as u128
+    }
+
+    /// Accumulates the last trade price over the time elapsed since the previous update.
+    ///
+    /// This should be called before the last trade price is modified. The first update only seeds the timestamp of the
+    /// accumulator, since a market migrated with a last trade price has no record of when that price started to hold.
+    pub fn update_price_accumulator(&mut self, cur_ts: u64) {
+        if cur_ts <= self.last_accumulator_update_ts {
+            return;
+        }
+        if self.last_accumulator_update_ts == 0 {
+            self.last_accumulator_update_ts = cur_ts;
+            return;
+        }
+        let elapsed = cur_ts - self.last_accumulator_update_ts;
+        let cumulative_price_time = self
+            .cumulative_price_time()
+            .wrapping_add(self.last_trade_price as u128 * elapsed as u128);
+        self.cumulative_price_time = [
+            cumulative_price_time as u64,
+            (cumulative_price_time >> 64) as u64,
+        ];
+        self.last_accumulator_update_ts = cur_ts;
+    }
 
-        Ok(bytemuck::from_bytes(data))
+    /// Stamps the fee rates of the market on a fill, and adds the fees it charges to the accrued fees of the market.
+    ///
+    /// The maker fee is charged on `maker_quote_qty`, which differs from the quote size of the fill on markets which
+    /// split fills. The taker fee rate is only stamped when `charge_taker` is set, which isn't the case for auction
+    /// fills since both of their orders rested in the orderbook.
+    pub(crate) fn charge_fees(
+        &mut self,
+        fill: &mut FillEvent,
+        maker_quote_qty: u64,
+        charge_taker: bool,
+    ) {
+        let taker_fee_bps = if charge_taker { self.taker_fee_bps } else { 0 };
+        fill.set_fee_rates(self.maker_fee_bps as u16, taker_fee_bps as u16);
+        self.accrued_maker_fees = self
+            .accrued_maker_fees
+            .wrapping_add(compute_fee(maker_quote_qty, self.maker_fee_bps as u16));
+        self.accrued_taker_fees = self.accrued_taker_fees.wrapping_add(fill.taker_fee());
     }
 
     #[allow(missing_docs)]
@@ -91,3 +432,156 @@ fn market_cast() {
     assert!(r.is_err());
     assert_eq!(r.unwrap_err(), ProgramError::InvalidAccountData)
 }
+
+#[test]
+fn market_trading_status() {
+    let mut buffer = [0u8; MarketState::LEN + 8];
+    let market_state = MarketState::initialize(&mut buffer, AccountTag::Uninitialized).unwrap();
+    let not_allowed = Err(AoError::OperationNotAllowed.into());
+    for (status, post, post_only, cancel) in [
+        (TradingStatus::Active, Ok(()), Ok(()), Ok(())),
+        (TradingStatus::PostOnly, not_allowed.clone(), Ok(()), Ok(())),
+        (
+            TradingStatus::CancelOnly,
+            not_allowed.clone(),
+            not_allowed.clone(),
+            Ok(()),
+        ),
+        (
+            TradingStatus::Halted,
+            not_allowed.clone(),
+            not_allowed.clone(),
+            not_allowed.clone(),
+        ),
+    ] {
+        market_state.status = status as u64;
+        assert_eq!(market_state.check_can_place_order(false), post);
+        assert_eq!(market_state.check_can_place_order(true), post_only);
+        assert_eq!(market_state.check_can_cancel_order(), cancel);
+    }
+}
+
+#[test]
+fn market_migration() {
+    let mut buffer = [0u8; MarketState::LEN + 8];
+    MarketState::initialize(&mut buffer, AccountTag::Uninitialized).unwrap();
+    assert!(MarketState::from_buffer(&buffer, AccountTag::Market).is_ok());
+
+    // Markets created before versioning hold a version of 0
+    buffer[ACCOUNT_VERSION_INDEX] = 0;
+    assert_eq!(
+        MarketState::from_buffer(&buffer, AccountTag::Market).unwrap_err(),
+        AoError::OutdatedAccountVersion.into()
+    );
+    assert_eq!(
+        migrate_account::<[u8; 32]>(&mut buffer, AccountTag::EventQueue).unwrap_err(),
+        ProgramError::InvalidAccountData
+    );
+    assert!(migrate_account::<[u8; 32]>(&mut buffer, AccountTag::Market).unwrap());
+    assert!(!migrate_account::<[u8; 32]>(&mut buffer, AccountTag::Market).unwrap());
+    assert_eq!(buffer[ACCOUNT_VERSION_INDEX], CURRENT_ACCOUNT_VERSION);
+    assert!(MarketState::from_buffer(&buffer, AccountTag::Market).is_ok());
+}
+
+#[test]
+fn market_policies() {
+    for corrupt in [
+        |m: &mut MarketState| m.booting_policy = BootingPolicy::BootSmallest as u64 + 1,
+        |m: &mut MarketState| m.event_queue_full_policy = EventQueueFullPolicy::LogOnly as u64 + 1,
+    ] {
+        let mut buffer = [0u8; MarketState::LEN + 8];
+        corrupt(MarketState::initialize(&mut buffer, AccountTag::Uninitialized).unwrap());
+        assert_eq!(
+            MarketState::from_buffer(&buffer, AccountTag::Market).unwrap_err(),
+            ProgramError::InvalidAccountData
+        );
+        assert_eq!(
+            MarketState::from_buffer_mut(&mut buffer, AccountTag::Market).unwrap_err(),
+            ProgramError::InvalidAccountData
+        );
+    }
+}
+
+#[test]
+fn market_price_band() {
+    let mut buffer = [0u8; MarketState::LEN + 8];
+    let market_state = MarketState::initialize(&mut buffer, AccountTag::Uninitialized).unwrap();
+    assert!(market_state
+        .check_price_band(Side::Bid, u64::MAX, Some(100 << 32))
+        .is_ok());
+
+    market_state.max_price_deviation_bps = 500;
+    // Without a reference price, any price is accepted
+    assert!(market_state
+        .check_price_band(Side::Bid, u64::MAX, None)
+        .is_ok());
+    assert!(market_state
+        .check_price_band(Side::Bid, 105 << 32, Some(100 << 32))
+        .is_ok());
+    assert!(market_state
+        .check_price_band(Side::Bid, (105 << 32) + 1, Some(100 << 32))
+        .is_err());
+    assert!(market_state
+        .check_price_band(Side::Ask, 95 << 32, Some(100 << 32))
+        .is_ok());
+    assert!(market_state
+        .check_price_band(Side::Ask, (95 << 32) - 1, Some(100 << 32))
+        .is_err());
+
+    market_state.last_trade_price = 100 << 32;
+    assert!(market_state
+        .check_price_band(Side::Ask, 90 << 32, None)
+        .is_err());
+}
+
+#[test]
+fn market_price_accumulator() {
+    let mut buffer = [0u8; MarketState::LEN + 8];
+    let market_state = MarketState::initialize(&mut buffer, AccountTag::Uninitialized).unwrap();
+    // A migrated market holds a last trade price but no accumulator timestamp, which the first update only seeds
+    market_state.last_trade_price = 50 << 32;
+    market_state.update_price_accumulator(100);
+    assert_eq!(market_state.cumulative_price_time(), 0);
+    assert_eq!(market_state.last_accumulator_update_ts, 100);
+
+    market_state.last_trade_price = 10 << 32;
+    market_state.update_price_accumulator(110);
+    market_state.last_trade_price = 20 << 32;
+    // Updates within the same second or going back in time don't accumulate anything
+    market_state.update_price_accumulator(110);
+    market_state.update_price_accumulator(105);
+    market_state.update_price_accumulator(140);
+    assert_eq!(
+        market_state.cumulative_price_time(),
+        (10 << 32) * 10 + (20 << 32) * 30
+    );
+    assert_eq!(market_state.last_accumulator_update_ts, 140);
+
+    // The accumulator carries into its high word
+    market_state.last_trade_price = u64::MAX;
+    market_state.update_price_accumulator(140 + u32::MAX as u64);
+    assert_eq!(
+        market_state.cumulative_price_time(),
+        (10u128 << 32) * 10 + (20 << 32) * 30 + u64::MAX as u128 * u32::MAX as u128
+    );
+    assert!(market_state.cumulative_price_time[1] > 0);
+}
+
+#[cfg(feature = "serde")]
+#[test]
+fn market_serde() {
+    let mut buffer = [0u8; MarketState::LEN + 8];
+    let market_state = MarketState::initialize(&mut buffer, AccountTag::Uninitialized).unwrap();
+    market_state.metadata[..4].copy_from_slice(&[1, 2, 3, 4]);
+    market_state.pending_taker.callback_info[63] = 5;
+
+    let json = serde_json::to_string(market_state).unwrap();
+    let deserialized = serde_json::from_str::<MarketState>(&json).unwrap();
+    assert_eq!(
+        bytemuck::bytes_of(&deserialized),
+        bytemuck::bytes_of(market_state)
+    );
+    assert!(
+        serde_json::from_str::<MarketState>(&json.replacen("[1,2,3,4,", "[1,2,3,", 1)).is_err()
+    );
+}
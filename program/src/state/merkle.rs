@@ -0,0 +1,214 @@
+//! Merkle commitments over the price levels of an orderbook side.
+//!
+//! The orders of each price level are the leaves of a level tree, in key order, and the price levels are the leaves
+//! of the book tree, in ascending price order. The root of the book tree commits to every order of the side, so that
+//! an [`OrderProof`] shows that an order with a given quantity rests at a given price without revealing the rest of
+//! the book.
+//!
+//! The nodes are hashed as follows, the last node of a tree level being promoted as is when the level has an odd
+//! number of nodes:
+//!
+//! - an order is hashed as its leaf node, see [`LeafNode::hash`],
+//! - a price level is hashed as the little-endian price followed by the root of its level tree,
+//! - an inner node is hashed as its left child followed by its right child.
+//!
+//! The hashed data of each kind of node has a different length, so that nodes of different kinds can't be confused.
+//! The root of an empty side is zeroed out.
+use solana_program::hash::hashv;
+
+use super::critbit::LeafNode;
+
+/// A sibling on the path from a node to the root of its Merkle tree.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct ProofStep {
+    /// The hash of the sibling.
+    pub sibling: [u8; 32],
+    /// Whether the sibling is the left child of their parent.
+    pub sibling_on_left: bool,
+}
+
+/// A proof that an order rests on an orderbook side with a given Merkle root.
+#[derive(Clone, Debug, PartialEq)]
+pub struct OrderProof {
+    /// The leaf node of the order.
+    pub order: LeafNode,
+    /// The path from the order to the root of its level tree.
+    pub level_path: Vec<ProofStep>,
+    /// The path from the price level of the order to the root of the book tree.
+    pub book_path: Vec<ProofStep>,
+}
+
+impl OrderProof {
+    /// The order id of the proven order.
+    pub fn order_id(&self) -> u128 {
+        self.order.order_id()
+    }
+
+    /// The price of the proven order.
+    pub fn price(&self) -> u64 {
+        self.order.price()
+    }
+
+    /// The quantity of base asset of the proven order which is displayed in the orderbook.
+    pub fn base_quantity(&self) -> u64 {
+        self.order.base_quantity()
+    }
+
+    /// Computes the root of the book tree which the proof leads to.
+    pub fn compute_root(&self) -> [u8; 32] {
+        let level_root = fold_path(self.order.hash(), &self.level_path);
+        fold_path(hash_level(self.price(), &level_root), &self.book_path)
+    }
+
+    /// Checks that the order rests on an orderbook side with the given Merkle root.
+    pub fn verify(&self, root: &[u8; 32]) -> bool {
+        &self.compute_root() == root
+    }
+}
+
+/// Computes the Merkle root over the price levels of an orderbook side, given its leaves in key order.
+///
+/// The leaves are hashed as they are read, so that the orderbook side doesn't need to be collected beforehand.
+pub fn compute_book_root<'a>(leaves: impl IntoIterator<Item = &'a LeafNode>) -> [u8; 32] {
+    let mut level_hashes = vec![];
+    let mut level: Option<(u64, Vec<[u8; 32]>)> = None;
+    for leaf in leaves {
+        match &mut level {
+            Some((price, order_hashes)) if *price == leaf.price() => order_hashes.push(leaf.hash()),
+            _ => {
+                if let Some((price, order_hashes)) =
+                    level.replace((leaf.price(), vec![leaf.hash()]))
+                {
+                    level_hashes.push(hash_level(price, &compute_root(order_hashes)));
+                }
+            }
+        }
+    }
+    if let Some((price, order_hashes)) = level {
+        level_hashes.push(hash_level(price, &compute_root(order_hashes)));
+    }
+    compute_root(level_hashes)
+}
+
+/// Builds the proof that an order rests on an orderbook side, given its leaves in key order.
+pub fn prove_order<'a>(
+    leaves: impl IntoIterator<Item = &'a LeafNode>,
+    order_id: u128,
+) -> Option<OrderProof> {
+    let levels = price_levels(leaves);
+    let level_index = levels
+        .iter()
+        .position(|(price, _)| *price == LeafNode::price_from_key(order_id))?;
+    let orders = &levels[level_index].1;
+    let order_index = orders.iter().position(|o| o.order_id() == order_id)?;
+    Some(OrderProof {
+        order: orders[order_index],
+        level_path: compute_path(order_hashes(orders), order_index),
+        book_path: compute_path(level_hashes(&levels), level_index),
+    })
+}
+
+/// Groups the leaves of an orderbook side by price level.
+fn price_levels<'a>(leaves: impl IntoIterator<Item = &'a LeafNode>) -> Vec<(u64, Vec<LeafNode>)> {
+    let mut levels: Vec<(u64, Vec<LeafNode>)> = vec![];
+    for leaf in leaves {
+        match levels.last_mut() {
+            Some((price, orders)) if *price == leaf.price() => orders.push(*leaf),
+            _ => levels.push((leaf.price(), vec![*leaf])),
+        }
+    }
+    levels
+}
+
+fn order_hashes(orders: &[LeafNode]) -> Vec<[u8; 32]> {
+    orders.iter().map(LeafNode::hash).collect()
+}
+
+fn level_hashes(levels: &[(u64, Vec<LeafNode>)]) -> Vec<[u8; 32]> {
+    levels
+        .iter()
+        .map(|(price, orders)| hash_level(*price, &compute_root(order_hashes(orders))))
+        .collect()
+}
+
+fn hash_level(price: u64, level_root: &[u8; 32]) -> [u8; 32] {
+    hashv(&[&price.to_le_bytes(), level_root]).to_bytes()
+}
+
+fn hash_pair(left: &[u8; 32], right: &[u8; 32]) -> [u8; 32] {
+    hashv(&[left, right]).to_bytes()
+}
+
+fn next_tree_level(nodes: &[[u8; 32]]) -> Vec<[u8; 32]> {
+    nodes
+        .chunks(2)
+        .map(|pair| match pair {
+            [left, right] => hash_pair(left, right),
+            [node] => *node,
+            _ => unreachable!(),
+        })
+        .collect()
+}
+
+fn compute_root(mut nodes: Vec<[u8; 32]>) -> [u8; 32] {
+    while nodes.len() > 1 {
+        nodes = next_tree_level(&nodes);
+    }
+    nodes.first().copied().unwrap_or_default()
+}
+
+fn compute_path(mut nodes: Vec<[u8; 32]>, mut index: usize) -> Vec<ProofStep> {
+    let mut path = vec![];
+    while nodes.len() > 1 {
+        let sibling_index = index ^ 1;
+        if let Some(sibling) = nodes.get(sibling_index) {
+            path.push(ProofStep {
+                sibling: *sibling,
+                sibling_on_left: sibling_index < index,
+            });
+        }
+        nodes = next_tree_level(&nodes);
+        index /= 2;
+    }
+    path
+}
+
+fn fold_path(node: [u8; 32], path: &[ProofStep]) -> [u8; 32] {
+    path.iter().fold(node, |node, step| {
+        if step.sibling_on_left {
+            hash_pair(&step.sibling, &node)
+        } else {
+            hash_pair(&node, &step.sibling)
+        }
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_order_proofs() {
+        assert_eq!(compute_book_root(&[]), [0; 32]);
+
+        // Three orders at a price of 10, one at 11 and two at 12
+        let leaves = [(10, 0), (10, 1), (10, 2), (11, 3), (12, 4), (12, 5)]
+            .iter()
+            .map(|&(price, seq_num)| LeafNode::new((price as u128) << 64 | seq_num, 100, u64::MAX))
+            .collect::<Vec<_>>();
+        let root = compute_book_root(&leaves);
+        for leaf in leaves.iter() {
+            let proof = prove_order(&leaves, leaf.order_id()).unwrap();
+            assert_eq!(proof.price(), leaf.price());
+            assert_eq!(proof.base_quantity(), 100);
+            assert!(proof.verify(&root));
+        }
+        assert_eq!(prove_order(&leaves, 11 << 64 | 4), None);
+
+        // A proof doesn't hold for another quantity or against another book
+        let mut proof = prove_order(&leaves, 10 << 64 | 1).unwrap();
+        assert!(!proof.verify(&compute_book_root(&leaves[1..])));
+        proof.order.set_base_quantity(101);
+        assert!(!proof.verify(&root));
+    }
+}
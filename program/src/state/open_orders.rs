@@ -0,0 +1,291 @@
+//! Off-chain views over the orders of a market, for use in user interfaces, bots and indexers.
+use std::collections::HashMap;
+
+use solana_program::program_error::ProgramError;
+
+use crate::{
+    state::{
+        critbit::{read_slab_orders, LeafNode},
+        AccountTag, OrderId, Side,
+    },
+    utils::compute_quote_qty,
+};
+
+/// An order resting in the orderbook.
+#[derive(Debug, Clone, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct OpenOrder {
+    /// The id of the order.
+    pub order_id: u128,
+    /// The side of the order.
+    pub side: Side,
+    /// The limit price of the order, as an FP32 number of quote lots per base lot.
+    pub price: u64,
+    /// The sequence number which was assigned to the order when it was posted.
+    pub sequence_number: u64,
+    /// The base quantity of the order which is displayed in the orderbook, in base lots.
+    pub base_quantity: u64,
+    /// The base quantity of an iceberg order which isn't displayed in the orderbook yet, in base lots.
+    pub hidden_quantity: u64,
+    /// The maximum quantity of base lots displayed at once by an iceberg order, or 0 for regular orders.
+    pub max_display_quantity: u64,
+    /// The maximum timestamp at which the order can be matched.
+    pub max_ts: u64,
+    /// The caller-defined flags of the order.
+    pub flags: u8,
+    /// The callback info of the order.
+    pub callback_info: Vec<u8>,
+}
+
+impl OpenOrder {
+    fn new(side: Side, leaf: &LeafNode, callback_info: &[u8]) -> Self {
+        let order_id = OrderId(leaf.order_id());
+        Self {
+            order_id: order_id.0,
+            side,
+            price: order_id.price(),
+            sequence_number: order_id.sequence(),
+            base_quantity: leaf.base_quantity(),
+            hidden_quantity: leaf.hidden_quantity(),
+            max_display_quantity: leaf.max_display_quantity(),
+            max_ts: leaf.max_ts(),
+            flags: leaf.flags(),
+            callback_info: callback_info.to_vec(),
+        }
+    }
+
+    /// The total base quantity of the order, hidden quantity included.
+    pub fn total_base_quantity(&self) -> u64 {
+        self.base_quantity + self.hidden_quantity
+    }
+}
+
+/// The quantities committed by the orders of an owner which rest in the orderbook.
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct OwnerExposure {
+    /// The number of resting orders of the owner, on both sides.
+    pub num_orders: u64,
+    /// The total base quantity of the owner's bids, hidden quantity included, in base lots.
+    pub bid_base_quantity: u64,
+    /// The total quote quantity committed by the owner's bids, each rounded up as it would be paid when matched.
+    pub bid_quote_quantity: u64,
+    /// The total base quantity committed by the owner's asks, hidden quantity included, in base lots.
+    pub ask_base_quantity: u64,
+    /// The total quote quantity of the owner's asks, each rounded down as it would be received when matched.
+    pub ask_quote_quantity: u64,
+}
+
+impl OwnerExposure {
+    fn add(&mut self, order: &OpenOrder, quote_scale_exponent: u64) {
+        let base_quantity = order.total_base_quantity();
+        let quote_quantity =
+            compute_quote_qty(order.side, base_quantity, order.price, quote_scale_exponent)
+                .unwrap_or(u64::MAX);
+        self.num_orders += 1;
+        match order.side {
+            Side::Bid => {
+                self.bid_base_quantity = self.bid_base_quantity.saturating_add(base_quantity);
+                self.bid_quote_quantity = self.bid_quote_quantity.saturating_add(quote_quantity);
+            }
+            Side::Ask => {
+                self.ask_base_quantity = self.ask_base_quantity.saturating_add(base_quantity);
+                self.ask_quote_quantity = self.ask_quote_quantity.saturating_add(quote_quantity);
+            }
+        }
+    }
+}
+
+/// The orders of a market, sorted by price-time priority on each side.
+#[derive(Debug, Clone, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct Orderbook {
+    /// The bids, from the best (highest) price to the worst.
+    pub bids: Vec<OpenOrder>,
+    /// The asks, from the best (lowest) price to the worst.
+    pub asks: Vec<OpenOrder>,
+}
+
+impl Orderbook {
+    /// Reads the orderbook from the data of the bids and asks accounts of a market whose callback infos are
+    /// `callback_info_len` bytes long.
+    pub fn from_account_data(
+        bids: &[u8],
+        asks: &[u8],
+        callback_info_len: usize,
+    ) -> Result<Self, ProgramError> {
+        let read_side = |data, tag, side| {
+            read_slab_orders(data, tag, callback_info_len, side == Side::Ask).map(|orders| {
+                orders
+                    .iter()
+                    .map(|(leaf, callback_info)| OpenOrder::new(side, leaf, callback_info))
+                    .collect()
+            })
+        };
+        Ok(Self {
+            bids: read_side(bids, AccountTag::Bids, Side::Bid)?,
+            asks: read_side(asks, AccountTag::Asks, Side::Ask)?,
+        })
+    }
+
+    /// Returns the orders of the given side.
+    pub fn side(&self, side: Side) -> &[OpenOrder] {
+        match side {
+            Side::Bid => &self.bids,
+            Side::Ask => &self.asks,
+        }
+    }
+
+    /// Returns the best order of the given side.
+    pub fn best_order(&self, side: Side) -> Option<&OpenOrder> {
+        self.side(side).first()
+    }
+
+    /// Sums the quantities committed by the resting orders whose callback info starts with `callback_info_prefix`,
+    /// which identifies an owner the same way as a
+    /// [`CancelOrdersByOwner`][`crate::instruction::AgnosticOrderbookInstruction::CancelOrdersByOwner`] instruction.
+    ///
+    /// Quote quantities are expressed in units of `2^quote_scale_exponent` raw quote units, following the market's
+    /// `quote_scale_exponent`. The sums saturate at `u64::MAX`.
+    pub fn owner_exposure(
+        &self,
+        callback_info_prefix: &[u8],
+        quote_scale_exponent: u64,
+    ) -> OwnerExposure {
+        let mut exposure = OwnerExposure::default();
+        for order in self.bids.iter().chain(self.asks.iter()) {
+            if order.callback_info.starts_with(callback_info_prefix) {
+                exposure.add(order, quote_scale_exponent);
+            }
+        }
+        exposure
+    }
+
+    /// Sums the quantities committed by the resting orders of each distinct callback info, see
+    /// [`owner_exposure`][`Orderbook::owner_exposure`].
+    pub fn exposure_by_owner(&self, quote_scale_exponent: u64) -> HashMap<Vec<u8>, OwnerExposure> {
+        let mut exposures = HashMap::<Vec<u8>, OwnerExposure>::new();
+        for order in self.bids.iter().chain(self.asks.iter()) {
+            exposures
+                .entry(order.callback_info.clone())
+                .or_default()
+                .add(order, quote_scale_exponent);
+        }
+        exposures
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::state::critbit::Slab;
+
+    #[test]
+    fn test_orderbook_from_account_data() {
+        let allocation_size = Slab::<[u8; 32]>::compute_allocation_size(10);
+        let (mut bids_buffer, mut asks_buffer) =
+            (vec![0; allocation_size], vec![0; allocation_size]);
+        Slab::<[u8; 32]>::initialize(&mut asks_buffer, &mut bids_buffer).unwrap();
+
+        let orders = [
+            (Side::Bid, 10, 0, 100),
+            (Side::Bid, 12, 1, 200),
+            (Side::Bid, 12, 2, 300),
+            (Side::Ask, 15, 3, 400),
+            (Side::Ask, 14, 4, 500),
+        ];
+        for (side, price, sequence, base_quantity) in orders.iter() {
+            let buffer = match side {
+                Side::Bid => &mut bids_buffer,
+                Side::Ask => &mut asks_buffer,
+            };
+            let tag = match side {
+                Side::Bid => AccountTag::Bids,
+                Side::Ask => AccountTag::Asks,
+            };
+            let mut slab = Slab::<[u8; 32]>::from_buffer(buffer, tag).unwrap();
+            let order_id = OrderId::new(*price << 32, *sequence, *side).0;
+            slab.insert_leaf(
+                &LeafNode::new(order_id, *base_quantity, u64::MAX),
+                &[*sequence as u8; 32],
+            )
+            .unwrap();
+        }
+
+        let orderbook = Orderbook::from_account_data(&bids_buffer, &asks_buffer, 32).unwrap();
+        let summarize = |orders: &[OpenOrder]| {
+            orders
+                .iter()
+                .map(|o| {
+                    (
+                        o.price >> 32,
+                        o.sequence_number,
+                        o.base_quantity,
+                        o.callback_info[0],
+                    )
+                })
+                .collect::<Vec<_>>()
+        };
+        assert_eq!(
+            summarize(&orderbook.bids),
+            vec![(12, 1, 200, 1), (12, 2, 300, 2), (10, 0, 100, 0)]
+        );
+        assert_eq!(
+            summarize(&orderbook.asks),
+            vec![(14, 4, 500, 4), (15, 3, 400, 3)]
+        );
+        assert_eq!(orderbook.best_order(Side::Bid).unwrap().side, Side::Bid);
+
+        assert!(Orderbook::from_account_data(&asks_buffer, &bids_buffer, 32).is_err());
+    }
+
+    #[test]
+    fn test_owner_exposure() {
+        let order = |side, price: u64, base_quantity, hidden_quantity, owner| OpenOrder {
+            order_id: 0,
+            side,
+            price: price << 32,
+            sequence_number: 0,
+            base_quantity,
+            hidden_quantity,
+            max_display_quantity: 0,
+            max_ts: u64::MAX,
+            flags: 0,
+            callback_info: vec![owner, 0],
+        };
+        let orderbook = Orderbook {
+            bids: vec![
+                order(Side::Bid, 11, 100, 50, 1),
+                order(Side::Bid, 10, 30, 0, 2),
+            ],
+            asks: vec![
+                order(Side::Ask, 14, 20, 0, 1),
+                order(Side::Ask, 15, 7, 0, 1),
+            ],
+        };
+
+        let exposure = orderbook.owner_exposure(&[1], 0);
+        assert_eq!(
+            exposure,
+            OwnerExposure {
+                num_orders: 3,
+                bid_base_quantity: 150,
+                bid_quote_quantity: 1_650,
+                ask_base_quantity: 27,
+                ask_quote_quantity: 385,
+            }
+        );
+        // Bids round their quote quantity up and asks round it down
+        let exposure = orderbook.owner_exposure(&[1, 0], 2);
+        assert_eq!(
+            (exposure.bid_quote_quantity, exposure.ask_quote_quantity),
+            (413, 96)
+        );
+        assert_eq!(orderbook.owner_exposure(&[3], 0), OwnerExposure::default());
+
+        let exposures = orderbook.exposure_by_owner(0);
+        assert_eq!(exposures.len(), 2);
+        assert_eq!(exposures[&vec![1, 0]], orderbook.owner_exposure(&[1], 0));
+        assert_eq!(exposures[&vec![2, 0]].bid_quote_quantity, 300);
+    }
+}
@@ -5,16 +5,25 @@
 //! object, in order to benefit from the AOB's included security checks.
 use crate::{
     error::AoError,
-    processor::new_order,
+    processor::{continue_match, new_order},
     state::{
         critbit::{LeafNode, NodeHandle, Slab},
-        event_queue::{EventQueue, FillEvent, OutEvent},
-        AccountTag, SelfTradeBehavior, Side,
+        event_queue::{
+            BootEvent, BootReason, EventQueue, FillEvent, MakerFillEvent, ModifyEvent, OutEvent,
+            ReferralEvent, ReplenishEvent, TakerFillEvent,
+        },
+        get_side_from_order_id,
+        market_state::{MarketState, PendingTaker, PENDING_TAKER_CALLBACK_INFO_LEN},
+        AccountTag, BootingPolicy, MatchingMode, OrderId, PostOnlyMode, SelfTradeBehavior, Side,
+    },
+    utils::{
+        compute_base_qty, compute_quote_qty, max_base_qty_within_worst_price,
+        remaining_compute_units,
     },
 };
-use bonfida_utils::fp_math::{fp32_div, fp32_mul_ceil, fp32_mul_floor};
 use borsh::{BorshDeserialize, BorshSerialize};
-use bytemuck::Pod;
+use bytemuck::{Pod, Zeroable};
+use num_traits::FromPrimitive;
 use solana_program::{msg, program_error::ProgramError};
 
 /// This struct is written back into the event queue's register after new_order or cancel_order.
@@ -24,8 +33,9 @@ use solana_program::{msg, program_error::ProgramError};
 ///
 /// In the case of an order cancellation, the quantities describe what was left of the order in the orderbook.
 #[derive(Debug, BorshDeserialize, BorshSerialize)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub struct OrderSummary {
-    /// When applicable, the order id of the newly created order.
+    /// When applicable, the order id of the newly created order, or of the parked remainder of a resumable order.
     pub posted_order_id: Option<u128>,
     /// The total base quantity.
     pub total_base_qty: u64,
@@ -33,6 +43,38 @@ pub struct OrderSummary {
     pub total_quote_qty: u64,
     /// The total base quantity that was posted to the orderbook.
     pub total_base_qty_posted: u64,
+    /// The total quote quantity that was posted to the orderbook.
+    ///
+    /// The matched (taker) quantities of a new order are `total_base_qty - total_base_qty_posted` and
+    /// `total_quote_qty - total_quote_qty_posted`.
+    pub total_quote_qty_posted: u64,
+    /// The total base quantity that was neither matched nor posted to the orderbook.
+    pub total_base_qty_unfilled: u64,
+    /// The caller-defined flags of the order.
+    pub flags: u8,
+    /// Whether matching stopped because the match limit was reached, while the order could still have been matched
+    /// against the orderbook.
+    pub match_limit_exhausted: bool,
+    /// Whether matching stopped because fewer compute units than the order's `compute_unit_reserve` remained, while
+    /// the order could still have been matched against the orderbook.
+    pub compute_budget_exhausted: bool,
+}
+
+/// The result of a dry run of the matching logic for a new order, as computed by [`OrderBookState::simulate_match`].
+#[derive(Debug, Clone, Copy, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct MatchEstimate {
+    /// The total base quantity which would be matched.
+    pub base_qty: u64,
+    /// The total quote quantity which would be matched.
+    pub quote_qty: u64,
+    /// Whether the order would be entirely matched against the orderbook.
+    pub fully_filled: bool,
+    /// The number of events which would be pushed onto the event queue by the matching process, counting a single
+    /// event per fill.
+    pub num_events: u64,
+    /// The number of fills which would occur, each of which pushes an extra event on markets which split fills.
+    pub num_fills: u64,
 }
 
 /// This trait defines a subobject which can be used to compare two callback information object to determine
@@ -54,7 +96,119 @@ impl CallbackInfo for [u8; 32] {
 }
 
 /// The serialized size of an OrderSummary object.
-pub const ORDER_SUMMARY_SIZE: u32 = 41;
+pub const ORDER_SUMMARY_SIZE: u32 = 60;
+
+/// Computes the size of a register account which can hold the order summaries of `num_orders` orders, serialized as a
+/// length-prefixed `Vec<OrderSummary>`.
+pub const fn compute_register_size(num_orders: usize) -> usize {
+    4 + num_orders * ORDER_SUMMARY_SIZE as usize
+}
+
+/// Reads back the order summaries written into a register account by a batch instruction.
+pub fn read_order_summaries(register_data: &[u8]) -> std::io::Result<Vec<OrderSummary>> {
+    Vec::<OrderSummary>::deserialize(&mut &register_data[..])
+}
+
+/// An order removed from the orderbook by a cancel_order instruction.
+///
+/// The instruction writes it into the register as an `Option<OrderSummary>` followed by the order's limit price, as a
+/// little-endian `u64`, and by its callback info, so that programs which only read the order summary are unaffected.
+/// It can be read back with [`read_cancelled_order`].
+#[derive(Debug)]
+pub struct CancelledOrder<C> {
+    /// What was left of the order in the orderbook.
+    pub summary: OrderSummary,
+    /// The limit price of the order. This value is understood as a 32-bit fixed point number.
+    pub price: u64,
+    /// The callback information with which the order was placed.
+    pub callback_info: C,
+}
+
+/// Reads back the cancelled order written into a register account by a cancel_order instruction.
+pub fn read_cancelled_order<C: Pod>(register_data: &[u8]) -> std::io::Result<CancelledOrder<C>> {
+    let invalid_data = || std::io::Error::from(std::io::ErrorKind::InvalidData);
+    let mut data = register_data;
+    let summary = Option::<OrderSummary>::deserialize(&mut data)?.ok_or_else(invalid_data)?;
+    let price = u64::deserialize(&mut data)?;
+    let callback_info = data
+        .get(..std::mem::size_of::<C>())
+        .map(bytemuck::pod_read_unaligned)
+        .ok_or_else(invalid_data)?;
+    Ok(CancelledOrder {
+        summary,
+        price,
+        callback_info,
+    })
+}
+
+/// The orders of the orderbook at a given price.
+#[derive(BorshDeserialize, BorshSerialize, Debug, Clone, Copy, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct PriceLevel {
+    /// The price of the level. This value is understood as a 32-bit fixed point number.
+    pub price: u64,
+    /// The total base quantity displayed in the orderbook at this price.
+    pub base_quantity: u64,
+}
+
+/// The state of an order resting in the orderbook.
+#[derive(BorshDeserialize, BorshSerialize, Debug, Clone, Copy, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct OrderState {
+    #[allow(missing_docs)]
+    pub order_id: u128,
+    /// The limit price of the order. This value is understood as a 32-bit fixed point number.
+    pub price: u64,
+    /// The base quantity of the order which is displayed in the orderbook.
+    pub base_quantity: u64,
+    /// The base quantity of an iceberg order which isn't displayed in the orderbook yet.
+    pub hidden_quantity: u64,
+    /// The max timestamp at which the order can be matched against.
+    pub max_ts: u64,
+    /// The caller-defined flags of the order.
+    pub flags: u8,
+}
+
+/// The top of the orderbook and, optionally, the state of an order, as written into the register by a get_order
+/// instruction.
+#[derive(BorshDeserialize, BorshSerialize, Debug, Clone, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct Quote {
+    /// The best bid price level, ignoring expired orders.
+    pub best_bid: Option<PriceLevel>,
+    /// The best ask price level, ignoring expired orders.
+    pub best_ask: Option<PriceLevel>,
+    /// The requested order, or `None` when no order was requested or when the order isn't in the orderbook.
+    pub order: Option<OrderState>,
+}
+
+/// Reads back the quote written into a register account by a get_order instruction.
+pub fn read_quote(register_data: &[u8]) -> std::io::Result<Quote> {
+    Quote::deserialize(&mut &register_data[..])
+}
+
+/// The expected result of swapping through the orderbook, as computed by [`OrderBookState::quote_exact_in`] and
+/// [`OrderBookState::quote_exact_out`].
+///
+/// Amounts are in base lots when they are base quantities, and in quote lots otherwise.
+#[derive(BorshDeserialize, BorshSerialize, Debug, Clone, Copy, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct SwapQuote {
+    /// The amount which would be spent: quote for a bid, base for an ask.
+    pub in_amount: u64,
+    /// The amount which would be received: base for a bid, quote for an ask.
+    pub out_amount: u64,
+    /// Whether the requested amount could be entirely swapped against the orderbook, up to a remainder too small to
+    /// buy a single base lot.
+    pub fully_filled: bool,
+    /// The number of orders which would be matched.
+    pub num_fills: u64,
+}
+
+/// Reads back the swap quote written into a register account by a quote_swap instruction.
+pub fn read_swap_quote(register_data: &[u8]) -> std::io::Result<SwapQuote> {
+    SwapQuote::deserialize(&mut &register_data[..])
+}
 
 #[doc(hidden)]
 pub struct OrderBookState<'a, C> {
@@ -75,7 +229,61 @@ impl<'slab, C: Pod + Copy> OrderBookState<'slab, C> {
     }
 }
 
-impl<'a, C> OrderBookState<'a, C> {
+impl<'a, C: Pod> OrderBookState<'a, C> {
+    /// Finds an order in the orderbook from its id alone, since the side of an order is encoded in its id.
+    pub fn find_order(&self, order_id: u128) -> Option<(Side, NodeHandle)> {
+        let side = get_side_from_order_id(order_id);
+        let slab = match side {
+            Side::Bid => &self.bids,
+            Side::Ask => &self.asks,
+        };
+        slab.find_by_key(order_id).map(|h| (side, h))
+    }
+
+    /// Returns the best price level of each side at the unix timestamp `cur_ts` and, when `order_id` isn't 0, the
+    /// state of the order with this id.
+    pub fn get_quote(&self, order_id: u128, cur_ts: u64) -> Quote {
+        let best_level = |slab: &Slab<C>, side: Side| {
+            let mut orders = slab
+                .iter(side == Side::Ask)
+                .filter(|leaf| leaf.max_ts() >= cur_ts);
+            let best = orders.next()?;
+            let base_quantity = orders
+                .take_while(|leaf| leaf.price() == best.price())
+                .fold(best.base_quantity(), |qty, leaf| {
+                    qty.saturating_add(leaf.base_quantity())
+                });
+            Some(PriceLevel {
+                price: best.price(),
+                base_quantity,
+            })
+        };
+        let order = if order_id == 0 {
+            None
+        } else {
+            self.find_order(order_id).map(|(side, handle)| {
+                let leaf = match side {
+                    Side::Bid => &self.bids,
+                    Side::Ask => &self.asks,
+                }
+                .leaf_nodes[handle as usize];
+                OrderState {
+                    order_id,
+                    price: leaf.price(),
+                    base_quantity: leaf.base_quantity(),
+                    hidden_quantity: leaf.hidden_quantity(),
+                    max_ts: leaf.max_ts(),
+                    flags: leaf.flags(),
+                }
+            })
+        };
+        Quote {
+            best_bid: best_level(&self.bids, Side::Bid),
+            best_ask: best_level(&self.asks, Side::Ask),
+            order,
+        }
+    }
+
     pub fn find_bbo(&self, side: Side) -> Option<NodeHandle> {
         match side {
             Side::Bid => self.bids.find_max(),
@@ -95,6 +303,21 @@ impl<'a, C> OrderBookState<'a, C> {
         (best_bid_price, best_ask_price)
     }
 
+    /// Computes the effective limit price of a market order, from the best opposite order and the maximum slippage.
+    ///
+    /// If the opposite side of the orderbook is empty, the returned limit price will not cross any order.
+    pub fn get_market_order_limit_price(&self, side: Side, max_slippage: u64) -> u64 {
+        let (best_bid_price, best_ask_price) = self.get_spread();
+        match side {
+            Side::Bid => best_ask_price
+                .map(|p| p.saturating_add(max_slippage))
+                .unwrap_or(0),
+            Side::Ask => best_bid_price
+                .map(|p| p.saturating_sub(max_slippage))
+                .unwrap_or(u64::MAX),
+        }
+    }
+
     pub fn get_tree(&mut self, side: Side) -> &mut Slab<'a, C> {
         match side {
             Side::Bid => &mut self.bids,
@@ -105,12 +328,25 @@ impl<'a, C> OrderBookState<'a, C> {
     pub fn is_empty(&self) -> bool {
         self.asks.header.leaf_count == 0 && self.bids.header.leaf_count == 0
     }
+
+    /// Checks whether the best bid is priced at or above the best ask.
+    ///
+    /// Crossing orders are always matched, so a crossed orderbook can only be the result of a bug.
+    pub fn is_crossed(&self) -> bool {
+        matches!(self.get_spread(), (Some(best_bid_price), Some(best_ask_price)) if best_bid_price >= best_ask_price)
+    }
 }
 
 impl<'a, C: CallbackInfo> OrderBookState<'a, C>
 where
     <C as CallbackInfo>::CallbackId: PartialEq,
 {
+    /// Counts the orders resting on both sides of the orderbook with the same callback information as `callback_info`,
+    /// using the owner index of the slabs.
+    pub fn count_owner_orders(&self, callback_info: &C) -> u64 {
+        self.bids.count_owner_orders(callback_info) + self.asks.count_owner_orders(callback_info)
+    }
+
     pub(crate) fn prune_orders(
         &mut self,
         num_orders_to_prune: u64,
@@ -122,888 +358,5675 @@ where
             let boot_candidate = slab.find_min().expect("Should be a bid/ask there");
             let boot_candidate_key = slab.leaf_nodes[boot_candidate as usize].key();
             let (order, callback_info_booted) = slab.remove_by_key(boot_candidate_key).unwrap();
-            let out = OutEvent::new(side, order.base_quantity(), order.order_id());
+            let boot = BootEvent::new(
+                side,
+                BootReason::Pruned,
+                order.total_base_quantity(),
+                order.order_id(),
+            );
             event_queue
-                .push_back(out, Some(callback_info_booted), None)
+                .push_back(boot, Some(callback_info_booted), None)
                 .map_err(|_| AoError::EventQueueFull)?;
         }
         Ok(())
     }
 
-    pub fn new_order(
+    pub(crate) fn prune_expired(
         &mut self,
-        params: new_order::Params<C>,
-        event_queue: &mut EventQueue<'a, C>,
-        min_base_order_size: u64,
+        num_orders_to_prune: u64,
         cur_ts: u64,
-    ) -> Result<OrderSummary, AoError> {
-        let new_order::Params {
-            max_base_qty,
-            max_quote_qty,
-            side,
-            limit_price,
-            callback_info,
-            post_only,
-            post_allowed,
-            self_trade_behavior,
-            max_ts,
-            mut match_limit,
-        } = params;
-
-        let mut base_qty_remaining = max_base_qty;
-        let mut quote_qty_remaining = max_quote_qty;
-
-        // New bid
-        let mut crossed = true;
-        loop {
-            if match_limit == 0 {
-                break;
+        event_queue: &mut EventQueue<'a, C>,
+    ) -> Result<u64, AoError> {
+        let mut num_pruned = 0;
+        for side in [Side::Bid, Side::Ask].iter() {
+            let slab = self.get_tree(*side);
+            let expired_order_ids = slab
+                .iter(true)
+                .filter(|leaf| leaf.max_ts() < cur_ts)
+                .map(|leaf| leaf.order_id())
+                .take((num_orders_to_prune - num_pruned) as usize)
+                .collect::<Vec<_>>();
+            for order_id in expired_order_ids {
+                let (order, callback_info_expired) = slab.remove_by_key(order_id).unwrap();
+                let out = OutEvent::new(*side, order.total_base_quantity(), order_id);
+                event_queue
+                    .push_back(out, Some(callback_info_expired), None)
+                    .map_err(|_| AoError::EventQueueFull)?;
+                num_pruned += 1;
             }
-            let best_bo_h = match self.find_bbo(side.opposite()) {
-                None => {
-                    crossed = false;
-                    break;
-                }
-                Some(h) => h,
-            };
-
-            let opposite_slab = self.get_tree(side.opposite());
-
-            let best_bo_ref = &mut opposite_slab.leaf_nodes[best_bo_h as usize];
+        }
+        Ok(num_pruned)
+    }
 
-            // The order on the book has exceeded max ts, we will boot it
-            // and continue attempting to match
-            if best_bo_ref.max_ts() < cur_ts {
-                let best_offer_id = best_bo_ref.order_id();
-                let provide_out_callback_info = &opposite_slab.callback_infos[best_bo_h as usize];
-                let provide_out =
-                    OutEvent::new(side.opposite(), best_bo_ref.base_quantity(), best_offer_id);
+    /// Removes at most `max_orders` orders whose remaining quantity is too small to be inserted into the orderbook,
+    /// from both sides of the orderbook. An Out event is pushed for each one of them.
+    pub(crate) fn clean_book(
+        &mut self,
+        max_orders: u64,
+        market_state: &MarketState,
+        event_queue: &mut EventQueue<'a, C>,
+    ) -> Result<u64, AoError> {
+        let mut num_cleaned = 0;
+        for side in [Side::Bid, Side::Ask].iter() {
+            let slab = self.get_tree(*side);
+            let dust_order_ids = slab
+                .iter(true)
+                .filter(|leaf| {
+                    !market_state.is_postable_size(leaf.total_base_quantity(), leaf.price())
+                })
+                .map(|leaf| leaf.order_id())
+                .take((max_orders - num_cleaned) as usize)
+                .collect::<Vec<_>>();
+            for order_id in dust_order_ids {
+                let (order, callback_info) = slab.remove_by_key(order_id).unwrap();
+                let out = OutEvent::new(*side, order.total_base_quantity(), order_id);
                 event_queue
-                    .push_back(provide_out, Some(provide_out_callback_info), None)
+                    .push_back(out, Some(callback_info), None)
                     .map_err(|_| AoError::EventQueueFull)?;
+                num_cleaned += 1;
+            }
+        }
+        Ok(num_cleaned)
+    }
 
-                self.get_tree(side.opposite())
-                    .remove_by_key(best_offer_id)
-                    .unwrap();
+    /// Uncrosses the orderbook by removing at most `max_orders` orders. While the best bid is priced at or above the
+    /// best ask, the more recent of the two is removed. An Out event is pushed for each one of them.
+    pub(crate) fn fix_crossed_book(
+        &mut self,
+        max_orders: u64,
+        event_queue: &mut EventQueue<'a, C>,
+    ) -> Result<u64, AoError> {
+        let mut num_removed = 0;
+        while num_removed < max_orders && self.is_crossed() {
+            let best_bid_id =
+                self.bids.leaf_nodes[self.bids.find_max().unwrap() as usize].order_id();
+            let best_ask_id =
+                self.asks.leaf_nodes[self.asks.find_min().unwrap() as usize].order_id();
+            let (side, order_id) =
+                if OrderId::from(best_bid_id).sequence() > OrderId::from(best_ask_id).sequence() {
+                    (Side::Bid, best_bid_id)
+                } else {
+                    (Side::Ask, best_ask_id)
+                };
+            let (order, callback_info) = self.get_tree(side).remove_by_key(order_id).unwrap();
+            let out = OutEvent::new(side, order.total_base_quantity(), order_id);
+            event_queue
+                .push_back(out, Some(callback_info), None)
+                .map_err(|_| AoError::EventQueueFull)?;
+            num_removed += 1;
+        }
+        Ok(num_removed)
+    }
 
-                match_limit -= 1;
+    /// Replenishes the visible quantity of an order which was just matched if it is an iceberg order, or removes it
+    /// from the orderbook with an Out event if its remaining quantity is below the minimum order size.
+    fn refresh_matched_order(
+        &mut self,
+        side: Side,
+        handle: NodeHandle,
+        min_base_order_size: u64,
+        event_queue: &mut EventQueue<'a, C>,
+    ) -> Result<(), AoError> {
+        let slab = self.get_tree(side);
+        let order = slab.leaf_nodes[handle as usize];
+        if order.base_quantity() < min_base_order_size.max(1) && order.hidden_quantity() > 0 {
+            let replenished_qty = slab.update_leaf(handle, |order| order.replenish());
+            let replenish_event = ReplenishEvent::new(side, replenished_qty, order.order_id());
+            event_queue
+                .push_back(
+                    replenish_event,
+                    Some(&slab.callback_infos[handle as usize]),
+                    None,
+                )
+                .map_err(|_| AoError::EventQueueFull)?;
+        } else if order.base_quantity() < min_base_order_size {
+            let order_id = order.order_id();
+            let out_event = OutEvent::new(side, order.base_quantity(), order_id);
+            let (_, out_event_callback_info) = slab.remove_by_key(order_id).unwrap();
+            event_queue
+                .push_back(out_event, Some(out_event_callback_info), None)
+                .map_err(|_| AoError::EventQueueFull)?;
+        }
+        Ok(())
+    }
 
-                continue;
+    /// Computes the single price at which the crossed orders of an auction market would be matched, along with the
+    /// matched base quantity.
+    ///
+    /// The clearing price is the price of a crossed order which maximizes the matched quantity. Ties are broken by
+    /// minimizing the unmatched quantity at that price, and then by picking the lowest price. Expired orders are
+    /// ignored. Returns `None` if no order would be matched.
+    pub fn compute_clearing_price(&self, cur_ts: u64) -> Option<(u64, u64)> {
+        let (best_bid_price, best_ask_price) = match self.get_spread() {
+            (Some(b), Some(a)) if b >= a => (b, a),
+            _ => return None,
+        };
+        // The cumulative quantities demanded at or above each bid price, in descending price order
+        let mut demand = Vec::new();
+        let mut total = 0u64;
+        for leaf in self.bids.iter(false) {
+            if leaf.price() < best_ask_price {
+                break;
             }
-
-            let trade_price = best_bo_ref.price();
-            crossed = match side {
-                Side::Bid => limit_price >= trade_price,
-                Side::Ask => limit_price <= trade_price,
-            };
-
-            if post_only || !crossed {
+            if leaf.max_ts() >= cur_ts {
+                total = total.saturating_add(leaf.total_base_quantity());
+                demand.push((leaf.price(), total));
+            }
+        }
+        // The cumulative quantities supplied at or below each ask price, in ascending price order
+        let mut supply = Vec::new();
+        let mut total = 0u64;
+        for leaf in self.asks.iter(true) {
+            if leaf.price() > best_bid_price {
                 break;
             }
+            if leaf.max_ts() >= cur_ts {
+                total = total.saturating_add(leaf.total_base_quantity());
+                supply.push((leaf.price(), total));
+            }
+        }
 
-            let offer_size = best_bo_ref.base_quantity();
-            let base_trade_qty = offer_size
-                .min(base_qty_remaining)
-                .min(fp32_div(quote_qty_remaining, best_bo_ref.price()).unwrap_or(u64::MAX));
+        let mut candidates = demand
+            .iter()
+            .chain(supply.iter())
+            .map(|(price, _)| *price)
+            .collect::<Vec<_>>();
+        candidates.sort_unstable();
+        candidates.dedup();
 
-            if base_trade_qty == 0 {
-                break;
+        let mut best: Option<(u64, u64, u64)> = None;
+        for price in candidates {
+            let demanded_qty = match demand.partition_point(|(p, _)| *p >= price) {
+                0 => 0,
+                i => demand[i - 1].1,
+            };
+            let supplied_qty = match supply.partition_point(|(p, _)| *p <= price) {
+                0 => 0,
+                i => supply[i - 1].1,
+            };
+            let matched_qty = demanded_qty.min(supplied_qty);
+            let imbalance = demanded_qty.max(supplied_qty) - matched_qty;
+            let is_better = match best {
+                None => true,
+                Some((_, best_matched_qty, best_imbalance)) => {
+                    matched_qty > best_matched_qty
+                        || (matched_qty == best_matched_qty && imbalance < best_imbalance)
+                }
+            };
+            if is_better {
+                best = Some((price, matched_qty, imbalance));
             }
+        }
+        best.filter(|(_, matched_qty, _)| *matched_qty > 0)
+            .map(|(price, matched_qty, _)| (price, matched_qty))
+    }
 
-            let quote_maker_qty = match side {
-                Side::Bid => fp32_mul_ceil(base_trade_qty, trade_price),
-                Side::Ask => fp32_mul_floor(base_trade_qty, trade_price),
+    /// Matches the crossed orders of an auction market at a single clearing price, see
+    /// [`OrderBookState::compute_clearing_price`].
+    ///
+    /// Both sides of each match are resting orders, so each match pushes a
+    /// [`MakerFillEvent`] for each of them, with a quote size rounded against its order. Expired orders are removed with
+    /// an Out event along the way. At most `match_limit` orders are matched or removed.
+    ///
+    /// Returns the clearing price, if any order was matched.
+    pub(crate) fn run_auction(
+        &mut self,
+        mut match_limit: u64,
+        market_state: &mut MarketState,
+        event_queue: &mut EventQueue<'a, C>,
+        cur_ts: u64,
+    ) -> Result<Option<u64>, AoError> {
+        let clearing_price = match self.compute_clearing_price(cur_ts) {
+            Some((price, _)) => price,
+            None => return Ok(None),
+        };
+        while match_limit > 0 {
+            let (bid_h, ask_h) = match (self.bids.find_max(), self.asks.find_min()) {
+                (Some(bid_h), Some(ask_h)) => (bid_h, ask_h),
+                _ => break,
+            };
+            let expired_order = [(Side::Bid, bid_h), (Side::Ask, ask_h)]
+                .iter()
+                .copied()
+                .find(|(side, h)| self.get_tree(*side).leaf_nodes[*h as usize].max_ts() < cur_ts);
+            if let Some((side, h)) = expired_order {
+                let slab = self.get_tree(side);
+                let order_id = slab.leaf_nodes[h as usize].order_id();
+                let (order, callback_info) = slab.remove_by_key(order_id).unwrap();
+                let out = OutEvent::new(side, order.total_base_quantity(), order_id);
+                event_queue
+                    .push_back(out, Some(callback_info), None)
+                    .map_err(|_| AoError::EventQueueFull)?;
+                match_limit -= 1;
+                continue;
             }
-            .map(|q| std::cmp::min(q, quote_qty_remaining))
-            .ok_or(AoError::NumericalOverflow)?;
 
-            if quote_maker_qty == 0 {
+            let bid = self.bids.leaf_nodes[bid_h as usize];
+            let ask = self.asks.leaf_nodes[ask_h as usize];
+            if bid.price() < clearing_price || ask.price() > clearing_price {
                 break;
             }
-
-            // The decrement take case can be handled by the caller program on event consumption, so no special logic
-            // is needed for it.
-            if self_trade_behavior != SelfTradeBehavior::DecrementTake {
-                let order_would_self_trade = callback_info.as_callback_id()
-                    == opposite_slab.callback_infos[best_bo_h as usize].as_callback_id();
-                if order_would_self_trade {
-                    let best_offer_id = best_bo_ref.order_id();
-
-                    if self_trade_behavior == SelfTradeBehavior::AbortTransaction {
-                        return Err(AoError::WouldSelfTrade);
-                    }
-                    assert!(self_trade_behavior == SelfTradeBehavior::CancelProvide);
-                    let provide_out_callback_info =
-                        &opposite_slab.callback_infos[best_bo_h as usize];
-                    let provide_out =
-                        OutEvent::new(side.opposite(), best_bo_ref.base_quantity(), best_offer_id);
-                    event_queue
-                        .push_back(provide_out, Some(provide_out_callback_info), None)
-                        .map_err(|_| AoError::EventQueueFull)?;
-
-                    self.get_tree(side.opposite())
-                        .remove_by_key(best_offer_id)
-                        .unwrap();
-
-                    match_limit -= 1;
-
-                    continue;
-                }
+            let base_trade_qty = bid.base_quantity().min(ask.base_quantity());
+            if base_trade_qty == 0 {
+                break;
             }
+            let bid_quote_qty = compute_quote_qty(
+                Side::Bid,
+                base_trade_qty,
+                clearing_price,
+                market_state.quote_scale_exponent,
+            )
+            .ok_or(AoError::NumericalOverflow)?;
+            let ask_quote_qty = compute_quote_qty(
+                Side::Ask,
+                base_trade_qty,
+                clearing_price,
+                market_state.quote_scale_exponent,
+            )
+            .ok_or(AoError::NumericalOverflow)?;
 
-            let maker_callback_info = &opposite_slab.callback_infos[best_bo_h as usize];
-
-            let maker_fill = FillEvent::new(
-                side,
-                quote_maker_qty,
-                best_bo_ref.order_id(),
+            let mut bid_fill = FillEvent::new(
+                Side::Ask,
+                bid_quote_qty,
+                bid.order_id(),
                 base_trade_qty,
+                ask.flags(),
+                bid.flags(),
             );
+            market_state.charge_fees(&mut bid_fill, bid_quote_qty, false);
             event_queue
-                .push_back(maker_fill, Some(maker_callback_info), Some(&callback_info))
+                .push_back(
+                    MakerFillEvent::new(bid_fill),
+                    Some(&self.bids.callback_infos[bid_h as usize]),
+                    None,
+                )
+                .map_err(|_| AoError::EventQueueFull)?;
+            let mut ask_fill = FillEvent::new(
+                Side::Bid,
+                ask_quote_qty,
+                ask.order_id(),
+                base_trade_qty,
+                bid.flags(),
+                ask.flags(),
+            );
+            market_state.charge_fees(&mut ask_fill, ask_quote_qty, false);
+            event_queue
+                .push_back(
+                    MakerFillEvent::new(ask_fill),
+                    Some(&self.asks.callback_infos[ask_h as usize]),
+                    None,
+                )
                 .map_err(|_| AoError::EventQueueFull)?;
 
-            best_bo_ref.set_base_quantity(best_bo_ref.base_quantity() - base_trade_qty);
-            base_qty_remaining -= base_trade_qty;
-            quote_qty_remaining -= quote_maker_qty;
-
-            if best_bo_ref.base_quantity() < min_base_order_size {
-                let best_offer_id = best_bo_ref.order_id();
-                let cur_side = side.opposite();
-                let out_event = OutEvent::new(cur_side, best_bo_ref.base_quantity(), best_offer_id);
+            self.bids.update_leaf(bid_h, |leaf| {
+                leaf.set_base_quantity(bid.base_quantity() - base_trade_qty)
+            });
+            self.asks.update_leaf(ask_h, |leaf| {
+                leaf.set_base_quantity(ask.base_quantity() - base_trade_qty)
+            });
+            market_state.update_price_accumulator(cur_ts);
+            market_state.last_trade_price = clearing_price;
+            market_state.cumulative_base_volume = market_state
+                .cumulative_base_volume
+                .wrapping_add(base_trade_qty);
+            market_state.cumulative_quote_volume = market_state
+                .cumulative_quote_volume
+                .wrapping_add(bid_quote_qty);
 
-                let (_, out_event_callback_info) = self
-                    .get_tree(cur_side)
-                    .remove_by_key(best_offer_id)
-                    .unwrap();
-                event_queue
-                    .push_back(out_event, Some(out_event_callback_info), None)
-                    .map_err(|_| AoError::EventQueueFull)?;
-            }
+            self.refresh_matched_order(
+                Side::Bid,
+                bid_h,
+                market_state.min_base_order_size,
+                event_queue,
+            )?;
+            self.refresh_matched_order(
+                Side::Ask,
+                ask_h,
+                market_state.min_base_order_size,
+                event_queue,
+            )?;
 
             match_limit -= 1;
         }
+        Ok(Some(clearing_price))
+    }
 
-        let base_qty_to_post = std::cmp::min(
-            fp32_div(quote_qty_remaining, limit_price).unwrap_or(u64::MAX),
-            base_qty_remaining,
-        );
-
-        if crossed || !post_allowed || base_qty_to_post < min_base_order_size {
-            return Ok(OrderSummary {
-                posted_order_id: None,
-                total_base_qty: max_base_qty - base_qty_remaining,
-                total_quote_qty: max_quote_qty - quote_qty_remaining,
-                total_base_qty_posted: 0,
-            });
-        }
-
-        let new_leaf_order_id = event_queue.gen_order_id(limit_price, side);
-        let new_leaf = LeafNode::new(new_leaf_order_id, base_qty_to_post, max_ts);
-        let insert_result = self.get_tree(side).insert_leaf(&new_leaf);
-        let k = if let Err(AoError::SlabOutOfSpace) = insert_result {
-            // Boot out the least aggressive orders
-            msg!("Orderbook is full! booting least aggressive orders...");
-            let slab = self.get_tree(side);
-            let boot_candidate = match side {
-                Side::Bid => slab.find_min().unwrap(),
-                Side::Ask => slab.find_max().unwrap(),
-            };
-            let boot_candidate_key = slab.leaf_nodes[boot_candidate as usize].key();
-            let boot_candidate_price = LeafNode::price_from_key(boot_candidate_key);
-            let should_boot = match side {
-                Side::Bid => boot_candidate_price < limit_price,
-                Side::Ask => boot_candidate_price > limit_price,
+    /// Removes at most `max_cancels` orders whose callback information starts with the given prefix, from both sides of
+    /// the orderbook. An Out event is pushed for each one of them.
+    ///
+    /// A prefix which spans the whole callback information is looked up through the owner index of the slabs, other
+    /// prefixes require a scan of the orderbook.
+    ///
+    /// Returns the total base and quote quantities of the removed orders.
+    pub(crate) fn cancel_orders_by_prefix(
+        &mut self,
+        callback_info_prefix: &[u8],
+        max_cancels: u64,
+        quote_scale_exponent: u64,
+        event_queue: &mut EventQueue<'a, C>,
+    ) -> Result<OrderSummary, AoError> {
+        let mut num_cancelled = 0;
+        let mut total_base_qty = 0u64;
+        let mut total_quote_qty = 0u64;
+        for side in [Side::Bid, Side::Ask].iter() {
+            let slab = self.get_tree(*side);
+            let max_orders = (max_cancels - num_cancelled) as usize;
+            let handles = if callback_info_prefix.len() == std::mem::size_of::<C>() {
+                let callback_info = bytemuck::pod_read_unaligned::<C>(callback_info_prefix);
+                slab.iter_owner_handles(&callback_info)
+                    .take(max_orders)
+                    .collect::<Vec<_>>()
+            } else {
+                slab.iter_handles(true)
+                    .filter(|h| {
+                        bytemuck::bytes_of(slab.get_callback_info(*h))
+                            .starts_with(callback_info_prefix)
+                    })
+                    .take(max_orders)
+                    .collect::<Vec<_>>()
             };
-            if should_boot {
-                let (order, callback_info_booted) = slab.remove_by_key(boot_candidate_key).unwrap();
-                let out = OutEvent::new(side, order.base_quantity(), order.order_id());
+            let order_ids = handles
+                .into_iter()
+                .map(|h| slab.leaf_nodes[h as usize].order_id())
+                .collect::<Vec<_>>();
+            for order_id in order_ids {
+                let (order, callback_info) = slab.remove_by_key(order_id).unwrap();
+                total_base_qty = total_base_qty
+                    .checked_add(order.total_base_quantity())
+                    .ok_or(AoError::NumericalOverflow)?;
+                total_quote_qty = compute_quote_qty(
+                    Side::Ask,
+                    order.total_base_quantity(),
+                    order.price(),
+                    quote_scale_exponent,
+                )
+                .and_then(|n| n.checked_add(total_quote_qty))
+                .ok_or(AoError::NumericalOverflow)?;
+                let out = OutEvent::new(*side, order.total_base_quantity(), order_id);
                 event_queue
-                    .push_back(out, Some(callback_info_booted), None)
+                    .push_back(out, Some(callback_info), None)
                     .map_err(|_| AoError::EventQueueFull)?;
-                slab.insert_leaf(&new_leaf).unwrap().0
-            } else {
-                return Ok(OrderSummary {
-                    posted_order_id: None,
-                    total_base_qty: max_base_qty - base_qty_remaining,
-                    total_quote_qty: max_quote_qty - quote_qty_remaining,
-                    total_base_qty_posted: 0,
-                });
+                num_cancelled += 1;
             }
-        } else {
-            insert_result.unwrap().0
-        };
-        *self.get_tree(side).get_callback_info_mut(k) = callback_info;
-        base_qty_remaining -= base_qty_to_post;
-        quote_qty_remaining -= match side {
-            Side::Bid => fp32_mul_ceil(base_qty_to_post, limit_price),
-            Side::Ask => fp32_mul_floor(base_qty_to_post, limit_price),
         }
-        .ok_or(AoError::NumericalOverflow)?;
         Ok(OrderSummary {
-            posted_order_id: Some(new_leaf_order_id),
-            total_base_qty: max_base_qty - base_qty_remaining,
-            total_quote_qty: max_quote_qty - quote_qty_remaining,
-            total_base_qty_posted: base_qty_to_post,
+            posted_order_id: None,
+            total_base_qty,
+            total_quote_qty,
+            total_base_qty_posted: 0,
+            total_quote_qty_posted: 0,
+            total_base_qty_unfilled: 0,
+            flags: 0,
+            match_limit_exhausted: false,
+            compute_budget_exhausted: false,
         })
     }
-}
 
-#[cfg(test)]
-mod tests {
-    use crate::state::event_queue::{EventRef, FillEventRef, OutEventRef};
+    /// Walks the opposite side of the orderbook to compute how much of a new order would be matched, without mutating any state.
+    ///
+    /// This mirrors the matching loop of [`OrderBookState::new_order`]: expired orders and orders cancelled through
+    /// [`SelfTradeBehavior::CancelProvide`] are skipped while still counting towards the match limit, and the hidden
+    /// quantity of iceberg orders is replenished as it would be during matching.
+    pub fn simulate_match(
+        &self,
+        params: &new_order::Params<C>,
+        min_base_order_size: u64,
+        quote_scale_exponent: u64,
+        cur_ts: u64,
+    ) -> Result<MatchEstimate, AoError> {
+        let mut base_qty_remaining = params.max_base_qty;
+        let mut quote_qty_remaining = params.max_quote_qty;
+        let mut match_limit = params.match_limit;
+        let mut fully_filled = false;
+        let mut num_events = 0;
+        let mut num_fills = 0;
+        let mut matched_notional = 0u128;
+        let mut worst_price_reached = false;
 
-    use super::*;
+        let opposite_slab = match params.side {
+            Side::Bid => &self.asks,
+            Side::Ask => &self.bids,
+        };
 
-    type SlabTest<'a> = Slab<'a, [u8; 32]>;
-    type OrderBookStateTest<'a> = OrderBookState<'a, [u8; 32]>;
-    type EventQueueTest<'a> = EventQueue<'a, [u8; 32]>;
+        'orders: for h in opposite_slab.iter_handles(params.side == Side::Bid) {
+            let mut leaf = opposite_slab.leaf_nodes[h as usize];
+            loop {
+                if match_limit == 0 || params.post_only || worst_price_reached {
+                    break 'orders;
+                }
+                if leaf.max_ts() < cur_ts {
+                    match_limit -= 1;
+                    num_events += 1;
+                    continue 'orders;
+                }
+                let trade_price = leaf.price();
+                let crossed = match params.side {
+                    Side::Bid => params.limit_price >= trade_price,
+                    Side::Ask => params.limit_price <= trade_price,
+                };
+                if !crossed {
+                    break 'orders;
+                }
+                let worst_price_bound =
+                    Some(params.worst_price).filter(|p| *p != 0).and_then(|p| {
+                        max_base_qty_within_worst_price(
+                            params.side,
+                            params.max_base_qty - base_qty_remaining,
+                            matched_notional,
+                            trade_price,
+                            p,
+                        )
+                    });
+                let base_trade_qty = leaf
+                    .base_quantity()
+                    .min(base_qty_remaining)
+                    .min(
+                        compute_base_qty(quote_qty_remaining, trade_price, quote_scale_exponent)
+                            .unwrap_or(u64::MAX),
+                    )
+                    .min(worst_price_bound.unwrap_or(u64::MAX));
+                worst_price_reached = matches!(worst_price_bound, Some(q) if q <= base_trade_qty);
+                if base_trade_qty == 0 {
+                    fully_filled = !worst_price_reached;
+                    break 'orders;
+                }
+                let quote_maker_qty = compute_quote_qty(
+                    params.side,
+                    base_trade_qty,
+                    trade_price,
+                    quote_scale_exponent,
+                )
+                .map(|q| std::cmp::min(q, quote_qty_remaining))
+                .ok_or(AoError::NumericalOverflow)?;
+                if quote_maker_qty == 0 {
+                    fully_filled = true;
+                    break 'orders;
+                }
+                if params.self_trade_behavior != SelfTradeBehavior::DecrementTake
+                    && params.callback_info.as_callback_id()
+                        == opposite_slab.callback_infos[h as usize].as_callback_id()
+                {
+                    match params.self_trade_behavior {
+                        SelfTradeBehavior::AbortTransaction => {
+                            msg!("The order would self trade!");
+                            return Err(AoError::WouldSelfTrade);
+                        }
+                        SelfTradeBehavior::CancelLowestPriority => break 'orders,
+                        SelfTradeBehavior::CancelBoth => {
+                            num_events += 1;
+                            break 'orders;
+                        }
+                        _ => {}
+                    }
+                    match_limit -= 1;
+                    num_events += 1;
+                    continue 'orders;
+                }
+                base_qty_remaining -= base_trade_qty;
+                quote_qty_remaining -= quote_maker_qty;
+                matched_notional =
+                    matched_notional.saturating_add(base_trade_qty as u128 * trade_price as u128);
+                match_limit -= 1;
+                num_events += 1;
+                num_fills += 1;
 
-    pub struct TestContext {
-        asks_buffer: Vec<u8>,
-        bids_buffer: Vec<u8>,
-        event_queue_buffer: Vec<u8>,
+                leaf.set_base_quantity(leaf.base_quantity() - base_trade_qty);
+                if leaf.base_quantity() < min_base_order_size.max(1) && leaf.hidden_quantity() > 0 {
+                    leaf.replenish();
+                    num_events += 1;
+                } else if leaf.base_quantity() < min_base_order_size {
+                    num_events += 1;
+                    if worst_price_reached {
+                        break 'orders;
+                    }
+                    continue 'orders;
+                }
+            }
+        }
+
+        Ok(MatchEstimate {
+            base_qty: params.max_base_qty - base_qty_remaining,
+            quote_qty: params.max_quote_qty - quote_qty_remaining,
+            fully_filled: fully_filled || base_qty_remaining == 0 || quote_qty_remaining == 0,
+            num_events,
+            num_fills,
+        })
     }
 
-    impl TestContext {
-        pub fn new(order_capacity: usize, event_capacity: usize) -> Self {
-            let allocation_size = SlabTest::compute_allocation_size(order_capacity);
-            let (mut asks_buffer, mut bids_buffer) =
-                (vec![0; allocation_size], vec![0; allocation_size]);
-            SlabTest::initialize(&mut asks_buffer, &mut bids_buffer).unwrap();
-            Self {
-                asks_buffer,
-                bids_buffer,
-                event_queue_buffer: vec![
-                    0;
-                    EventQueueTest::compute_allocation_size(event_capacity)
-                ],
+    /// Computes how much a market order of the given side would receive for spending `amount_in`, without mutating any
+    /// state. At most `match_limit` orders are walked.
+    pub fn quote_exact_in(
+        &self,
+        side: Side,
+        amount_in: u64,
+        match_limit: u64,
+        market_state: &MarketState,
+        cur_ts: u64,
+    ) -> Result<SwapQuote, AoError> {
+        let (max_base_qty, max_quote_qty) = match side {
+            Side::Bid => (u64::MAX, amount_in),
+            Side::Ask => (amount_in, u64::MAX),
+        };
+        self.quote_swap(
+            side,
+            max_base_qty,
+            max_quote_qty,
+            match_limit,
+            market_state,
+            cur_ts,
+        )
+    }
+
+    /// Computes how much a market order of the given side would need to spend to receive `amount_out`, without
+    /// mutating any state. At most `match_limit` orders are walked.
+    pub fn quote_exact_out(
+        &self,
+        side: Side,
+        amount_out: u64,
+        match_limit: u64,
+        market_state: &MarketState,
+        cur_ts: u64,
+    ) -> Result<SwapQuote, AoError> {
+        let (max_base_qty, max_quote_qty) = match side {
+            Side::Bid => (amount_out, u64::MAX),
+            Side::Ask => (u64::MAX, amount_out),
+        };
+        self.quote_swap(
+            side,
+            max_base_qty,
+            max_quote_qty,
+            match_limit,
+            market_state,
+            cur_ts,
+        )
+    }
+
+    fn quote_swap(
+        &self,
+        side: Side,
+        max_base_qty: u64,
+        max_quote_qty: u64,
+        match_limit: u64,
+        market_state: &MarketState,
+        cur_ts: u64,
+    ) -> Result<SwapQuote, AoError> {
+        let params = new_order::Params {
+            max_base_qty,
+            max_quote_qty,
+            limit_price: match side {
+                Side::Bid => u64::MAX,
+                Side::Ask => 0,
+            },
+            side,
+            match_limit,
+            callback_info: C::zeroed(),
+            post_only: false,
+            post_allowed: false,
+            self_trade_behavior: SelfTradeBehavior::DecrementTake,
+            max_ts: u64::MAX,
+            immediate_or_cancel: true,
+            fill_or_kill: false,
+            max_display_qty: 0,
+            post_only_mode: PostOnlyMode::Reject,
+            max_slippage: 0,
+            flags: 0,
+            min_base_qty: 0,
+            compute_unit_reserve: 0,
+            resumable: false,
+            worst_price: 0,
+            referral: [0; 32],
+            client_order_id: 0,
+        };
+        let estimate = self.simulate_match(
+            &params,
+            market_state.min_base_order_size,
+            market_state.quote_scale_exponent,
+            cur_ts,
+        )?;
+        let (in_amount, out_amount) = match side {
+            Side::Bid => (estimate.quote_qty, estimate.base_qty),
+            Side::Ask => (estimate.base_qty, estimate.quote_qty),
+        };
+        Ok(SwapQuote {
+            in_amount,
+            out_amount,
+            fully_filled: estimate.fully_filled,
+            num_fills: estimate.num_fills,
+        })
+    }
+
+    /// Removes an order from the orderbook and returns what was left of it.
+    pub fn cancel_order(
+        &mut self,
+        order_id: u128,
+        market_state: &MarketState,
+    ) -> Result<CancelledOrder<C>, AoError> {
+        let (side, _) = self.find_order(order_id).ok_or(AoError::OrderNotFound)?;
+        let (leaf_node, callback_info) = self.get_tree(side).remove_by_key(order_id).unwrap();
+        let callback_info = *callback_info;
+        let total_base_qty = leaf_node.total_base_quantity();
+        let total_quote_qty = compute_quote_qty(
+            Side::Ask,
+            leaf_node.total_base_quantity(),
+            leaf_node.price(),
+            market_state.quote_scale_exponent,
+        )
+        .ok_or(AoError::NumericalOverflow)?;
+
+        Ok(CancelledOrder {
+            summary: OrderSummary {
+                posted_order_id: None,
+                total_base_qty,
+                total_quote_qty,
+                total_base_qty_posted: 0,
+                total_quote_qty_posted: 0,
+                total_base_qty_unfilled: 0,
+                flags: leaf_node.flags(),
+                match_limit_exhausted: false,
+                compute_budget_exhausted: false,
+            },
+            price: leaf_node.price(),
+            callback_info,
+        })
+    }
+
+    /// Cancels the order of an owner, identified by its callback information, which was placed with the given client
+    /// order id.
+    ///
+    /// The order is looked up through the owner index of the slabs, so only the orders of the owner are visited. The
+    /// orderbook never holds two orders of an owner with the same non-zero client order id.
+    pub fn cancel_order_by_client_id(
+        &mut self,
+        client_order_id: u64,
+        callback_info: &C,
+        market_state: &MarketState,
+    ) -> Result<CancelledOrder<C>, AoError> {
+        if client_order_id == 0 {
+            msg!("The client order id of the order to cancel can't be 0!");
+            return Err(AoError::OrderNotFound);
+        }
+        let order_id = self
+            .find_client_order_id(callback_info, client_order_id)
+            .ok_or(AoError::OrderNotFound)?;
+        self.cancel_order(order_id, market_state)
+    }
+
+    /// Finds the id of the order of an owner, identified by its callback information, which holds the given client
+    /// order id.
+    fn find_client_order_id(&self, callback_info: &C, client_order_id: u64) -> Option<u128> {
+        [&self.bids, &self.asks].iter().find_map(|slab| {
+            slab.iter_owner_handles(callback_info)
+                .find(|h| slab.get_client_order_id(*h) == client_order_id)
+                .map(|h| slab.leaf_nodes[h as usize].order_id())
+        })
+    }
+
+    /// Atomically cancels an existing order and executes a new order in its place.
+    ///
+    /// When the new order only decreases the size of the existing order at the same price, the existing order is
+    /// modified in place and keeps its priority in the orderbook. An Out event is pushed for the base quantity which is
+    /// removed from the existing order.
+    pub fn replace_order(
+        &mut self,
+        order_id: u128,
+        params: new_order::Params<C>,
+        event_queue: &mut EventQueue<'a, C>,
+        market_state: &mut MarketState,
+        cur_ts: u64,
+    ) -> Result<OrderSummary, AoError> {
+        let (side, handle) = self.find_order(order_id).ok_or(AoError::OrderNotFound)?;
+        // The replaced order itself may hold the client order id, which it then passes on to the new order
+        if params.client_order_id != 0
+            && matches!(
+                self.find_client_order_id(&params.callback_info, params.client_order_id),
+                Some(id) if id != order_id
+            )
+        {
+            msg!("The owner already has an order with this client order id!");
+            return Err(AoError::DuplicateClientOrderId);
+        }
+        let slab = self.get_tree(side);
+        let leaf = slab.leaf_nodes[handle as usize];
+        let base_qty_to_keep = if params.limit_price == 0 {
+            0
+        } else {
+            std::cmp::min(
+                compute_base_qty(
+                    params.max_quote_qty,
+                    params.limit_price,
+                    market_state.quote_scale_exponent,
+                )
+                .unwrap_or(u64::MAX),
+                params.max_base_qty,
+            )
+        };
+        let keep_priority = params.side == side
+            && params.limit_price == leaf.price()
+            && params.max_display_qty == 0
+            && leaf.max_display_quantity() == 0
+            && params.post_allowed
+            && !params.immediate_or_cancel
+            && !params.fill_or_kill
+            && market_state.is_postable_size(base_qty_to_keep, params.limit_price)
+            && base_qty_to_keep <= leaf.base_quantity();
+
+        if !keep_priority {
+            let (order, callback_info) = slab.remove_by_key(order_id).unwrap();
+            let out = OutEvent::new(side, order.total_base_quantity(), order_id);
+            event_queue
+                .push_back(out, Some(callback_info), None)
+                .map_err(|_| AoError::EventQueueFull)?;
+            return self.new_order(params, event_queue, market_state, cur_ts);
+        }
+
+        let base_qty_removed = slab.update_leaf(handle, |leaf| {
+            let base_qty_removed = leaf.base_quantity() - base_qty_to_keep;
+            leaf.set_base_quantity(base_qty_to_keep);
+            leaf.set_max_ts(params.max_ts);
+            leaf.set_flags(params.flags);
+            base_qty_removed
+        });
+        let modify = ModifyEvent::new(side, order_id, base_qty_to_keep, base_qty_removed);
+        event_queue
+            .push_back(modify, Some(slab.get_callback_info(handle)), None)
+            .map_err(|_| AoError::EventQueueFull)?;
+        slab.set_callback_info(handle, &params.callback_info);
+        slab.set_client_order_id(handle, params.client_order_id);
+        let quote_qty_to_keep = compute_quote_qty(
+            side,
+            base_qty_to_keep,
+            params.limit_price,
+            market_state.quote_scale_exponent,
+        )
+        .ok_or(AoError::NumericalOverflow)?;
+        Ok(OrderSummary {
+            posted_order_id: Some(order_id),
+            total_base_qty: base_qty_to_keep,
+            total_quote_qty: quote_qty_to_keep,
+            total_base_qty_posted: base_qty_to_keep,
+            total_quote_qty_posted: quote_qty_to_keep,
+            total_base_qty_unfilled: params.max_base_qty - base_qty_to_keep,
+            flags: params.flags,
+            match_limit_exhausted: false,
+            compute_budget_exhausted: false,
+        })
+    }
+
+    pub fn new_order(
+        &mut self,
+        params: new_order::Params<C>,
+        event_queue: &mut EventQueue<'a, C>,
+        market_state: &mut MarketState,
+        cur_ts: u64,
+    ) -> Result<OrderSummary, AoError> {
+        if market_state.pending_taker.active != 0 {
+            msg!("The pending resumable order must be matched or cancelled before placing new orders!");
+            return Err(AoError::PendingTakerInProgress);
+        }
+        self.match_order(params, event_queue, market_state, cur_ts, None)
+    }
+
+    /// Continues matching the resumable order parked in the market.
+    ///
+    /// The order is matched as a new order for its remaining quantities, and keeps its order id if its remainder is
+    /// posted. It is parked again if matching is interrupted once more. When `params.cancel` is set, the order is
+    /// removed from the market instead and its remaining base quantity is reported as unfilled.
+    pub(crate) fn continue_match(
+        &mut self,
+        params: continue_match::Params,
+        event_queue: &mut EventQueue<'a, C>,
+        market_state: &mut MarketState,
+        cur_ts: u64,
+    ) -> Result<OrderSummary, AoError> {
+        let continue_match::Params {
+            order_id,
+            match_limit,
+            compute_unit_reserve,
+            cancel,
+        } = params;
+        let pending_taker = market_state.pending_taker;
+        if pending_taker.active == 0 || pending_taker.order_id() != order_id {
+            msg!("The order is not the resumable order being matched on the market!");
+            return Err(AoError::OrderNotFound);
+        }
+        market_state.pending_taker = PendingTaker::zeroed();
+        if cancel {
+            return Ok(OrderSummary {
+                posted_order_id: None,
+                total_base_qty: 0,
+                total_quote_qty: 0,
+                total_base_qty_posted: 0,
+                total_quote_qty_posted: 0,
+                total_base_qty_unfilled: pending_taker.base_qty_remaining,
+                flags: pending_taker.flags as u8,
+                match_limit_exhausted: false,
+                compute_budget_exhausted: false,
+            });
+        }
+        let params = new_order::Params {
+            max_base_qty: pending_taker.base_qty_remaining,
+            max_quote_qty: pending_taker.quote_qty_remaining,
+            limit_price: pending_taker.limit_price,
+            side: Side::from_u64(pending_taker.side).unwrap(),
+            match_limit,
+            callback_info: bytemuck::pod_read_unaligned(
+                &pending_taker.callback_info[..std::mem::size_of::<C>()],
+            ),
+            post_only: false,
+            post_allowed: pending_taker.post_allowed != 0,
+            self_trade_behavior: SelfTradeBehavior::from_u64(pending_taker.self_trade_behavior)
+                .unwrap(),
+            max_ts: pending_taker.max_ts,
+            immediate_or_cancel: false,
+            fill_or_kill: false,
+            max_display_qty: pending_taker.max_display_qty,
+            post_only_mode: PostOnlyMode::Reject,
+            max_slippage: 0,
+            flags: pending_taker.flags as u8,
+            min_base_qty: 0,
+            compute_unit_reserve,
+            resumable: true,
+            worst_price: 0,
+            referral: [0; 32],
+            client_order_id: 0,
+        };
+        self.match_order(params, event_queue, market_state, cur_ts, Some(order_id))
+    }
+
+    /// Matches a new order against the orderbook and posts its remainder, using `order_id` as the id of the posted
+    /// order when continuing a resumable order.
+    fn match_order(
+        &mut self,
+        params: new_order::Params<C>,
+        event_queue: &mut EventQueue<'a, C>,
+        market_state: &mut MarketState,
+        cur_ts: u64,
+        order_id: Option<u128>,
+    ) -> Result<OrderSummary, AoError> {
+        if params.limit_price % market_state.tick_size != 0 {
+            msg!("The limit price must be a multiple of the tick size!");
+            return Err(AoError::InvalidTickSize);
+        }
+        if params.client_order_id != 0
+            && self
+                .find_client_order_id(&params.callback_info, params.client_order_id)
+                .is_some()
+        {
+            msg!("The owner already has an order with this client order id!");
+            return Err(AoError::DuplicateClientOrderId);
+        }
+        if market_state.matching_mode == MatchingMode::Auction as u64
+            && (params.limit_price == 0
+                || !params.post_allowed
+                || params.immediate_or_cancel
+                || params.fill_or_kill
+                || params.min_base_qty != 0)
+        {
+            msg!("Orders placed on an auction market must be posted in the orderbook!");
+            return Err(AoError::OperationNotAllowed);
+        }
+        let mut params = params;
+        let min_base_order_size = market_state.min_base_order_size;
+        let is_market_order = params.limit_price == 0;
+        if is_market_order {
+            params.limit_price =
+                self.get_market_order_limit_price(params.side, params.max_slippage);
+        }
+        let (best_bid_price, best_ask_price) = self.get_spread();
+        let best_opposite_price = match params.side {
+            Side::Bid => best_ask_price,
+            Side::Ask => best_bid_price,
+        };
+        market_state.check_price_band(params.side, params.limit_price, best_opposite_price)?;
+        // Each match pushes at most two events, or three when fills are split, plus one when the order has a referral,
+        // and posting the order can boot out another order. When the event queue might not have enough room for all of
+        // them, the exact number of events is computed beforehand so that the instruction fails before the orderbook
+        // is modified.
+        let event_queue_capacity = event_queue.remaining_capacity();
+        let has_referral = params.referral != [0; 32];
+        let extra_events_per_fill = (market_state.split_fills != 0) as u64 + has_referral as u64;
+        let events_per_match = 2 + extra_events_per_fill;
+        let may_overflow = params
+            .match_limit
+            .saturating_mul(events_per_match)
+            .saturating_add(1)
+            > event_queue_capacity;
+        if params.fill_or_kill || params.min_base_qty != 0 || may_overflow {
+            let estimate = self.simulate_match(
+                &params,
+                min_base_order_size,
+                market_state.quote_scale_exponent,
+                cur_ts,
+            )?;
+            if params.fill_or_kill && !estimate.fully_filled {
+                msg!("The fill or kill order can't be entirely filled!");
+                return Err(AoError::UnableToFill);
+            }
+            if estimate.base_qty < params.min_base_qty {
+                msg!(
+                    "Only {} base lots of the order can be filled, below the minimum of {}!",
+                    estimate.base_qty,
+                    params.min_base_qty
+                );
+                return Err(AoError::UnableToFill);
+            }
+            let num_events = estimate.num_events + extra_events_per_fill * estimate.num_fills;
+            if num_events + 1 > event_queue_capacity {
+                msg!(
+                    "The order needs {} events, the event queue only has room for {}!",
+                    num_events + 1,
+                    event_queue_capacity
+                );
+                return Err(AoError::EventQueueFull);
             }
         }
-        pub fn get(&mut self) -> (OrderBookStateTest, EventQueueTest) {
-            (
-                OrderBookStateTest::new_safe(&mut self.bids_buffer, &mut self.asks_buffer).unwrap(),
-                EventQueueTest::from_buffer(
-                    &mut self.event_queue_buffer,
-                    AccountTag::Uninitialized,
+
+        let new_order::Params {
+            max_base_qty,
+            max_quote_qty,
+            side,
+            mut limit_price,
+            callback_info,
+            post_only,
+            post_allowed,
+            self_trade_behavior,
+            max_ts,
+            immediate_or_cancel,
+            fill_or_kill,
+            max_display_qty,
+            post_only_mode,
+            max_slippage: _,
+            flags,
+            min_base_qty: _,
+            mut match_limit,
+            compute_unit_reserve,
+            resumable,
+            worst_price,
+            referral,
+            client_order_id,
+        } = params;
+        if resumable && std::mem::size_of::<C>() > PENDING_TAKER_CALLBACK_INFO_LEN {
+            msg!("The callback information is too large for the order to be resumable!");
+            return Err(AoError::InvalidCallbackInfo);
+        }
+
+        let mut base_qty_remaining = max_base_qty;
+        let mut quote_qty_remaining = max_quote_qty;
+        let mut matched_notional = 0u128;
+
+        // New bid
+        let mut crossed = true;
+        let mut compute_budget_exhausted = false;
+        let mut worst_price_reached = false;
+        loop {
+            // Orders placed on an auction market are only matched by run_auction
+            if market_state.matching_mode == MatchingMode::Auction as u64 {
+                crossed = false;
+                break;
+            }
+            if match_limit == 0 {
+                break;
+            }
+            if matches!(remaining_compute_units(), Some(units) if units < compute_unit_reserve) {
+                compute_budget_exhausted = true;
+                break;
+            }
+            let best_bo_h = match self.find_bbo(side.opposite()) {
+                None => {
+                    crossed = false;
+                    break;
+                }
+                Some(h) => h,
+            };
+
+            let opposite_slab = self.get_tree(side.opposite());
+
+            let best_bo_ref = &opposite_slab.leaf_nodes[best_bo_h as usize];
+
+            // The order on the book has exceeded max ts, we will boot it
+            // and continue attempting to match
+            if best_bo_ref.max_ts() < cur_ts {
+                let best_offer_id = best_bo_ref.order_id();
+                let provide_out_callback_info = &opposite_slab.callback_infos[best_bo_h as usize];
+                let provide_out = OutEvent::new(
+                    side.opposite(),
+                    best_bo_ref.total_base_quantity(),
+                    best_offer_id,
+                );
+                event_queue
+                    .push_back(provide_out, Some(provide_out_callback_info), None)
+                    .map_err(|_| AoError::EventQueueFull)?;
+
+                self.get_tree(side.opposite())
+                    .remove_by_key(best_offer_id)
+                    .unwrap();
+
+                match_limit -= 1;
+
+                continue;
+            }
+
+            let trade_price = best_bo_ref.price();
+            crossed = match side {
+                Side::Bid => limit_price >= trade_price,
+                Side::Ask => limit_price <= trade_price,
+            };
+
+            if post_only && crossed && post_only_mode == PostOnlyMode::Slide {
+                let slid_price = match side {
+                    Side::Bid => trade_price.checked_sub(market_state.tick_size),
+                    Side::Ask => trade_price.checked_add(market_state.tick_size),
+                };
+                if let Some(p) = slid_price.filter(|p| *p != 0) {
+                    limit_price = p;
+                    crossed = false;
+                }
+            }
+
+            if post_only || !crossed {
+                break;
+            }
+
+            let offer_size = best_bo_ref.base_quantity();
+            let worst_price_bound = Some(worst_price).filter(|p| *p != 0).and_then(|p| {
+                max_base_qty_within_worst_price(
+                    side,
+                    max_base_qty - base_qty_remaining,
+                    matched_notional,
+                    trade_price,
+                    p,
+                )
+            });
+            let base_trade_qty = offer_size
+                .min(base_qty_remaining)
+                .min(
+                    compute_base_qty(
+                        quote_qty_remaining,
+                        best_bo_ref.price(),
+                        market_state.quote_scale_exponent,
+                    )
+                    .unwrap_or(u64::MAX),
+                )
+                .min(worst_price_bound.unwrap_or(u64::MAX));
+            worst_price_reached = matches!(worst_price_bound, Some(q) if q <= base_trade_qty);
+
+            if base_trade_qty == 0 {
+                break;
+            }
+
+            let quote_maker_qty = compute_quote_qty(
+                side,
+                base_trade_qty,
+                trade_price,
+                market_state.quote_scale_exponent,
+            )
+            .map(|q| std::cmp::min(q, quote_qty_remaining))
+            .ok_or(AoError::NumericalOverflow)?;
+
+            if quote_maker_qty == 0 {
+                break;
+            }
+
+            // The decrement take case can be handled by the caller program on event consumption, so no special logic
+            // is needed for it.
+            if self_trade_behavior != SelfTradeBehavior::DecrementTake {
+                let order_would_self_trade = callback_info.as_callback_id()
+                    == opposite_slab.callback_infos[best_bo_h as usize].as_callback_id();
+                if order_would_self_trade {
+                    let best_offer_id = best_bo_ref.order_id();
+
+                    match self_trade_behavior {
+                        SelfTradeBehavior::AbortTransaction => {
+                            msg!("The order would self trade!");
+                            return Err(AoError::WouldSelfTrade);
+                        }
+                        // The current order has the lowest time priority, its remainder is not posted
+                        SelfTradeBehavior::CancelLowestPriority => break,
+                        _ => {}
+                    }
+                    let provide_out_callback_info =
+                        &opposite_slab.callback_infos[best_bo_h as usize];
+                    let provide_out = OutEvent::new(
+                        side.opposite(),
+                        best_bo_ref.total_base_quantity(),
+                        best_offer_id,
+                    );
+                    event_queue
+                        .push_back(provide_out, Some(provide_out_callback_info), None)
+                        .map_err(|_| AoError::EventQueueFull)?;
+
+                    self.get_tree(side.opposite())
+                        .remove_by_key(best_offer_id)
+                        .unwrap();
+
+                    if self_trade_behavior == SelfTradeBehavior::CancelBoth {
+                        break;
+                    }
+
+                    match_limit -= 1;
+
+                    continue;
+                }
+            }
+
+            let maker_callback_info = &opposite_slab.callback_infos[best_bo_h as usize];
+
+            let mut maker_fill = FillEvent::new(
+                side,
+                quote_maker_qty,
+                best_bo_ref.order_id(),
+                base_trade_qty,
+                flags,
+                best_bo_ref.flags(),
+            );
+            if market_state.split_fills == 0 {
+                market_state.charge_fees(&mut maker_fill, quote_maker_qty, true);
+                event_queue
+                    .push_back(maker_fill, Some(maker_callback_info), Some(&callback_info))
+                    .map_err(|_| AoError::EventQueueFull)?;
+                if has_referral {
+                    event_queue
+                        .push_back(
+                            ReferralEvent::new(referral),
+                            Some(maker_callback_info),
+                            Some(&callback_info),
+                        )
+                        .map_err(|_| AoError::EventQueueFull)?;
+                }
+            } else {
+                // The maker's quote quantity is rounded against the maker, so that the two sides of a split fill never
+                // exchange more quote than the taker's rounded quote quantity
+                let maker_quote_qty = compute_quote_qty(
+                    side.opposite(),
+                    base_trade_qty,
+                    trade_price,
+                    market_state.quote_scale_exponent,
+                )
+                .ok_or(AoError::NumericalOverflow)?;
+                market_state.charge_fees(&mut maker_fill, maker_quote_qty, true);
+                let mut maker_side_fill = maker_fill;
+                maker_side_fill.set_quote_size(maker_quote_qty);
+                event_queue
+                    .push_back(
+                        MakerFillEvent::new(maker_side_fill),
+                        Some(maker_callback_info),
+                        None,
+                    )
+                    .map_err(|_| AoError::EventQueueFull)?;
+                event_queue
+                    .push_back(TakerFillEvent::new(maker_fill), Some(&callback_info), None)
+                    .map_err(|_| AoError::EventQueueFull)?;
+                if has_referral {
+                    event_queue
+                        .push_back(ReferralEvent::new(referral), Some(&callback_info), None)
+                        .map_err(|_| AoError::EventQueueFull)?;
+                }
+            }
+
+            let best_bo_qty = best_bo_ref.base_quantity() - base_trade_qty;
+            opposite_slab.update_leaf(best_bo_h, |leaf| leaf.set_base_quantity(best_bo_qty));
+            base_qty_remaining -= base_trade_qty;
+            quote_qty_remaining -= quote_maker_qty;
+            matched_notional =
+                matched_notional.saturating_add(base_trade_qty as u128 * trade_price as u128);
+            market_state.update_price_accumulator(cur_ts);
+            market_state.last_trade_price = trade_price;
+            market_state.cumulative_base_volume = market_state
+                .cumulative_base_volume
+                .wrapping_add(base_trade_qty);
+            market_state.cumulative_quote_volume = market_state
+                .cumulative_quote_volume
+                .wrapping_add(quote_maker_qty);
+
+            self.refresh_matched_order(
+                side.opposite(),
+                best_bo_h,
+                min_base_order_size,
+                event_queue,
+            )?;
+
+            match_limit -= 1;
+
+            if worst_price_reached {
+                break;
+            }
+        }
+
+        let stopped_early = !worst_price_reached
+            && (match_limit == 0 || compute_budget_exhausted)
+            && base_qty_remaining != 0
+            && quote_qty_remaining != 0
+            && match self.find_bbo(side.opposite()) {
+                Some(h) => {
+                    let price = self.get_tree(side.opposite()).leaf_nodes[h as usize].price();
+                    match side {
+                        Side::Bid => limit_price >= price,
+                        Side::Ask => limit_price <= price,
+                    }
+                }
+                None => false,
+            };
+        let match_limit_exhausted = stopped_early && match_limit == 0;
+        let compute_budget_exhausted = stopped_early && compute_budget_exhausted;
+
+        if resumable && stopped_early {
+            let order_id = order_id.unwrap_or_else(|| event_queue.gen_order_id(limit_price, side));
+            let mut pending_callback_info = [0; PENDING_TAKER_CALLBACK_INFO_LEN];
+            pending_callback_info[..std::mem::size_of::<C>()]
+                .copy_from_slice(bytemuck::bytes_of(&callback_info));
+            market_state.pending_taker = PendingTaker {
+                active: 1,
+                sequence_number: OrderId::from(order_id).sequence(),
+                side: side as u64,
+                limit_price,
+                base_qty_remaining,
+                quote_qty_remaining,
+                max_ts,
+                max_display_qty,
+                self_trade_behavior: self_trade_behavior as u64,
+                post_allowed: (post_allowed
+                    && !immediate_or_cancel
+                    && !fill_or_kill
+                    && !is_market_order) as u64,
+                flags: flags as u64,
+                callback_info: pending_callback_info,
+            };
+            return Ok(OrderSummary {
+                posted_order_id: Some(order_id),
+                total_base_qty: max_base_qty - base_qty_remaining,
+                total_quote_qty: max_quote_qty - quote_qty_remaining,
+                total_base_qty_posted: 0,
+                total_quote_qty_posted: 0,
+                total_base_qty_unfilled: base_qty_remaining,
+                flags,
+                match_limit_exhausted,
+                compute_budget_exhausted,
+            });
+        }
+
+        let should_post =
+            !crossed && post_allowed && !immediate_or_cancel && !fill_or_kill && !is_market_order;
+        let base_qty_to_post = if should_post {
+            std::cmp::min(
+                compute_base_qty(
+                    quote_qty_remaining,
+                    limit_price,
+                    market_state.quote_scale_exponent,
+                )
+                .unwrap_or(u64::MAX),
+                base_qty_remaining,
+            )
+        } else {
+            0
+        };
+
+        if !should_post || !market_state.is_postable_size(base_qty_to_post, limit_price) {
+            return Ok(OrderSummary {
+                posted_order_id: None,
+                total_base_qty: max_base_qty - base_qty_remaining,
+                total_quote_qty: max_quote_qty - quote_qty_remaining,
+                total_base_qty_posted: 0,
+                total_quote_qty_posted: 0,
+                total_base_qty_unfilled: base_qty_remaining,
+                flags,
+                match_limit_exhausted,
+                compute_budget_exhausted,
+            });
+        }
+
+        if market_state.max_orders_per_owner != 0
+            && self.count_owner_orders(&callback_info) >= market_state.max_orders_per_owner
+        {
+            msg!("The owner of the order has too many orders resting in the orderbook!");
+            return Err(AoError::TooManyOpenOrders);
+        }
+
+        let new_leaf_order_id =
+            order_id.unwrap_or_else(|| event_queue.gen_order_id(limit_price, side));
+        let mut new_leaf = LeafNode::new(new_leaf_order_id, base_qty_to_post, max_ts);
+        if max_display_qty != 0 && base_qty_to_post > max_display_qty {
+            new_leaf.set_base_quantity(max_display_qty);
+            new_leaf.set_hidden_quantity(base_qty_to_post - max_display_qty);
+        }
+        new_leaf.set_max_display_quantity(max_display_qty);
+        new_leaf.set_flags(flags);
+        let insert_result = self.get_tree(side).insert_leaf(&new_leaf, &callback_info);
+        let k = if let Err(AoError::SlabOutOfSpace) = insert_result {
+            let slab = self.get_tree(side);
+            let (boot_candidate, should_boot) =
+                match BootingPolicy::from_u64(market_state.booting_policy).unwrap() {
+                    BootingPolicy::BootLeastAggressive => {
+                        // Boot out the least aggressive orders
+                        msg!("Orderbook is full! booting least aggressive orders...");
+                        let boot_candidate = match side {
+                            Side::Bid => slab.find_min().unwrap(),
+                            Side::Ask => slab.find_max().unwrap(),
+                        };
+                        let boot_candidate_price = slab.leaf_nodes[boot_candidate as usize].price();
+                        let should_boot = match side {
+                            Side::Bid => boot_candidate_price < limit_price,
+                            Side::Ask => boot_candidate_price > limit_price,
+                        };
+                        (boot_candidate, should_boot)
+                    }
+                    BootingPolicy::RejectNewOrder => {
+                        msg!("Orderbook is full! rejecting the new order...");
+                        return Err(AoError::SlabOutOfSpace);
+                    }
+                    BootingPolicy::BootSmallest => {
+                        // Boot out the smallest orders, the least aggressive one among equally small orders
+                        msg!("Orderbook is full! booting smallest orders...");
+                        let boot_candidate = slab
+                            .iter_handles(side == Side::Bid)
+                            .min_by_key(|h| slab.leaf_nodes[*h as usize].total_base_quantity())
+                            .unwrap();
+                        let should_boot = slab.leaf_nodes[boot_candidate as usize]
+                            .total_base_quantity()
+                            < base_qty_to_post;
+                        (boot_candidate, should_boot)
+                    }
+                };
+            let boot_candidate_key = slab.leaf_nodes[boot_candidate as usize].key();
+            if should_boot {
+                let (order, callback_info_booted) = slab.remove_by_key(boot_candidate_key).unwrap();
+                let boot = BootEvent::new(
+                    side,
+                    BootReason::OrderbookFull,
+                    order.total_base_quantity(),
+                    order.order_id(),
+                );
+                event_queue
+                    .push_back(boot, Some(callback_info_booted), None)
+                    .map_err(|_| AoError::EventQueueFull)?;
+                slab.insert_leaf(&new_leaf, &callback_info).unwrap().0
+            } else {
+                return Ok(OrderSummary {
+                    posted_order_id: None,
+                    total_base_qty: max_base_qty - base_qty_remaining,
+                    total_quote_qty: max_quote_qty - quote_qty_remaining,
+                    total_base_qty_posted: 0,
+                    total_quote_qty_posted: 0,
+                    total_base_qty_unfilled: base_qty_remaining,
+                    flags,
+                    match_limit_exhausted,
+                    compute_budget_exhausted,
+                });
+            }
+        } else {
+            insert_result.unwrap().0
+        };
+        self.get_tree(side).set_client_order_id(k, client_order_id);
+        debug_assert!(
+            market_state.matching_mode == MatchingMode::Auction as u64 || !self.is_crossed(),
+            "The orderbook is crossed"
+        );
+        base_qty_remaining -= base_qty_to_post;
+        let quote_qty_to_post = compute_quote_qty(
+            side,
+            base_qty_to_post,
+            limit_price,
+            market_state.quote_scale_exponent,
+        )
+        .ok_or(AoError::NumericalOverflow)?;
+        quote_qty_remaining -= quote_qty_to_post;
+        Ok(OrderSummary {
+            posted_order_id: Some(new_leaf_order_id),
+            total_base_qty: max_base_qty - base_qty_remaining,
+            total_quote_qty: max_quote_qty - quote_qty_remaining,
+            total_base_qty_posted: base_qty_to_post,
+            total_quote_qty_posted: quote_qty_to_post,
+            total_base_qty_unfilled: base_qty_remaining,
+            flags,
+            match_limit_exhausted,
+            compute_budget_exhausted,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::state::event_queue::{
+        BootEventRef, EventRef, FillEventRef, MakerFillEventRef, ModifyEventRef, OutEventRef,
+        ReferralEventRef, ReplenishEventRef, TakerFillEventRef,
+    };
+
+    use super::*;
+    use crate::state::{
+        market_state::{MARKET_METADATA_LEN, MAX_EVENT_QUEUE_SHARDS},
+        TradingStatus,
+    };
+    use solana_program::pubkey::Pubkey;
+
+    type SlabTest<'a> = Slab<'a, [u8; 32]>;
+    type OrderBookStateTest<'a> = OrderBookState<'a, [u8; 32]>;
+    type EventQueueTest<'a> = EventQueue<'a, [u8; 32]>;
+
+    pub struct TestContext {
+        asks_buffer: Vec<u8>,
+        bids_buffer: Vec<u8>,
+        event_queue_buffer: Vec<u8>,
+    }
+
+    impl TestContext {
+        pub fn new(order_capacity: usize, event_capacity: usize) -> Self {
+            let allocation_size = SlabTest::compute_allocation_size(order_capacity);
+            let (mut asks_buffer, mut bids_buffer) =
+                (vec![0; allocation_size], vec![0; allocation_size]);
+            SlabTest::initialize(&mut asks_buffer, &mut bids_buffer).unwrap();
+            Self {
+                asks_buffer,
+                bids_buffer,
+                event_queue_buffer: vec![
+                    0;
+                    EventQueueTest::compute_allocation_size(event_capacity)
+                ],
+            }
+        }
+        pub fn get(&mut self) -> (OrderBookStateTest, EventQueueTest) {
+            (
+                OrderBookStateTest::new_safe(&mut self.bids_buffer, &mut self.asks_buffer).unwrap(),
+                EventQueueTest::from_buffer(
+                    &mut self.event_queue_buffer,
+                    AccountTag::Uninitialized,
+                )
+                .unwrap(),
+            )
+        }
+    }
+
+    fn test_market_state() -> MarketState {
+        MarketState {
+            event_queue: Pubkey::default(),
+            bids: Pubkey::default(),
+            asks: Pubkey::default(),
+            min_base_order_size: 10,
+            tick_size: 1,
+            stop_orders: Pubkey::default(),
+            last_trade_price: 0,
+            pegged_orders: Pubkey::default(),
+            min_quote_order_size: 0,
+            booting_policy: BootingPolicy::BootLeastAggressive as u64,
+            split_fills: 0,
+            dead_letter_queue: Pubkey::default(),
+            max_event_age_slots: 0,
+            log_events: 0,
+            event_queue_shards: [Pubkey::default(); MAX_EVENT_QUEUE_SHARDS],
+            status: TradingStatus::Active as u64,
+            metadata: [0; MARKET_METADATA_LEN],
+            market_registry: Pubkey::default(),
+            quote_scale_exponent: 0,
+            max_price_deviation_bps: 0,
+            cumulative_base_volume: 0,
+            cumulative_quote_volume: 0,
+            cumulative_price_time: [0; 2],
+            last_accumulator_update_ts: 0,
+            matching_mode: MatchingMode::Continuous as u64,
+            pending_taker: PendingTaker::zeroed(),
+            maker_fee_bps: 0,
+            taker_fee_bps: 0,
+            accrued_maker_fees: 0,
+            accrued_taker_fees: 0,
+            max_orders_per_owner: 0,
+            consumer_program: Pubkey::default(),
+            max_events_per_consumer_call: 0,
+            order_allowlist: Pubkey::default(),
+            bids_root: [0; 32],
+            asks_root: [0; 32],
+            book_commit_slot: 0,
+            event_queue_full_policy: 0,
+        }
+    }
+
+    #[test]
+    fn test_ob_0() {
+        let mut test_context = TestContext::new(1000, 1000);
+        let (mut orderbook, mut event_queue) = test_context.get();
+        let mut market_state = test_market_state();
+        let alice = [1; 32];
+        let bob = [2; 32];
+        orderbook
+            .new_order(
+                new_order::Params {
+                    max_base_qty: 10,
+                    max_quote_qty: 10,
+                    limit_price: 10 << 32,
+                    side: Side::Ask,
+                    match_limit: 0,
+                    callback_info: [0; 32],
+                    post_only: false,
+                    post_allowed: false,
+                    self_trade_behavior: SelfTradeBehavior::DecrementTake,
+                    max_ts: u64::MAX,
+                    immediate_or_cancel: false,
+                    fill_or_kill: false,
+                    max_display_qty: 0,
+                    post_only_mode: PostOnlyMode::Reject,
+                    max_slippage: 0,
+                    flags: 0,
+                    min_base_qty: 0,
+                    compute_unit_reserve: 0,
+                    resumable: false,
+                    worst_price: 0,
+                    referral: [0; 32],
+                    client_order_id: 0,
+                },
+                &mut event_queue,
+                &mut market_state,
+                u64::MIN,
+            )
+            .unwrap();
+        assert!(event_queue.header.count == 0);
+
+        // Alice posts a bid order for 1 BTC at 10 USD/BTC
+
+        let OrderSummary {
+            posted_order_id,
+            total_base_qty,
+            total_quote_qty,
+            total_base_qty_posted,
+            ..
+        } = orderbook
+            .new_order(
+                new_order::Params {
+                    max_base_qty: 2_000_000,
+                    max_quote_qty: 10_000_000,
+                    limit_price: 10 << 32,
+                    side: Side::Bid,
+                    match_limit: 10,
+                    callback_info: alice,
+                    post_only: false,
+                    post_allowed: true,
+                    self_trade_behavior: SelfTradeBehavior::DecrementTake,
+                    max_ts: u64::MAX,
+                    immediate_or_cancel: false,
+                    fill_or_kill: false,
+                    max_display_qty: 0,
+                    post_only_mode: PostOnlyMode::Reject,
+                    max_slippage: 0,
+                    flags: 0,
+                    min_base_qty: 0,
+                    compute_unit_reserve: 0,
+                    resumable: false,
+                    worst_price: 0,
+                    referral: [0; 32],
+                    client_order_id: 0,
+                },
+                &mut event_queue,
+                &mut market_state,
+                u64::MIN,
+            )
+            .unwrap();
+        assert!(posted_order_id.is_some());
+        assert_eq!(total_base_qty, 1_000_000);
+        assert_eq!(total_quote_qty, 10_000_000);
+        assert_eq!(total_base_qty_posted, 1_000_000);
+
+        // Bob posts an ask order for 3 BTC at 20 USD/BTC
+        let OrderSummary {
+            posted_order_id,
+            total_base_qty,
+            total_quote_qty,
+            total_base_qty_posted,
+            ..
+        } = orderbook
+            .new_order(
+                new_order::Params {
+                    max_base_qty: 3_000_000,
+                    max_quote_qty: 1_000_000_000,
+                    limit_price: 20 << 32,
+                    side: Side::Ask,
+                    match_limit: 10,
+                    callback_info: bob,
+                    post_only: false,
+                    post_allowed: true,
+                    self_trade_behavior: SelfTradeBehavior::DecrementTake,
+                    max_ts: u64::MAX,
+                    immediate_or_cancel: false,
+                    fill_or_kill: false,
+                    max_display_qty: 0,
+                    post_only_mode: PostOnlyMode::Reject,
+                    max_slippage: 0,
+                    flags: 0,
+                    min_base_qty: 0,
+                    compute_unit_reserve: 0,
+                    resumable: false,
+                    worst_price: 0,
+                    referral: [0; 32],
+                    client_order_id: 0,
+                },
+                &mut event_queue,
+                &mut market_state,
+                u64::MIN,
+            )
+            .unwrap();
+        assert!(posted_order_id.is_some());
+        assert_eq!(total_base_qty, 3_000_000);
+        assert_eq!(total_quote_qty, 60_000_000);
+        assert_eq!(total_base_qty_posted, 3_000_000);
+
+        // Bob posts a bid order for 0.5 BTC at 15 USD/BTC
+        let OrderSummary {
+            posted_order_id: bob_order_id_0,
+            total_base_qty,
+            total_quote_qty,
+            total_base_qty_posted,
+            ..
+        } = orderbook
+            .new_order(
+                new_order::Params {
+                    max_base_qty: 500_000,
+                    max_quote_qty: 1_000_000_000,
+                    limit_price: 15 << 32,
+                    side: Side::Bid,
+                    match_limit: 10,
+                    callback_info: bob,
+                    post_only: false,
+                    post_allowed: true,
+                    self_trade_behavior: SelfTradeBehavior::DecrementTake,
+                    max_ts: u64::MAX,
+                    immediate_or_cancel: false,
+                    fill_or_kill: false,
+                    max_display_qty: 0,
+                    post_only_mode: PostOnlyMode::Reject,
+                    max_slippage: 0,
+                    flags: 0,
+                    min_base_qty: 0,
+                    compute_unit_reserve: 0,
+                    resumable: false,
+                    worst_price: 0,
+                    referral: [0; 32],
+                    client_order_id: 0,
+                },
+                &mut event_queue,
+                &mut market_state,
+                u64::MIN,
+            )
+            .unwrap();
+        assert!(posted_order_id.is_some());
+        assert_eq!(total_base_qty, 500_000);
+        assert_eq!(total_quote_qty, 7_500_000);
+        assert_eq!(total_base_qty_posted, 500_000);
+        assert_eq!(orderbook.get_spread(), (Some(15 << 32), Some(20 << 32)));
+
+        // Alice posts an ask order for 0.75 BTC at 14 USD/BTC, and is partially matched
+        let OrderSummary {
+            posted_order_id: alice_order_id_0,
+            total_base_qty,
+            total_quote_qty,
+            total_base_qty_posted,
+            ..
+        } = orderbook
+            .new_order(
+                new_order::Params {
+                    max_base_qty: 750_000,
+                    max_quote_qty: 1_000_000_000,
+                    limit_price: 14 << 32,
+                    side: Side::Ask,
+                    match_limit: 10,
+                    callback_info: alice,
+                    post_only: false,
+                    post_allowed: true,
+                    self_trade_behavior: SelfTradeBehavior::DecrementTake,
+                    max_ts: u64::MAX,
+                    immediate_or_cancel: false,
+                    fill_or_kill: false,
+                    max_display_qty: 0,
+                    post_only_mode: PostOnlyMode::Reject,
+                    max_slippage: 0,
+                    flags: 0,
+                    min_base_qty: 0,
+                    compute_unit_reserve: 0,
+                    resumable: false,
+                    worst_price: 0,
+                    referral: [0; 32],
+                    client_order_id: 0,
+                },
+                &mut event_queue,
+                &mut market_state,
+                u64::MIN,
+            )
+            .unwrap();
+        assert!(posted_order_id.is_some());
+        assert_eq!(total_base_qty, 750_000);
+        assert_eq!(total_quote_qty, 500_000 * 15 + 250_000 * 14);
+        assert_eq!(total_base_qty_posted, 250_000);
+        assert_eq!(orderbook.get_spread(), (Some(10 << 32), Some(14 << 32)));
+
+        assert_eq!(event_queue.header.count, 2);
+        let mut event_queue_iter = event_queue.iter();
+        assert_eq!(
+            event_queue_iter.next().unwrap(),
+            EventRef::Fill(FillEventRef {
+                event: &FillEvent::new(
+                    Side::Ask,
+                    500_000 * 15,
+                    bob_order_id_0.unwrap(),
+                    500_000,
+                    0,
+                    0
+                ),
+                maker_callback_info: &bob,
+                taker_callback_info: &alice
+            })
+        );
+
+        assert_eq!(
+            event_queue_iter.next().unwrap(),
+            EventRef::Out(OutEventRef {
+                event: &OutEvent::new(Side::Bid, 0, bob_order_id_0.unwrap(),),
+                callback_info: &bob
+            })
+        );
+        println!("Event queue head: {}", event_queue.header.head);
+        event_queue.pop_n(2);
+        println!("Event queue head: {}", event_queue.header.head);
+
+        assert_eq!(event_queue.header.count, 0);
+
+        // Alice makes a bid for 0.05 BTC at 15 USD/BTC, and attempts to self-trade
+        let r = orderbook
+            .new_order(
+                new_order::Params {
+                    max_base_qty: 50_000,
+                    max_quote_qty: 1_000_000_000,
+                    limit_price: 15 << 32,
+                    side: Side::Bid,
+                    match_limit: 10,
+                    callback_info: alice,
+                    post_only: false,
+                    post_allowed: true,
+                    self_trade_behavior: SelfTradeBehavior::AbortTransaction,
+                    max_ts: u64::MAX,
+                    immediate_or_cancel: false,
+                    fill_or_kill: false,
+                    max_display_qty: 0,
+                    post_only_mode: PostOnlyMode::Reject,
+                    max_slippage: 0,
+                    flags: 0,
+                    min_base_qty: 0,
+                    compute_unit_reserve: 0,
+                    resumable: false,
+                    worst_price: 0,
+                    referral: [0; 32],
+                    client_order_id: 0,
+                },
+                &mut event_queue,
+                &mut market_state,
+                u64::MIN,
+            )
+            .unwrap_err();
+        assert!(matches!(r, AoError::WouldSelfTrade));
+        println!("Event queue head: {}", event_queue.header.head);
+
+        assert_eq!(event_queue.header.count, 0);
+
+        // Alice changes her self trading behavior
+
+        let OrderSummary {
+            posted_order_id,
+            total_base_qty,
+            total_quote_qty,
+            total_base_qty_posted,
+            ..
+        } = orderbook
+            .new_order(
+                new_order::Params {
+                    max_base_qty: 50_000,
+                    max_quote_qty: 1_000_000_000,
+                    limit_price: 15 << 32,
+                    side: Side::Bid,
+                    match_limit: 10,
+                    callback_info: alice,
+                    post_only: false,
+                    post_allowed: true,
+                    self_trade_behavior: SelfTradeBehavior::CancelProvide,
+                    max_ts: u64::MAX,
+                    immediate_or_cancel: false,
+                    fill_or_kill: false,
+                    max_display_qty: 0,
+                    post_only_mode: PostOnlyMode::Reject,
+                    max_slippage: 0,
+                    flags: 0,
+                    min_base_qty: 0,
+                    compute_unit_reserve: 0,
+                    resumable: false,
+                    worst_price: 0,
+                    referral: [0; 32],
+                    client_order_id: 0,
+                },
+                &mut event_queue,
+                &mut market_state,
+                u64::MIN,
+            )
+            .unwrap();
+        assert!(posted_order_id.is_some());
+        assert_eq!(total_base_qty, 50_000);
+        assert_eq!(total_quote_qty, 50_000 * 15);
+        assert_eq!(total_base_qty_posted, 50_000);
+        assert_eq!(event_queue.header.count, 1);
+        println!("Event queue head: {}", event_queue.header.head);
+
+        assert_eq!(
+            event_queue.iter().next().unwrap(),
+            EventRef::Out(OutEventRef {
+                event: &OutEvent::new(Side::Ask, 250_000, alice_order_id_0.unwrap() as u128,),
+                callback_info: &alice
+            })
+        );
+
+        event_queue.pop_n(1);
+
+        orderbook
+            .new_order(
+                new_order::Params {
+                    max_base_qty: 1,
+                    max_quote_qty: 10,
+                    limit_price: 1,
+                    side: Side::Ask,
+                    match_limit: 10,
+                    callback_info: [0; 32],
+                    post_only: false,
+                    post_allowed: false,
+                    self_trade_behavior: SelfTradeBehavior::DecrementTake,
+                    max_ts: u64::MAX,
+                    immediate_or_cancel: false,
+                    fill_or_kill: false,
+                    max_display_qty: 0,
+                    post_only_mode: PostOnlyMode::Reject,
+                    max_slippage: 0,
+                    flags: 0,
+                    min_base_qty: 0,
+                    compute_unit_reserve: 0,
+                    resumable: false,
+                    worst_price: 0,
+                    referral: [0; 32],
+                    client_order_id: 0,
+                },
+                &mut event_queue,
+                &mut market_state,
+                u64::MIN,
+            )
+            .unwrap();
+        assert!(event_queue.header.count == 0);
+
+        println!("Spread : {:?}", orderbook.get_spread());
+    }
+
+    #[test]
+    fn test_ob_booting_ask() {
+        let mut test_context = TestContext::new(2, 1000);
+        let (mut orderbook, mut event_queue) = test_context.get();
+        let mut market_state = test_market_state();
+        let alice = [1; 32];
+
+        // Alice posts an ask order for 3 BTC at 20 USD/BTC
+        let OrderSummary {
+            posted_order_id,
+            total_base_qty,
+            total_quote_qty,
+            total_base_qty_posted,
+            ..
+        } = orderbook
+            .new_order(
+                new_order::Params {
+                    max_base_qty: 3_000_000,
+                    max_quote_qty: 1_000_000_000,
+                    limit_price: 20 << 32,
+                    side: Side::Ask,
+                    match_limit: 10,
+                    callback_info: alice,
+                    post_only: false,
+                    post_allowed: true,
+                    self_trade_behavior: SelfTradeBehavior::DecrementTake,
+                    max_ts: u64::MAX,
+                    immediate_or_cancel: false,
+                    fill_or_kill: false,
+                    max_display_qty: 0,
+                    post_only_mode: PostOnlyMode::Reject,
+                    max_slippage: 0,
+                    flags: 0,
+                    min_base_qty: 0,
+                    compute_unit_reserve: 0,
+                    resumable: false,
+                    worst_price: 0,
+                    referral: [0; 32],
+                    client_order_id: 0,
+                },
+                &mut event_queue,
+                &mut market_state,
+                u64::MIN,
+            )
+            .unwrap();
+        assert!(posted_order_id.is_some());
+        assert_eq!(total_base_qty, 3_000_000);
+        assert_eq!(total_quote_qty, 60_000_000);
+        assert_eq!(total_base_qty_posted, 3_000_000);
+        assert_eq!(event_queue.header.count, 0);
+
+        // Alice posts an ask order for 6 BTC at 40 USD/BTC
+        let OrderSummary {
+            posted_order_id: order_id_to_be_booted,
+            total_base_qty,
+            total_quote_qty,
+            total_base_qty_posted,
+            ..
+        } = orderbook
+            .new_order(
+                new_order::Params {
+                    max_base_qty: 6_000_000,
+                    max_quote_qty: 1_000_000_000,
+                    limit_price: 40 << 32,
+                    side: Side::Ask,
+                    match_limit: 10,
+                    callback_info: alice,
+                    post_only: false,
+                    post_allowed: true,
+                    self_trade_behavior: SelfTradeBehavior::DecrementTake,
+                    max_ts: u64::MAX,
+                    immediate_or_cancel: false,
+                    fill_or_kill: false,
+                    max_display_qty: 0,
+                    post_only_mode: PostOnlyMode::Reject,
+                    max_slippage: 0,
+                    flags: 0,
+                    min_base_qty: 0,
+                    compute_unit_reserve: 0,
+                    resumable: false,
+                    worst_price: 0,
+                    referral: [0; 32],
+                    client_order_id: 0,
+                },
+                &mut event_queue,
+                &mut market_state,
+                u64::MIN,
+            )
+            .unwrap();
+        assert!(posted_order_id.is_some());
+        assert_eq!(total_base_qty, 6_000_000);
+        assert_eq!(total_quote_qty, 240_000_000);
+        assert_eq!(total_base_qty_posted, 6_000_000);
+        assert_eq!(event_queue.header.count, 0);
+
+        let asks = orderbook.get_tree(Side::Ask);
+        asks.dump();
+        let bids = orderbook.get_tree(Side::Bid);
+        bids.dump();
+
+        // Alice posts an ask order for 1 BTC at 10 USD/BTC
+        let OrderSummary {
+            posted_order_id,
+            total_base_qty,
+            total_quote_qty,
+            total_base_qty_posted,
+            ..
+        } = orderbook
+            .new_order(
+                new_order::Params {
+                    max_base_qty: 1_000_000,
+                    max_quote_qty: 1_000_000_000,
+                    limit_price: 10 << 32,
+                    side: Side::Ask,
+                    match_limit: 10,
+                    callback_info: alice,
+                    post_only: false,
+                    post_allowed: true,
+                    self_trade_behavior: SelfTradeBehavior::DecrementTake,
+                    max_ts: u64::MAX,
+                    immediate_or_cancel: false,
+                    fill_or_kill: false,
+                    max_display_qty: 0,
+                    post_only_mode: PostOnlyMode::Reject,
+                    max_slippage: 0,
+                    flags: 0,
+                    min_base_qty: 0,
+                    compute_unit_reserve: 0,
+                    resumable: false,
+                    worst_price: 0,
+                    referral: [0; 32],
+                    client_order_id: 0,
+                },
+                &mut event_queue,
+                &mut market_state,
+                u64::MIN,
+            )
+            .unwrap();
+        assert!(posted_order_id.is_some());
+        assert_eq!(total_base_qty, 1_000_000);
+        assert_eq!(total_quote_qty, 10_000_000);
+        assert_eq!(total_base_qty_posted, 1_000_000);
+        assert_eq!(event_queue.header.count, 1);
+
+        assert_eq!(
+            event_queue.iter().next().unwrap(),
+            EventRef::Boot(BootEventRef {
+                event: &BootEvent::new(
+                    Side::Ask,
+                    BootReason::OrderbookFull,
+                    6_000_000,
+                    order_id_to_be_booted.unwrap() as u128,
+                ),
+                callback_info: &alice
+            })
+        );
+
+        event_queue.pop_n(1);
+
+        // Alice posts an ask order for 1 BTC at 50 USD/BTC, but is not added to the ob
+        let OrderSummary {
+            posted_order_id,
+            total_base_qty,
+            total_quote_qty,
+            total_base_qty_posted,
+            ..
+        } = orderbook
+            .new_order(
+                new_order::Params {
+                    max_base_qty: 1_000_000,
+                    max_quote_qty: 1_000_000_000,
+                    limit_price: 50 << 32,
+                    side: Side::Ask,
+                    match_limit: 10,
+                    callback_info: alice,
+                    post_only: false,
+                    post_allowed: true,
+                    self_trade_behavior: SelfTradeBehavior::DecrementTake,
+                    max_ts: u64::MAX,
+                    immediate_or_cancel: false,
+                    fill_or_kill: false,
+                    max_display_qty: 0,
+                    post_only_mode: PostOnlyMode::Reject,
+                    max_slippage: 0,
+                    flags: 0,
+                    min_base_qty: 0,
+                    compute_unit_reserve: 0,
+                    resumable: false,
+                    worst_price: 0,
+                    referral: [0; 32],
+                    client_order_id: 0,
+                },
+                &mut event_queue,
+                &mut market_state,
+                u64::MIN,
+            )
+            .unwrap();
+        assert!(posted_order_id.is_none());
+        assert_eq!(total_base_qty, 0);
+        assert_eq!(total_quote_qty, 0);
+        assert_eq!(total_base_qty_posted, 0);
+        assert_eq!(event_queue.header.count, 0);
+    }
+
+    #[test]
+    fn test_ob_small_prices() {
+        let mut test_context = TestContext::new(2, 1000);
+        let (mut orderbook, mut event_queue) = test_context.get();
+        let mut market_state = test_market_state();
+        let alice = [1; 32];
+
+        // Alice posts a bid order for 1 BTC at 0.25 USD/BTC
+
+        let OrderSummary {
+            posted_order_id,
+            total_base_qty,
+            total_quote_qty,
+            total_base_qty_posted,
+            ..
+        } = orderbook
+            .new_order(
+                new_order::Params {
+                    max_base_qty: 2_000_000,
+                    max_quote_qty: 10_000_000,
+                    limit_price: 1 << 30,
+                    side: Side::Bid,
+                    match_limit: 10,
+                    callback_info: alice,
+                    post_only: false,
+                    post_allowed: true,
+                    self_trade_behavior: SelfTradeBehavior::DecrementTake,
+                    max_ts: u64::MAX,
+                    immediate_or_cancel: false,
+                    fill_or_kill: false,
+                    max_display_qty: 0,
+                    post_only_mode: PostOnlyMode::Reject,
+                    max_slippage: 0,
+                    flags: 0,
+                    min_base_qty: 0,
+                    compute_unit_reserve: 0,
+                    resumable: false,
+                    worst_price: 0,
+                    referral: [0; 32],
+                    client_order_id: 0,
+                },
+                &mut event_queue,
+                &mut market_state,
+                u64::MIN,
+            )
+            .unwrap();
+        assert!(posted_order_id.is_some());
+        assert_eq!(total_base_qty, 2_000_000);
+        assert_eq!(total_quote_qty, 500_000);
+        assert_eq!(total_base_qty_posted, 2_000_000);
+        assert!(event_queue.header.count == 0);
+
+        // Best bid is 0.25 USD / BTC
+
+        orderbook
+            .new_order(
+                new_order::Params {
+                    max_base_qty: 3,
+                    max_quote_qty: 1_000_000,
+                    limit_price: 1 << 28,
+                    side: Side::Ask,
+                    match_limit: 10,
+                    callback_info: [0; 32],
+                    post_only: false,
+                    post_allowed: false,
+                    self_trade_behavior: SelfTradeBehavior::DecrementTake,
+                    max_ts: u64::MAX,
+                    immediate_or_cancel: false,
+                    fill_or_kill: false,
+                    max_display_qty: 0,
+                    post_only_mode: PostOnlyMode::Reject,
+                    max_slippage: 0,
+                    flags: 0,
+                    min_base_qty: 0,
+                    compute_unit_reserve: 0,
+                    resumable: false,
+                    worst_price: 0,
+                    referral: [0; 32],
+                    client_order_id: 0,
+                },
+                &mut event_queue,
+                &mut market_state,
+                u64::MIN,
+            )
+            .unwrap();
+        assert!(event_queue.header.count == 0);
+    }
+
+    #[test]
+    fn test_ob_booting_bid() {
+        let mut test_context = TestContext::new(2, 1000);
+        let (mut orderbook, mut event_queue) = test_context.get();
+        let mut market_state = test_market_state();
+        let alice = [1; 32];
+
+        // Alice posts an ask order for 3 BTC at 20 USD/BTC
+        let OrderSummary {
+            posted_order_id,
+            total_base_qty,
+            total_quote_qty,
+            total_base_qty_posted,
+            ..
+        } = orderbook
+            .new_order(
+                new_order::Params {
+                    max_base_qty: 3_000_000,
+                    max_quote_qty: 1_000_000_000,
+                    limit_price: 20 << 32,
+                    side: Side::Bid,
+                    match_limit: 10,
+                    callback_info: alice,
+                    post_only: false,
+                    post_allowed: true,
+                    self_trade_behavior: SelfTradeBehavior::DecrementTake,
+                    max_ts: u64::MAX,
+                    immediate_or_cancel: false,
+                    fill_or_kill: false,
+                    max_display_qty: 0,
+                    post_only_mode: PostOnlyMode::Reject,
+                    max_slippage: 0,
+                    flags: 0,
+                    min_base_qty: 0,
+                    compute_unit_reserve: 0,
+                    resumable: false,
+                    worst_price: 0,
+                    referral: [0; 32],
+                    client_order_id: 0,
+                },
+                &mut event_queue,
+                &mut market_state,
+                u64::MIN,
+            )
+            .unwrap();
+        assert!(posted_order_id.is_some());
+        assert_eq!(total_base_qty, 3_000_000);
+        assert_eq!(total_quote_qty, 60_000_000);
+        assert_eq!(total_base_qty_posted, 3_000_000);
+        assert_eq!(event_queue.header.count, 0);
+
+        // Alice posts a bid order for 6 BTC at 10 USD/BTC
+        let OrderSummary {
+            posted_order_id: order_id_to_be_booted,
+            total_base_qty,
+            total_quote_qty,
+            total_base_qty_posted,
+            ..
+        } = orderbook
+            .new_order(
+                new_order::Params {
+                    max_base_qty: 6_000_000,
+                    max_quote_qty: 1_000_000_000,
+                    limit_price: 10 << 32,
+                    side: Side::Bid,
+                    match_limit: 10,
+                    callback_info: alice,
+                    post_only: false,
+                    post_allowed: true,
+                    self_trade_behavior: SelfTradeBehavior::DecrementTake,
+                    max_ts: u64::MAX,
+                    immediate_or_cancel: false,
+                    fill_or_kill: false,
+                    max_display_qty: 0,
+                    post_only_mode: PostOnlyMode::Reject,
+                    max_slippage: 0,
+                    flags: 0,
+                    min_base_qty: 0,
+                    compute_unit_reserve: 0,
+                    resumable: false,
+                    worst_price: 0,
+                    referral: [0; 32],
+                    client_order_id: 0,
+                },
+                &mut event_queue,
+                &mut market_state,
+                u64::MIN,
+            )
+            .unwrap();
+        assert!(posted_order_id.is_some());
+        assert_eq!(total_base_qty, 6_000_000);
+        assert_eq!(total_quote_qty, 60_000_000);
+        assert_eq!(total_base_qty_posted, 6_000_000);
+        assert_eq!(event_queue.header.count, 0);
+
+        // Alice posts a bid order for 1 BTC at 40 USD/BTC
+        let OrderSummary {
+            posted_order_id,
+            total_base_qty,
+            total_quote_qty,
+            total_base_qty_posted,
+            ..
+        } = orderbook
+            .new_order(
+                new_order::Params {
+                    max_base_qty: 1_000_000,
+                    max_quote_qty: 1_000_000_000,
+                    limit_price: 40 << 32,
+                    side: Side::Bid,
+                    match_limit: 10,
+                    callback_info: alice,
+                    post_only: false,
+                    post_allowed: true,
+                    self_trade_behavior: SelfTradeBehavior::DecrementTake,
+                    max_ts: u64::MAX,
+                    immediate_or_cancel: false,
+                    fill_or_kill: false,
+                    max_display_qty: 0,
+                    post_only_mode: PostOnlyMode::Reject,
+                    max_slippage: 0,
+                    flags: 0,
+                    min_base_qty: 0,
+                    compute_unit_reserve: 0,
+                    resumable: false,
+                    worst_price: 0,
+                    referral: [0; 32],
+                    client_order_id: 0,
+                },
+                &mut event_queue,
+                &mut market_state,
+                u64::MIN,
+            )
+            .unwrap();
+        assert!(posted_order_id.is_some());
+        assert_eq!(total_base_qty, 1_000_000);
+        assert_eq!(total_quote_qty, 40_000_000);
+        assert_eq!(total_base_qty_posted, 1_000_000);
+        assert_eq!(event_queue.header.count, 1);
+
+        assert_eq!(
+            event_queue.iter().next().unwrap(),
+            EventRef::Boot(BootEventRef {
+                event: &BootEvent::new(
+                    Side::Bid,
+                    BootReason::OrderbookFull,
+                    6_000_000,
+                    order_id_to_be_booted.unwrap() as u128,
+                ),
+                callback_info: &alice
+            })
+        );
+
+        event_queue.pop_n(1);
+
+        // Alice posts an ask order for 1 BTC at 50 USD/BTC, but is not added to the ob
+        let OrderSummary {
+            posted_order_id,
+            total_base_qty,
+            total_quote_qty,
+            total_base_qty_posted,
+            ..
+        } = orderbook
+            .new_order(
+                new_order::Params {
+                    max_base_qty: 1_000_000,
+                    max_quote_qty: 1_000_000_000,
+                    limit_price: 5 << 32,
+                    side: Side::Bid,
+                    match_limit: 10,
+                    callback_info: alice,
+                    post_only: false,
+                    post_allowed: true,
+                    self_trade_behavior: SelfTradeBehavior::DecrementTake,
+                    max_ts: u64::MAX,
+                    immediate_or_cancel: false,
+                    fill_or_kill: false,
+                    max_display_qty: 0,
+                    post_only_mode: PostOnlyMode::Reject,
+                    max_slippage: 0,
+                    flags: 0,
+                    min_base_qty: 0,
+                    compute_unit_reserve: 0,
+                    resumable: false,
+                    worst_price: 0,
+                    referral: [0; 32],
+                    client_order_id: 0,
+                },
+                &mut event_queue,
+                &mut market_state,
+                u64::MIN,
+            )
+            .unwrap();
+        assert!(posted_order_id.is_none());
+        assert_eq!(total_base_qty, 0);
+        assert_eq!(total_quote_qty, 0);
+        assert_eq!(total_base_qty_posted, 0);
+        assert_eq!(event_queue.header.count, 0);
+    }
+
+    #[test]
+    fn test_ob_immediate_or_cancel() {
+        let mut test_context = TestContext::new(1000, 1000);
+        let (mut orderbook, mut event_queue) = test_context.get();
+        let mut market_state = test_market_state();
+        let alice = [1; 32];
+        let bob = [2; 32];
+
+        // Alice posts an ask order for 1 BTC at 10 USD/BTC
+        orderbook
+            .new_order(
+                new_order::Params {
+                    max_base_qty: 1_000_000,
+                    max_quote_qty: 10_000_000,
+                    limit_price: 10 << 32,
+                    side: Side::Ask,
+                    match_limit: 10,
+                    callback_info: alice,
+                    post_only: false,
+                    post_allowed: true,
+                    self_trade_behavior: SelfTradeBehavior::DecrementTake,
+                    max_ts: u64::MAX,
+                    immediate_or_cancel: false,
+                    fill_or_kill: false,
+                    max_display_qty: 0,
+                    post_only_mode: PostOnlyMode::Reject,
+                    max_slippage: 0,
+                    flags: 0,
+                    min_base_qty: 0,
+                    compute_unit_reserve: 0,
+                    resumable: false,
+                    worst_price: 0,
+                    referral: [0; 32],
+                    client_order_id: 0,
+                },
+                &mut event_queue,
+                &mut market_state,
+                u64::MIN,
+            )
+            .unwrap();
+
+        // Bob sends an IOC bid for 3 BTC at 10 USD/BTC, only 1 BTC can be filled
+        let OrderSummary {
+            posted_order_id,
+            total_base_qty,
+            total_quote_qty,
+            total_base_qty_posted,
+            total_base_qty_unfilled,
+            ..
+        } = orderbook
+            .new_order(
+                new_order::Params {
+                    max_base_qty: 3_000_000,
+                    max_quote_qty: 30_000_000,
+                    limit_price: 10 << 32,
+                    side: Side::Bid,
+                    match_limit: 10,
+                    callback_info: bob,
+                    post_only: false,
+                    post_allowed: true,
+                    self_trade_behavior: SelfTradeBehavior::DecrementTake,
+                    max_ts: u64::MAX,
+                    immediate_or_cancel: true,
+                    fill_or_kill: false,
+                    max_display_qty: 0,
+                    post_only_mode: PostOnlyMode::Reject,
+                    max_slippage: 0,
+                    flags: 0,
+                    min_base_qty: 0,
+                    compute_unit_reserve: 0,
+                    resumable: false,
+                    worst_price: 0,
+                    referral: [0; 32],
+                    client_order_id: 0,
+                },
+                &mut event_queue,
+                &mut market_state,
+                u64::MIN,
+            )
+            .unwrap();
+        assert!(posted_order_id.is_none());
+        assert_eq!(total_base_qty, 1_000_000);
+        assert_eq!(total_quote_qty, 10_000_000);
+        assert_eq!(total_base_qty_posted, 0);
+        assert_eq!(total_base_qty_unfilled, 2_000_000);
+        // One fill event and one out event for Alice's fully matched order
+        assert_eq!(event_queue.header.count, 2);
+        assert!(orderbook.is_empty());
+    }
+
+    #[test]
+    fn test_ob_fill_or_kill() {
+        let mut test_context = TestContext::new(1000, 1000);
+        let (mut orderbook, mut event_queue) = test_context.get();
+        let mut market_state = test_market_state();
+        let alice = [1; 32];
+        let bob = [2; 32];
+
+        // Alice posts an ask order for 1 BTC at 10 USD/BTC
+        orderbook
+            .new_order(
+                new_order::Params {
+                    max_base_qty: 1_000_000,
+                    max_quote_qty: 10_000_000,
+                    limit_price: 10 << 32,
+                    side: Side::Ask,
+                    match_limit: 10,
+                    callback_info: alice,
+                    post_only: false,
+                    post_allowed: true,
+                    self_trade_behavior: SelfTradeBehavior::DecrementTake,
+                    max_ts: u64::MAX,
+                    immediate_or_cancel: false,
+                    fill_or_kill: false,
+                    max_display_qty: 0,
+                    post_only_mode: PostOnlyMode::Reject,
+                    max_slippage: 0,
+                    flags: 0,
+                    min_base_qty: 0,
+                    compute_unit_reserve: 0,
+                    resumable: false,
+                    worst_price: 0,
+                    referral: [0; 32],
+                    client_order_id: 0,
+                },
+                &mut event_queue,
+                &mut market_state,
+                u64::MIN,
+            )
+            .unwrap();
+
+        let bob_params = |max_base_qty: u64| new_order::Params {
+            max_base_qty,
+            max_quote_qty: u64::MAX,
+            limit_price: 10 << 32,
+            side: Side::Bid,
+            match_limit: 10,
+            callback_info: bob,
+            post_only: false,
+            post_allowed: true,
+            self_trade_behavior: SelfTradeBehavior::DecrementTake,
+            max_ts: u64::MAX,
+            immediate_or_cancel: false,
+            fill_or_kill: true,
+            max_display_qty: 0,
+            post_only_mode: PostOnlyMode::Reject,
+            max_slippage: 0,
+            flags: 0,
+            min_base_qty: 0,
+            compute_unit_reserve: 0,
+            resumable: false,
+            worst_price: 0,
+            referral: [0; 32],
+            client_order_id: 0,
+        };
+
+        // Bob sends a FOK bid for 2 BTC at 10 USD/BTC, which cannot be entirely filled
+        let res = orderbook.new_order(
+            bob_params(2_000_000),
+            &mut event_queue,
+            &mut market_state,
+            u64::MIN,
+        );
+        assert!(matches!(res, Err(AoError::UnableToFill)));
+        assert_eq!(event_queue.header.count, 0);
+        assert_eq!(orderbook.asks.header.leaf_count, 1);
+
+        // Bob sends a FOK bid for 0.5 BTC at 10 USD/BTC
+        let OrderSummary {
+            posted_order_id,
+            total_base_qty,
+            total_quote_qty,
+            total_base_qty_posted,
+            total_base_qty_unfilled,
+            ..
+        } = orderbook
+            .new_order(
+                bob_params(500_000),
+                &mut event_queue,
+                &mut market_state,
+                u64::MIN,
+            )
+            .unwrap();
+        assert!(posted_order_id.is_none());
+        assert_eq!(total_base_qty, 500_000);
+        assert_eq!(total_quote_qty, 5_000_000);
+        assert_eq!(total_base_qty_posted, 0);
+        assert_eq!(total_base_qty_unfilled, 0);
+        assert_eq!(event_queue.header.count, 1);
+    }
+
+    #[test]
+    fn test_ob_prune_expired() {
+        let mut test_context = TestContext::new(1000, 1000);
+        let (mut orderbook, mut event_queue) = test_context.get();
+        let mut market_state = test_market_state();
+        let alice = [1; 32];
+
+        let alice_params = |side: Side, limit_price: u64, max_ts: u64| new_order::Params {
+            max_base_qty: 1_000_000,
+            max_quote_qty: u64::MAX,
+            limit_price,
+            side,
+            match_limit: 10,
+            callback_info: alice,
+            post_only: true,
+            post_allowed: true,
+            self_trade_behavior: SelfTradeBehavior::DecrementTake,
+            max_ts,
+            immediate_or_cancel: false,
+            fill_or_kill: false,
+            max_display_qty: 0,
+            post_only_mode: PostOnlyMode::Reject,
+            max_slippage: 0,
+            flags: 0,
+            min_base_qty: 0,
+            compute_unit_reserve: 0,
+            resumable: false,
+            worst_price: 0,
+            referral: [0; 32],
+            client_order_id: 0,
+        };
+
+        let OrderSummary {
+            posted_order_id: expiring_bid_id,
+            ..
+        } = orderbook
+            .new_order(
+                alice_params(Side::Bid, 9 << 32, 10),
+                &mut event_queue,
+                &mut market_state,
+                0,
+            )
+            .unwrap();
+        orderbook
+            .new_order(
+                alice_params(Side::Bid, 8 << 32, 20),
+                &mut event_queue,
+                &mut market_state,
+                0,
+            )
+            .unwrap();
+        orderbook
+            .new_order(
+                alice_params(Side::Ask, 11 << 32, 10),
+                &mut event_queue,
+                &mut market_state,
+                0,
+            )
+            .unwrap();
+        orderbook
+            .new_order(
+                alice_params(Side::Ask, 12 << 32, u64::MAX),
+                &mut event_queue,
+                &mut market_state,
+                0,
+            )
+            .unwrap();
+
+        // Only one of the two expired orders can be pruned
+        let num_pruned = orderbook.prune_expired(1, 15, &mut event_queue).unwrap();
+        assert_eq!(num_pruned, 1);
+        assert_eq!(orderbook.bids.header.leaf_count, 1);
+        assert_eq!(orderbook.asks.header.leaf_count, 2);
+        assert_eq!(
+            event_queue.peek_at(0).unwrap(),
+            EventRef::Out(OutEventRef {
+                event: &OutEvent::new(Side::Bid, 1_000_000, expiring_bid_id.unwrap()),
+                callback_info: &alice
+            })
+        );
+
+        let num_pruned = orderbook.prune_expired(10, 15, &mut event_queue).unwrap();
+        assert_eq!(num_pruned, 1);
+        assert_eq!(orderbook.bids.header.leaf_count, 1);
+        assert_eq!(orderbook.asks.header.leaf_count, 1);
+        assert_eq!(event_queue.header.count, 2);
+    }
+
+    #[test]
+    fn test_ob_get_quote() {
+        let mut test_context = TestContext::new(1000, 1000);
+        let (mut orderbook, mut event_queue) = test_context.get();
+        let mut market_state = test_market_state();
+
+        let params =
+            |side: Side, limit_price: u64, max_base_qty: u64, max_ts: u64| new_order::Params {
+                max_base_qty,
+                max_quote_qty: u64::MAX,
+                limit_price,
+                side,
+                match_limit: 10,
+                callback_info: [1; 32],
+                post_only: true,
+                post_allowed: true,
+                self_trade_behavior: SelfTradeBehavior::DecrementTake,
+                max_ts,
+                immediate_or_cancel: false,
+                fill_or_kill: false,
+                max_display_qty: 0,
+                post_only_mode: PostOnlyMode::Reject,
+                max_slippage: 0,
+                flags: 3,
+                min_base_qty: 0,
+                compute_unit_reserve: 0,
+                resumable: false,
+                worst_price: 0,
+                referral: [0; 32],
+                client_order_id: 0,
+            };
+
+        assert_eq!(
+            orderbook.get_quote(0, 0),
+            Quote {
+                best_bid: None,
+                best_ask: None,
+                order: None
+            }
+        );
+
+        let mut post = |params| {
+            orderbook
+                .new_order(params, &mut event_queue, &mut market_state, 0)
+                .unwrap()
+                .posted_order_id
+                .unwrap()
+        };
+        let expiring_bid_id = post(params(Side::Bid, 9 << 32, 100, 10));
+        post(params(Side::Bid, 8 << 32, 200, u64::MAX));
+        post(params(Side::Bid, 8 << 32, 300, u64::MAX));
+        post(params(Side::Bid, 7 << 32, 400, u64::MAX));
+        post(params(Side::Ask, 12 << 32, 500, u64::MAX));
+
+        let quote = orderbook.get_quote(expiring_bid_id, 5);
+        assert_eq!(
+            quote.best_bid,
+            Some(PriceLevel {
+                price: 9 << 32,
+                base_quantity: 100
+            })
+        );
+        assert_eq!(
+            quote.best_ask,
+            Some(PriceLevel {
+                price: 12 << 32,
+                base_quantity: 500
+            })
+        );
+
+        // Expired orders are skipped when computing the best price levels, but can still be looked up
+        let quote = orderbook.get_quote(expiring_bid_id, 15);
+        assert_eq!(
+            quote.best_bid,
+            Some(PriceLevel {
+                price: 8 << 32,
+                base_quantity: 500
+            })
+        );
+        assert_eq!(
+            quote.order,
+            Some(OrderState {
+                order_id: expiring_bid_id,
+                price: 9 << 32,
+                base_quantity: 100,
+                hidden_quantity: 0,
+                max_ts: 10,
+                flags: 3,
+            })
+        );
+
+        orderbook
+            .cancel_order(expiring_bid_id, &market_state)
+            .unwrap();
+        assert_eq!(orderbook.get_quote(expiring_bid_id, 15).order, None);
+    }
+
+    #[test]
+    fn test_ob_worst_price() {
+        let mut test_context = TestContext::new(1000, 1000);
+        let (mut orderbook, mut event_queue) = test_context.get();
+        let mut market_state = test_market_state();
+
+        let params =
+            |side: Side, limit_price: u64, max_base_qty: u64, worst_price: u64| new_order::Params {
+                max_base_qty,
+                max_quote_qty: u64::MAX,
+                limit_price,
+                side,
+                match_limit: 10,
+                callback_info: [1; 32],
+                post_only: false,
+                post_allowed: true,
+                self_trade_behavior: SelfTradeBehavior::DecrementTake,
+                max_ts: u64::MAX,
+                immediate_or_cancel: false,
+                fill_or_kill: false,
+                max_display_qty: 0,
+                post_only_mode: PostOnlyMode::Reject,
+                max_slippage: 0,
+                flags: 0,
+                min_base_qty: 0,
+                compute_unit_reserve: 0,
+                resumable: false,
+                worst_price,
+                referral: [0; 32],
+                client_order_id: 0,
+            };
+        for order in [
+            params(Side::Ask, 10 << 32, 100, 0),
+            params(Side::Ask, 11 << 32, 100, 0),
+            params(Side::Ask, 14 << 32, 300, 0),
+            params(Side::Bid, 9 << 32, 100, 0),
+            params(Side::Bid, 8 << 32, 100, 0),
+        ]
+        .iter()
+        {
+            orderbook
+                .new_order(order.clone(), &mut event_queue, &mut market_state, 0)
+                .unwrap();
+        }
+
+        // The fill or kill order can't be entirely matched within its worst price
+        let mut fill_or_kill = params(Side::Bid, 15 << 32, 400, 12 << 32);
+        fill_or_kill.fill_or_kill = true;
+        let res = orderbook.new_order(fill_or_kill, &mut event_queue, &mut market_state, 0);
+        assert!(matches!(res, Err(AoError::UnableToFill)));
+
+        // 150 base are bought at 14 for an average price of 12, and the remainder isn't posted despite the limit price
+        let summary = orderbook
+            .new_order(
+                params(Side::Bid, 15 << 32, 1000, 12 << 32),
+                &mut event_queue,
+                &mut market_state,
+                0,
+            )
+            .unwrap();
+        assert_eq!(summary.posted_order_id, None);
+        assert_eq!(summary.total_base_qty, 350);
+        assert_eq!(summary.total_quote_qty, 4200);
+        assert_eq!(summary.total_base_qty_unfilled, 650);
+        assert_eq!(orderbook.asks.header.leaf_count, 1);
+        assert_eq!(orderbook.bids.header.leaf_count, 2);
+
+        // Selling into the bids stops once the average price reaches 8.5
+        let summary = orderbook
+            .new_order(
+                params(Side::Ask, 1 << 32, 300, 17 << 31),
+                &mut event_queue,
+                &mut market_state,
+                0,
+            )
+            .unwrap();
+        assert_eq!(summary.total_base_qty, 200);
+        assert_eq!(summary.total_quote_qty, 1700);
+        assert_eq!(summary.total_base_qty_unfilled, 100);
+        assert!(orderbook.bids.header.leaf_count == 0 && orderbook.asks.header.leaf_count == 1);
+    }
+
+    #[test]
+    fn test_ob_fees() {
+        let mut test_context = TestContext::new(1000, 1000);
+        let (mut orderbook, mut event_queue) = test_context.get();
+        let mut market_state = test_market_state();
+        market_state.maker_fee_bps = 10;
+        market_state.taker_fee_bps = 25;
+
+        let params = |side: Side, max_base_qty: u64| new_order::Params {
+            max_base_qty,
+            max_quote_qty: u64::MAX,
+            limit_price: 10 << 32,
+            side,
+            match_limit: 10,
+            callback_info: [1; 32],
+            post_only: false,
+            post_allowed: true,
+            self_trade_behavior: SelfTradeBehavior::DecrementTake,
+            max_ts: u64::MAX,
+            immediate_or_cancel: false,
+            fill_or_kill: false,
+            max_display_qty: 0,
+            post_only_mode: PostOnlyMode::Reject,
+            max_slippage: 0,
+            flags: 0,
+            min_base_qty: 0,
+            compute_unit_reserve: 0,
+            resumable: false,
+            worst_price: 0,
+            referral: [0; 32],
+            client_order_id: 0,
+        };
+        orderbook
+            .new_order(
+                params(Side::Ask, 1_000_000),
+                &mut event_queue,
+                &mut market_state,
+                0,
+            )
+            .unwrap();
+        orderbook
+            .new_order(
+                params(Side::Bid, 100_000),
+                &mut event_queue,
+                &mut market_state,
+                0,
+            )
+            .unwrap();
+        let fill = match event_queue.peek_at(0).unwrap() {
+            EventRef::Fill(FillEventRef { event, .. }) => *event,
+            _ => panic!(),
+        };
+        assert_eq!((fill.maker_fee_bps(), fill.taker_fee_bps()), (10, 25));
+        assert_eq!((fill.maker_fee(), fill.taker_fee()), (1_000, 2_500));
+        assert_eq!(market_state.accrued_maker_fees, 1_000);
+        assert_eq!(market_state.accrued_taker_fees, 2_500);
+
+        // The fees of split fills are rounded up on the quote quantity of each side
+        market_state.split_fills = 1;
+        orderbook
+            .new_order(
+                params(Side::Bid, 55),
+                &mut event_queue,
+                &mut market_state,
+                0,
+            )
+            .unwrap();
+        let (maker_fill, taker_fill) = match (event_queue.peek_at(1), event_queue.peek_at(2)) {
+            (
+                Some(EventRef::MakerFill(MakerFillEventRef { event: m, .. })),
+                Some(EventRef::TakerFill(TakerFillEventRef { event: t, .. })),
+            ) => (m.0, t.0),
+            _ => panic!(),
+        };
+        assert_eq!(maker_fill.maker_fee(), 1);
+        assert_eq!(taker_fill.taker_fee(), 2);
+        assert_eq!(market_state.accrued_maker_fees, 1_001);
+        assert_eq!(market_state.accrued_taker_fees, 2_502);
+    }
+
+    #[test]
+    fn test_ob_referral() {
+        let mut test_context = TestContext::new(1000, 1000);
+        let (mut orderbook, mut event_queue) = test_context.get();
+        let mut market_state = test_market_state();
+
+        let params =
+            |side: Side, max_base_qty: u64, callback_info: [u8; 32], referral: [u8; 32]| {
+                new_order::Params {
+                    max_base_qty,
+                    max_quote_qty: u64::MAX,
+                    limit_price: 10 << 32,
+                    side,
+                    match_limit: 10,
+                    callback_info,
+                    post_only: false,
+                    post_allowed: true,
+                    self_trade_behavior: SelfTradeBehavior::DecrementTake,
+                    max_ts: u64::MAX,
+                    immediate_or_cancel: false,
+                    fill_or_kill: false,
+                    max_display_qty: 0,
+                    post_only_mode: PostOnlyMode::Reject,
+                    max_slippage: 0,
+                    flags: 0,
+                    min_base_qty: 0,
+                    compute_unit_reserve: 0,
+                    resumable: false,
+                    worst_price: 0,
+                    referral,
+                    client_order_id: 0,
+                }
+            };
+        for _ in 0..3 {
+            orderbook
+                .new_order(
+                    params(Side::Ask, 100, [1; 32], [0; 32]),
+                    &mut event_queue,
+                    &mut market_state,
+                    0,
+                )
+                .unwrap();
+        }
+
+        // Each fill of the taker order is followed by the referral tag, with the same callback infos
+        orderbook
+            .new_order(
+                params(Side::Bid, 150, [2; 32], [7; 32]),
+                &mut event_queue,
+                &mut market_state,
+                0,
+            )
+            .unwrap();
+        match event_queue.iter().collect::<Vec<_>>().as_slice() {
+            [EventRef::Fill(fill), EventRef::Referral(first), EventRef::Out(_), EventRef::Fill(_), EventRef::Referral(second)] =>
+            {
+                assert_eq!(first.event.referral(), [7; 32]);
+                assert_eq!(second.event.referral(), [7; 32]);
+                assert_eq!(fill.maker_callback_info, &[1; 32]);
+                assert_eq!(fill.taker_callback_info, &[2; 32]);
+                assert_eq!(first.callback_info, fill.maker_callback_info);
+            }
+            _ => panic!(),
+        }
+        event_queue.pop_n(5);
+
+        // On markets which split fills, the referral tag follows the taker side of the fill
+        market_state.split_fills = 1;
+        orderbook
+            .new_order(
+                params(Side::Bid, 50, [2; 32], [7; 32]),
+                &mut event_queue,
+                &mut market_state,
+                0,
+            )
+            .unwrap();
+        match event_queue.iter().collect::<Vec<_>>().as_slice() {
+            [EventRef::MakerFill(_), EventRef::TakerFill(_), EventRef::Referral(ReferralEventRef {
+                event,
+                callback_info,
+            }), EventRef::Out(_)] => {
+                assert_eq!(event.referral(), [7; 32]);
+                assert_eq!(*callback_info, &[2; 32]);
+            }
+            _ => panic!(),
+        }
+
+        // Orders without a referral don't push referral events
+        event_queue.pop_n(4);
+        orderbook
+            .new_order(
+                params(Side::Bid, 50, [2; 32], [0; 32]),
+                &mut event_queue,
+                &mut market_state,
+                0,
+            )
+            .unwrap();
+        assert_eq!(event_queue.iter().count(), 2);
+    }
+
+    #[test]
+    fn test_ob_max_orders_per_owner() {
+        let mut test_context = TestContext::new(1000, 1000);
+        let (mut orderbook, mut event_queue) = test_context.get();
+        let mut market_state = test_market_state();
+        market_state.max_orders_per_owner = 2;
+
+        let params = |side: Side, limit_price: u64, callback_info: [u8; 32]| new_order::Params {
+            max_base_qty: 100,
+            max_quote_qty: u64::MAX,
+            limit_price,
+            side,
+            match_limit: 10,
+            callback_info,
+            post_only: false,
+            post_allowed: true,
+            self_trade_behavior: SelfTradeBehavior::DecrementTake,
+            max_ts: u64::MAX,
+            immediate_or_cancel: false,
+            fill_or_kill: false,
+            max_display_qty: 0,
+            post_only_mode: PostOnlyMode::Reject,
+            max_slippage: 0,
+            flags: 0,
+            min_base_qty: 0,
+            compute_unit_reserve: 0,
+            resumable: false,
+            worst_price: 0,
+            referral: [0; 32],
+            client_order_id: 0,
+        };
+        let alice_bid = orderbook
+            .new_order(
+                params(Side::Bid, 9 << 32, [1; 32]),
+                &mut event_queue,
+                &mut market_state,
+                0,
+            )
+            .unwrap()
+            .posted_order_id
+            .unwrap();
+        orderbook
+            .new_order(
+                params(Side::Ask, 11 << 32, [1; 32]),
+                &mut event_queue,
+                &mut market_state,
+                0,
+            )
+            .unwrap();
+        assert_eq!(orderbook.count_owner_orders(&[1; 32]), 2);
+
+        // The limit is shared by both sides of the orderbook
+        assert!(matches!(
+            orderbook.new_order(
+                params(Side::Bid, 8 << 32, [1; 32]),
+                &mut event_queue,
+                &mut market_state,
+                0,
+            ),
+            Err(AoError::TooManyOpenOrders)
+        ));
+
+        // Orders which don't rest in the orderbook aren't limited
+        orderbook
+            .new_order(
+                params(Side::Bid, 10 << 32, [2; 32]),
+                &mut event_queue,
+                &mut market_state,
+                0,
+            )
+            .unwrap();
+        let mut take = params(Side::Ask, 10 << 32, [1; 32]);
+        take.immediate_or_cancel = true;
+        let order_summary = orderbook
+            .new_order(take, &mut event_queue, &mut market_state, 0)
+            .unwrap();
+        assert_eq!(order_summary.total_base_qty, 100);
+
+        // Other owners are counted separately, and cancelled orders free up room
+        orderbook
+            .new_order(
+                params(Side::Bid, 8 << 32, [2; 32]),
+                &mut event_queue,
+                &mut market_state,
+                0,
+            )
+            .unwrap();
+        orderbook.cancel_order(alice_bid, &market_state).unwrap();
+        orderbook
+            .new_order(
+                params(Side::Bid, 8 << 32, [1; 32]),
+                &mut event_queue,
+                &mut market_state,
+                0,
+            )
+            .unwrap();
+        assert_eq!(orderbook.count_owner_orders(&[1; 32]), 2);
+        assert_eq!(orderbook.count_owner_orders(&[2; 32]), 1);
+    }
+
+    #[test]
+    fn test_ob_quote_swap() {
+        let mut test_context = TestContext::new(1000, 1000);
+        let (mut orderbook, mut event_queue) = test_context.get();
+        let mut market_state = test_market_state();
+
+        let params =
+            |side: Side, limit_price: u64, max_base_qty: u64, max_ts: u64| new_order::Params {
+                max_base_qty,
+                max_quote_qty: u64::MAX,
+                limit_price,
+                side,
+                match_limit: 10,
+                callback_info: [1; 32],
+                post_only: true,
+                post_allowed: true,
+                self_trade_behavior: SelfTradeBehavior::DecrementTake,
+                max_ts,
+                immediate_or_cancel: false,
+                fill_or_kill: false,
+                max_display_qty: 0,
+                post_only_mode: PostOnlyMode::Reject,
+                max_slippage: 0,
+                flags: 0,
+                min_base_qty: 0,
+                compute_unit_reserve: 0,
+                resumable: false,
+                worst_price: 0,
+                referral: [0; 32],
+                client_order_id: 0,
+            };
+        for order in [
+            params(Side::Ask, 9 << 32, 1000, 10),
+            params(Side::Ask, 10 << 32, 100, u64::MAX),
+            params(Side::Ask, 12 << 32, 200, u64::MAX),
+            params(Side::Bid, 8 << 32, 300, u64::MAX),
+        ]
+        .iter()
+        {
+            orderbook
+                .new_order(order.clone(), &mut event_queue, &mut market_state, 0)
+                .unwrap();
+        }
+        let quote = |side, amount, exact_in| {
+            let q = if exact_in {
+                orderbook.quote_exact_in(side, amount, 10, &market_state, 15)
+            } else {
+                orderbook.quote_exact_out(side, amount, 10, &market_state, 15)
+            }
+            .unwrap();
+            (q.in_amount, q.out_amount, q.fully_filled)
+        };
+
+        // The expired ask is skipped, and the remaining 4 quote lots can't buy a single base lot at 12
+        assert_eq!(quote(Side::Bid, 1400, true), (1396, 133, true));
+        assert_eq!(quote(Side::Bid, 150, false), (1600, 150, true));
+        assert_eq!(quote(Side::Bid, 400, false), (3400, 300, false));
+        assert_eq!(quote(Side::Ask, 50, true), (50, 400, true));
+        assert_eq!(quote(Side::Ask, 800, false), (100, 800, true));
+        assert_eq!(quote(Side::Ask, 500, true), (300, 2400, false));
+        assert_eq!(
+            orderbook
+                .quote_exact_in(Side::Bid, 1400, 1, &market_state, 15)
+                .unwrap()
+                .num_fills,
+            0
+        );
+
+        // Quoting leaves the orderbook untouched
+        assert_eq!(orderbook.asks.header.leaf_count, 3);
+    }
+
+    #[test]
+    fn test_ob_iceberg() {
+        let mut test_context = TestContext::new(1000, 1000);
+        let (mut orderbook, mut event_queue) = test_context.get();
+        let mut market_state = test_market_state();
+        let alice = [1; 32];
+        let bob = [2; 32];
+
+        // Alice posts an ask order for 3 BTC at 10 USD/BTC, displaying at most 1 BTC at once
+        let OrderSummary {
+            posted_order_id,
+            total_base_qty_posted,
+            ..
+        } = orderbook
+            .new_order(
+                new_order::Params {
+                    max_base_qty: 3_000_000,
+                    max_quote_qty: 30_000_000,
+                    limit_price: 10 << 32,
+                    side: Side::Ask,
+                    match_limit: 10,
+                    callback_info: alice,
+                    post_only: false,
+                    post_allowed: true,
+                    self_trade_behavior: SelfTradeBehavior::DecrementTake,
+                    max_ts: u64::MAX,
+                    immediate_or_cancel: false,
+                    fill_or_kill: false,
+                    max_display_qty: 1_000_000,
+                    post_only_mode: PostOnlyMode::Reject,
+                    max_slippage: 0,
+                    flags: 0,
+                    min_base_qty: 0,
+                    compute_unit_reserve: 0,
+                    resumable: false,
+                    worst_price: 0,
+                    referral: [0; 32],
+                    client_order_id: 0,
+                },
+                &mut event_queue,
+                &mut market_state,
+                u64::MIN,
+            )
+            .unwrap();
+        let alice_order_id = posted_order_id.unwrap();
+        assert_eq!(total_base_qty_posted, 3_000_000);
+        let alice_order = orderbook.asks.leaf_nodes[orderbook.asks.find_min().unwrap() as usize];
+        assert_eq!(alice_order.base_quantity(), 1_000_000);
+        assert_eq!(alice_order.hidden_quantity(), 2_000_000);
+
+        // Bob sends a bid for 1.5 BTC at 10 USD/BTC, which goes through the displayed quantity
+        let OrderSummary { total_base_qty, .. } = orderbook
+            .new_order(
+                new_order::Params {
+                    max_base_qty: 1_500_000,
+                    max_quote_qty: 15_000_000,
+                    limit_price: 10 << 32,
+                    side: Side::Bid,
+                    match_limit: 10,
+                    callback_info: bob,
+                    post_only: false,
+                    post_allowed: true,
+                    self_trade_behavior: SelfTradeBehavior::DecrementTake,
+                    max_ts: u64::MAX,
+                    immediate_or_cancel: false,
+                    fill_or_kill: false,
+                    max_display_qty: 0,
+                    post_only_mode: PostOnlyMode::Reject,
+                    max_slippage: 0,
+                    flags: 0,
+                    min_base_qty: 0,
+                    compute_unit_reserve: 0,
+                    resumable: false,
+                    worst_price: 0,
+                    referral: [0; 32],
+                    client_order_id: 0,
+                },
+                &mut event_queue,
+                &mut market_state,
+                u64::MIN,
+            )
+            .unwrap();
+        assert_eq!(total_base_qty, 1_500_000);
+        assert_eq!(event_queue.header.count, 3);
+        assert_eq!(
+            event_queue.peek_at(1).unwrap(),
+            EventRef::Replenish(ReplenishEventRef {
+                event: &ReplenishEvent::new(Side::Ask, 1_000_000, alice_order_id),
+                callback_info: &alice
+            })
+        );
+        let alice_order = orderbook.asks.leaf_nodes[orderbook.asks.find_min().unwrap() as usize];
+        assert_eq!(alice_order.order_id(), alice_order_id);
+        assert_eq!(alice_order.base_quantity(), 500_000);
+        assert_eq!(alice_order.hidden_quantity(), 1_000_000);
+        assert_eq!(alice_order.total_base_quantity(), 1_500_000);
+        assert!(orderbook.asks.verify_checksum());
+    }
+
+    #[test]
+    fn test_ob_post_only_slide() {
+        let mut test_context = TestContext::new(1000, 1000);
+        let (mut orderbook, mut event_queue) = test_context.get();
+        let mut market_state = MarketState {
+            tick_size: 1 << 30,
+            ..test_market_state()
+        };
+        let alice = [1; 32];
+        let bob = [2; 32];
+
+        let post_only_params = |side: Side,
+                                limit_price: u64,
+                                callback_info: [u8; 32],
+                                post_only_mode: PostOnlyMode| {
+            new_order::Params {
+                max_base_qty: 1_000_000,
+                max_quote_qty: u64::MAX,
+                limit_price,
+                side,
+                match_limit: 10,
+                callback_info,
+                post_only: true,
+                post_allowed: true,
+                self_trade_behavior: SelfTradeBehavior::DecrementTake,
+                max_ts: u64::MAX,
+                immediate_or_cancel: false,
+                fill_or_kill: false,
+                max_display_qty: 0,
+                post_only_mode,
+                max_slippage: 0,
+                flags: 0,
+                min_base_qty: 0,
+                compute_unit_reserve: 0,
+                resumable: false,
+                worst_price: 0,
+                referral: [0; 32],
+                client_order_id: 0,
+            }
+        };
+
+        // Alice posts an ask order for 1 BTC at 10 USD/BTC
+        orderbook
+            .new_order(
+                post_only_params(Side::Ask, 10 << 32, alice, PostOnlyMode::Reject),
+                &mut event_queue,
+                &mut market_state,
+                u64::MIN,
+            )
+            .unwrap();
+
+        // Bob's crossing post only bid at 11 USD/BTC is rejected
+        let OrderSummary {
+            posted_order_id, ..
+        } = orderbook
+            .new_order(
+                post_only_params(Side::Bid, 11 << 32, bob, PostOnlyMode::Reject),
+                &mut event_queue,
+                &mut market_state,
+                u64::MIN,
+            )
+            .unwrap();
+        assert!(posted_order_id.is_none());
+
+        // Bob's crossing post only bid at 11 USD/BTC slides to 9.75 USD/BTC
+        let OrderSummary {
+            posted_order_id,
+            total_base_qty_posted,
+            ..
+        } = orderbook
+            .new_order(
+                post_only_params(Side::Bid, 11 << 32, bob, PostOnlyMode::Slide),
+                &mut event_queue,
+                &mut market_state,
+                u64::MIN,
+            )
+            .unwrap();
+        assert_eq!(
+            LeafNode::price_from_key(posted_order_id.unwrap()),
+            (10 << 32) - (1 << 30)
+        );
+        assert_eq!(total_base_qty_posted, 1_000_000);
+        assert_eq!(event_queue.header.count, 0);
+        assert_eq!(orderbook.bids.header.leaf_count, 1);
+        assert_eq!(orderbook.asks.header.leaf_count, 1);
+    }
+
+    #[test]
+    fn test_ob_market_order() {
+        let mut test_context = TestContext::new(1000, 1000);
+        let (mut orderbook, mut event_queue) = test_context.get();
+        let mut market_state = test_market_state();
+        let alice = [1; 32];
+        let bob = [2; 32];
+
+        let params = |side: Side, limit_price: u64, callback_info: [u8; 32], max_slippage: u64| {
+            new_order::Params {
+                max_base_qty: 2_000_000,
+                max_quote_qty: u64::MAX,
+                limit_price,
+                side,
+                match_limit: 10,
+                callback_info,
+                post_only: false,
+                post_allowed: true,
+                self_trade_behavior: SelfTradeBehavior::DecrementTake,
+                max_ts: u64::MAX,
+                immediate_or_cancel: false,
+                fill_or_kill: false,
+                max_display_qty: 0,
+                post_only_mode: PostOnlyMode::Reject,
+                max_slippage,
+                flags: 0,
+                min_base_qty: 0,
+                compute_unit_reserve: 0,
+                resumable: false,
+                worst_price: 0,
+                referral: [0; 32],
+                client_order_id: 0,
+            }
+        };
+
+        // A market order against an empty orderbook does nothing
+        let OrderSummary {
+            posted_order_id,
+            total_base_qty,
+            ..
+        } = orderbook
+            .new_order(
+                params(Side::Ask, 0, bob, u64::MAX),
+                &mut event_queue,
+                &mut market_state,
+                u64::MIN,
+            )
+            .unwrap();
+        assert!(posted_order_id.is_none());
+        assert_eq!(total_base_qty, 0);
+        assert!(orderbook.is_empty());
+
+        // Alice posts ask orders for 2 BTC at 10 USD/BTC and 2 BTC at 12 USD/BTC
+        for price in [10, 12].iter() {
+            orderbook
+                .new_order(
+                    params(Side::Ask, price << 32, alice, 0),
+                    &mut event_queue,
+                    &mut market_state,
+                    u64::MIN,
+                )
+                .unwrap();
+        }
+
+        // Bob's market bid can't go further than 1 USD/BTC above the best ask
+        let OrderSummary {
+            posted_order_id,
+            total_base_qty,
+            total_quote_qty,
+            total_base_qty_unfilled,
+            ..
+        } = orderbook
+            .new_order(
+                params(Side::Bid, 0, bob, 1 << 32),
+                &mut event_queue,
+                &mut market_state,
+                u64::MIN,
+            )
+            .unwrap();
+        assert!(posted_order_id.is_none());
+        assert_eq!(total_base_qty, 2_000_000);
+        assert_eq!(total_quote_qty, 20_000_000);
+        assert_eq!(total_base_qty_unfilled, 0);
+
+        // Bob's unbounded market bid goes through the remaining ask
+        let OrderSummary {
+            total_base_qty,
+            total_quote_qty,
+            ..
+        } = orderbook
+            .new_order(
+                params(Side::Bid, 0, bob, u64::MAX),
+                &mut event_queue,
+                &mut market_state,
+                u64::MIN,
+            )
+            .unwrap();
+        assert_eq!(total_base_qty, 2_000_000);
+        assert_eq!(total_quote_qty, 24_000_000);
+        assert!(orderbook.is_empty());
+    }
+
+    #[test]
+    fn test_ob_stop_orders() {
+        use crate::state::stop_orders::{self, StopOrder, StopOrdersSlab};
+
+        let mut test_context = TestContext::new(1000, 1000);
+        let (mut orderbook, mut event_queue) = test_context.get();
+        let mut market_state = test_market_state();
+        let mut stop_orders_buffer =
+            vec![0; StopOrdersSlab::<[u8; 32]>::compute_allocation_size(10)];
+        stop_orders::initialize(&mut stop_orders_buffer).unwrap();
+        let mut stop_orders_slab = StopOrdersSlab::<[u8; 32]>::from_buffer(
+            &mut stop_orders_buffer,
+            AccountTag::StopOrders,
+        )
+        .unwrap();
+        let alice = [1; 32];
+        let bob = [2; 32];
+        let charlie = [3; 32];
+
+        let params = |side: Side, limit_price: u64, max_base_qty: u64, callback_info: [u8; 32]| {
+            new_order::Params {
+                max_base_qty,
+                max_quote_qty: u64::MAX,
+                limit_price,
+                side,
+                match_limit: 10,
+                callback_info,
+                post_only: false,
+                post_allowed: true,
+                self_trade_behavior: SelfTradeBehavior::DecrementTake,
+                max_ts: u64::MAX,
+                immediate_or_cancel: false,
+                fill_or_kill: false,
+                max_display_qty: 0,
+                post_only_mode: PostOnlyMode::Reject,
+                max_slippage: 0,
+                flags: 0,
+                min_base_qty: 0,
+                compute_unit_reserve: 0,
+                resumable: false,
+                worst_price: 0,
+                referral: [0; 32],
+                client_order_id: 0,
+            }
+        };
+        let stop_order = |callback_info: [u8; 32]| StopOrder {
+            limit_price: 0,
+            max_quote_qty: u64::MAX,
+            match_limit: 10,
+            max_slippage: u64::MAX,
+            self_trade_behavior: SelfTradeBehavior::DecrementTake as u8,
+            flags: 0,
+            callback_info,
+        };
+
+        // Alice posts ask orders for 2 BTC at 10 USD/BTC and 2 BTC at 12 USD/BTC
+        for price in [10, 12].iter() {
+            orderbook
+                .new_order(
+                    params(Side::Ask, price << 32, 2_000_000, alice),
+                    &mut event_queue,
+                    &mut market_state,
+                    u64::MIN,
+                )
+                .unwrap();
+        }
+
+        // Bob places a buy stop market order for 3 BTC triggered at 10 USD/BTC,
+        // and Charlie a sell stop market order for 1 BTC triggered at 8 USD/BTC
+        let bob_stop_id = stop_orders::insert(
+            &mut stop_orders_slab,
+            &mut event_queue,
+            10 << 32,
+            Side::Bid,
+            3_000_000,
+            u64::MAX,
+            stop_order(bob),
+        )
+        .unwrap();
+        stop_orders::insert(
+            &mut stop_orders_slab,
+            &mut event_queue,
+            8 << 32,
+            Side::Ask,
+            1_000_000,
+            u64::MAX,
+            stop_order(charlie),
+        )
+        .unwrap();
+
+        // Nothing is triggered before the first trade
+        let num_triggered = stop_orders::trigger_orders(
+            &mut stop_orders_slab,
+            &mut orderbook,
+            &mut event_queue,
+            &mut market_state,
+            10,
+            u64::MIN,
+        )
+        .unwrap();
+        assert_eq!(num_triggered, 0);
+
+        // Charlie buys 1 BTC at 10 USD/BTC
+        orderbook
+            .new_order(
+                params(Side::Bid, 10 << 32, 1_000_000, charlie),
+                &mut event_queue,
+                &mut market_state,
+                u64::MIN,
+            )
+            .unwrap();
+        assert_eq!(market_state.last_trade_price, 10 << 32);
+        event_queue.pop_n(event_queue.len());
+
+        // Only Bob's stop order is triggered, and it takes all the remaining asks
+        let num_triggered = stop_orders::trigger_orders(
+            &mut stop_orders_slab,
+            &mut orderbook,
+            &mut event_queue,
+            &mut market_state,
+            10,
+            u64::MIN,
+        )
+        .unwrap();
+        assert_eq!(num_triggered, 1);
+        assert_eq!(stop_orders_slab.header.leaf_count, 1);
+        assert_eq!(market_state.last_trade_price, 12 << 32);
+        assert!(orderbook.asks.root().is_none());
+
+        let last_event = event_queue.peek_at(event_queue.len() - 1).unwrap();
+        assert_eq!(
+            last_event,
+            EventRef::Out(OutEventRef {
+                event: &OutEvent::new(Side::Bid, 0, bob_stop_id),
+                callback_info: &bob
+            })
+        );
+
+        // Bob places a buy stop limit order for 2 BTC triggered at 12 USD/BTC with a limit of 13 USD/BTC, which rests
+        // in the orderbook since there is no ask left
+        let bob_stop_id = stop_orders::insert(
+            &mut stop_orders_slab,
+            &mut event_queue,
+            12 << 32,
+            Side::Bid,
+            2_000_000,
+            u64::MAX,
+            StopOrder {
+                limit_price: 13 << 32,
+                ..stop_order(bob)
+            },
+        )
+        .unwrap();
+        let num_triggered = stop_orders::trigger_orders(
+            &mut stop_orders_slab,
+            &mut orderbook,
+            &mut event_queue,
+            &mut market_state,
+            10,
+            u64::MIN,
+        )
+        .unwrap();
+        assert_eq!(num_triggered, 1);
+        let best_bid = orderbook.bids.leaf_nodes[orderbook.bids.find_max().unwrap() as usize];
+        assert_eq!(best_bid.price(), 13 << 32);
+        assert_eq!(best_bid.base_quantity(), 2_000_000);
+        assert_eq!(
+            orderbook.bids.callback_infos[orderbook.bids.find_max().unwrap() as usize],
+            bob
+        );
+        let last_event = event_queue.peek_at(event_queue.len() - 1).unwrap();
+        assert_eq!(
+            last_event,
+            EventRef::Out(OutEventRef {
+                event: &OutEvent::new(Side::Bid, 0, bob_stop_id),
+                callback_info: &bob
+            })
+        );
+
+        // A stop order which can't be executed is removed with its whole quantity unfilled
+        let bob_stop_id = stop_orders::insert(
+            &mut stop_orders_slab,
+            &mut event_queue,
+            12 << 32,
+            Side::Bid,
+            1_000_000,
+            u64::MAX,
+            stop_order(bob),
+        )
+        .unwrap();
+        market_state.pending_taker.active = 1;
+        let num_triggered = stop_orders::trigger_orders(
+            &mut stop_orders_slab,
+            &mut orderbook,
+            &mut event_queue,
+            &mut market_state,
+            10,
+            u64::MIN,
+        )
+        .unwrap();
+        assert_eq!(num_triggered, 1);
+        assert_eq!(stop_orders_slab.header.leaf_count, 1);
+        let last_event = event_queue.peek_at(event_queue.len() - 1).unwrap();
+        assert_eq!(
+            last_event,
+            EventRef::Out(OutEventRef {
+                event: &OutEvent::new(Side::Bid, 1_000_000, bob_stop_id),
+                callback_info: &bob
+            })
+        );
+    }
+
+    #[test]
+    fn test_ob_flags() {
+        let mut test_context = TestContext::new(1000, 1000);
+        let (mut orderbook, mut event_queue) = test_context.get();
+        let mut market_state = test_market_state();
+        let alice = [1; 32];
+        let bob = [2; 32];
+
+        let params = |side: Side, callback_info: [u8; 32], flags: u8| new_order::Params {
+            max_base_qty: 1_000_000,
+            max_quote_qty: u64::MAX,
+            limit_price: 10 << 32,
+            side,
+            match_limit: 10,
+            callback_info,
+            post_only: false,
+            post_allowed: true,
+            self_trade_behavior: SelfTradeBehavior::DecrementTake,
+            max_ts: u64::MAX,
+            immediate_or_cancel: false,
+            fill_or_kill: false,
+            max_display_qty: 0,
+            post_only_mode: PostOnlyMode::Reject,
+            max_slippage: 0,
+            flags,
+            min_base_qty: 0,
+            compute_unit_reserve: 0,
+            resumable: false,
+            worst_price: 0,
+            referral: [0; 32],
+            client_order_id: 0,
+        };
+
+        // Alice posts an ask for 1 BTC at 10 USD/BTC with some flags
+        let alice_summary = orderbook
+            .new_order(
+                params(Side::Ask, alice, 0b10),
+                &mut event_queue,
+                &mut market_state,
+                u64::MIN,
+            )
+            .unwrap();
+        assert_eq!(alice_summary.flags, 0b10);
+
+        // Bob takes it with a reduce-only bid, and both flags travel with the fill
+        let bob_summary = orderbook
+            .new_order(
+                params(Side::Bid, bob, 0b01),
+                &mut event_queue,
+                &mut market_state,
+                u64::MIN,
+            )
+            .unwrap();
+        assert_eq!(bob_summary.flags, 0b01);
+        match event_queue.peek_at(0).unwrap() {
+            EventRef::Fill(FillEventRef { event, .. }) => {
+                assert_eq!(event.taker_flags(), 0b01);
+                assert_eq!(event.maker_flags(), 0b10);
+                assert_eq!(
+                    event.maker_order_id(),
+                    alice_summary.posted_order_id.unwrap()
+                );
+            }
+            _ => panic!("Expected a fill event"),
+        }
+    }
+
+    #[test]
+    fn test_ob_replace_order() {
+        let mut test_context = TestContext::new(1000, 1000);
+        let (mut orderbook, mut event_queue) = test_context.get();
+        let mut market_state = test_market_state();
+        let alice = [1; 32];
+        let bob = [2; 32];
+
+        let params =
+            |limit_price: u64, max_base_qty: u64, callback_info: [u8; 32]| new_order::Params {
+                max_base_qty,
+                max_quote_qty: u64::MAX,
+                limit_price,
+                side: Side::Bid,
+                match_limit: 10,
+                callback_info,
+                post_only: false,
+                post_allowed: true,
+                self_trade_behavior: SelfTradeBehavior::DecrementTake,
+                max_ts: u64::MAX,
+                immediate_or_cancel: false,
+                fill_or_kill: false,
+                max_display_qty: 0,
+                post_only_mode: PostOnlyMode::Reject,
+                max_slippage: 0,
+                flags: 0,
+                min_base_qty: 0,
+                compute_unit_reserve: 0,
+                resumable: false,
+                worst_price: 0,
+                referral: [0; 32],
+                client_order_id: 0,
+            };
+
+        // Alice then Bob bid for 2 BTC at 10 USD/BTC
+        let alice_order_id = orderbook
+            .new_order(
+                params(10 << 32, 2_000_000, alice),
+                &mut event_queue,
+                &mut market_state,
+                u64::MIN,
+            )
+            .unwrap()
+            .posted_order_id
+            .unwrap();
+        orderbook
+            .new_order(
+                params(10 << 32, 2_000_000, bob),
+                &mut event_queue,
+                &mut market_state,
+                u64::MIN,
+            )
+            .unwrap();
+
+        // Alice decreases her order size to 1 BTC and keeps her priority
+        let OrderSummary {
+            posted_order_id,
+            total_base_qty_posted,
+            ..
+        } = orderbook
+            .replace_order(
+                alice_order_id,
+                params(10 << 32, 1_000_000, alice),
+                &mut event_queue,
+                &mut market_state,
+                u64::MIN,
+            )
+            .unwrap();
+        assert_eq!(posted_order_id, Some(alice_order_id));
+        assert_eq!(total_base_qty_posted, 1_000_000);
+        let best_bid = &orderbook.bids.leaf_nodes[orderbook.find_bbo(Side::Bid).unwrap() as usize];
+        assert_eq!(best_bid.order_id(), alice_order_id);
+        assert_eq!(best_bid.base_quantity(), 1_000_000);
+        assert_eq!(
+            event_queue.iter().next().unwrap(),
+            EventRef::Modify(ModifyEventRef {
+                event: &ModifyEvent::new(Side::Bid, alice_order_id, 1_000_000, 1_000_000),
+                callback_info: &alice
+            })
+        );
+        event_queue.pop_n(1);
+
+        // Alice increases her order size back to 2 BTC, and loses her priority
+        let OrderSummary {
+            posted_order_id, ..
+        } = orderbook
+            .replace_order(
+                alice_order_id,
+                params(10 << 32, 2_000_000, alice),
+                &mut event_queue,
+                &mut market_state,
+                u64::MIN,
+            )
+            .unwrap();
+        assert_ne!(posted_order_id, Some(alice_order_id));
+        assert_eq!(orderbook.bids.header.leaf_count, 2);
+        assert!(orderbook.bids.find_by_key(alice_order_id).is_none());
+        assert_ne!(
+            orderbook.bids.leaf_nodes[orderbook.find_bbo(Side::Bid).unwrap() as usize].order_id(),
+            posted_order_id.unwrap()
+        );
+        assert_eq!(
+            event_queue.iter().next().unwrap(),
+            EventRef::Out(OutEventRef {
+                event: &OutEvent::new(Side::Bid, 1_000_000, alice_order_id),
+                callback_info: &alice
+            })
+        );
+        assert!(orderbook.bids.verify_checksum());
+    }
+
+    #[test]
+    fn test_ob_cancel_orders_by_prefix() {
+        let mut test_context = TestContext::new(1000, 1000);
+        let (mut orderbook, mut event_queue) = test_context.get();
+        let mut market_state = test_market_state();
+        let mut alice = [1; 32];
+        let bob = [2; 32];
+
+        let params = |side: Side, limit_price: u64, callback_info: [u8; 32]| new_order::Params {
+            max_base_qty: 1_000_000,
+            max_quote_qty: u64::MAX,
+            limit_price,
+            side,
+            match_limit: 10,
+            callback_info,
+            post_only: false,
+            post_allowed: true,
+            self_trade_behavior: SelfTradeBehavior::DecrementTake,
+            max_ts: u64::MAX,
+            immediate_or_cancel: false,
+            fill_or_kill: false,
+            max_display_qty: 0,
+            post_only_mode: PostOnlyMode::Reject,
+            max_slippage: 0,
+            flags: 0,
+            min_base_qty: 0,
+            compute_unit_reserve: 0,
+            resumable: false,
+            worst_price: 0,
+            referral: [0; 32],
+            client_order_id: 0,
+        };
+
+        // Alice quotes on both sides with different callback information suffixes, Bob posts a bid
+        for (side, price) in [(Side::Bid, 9), (Side::Ask, 11), (Side::Ask, 12)].iter() {
+            alice[31] = *price as u8;
+            orderbook
+                .new_order(
+                    params(*side, price << 32, alice),
+                    &mut event_queue,
+                    &mut market_state,
+                    u64::MIN,
+                )
+                .unwrap();
+        }
+        orderbook
+            .new_order(
+                params(Side::Bid, 8 << 32, bob),
+                &mut event_queue,
+                &mut market_state,
+                u64::MIN,
+            )
+            .unwrap();
+
+        // Alice's orders are cancelled two at a time
+        let summary = orderbook
+            .cancel_orders_by_prefix(&[1; 4], 2, 0, &mut event_queue)
+            .unwrap();
+        assert_eq!(summary.total_base_qty, 2_000_000);
+        assert_eq!(summary.total_quote_qty, 20_000_000);
+        assert_eq!(event_queue.len(), 2);
+        let summary = orderbook
+            .cancel_orders_by_prefix(&[1; 4], 2, 0, &mut event_queue)
+            .unwrap();
+        assert_eq!(summary.total_base_qty, 1_000_000);
+        assert_eq!(event_queue.len(), 3);
+        assert_eq!(orderbook.bids.header.leaf_count, 1);
+        assert_eq!(orderbook.asks.header.leaf_count, 0);
+
+        // A whole callback information is looked up through the owner index
+        let summary = orderbook
+            .cancel_orders_by_prefix(&bob, 10, 0, &mut event_queue)
+            .unwrap();
+        assert_eq!(summary.total_base_qty, 1_000_000);
+        assert_eq!(orderbook.bids.header.leaf_count, 0);
+    }
+
+    #[test]
+    fn test_ob_cancel_order_by_client_id() {
+        let mut test_context = TestContext::new(1000, 1000);
+        let (mut orderbook, mut event_queue) = test_context.get();
+        let mut market_state = test_market_state();
+        let alice = [1; 32];
+        let bob = [2; 32];
+
+        let params =
+            |side: Side, limit_price: u64, callback_info: [u8; 32], client_order_id: u64| {
+                new_order::Params {
+                    max_base_qty: 1_000_000,
+                    max_quote_qty: u64::MAX,
+                    limit_price,
+                    side,
+                    match_limit: 10,
+                    callback_info,
+                    post_only: false,
+                    post_allowed: true,
+                    self_trade_behavior: SelfTradeBehavior::DecrementTake,
+                    max_ts: u64::MAX,
+                    immediate_or_cancel: false,
+                    fill_or_kill: false,
+                    max_display_qty: 0,
+                    post_only_mode: PostOnlyMode::Reject,
+                    max_slippage: 0,
+                    flags: 0,
+                    min_base_qty: 0,
+                    compute_unit_reserve: 0,
+                    resumable: false,
+                    worst_price: 0,
+                    referral: [0; 32],
+                    client_order_id,
+                }
+            };
+
+        // Alice and Bob both use the client order id 7, Alice also posts an order without a client order id
+        for (side, price, callback_info, client_order_id) in [
+            (Side::Bid, 9, alice, 7),
+            (Side::Ask, 11, alice, 0),
+            (Side::Ask, 12, bob, 7),
+        ]
+        .iter()
+        {
+            orderbook
+                .new_order(
+                    params(*side, price << 32, *callback_info, *client_order_id),
+                    &mut event_queue,
+                    &mut market_state,
+                    u64::MIN,
+                )
+                .unwrap();
+        }
+
+        // Alice can't use the client order id 7 twice, on either side
+        for side in [Side::Bid, Side::Ask].iter() {
+            assert!(matches!(
+                orderbook.new_order(
+                    params(*side, 10 << 32, alice, 7),
+                    &mut event_queue,
+                    &mut market_state,
+                    u64::MIN,
+                ),
+                Err(AoError::DuplicateClientOrderId)
+            ));
+        }
+
+        let cancelled_order = orderbook
+            .cancel_order_by_client_id(7, &bob, &market_state)
+            .unwrap();
+        assert_eq!(cancelled_order.price, 12 << 32);
+        assert_eq!(cancelled_order.callback_info, bob);
+        assert!(matches!(
+            orderbook.cancel_order_by_client_id(7, &bob, &market_state),
+            Err(AoError::OrderNotFound)
+        ));
+        assert!(matches!(
+            orderbook.cancel_order_by_client_id(0, &alice, &market_state),
+            Err(AoError::OrderNotFound)
+        ));
+
+        // Replacing an order in place updates its client order id
+        let alice_bid = orderbook.bids.leaf_nodes[orderbook.bids.find_min().unwrap() as usize];
+        orderbook
+            .replace_order(
+                alice_bid.order_id(),
+                params(Side::Bid, 9 << 32, alice, 8),
+                &mut event_queue,
+                &mut market_state,
+                u64::MIN,
+            )
+            .unwrap();
+        assert!(orderbook
+            .cancel_order_by_client_id(7, &alice, &market_state)
+            .is_err());
+
+        // Another order of Alice can't take the client order id of her bid
+        let alice_ask = orderbook.asks.leaf_nodes[orderbook.asks.find_min().unwrap() as usize];
+        assert!(matches!(
+            orderbook.replace_order(
+                alice_ask.order_id(),
+                params(Side::Ask, 11 << 32, alice, 8),
+                &mut event_queue,
+                &mut market_state,
+                u64::MIN,
+            ),
+            Err(AoError::DuplicateClientOrderId)
+        ));
+
+        let cancelled_order = orderbook
+            .cancel_order_by_client_id(8, &alice, &market_state)
+            .unwrap();
+        assert_eq!(cancelled_order.price, 9 << 32);
+        assert_eq!(orderbook.bids.header.leaf_count, 0);
+        assert_eq!(orderbook.asks.header.leaf_count, 1);
+    }
+
+    #[test]
+    fn test_ob_self_trade_cancel_both() {
+        let mut test_context = TestContext::new(1000, 1000);
+        let (mut orderbook, mut event_queue) = test_context.get();
+        let mut market_state = test_market_state();
+        let alice = [1; 32];
+
+        let params = |side: Side, limit_price: u64, self_trade_behavior: SelfTradeBehavior| {
+            new_order::Params {
+                max_base_qty: 1_000_000,
+                max_quote_qty: u64::MAX,
+                limit_price,
+                side,
+                match_limit: 10,
+                callback_info: alice,
+                post_only: false,
+                post_allowed: true,
+                self_trade_behavior,
+                max_ts: u64::MAX,
+                immediate_or_cancel: false,
+                fill_or_kill: false,
+                max_display_qty: 0,
+                post_only_mode: PostOnlyMode::Reject,
+                max_slippage: 0,
+                flags: 0,
+                min_base_qty: 0,
+                compute_unit_reserve: 0,
+                resumable: false,
+                worst_price: 0,
+                referral: [0; 32],
+                client_order_id: 0,
+            }
+        };
+
+        // Alice posts asks for 1 BTC at 10 USD/BTC and 1 BTC at 11 USD/BTC
+        let mut ask_ids = vec![];
+        for price in [10, 11].iter() {
+            let summary = orderbook
+                .new_order(
+                    params(Side::Ask, price << 32, SelfTradeBehavior::DecrementTake),
+                    &mut event_queue,
+                    &mut market_state,
+                    u64::MIN,
+                )
+                .unwrap();
+            ask_ids.push(summary.posted_order_id.unwrap());
+        }
+
+        // Alice's crossing bid is cancelled without touching her asks
+        let summary = orderbook
+            .new_order(
+                params(Side::Bid, 11 << 32, SelfTradeBehavior::CancelLowestPriority),
+                &mut event_queue,
+                &mut market_state,
+                u64::MIN,
+            )
+            .unwrap();
+        assert!(summary.posted_order_id.is_none());
+        assert_eq!(summary.total_base_qty, 0);
+        assert_eq!(summary.total_base_qty_unfilled, 1_000_000);
+        assert_eq!(orderbook.asks.header.leaf_count, 2);
+        assert_eq!(orderbook.bids.header.leaf_count, 0);
+        assert_eq!(event_queue.len(), 0);
+
+        // Alice's crossing bid cancels her best ask, and its remainder is cancelled as well
+        let summary = orderbook
+            .new_order(
+                params(Side::Bid, 11 << 32, SelfTradeBehavior::CancelBoth),
+                &mut event_queue,
+                &mut market_state,
+                u64::MIN,
+            )
+            .unwrap();
+        assert!(summary.posted_order_id.is_none());
+        assert_eq!(summary.total_base_qty, 0);
+        assert_eq!(summary.total_base_qty_unfilled, 1_000_000);
+        assert_eq!(orderbook.asks.header.leaf_count, 1);
+        assert!(orderbook.asks.find_by_key(ask_ids[1]).is_some());
+        assert_eq!(orderbook.bids.header.leaf_count, 0);
+        assert_eq!(
+            event_queue.iter().next().unwrap(),
+            EventRef::Out(OutEventRef {
+                event: &OutEvent::new(Side::Ask, 1_000_000, ask_ids[0]),
+                callback_info: &alice
+            })
+        );
+        assert_eq!(event_queue.len(), 1);
+    }
+
+    #[test]
+    fn test_ob_min_base_qty() {
+        let mut test_context = TestContext::new(1000, 1000);
+        let (mut orderbook, mut event_queue) = test_context.get();
+        let mut market_state = test_market_state();
+        let alice = [1; 32];
+        let bob = [2; 32];
+
+        let params = |side: Side, max_base_qty: u64, callback_info: [u8; 32], min_base_qty: u64| {
+            new_order::Params {
+                max_base_qty,
+                max_quote_qty: u64::MAX,
+                limit_price: 10 << 32,
+                side,
+                match_limit: 10,
+                callback_info,
+                post_only: false,
+                post_allowed: true,
+                self_trade_behavior: SelfTradeBehavior::DecrementTake,
+                max_ts: u64::MAX,
+                immediate_or_cancel: false,
+                fill_or_kill: false,
+                max_display_qty: 0,
+                post_only_mode: PostOnlyMode::Reject,
+                max_slippage: 0,
+                flags: 0,
+                min_base_qty,
+                compute_unit_reserve: 0,
+                resumable: false,
+                worst_price: 0,
+                referral: [0; 32],
+                client_order_id: 0,
+            }
+        };
+
+        // Alice posts an ask for 1 BTC at 10 USD/BTC
+        orderbook
+            .new_order(
+                params(Side::Ask, 1_000_000, alice, 0),
+                &mut event_queue,
+                &mut market_state,
+                u64::MIN,
+            )
+            .unwrap();
+
+        // Bob's bid requires at least 1.5 BTC to be matched, and fails without modifying the orderbook
+        let r = orderbook.new_order(
+            params(Side::Bid, 2_000_000, bob, 1_500_000),
+            &mut event_queue,
+            &mut market_state,
+            u64::MIN,
+        );
+        assert!(matches!(r, Err(AoError::UnableToFill)));
+        assert_eq!(event_queue.len(), 0);
+        assert_eq!(orderbook.asks.header.leaf_count, 1);
+        assert_eq!(orderbook.bids.header.leaf_count, 0);
+
+        // Bob's bid requires at least 1 BTC to be matched, and goes through
+        let summary = orderbook
+            .new_order(
+                params(Side::Bid, 2_000_000, bob, 1_000_000),
+                &mut event_queue,
+                &mut market_state,
+                u64::MIN,
+            )
+            .unwrap();
+        assert_eq!(summary.total_base_qty, 2_000_000);
+        assert_eq!(summary.total_base_qty_posted, 1_000_000);
+        assert!(orderbook.asks.root().is_none());
+    }
+
+    #[test]
+    fn test_ob_pegged_orders() {
+        use crate::state::{
+            pegged_orders::{self, PeggedOrdersSlab},
+            PegReference,
+        };
+
+        let mut test_context = TestContext::new(1000, 1000);
+        let (mut orderbook, mut event_queue) = test_context.get();
+        let mut market_state = test_market_state();
+        let mut pegged_orders_buffer = vec![0; PeggedOrdersSlab::compute_allocation_size(10)];
+        pegged_orders::initialize(&mut pegged_orders_buffer).unwrap();
+        let mut pegged_orders_slab =
+            PeggedOrdersSlab::from_buffer(&mut pegged_orders_buffer, AccountTag::PeggedOrders)
+                .unwrap();
+        let alice = [1; 32];
+        let bob = [2; 32];
+        let charlie = [3; 32];
+
+        let params = |side: Side, limit_price: u64, callback_info: [u8; 32], post_only: bool| {
+            new_order::Params {
+                max_base_qty: 1_000_000,
+                max_quote_qty: u64::MAX,
+                limit_price,
+                side,
+                match_limit: 10,
+                callback_info,
+                post_only,
+                post_allowed: true,
+                self_trade_behavior: SelfTradeBehavior::DecrementTake,
+                max_ts: u64::MAX,
+                immediate_or_cancel: false,
+                fill_or_kill: false,
+                max_display_qty: 0,
+                post_only_mode: PostOnlyMode::Slide,
+                max_slippage: 0,
+                flags: 0,
+                min_base_qty: 0,
+                compute_unit_reserve: 0,
+                resumable: false,
+                worst_price: 0,
+                referral: [0; 32],
+                client_order_id: 0,
+            }
+        };
+
+        // There is no reference price on an empty orderbook
+        assert!(pegged_orders::get_peg_price(
+            &orderbook,
+            Side::Bid,
+            PegReference::OppositeSide,
+            -(1 << 32),
+            market_state.tick_size
+        )
+        .is_none());
+
+        // Alice posts an ask for 1 BTC at 12 USD/BTC
+        let alice_order_id = orderbook
+            .new_order(
+                params(Side::Ask, 12 << 32, alice, false),
+                &mut event_queue,
+                &mut market_state,
+                u64::MIN,
+            )
+            .unwrap()
+            .posted_order_id
+            .unwrap();
+
+        // Bob posts a bid pegged 1 USD/BTC below the best ask
+        let limit_price = pegged_orders::get_peg_price(
+            &orderbook,
+            Side::Bid,
+            PegReference::OppositeSide,
+            -(1 << 32),
+            market_state.tick_size,
+        )
+        .unwrap();
+        assert_eq!(limit_price, 11 << 32);
+        let bob_pegged_id = orderbook
+            .new_order(
+                params(Side::Bid, limit_price, bob, true),
+                &mut event_queue,
+                &mut market_state,
+                u64::MIN,
+            )
+            .unwrap()
+            .posted_order_id
+            .unwrap();
+        pegged_orders::insert(
+            &mut pegged_orders_slab,
+            bob_pegged_id,
+            u64::MAX,
+            PegReference::OppositeSide,
+            -(1 << 32),
+        )
+        .unwrap();
+
+        // Nothing moves while the best ask stays in place
+        let (num_repriced, next_start_key) = pegged_orders::reprice(
+            &mut pegged_orders_slab,
+            &mut orderbook,
+            &mut event_queue,
+            &mut market_state,
+            10,
+            0,
+            u64::MIN,
+        )
+        .unwrap();
+        assert_eq!((num_repriced, next_start_key), (1, None));
+        assert!(orderbook.bids.find_by_key(bob_pegged_id).is_some());
+
+        // Alice moves her ask to 14 USD/BTC, and Bob's bid follows it to 13 USD/BTC without pushing any event
+        orderbook.asks.remove_by_key(alice_order_id).unwrap();
+        orderbook
+            .new_order(
+                params(Side::Ask, 14 << 32, alice, false),
+                &mut event_queue,
+                &mut market_state,
+                u64::MIN,
+            )
+            .unwrap();
+        pegged_orders::reprice(
+            &mut pegged_orders_slab,
+            &mut orderbook,
+            &mut event_queue,
+            &mut market_state,
+            10,
+            0,
+            u64::MIN,
+        )
+        .unwrap();
+        assert_eq!(event_queue.len(), 0);
+        assert_eq!(orderbook.bids.header.leaf_count, 1);
+        let best_bid = orderbook.bids.leaf_nodes[orderbook.bids.find_max().unwrap() as usize];
+        assert_eq!(best_bid.price(), 13 << 32);
+        assert_eq!(best_bid.base_quantity(), 1_000_000);
+        assert_eq!(
+            orderbook.bids.callback_infos[orderbook.bids.find_max().unwrap() as usize],
+            bob
+        );
+        let pegged_handle = pegged_orders_slab.find_by_key(bob_pegged_id).unwrap();
+        let book_order_id = pegged_orders_slab.get_callback_info(pegged_handle).order_id;
+        assert_eq!(book_order_id, best_bid.order_id());
+
+        // Charlie's ask fills Bob's bid, which is forgotten on the next reprice
+        orderbook
+            .new_order(
+                params(Side::Ask, 13 << 32, charlie, false),
+                &mut event_queue,
+                &mut market_state,
+                u64::MIN,
+            )
+            .unwrap();
+        assert!(orderbook.bids.root().is_none());
+        pegged_orders::reprice(
+            &mut pegged_orders_slab,
+            &mut orderbook,
+            &mut event_queue,
+            &mut market_state,
+            10,
+            0,
+            u64::MIN,
+        )
+        .unwrap();
+        assert!(pegged_orders_slab.root().is_none());
+
+        // Bob posts three pegged bids, which are repriced two at a time
+        let mut pegged_ids = vec![];
+        for ticks in 1..4 {
+            let offset = -(ticks << 32);
+            let limit_price = pegged_orders::get_peg_price(
+                &orderbook,
+                Side::Bid,
+                PegReference::OppositeSide,
+                offset,
+                market_state.tick_size,
+            )
+            .unwrap();
+            let pegged_id = orderbook
+                .new_order(
+                    params(Side::Bid, limit_price, bob, true),
+                    &mut event_queue,
+                    &mut market_state,
+                    u64::MIN,
                 )
-                .unwrap(),
+                .unwrap()
+                .posted_order_id
+                .unwrap();
+            pegged_orders::insert(
+                &mut pegged_orders_slab,
+                pegged_id,
+                u64::MAX,
+                PegReference::OppositeSide,
+                offset,
             )
+            .unwrap();
+            pegged_ids.push(pegged_id);
         }
+        pegged_ids.sort_unstable();
+        assert_eq!(
+            pegged_orders::reprice(
+                &mut pegged_orders_slab,
+                &mut orderbook,
+                &mut event_queue,
+                &mut market_state,
+                2,
+                0,
+                u64::MIN,
+            )
+            .unwrap(),
+            (2, Some(pegged_ids[2]))
+        );
+        assert_eq!(
+            pegged_orders::reprice(
+                &mut pegged_orders_slab,
+                &mut orderbook,
+                &mut event_queue,
+                &mut market_state,
+                2,
+                pegged_ids[2],
+                u64::MIN,
+            )
+            .unwrap(),
+            (1, None)
+        );
+
+        // The bids can't be posted again while a resumable order is being matched, so they are taken out of the
+        // orderbook
+        let alice_order_id =
+            orderbook.asks.leaf_nodes[orderbook.asks.find_min().unwrap() as usize].order_id();
+        orderbook.asks.remove_by_key(alice_order_id).unwrap();
+        orderbook
+            .new_order(
+                params(Side::Ask, 15 << 32, alice, false),
+                &mut event_queue,
+                &mut market_state,
+                u64::MIN,
+            )
+            .unwrap();
+        let events_before = event_queue.len();
+        market_state.pending_taker.active = 1;
+        assert_eq!(
+            pegged_orders::reprice(
+                &mut pegged_orders_slab,
+                &mut orderbook,
+                &mut event_queue,
+                &mut market_state,
+                10,
+                0,
+                u64::MIN,
+            )
+            .unwrap(),
+            (3, None)
+        );
+        assert_eq!(event_queue.len(), events_before + 3);
+        assert!(orderbook.bids.root().is_none());
+        assert!(pegged_orders_slab.root().is_none());
     }
 
     #[test]
-    fn test_ob_0() {
+    fn test_ob_tick_size() {
         let mut test_context = TestContext::new(1000, 1000);
         let (mut orderbook, mut event_queue) = test_context.get();
+        let mut market_state = test_market_state();
+        market_state.tick_size = 1 << 31;
         let alice = [1; 32];
-        let bob = [2; 32];
+
+        let params = |limit_price: u64| new_order::Params {
+            max_base_qty: 1_000_000,
+            max_quote_qty: u64::MAX,
+            limit_price,
+            side: Side::Ask,
+            match_limit: 10,
+            callback_info: alice,
+            post_only: false,
+            post_allowed: true,
+            self_trade_behavior: SelfTradeBehavior::DecrementTake,
+            max_ts: u64::MAX,
+            immediate_or_cancel: false,
+            fill_or_kill: false,
+            max_display_qty: 0,
+            post_only_mode: PostOnlyMode::Reject,
+            max_slippage: 0,
+            flags: 0,
+            min_base_qty: 0,
+            compute_unit_reserve: 0,
+            resumable: false,
+            worst_price: 0,
+            referral: [0; 32],
+            client_order_id: 0,
+        };
+
+        // 10.25 USD/BTC isn't a multiple of the 0.5 USD/BTC tick
+        let r = orderbook.new_order(
+            params((10 << 32) + (1 << 30)),
+            &mut event_queue,
+            &mut market_state,
+            u64::MIN,
+        );
+        assert!(matches!(r, Err(AoError::InvalidTickSize)));
+        assert!(orderbook.asks.root().is_none());
+
+        // 10.5 USD/BTC is
         orderbook
             .new_order(
-                new_order::Params {
-                    max_base_qty: 10,
-                    max_quote_qty: 10,
-                    limit_price: 10 << 32,
-                    side: Side::Ask,
-                    match_limit: 0,
-                    callback_info: [0; 32],
-                    post_only: false,
-                    post_allowed: false,
-                    self_trade_behavior: SelfTradeBehavior::DecrementTake,
-                    max_ts: u64::MAX,
-                },
+                params((10 << 32) + (1 << 31)),
                 &mut event_queue,
-                10,
+                &mut market_state,
                 u64::MIN,
             )
             .unwrap();
-        assert!(event_queue.header.count == 0);
+        assert_eq!(orderbook.asks.header.leaf_count, 1);
+    }
 
-        // Alice posts a bid order for 1 BTC at 10 USD/BTC
+    #[test]
+    fn test_ob_min_quote_order_size() {
+        let mut test_context = TestContext::new(1000, 1000);
+        let (mut orderbook, mut event_queue) = test_context.get();
+        let mut market_state = MarketState {
+            min_quote_order_size: 5_000_000,
+            ..test_market_state()
+        };
+        let alice = [1; 32];
+        let bob = [2; 32];
 
-        let OrderSummary {
-            posted_order_id,
-            total_base_qty,
-            total_quote_qty,
-            total_base_qty_posted,
-        } = orderbook
+        let params = |side: Side, max_base_qty: u64, callback_info: [u8; 32]| new_order::Params {
+            max_base_qty,
+            max_quote_qty: u64::MAX,
+            limit_price: 10 << 32,
+            side,
+            match_limit: 10,
+            callback_info,
+            post_only: false,
+            post_allowed: true,
+            self_trade_behavior: SelfTradeBehavior::DecrementTake,
+            max_ts: u64::MAX,
+            immediate_or_cancel: false,
+            fill_or_kill: false,
+            max_display_qty: 0,
+            post_only_mode: PostOnlyMode::Reject,
+            max_slippage: 0,
+            flags: 0,
+            min_base_qty: 0,
+            compute_unit_reserve: 0,
+            resumable: false,
+            worst_price: 0,
+            referral: [0; 32],
+            client_order_id: 0,
+        };
+
+        // Alice's ask for 0.4 BTC at 10 USD/BTC is worth less than 5 USD and isn't posted
+        let summary = orderbook
             .new_order(
-                new_order::Params {
-                    max_base_qty: 2_000_000,
-                    max_quote_qty: 10_000_000,
-                    limit_price: 10 << 32,
-                    side: Side::Bid,
-                    match_limit: 10,
-                    callback_info: alice,
-                    post_only: false,
-                    post_allowed: true,
-                    self_trade_behavior: SelfTradeBehavior::DecrementTake,
-                    max_ts: u64::MAX,
-                },
+                params(Side::Ask, 400_000, alice),
                 &mut event_queue,
-                10,
+                &mut market_state,
                 u64::MIN,
             )
             .unwrap();
-        assert!(posted_order_id.is_some());
-        assert_eq!(total_base_qty, 1_000_000);
-        assert_eq!(total_quote_qty, 10_000_000);
-        assert_eq!(total_base_qty_posted, 1_000_000);
+        assert!(summary.posted_order_id.is_none());
+        assert_eq!(summary.total_base_qty_unfilled, 400_000);
+        assert!(orderbook.asks.root().is_none());
 
-        // Bob posts an ask order for 3 BTC at 20 USD/BTC
-        let OrderSummary {
-            posted_order_id,
-            total_base_qty,
-            total_quote_qty,
-            total_base_qty_posted,
-        } = orderbook
+        // Alice's ask for 1 BTC at 10 USD/BTC is posted
+        orderbook
             .new_order(
-                new_order::Params {
-                    max_base_qty: 3_000_000,
-                    max_quote_qty: 1_000_000_000,
-                    limit_price: 20 << 32,
-                    side: Side::Ask,
-                    match_limit: 10,
-                    callback_info: bob,
-                    post_only: false,
-                    post_allowed: true,
-                    self_trade_behavior: SelfTradeBehavior::DecrementTake,
-                    max_ts: u64::MAX,
-                },
+                params(Side::Ask, 1_000_000, alice),
                 &mut event_queue,
-                10,
+                &mut market_state,
                 u64::MIN,
             )
             .unwrap();
-        assert!(posted_order_id.is_some());
-        assert_eq!(total_base_qty, 3_000_000);
-        assert_eq!(total_quote_qty, 60_000_000);
-        assert_eq!(total_base_qty_posted, 3_000_000);
 
-        // Bob posts a bid order for 0.5 BTC at 15 USD/BTC
-        let OrderSummary {
-            posted_order_id: bob_order_id_0,
-            total_base_qty,
-            total_quote_qty,
-            total_base_qty_posted,
-        } = orderbook
+        // Bob's bid for 1.3 BTC is partially matched, and its 0.3 BTC residual isn't posted
+        let summary = orderbook
             .new_order(
-                new_order::Params {
-                    max_base_qty: 500_000,
-                    max_quote_qty: 1_000_000_000,
-                    limit_price: 15 << 32,
-                    side: Side::Bid,
-                    match_limit: 10,
-                    callback_info: bob,
-                    post_only: false,
-                    post_allowed: true,
-                    self_trade_behavior: SelfTradeBehavior::DecrementTake,
-                    max_ts: u64::MAX,
-                },
+                params(Side::Bid, 1_300_000, bob),
                 &mut event_queue,
-                10,
+                &mut market_state,
                 u64::MIN,
             )
             .unwrap();
-        assert!(posted_order_id.is_some());
-        assert_eq!(total_base_qty, 500_000);
-        assert_eq!(total_quote_qty, 7_500_000);
-        assert_eq!(total_base_qty_posted, 500_000);
-        assert_eq!(orderbook.get_spread(), (Some(15 << 32), Some(20 << 32)));
+        assert_eq!(summary.total_base_qty, 1_000_000);
+        assert_eq!(summary.total_base_qty_posted, 0);
+        assert_eq!(summary.total_base_qty_unfilled, 300_000);
+        assert!(orderbook.bids.root().is_none());
+    }
 
-        // Alice posts an ask order for 0.75 BTC at 14 USD/BTC, and is partially matched
-        let OrderSummary {
-            posted_order_id: alice_order_id_0,
-            total_base_qty,
-            total_quote_qty,
-            total_base_qty_posted,
-        } = orderbook
-            .new_order(
-                new_order::Params {
-                    max_base_qty: 750_000,
-                    max_quote_qty: 1_000_000_000,
-                    limit_price: 14 << 32,
-                    side: Side::Ask,
-                    match_limit: 10,
-                    callback_info: alice,
-                    post_only: false,
-                    post_allowed: true,
-                    self_trade_behavior: SelfTradeBehavior::DecrementTake,
-                    max_ts: u64::MAX,
-                },
-                &mut event_queue,
-                10,
-                u64::MIN,
-            )
+    #[test]
+    fn test_ob_clean_book() {
+        let mut test_context = TestContext::new(1000, 1000);
+        let (mut orderbook, mut event_queue) = test_context.get();
+        let mut market_state = test_market_state();
+        let alice = [1; 32];
+
+        let params = |side: Side, limit_price: u64| new_order::Params {
+            max_base_qty: 1_000_000,
+            max_quote_qty: u64::MAX,
+            limit_price,
+            side,
+            match_limit: 10,
+            callback_info: alice,
+            post_only: false,
+            post_allowed: true,
+            self_trade_behavior: SelfTradeBehavior::DecrementTake,
+            max_ts: u64::MAX,
+            immediate_or_cancel: false,
+            fill_or_kill: false,
+            max_display_qty: 0,
+            post_only_mode: PostOnlyMode::Reject,
+            max_slippage: 0,
+            flags: 0,
+            min_base_qty: 0,
+            compute_unit_reserve: 0,
+            resumable: false,
+            worst_price: 0,
+            referral: [0; 32],
+            client_order_id: 0,
+        };
+
+        // Alice posts a bid at 9 USD/BTC and asks at 11 and 12 USD/BTC
+        for (side, price) in [(Side::Bid, 9), (Side::Ask, 11), (Side::Ask, 12)].iter() {
+            orderbook
+                .new_order(
+                    params(*side, price << 32),
+                    &mut event_queue,
+                    &mut market_state,
+                    u64::MIN,
+                )
+                .unwrap();
+        }
+
+        // The bid and the ask at 12 USD/BTC end up below the minimum order size
+        let h = orderbook.bids.find_max().unwrap();
+        orderbook.bids.leaf_nodes[h as usize].set_base_quantity(0);
+        let h = orderbook.asks.find_max().unwrap();
+        orderbook.asks.leaf_nodes[h as usize].set_base_quantity(5);
+        let dust_ask_id = orderbook.asks.leaf_nodes[h as usize].order_id();
+
+        let num_cleaned = orderbook
+            .clean_book(10, &market_state, &mut event_queue)
             .unwrap();
-        assert!(posted_order_id.is_some());
-        assert_eq!(total_base_qty, 750_000);
-        assert_eq!(total_quote_qty, 500_000 * 15 + 250_000 * 14);
-        assert_eq!(total_base_qty_posted, 250_000);
-        assert_eq!(orderbook.get_spread(), (Some(10 << 32), Some(14 << 32)));
+        assert_eq!(num_cleaned, 2);
+        assert!(orderbook.bids.root().is_none());
+        assert_eq!(orderbook.asks.header.leaf_count, 1);
+        assert_eq!(event_queue.len(), 2);
 
-        assert_eq!(event_queue.header.count, 2);
-        let mut event_queue_iter = event_queue.iter();
         assert_eq!(
-            event_queue_iter.next().unwrap(),
-            EventRef::Fill(FillEventRef {
-                event: &FillEvent::new(Side::Ask, 500_000 * 15, bob_order_id_0.unwrap(), 500_000),
-                maker_callback_info: &bob,
-                taker_callback_info: &alice
+            event_queue.iter().nth(1).unwrap(),
+            EventRef::Out(OutEventRef {
+                event: &OutEvent::new(Side::Ask, 5, dust_ask_id),
+                callback_info: &alice
+            })
+        );
+    }
+
+    #[test]
+    fn test_ob_fix_crossed_book() {
+        let mut test_context = TestContext::new(1000, 1000);
+        let (mut orderbook, mut event_queue) = test_context.get();
+        let mut market_state = test_market_state();
+        let alice = [1; 32];
+        let bob = [2; 32];
+
+        let params = |side: Side, limit_price: u64| new_order::Params {
+            max_base_qty: 1_000_000,
+            max_quote_qty: u64::MAX,
+            limit_price,
+            side,
+            match_limit: 10,
+            callback_info: alice,
+            post_only: false,
+            post_allowed: true,
+            self_trade_behavior: SelfTradeBehavior::DecrementTake,
+            max_ts: u64::MAX,
+            immediate_or_cancel: false,
+            fill_or_kill: false,
+            max_display_qty: 0,
+            post_only_mode: PostOnlyMode::Reject,
+            max_slippage: 0,
+            flags: 0,
+            min_base_qty: 0,
+            compute_unit_reserve: 0,
+            resumable: false,
+            worst_price: 0,
+            referral: [0; 32],
+            client_order_id: 0,
+        };
+
+        // Alice posts bids at 9 and 10 USD/BTC and an ask at 11 USD/BTC
+        for (side, price) in [(Side::Bid, 9), (Side::Bid, 10), (Side::Ask, 11)].iter() {
+            orderbook
+                .new_order(
+                    params(*side, price << 32),
+                    &mut event_queue,
+                    &mut market_state,
+                    u64::MIN,
+                )
+                .unwrap();
+        }
+        assert!(!orderbook.is_crossed());
+
+        // Bob's ask at 9 USD/BTC and bid at 12 USD/BTC somehow end up in the orderbook without being matched
+        let crossed_ask_id: u128 = OrderId::new(9 << 32, 3, Side::Ask).into();
+        let crossed_bid_id: u128 = OrderId::new(12 << 32, 4, Side::Bid).into();
+        orderbook
+            .asks
+            .insert_leaf(&LeafNode::new(crossed_ask_id, 1_000, u64::MAX), &bob)
+            .unwrap();
+        orderbook
+            .bids
+            .insert_leaf(&LeafNode::new(crossed_bid_id, 2_000, u64::MAX), &bob)
+            .unwrap();
+        assert!(orderbook.is_crossed());
+        event_queue.pop_n(event_queue.len());
+
+        // The bid at 12 USD/BTC is more recent than the ask at 9 USD/BTC, which is more recent than the bid at 10 USD/BTC
+        assert_eq!(orderbook.fix_crossed_book(1, &mut event_queue).unwrap(), 1);
+        assert!(orderbook.is_crossed());
+        assert_eq!(orderbook.fix_crossed_book(10, &mut event_queue).unwrap(), 1);
+        assert!(!orderbook.is_crossed());
+        assert_eq!(orderbook.get_spread(), (Some(10 << 32), Some(11 << 32)));
+
+        let mut events = event_queue.iter();
+        assert_eq!(
+            events.next().unwrap(),
+            EventRef::Out(OutEventRef {
+                event: &OutEvent::new(Side::Bid, 2_000, crossed_bid_id),
+                callback_info: &bob
+            })
+        );
+        assert_eq!(
+            events.next().unwrap(),
+            EventRef::Out(OutEventRef {
+                event: &OutEvent::new(Side::Ask, 1_000, crossed_ask_id),
+                callback_info: &bob
+            })
+        );
+        assert!(events.next().is_none());
+    }
+
+    #[test]
+    fn test_ob_auction() {
+        let mut test_context = TestContext::new(1000, 1000);
+        let (mut orderbook, mut event_queue) = test_context.get();
+        let mut market_state = test_market_state();
+        market_state.matching_mode = MatchingMode::Auction as u64;
+        let alice = [1; 32];
+        let bob = [2; 32];
+
+        let params = |side: Side, max_base_qty: u64, limit_price: u64| new_order::Params {
+            max_base_qty,
+            max_quote_qty: u64::MAX,
+            limit_price,
+            side,
+            match_limit: 10,
+            callback_info: if side == Side::Bid { alice } else { bob },
+            post_only: false,
+            post_allowed: true,
+            self_trade_behavior: SelfTradeBehavior::DecrementTake,
+            max_ts: u64::MAX,
+            immediate_or_cancel: false,
+            fill_or_kill: false,
+            max_display_qty: 0,
+            post_only_mode: PostOnlyMode::Reject,
+            max_slippage: 0,
+            flags: 0,
+            min_base_qty: 0,
+            compute_unit_reserve: 0,
+            resumable: false,
+            worst_price: 0,
+            referral: [0; 32],
+            client_order_id: 0,
+        };
+
+        // Alice bids at 12, 11 and 10 USD/BTC, and Bob asks at 9, 10 and 11 USD/BTC. Nothing is matched.
+        let orders = [
+            (Side::Bid, 300, 12),
+            (Side::Bid, 300, 11),
+            (Side::Bid, 400, 10),
+            (Side::Ask, 200, 9),
+            (Side::Ask, 300, 10),
+            (Side::Ask, 500, 11),
+        ];
+        let mut order_ids = vec![];
+        for (side, max_base_qty, price) in orders.iter() {
+            let order_summary = orderbook
+                .new_order(
+                    params(*side, *max_base_qty, price << 32),
+                    &mut event_queue,
+                    &mut market_state,
+                    u64::MIN,
+                )
+                .unwrap();
+            assert_eq!(order_summary.total_base_qty_posted, *max_base_qty);
+            order_ids.push(order_summary.posted_order_id.unwrap());
+        }
+        assert!(orderbook.is_crossed());
+        assert_eq!(event_queue.len(), 0);
+
+        // Orders which can't rest in the orderbook are rejected, since they would never be matched
+        let rejected_orders = [
+            // A fill or kill order
+            new_order::Params {
+                fill_or_kill: true,
+                ..params(Side::Bid, 100, 12 << 32)
+            },
+            // An immediate or cancel order
+            new_order::Params {
+                immediate_or_cancel: true,
+                ..params(Side::Bid, 100, 12 << 32)
+            },
+            // A market order
+            params(Side::Bid, 100, 0),
+            // An order which must fill a minimum quantity
+            new_order::Params {
+                min_base_qty: 50,
+                ..params(Side::Bid, 100, 12 << 32)
+            },
+        ];
+        for order in rejected_orders.iter() {
+            let r = orderbook
+                .new_order(order.clone(), &mut event_queue, &mut market_state, u64::MIN)
+                .unwrap_err();
+            assert!(matches!(r, AoError::OperationNotAllowed));
+        }
+        assert_eq!(orderbook.bids.len(), 3);
+
+        // 600 BTC are demanded and 1000 BTC are supplied at 11 USD/BTC, which maximizes the matched quantity
+        assert_eq!(
+            orderbook.compute_clearing_price(u64::MIN),
+            Some((11 << 32, 600))
+        );
+        let clearing_price = orderbook
+            .run_auction(10, &mut market_state, &mut event_queue, u64::MIN)
+            .unwrap();
+        assert_eq!(clearing_price, Some(11 << 32));
+        assert!(!orderbook.is_crossed());
+        assert_eq!(orderbook.get_spread(), (Some(10 << 32), Some(11 << 32)));
+        assert_eq!(market_state.last_trade_price, 11 << 32);
+        assert_eq!(market_state.cumulative_base_volume, 600);
+        assert_eq!(market_state.cumulative_quote_volume, 6_600);
+
+        // Each match pushes a maker fill event for both orders, followed by Out events for the filled orders
+        assert_eq!(event_queue.len(), 12);
+        let mut events = event_queue.iter();
+        assert_eq!(
+            events.next().unwrap(),
+            EventRef::MakerFill(MakerFillEventRef {
+                event: &MakerFillEvent::new(FillEvent::new(
+                    Side::Ask,
+                    2_200,
+                    order_ids[0],
+                    200,
+                    0,
+                    0
+                )),
+                callback_info: &alice
+            })
+        );
+        assert_eq!(
+            events.next().unwrap(),
+            EventRef::MakerFill(MakerFillEventRef {
+                event: &MakerFillEvent::new(FillEvent::new(
+                    Side::Bid,
+                    2_200,
+                    order_ids[3],
+                    200,
+                    0,
+                    0
+                )),
+                callback_info: &bob
             })
         );
-
         assert_eq!(
-            event_queue_iter.next().unwrap(),
+            events.next().unwrap(),
             EventRef::Out(OutEventRef {
-                event: &OutEvent::new(Side::Bid, 0, bob_order_id_0.unwrap(),),
+                event: &OutEvent::new(Side::Ask, 0, order_ids[3]),
                 callback_info: &bob
             })
         );
-        println!("Event queue head: {}", event_queue.header.head);
-        event_queue.pop_n(2);
-        println!("Event queue head: {}", event_queue.header.head);
 
-        assert_eq!(event_queue.header.count, 0);
+        // The orderbook is no longer crossed
+        assert_eq!(orderbook.compute_clearing_price(u64::MIN), None);
+        assert_eq!(
+            orderbook
+                .run_auction(10, &mut market_state, &mut event_queue, u64::MIN)
+                .unwrap(),
+            None
+        );
 
-        // Alice makes a bid for 0.05 BTC at 15 USD/BTC, and attempts to self-trade
-        let r = orderbook
-            .new_order(
-                new_order::Params {
-                    max_base_qty: 50_000,
-                    max_quote_qty: 1_000_000_000,
-                    limit_price: 15 << 32,
-                    side: Side::Bid,
-                    match_limit: 10,
-                    callback_info: alice,
-                    post_only: false,
-                    post_allowed: true,
-                    self_trade_behavior: SelfTradeBehavior::AbortTransaction,
-                    max_ts: u64::MAX,
-                },
-                &mut event_queue,
-                10,
-                u64::MIN,
-            )
-            .unwrap_err();
-        assert!(matches!(r, AoError::WouldSelfTrade));
-        println!("Event queue head: {}", event_queue.header.head);
+        // The checksums follow the partially matched orders
+        assert!(orderbook.bids.verify_checksum() && orderbook.asks.verify_checksum());
+    }
 
-        assert_eq!(event_queue.header.count, 0);
+    #[test]
+    fn test_ob_resumable_order() {
+        let mut test_context = TestContext::new(1000, 1000);
+        let (mut orderbook, mut event_queue) = test_context.get();
+        let mut market_state = test_market_state();
+        let alice = [1; 32];
+        let bob = [2; 32];
 
-        // Alice changes her self trading behavior
+        let params = |side: Side, max_base_qty: u64, limit_price: u64, callback_info: [u8; 32]| {
+            new_order::Params {
+                max_base_qty,
+                max_quote_qty: u64::MAX,
+                limit_price,
+                side,
+                match_limit: 1,
+                callback_info,
+                post_only: false,
+                post_allowed: true,
+                self_trade_behavior: SelfTradeBehavior::DecrementTake,
+                max_ts: u64::MAX,
+                immediate_or_cancel: false,
+                fill_or_kill: false,
+                max_display_qty: 0,
+                post_only_mode: PostOnlyMode::Reject,
+                max_slippage: 0,
+                flags: 0,
+                min_base_qty: 0,
+                compute_unit_reserve: 0,
+                resumable: side == Side::Bid,
+                worst_price: 0,
+                referral: [0; 32],
+                client_order_id: 0,
+            }
+        };
 
-        let OrderSummary {
-            posted_order_id,
-            total_base_qty,
-            total_quote_qty,
-            total_base_qty_posted,
-        } = orderbook
+        // Alice asks 100 BTC at each of 10, 11 and 12 USD/BTC
+        for price in [10, 11, 12].iter() {
+            orderbook
+                .new_order(
+                    params(Side::Ask, 100, price << 32, alice),
+                    &mut event_queue,
+                    &mut market_state,
+                    u64::MIN,
+                )
+                .unwrap();
+        }
+
+        // Bob's resumable bid for 400 BTC at 12 USD/BTC is parked after its first match
+        let summary = orderbook
             .new_order(
-                new_order::Params {
-                    max_base_qty: 50_000,
-                    max_quote_qty: 1_000_000_000,
-                    limit_price: 15 << 32,
-                    side: Side::Bid,
-                    match_limit: 10,
-                    callback_info: alice,
-                    post_only: false,
-                    post_allowed: true,
-                    self_trade_behavior: SelfTradeBehavior::CancelProvide,
-                    max_ts: u64::MAX,
-                },
+                params(Side::Bid, 400, 12 << 32, bob),
                 &mut event_queue,
-                10,
+                &mut market_state,
                 u64::MIN,
             )
             .unwrap();
-        assert!(posted_order_id.is_some());
-        assert_eq!(total_base_qty, 50_000);
-        assert_eq!(total_quote_qty, 50_000 * 15);
-        assert_eq!(total_base_qty_posted, 50_000);
-        assert_eq!(event_queue.header.count, 1);
-        println!("Event queue head: {}", event_queue.header.head);
-
-        assert_eq!(
-            event_queue.iter().next().unwrap(),
-            EventRef::Out(OutEventRef {
-                event: &OutEvent::new(Side::Ask, 250_000, alice_order_id_0.unwrap() as u128,),
-                callback_info: &alice
-            })
-        );
+        assert!(summary.match_limit_exhausted);
+        assert_eq!(summary.total_base_qty, 100);
+        assert_eq!(summary.total_base_qty_posted, 0);
+        let order_id = summary.posted_order_id.unwrap();
+        assert_eq!(market_state.pending_taker.active, 1);
+        assert_eq!(market_state.pending_taker.order_id(), order_id);
+        assert_eq!(market_state.pending_taker.base_qty_remaining, 300);
+        assert!(orderbook.bids.root().is_none());
 
-        event_queue.pop_n(1);
+        // No new orders can be placed until the parked order has been matched
+        assert!(matches!(
+            orderbook.new_order(
+                params(Side::Ask, 100, 13 << 32, alice),
+                &mut event_queue,
+                &mut market_state,
+                u64::MIN,
+            ),
+            Err(AoError::PendingTakerInProgress)
+        ));
+        assert!(matches!(
+            orderbook.continue_match(
+                continue_match::Params {
+                    order_id: order_id + 1,
+                    match_limit: 1,
+                    compute_unit_reserve: 0,
+                    cancel: false,
+                },
+                &mut event_queue,
+                &mut market_state,
+                u64::MIN
+            ),
+            Err(AoError::OrderNotFound)
+        ));
 
-        orderbook
-            .new_order(
-                new_order::Params {
-                    max_base_qty: 1,
-                    max_quote_qty: 10,
-                    limit_price: 1,
-                    side: Side::Ask,
-                    match_limit: 10,
-                    callback_info: [0; 32],
-                    post_only: false,
-                    post_allowed: false,
-                    self_trade_behavior: SelfTradeBehavior::DecrementTake,
-                    max_ts: u64::MAX,
+        let summary = orderbook
+            .continue_match(
+                continue_match::Params {
+                    order_id,
+                    match_limit: 1,
+                    compute_unit_reserve: 0,
+                    cancel: false,
                 },
                 &mut event_queue,
-                10,
+                &mut market_state,
                 u64::MIN,
             )
             .unwrap();
-        assert!(event_queue.header.count == 0);
-
-        println!("Spread : {:?}", orderbook.get_spread());
-    }
-
-    #[test]
-    fn test_ob_booting_ask() {
-        let mut test_context = TestContext::new(2, 1000);
-        let (mut orderbook, mut event_queue) = test_context.get();
-        let alice = [1; 32];
+        assert!(summary.match_limit_exhausted);
+        assert_eq!(summary.total_base_qty, 100);
+        assert_eq!(summary.posted_order_id, Some(order_id));
+        assert_eq!(market_state.pending_taker.base_qty_remaining, 200);
 
-        // Alice posts an ask order for 3 BTC at 20 USD/BTC
-        let OrderSummary {
-            posted_order_id,
-            total_base_qty,
-            total_quote_qty,
-            total_base_qty_posted,
-        } = orderbook
-            .new_order(
-                new_order::Params {
-                    max_base_qty: 3_000_000,
-                    max_quote_qty: 1_000_000_000,
-                    limit_price: 20 << 32,
-                    side: Side::Ask,
+        // The last ask is matched and the remainder is posted with the same order id
+        let summary = orderbook
+            .continue_match(
+                continue_match::Params {
+                    order_id,
                     match_limit: 10,
-                    callback_info: alice,
-                    post_only: false,
-                    post_allowed: true,
-                    self_trade_behavior: SelfTradeBehavior::DecrementTake,
-                    max_ts: u64::MAX,
+                    compute_unit_reserve: 0,
+                    cancel: false,
                 },
                 &mut event_queue,
-                10,
+                &mut market_state,
                 u64::MIN,
             )
             .unwrap();
-        assert!(posted_order_id.is_some());
-        assert_eq!(total_base_qty, 3_000_000);
-        assert_eq!(total_quote_qty, 60_000_000);
-        assert_eq!(total_base_qty_posted, 3_000_000);
-        assert_eq!(event_queue.header.count, 0);
+        assert!(!summary.match_limit_exhausted);
+        assert_eq!(summary.total_base_qty, 200);
+        assert_eq!(summary.total_base_qty_posted, 100);
+        assert_eq!(summary.posted_order_id, Some(order_id));
+        assert_eq!(market_state.pending_taker.active, 0);
+        assert!(orderbook.asks.root().is_none());
+        assert_eq!(orderbook.find_order(order_id).unwrap().0, Side::Bid);
+        assert_eq!(market_state.cumulative_base_volume, 300);
 
-        // Alice posts an ask order for 6 BTC at 40 USD/BTC
-        let OrderSummary {
-            posted_order_id: order_id_to_be_booted,
-            total_base_qty,
-            total_quote_qty,
-            total_base_qty_posted,
-        } = orderbook
+        // A parked order can be cancelled
+        for _ in 0..3 {
+            orderbook
+                .new_order(
+                    params(Side::Ask, 100, 12 << 32, alice),
+                    &mut event_queue,
+                    &mut market_state,
+                    u64::MIN,
+                )
+                .unwrap();
+        }
+        let summary = orderbook
             .new_order(
-                new_order::Params {
-                    max_base_qty: 6_000_000,
-                    max_quote_qty: 1_000_000_000,
-                    limit_price: 40 << 32,
-                    side: Side::Ask,
-                    match_limit: 10,
-                    callback_info: alice,
-                    post_only: false,
-                    post_allowed: true,
-                    self_trade_behavior: SelfTradeBehavior::DecrementTake,
-                    max_ts: u64::MAX,
+                params(Side::Bid, 300, 12 << 32, bob),
+                &mut event_queue,
+                &mut market_state,
+                u64::MIN,
+            )
+            .unwrap();
+        let order_id = summary.posted_order_id.unwrap();
+        let summary = orderbook
+            .continue_match(
+                continue_match::Params {
+                    order_id,
+                    match_limit: 0,
+                    compute_unit_reserve: 0,
+                    cancel: true,
                 },
                 &mut event_queue,
-                10,
+                &mut market_state,
                 u64::MIN,
             )
             .unwrap();
-        assert!(posted_order_id.is_some());
-        assert_eq!(total_base_qty, 6_000_000);
-        assert_eq!(total_quote_qty, 240_000_000);
-        assert_eq!(total_base_qty_posted, 6_000_000);
-        assert_eq!(event_queue.header.count, 0);
+        assert_eq!(summary.total_base_qty_unfilled, 200);
+        assert_eq!(market_state.pending_taker.active, 0);
+    }
 
-        let asks = orderbook.get_tree(Side::Ask);
-        asks.dump();
-        let bids = orderbook.get_tree(Side::Bid);
-        bids.dump();
+    #[test]
+    fn test_ob_event_queue_capacity() {
+        let mut test_context = TestContext::new(1000, 5);
+        let (mut orderbook, mut event_queue) = test_context.get();
+        let mut market_state = test_market_state();
+        let alice = [1; 32];
+        let bob = [2; 32];
 
-        // Alice posts an ask order for 1 BTC at 10 USD/BTC
-        let OrderSummary {
-            posted_order_id,
-            total_base_qty,
-            total_quote_qty,
-            total_base_qty_posted,
-        } = orderbook
+        let params = |side: Side, max_base_qty: u64, callback_info: [u8; 32], match_limit: u64| {
+            new_order::Params {
+                max_base_qty,
+                max_quote_qty: u64::MAX,
+                limit_price: 10 << 32,
+                side,
+                match_limit,
+                callback_info,
+                post_only: false,
+                post_allowed: true,
+                self_trade_behavior: SelfTradeBehavior::DecrementTake,
+                max_ts: u64::MAX,
+                immediate_or_cancel: false,
+                fill_or_kill: false,
+                max_display_qty: 0,
+                post_only_mode: PostOnlyMode::Reject,
+                max_slippage: 0,
+                flags: 0,
+                min_base_qty: 0,
+                compute_unit_reserve: 0,
+                resumable: false,
+                worst_price: 0,
+                referral: [0; 32],
+                client_order_id: 0,
+            }
+        };
+
+        // Alice posts 3 asks for 1 BTC at 10 USD/BTC
+        for _ in 0..3 {
+            orderbook
+                .new_order(
+                    params(Side::Ask, 1_000_000, alice, 10),
+                    &mut event_queue,
+                    &mut market_state,
+                    u64::MIN,
+                )
+                .unwrap();
+        }
+        assert_eq!(event_queue.remaining_capacity(), 5);
+
+        // Bob's bid for 3 BTC would push more events than the queue can hold, and fails without modifying the orderbook
+        let r = orderbook.new_order(
+            params(Side::Bid, 3_000_000, bob, 10),
+            &mut event_queue,
+            &mut market_state,
+            u64::MIN,
+        );
+        assert!(matches!(r, Err(AoError::EventQueueFull)));
+        assert_eq!(orderbook.asks.header.leaf_count, 3);
+        assert!(event_queue.is_empty());
+
+        // Bob's bid for 2 BTC fits in the event queue, with a fill and an out event for each one of Alice's orders
+        let summary = orderbook
             .new_order(
-                new_order::Params {
-                    max_base_qty: 1_000_000,
-                    max_quote_qty: 1_000_000_000,
-                    limit_price: 10 << 32,
-                    side: Side::Ask,
-                    match_limit: 10,
-                    callback_info: alice,
-                    post_only: false,
-                    post_allowed: true,
-                    self_trade_behavior: SelfTradeBehavior::DecrementTake,
-                    max_ts: u64::MAX,
-                },
+                params(Side::Bid, 2_000_000, bob, 10),
                 &mut event_queue,
-                10,
+                &mut market_state,
                 u64::MIN,
             )
             .unwrap();
-        assert!(posted_order_id.is_some());
-        assert_eq!(total_base_qty, 1_000_000);
-        assert_eq!(total_quote_qty, 10_000_000);
-        assert_eq!(total_base_qty_posted, 1_000_000);
-        assert_eq!(event_queue.header.count, 1);
+        assert_eq!(summary.total_base_qty, 2_000_000);
+        assert_eq!(orderbook.asks.header.leaf_count, 1);
+        assert_eq!(event_queue.remaining_capacity(), 1);
+    }
 
-        assert_eq!(
-            event_queue.iter().next().unwrap(),
-            EventRef::Out(OutEventRef {
-                event: &OutEvent::new(Side::Ask, 6_000_000, order_id_to_be_booted.unwrap() as u128,),
-                callback_info: &alice
-            })
-        );
+    #[test]
+    fn test_ob_match_limit_exhausted() {
+        let mut test_context = TestContext::new(1000, 1000);
+        let (mut orderbook, mut event_queue) = test_context.get();
+        let mut market_state = test_market_state();
+        let alice = [1; 32];
+        let bob = [2; 32];
 
-        event_queue.pop_n(1);
+        let params = |side: Side, max_base_qty: u64, callback_info: [u8; 32], match_limit: u64| {
+            new_order::Params {
+                max_base_qty,
+                max_quote_qty: u64::MAX,
+                limit_price: 10 << 32,
+                side,
+                match_limit,
+                callback_info,
+                post_only: false,
+                post_allowed: true,
+                self_trade_behavior: SelfTradeBehavior::DecrementTake,
+                max_ts: u64::MAX,
+                immediate_or_cancel: false,
+                fill_or_kill: false,
+                max_display_qty: 0,
+                post_only_mode: PostOnlyMode::Reject,
+                max_slippage: 0,
+                flags: 0,
+                min_base_qty: 0,
+                compute_unit_reserve: 0,
+                resumable: false,
+                worst_price: 0,
+                referral: [0; 32],
+                client_order_id: 0,
+            }
+        };
 
-        // Alice posts an ask order for 1 BTC at 50 USD/BTC, but is not added to the ob
-        let OrderSummary {
-            posted_order_id,
-            total_base_qty,
-            total_quote_qty,
-            total_base_qty_posted,
-        } = orderbook
+        // Alice posts 3 asks for 1 BTC at 10 USD/BTC
+        for _ in 0..3 {
+            let summary = orderbook
+                .new_order(
+                    params(Side::Ask, 1_000_000, alice, 10),
+                    &mut event_queue,
+                    &mut market_state,
+                    u64::MIN,
+                )
+                .unwrap();
+            assert!(!summary.match_limit_exhausted);
+        }
+
+        // Bob's bid for 3 BTC can only match 2 orders and reports it
+        let summary = orderbook
             .new_order(
-                new_order::Params {
-                    max_base_qty: 1_000_000,
-                    max_quote_qty: 1_000_000_000,
-                    limit_price: 50 << 32,
-                    side: Side::Ask,
-                    match_limit: 10,
-                    callback_info: alice,
-                    post_only: false,
-                    post_allowed: true,
-                    self_trade_behavior: SelfTradeBehavior::DecrementTake,
-                    max_ts: u64::MAX,
-                },
+                params(Side::Bid, 3_000_000, bob, 2),
+                &mut event_queue,
+                &mut market_state,
+                u64::MIN,
+            )
+            .unwrap();
+        assert_eq!(summary.total_base_qty, 2_000_000);
+        assert!(summary.match_limit_exhausted);
+
+        // Bob's bid for 1 BTC is entirely matched, even though it uses up its match limit
+        let summary = orderbook
+            .new_order(
+                params(Side::Bid, 1_000_000, bob, 1),
                 &mut event_queue,
-                10,
+                &mut market_state,
                 u64::MIN,
             )
             .unwrap();
-        assert!(posted_order_id.is_none());
-        assert_eq!(total_base_qty, 0);
-        assert_eq!(total_quote_qty, 0);
-        assert_eq!(total_base_qty_posted, 0);
-        assert_eq!(event_queue.header.count, 0);
+        assert_eq!(summary.total_base_qty, 1_000_000);
+        assert!(!summary.match_limit_exhausted);
     }
 
     #[test]
-    fn test_ob_small_prices() {
-        let mut test_context = TestContext::new(2, 1000);
+    fn test_ob_quote_qty_posted() {
+        let mut test_context = TestContext::new(1000, 1000);
         let (mut orderbook, mut event_queue) = test_context.get();
+        let mut market_state = test_market_state();
         let alice = [1; 32];
+        let bob = [2; 32];
 
-        // Alice posts a bid order for 1 BTC at 0.25 USD/BTC
+        let params = |side: Side, limit_price: u64, callback_info: [u8; 32]| new_order::Params {
+            max_base_qty: 1_000_000,
+            max_quote_qty: u64::MAX,
+            limit_price,
+            side,
+            match_limit: 10,
+            callback_info,
+            post_only: false,
+            post_allowed: true,
+            self_trade_behavior: SelfTradeBehavior::DecrementTake,
+            max_ts: u64::MAX,
+            immediate_or_cancel: false,
+            fill_or_kill: false,
+            max_display_qty: 0,
+            post_only_mode: PostOnlyMode::Reject,
+            max_slippage: 0,
+            flags: 0,
+            min_base_qty: 0,
+            compute_unit_reserve: 0,
+            resumable: false,
+            worst_price: 0,
+            referral: [0; 32],
+            client_order_id: 0,
+        };
 
-        let OrderSummary {
-            posted_order_id,
-            total_base_qty,
-            total_quote_qty,
-            total_base_qty_posted,
-        } = orderbook
+        // Alice posts an ask for 1 BTC at 10 USD/BTC
+        orderbook
+            .new_order(
+                params(Side::Ask, 10 << 32, alice),
+                &mut event_queue,
+                &mut market_state,
+                u64::MIN,
+            )
+            .unwrap();
+
+        // Bob buys 1 BTC at 10 USD/BTC and posts a bid for 1 BTC at 12 USD/BTC
+        let summary = orderbook
             .new_order(
                 new_order::Params {
                     max_base_qty: 2_000_000,
-                    max_quote_qty: 10_000_000,
-                    limit_price: 1 << 30,
-                    side: Side::Bid,
-                    match_limit: 10,
-                    callback_info: alice,
-                    post_only: false,
-                    post_allowed: true,
-                    self_trade_behavior: SelfTradeBehavior::DecrementTake,
-                    max_ts: u64::MAX,
+                    ..params(Side::Bid, 12 << 32, bob)
                 },
                 &mut event_queue,
-                10,
+                &mut market_state,
                 u64::MIN,
             )
             .unwrap();
-        assert!(posted_order_id.is_some());
-        assert_eq!(total_base_qty, 2_000_000);
-        assert_eq!(total_quote_qty, 500_000);
-        assert_eq!(total_base_qty_posted, 2_000_000);
-        assert!(event_queue.header.count == 0);
+        assert_eq!(summary.total_base_qty, 2_000_000);
+        assert_eq!(summary.total_quote_qty, 22_000_000);
+        assert_eq!(summary.total_base_qty_posted, 1_000_000);
+        assert_eq!(summary.total_quote_qty_posted, 12_000_000);
+    }
 
-        // Best bid is 0.25 USD / BTC
+    #[test]
+    fn test_ob_depth() {
+        let mut test_context = TestContext::new(1000, 1000);
+        let (mut orderbook, mut event_queue) = test_context.get();
+        let mut market_state = test_market_state();
+        let alice = [1; 32];
 
-        orderbook
+        let params = |side: Side, limit_price: u64, max_display_qty: u64| new_order::Params {
+            max_base_qty: 1_000_000,
+            max_quote_qty: u64::MAX,
+            limit_price,
+            side,
+            match_limit: 10,
+            callback_info: alice,
+            post_only: false,
+            post_allowed: true,
+            self_trade_behavior: SelfTradeBehavior::DecrementTake,
+            max_ts: u64::MAX,
+            immediate_or_cancel: false,
+            fill_or_kill: false,
+            max_display_qty,
+            post_only_mode: PostOnlyMode::Reject,
+            max_slippage: 0,
+            flags: 0,
+            min_base_qty: 0,
+            compute_unit_reserve: 0,
+            resumable: false,
+            worst_price: 0,
+            referral: [0; 32],
+            client_order_id: 0,
+        };
+
+        for (side, price, max_display_qty) in [
+            (Side::Bid, 9, 0),
+            (Side::Bid, 9, 400_000),
+            (Side::Bid, 8, 0),
+            (Side::Bid, 7, 0),
+            (Side::Ask, 11, 0),
+            (Side::Ask, 12, 0),
+            (Side::Ask, 12, 0),
+        ]
+        .iter()
+        {
+            orderbook
+                .new_order(
+                    params(*side, price << 32, *max_display_qty),
+                    &mut event_queue,
+                    &mut market_state,
+                    u64::MIN,
+                )
+                .unwrap();
+        }
+
+        // Only the displayed quantity of iceberg orders is accounted for
+        assert_eq!(
+            orderbook.bids.get_depth(2, Side::Bid),
+            vec![(9 << 32, 1_400_000), (8 << 32, 1_000_000)]
+        );
+        assert_eq!(
+            orderbook.asks.get_depth(5, Side::Ask),
+            vec![(11 << 32, 1_000_000), (12 << 32, 2_000_000)]
+        );
+        assert!(orderbook.asks.get_depth(0, Side::Ask).is_empty());
+    }
+
+    #[test]
+    fn test_ob_find_order() {
+        let mut test_context = TestContext::new(1000, 1000);
+        let (mut orderbook, mut event_queue) = test_context.get();
+        let mut market_state = test_market_state();
+        let alice = [1; 32];
+
+        let params = |side: Side, limit_price: u64| new_order::Params {
+            max_base_qty: 1_000_000,
+            max_quote_qty: u64::MAX,
+            limit_price,
+            side,
+            match_limit: 10,
+            callback_info: alice,
+            post_only: false,
+            post_allowed: true,
+            self_trade_behavior: SelfTradeBehavior::DecrementTake,
+            max_ts: u64::MAX,
+            immediate_or_cancel: false,
+            fill_or_kill: false,
+            max_display_qty: 0,
+            post_only_mode: PostOnlyMode::Reject,
+            max_slippage: 0,
+            flags: 0,
+            min_base_qty: 0,
+            compute_unit_reserve: 0,
+            resumable: false,
+            worst_price: 0,
+            referral: [0; 32],
+            client_order_id: 0,
+        };
+
+        let bid_id = orderbook
             .new_order(
-                new_order::Params {
-                    max_base_qty: 3,
-                    max_quote_qty: 1_000_000,
-                    limit_price: 1 << 28,
-                    side: Side::Ask,
-                    match_limit: 10,
-                    callback_info: [0; 32],
-                    post_only: false,
-                    post_allowed: false,
-                    self_trade_behavior: SelfTradeBehavior::DecrementTake,
-                    max_ts: u64::MAX,
-                },
+                params(Side::Bid, 9 << 32),
                 &mut event_queue,
-                10,
+                &mut market_state,
                 u64::MIN,
             )
+            .unwrap()
+            .posted_order_id
             .unwrap();
-        assert!(event_queue.header.count == 0);
+        let ask_id = orderbook
+            .new_order(
+                params(Side::Ask, 11 << 32),
+                &mut event_queue,
+                &mut market_state,
+                u64::MIN,
+            )
+            .unwrap()
+            .posted_order_id
+            .unwrap();
+
+        let (side, h) = orderbook.find_order(bid_id).unwrap();
+        assert_eq!(side, Side::Bid);
+        assert_eq!(orderbook.bids.leaf_nodes[h as usize].order_id(), bid_id);
+        let (side, h) = orderbook.find_order(ask_id).unwrap();
+        assert_eq!(side, Side::Ask);
+        assert_eq!(orderbook.asks.leaf_nodes[h as usize].order_id(), ask_id);
+
+        orderbook.asks.remove_by_key(ask_id).unwrap();
+        assert!(orderbook.find_order(ask_id).is_none());
     }
 
     #[test]
-    fn test_ob_booting_bid() {
+    fn test_ob_booting_policy() {
         let mut test_context = TestContext::new(2, 1000);
         let (mut orderbook, mut event_queue) = test_context.get();
+        let mut market_state = test_market_state();
         let alice = [1; 32];
 
-        // Alice posts an ask order for 3 BTC at 20 USD/BTC
-        let OrderSummary {
-            posted_order_id,
-            total_base_qty,
-            total_quote_qty,
-            total_base_qty_posted,
-        } = orderbook
+        let params = |max_base_qty: u64, limit_price: u64| new_order::Params {
+            max_base_qty,
+            max_quote_qty: u64::MAX,
+            limit_price,
+            side: Side::Ask,
+            match_limit: 10,
+            callback_info: alice,
+            post_only: false,
+            post_allowed: true,
+            self_trade_behavior: SelfTradeBehavior::DecrementTake,
+            max_ts: u64::MAX,
+            immediate_or_cancel: false,
+            fill_or_kill: false,
+            max_display_qty: 0,
+            post_only_mode: PostOnlyMode::Reject,
+            max_slippage: 0,
+            flags: 0,
+            min_base_qty: 0,
+            compute_unit_reserve: 0,
+            resumable: false,
+            worst_price: 0,
+            referral: [0; 32],
+            client_order_id: 0,
+        };
+
+        let small_id = orderbook
             .new_order(
-                new_order::Params {
-                    max_base_qty: 3_000_000,
-                    max_quote_qty: 1_000_000_000,
-                    limit_price: 20 << 32,
-                    side: Side::Bid,
-                    match_limit: 10,
-                    callback_info: alice,
-                    post_only: false,
-                    post_allowed: true,
-                    self_trade_behavior: SelfTradeBehavior::DecrementTake,
-                    max_ts: u64::MAX,
-                },
+                params(1_000, 10 << 32),
                 &mut event_queue,
-                10,
+                &mut market_state,
                 u64::MIN,
             )
+            .unwrap()
+            .posted_order_id
             .unwrap();
-        assert!(posted_order_id.is_some());
-        assert_eq!(total_base_qty, 3_000_000);
-        assert_eq!(total_quote_qty, 60_000_000);
-        assert_eq!(total_base_qty_posted, 3_000_000);
-        assert_eq!(event_queue.header.count, 0);
-
-        // Alice posts a bid order for 6 BTC at 10 USD/BTC
-        let OrderSummary {
-            posted_order_id: order_id_to_be_booted,
-            total_base_qty,
-            total_quote_qty,
-            total_base_qty_posted,
-        } = orderbook
+        orderbook
             .new_order(
-                new_order::Params {
-                    max_base_qty: 6_000_000,
-                    max_quote_qty: 1_000_000_000,
-                    limit_price: 10 << 32,
-                    side: Side::Bid,
-                    match_limit: 10,
-                    callback_info: alice,
-                    post_only: false,
-                    post_allowed: true,
-                    self_trade_behavior: SelfTradeBehavior::DecrementTake,
-                    max_ts: u64::MAX,
-                },
+                params(1_000_000, 11 << 32),
                 &mut event_queue,
-                10,
+                &mut market_state,
                 u64::MIN,
             )
             .unwrap();
-        assert!(posted_order_id.is_some());
-        assert_eq!(total_base_qty, 6_000_000);
-        assert_eq!(total_quote_qty, 60_000_000);
-        assert_eq!(total_base_qty_posted, 6_000_000);
-        assert_eq!(event_queue.header.count, 0);
 
-        // Alice posts a bid order for 1 BTC at 40 USD/BTC
-        let OrderSummary {
-            posted_order_id,
-            total_base_qty,
-            total_quote_qty,
-            total_base_qty_posted,
-        } = orderbook
+        market_state.booting_policy = BootingPolicy::RejectNewOrder as u64;
+        let r = orderbook.new_order(
+            params(10_000, 9 << 32),
+            &mut event_queue,
+            &mut market_state,
+            u64::MIN,
+        );
+        assert!(matches!(r, Err(AoError::SlabOutOfSpace)));
+        assert_eq!(orderbook.asks.header.leaf_count, 2);
+
+        market_state.booting_policy = BootingPolicy::BootSmallest as u64;
+        // The new order is smaller than every order in the book
+        let order_summary = orderbook
             .new_order(
-                new_order::Params {
-                    max_base_qty: 1_000_000,
-                    max_quote_qty: 1_000_000_000,
-                    limit_price: 40 << 32,
-                    side: Side::Bid,
-                    match_limit: 10,
-                    callback_info: alice,
-                    post_only: false,
-                    post_allowed: true,
-                    self_trade_behavior: SelfTradeBehavior::DecrementTake,
-                    max_ts: u64::MAX,
-                },
+                params(100, 9 << 32),
                 &mut event_queue,
-                10,
+                &mut market_state,
                 u64::MIN,
             )
             .unwrap();
-        assert!(posted_order_id.is_some());
-        assert_eq!(total_base_qty, 1_000_000);
-        assert_eq!(total_quote_qty, 40_000_000);
-        assert_eq!(total_base_qty_posted, 1_000_000);
-        assert_eq!(event_queue.header.count, 1);
-
+        assert!(order_summary.posted_order_id.is_none());
+        let order_summary = orderbook
+            .new_order(
+                params(10_000, 12 << 32),
+                &mut event_queue,
+                &mut market_state,
+                u64::MIN,
+            )
+            .unwrap();
+        assert!(order_summary.posted_order_id.is_some());
+        assert!(orderbook.asks.find_by_key(small_id).is_none());
         assert_eq!(
-            event_queue.iter().next().unwrap(),
-            EventRef::Out(OutEventRef {
-                event: &OutEvent::new(Side::Bid, 6_000_000, order_id_to_be_booted.unwrap() as u128,),
+            event_queue.iter().last().unwrap(),
+            EventRef::Boot(BootEventRef {
+                event: &BootEvent::new(Side::Ask, BootReason::OrderbookFull, 1_000, small_id),
                 callback_info: &alice
             })
         );
+    }
 
-        event_queue.pop_n(1);
+    #[test]
+    fn test_ob_split_fills() {
+        let mut test_context = TestContext::new(1000, 1000);
+        let (mut orderbook, mut event_queue) = test_context.get();
+        let mut market_state = test_market_state();
+        market_state.split_fills = 1;
+        let alice = [1; 32];
+        let bob = [2; 32];
 
-        // Alice posts an ask order for 1 BTC at 50 USD/BTC, but is not added to the ob
-        let OrderSummary {
-            posted_order_id,
-            total_base_qty,
-            total_quote_qty,
-            total_base_qty_posted,
-        } = orderbook
+        let params = |side: Side, callback_info: [u8; 32]| new_order::Params {
+            max_base_qty: 1_000_000,
+            max_quote_qty: u64::MAX,
+            limit_price: 10 << 32,
+            side,
+            match_limit: 10,
+            callback_info,
+            post_only: false,
+            post_allowed: true,
+            self_trade_behavior: SelfTradeBehavior::DecrementTake,
+            max_ts: u64::MAX,
+            immediate_or_cancel: false,
+            fill_or_kill: false,
+            max_display_qty: 0,
+            post_only_mode: PostOnlyMode::Reject,
+            max_slippage: 0,
+            flags: 0,
+            min_base_qty: 0,
+            compute_unit_reserve: 0,
+            resumable: false,
+            worst_price: 0,
+            referral: [0; 32],
+            client_order_id: 0,
+        };
+
+        let alice_order_id = orderbook
             .new_order(
-                new_order::Params {
-                    max_base_qty: 1_000_000,
-                    max_quote_qty: 1_000_000_000,
-                    limit_price: 5 << 32,
-                    side: Side::Bid,
-                    match_limit: 10,
-                    callback_info: alice,
-                    post_only: false,
-                    post_allowed: true,
-                    self_trade_behavior: SelfTradeBehavior::DecrementTake,
-                    max_ts: u64::MAX,
-                },
+                params(Side::Ask, alice),
                 &mut event_queue,
-                10,
+                &mut market_state,
                 u64::MIN,
             )
+            .unwrap()
+            .posted_order_id
             .unwrap();
-        assert!(posted_order_id.is_none());
-        assert_eq!(total_base_qty, 0);
-        assert_eq!(total_quote_qty, 0);
-        assert_eq!(total_base_qty_posted, 0);
-        assert_eq!(event_queue.header.count, 0);
+        orderbook
+            .new_order(
+                params(Side::Bid, bob),
+                &mut event_queue,
+                &mut market_state,
+                u64::MIN,
+            )
+            .unwrap();
+
+        assert_eq!(market_state.cumulative_base_volume, 1_000_000);
+        assert_eq!(market_state.cumulative_quote_volume, 10_000_000);
+
+        let fill = FillEvent::new(Side::Bid, 10_000_000, alice_order_id, 1_000_000, 0, 0);
+        let mut events = event_queue.iter();
+        assert_eq!(
+            events.next().unwrap(),
+            EventRef::MakerFill(MakerFillEventRef {
+                event: &MakerFillEvent::new(fill),
+                callback_info: &alice
+            })
+        );
+        assert_eq!(
+            events.next().unwrap(),
+            EventRef::TakerFill(TakerFillEventRef {
+                event: &TakerFillEvent::new(fill),
+                callback_info: &bob
+            })
+        );
+        assert!(matches!(events.next().unwrap(), EventRef::Out(_)));
+        assert!(events.next().is_none());
+    }
+
+    #[test]
+    fn test_ob_split_fills_rounding() {
+        let alice = [1; 32];
+        let bob = [2; 32];
+        // A price which isn't a whole number of quote units, so that the quote quantities need to be rounded
+        let price = (10 << 32) + 1;
+
+        for taker_side in [Side::Bid, Side::Ask].iter().copied() {
+            let mut test_context = TestContext::new(1000, 1000);
+            let (mut orderbook, mut event_queue) = test_context.get();
+            let mut market_state = test_market_state();
+            market_state.split_fills = 1;
+
+            let params = |side: Side, callback_info: [u8; 32]| new_order::Params {
+                max_base_qty: 30,
+                max_quote_qty: u64::MAX,
+                limit_price: price,
+                side,
+                match_limit: 10,
+                callback_info,
+                post_only: false,
+                post_allowed: true,
+                self_trade_behavior: SelfTradeBehavior::DecrementTake,
+                max_ts: u64::MAX,
+                immediate_or_cancel: false,
+                fill_or_kill: false,
+                max_display_qty: 0,
+                post_only_mode: PostOnlyMode::Reject,
+                max_slippage: 0,
+                flags: 0,
+                min_base_qty: 0,
+                compute_unit_reserve: 0,
+                resumable: false,
+                worst_price: 0,
+                referral: [0; 32],
+                client_order_id: 0,
+            };
+            orderbook
+                .new_order(
+                    params(taker_side.opposite(), alice),
+                    &mut event_queue,
+                    &mut market_state,
+                    u64::MIN,
+                )
+                .unwrap();
+            let summary = orderbook
+                .new_order(
+                    params(taker_side, bob),
+                    &mut event_queue,
+                    &mut market_state,
+                    u64::MIN,
+                )
+                .unwrap();
+
+            let mut events = event_queue.iter();
+            let maker_quote_qty = match events.next().unwrap() {
+                EventRef::MakerFill(MakerFillEventRef { event, .. }) => event.0.quote_size(),
+                _ => panic!("Expected a maker fill event"),
+            };
+            let taker_quote_qty = match events.next().unwrap() {
+                EventRef::TakerFill(TakerFillEventRef { event, .. }) => event.0.quote_size(),
+                _ => panic!("Expected a taker fill event"),
+            };
+            assert_eq!(summary.total_quote_qty, taker_quote_qty);
+            // The bid always pays the rounded up quote quantity, and the ask receives the rounded down one, so that
+            // no quote is created by the fill
+            match taker_side {
+                Side::Bid => {
+                    assert_eq!(taker_quote_qty, 301);
+                    assert_eq!(maker_quote_qty, 300);
+                }
+                Side::Ask => {
+                    assert_eq!(taker_quote_qty, 300);
+                    assert_eq!(maker_quote_qty, 301);
+                }
+            }
+        }
     }
 }
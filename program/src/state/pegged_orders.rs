@@ -0,0 +1,211 @@
+//! Pegged orders rest in the orderbook at a price which is derived from the best price of one of its sides, plus a
+//! signed offset.
+//!
+//! The peg parameters of each pegged order are kept in a separate critbit tree, keyed by the id which was assigned to
+//! the pegged order when it was first posted. Since an order id encodes the order's price, repricing a pegged order
+//! moves it to a new order id, which is tracked by the [`PeggedOrder`] object. No event is pushed when an order is
+//! repriced: the order's quantity and callback information are left untouched.
+use bytemuck::{Pod, Zeroable};
+use num_traits::FromPrimitive;
+use solana_program::{msg, program_error::ProgramError};
+
+use super::{
+    critbit::{LeafNode, Slab},
+    event_queue::{EventQueue, OutEvent},
+    get_side_from_order_id,
+    market_state::MarketState,
+    orderbook::{CallbackInfo, OrderBookState},
+    read_account_tag, write_account_tag, AccountTag, PegReference, PostOnlyMode, SelfTradeBehavior,
+    Side,
+};
+use crate::{error::AoError, processor::new_order, utils::round_price};
+
+#[derive(Clone, Copy, Zeroable, Pod)]
+#[repr(C, packed)]
+/// The peg parameters of a pegged order, which are stored as the callback information of the pegged orders [`Slab`].
+pub struct PeggedOrder {
+    /// The id of the order which currently rests in the orderbook.
+    pub order_id: u128,
+    /// The signed offset (FP32) which is added to the reference price.
+    pub offset: i64,
+    /// The u8 representation for a [`PegReference`] enum.
+    pub reference: u8,
+}
+
+/// The pegged orders critbit tree.
+pub type PeggedOrdersSlab<'a> = Slab<'a, PeggedOrder>;
+
+/// Tags a freshly allocated or zeroed out account as a pegged orders account.
+pub fn initialize(buffer: &mut [u8]) -> Result<(), ProgramError> {
+    if read_account_tag(buffer)? != AccountTag::Uninitialized {
+        msg!("The pegged orders account is already initialized!");
+        return Err(ProgramError::AccountAlreadyInitialized);
+    }
+    write_account_tag(buffer, AccountTag::PeggedOrders);
+    Ok(())
+}
+
+/// Computes the price of a pegged order from the current state of the orderbook, rounded to the market's tick size
+/// in the passive direction.
+///
+/// Returns `None` when the reference side of the orderbook is empty or when the resulting price isn't positive.
+pub fn get_peg_price<C: Pod>(
+    order_book: &OrderBookState<C>,
+    side: Side,
+    reference: PegReference,
+    offset: i64,
+    tick_size: u64,
+) -> Option<u64> {
+    let (best_bid_price, best_ask_price) = order_book.get_spread();
+    let reference_price = match (side, reference) {
+        (Side::Bid, PegReference::SameSide) | (Side::Ask, PegReference::OppositeSide) => {
+            best_bid_price?
+        }
+        (Side::Ask, PegReference::SameSide) | (Side::Bid, PegReference::OppositeSide) => {
+            best_ask_price?
+        }
+    };
+    let price = if offset >= 0 {
+        reference_price.checked_add(offset as u64)?
+    } else {
+        reference_price.checked_sub(offset.unsigned_abs())?
+    };
+    let price = round_price(tick_size, price, side);
+    if price == 0 {
+        return None;
+    }
+    Some(price)
+}
+
+/// Records the peg parameters of an order which has just been posted into the orderbook.
+///
+/// The id of the posted order identifies the pegged order from then on.
+pub fn insert(
+    slab: &mut PeggedOrdersSlab,
+    order_id: u128,
+    max_ts: u64,
+    reference: PegReference,
+    offset: i64,
+) -> Result<(), AoError> {
+    slab.insert_leaf(
+        &LeafNode::new(order_id, 0, max_ts),
+        &PeggedOrder {
+            order_id,
+            offset,
+            reference: reference as u8,
+        },
+    )?;
+    Ok(())
+}
+
+/// Moves at most `max_orders` pegged orders to their current peg price, starting from the pegged order with the
+/// lowest id which isn't lower than `start_key`.
+///
+/// Pegged orders which are no longer in the orderbook because they were matched, cancelled or pruned are forgotten.
+/// Pegged orders whose reference side of the orderbook is empty, or which are already at their peg price, are left
+/// untouched. Pegged orders which can't be posted again at their new price are taken out of the orderbook.
+///
+/// Returns the number of pegged orders which have been processed, along with the id of the pegged order from which
+/// the next call should start when some pegged orders haven't been reached.
+pub fn reprice<'a, C: Pod + CallbackInfo + PartialEq>(
+    slab: &mut PeggedOrdersSlab,
+    order_book: &mut OrderBookState<'a, C>,
+    event_queue: &mut EventQueue<'a, C>,
+    market_state: &mut MarketState,
+    max_orders: u64,
+    start_key: u128,
+    cur_ts: u64,
+) -> Result<(u64, Option<u128>), AoError>
+where
+    <C as CallbackInfo>::CallbackId: PartialEq,
+{
+    let mut pegged_ids = slab
+        .iter_handles(true)
+        .map(|h| slab.leaf_nodes[h as usize].order_id())
+        .skip_while(|pegged_id| *pegged_id < start_key)
+        .take((max_orders as usize).saturating_add(1))
+        .collect::<Vec<_>>();
+    let next_start_key = if pegged_ids.len() as u64 > max_orders {
+        pegged_ids.pop()
+    } else {
+        None
+    };
+    for pegged_id in pegged_ids.iter().copied() {
+        let pegged_order = *slab.get_callback_info(slab.find_by_key(pegged_id).unwrap());
+        let side = get_side_from_order_id(pegged_id);
+        let book_order_id = pegged_order.order_id;
+        // The order is taken out of the orderbook first, so that it doesn't take part in its own reference price
+        let (order, callback_info) = match order_book.get_tree(side).remove_by_key(book_order_id) {
+            Some((order, callback_info)) => (order, *callback_info),
+            None => {
+                slab.remove_by_key(pegged_id);
+                continue;
+            }
+        };
+        let reference = PegReference::from_u8(pegged_order.reference).unwrap();
+        let new_price = get_peg_price(
+            order_book,
+            side,
+            reference,
+            pegged_order.offset,
+            market_state.tick_size,
+        );
+        if new_price.is_none() || new_price == Some(order.price()) {
+            // The order is put back in place, which preserves its priority
+            order_book
+                .get_tree(side)
+                .insert_leaf(&order, &callback_info)?;
+            continue;
+        }
+        let order_summary = order_book.new_order(
+            new_order::Params {
+                max_base_qty: order.total_base_quantity(),
+                max_quote_qty: u64::MAX,
+                limit_price: new_price.unwrap(),
+                side,
+                match_limit: 1,
+                callback_info,
+                post_only: true,
+                post_allowed: true,
+                self_trade_behavior: SelfTradeBehavior::DecrementTake,
+                max_ts: order.max_ts(),
+                immediate_or_cancel: false,
+                fill_or_kill: false,
+                max_display_qty: 0,
+                post_only_mode: PostOnlyMode::Slide,
+                max_slippage: 0,
+                flags: order.flags(),
+                min_base_qty: 0,
+                compute_unit_reserve: 0,
+                resumable: false,
+                worst_price: 0,
+                referral: [0; 32],
+                client_order_id: 0,
+            },
+            event_queue,
+            market_state,
+            cur_ts,
+        );
+        match order_summary.map(|s| s.posted_order_id) {
+            Ok(Some(order_id)) => {
+                let h = slab.find_by_key(pegged_id).unwrap();
+                slab.set_callback_info(
+                    h,
+                    &PeggedOrder {
+                        order_id,
+                        ..pegged_order
+                    },
+                );
+            }
+            Ok(None) | Err(_) => {
+                // The order could not be posted again, it is taken out of the orderbook
+                let out = OutEvent::new(side, order.total_base_quantity(), book_order_id);
+                event_queue
+                    .push_back(out, Some(&callback_info), None)
+                    .map_err(|_| AoError::EventQueueFull)?;
+                slab.remove_by_key(pegged_id);
+            }
+        }
+    }
+    Ok((pegged_ids.len() as u64, next_start_key))
+}
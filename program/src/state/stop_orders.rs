@@ -0,0 +1,193 @@
+//! Stop orders are kept in a separate critbit tree, keyed by their trigger price and side, until the market's last
+//! trade price reaches their trigger price.
+//!
+//! Once triggered, a stop market order is matched against the orderbook as an immediate-or-cancel order, and a stop
+//! limit order as a regular limit order whose remainder is posted in the orderbook. The unfilled quantity of the stop
+//! order is then reported through an Out event which carries the stop order's id.
+use bytemuck::{Pod, Zeroable};
+use num_traits::FromPrimitive;
+use solana_program::{msg, program_error::ProgramError};
+
+use super::{
+    critbit::{LeafNode, Slab},
+    event_queue::{EventQueue, OutEvent},
+    get_side_from_order_id,
+    market_state::MarketState,
+    orderbook::{CallbackInfo, OrderBookState},
+    read_account_tag, write_account_tag, AccountTag, PostOnlyMode, SelfTradeBehavior, Side,
+};
+use crate::{error::AoError, processor::new_order};
+
+#[derive(Clone, Copy)]
+#[repr(C, packed)]
+/// The parameters of a stop order, which are stored as the callback information of the stop orders [`Slab`].
+///
+/// The stop order's base quantity and max timestamp are stored in the associated [`LeafNode`].
+pub struct StopOrder<C> {
+    /// The limit price of the order once triggered, or 0 for a stop market order.
+    pub limit_price: u64,
+    /// The maximum quantity of quote to be traded once triggered.
+    pub max_quote_qty: u64,
+    /// The maximum number of orders to match against once triggered.
+    pub match_limit: u64,
+    /// For stop market orders, the maximum slippage from the best opposite order once triggered.
+    pub max_slippage: u64,
+    /// The u8 representation for a [`SelfTradeBehavior`][`super::SelfTradeBehavior`] enum.
+    pub self_trade_behavior: u8,
+    /// The caller-defined flags of the order.
+    pub flags: u8,
+    /// The callback information of the order.
+    pub callback_info: C,
+}
+
+unsafe impl<C: Pod> Zeroable for StopOrder<C> {}
+unsafe impl<C: Pod> Pod for StopOrder<C> {}
+
+/// The stop orders critbit tree.
+pub type StopOrdersSlab<'a, C> = Slab<'a, StopOrder<C>>;
+
+/// Tags a freshly allocated or zeroed out account as a stop orders account.
+pub fn initialize(buffer: &mut [u8]) -> Result<(), ProgramError> {
+    if read_account_tag(buffer)? != AccountTag::Uninitialized {
+        msg!("The stop orders account is already initialized!");
+        return Err(ProgramError::AccountAlreadyInitialized);
+    }
+    write_account_tag(buffer, AccountTag::StopOrders);
+    Ok(())
+}
+
+/// Collects the ids of at most `max_orders` stop orders which are triggered by the given last trade price.
+///
+/// Buy stop orders are triggered when the price rises to their trigger price, and sell stop orders when the price
+/// falls to their trigger price. No stop order is triggered before the market's first trade.
+pub fn find_triggered<C: Pod>(
+    slab: &StopOrdersSlab<C>,
+    last_trade_price: u64,
+    max_orders: usize,
+) -> Vec<u128> {
+    if last_trade_price == 0 {
+        return vec![];
+    }
+    let mut triggered = Vec::with_capacity(max_orders.min(slab.header.leaf_count as usize));
+    // Triggered buy stop orders are found by walking up to the last trade price from the bottom of the tree,
+    // and triggered sell stop orders by walking down to it from the top of the tree.
+    for (price_ascending, side) in [(true, Side::Bid), (false, Side::Ask)].iter() {
+        for h in slab.iter_handles(*price_ascending) {
+            if triggered.len() == max_orders {
+                return triggered;
+            }
+            let leaf = &slab.leaf_nodes[h as usize];
+            let crossed = match side {
+                Side::Bid => leaf.price() <= last_trade_price,
+                Side::Ask => leaf.price() >= last_trade_price,
+            };
+            if !crossed {
+                break;
+            }
+            if get_side_from_order_id(leaf.order_id()) == *side {
+                triggered.push(leaf.order_id());
+            }
+        }
+    }
+    triggered
+}
+
+/// Inserts a new stop order into the tree and returns its id.
+///
+/// The stop order id is generated from the trigger price in the same way as regular order ids.
+pub fn insert<'a, C: Pod>(
+    slab: &mut StopOrdersSlab<C>,
+    event_queue: &mut EventQueue<'a, C>,
+    trigger_price: u64,
+    side: Side,
+    base_qty: u64,
+    max_ts: u64,
+    stop_order: StopOrder<C>,
+) -> Result<u128, AoError> {
+    let order_id = event_queue.gen_order_id(trigger_price, side);
+    let new_leaf = LeafNode::new(order_id, base_qty, max_ts);
+    slab.insert_leaf(&new_leaf, &stop_order)?;
+    Ok(order_id)
+}
+
+/// Removes at most `max_orders` stop orders which are triggered by the market's last trade price, and executes them
+/// against the orderbook.
+///
+/// An Out event carrying the stop order id and the unfilled base quantity is pushed for each one of them. Expired stop
+/// orders, and stop orders which can't be executed, are removed with their whole base quantity unfilled. Returns the
+/// number of stop orders which have been removed.
+pub fn trigger_orders<'a, C: Pod + CallbackInfo + PartialEq>(
+    slab: &mut StopOrdersSlab<C>,
+    order_book: &mut OrderBookState<'a, C>,
+    event_queue: &mut EventQueue<'a, C>,
+    market_state: &mut MarketState,
+    max_orders: u64,
+    cur_ts: u64,
+) -> Result<u64, AoError>
+where
+    <C as CallbackInfo>::CallbackId: PartialEq,
+{
+    let triggered = find_triggered(slab, market_state.last_trade_price, max_orders as usize);
+    for order_id in triggered.iter() {
+        let side = get_side_from_order_id(*order_id);
+        let (leaf, stop_order) = slab.remove_by_key(*order_id).unwrap();
+        let stop_order = *stop_order;
+        let callback_info = stop_order.callback_info;
+        let base_qty_unfilled = if leaf.max_ts() < cur_ts {
+            leaf.base_quantity()
+        } else {
+            let order_summary = order_book.new_order(
+                new_order::Params {
+                    max_base_qty: leaf.base_quantity(),
+                    max_quote_qty: stop_order.max_quote_qty,
+                    limit_price: stop_order.limit_price,
+                    side,
+                    match_limit: stop_order.match_limit,
+                    callback_info,
+                    post_only: false,
+                    post_allowed: stop_order.limit_price != 0,
+                    self_trade_behavior: SelfTradeBehavior::from_u8(stop_order.self_trade_behavior)
+                        .unwrap(),
+                    max_ts: leaf.max_ts(),
+                    immediate_or_cancel: stop_order.limit_price == 0,
+                    fill_or_kill: false,
+                    max_display_qty: 0,
+                    post_only_mode: PostOnlyMode::Reject,
+                    max_slippage: stop_order.max_slippage,
+                    flags: stop_order.flags,
+                    min_base_qty: 0,
+                    compute_unit_reserve: 0,
+                    resumable: false,
+                    worst_price: 0,
+                    referral: [0; 32],
+                    client_order_id: 0,
+                },
+                event_queue,
+                market_state,
+                cur_ts,
+            );
+            match order_summary {
+                Ok(order_summary) => {
+                    if let Some(posted_order_id) = order_summary.posted_order_id {
+                        msg!(
+                            "Stop order {} posted as order {}",
+                            order_id,
+                            posted_order_id
+                        );
+                    }
+                    order_summary.total_base_qty_unfilled
+                }
+                Err(_) => {
+                    // The stop order is dropped so that it doesn't prevent other stop orders from being triggered
+                    msg!("Stop order {} could not be executed", order_id);
+                    leaf.base_quantity()
+                }
+            }
+        };
+        let out = OutEvent::new(side, base_qty_unfilled, *order_id);
+        event_queue
+            .push_back(out, Some(&callback_info), None)
+            .map_err(|_| AoError::EventQueueFull)?;
+    }
+    Ok(triggered.len() as u64)
+}
@@ -0,0 +1,376 @@
+//! A [`MarketHarness`] which sets up a market in a `solana-program-test` bank and wraps the instruction builders, so
+//! that tests of the orderbook and of the programs built on top of it don't have to duplicate the setup boilerplate.
+//!
+//! A [`MarketSnapshot`] checkpoints the accounts of a market, so that several scenarios can be branched from a complex
+//! orderbook state without building it again.
+//!
+//! This module is only available with the `test-utils` feature.
+use std::marker::PhantomData;
+
+use bonfida_utils::BorshSize;
+use borsh::{BorshDeserialize, BorshSerialize};
+use bytemuck::Pod;
+use solana_program::{
+    instruction::Instruction, pubkey::Pubkey, system_instruction::create_account,
+};
+use solana_program_test::{processor, BanksClientError, ProgramTest, ProgramTestContext};
+use solana_sdk::{
+    account::{Account, AccountSharedData},
+    signature::{Keypair, Signer},
+    transaction::Transaction,
+};
+
+use crate::{
+    instruction::{cancel_order, consume_events, create_market, new_order},
+    state::{
+        critbit::{LeafNode, Slab},
+        event_queue::EventQueue,
+        market_state::MarketState,
+        orderbook::{read_cancelled_order, CancelledOrder},
+        AccountTag, OrderSummary, Side,
+    },
+};
+
+/// The size of the register account created by the harness, which is enough to hold the result of any instruction
+/// sent by its helpers.
+pub const REGISTER_ACCOUNT_LEN: usize = 10_240;
+
+/// Returns a [`ProgramTest`] which runs the bundled test entrypoint of the orderbook, with `[u8; 32]` callback infos.
+pub fn program_test() -> ProgramTest {
+    ProgramTest::new(
+        "agnostic_orderbook",
+        crate::ID,
+        processor!(crate::entrypoint::process_instruction),
+    )
+}
+
+/// Signs and sends the given instructions in a single transaction paid for by the context's payer.
+pub async fn sign_send_instructions(
+    ctx: &mut ProgramTestContext,
+    instructions: Vec<Instruction>,
+    signers: Vec<&Keypair>,
+) -> Result<(), BanksClientError> {
+    let mut transaction = Transaction::new_with_payer(&instructions, Some(&ctx.payer.pubkey()));
+    let mut payer_signers = vec![&ctx.payer];
+    for s in signers {
+        payer_signers.push(s);
+    }
+    transaction.partial_sign(&payer_signers, ctx.last_blockhash);
+    ctx.banks_client.process_transaction(transaction).await
+}
+
+/// Creates a rent exempt account of `space` bytes owned by the orderbook program, and returns its address.
+pub async fn create_program_account(ctx: &mut ProgramTestContext, space: usize) -> Pubkey {
+    let rent = ctx.banks_client.get_rent().await.unwrap();
+    let account = Keypair::new();
+    let instruction = create_account(
+        &ctx.payer.pubkey(),
+        &account.pubkey(),
+        rent.minimum_balance(space),
+        space as u64,
+        &crate::ID,
+    );
+    sign_send_instructions(ctx, vec![instruction], vec![&account])
+        .await
+        .unwrap();
+    account.pubkey()
+}
+
+/// The addresses of the accounts of a market.
+#[derive(Debug, Clone, Copy)]
+pub struct MarketAccounts {
+    #[allow(missing_docs)]
+    pub market: Pubkey,
+    #[allow(missing_docs)]
+    pub event_queue: Pubkey,
+    #[allow(missing_docs)]
+    pub bids: Pubkey,
+    #[allow(missing_docs)]
+    pub asks: Pubkey,
+}
+
+/// Creates the market, event queue and slab accounts with the sizes required by the capacities of `params`, then
+/// sends the create_market instruction.
+pub async fn create_market_accounts<C>(
+    ctx: &mut ProgramTestContext,
+    register_account: Pubkey,
+    params: create_market::Params,
+) -> MarketAccounts {
+    let market = create_program_account(ctx, MarketState::LEN + 8).await;
+    let event_queue = create_program_account(
+        ctx,
+        EventQueue::<C>::compute_allocation_size(params.event_capacity as usize),
+    )
+    .await;
+    let slab_size = Slab::<C>::compute_allocation_size(params.order_capacity as usize);
+    let bids = create_program_account(ctx, slab_size).await;
+    let asks = create_program_account(ctx, slab_size).await;
+
+    let instruction = create_market(
+        create_market::Accounts {
+            market: &market,
+            event_queue: &event_queue,
+            bids: &bids,
+            asks: &asks,
+            market_registry: None,
+        },
+        register_account,
+        params,
+    );
+    sign_send_instructions(ctx, vec![instruction], vec![])
+        .await
+        .unwrap();
+
+    MarketAccounts {
+        market,
+        event_queue,
+        bids,
+        asks,
+    }
+}
+
+/// The accounts of a market at a point in time.
+#[derive(Debug, Clone)]
+pub struct MarketSnapshot {
+    #[allow(missing_docs)]
+    pub market: Account,
+    #[allow(missing_docs)]
+    pub bids: Account,
+    #[allow(missing_docs)]
+    pub asks: Account,
+    #[allow(missing_docs)]
+    pub event_queue: Account,
+}
+
+impl MarketSnapshot {
+    /// Reads the current state of the market's accounts from the bank.
+    pub async fn capture(ctx: &mut ProgramTestContext, accounts: &MarketAccounts) -> Self {
+        let banks_client = &mut ctx.banks_client;
+        Self {
+            market: banks_client
+                .get_account(accounts.market)
+                .await
+                .unwrap()
+                .unwrap(),
+            bids: banks_client
+                .get_account(accounts.bids)
+                .await
+                .unwrap()
+                .unwrap(),
+            asks: banks_client
+                .get_account(accounts.asks)
+                .await
+                .unwrap()
+                .unwrap(),
+            event_queue: banks_client
+                .get_account(accounts.event_queue)
+                .await
+                .unwrap()
+                .unwrap(),
+        }
+    }
+
+    /// Overwrites the market's accounts in the bank with the captured state.
+    ///
+    /// The snapshot can be restored any number of times, and into the accounts of another market with the same
+    /// capacities.
+    ///
+    /// The bank rejects transactions identical to ones it has already processed, so that a scenario which sends the
+    /// same instructions again after a restore should first refresh `ctx.last_blockhash`.
+    pub fn restore(&self, ctx: &mut ProgramTestContext, accounts: &MarketAccounts) {
+        for (key, account) in [
+            (accounts.market, &self.market),
+            (accounts.bids, &self.bids),
+            (accounts.asks, &self.asks),
+            (accounts.event_queue, &self.event_queue),
+        ] {
+            ctx.set_account(&key, &AccountSharedData::from(account.clone()));
+        }
+    }
+}
+
+/// A market running in a `solana-program-test` bank, along with its register account.
+///
+/// The `C` type parameter is the callback info type of the program which processes the orderbook's instructions.
+pub struct MarketHarness<C> {
+    /// The context of the bank in which the market lives.
+    pub ctx: ProgramTestContext,
+    /// The register account into which the results of the instructions are written.
+    pub register_account: Pubkey,
+    /// The accounts of the market.
+    pub accounts: MarketAccounts,
+    _callback_info: PhantomData<C>,
+}
+
+impl<C: Pod + BorshSerialize + BorshSize> MarketHarness<C> {
+    /// Starts the bank of `program_test` and creates a market in it with the given parameters.
+    pub async fn start(mut program_test: ProgramTest, params: create_market::Params) -> Self {
+        let register_account = Pubkey::new_unique();
+        program_test.add_account(
+            register_account,
+            Account {
+                lamports: 1_000_000_000,
+                data: vec![0; REGISTER_ACCOUNT_LEN],
+                owner: crate::ID,
+                ..Account::default()
+            },
+        );
+        let mut ctx = program_test.start_with_context().await;
+        let accounts = create_market_accounts::<C>(&mut ctx, register_account, params).await;
+        Self {
+            ctx,
+            register_account,
+            accounts,
+            _callback_info: PhantomData,
+        }
+    }
+
+    /// Signs and sends the given instructions in a single transaction.
+    pub async fn send(
+        &mut self,
+        instructions: Vec<Instruction>,
+        signers: Vec<&Keypair>,
+    ) -> Result<(), BanksClientError> {
+        sign_send_instructions(&mut self.ctx, instructions, signers).await
+    }
+
+    /// Builds a new_order instruction for the market.
+    pub fn new_order_instruction(&self, params: new_order::Params<C>) -> Instruction {
+        new_order(
+            new_order::Accounts {
+                market: &self.accounts.market,
+                event_queue: &self.accounts.event_queue,
+                bids: &self.accounts.bids,
+                asks: &self.accounts.asks,
+                allowlist: None,
+                event_queue_shards: &[],
+            },
+            self.register_account,
+            params,
+        )
+    }
+
+    /// Builds a cancel_order instruction for the market.
+    pub fn cancel_order_instruction(&self, order_id: u128) -> Instruction {
+        cancel_order(
+            cancel_order::Accounts {
+                market: &self.accounts.market,
+                event_queue: &self.accounts.event_queue,
+                bids: &self.accounts.bids,
+                asks: &self.accounts.asks,
+            },
+            self.register_account,
+            cancel_order::Params { order_id },
+        )
+    }
+
+    /// Builds a consume_events instruction which pops at most `number_of_entries_to_consume` events off the event
+    /// queue.
+    pub fn consume_events_instruction(&self, number_of_entries_to_consume: u64) -> Instruction {
+        consume_events(
+            consume_events::Accounts {
+                market: &self.accounts.market,
+                event_queue: &self.accounts.event_queue,
+                consumer_program: None,
+                consumer_authority: None,
+                consumer_accounts: &[],
+            },
+            self.register_account,
+            consume_events::Params {
+                number_of_entries_to_consume,
+                max_seq_num: u64::MAX,
+                write_to_register: false,
+                queue_index: 0,
+            },
+        )
+    }
+
+    /// Sends a new_order instruction and returns the resulting order summary.
+    pub async fn new_order(
+        &mut self,
+        params: new_order::Params<C>,
+    ) -> Result<Option<OrderSummary>, BanksClientError> {
+        let instruction = self.new_order_instruction(params);
+        self.send(vec![instruction], vec![]).await?;
+        Ok(self.order_summary().await)
+    }
+
+    /// Sends a cancel_order instruction and returns the cancelled order.
+    pub async fn cancel_order(
+        &mut self,
+        order_id: u128,
+    ) -> Result<CancelledOrder<C>, BanksClientError> {
+        let instruction = self.cancel_order_instruction(order_id);
+        self.send(vec![instruction], vec![]).await?;
+        let data = self.account_data(self.register_account).await;
+        Ok(read_cancelled_order(&data).unwrap())
+    }
+
+    /// Sends a consume_events instruction which pops at most `number_of_entries_to_consume` events off the event queue.
+    pub async fn consume_events(
+        &mut self,
+        number_of_entries_to_consume: u64,
+    ) -> Result<(), BanksClientError> {
+        let instruction = self.consume_events_instruction(number_of_entries_to_consume);
+        self.send(vec![instruction], vec![]).await
+    }
+
+    /// Returns the data of the given account.
+    pub async fn account_data(&mut self, key: Pubkey) -> Vec<u8> {
+        self.ctx
+            .banks_client
+            .get_account(key)
+            .await
+            .unwrap()
+            .unwrap()
+            .data
+    }
+
+    /// Reads back the order summary written into the register account by the last instruction.
+    pub async fn order_summary(&mut self) -> Option<OrderSummary> {
+        let data = self.account_data(self.register_account).await;
+        Option::<OrderSummary>::deserialize(&mut &data[..]).unwrap()
+    }
+
+    /// Reads back the market state.
+    pub async fn market_state(&mut self) -> MarketState {
+        let data = self.account_data(self.accounts.market).await;
+        *MarketState::from_buffer(&data, AccountTag::Market).unwrap()
+    }
+
+    /// Reads back the orders of one side of the orderbook along with their callback infos, from the best price to the
+    /// worst.
+    pub async fn orders(&mut self, side: Side) -> Vec<(LeafNode, C)> {
+        let (key, tag) = match side {
+            Side::Bid => (self.accounts.bids, AccountTag::Bids),
+            Side::Ask => (self.accounts.asks, AccountTag::Asks),
+        };
+        let mut data = self.account_data(key).await;
+        let slab = Slab::<C>::from_buffer(&mut data, tag).unwrap();
+        slab.iter_handles(side == Side::Ask)
+            .map(|h| (slab.leaf_nodes[h as usize], *slab.get_callback_info(h)))
+            .collect()
+    }
+
+    /// Reads back the event queue and passes it to `f`, returning its result.
+    pub async fn with_event_queue<R>(&mut self, f: impl FnOnce(&EventQueue<C>) -> R) -> R {
+        let mut data = self.account_data(self.accounts.event_queue).await;
+        let event_queue = EventQueue::<C>::from_buffer(&mut data, AccountTag::EventQueue).unwrap();
+        f(&event_queue)
+    }
+
+    /// Returns the number of events in the event queue.
+    pub async fn event_count(&mut self) -> u64 {
+        self.with_event_queue(|q| q.len()).await
+    }
+
+    /// Captures the current state of the market's accounts.
+    pub async fn snapshot(&mut self) -> MarketSnapshot {
+        MarketSnapshot::capture(&mut self.ctx, &self.accounts).await
+    }
+
+    /// Restores the market's accounts to a state captured by [`snapshot`][`MarketHarness::snapshot`].
+    pub fn restore(&mut self, snapshot: &MarketSnapshot) {
+        snapshot.restore(&mut self.ctx, &self.accounts)
+    }
+}
@@ -1,6 +1,11 @@
-use crate::{error::AoError, state::Side};
+use crate::{
+    error::AoError,
+    state::{allowlist::Allowlist, market_state::MarketState, AccountTag, Side},
+};
+use bytemuck::Pod;
 
-use solana_program::{account_info::AccountInfo, pubkey::Pubkey};
+use solana_program::{account_info::AccountInfo, msg, program_error::ProgramError, pubkey::Pubkey};
+use std::convert::TryInto;
 
 use crate::state::orderbook::{CallbackInfo, OrderBookState};
 
@@ -16,6 +21,46 @@ pub(crate) fn check_account_key(
     Ok(())
 }
 
+/// Checks that the given accounts are the event queue shards of the market, in order.
+pub(crate) fn check_event_queue_shards(
+    accounts: &[AccountInfo],
+    market_state: &MarketState,
+) -> Result<(), AoError> {
+    let shards = market_state.event_queue_shards();
+    if accounts.len() != shards.len() || accounts.iter().zip(shards).any(|(a, k)| a.key != k) {
+        msg!("The event queue shards of the market must be provided in order!");
+        return Err(AoError::WrongEventQueueAccount);
+    }
+    Ok(())
+}
+
+/// Checks that the allowlist of the market authorizes the callback infos of the orders, when the market has one.
+pub(crate) fn check_order_allowlist<'a, C: Pod>(
+    allowlist: Option<&AccountInfo>,
+    market_state: &MarketState,
+    callback_infos: impl IntoIterator<Item = &'a C>,
+) -> Result<(), ProgramError> {
+    if market_state.order_allowlist == Pubkey::default() {
+        return Ok(());
+    }
+    let allowlist = allowlist.ok_or_else(|| {
+        msg!("The allowlist of the market must be provided!");
+        AoError::WrongAllowlistAccount
+    })?;
+    check_account_key(
+        allowlist,
+        &market_state.order_allowlist,
+        AoError::WrongAllowlistAccount,
+    )?;
+    let mut allowlist_data = allowlist.data.borrow_mut();
+    let allowlist = Allowlist::from_buffer(&mut allowlist_data, AccountTag::Allowlist)?;
+    if !callback_infos.into_iter().all(|c| allowlist.allows(c)) {
+        msg!("The order's callback info isn't in the allowlist of the market!");
+        return Err(AoError::OrderNotAllowlisted.into());
+    }
+    Ok(())
+}
+
 pub(crate) fn check_account_owner(
     account: &AccountInfo,
     owner: &[u8],
@@ -28,18 +73,56 @@ pub(crate) fn check_account_owner(
 }
 
 /// This util is used to return the orderbook's spread (best_bid_price, best_ask_price) with both values in FP32 format
+///
+/// Fails if the accounts don't hold the bids and asks of a market.
 pub fn get_spread<'ob, 'b: 'ob, C: CallbackInfo + PartialEq>(
     bids_account: &'ob AccountInfo<'b>,
     asks_account: &'ob AccountInfo<'b>,
-) -> (Option<u64>, Option<u64>)
+) -> Result<(Option<u64>, Option<u64>), ProgramError>
+where
+    <C as CallbackInfo>::CallbackId: PartialEq,
+{
+    let mut bids = bids_account.data.borrow_mut();
+    let mut asks = asks_account.data.borrow_mut();
+
+    let orderbook = OrderBookState::<C>::new_safe(&mut bids, &mut asks)?;
+    Ok(orderbook.get_spread())
+}
+
+/// The utilization of the two sides of an orderbook, as returned by [`get_market_status`].
+#[derive(Debug, Clone, Copy, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct MarketStatus {
+    /// The number of orders in the bids account.
+    pub bids_len: u64,
+    /// The maximum number of orders which the bids account can hold.
+    pub bids_capacity: u64,
+    /// The number of orders in the asks account.
+    pub asks_len: u64,
+    /// The maximum number of orders which the asks account can hold.
+    pub asks_capacity: u64,
+}
+
+/// This util is used to monitor how close the orderbook's bids and asks accounts are to being full
+///
+/// Fails if the accounts don't hold the bids and asks of a market.
+pub fn get_market_status<'ob, 'b: 'ob, C: CallbackInfo + PartialEq>(
+    bids_account: &'ob AccountInfo<'b>,
+    asks_account: &'ob AccountInfo<'b>,
+) -> Result<MarketStatus, ProgramError>
 where
     <C as CallbackInfo>::CallbackId: PartialEq,
 {
     let mut bids = bids_account.data.borrow_mut();
     let mut asks = asks_account.data.borrow_mut();
 
-    let orderbook = OrderBookState::<C>::new_safe(&mut bids, &mut asks).unwrap();
-    orderbook.get_spread()
+    let orderbook = OrderBookState::<C>::new_safe(&mut bids, &mut asks)?;
+    Ok(MarketStatus {
+        bids_len: orderbook.bids.len(),
+        bids_capacity: orderbook.bids.capacity(),
+        asks_len: orderbook.asks.len(),
+        asks_capacity: orderbook.asks.capacity(),
+    })
 }
 
 /// Rounds a given price the nearest tick size according to the rules of the AOB
@@ -52,6 +135,125 @@ pub fn round_price(tick_size: u64, limit_price: u64, side: Side) -> u64 {
     }
 }
 
+/// Computes the base quantity which can be exchanged for a quote quantity at an FP32 price, rounded down.
+///
+/// Quote quantities are expressed in units of `2^quote_scale_exponent` raw quote units, where a raw quote quantity is
+/// the product of a base quantity and an FP32 price. Returns `None` instead of panicking when the price is zero, as
+/// well as when the result doesn't fit in a u64.
+pub(crate) fn compute_base_qty(
+    quote_qty: u64,
+    price: u64,
+    quote_scale_exponent: u64,
+) -> Option<u64> {
+    if price == 0 {
+        return None;
+    }
+    (((quote_qty as u128) << (32 + quote_scale_exponent)) / price as u128)
+        .try_into()
+        .ok()
+}
+
+/// Computes the quote quantity exchanged by a side for a base quantity at an FP32 price, rounded in the direction
+/// which never favors that side: bids pay a rounded up quote quantity, and asks receive a rounded down one.
+///
+/// The quote quantity is expressed in units of `2^quote_scale_exponent` raw quote units, see [`compute_base_qty`].
+pub(crate) fn compute_quote_qty(
+    side: Side,
+    base_qty: u64,
+    price: u64,
+    quote_scale_exponent: u64,
+) -> Option<u64> {
+    let shift = 32 + quote_scale_exponent;
+    let raw_quote_qty = base_qty as u128 * price as u128;
+    let quote_qty = match side {
+        Side::Bid => (raw_quote_qty + (1 << shift) - 1) >> shift,
+        Side::Ask => raw_quote_qty >> shift,
+    };
+    quote_qty.try_into().ok()
+}
+
+/// Computes the fee charged at a rate of `fee_bps` basis points on a quote quantity, rounded up so that the fee never
+/// favors the party paying it.
+pub fn compute_fee(quote_qty: u64, fee_bps: u16) -> u64 {
+    ((quote_qty as u128 * fee_bps as u128 + 9_999) / 10_000) as u64
+}
+
+/// Computes the largest base quantity which can be matched at an FP32 `price` without the volume-weighted average price
+/// of an order of the given side going past `worst_price`.
+///
+/// The order's matched volume so far is described by its base quantity and its notional value, which is the sum of the
+/// matched base quantities multiplied by their FP32 prices, so that the bound isn't affected by quote rounding. Returns
+/// `None` when `price` is no worse than `worst_price`, in which case the quantity isn't bounded.
+pub(crate) fn max_base_qty_within_worst_price(
+    side: Side,
+    matched_base_qty: u64,
+    matched_notional: u128,
+    price: u64,
+    worst_price: u64,
+) -> Option<u64> {
+    let bound = matched_base_qty as u128 * worst_price as u128;
+    let (headroom, price_difference) = match side {
+        Side::Bid => (
+            bound.checked_sub(matched_notional),
+            price.checked_sub(worst_price),
+        ),
+        Side::Ask => (
+            matched_notional.checked_sub(bound),
+            worst_price.checked_sub(price),
+        ),
+    };
+    match price_difference {
+        None | Some(0) => None,
+        Some(d) => Some(
+            (headroom.unwrap_or(0) / d as u128)
+                .try_into()
+                .unwrap_or(u64::MAX),
+        ),
+    }
+}
+
+/// Returns the number of compute units remaining in the current transaction, if the runtime exposes it.
+///
+/// The `sol_remaining_compute_units` syscall is only used when the program is built with the `compute-budget` feature,
+/// since a program which references it can't be deployed on a cluster where it isn't available.
+#[cfg(all(feature = "compute-budget", target_os = "solana"))]
+pub fn remaining_compute_units() -> Option<u64> {
+    extern "C" {
+        fn sol_remaining_compute_units() -> u64;
+    }
+    Some(unsafe { sol_remaining_compute_units() })
+}
+
+/// Returns the number of compute units remaining in the current transaction, if the runtime exposes it.
+#[cfg(not(all(feature = "compute-budget", target_os = "solana")))]
+pub fn remaining_compute_units() -> Option<u64> {
+    None
+}
+
+/// Serializes fixed-size byte arrays, which serde only supports up to 32 bytes, as byte strings.
+#[cfg(feature = "serde")]
+pub(crate) mod serde_byte_array {
+    use serde::{de::Error, Deserialize, Deserializer, Serializer};
+    use std::convert::TryInto;
+
+    pub fn serialize<S: Serializer, const N: usize>(
+        bytes: &[u8; N],
+        serializer: S,
+    ) -> Result<S::Ok, S::Error> {
+        serializer.serialize_bytes(bytes)
+    }
+
+    pub fn deserialize<'de, D: Deserializer<'de>, const N: usize>(
+        deserializer: D,
+    ) -> Result<[u8; N], D::Error> {
+        Vec::<u8>::deserialize(deserializer)?
+            .try_into()
+            .map_err(|b: Vec<u8>| {
+                D::Error::custom(format!("expected {} bytes, got {}", N, b.len()))
+            })
+    }
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -66,4 +268,63 @@ mod tests {
         let rounded_price_ask = round_price(tick_size, price, Side::Ask);
         assert_eq!(rounded_price_ask, (5.5 * 2.0f64.powi(32)) as u64);
     }
+
+    #[test]
+    fn test_compute_base_qty() {
+        assert_eq!(compute_base_qty(10, 2 << 32, 0), Some(5));
+        assert_eq!(compute_base_qty(10, 0, 0), None);
+        assert_eq!(compute_base_qty(u64::MAX, 1, 0), None);
+        assert_eq!(compute_base_qty(10, 2 << 32, 3), Some(40));
+    }
+
+    #[test]
+    fn test_compute_quote_qty() {
+        let price = (10 << 32) + 1;
+        assert_eq!(compute_quote_qty(Side::Bid, 30, price, 0), Some(301));
+        assert_eq!(compute_quote_qty(Side::Ask, 30, price, 0), Some(300));
+        assert_eq!(compute_quote_qty(Side::Bid, 32, 10 << 32, 5), Some(10));
+        assert_eq!(compute_quote_qty(Side::Ask, 33, 10 << 32, 5), Some(10));
+        assert_eq!(compute_quote_qty(Side::Bid, 33, 10 << 32, 5), Some(11));
+        // Quote quantities which overflow a u64 can be represented with a larger exponent
+        assert_eq!(compute_quote_qty(Side::Ask, u64::MAX, u64::MAX, 0), None);
+        assert_eq!(
+            compute_quote_qty(Side::Ask, u64::MAX, u64::MAX, 32),
+            Some(u64::MAX - 1)
+        );
+    }
+
+    #[test]
+    fn test_compute_fee() {
+        assert_eq!(compute_fee(10_000, 25), 25);
+        assert_eq!(compute_fee(10_001, 25), 26);
+        assert_eq!(compute_fee(1, 1), 1);
+        assert_eq!(compute_fee(1_000_000, 0), 0);
+        assert_eq!(compute_fee(u64::MAX, 10_000), u64::MAX);
+    }
+
+    #[test]
+    fn test_max_base_qty_within_worst_price() {
+        // 200 base matched for an average price of 10.5
+        let notional = 2100 << 32;
+        assert_eq!(
+            max_base_qty_within_worst_price(Side::Bid, 200, notional, 14 << 32, 12 << 32),
+            Some(150)
+        );
+        assert_eq!(
+            max_base_qty_within_worst_price(Side::Bid, 200, notional, 12 << 32, 12 << 32),
+            None
+        );
+        assert_eq!(
+            max_base_qty_within_worst_price(Side::Bid, 200, notional, 11 << 32, 10 << 32),
+            Some(0)
+        );
+        assert_eq!(
+            max_base_qty_within_worst_price(Side::Ask, 200, notional, 8 << 32, 10 << 32),
+            Some(50)
+        );
+        assert_eq!(
+            max_base_qty_within_worst_price(Side::Ask, 0, 0, 8 << 32, 9 << 32),
+            Some(0)
+        );
+    }
 }
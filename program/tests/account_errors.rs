@@ -0,0 +1,178 @@
+use agnostic_orderbook::error::AoError;
+use agnostic_orderbook::instruction::new_order;
+use agnostic_orderbook::state::{PostOnlyMode, SelfTradeBehavior, Side};
+use agnostic_orderbook::test_utils::{create_program_account, program_test, MarketHarness};
+use bonfida_utils::BorshSize;
+use borsh::{BorshDeserialize, BorshSerialize};
+use solana_program::instruction::{Instruction, InstructionError};
+use solana_program::pubkey::Pubkey;
+use solana_sdk::signature::Signer;
+use solana_sdk::transaction::TransactionError;
+pub mod common;
+use crate::common::utils::market_params;
+
+#[derive(BorshDeserialize, BorshSerialize, Clone, Copy)]
+#[repr(transparent)]
+pub struct C([u8; 32]);
+
+unsafe impl bytemuck::Zeroable for C {}
+unsafe impl bytemuck::Pod for C {}
+
+impl BorshSize for C {
+    fn borsh_len(&self) -> usize {
+        32
+    }
+}
+
+const MARKET: usize = 0;
+const EVENT_QUEUE: usize = 1;
+const BIDS: usize = 2;
+const ASKS: usize = 3;
+
+fn order_params() -> new_order::Params<C> {
+    new_order::Params {
+        max_base_qty: 1000,
+        max_quote_qty: u64::MAX,
+        limit_price: 10 << 32,
+        side: Side::Bid,
+        callback_info: C(Pubkey::new_unique().to_bytes()),
+        post_only: false,
+        post_allowed: true,
+        self_trade_behavior: SelfTradeBehavior::DecrementTake,
+        match_limit: 10,
+        max_ts: u64::MAX,
+        immediate_or_cancel: false,
+        fill_or_kill: false,
+        max_display_qty: 0,
+        post_only_mode: PostOnlyMode::Reject,
+        max_slippage: 0,
+        flags: 0,
+        min_base_qty: 0,
+        compute_unit_reserve: 0,
+        resumable: false,
+        worst_price: 0,
+        referral: [0; 32],
+        client_order_id: 0,
+    }
+}
+
+/// Replaces the account at `index` in the instruction with `key`.
+fn with_account(mut instruction: Instruction, index: usize, key: Pubkey) -> Instruction {
+    instruction.accounts[index].pubkey = key;
+    instruction
+}
+
+/// Only keeps the register account, which the entrypoint expects last.
+fn without_accounts(mut instruction: Instruction) -> Instruction {
+    instruction.accounts.drain(..instruction.accounts.len() - 1);
+    instruction
+}
+
+async fn assert_error(
+    market: &mut MarketHarness<C>,
+    instruction: Instruction,
+    expected: InstructionError,
+) {
+    let error = market.send(vec![instruction], vec![]).await.unwrap_err();
+    assert_eq!(
+        error.unwrap(),
+        TransactionError::InstructionError(0, expected)
+    );
+}
+
+fn custom(error: AoError) -> InstructionError {
+    InstructionError::Custom(error as u32)
+}
+
+/// The instructions taking the market, event queue, bids and asks accounts fail on each mis-supplied account.
+#[tokio::test]
+async fn test_orderbook_account_errors() {
+    let mut market = MarketHarness::<C>::start(program_test(), market_params()).await;
+    let accounts = market.accounts;
+    let system_account = market.ctx.payer.pubkey();
+    let small_account = create_program_account(&mut market.ctx, 4).await;
+
+    let instructions = vec![
+        market.new_order_instruction(order_params()),
+        market.cancel_order_instruction(0),
+    ];
+    for instruction in instructions {
+        let owner_errors = [
+            (MARKET, AoError::WrongMarketOwner),
+            (EVENT_QUEUE, AoError::WrongEventQueueOwner),
+            (BIDS, AoError::WrongBidsOwner),
+            (ASKS, AoError::WrongAsksOwner),
+        ];
+        for (index, error) in owner_errors {
+            let instruction = with_account(instruction.clone(), index, system_account);
+            assert_error(&mut market, instruction, custom(error)).await;
+        }
+
+        let key_errors = [
+            (EVENT_QUEUE, accounts.bids, AoError::WrongEventQueueAccount),
+            (BIDS, accounts.asks, AoError::WrongBidsAccount),
+            (ASKS, accounts.bids, AoError::WrongAsksAccount),
+        ];
+        for (index, key, error) in key_errors {
+            let instruction = with_account(instruction.clone(), index, key);
+            assert_error(&mut market, instruction, custom(error)).await;
+        }
+
+        let market_errors = [
+            (accounts.event_queue, InstructionError::InvalidAccountData),
+            (small_account, InstructionError::AccountDataTooSmall),
+        ];
+        for (key, error) in market_errors {
+            let instruction = with_account(instruction.clone(), MARKET, key);
+            assert_error(&mut market, instruction, error).await;
+        }
+
+        assert_error(
+            &mut market,
+            without_accounts(instruction),
+            InstructionError::NotEnoughAccountKeys,
+        )
+        .await;
+    }
+}
+
+/// The consume_events instruction fails on each mis-supplied account.
+#[tokio::test]
+async fn test_consume_events_account_errors() {
+    let mut market = MarketHarness::<C>::start(program_test(), market_params()).await;
+    let accounts = market.accounts;
+    let system_account = market.ctx.payer.pubkey();
+    let small_account = create_program_account(&mut market.ctx, 4).await;
+    let instruction = market.consume_events_instruction(1);
+
+    let errors = [
+        (MARKET, system_account, custom(AoError::WrongMarketOwner)),
+        (
+            EVENT_QUEUE,
+            system_account,
+            custom(AoError::WrongEventQueueOwner),
+        ),
+        (
+            EVENT_QUEUE,
+            accounts.bids,
+            custom(AoError::WrongEventQueueAccount),
+        ),
+        (
+            MARKET,
+            accounts.event_queue,
+            InstructionError::InvalidAccountData,
+        ),
+        (MARKET, small_account, InstructionError::AccountDataTooSmall),
+    ];
+    for (index, key, error) in errors {
+        let instruction = with_account(instruction.clone(), index, key);
+        assert_error(&mut market, instruction, error).await;
+    }
+
+    assert_error(
+        &mut market,
+        without_accounts(instruction),
+        InstructionError::NotEnoughAccountKeys,
+    )
+    .await;
+}
@@ -0,0 +1,138 @@
+use agnostic_orderbook::error::AoError;
+use agnostic_orderbook::instruction::{create_allowlist, new_order, update_allowlist};
+use agnostic_orderbook::state::allowlist::{allowlist_entry, Allowlist};
+use agnostic_orderbook::state::{PostOnlyMode, SelfTradeBehavior, Side};
+use agnostic_orderbook::test_utils::{create_program_account, program_test, MarketHarness};
+use bonfida_utils::BorshSize;
+use borsh::{BorshDeserialize, BorshSerialize};
+use solana_program::instruction::{AccountMeta, Instruction, InstructionError};
+use solana_program::pubkey::Pubkey;
+use solana_sdk::transaction::TransactionError;
+pub mod common;
+use crate::common::utils::market_params;
+
+#[derive(BorshDeserialize, BorshSerialize, Clone, Copy)]
+#[repr(transparent)]
+pub struct C([u8; 32]);
+
+unsafe impl bytemuck::Zeroable for C {}
+unsafe impl bytemuck::Pod for C {}
+
+impl BorshSize for C {
+    fn borsh_len(&self) -> usize {
+        32
+    }
+}
+
+/// The index of the allowlist in the new_order accounts, right after the asks account.
+const ALLOWLIST: usize = 4;
+
+fn order_params(callback_info: C) -> new_order::Params<C> {
+    new_order::Params {
+        max_base_qty: 1000,
+        max_quote_qty: u64::MAX,
+        limit_price: 10 << 32,
+        side: Side::Bid,
+        callback_info,
+        post_only: false,
+        post_allowed: true,
+        self_trade_behavior: SelfTradeBehavior::DecrementTake,
+        match_limit: 10,
+        max_ts: u64::MAX,
+        immediate_or_cancel: false,
+        fill_or_kill: false,
+        max_display_qty: 0,
+        post_only_mode: PostOnlyMode::Reject,
+        max_slippage: 0,
+        flags: 0,
+        min_base_qty: 0,
+        compute_unit_reserve: 0,
+        resumable: false,
+        worst_price: 0,
+        referral: [0; 32],
+        client_order_id: 0,
+    }
+}
+
+/// Builds a new_order instruction for the market which is given the allowlist account.
+fn new_order_instruction(
+    market: &MarketHarness<C>,
+    allowlist: &Pubkey,
+    callback_info: C,
+) -> Instruction {
+    let mut instruction = market.new_order_instruction(order_params(callback_info));
+    instruction
+        .accounts
+        .insert(ALLOWLIST, AccountMeta::new_readonly(*allowlist, false));
+    instruction
+}
+
+fn update_allowlist_instruction(
+    market: &MarketHarness<C>,
+    allowlist: &Pubkey,
+    callback_info: &C,
+    allowed: bool,
+) -> Instruction {
+    update_allowlist(
+        update_allowlist::Accounts {
+            market: &market.accounts.market,
+            allowlist,
+        },
+        market.register_account,
+        update_allowlist::Params {
+            entry: allowlist_entry(callback_info),
+            allowed,
+        },
+    )
+}
+
+async fn assert_error(market: &mut MarketHarness<C>, instruction: Instruction, expected: AoError) {
+    let error = market.send(vec![instruction], vec![]).await.unwrap_err();
+    assert_eq!(
+        error.unwrap(),
+        TransactionError::InstructionError(0, InstructionError::Custom(expected as u32))
+    );
+}
+
+/// Only the callback infos in the allowlist of a permissioned market can place orders.
+#[tokio::test]
+async fn test_allowlist() {
+    let mut market = MarketHarness::<C>::start(program_test(), market_params()).await;
+    let allowlist =
+        create_program_account(&mut market.ctx, Allowlist::compute_allocation_size(4)).await;
+    let instruction = create_allowlist(
+        create_allowlist::Accounts {
+            market: &market.accounts.market,
+            allowlist: &allowlist,
+        },
+        market.register_account,
+        create_allowlist::Params {},
+    );
+    market.send(vec![instruction], vec![]).await.unwrap();
+    assert_eq!(market.market_state().await.order_allowlist, allowlist);
+
+    let user = C(Pubkey::new_unique().to_bytes());
+
+    // The allowlist must be given once the market has one
+    let instruction = market.new_order_instruction(order_params(user));
+    assert_error(&mut market, instruction, AoError::WrongAllowlistAccount).await;
+
+    let instruction = new_order_instruction(&market, &allowlist, user);
+    assert_error(&mut market, instruction, AoError::OrderNotAllowlisted).await;
+
+    let instruction = update_allowlist_instruction(&market, &allowlist, &user, true);
+    market.send(vec![instruction], vec![]).await.unwrap();
+    let instruction = new_order_instruction(&market, &allowlist, user);
+    market.send(vec![instruction], vec![]).await.unwrap();
+    assert!(market
+        .order_summary()
+        .await
+        .unwrap()
+        .posted_order_id
+        .is_some());
+
+    let instruction = update_allowlist_instruction(&market, &allowlist, &user, false);
+    market.send(vec![instruction], vec![]).await.unwrap();
+    let instruction = new_order_instruction(&market, &allowlist, user);
+    assert_error(&mut market, instruction, AoError::OrderNotAllowlisted).await;
+}
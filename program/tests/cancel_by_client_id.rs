@@ -0,0 +1,121 @@
+use agnostic_orderbook::error::AoError;
+use agnostic_orderbook::instruction::{cancel_by_client_id, new_order};
+use agnostic_orderbook::state::orderbook::read_cancelled_order;
+use agnostic_orderbook::state::{PostOnlyMode, SelfTradeBehavior, Side};
+use agnostic_orderbook::test_utils::{program_test, MarketHarness};
+use bonfida_utils::BorshSize;
+use borsh::{BorshDeserialize, BorshSerialize};
+use solana_program::instruction::{Instruction, InstructionError};
+use solana_sdk::transaction::TransactionError;
+pub mod common;
+use crate::common::utils::market_params;
+
+#[derive(BorshDeserialize, BorshSerialize, Clone, Copy, Debug, PartialEq)]
+#[repr(transparent)]
+pub struct C([u8; 32]);
+
+unsafe impl bytemuck::Zeroable for C {}
+unsafe impl bytemuck::Pod for C {}
+
+impl BorshSize for C {
+    fn borsh_len(&self) -> usize {
+        32
+    }
+}
+
+fn order_params(
+    limit_price: u64,
+    owner: u8,
+    client_order_id: u64,
+    resumable: bool,
+) -> new_order::Params<C> {
+    new_order::Params {
+        max_base_qty: 1000,
+        max_quote_qty: u64::MAX,
+        limit_price,
+        side: Side::Bid,
+        callback_info: C([owner; 32]),
+        post_only: false,
+        post_allowed: true,
+        self_trade_behavior: SelfTradeBehavior::DecrementTake,
+        match_limit: 10,
+        max_ts: u64::MAX,
+        immediate_or_cancel: false,
+        fill_or_kill: false,
+        max_display_qty: 0,
+        post_only_mode: PostOnlyMode::Reject,
+        max_slippage: 0,
+        flags: 0,
+        min_base_qty: 0,
+        compute_unit_reserve: 0,
+        resumable,
+        worst_price: 0,
+        referral: [0; 32],
+        client_order_id,
+    }
+}
+
+fn cancel_instruction(market: &MarketHarness<C>, owner: u8, client_order_id: u64) -> Instruction {
+    cancel_by_client_id(
+        cancel_by_client_id::Accounts {
+            market: &market.accounts.market,
+            event_queue: &market.accounts.event_queue,
+            bids: &market.accounts.bids,
+            asks: &market.accounts.asks,
+        },
+        market.register_account,
+        cancel_by_client_id::Params {
+            client_order_id,
+            callback_info: vec![owner; 32],
+        },
+    )
+}
+
+/// An order is cancelled by the client order id attached to it by its owner, without its order id.
+#[tokio::test]
+async fn test_cancel_by_client_id() {
+    let mut market = MarketHarness::<C>::start(program_test(), market_params()).await;
+    // Both owners use the same client order id
+    for (price, owner) in [(10, 1), (9, 2)] {
+        market
+            .new_order(order_params(price << 32, owner, 7, false))
+            .await
+            .unwrap();
+    }
+
+    let instruction = cancel_instruction(&market, 2, 7);
+    market.send(vec![instruction], vec![]).await.unwrap();
+    let register = market.account_data(market.register_account).await;
+    let cancelled_order = read_cancelled_order::<C>(&register).unwrap();
+    assert_eq!(cancelled_order.price, 9 << 32);
+    assert_eq!(cancelled_order.callback_info, C([2; 32]));
+    assert_eq!(cancelled_order.summary.total_base_qty, 1000);
+    let bids = market.orders(Side::Bid).await;
+    assert_eq!(bids.len(), 1);
+    assert_eq!(bids[0].1, C([1; 32]));
+
+    for (owner, client_order_id) in [(2, 7), (1, 8), (1, 0)] {
+        let instruction = cancel_instruction(&market, owner, client_order_id);
+        let error = market.send(vec![instruction], vec![]).await.unwrap_err();
+        assert_eq!(
+            error.unwrap(),
+            TransactionError::InstructionError(
+                0,
+                InstructionError::Custom(AoError::OrderNotFound as u32)
+            )
+        );
+    }
+
+    // A resumable order can't have a client order id
+    let error = market
+        .new_order(order_params(10 << 32, 3, 7, true))
+        .await
+        .unwrap_err();
+    assert_eq!(
+        error.unwrap(),
+        TransactionError::InstructionError(
+            0,
+            InstructionError::Custom(AoError::InvalidClientOrderId as u32)
+        )
+    );
+}
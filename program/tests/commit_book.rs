@@ -0,0 +1,89 @@
+use agnostic_orderbook::instruction::{commit_book, new_order};
+use agnostic_orderbook::state::critbit::read_slab_orders;
+use agnostic_orderbook::state::merkle::prove_order;
+use agnostic_orderbook::state::{AccountTag, PostOnlyMode, SelfTradeBehavior, Side};
+use agnostic_orderbook::test_utils::{program_test, MarketHarness};
+use bonfida_utils::BorshSize;
+use borsh::{BorshDeserialize, BorshSerialize};
+use solana_program::pubkey::Pubkey;
+pub mod common;
+use crate::common::utils::market_params;
+
+#[derive(BorshDeserialize, BorshSerialize, Clone, Copy)]
+#[repr(transparent)]
+pub struct C([u8; 32]);
+
+unsafe impl bytemuck::Zeroable for C {}
+unsafe impl bytemuck::Pod for C {}
+
+impl BorshSize for C {
+    fn borsh_len(&self) -> usize {
+        32
+    }
+}
+
+fn order_params(limit_price: u64, max_base_qty: u64) -> new_order::Params<C> {
+    new_order::Params {
+        max_base_qty,
+        max_quote_qty: u64::MAX,
+        limit_price,
+        side: Side::Bid,
+        callback_info: C(Pubkey::new_unique().to_bytes()),
+        post_only: false,
+        post_allowed: true,
+        self_trade_behavior: SelfTradeBehavior::DecrementTake,
+        match_limit: 10,
+        max_ts: u64::MAX,
+        immediate_or_cancel: false,
+        fill_or_kill: false,
+        max_display_qty: 0,
+        post_only_mode: PostOnlyMode::Reject,
+        max_slippage: 0,
+        flags: 0,
+        min_base_qty: 0,
+        compute_unit_reserve: 0,
+        resumable: false,
+        worst_price: 0,
+        referral: [0; 32],
+        client_order_id: 0,
+    }
+}
+
+/// Each resting order can be proven against the Merkle roots committed by commit_book.
+#[tokio::test]
+async fn test_commit_book() {
+    let mut market = MarketHarness::<C>::start(program_test(), market_params()).await;
+    for (price, qty) in [(10, 1000), (10, 2000), (9, 3000)] {
+        market
+            .new_order(order_params(price << 32, qty))
+            .await
+            .unwrap();
+    }
+
+    let instruction = commit_book(
+        commit_book::Accounts {
+            market: &market.accounts.market,
+            bids: &market.accounts.bids,
+            asks: &market.accounts.asks,
+        },
+        market.register_account,
+        commit_book::Params {},
+    );
+    market.send(vec![instruction], vec![]).await.unwrap();
+    let market_state = market.market_state().await;
+    assert_ne!(market_state.bids_root, [0; 32]);
+    assert_eq!(market_state.asks_root, [0; 32]);
+
+    let bids = market.account_data(market.accounts.bids).await;
+    let leaves = read_slab_orders(&bids, AccountTag::Bids, 32, true)
+        .unwrap()
+        .into_iter()
+        .map(|(leaf, _)| leaf)
+        .collect::<Vec<_>>();
+    assert_eq!(leaves.len(), 3);
+    for leaf in leaves.iter() {
+        let proof = prove_order(&leaves, leaf.order_id()).unwrap();
+        assert!(proof.verify(&market_state.bids_root));
+        assert!(!proof.verify(&market_state.asks_root));
+    }
+}
@@ -0,0 +1,187 @@
+//! Measures the compute units consumed by the main instructions of the program, and fails when they regress.
+//!
+//! The program is loaded from its BPF build, so it must be built beforehand with the `entrypoint` feature, and the
+//! test run with `cargo test --test compute_units --features test-bpf`.
+//!
+//! The measurements are compared to the baseline stored in `benches/compute_units.json`. The test fails when an
+//! instruction consumes more than `CU_REGRESSION_THRESHOLD_PCT` percent (5 by default) above its baseline, or when an
+//! instruction has no baseline. The baseline is rewritten when `UPDATE_CU_BASELINE` is set.
+#![cfg(feature = "test-bpf")]
+use agnostic_orderbook::instruction::new_order;
+use agnostic_orderbook::state::{PostOnlyMode, SelfTradeBehavior, Side};
+use agnostic_orderbook::test_utils::MarketHarness;
+use bonfida_utils::BorshSize;
+use borsh::{BorshDeserialize, BorshSerialize};
+use solana_program::instruction::Instruction;
+use solana_program::pubkey::Pubkey;
+use solana_program_test::ProgramTest;
+use solana_sdk::signature::Signer;
+use solana_sdk::transaction::Transaction;
+use std::collections::BTreeMap;
+pub mod common;
+use crate::common::utils::market_params;
+
+const BASELINE_PATH: &str = concat!(env!("CARGO_MANIFEST_DIR"), "/benches/compute_units.json");
+const DEFAULT_THRESHOLD_PCT: u64 = 5;
+
+#[derive(BorshDeserialize, BorshSerialize, Clone, Copy)]
+#[repr(transparent)]
+pub struct C([u8; 32]);
+
+unsafe impl bytemuck::Zeroable for C {}
+unsafe impl bytemuck::Pod for C {}
+
+impl BorshSize for C {
+    fn borsh_len(&self) -> usize {
+        32
+    }
+}
+
+type Market = MarketHarness<C>;
+
+async fn start_market() -> Market {
+    let mut program_test = ProgramTest::new("agnostic_orderbook", agnostic_orderbook::ID, None);
+    program_test.prefer_bpf(true);
+    MarketHarness::start(program_test, market_params()).await
+}
+
+fn order(
+    side: Side,
+    max_base_qty: u64,
+    limit_price: u64,
+    match_limit: u64,
+) -> new_order::Params<C> {
+    new_order::Params {
+        max_base_qty,
+        max_quote_qty: u64::MAX,
+        limit_price,
+        side,
+        callback_info: C(Pubkey::new_unique().to_bytes()),
+        post_only: false,
+        post_allowed: true,
+        self_trade_behavior: SelfTradeBehavior::DecrementTake,
+        match_limit,
+        max_ts: u64::MAX,
+        immediate_or_cancel: false,
+        fill_or_kill: false,
+        max_display_qty: 0,
+        post_only_mode: PostOnlyMode::Reject,
+        max_slippage: 0,
+        flags: 0,
+        min_base_qty: 0,
+        compute_unit_reserve: 0,
+        resumable: false,
+        worst_price: 0,
+        referral: [0; 32],
+        client_order_id: 0,
+    }
+}
+
+/// Posts `n` asks of 1000 base lots at 10 quote lots per base lot
+async fn post_asks(market: &mut Market, n: u64) {
+    for _ in 0..n {
+        market
+            .new_order(order(Side::Ask, 1_000, 10 << 32, 1))
+            .await
+            .unwrap();
+    }
+}
+
+/// Sends an instruction in its own transaction and returns the number of compute units it consumed
+async fn measure(market: &mut Market, instruction: Instruction) -> u64 {
+    let ctx = &mut market.ctx;
+    let mut transaction = Transaction::new_with_payer(&[instruction], Some(&ctx.payer.pubkey()));
+    transaction.sign(&[&ctx.payer], ctx.last_blockhash);
+    let result = ctx
+        .banks_client
+        .process_transaction_with_metadata(transaction)
+        .await
+        .unwrap();
+    result.result.unwrap();
+    result.metadata.unwrap().compute_units_consumed
+}
+
+async fn measure_all() -> BTreeMap<String, u64> {
+    let mut results = BTreeMap::new();
+
+    for n in [0, 1, 5, 20].iter() {
+        let mut market = start_market().await;
+        post_asks(&mut market, *n).await;
+        // Without any ask, the bid is posted into the orderbook
+        let instruction =
+            market.new_order_instruction(order(Side::Bid, 1_000 * (*n).max(1), 10 << 32, *n));
+        let units = measure(&mut market, instruction).await;
+        results.insert(format!("new_order_{}_matches", n), units);
+    }
+
+    let mut market = start_market().await;
+    let order_id = market
+        .new_order(order(Side::Bid, 1_000, 10 << 32, 1))
+        .await
+        .unwrap()
+        .unwrap()
+        .posted_order_id
+        .unwrap();
+    let instruction = market.cancel_order_instruction(order_id);
+    results.insert(
+        "cancel_order".to_owned(),
+        measure(&mut market, instruction).await,
+    );
+
+    let mut market = start_market().await;
+    post_asks(&mut market, 5).await;
+    market
+        .new_order(order(Side::Bid, 5_000, 10 << 32, 5))
+        .await
+        .unwrap();
+    let instruction = market.consume_events_instruction(10);
+    results.insert(
+        "consume_events_10".to_owned(),
+        measure(&mut market, instruction).await,
+    );
+    results
+}
+
+/// The compute units consumed by each instruction stay within the threshold above their baseline.
+#[tokio::test]
+async fn test_compute_units() {
+    let results = measure_all().await;
+    for (name, units) in results.iter() {
+        println!("{:<24} {:>8} CU", name, units);
+    }
+
+    if std::env::var("UPDATE_CU_BASELINE").is_ok() {
+        std::fs::write(
+            BASELINE_PATH,
+            serde_json::to_string_pretty(&results).unwrap() + "\n",
+        )
+        .unwrap();
+        println!("Compute unit baseline written to {}", BASELINE_PATH);
+        return;
+    }
+    let baseline = std::fs::read_to_string(BASELINE_PATH).unwrap_or_else(|_| {
+        panic!(
+            "No compute unit baseline at {}, run the test with UPDATE_CU_BASELINE set to write it",
+            BASELINE_PATH
+        )
+    });
+    let baseline = serde_json::from_str::<BTreeMap<String, u64>>(&baseline).unwrap();
+
+    let threshold_pct = std::env::var("CU_REGRESSION_THRESHOLD_PCT")
+        .map(|t| t.parse().unwrap())
+        .unwrap_or(DEFAULT_THRESHOLD_PCT);
+    let mut regressions = vec![];
+    for (name, units) in results.iter() {
+        match baseline.get(name) {
+            Some(baseline_units) if *units * 100 > *baseline_units * (100 + threshold_pct) => {
+                regressions.push(format!(
+                    "{} regressed from {} to {} CU, above the {}% threshold",
+                    name, baseline_units, units, threshold_pct
+                ))
+            }
+            Some(_) => {}
+            None => regressions.push(format!("{} has no baseline", name)),
+        }
+    }
+    assert!(regressions.is_empty(), "{}", regressions.join("\n"));
+}
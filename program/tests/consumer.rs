@@ -0,0 +1,209 @@
+use agnostic_orderbook::error::AoError;
+use agnostic_orderbook::instruction::consume_events::{
+    find_consumer_authority, HANDLE_EVENT_DISCRIMINATOR,
+};
+use agnostic_orderbook::instruction::{consume_events, new_order};
+use agnostic_orderbook::state::event_queue::read_consumed_events;
+use agnostic_orderbook::state::{PostOnlyMode, SelfTradeBehavior, Side};
+use agnostic_orderbook::test_utils::{program_test, MarketHarness};
+use bonfida_utils::BorshSize;
+use borsh::{BorshDeserialize, BorshSerialize};
+use solana_program::account_info::AccountInfo;
+use solana_program::entrypoint::ProgramResult;
+use solana_program::instruction::{Instruction, InstructionError};
+use solana_program::program_error::ProgramError;
+use solana_program::pubkey::Pubkey;
+use solana_program_test::processor;
+use solana_sdk::account::Account;
+use solana_sdk::transaction::TransactionError;
+use std::convert::TryInto;
+pub mod common;
+use crate::common::utils::market_params;
+
+#[derive(BorshDeserialize, BorshSerialize, Clone, Copy)]
+#[repr(transparent)]
+pub struct C([u8; 32]);
+
+unsafe impl bytemuck::Zeroable for C {}
+unsafe impl bytemuck::Pod for C {}
+
+impl BorshSize for C {
+    fn borsh_len(&self) -> usize {
+        32
+    }
+}
+
+const CONSUMER_PROGRAM: Pubkey = Pubkey::new_from_array([42; 32]);
+
+/// Counts the handle_event calls and the events they carry into its record account.
+fn process_handle_event(
+    _program_id: &Pubkey,
+    accounts: &[AccountInfo],
+    instruction_data: &[u8],
+) -> ProgramResult {
+    let (authority, market, record) = match accounts {
+        [authority, market, record, ..] => (authority, market, record),
+        _ => return Err(ProgramError::NotEnoughAccountKeys),
+    };
+    let (expected_authority, _) = find_consumer_authority(&agnostic_orderbook::ID, market.key);
+    if !authority.is_signer || authority.key != &expected_authority {
+        return Err(ProgramError::MissingRequiredSignature);
+    }
+    let events = instruction_data
+        .strip_prefix(&HANDLE_EVENT_DISCRIMINATOR)
+        .ok_or(ProgramError::InvalidInstructionData)?;
+    let events = read_consumed_events::<C>(events)?;
+
+    let mut record = record.data.borrow_mut();
+    let calls = u64::from_le_bytes(record[..8].try_into().unwrap()) + 1;
+    let total = u64::from_le_bytes(record[8..16].try_into().unwrap()) + events.len() as u64;
+    record[..8].copy_from_slice(&calls.to_le_bytes());
+    record[8..16].copy_from_slice(&total.to_le_bytes());
+    Ok(())
+}
+
+fn order_params(side: Side) -> new_order::Params<C> {
+    new_order::Params {
+        max_base_qty: 1000,
+        max_quote_qty: u64::MAX,
+        limit_price: 10 << 32,
+        side,
+        callback_info: C(Pubkey::new_unique().to_bytes()),
+        post_only: false,
+        post_allowed: true,
+        self_trade_behavior: SelfTradeBehavior::DecrementTake,
+        match_limit: 10,
+        max_ts: u64::MAX,
+        immediate_or_cancel: false,
+        fill_or_kill: false,
+        max_display_qty: 0,
+        post_only_mode: PostOnlyMode::Reject,
+        max_slippage: 0,
+        flags: 0,
+        min_base_qty: 0,
+        compute_unit_reserve: 0,
+        resumable: false,
+        worst_price: 0,
+        referral: [0; 32],
+        client_order_id: 0,
+    }
+}
+
+/// Starts a market whose consumer program records the events it is sent into `record`.
+async fn start_market(record: Pubkey) -> MarketHarness<C> {
+    let mut program_test = program_test();
+    program_test.add_program(
+        "consumer",
+        CONSUMER_PROGRAM,
+        processor!(process_handle_event),
+    );
+    program_test.add_account(
+        record,
+        Account {
+            lamports: 1_000_000_000,
+            data: vec![0; 16],
+            owner: CONSUMER_PROGRAM,
+            ..Account::default()
+        },
+    );
+    let mut params = market_params();
+    params.consumer_program = CONSUMER_PROGRAM;
+    params.max_events_per_consumer_call = 1;
+    MarketHarness::start(program_test, params).await
+}
+
+fn consume_events_instruction(
+    market: &MarketHarness<C>,
+    consumer_program: Option<&Pubkey>,
+    consumer_authority: Option<&Pubkey>,
+    consumer_accounts: &[Pubkey],
+) -> Instruction {
+    consume_events(
+        consume_events::Accounts {
+            market: &market.accounts.market,
+            event_queue: &market.accounts.event_queue,
+            consumer_program,
+            consumer_authority,
+            consumer_accounts,
+        },
+        market.register_account,
+        consume_events::Params {
+            number_of_entries_to_consume: 10,
+            max_seq_num: u64::MAX,
+            write_to_register: false,
+            queue_index: 0,
+        },
+    )
+}
+
+/// The consumed events are pushed into the consumer program, one call per event.
+#[tokio::test]
+async fn test_consumer_program() {
+    let record = Pubkey::new_unique();
+    let mut market = start_market(record).await;
+    market.new_order(order_params(Side::Bid)).await.unwrap();
+    market.new_order(order_params(Side::Ask)).await.unwrap();
+    let event_count = market.event_count().await;
+    assert!(event_count > 1);
+
+    let (consumer_authority, _) =
+        find_consumer_authority(&agnostic_orderbook::ID, &market.accounts.market);
+    let instruction = consume_events_instruction(
+        &market,
+        Some(&CONSUMER_PROGRAM),
+        Some(&consumer_authority),
+        &[record],
+    );
+    market.send(vec![instruction], vec![]).await.unwrap();
+
+    assert_eq!(market.event_count().await, 0);
+    let data = market.account_data(record).await;
+    let calls = u64::from_le_bytes(data[..8].try_into().unwrap());
+    let events = u64::from_le_bytes(data[8..16].try_into().unwrap());
+    assert_eq!(calls, event_count);
+    assert_eq!(events, event_count);
+}
+
+/// The events of a market with a consumer program can't be consumed without it or with another authority.
+#[tokio::test]
+async fn test_consumer_account_errors() {
+    let record = Pubkey::new_unique();
+    let mut market = start_market(record).await;
+    market.new_order(order_params(Side::Bid)).await.unwrap();
+    market.new_order(order_params(Side::Ask)).await.unwrap();
+
+    let (consumer_authority, _) =
+        find_consumer_authority(&agnostic_orderbook::ID, &market.accounts.market);
+    let wrong_key = Pubkey::new_unique();
+    let cases = [
+        (
+            consume_events_instruction(&market, None, None, &[]),
+            AoError::WrongConsumerProgram,
+        ),
+        (
+            consume_events_instruction(
+                &market,
+                Some(&wrong_key),
+                Some(&consumer_authority),
+                &[record],
+            ),
+            AoError::WrongConsumerProgram,
+        ),
+        (
+            consume_events_instruction(
+                &market,
+                Some(&CONSUMER_PROGRAM),
+                Some(&wrong_key),
+                &[record],
+            ),
+            AoError::WrongConsumerAuthority,
+        ),
+    ];
+    for (instruction, expected) in cases {
+        let error = market.send(vec![instruction], vec![]).await.unwrap_err();
+        assert_eq!(
+            error.unwrap(),
+            TransactionError::InstructionError(0, InstructionError::Custom(expected as u32))
+        );
+    }
+}
@@ -4,9 +4,10 @@ use agnostic_orderbook::{
     state::{
         critbit::Slab,
         event_queue::EventQueue,
-        market_state::MarketState,
+        market_state::{MarketState, PendingTaker, MARKET_METADATA_LEN, MAX_EVENT_QUEUE_SHARDS},
         orderbook::{CallbackInfo, OrderBookState},
-        AccountTag, SelfTradeBehavior, Side,
+        AccountTag, BootingPolicy, MatchingMode, PostOnlyMode, SelfTradeBehavior, Side,
+        TradingStatus, ORDER_SUMMARY_SIZE,
     },
 };
 use bonfida_utils::{bench::get_env_arg, BorshSize};
@@ -52,6 +53,8 @@ async fn run(ctx: Context) {
             event_queue: &event_queue,
             bids: &bids,
             asks: &asks,
+            allowlist: None,
+            event_queue_shards: &[],
         },
         register,
         new_order::Params {
@@ -65,6 +68,18 @@ async fn run(ctx: Context) {
             post_allowed: true,
             self_trade_behavior: SelfTradeBehavior::DecrementTake,
             max_ts: u64::MAX,
+            immediate_or_cancel: false,
+            fill_or_kill: false,
+            max_display_qty: 0,
+            post_only_mode: PostOnlyMode::Reject,
+            max_slippage: 0,
+            flags: 0,
+            min_base_qty: 0,
+            compute_unit_reserve: 0,
+            resumable: false,
+            worst_price: 0,
+            referral: [0; 32],
+            client_order_id: 0,
         },
     );
     sign_send_instructions(&mut ctx, vec![instruction], vec![])
@@ -135,8 +150,43 @@ async fn prepare() -> Context {
             asks: asks_key,
             min_base_order_size: 1,
             tick_size: 1,
+            stop_orders: Pubkey::default(),
+            last_trade_price: 0,
+            pegged_orders: Pubkey::default(),
+            min_quote_order_size: 0,
+            booting_policy: BootingPolicy::BootLeastAggressive as u64,
+            split_fills: 0,
+            dead_letter_queue: Pubkey::default(),
+            max_event_age_slots: 0,
+            log_events: 0,
+            event_queue_shards: [Pubkey::default(); MAX_EVENT_QUEUE_SHARDS],
+            status: TradingStatus::Active as u64,
+            metadata: [0; MARKET_METADATA_LEN],
+            market_registry: Pubkey::default(),
+            quote_scale_exponent: 0,
+            max_price_deviation_bps: 0,
+            cumulative_base_volume: 0,
+            cumulative_quote_volume: 0,
+            cumulative_price_time: [0; 2],
+            last_accumulator_update_ts: 0,
+            matching_mode: MatchingMode::Continuous as u64,
+            pending_taker: PendingTaker::zeroed(),
+            maker_fee_bps: 0,
+            taker_fee_bps: 0,
+            accrued_maker_fees: 0,
+            accrued_taker_fees: 0,
+            max_orders_per_owner: 0,
+            consumer_program: Pubkey::default(),
+            max_events_per_consumer_call: 0,
+            order_allowlist: Pubkey::default(),
+            bids_root: [0; 32],
+            asks_root: [0; 32],
+            book_commit_slot: 0,
+            event_queue_full_policy: 0,
         }
     }
+    let market_state =
+        MarketState::from_buffer_mut(&mut market_state_buffer, AccountTag::Market).unwrap();
     let asks_slab = Slab::<C>::from_buffer(&mut asks_buffer, AccountTag::Asks).unwrap();
     let bids_slab = Slab::<C>::from_buffer(&mut bids_buffer, AccountTag::Bids).unwrap();
     let mut orderbook = OrderBookState::<C> {
@@ -163,9 +213,21 @@ async fn prepare() -> Context {
                     post_allowed: true,
                     self_trade_behavior: SelfTradeBehavior::DecrementTake,
                     max_ts: u64::MAX,
+                    immediate_or_cancel: false,
+                    fill_or_kill: false,
+                    max_display_qty: 0,
+                    post_only_mode: PostOnlyMode::Reject,
+                    max_slippage: 0,
+                    flags: 0,
+                    min_base_qty: 0,
+                    compute_unit_reserve: 0,
+                    resumable: false,
+                    worst_price: 0,
+                    referral: [0; 32],
+                    client_order_id: 0,
                 },
                 &mut event_queue,
-                1,
+                market_state,
                 0,
             )
             .unwrap();
@@ -183,9 +245,21 @@ async fn prepare() -> Context {
                     post_allowed: true,
                     self_trade_behavior: SelfTradeBehavior::DecrementTake,
                     max_ts: u64::MAX,
+                    immediate_or_cancel: false,
+                    fill_or_kill: false,
+                    max_display_qty: 0,
+                    post_only_mode: PostOnlyMode::Reject,
+                    max_slippage: 0,
+                    flags: 0,
+                    min_base_qty: 0,
+                    compute_unit_reserve: 0,
+                    resumable: false,
+                    worst_price: 0,
+                    referral: [0; 32],
+                    client_order_id: 0,
                 },
                 &mut event_queue,
-                1,
+                market_state,
                 0,
             )
             .unwrap();
@@ -212,7 +286,7 @@ async fn prepare() -> Context {
         (event_queue_key, event_queue_buffer),
         (bids_key, bids_buffer),
         (asks_key, asks_buffer),
-        (register_key, vec![0; 42]),
+        (register_key, vec![0; ORDER_SUMMARY_SIZE as usize + 1]),
     ];
 
     for (k, data) in accounts_to_add.into_iter() {
@@ -1,6 +1,7 @@
 use agnostic_orderbook::instruction::{cancel_order, close_market, consume_events, new_order};
-use agnostic_orderbook::state::{market_state::MarketState, OrderSummary};
-use agnostic_orderbook::state::{AccountTag, SelfTradeBehavior, Side};
+use agnostic_orderbook::state::orderbook::read_cancelled_order;
+use agnostic_orderbook::state::{market_state::MarketState, OrderSummary, ORDER_SUMMARY_SIZE};
+use agnostic_orderbook::state::{AccountTag, PostOnlyMode, SelfTradeBehavior, Side};
 use bonfida_utils::BorshSize;
 use borsh::{BorshDeserialize, BorshSerialize};
 use solana_program::program_option::COption;
@@ -56,7 +57,7 @@ async fn test_agnostic_orderbook() {
         register_account,
         Account {
             lamports: 1_000_000,
-            data: vec![0; 42],
+            data: vec![0; ORDER_SUMMARY_SIZE as usize + 1],
             owner: agnostic_orderbook::ID,
             ..Account::default()
         },
@@ -84,9 +85,7 @@ async fn test_agnostic_orderbook() {
     .unwrap();
 
     println!("Market Account: {}", market_account.pubkey());
-    let market_account =
-        create_market_and_accounts(&mut prg_test_ctx, register_account, agnostic_orderbook::ID)
-            .await;
+    let market_account = create_market_and_accounts(&mut prg_test_ctx, register_account).await;
 
     let mut market_state_data = prg_test_ctx
         .banks_client
@@ -120,6 +119,8 @@ async fn test_agnostic_orderbook() {
             event_queue: &market_state.event_queue,
             bids: &market_state.bids,
             asks: &market_state.asks,
+            allowlist: None,
+            event_queue_shards: &[],
         },
         register_account,
         new_order::Params {
@@ -133,6 +134,18 @@ async fn test_agnostic_orderbook() {
             self_trade_behavior: SelfTradeBehavior::CancelProvide,
             match_limit: 3,
             max_ts: u64::MAX,
+            immediate_or_cancel: false,
+            fill_or_kill: false,
+            max_display_qty: 0,
+            post_only_mode: PostOnlyMode::Reject,
+            max_slippage: 0,
+            flags: 0,
+            min_base_qty: 0,
+            compute_unit_reserve: 0,
+            resumable: false,
+            worst_price: 0,
+            referral: [0; 32],
+            client_order_id: 0,
         },
     );
 
@@ -161,6 +174,8 @@ async fn test_agnostic_orderbook() {
             event_queue: &market_state.event_queue,
             bids: &market_state.bids,
             asks: &market_state.asks,
+            allowlist: None,
+            event_queue_shards: &[],
         },
         register_account,
         new_order::Params {
@@ -174,6 +189,18 @@ async fn test_agnostic_orderbook() {
             self_trade_behavior: SelfTradeBehavior::CancelProvide,
             match_limit: 3,
             max_ts: u64::MAX,
+            immediate_or_cancel: false,
+            fill_or_kill: false,
+            max_display_qty: 0,
+            post_only_mode: PostOnlyMode::Reject,
+            max_slippage: 0,
+            flags: 0,
+            min_base_qty: 0,
+            compute_unit_reserve: 0,
+            resumable: false,
+            worst_price: 0,
+            referral: [0; 32],
+            client_order_id: 0,
         },
     );
     sign_send_instructions(&mut prg_test_ctx, vec![new_order_instruction], vec![])
@@ -215,6 +242,15 @@ async fn test_agnostic_orderbook() {
     sign_send_instructions(&mut prg_test_ctx, vec![cancel_order_instruction], vec![])
         .await
         .unwrap();
+    let register_data = prg_test_ctx
+        .banks_client
+        .get_account(register_account)
+        .await
+        .unwrap()
+        .unwrap()
+        .data;
+    let cancelled_order = read_cancelled_order::<[u8; 32]>(&register_data).unwrap();
+    assert_eq!(cancelled_order.price, 1000 << 32);
 
     // Create reward target account
     let reward_target = Keypair::new();
@@ -238,10 +274,16 @@ async fn test_agnostic_orderbook() {
         consume_events::Accounts {
             market: &market_account,
             event_queue: &market_state.event_queue,
+            consumer_program: None,
+            consumer_authority: None,
+            consumer_accounts: &[],
         },
         register_account,
         consume_events::Params {
             number_of_entries_to_consume: 10,
+            max_seq_num: u64::MAX,
+            write_to_register: false,
+            queue_index: 0,
         },
     );
     sign_send_instructions(&mut prg_test_ctx, vec![consume_events_instruction], vec![])
@@ -256,6 +298,7 @@ async fn test_agnostic_orderbook() {
             bids: &market_state.bids,
             asks: &market_state.asks,
             lamports_target_account: &reward_target.pubkey(),
+            registered_accounts: &[],
         },
         register_account,
         close_market::Params {},
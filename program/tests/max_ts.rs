@@ -1,6 +1,6 @@
 use agnostic_orderbook::instruction::{cancel_order, close_market, consume_events, new_order};
-use agnostic_orderbook::state::{market_state::MarketState, OrderSummary};
-use agnostic_orderbook::state::{AccountTag, SelfTradeBehavior, Side};
+use agnostic_orderbook::state::{market_state::MarketState, OrderSummary, ORDER_SUMMARY_SIZE};
+use agnostic_orderbook::state::{AccountTag, PostOnlyMode, SelfTradeBehavior, Side};
 use bonfida_utils::BorshSize;
 use borsh::{BorshDeserialize, BorshSerialize};
 use solana_program::clock::Clock;
@@ -56,7 +56,7 @@ async fn test_agnostic_orderbook() {
         register_account,
         Account {
             lamports: 1_000_000,
-            data: vec![0; 42],
+            data: vec![0; ORDER_SUMMARY_SIZE as usize + 1],
             owner: agnostic_orderbook::ID,
             ..Account::default()
         },
@@ -66,7 +66,7 @@ async fn test_agnostic_orderbook() {
         register_account2,
         Account {
             lamports: 1_000_000,
-            data: vec![0; 42],
+            data: vec![0; ORDER_SUMMARY_SIZE as usize + 1],
             owner: agnostic_orderbook::ID,
             ..Account::default()
         },
@@ -92,9 +92,7 @@ async fn test_agnostic_orderbook() {
     )
     .await
     .unwrap();
-    let market_account =
-        create_market_and_accounts(&mut prg_test_ctx, register_account, agnostic_orderbook::ID)
-            .await;
+    let market_account = create_market_and_accounts(&mut prg_test_ctx, register_account).await;
 
     let mut market_state_data = prg_test_ctx
         .banks_client
@@ -131,6 +129,8 @@ async fn test_agnostic_orderbook() {
             event_queue: &market_state.event_queue,
             bids: &market_state.bids,
             asks: &market_state.asks,
+            allowlist: None,
+            event_queue_shards: &[],
         },
         register_account,
         new_order::Params {
@@ -144,6 +144,18 @@ async fn test_agnostic_orderbook() {
             self_trade_behavior: SelfTradeBehavior::CancelProvide,
             match_limit: 3,
             max_ts: cur_ts + 10,
+            immediate_or_cancel: false,
+            fill_or_kill: false,
+            max_display_qty: 0,
+            post_only_mode: PostOnlyMode::Reject,
+            max_slippage: 0,
+            flags: 0,
+            min_base_qty: 0,
+            compute_unit_reserve: 0,
+            resumable: false,
+            worst_price: 0,
+            referral: [0; 32],
+            client_order_id: 0,
         },
     );
 
@@ -174,6 +186,8 @@ async fn test_agnostic_orderbook() {
             event_queue: &market_state.event_queue,
             bids: &market_state.bids,
             asks: &market_state.asks,
+            allowlist: None,
+            event_queue_shards: &[],
         },
         register_account,
         new_order::Params {
@@ -187,6 +201,18 @@ async fn test_agnostic_orderbook() {
             self_trade_behavior: SelfTradeBehavior::CancelProvide,
             match_limit: 3,
             max_ts: u64::MAX,
+            immediate_or_cancel: false,
+            fill_or_kill: false,
+            max_display_qty: 0,
+            post_only_mode: PostOnlyMode::Reject,
+            max_slippage: 0,
+            flags: 0,
+            min_base_qty: 0,
+            compute_unit_reserve: 0,
+            resumable: false,
+            worst_price: 0,
+            referral: [0; 32],
+            client_order_id: 0,
         },
     );
     sign_send_instructions(&mut prg_test_ctx, vec![new_order_instruction], vec![])
@@ -200,6 +226,8 @@ async fn test_agnostic_orderbook() {
             event_queue: &market_state.event_queue,
             bids: &market_state.bids,
             asks: &market_state.asks,
+            allowlist: None,
+            event_queue_shards: &[],
         },
         register_account2,
         new_order::Params {
@@ -213,6 +241,18 @@ async fn test_agnostic_orderbook() {
             self_trade_behavior: SelfTradeBehavior::CancelProvide,
             match_limit: 3,
             max_ts: u64::MAX,
+            immediate_or_cancel: false,
+            fill_or_kill: false,
+            max_display_qty: 0,
+            post_only_mode: PostOnlyMode::Reject,
+            max_slippage: 0,
+            flags: 0,
+            min_base_qty: 0,
+            compute_unit_reserve: 0,
+            resumable: false,
+            worst_price: 0,
+            referral: [0; 32],
+            client_order_id: 0,
         },
     );
 
@@ -318,10 +358,16 @@ async fn test_agnostic_orderbook() {
         consume_events::Accounts {
             market: &market_account,
             event_queue: &market_state.event_queue,
+            consumer_program: None,
+            consumer_authority: None,
+            consumer_accounts: &[],
         },
         register_account,
         consume_events::Params {
             number_of_entries_to_consume: 10,
+            max_seq_num: u64::MAX,
+            write_to_register: false,
+            queue_index: 0,
         },
     );
     sign_send_instructions(&mut prg_test_ctx, vec![consume_events_instruction], vec![])
@@ -336,6 +382,7 @@ async fn test_agnostic_orderbook() {
             bids: &market_state.bids,
             asks: &market_state.asks,
             lamports_target_account: &reward_target.pubkey(),
+            registered_accounts: &[],
         },
         register_account,
         close_market::Params {},
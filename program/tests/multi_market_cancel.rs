@@ -0,0 +1,132 @@
+use agnostic_orderbook::error::AoError;
+use agnostic_orderbook::instruction::{multi_market_cancel, new_order};
+use agnostic_orderbook::state::orderbook::read_order_summaries;
+use agnostic_orderbook::state::{PostOnlyMode, SelfTradeBehavior, Side};
+use agnostic_orderbook::test_utils::{
+    create_market_accounts, program_test, MarketAccounts, MarketHarness,
+};
+use bonfida_utils::BorshSize;
+use borsh::{BorshDeserialize, BorshSerialize};
+use solana_program::instruction::{Instruction, InstructionError};
+use solana_program::pubkey::Pubkey;
+use solana_sdk::transaction::TransactionError;
+pub mod common;
+use crate::common::utils::market_params;
+
+#[derive(BorshDeserialize, BorshSerialize, Clone, Copy)]
+#[repr(transparent)]
+pub struct C([u8; 32]);
+
+unsafe impl bytemuck::Zeroable for C {}
+unsafe impl bytemuck::Pod for C {}
+
+impl BorshSize for C {
+    fn borsh_len(&self) -> usize {
+        32
+    }
+}
+
+fn order_params() -> new_order::Params<C> {
+    new_order::Params {
+        max_base_qty: 1000,
+        max_quote_qty: u64::MAX,
+        limit_price: 10 << 32,
+        side: Side::Bid,
+        callback_info: C(Pubkey::new_unique().to_bytes()),
+        post_only: false,
+        post_allowed: true,
+        self_trade_behavior: SelfTradeBehavior::DecrementTake,
+        match_limit: 10,
+        max_ts: u64::MAX,
+        immediate_or_cancel: false,
+        fill_or_kill: false,
+        max_display_qty: 0,
+        post_only_mode: PostOnlyMode::Reject,
+        max_slippage: 0,
+        flags: 0,
+        min_base_qty: 0,
+        compute_unit_reserve: 0,
+        resumable: false,
+        worst_price: 0,
+        referral: [0; 32],
+        client_order_id: 0,
+    }
+}
+
+/// Posts a bid on the given market of the harness and returns its order id.
+async fn post_bid(market: &mut MarketHarness<C>, accounts: MarketAccounts) -> u128 {
+    market.accounts = accounts;
+    let summary = market.new_order(order_params()).await.unwrap().unwrap();
+    summary.posted_order_id.unwrap()
+}
+
+fn multi_market_cancel_instruction(
+    register_account: Pubkey,
+    markets: &[MarketAccounts],
+    order_ids: Vec<Vec<u128>>,
+) -> Instruction {
+    let market_accounts = markets
+        .iter()
+        .flat_map(|m| [m.market, m.event_queue, m.bids, m.asks])
+        .collect::<Vec<_>>();
+    multi_market_cancel(
+        multi_market_cancel::Accounts {
+            market_accounts: &market_accounts,
+        },
+        register_account,
+        multi_market_cancel::Params { order_ids },
+    )
+}
+
+/// The orders of several markets are cancelled in a single instruction, or not at all.
+#[tokio::test]
+async fn test_multi_market_cancel() {
+    let mut market = MarketHarness::<C>::start(program_test(), market_params()).await;
+    let first = market.accounts;
+    let second =
+        create_market_accounts::<C>(&mut market.ctx, market.register_account, market_params())
+            .await;
+    let first_order = post_bid(&mut market, first).await;
+    let second_order = post_bid(&mut market, second).await;
+    let markets = [first, second];
+
+    let cases = [
+        (
+            vec![vec![first_order]],
+            InstructionError::Custom(AoError::MarketAccountsMismatch as u32),
+        ),
+        (
+            vec![vec![first_order], vec![second_order + 1]],
+            InstructionError::Custom(AoError::OrderNotFound as u32),
+        ),
+        (
+            vec![vec![first_order], vec![second_order], vec![]],
+            InstructionError::NotEnoughAccountKeys,
+        ),
+    ];
+    for (order_ids, expected) in cases {
+        let instruction =
+            multi_market_cancel_instruction(market.register_account, &markets, order_ids);
+        let error = market.send(vec![instruction], vec![]).await.unwrap_err();
+        assert_eq!(
+            error.unwrap(),
+            TransactionError::InstructionError(0, expected)
+        );
+    }
+
+    let instruction = multi_market_cancel_instruction(
+        market.register_account,
+        &markets,
+        vec![vec![first_order], vec![second_order]],
+    );
+    market.send(vec![instruction], vec![]).await.unwrap();
+
+    let register = market.account_data(market.register_account).await;
+    let summaries = read_order_summaries(&register).unwrap();
+    assert_eq!(summaries.len(), 2);
+    for (summary, accounts) in summaries.iter().zip(markets) {
+        assert_eq!(summary.total_base_qty, 1000);
+        market.accounts = accounts;
+        assert!(market.orders(Side::Bid).await.is_empty());
+    }
+}